@@ -1,21 +1,66 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use bat::PrettyPrinter;
 use colored::*;
 use directories::UserDirs;
-use lumo::agent::Step;
+use lumo::agent::{
+    AgentStep, AnswerSource, ConversationSummary, RunSummary, Step, StepDecision, StepHook, StepOverrides,
+};
+use lumo::models::ollama::{OllamaModelInfo, PullProgress};
 use lumo::models::openai::ToolCall;
+use lumo::models::types::Message;
+use lumo::pipeline::PipelineOutput;
+use lumo::tools::UserInput;
 use rustyline::error::ReadlineError;
 use rustyline::history::FileHistory;
 use rustyline::{Config, Editor};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::field::Visit;
 use tracing::Subscriber;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::{self, FormatEvent, FormatFields};
 use tracing_subscriber::registry::LookupSpan;
 
+use crate::i18n::{decimal, t, tf, Msg};
+
 pub struct ToolCallsFormatter;
 
+/// Asks the questions of the agent in the terminal.
+pub struct CliUserInput;
+
+#[async_trait]
+impl UserInput for CliUserInput {
+    async fn ask(&self, question: &str) -> Result<Option<String>> {
+        println!("\n{} {}", "❓".yellow(), question.yellow().bold());
+        tokio::task::spawn_blocking(|| {
+            let mut editor = Editor::<(), FileHistory>::new()?;
+            match editor.readline("👤> ") {
+                Ok(line) => Ok(Some(line)),
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(None),
+                Err(err) => Err(anyhow::anyhow!("Error: {:?}", err)),
+            }
+        })
+        .await?
+    }
+}
+
+/// Pauses the run after each step, for `--debug-steps`.
+pub struct StepDebugger;
+
+#[async_trait]
+impl StepHook for StepDebugger {
+    async fn after_step(&mut self, step: &AgentStep, memory: &[Message]) -> StepDecision {
+        let step = step.step;
+        let memory = memory.to_vec();
+        tokio::task::spawn_blocking(move || CliPrinter::debug_step(step, &memory))
+            .await
+            .unwrap_or_else(|e| {
+                println!("{} {}", t(Msg::Error), e);
+                StepDecision::Abort
+            })
+    }
+}
+
 impl<S, N> FormatEvent<S, N> for ToolCallsFormatter
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
@@ -47,7 +92,7 @@ where
         event.record(&mut visitor);
 
         if let Some(step) = visitor.1 {
-            println!("\n{} {}", "📍 Step:".bright_cyan().bold(), step);
+            println!("\n{} {}", t(Msg::Step).bright_cyan().bold(), step);
         }
 
         if let Some(tool_calls) = visitor.0 {
@@ -113,19 +158,106 @@ impl CliPrinter {
         }
     }
 
+    /// Ask what to do before the step after `step`, until the user continues or aborts.
+    fn debug_step(step: usize, memory: &[Message]) -> StepDecision {
+        println!(
+            "\n{} {} {}",
+            "🐞 Debug:".bright_yellow().bold(),
+            tf(Msg::DebugPaused, &[&step]),
+            t(Msg::DebugHelpHint).dimmed()
+        );
+        let mut overrides = StepOverrides::default();
+        let mut editor = match Editor::<(), FileHistory>::new() {
+            Ok(editor) => editor,
+            Err(e) => {
+                println!("{} {}", t(Msg::Error), e);
+                return StepDecision::Abort;
+            }
+        };
+        loop {
+            let line = match editor.readline("🐞> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    return StepDecision::Abort
+                }
+                Err(err) => {
+                    println!("{} {:?}", t(Msg::Error), err);
+                    return StepDecision::Abort;
+                }
+            };
+            let (command, argument) = line
+                .trim()
+                .split_once(' ')
+                .map(|(command, argument)| (command, argument.trim()))
+                .unwrap_or((line.trim(), ""));
+            match (command, argument) {
+                ("" | "c" | "continue", _) => return StepDecision::Continue(overrides),
+                ("a" | "abort", _) => {
+                    println!("{}", t(Msg::RunAborted).bright_red().bold());
+                    return StepDecision::Abort;
+                }
+                ("m" | "memory", _) => Self::print_memory(memory),
+                ("p" | "prompt", prompt) if !prompt.is_empty() => {
+                    overrides.prompt = Some(prompt.to_string());
+                    println!("{}", t(Msg::DebugPromptAdded));
+                }
+                ("f" | "force", tool) if !tool.is_empty() => {
+                    overrides.force_tool = Some(tool.to_string());
+                    println!("{}", tf(Msg::DebugForceTool, &[&tool.bright_white()]));
+                }
+                ("s" | "skip", tool) if !tool.is_empty() => {
+                    overrides.skip_tools.push(tool.to_string());
+                    println!("{}", tf(Msg::DebugSkipTool, &[&tool.bright_white()]));
+                }
+                _ => println!("{}", t(Msg::DebugCommands)),
+            }
+        }
+    }
+
+    /// Print the messages of a memory, for `/memory` and the `m` command of `--debug-steps`.
+    pub fn print_memory(memory: &[Message]) {
+        for message in memory {
+            println!(
+                "\n{}",
+                format!("[{}]", message.role).bright_cyan().bold()
+            );
+            if !message.content.is_empty() {
+                println!("{}", message.content);
+            }
+            for tool_call in message.tool_calls.iter().flatten() {
+                println!(
+                    "{} {} {}",
+                    "→".bright_magenta(),
+                    tool_call.function.name.bright_white().bold(),
+                    tool_call.function.arguments
+                );
+            }
+        }
+        println!();
+    }
+
     pub fn handle_empty_input() {
-        println!("{}", "⚠️  Please enter a task to execute".yellow().italic());
+        println!("{}", t(Msg::EmptyTask).yellow().italic());
+    }
+
+    /// Confirm that the session report was written, for `--report` and `/export`.
+    pub fn print_report_written(path: &Path) {
+        println!(
+            "{} {}",
+            t(Msg::ReportWritten).bright_blue().bold(),
+            path.display().to_string().bright_white()
+        );
     }
 
     pub fn print_goodbye() {
-        println!("{}", "👋 Goodbye!".bright_blue().bold());
+        println!("{}", t(Msg::Goodbye).bright_blue().bold());
     }
 
     pub fn print_step(step: &Step) -> Result<String> {
         match step {
             Step::ActionStep(action_step) => {
                 if let Some(error) = &action_step.error {
-                    println!("{} {}", "❌ Error:".bright_red().bold(), error);
+                    println!("{} {}", format!("❌ {}", t(Msg::Error)).bright_red().bold(), error);
                 }
 
                 if let Some(answer) = &action_step.final_answer {
@@ -134,14 +266,14 @@ impl CliPrinter {
                 }
             }
             Step::PlanningStep(plan, facts) => {
-                println!("\n{} Planning", "📍 Step:".bright_cyan().bold());
-                println!("\n{}", "📝 Facts:".bright_blue().bold());
+                println!("\n{} {}", t(Msg::Step).bright_cyan().bold(), t(Msg::Planning));
+                println!("\n{}", t(Msg::Facts).bright_blue().bold());
                 bat::PrettyPrinter::new()
                     .input(bat::Input::from_bytes(facts.as_bytes()))
                     .language("Markdown")
                     .wrapping_mode(bat::WrappingMode::NoWrapping(true))
                     .print()?;
-                println!("\n\n{}", "📝 Plan:".bright_blue().bold());
+                println!("\n\n{}", t(Msg::Plan).bright_blue().bold());
                 bat::PrettyPrinter::new()
                     .input(bat::Input::from_bytes(plan.as_bytes()))
                     .language("Markdown")
@@ -159,7 +291,7 @@ impl CliPrinter {
     pub fn print_regular_tool_call(tool_call: &[lumo::models::openai::ToolCall]) {
         println!(
             "{} {}",
-            "🔧 Executing Tools: \n".bright_magenta().bold(),
+            format!("{} \n", t(Msg::ExecutingTools)).bright_magenta().bold(),
             tool_call
                 .iter()
                 .map(|tool_call| {
@@ -191,7 +323,7 @@ impl CliPrinter {
     fn print_python_tool_call(tool_call: &[lumo::models::openai::ToolCall]) {
         println!(
             "{} {}",
-            "🔧 Executing:".bright_magenta().bold(),
+            t(Msg::Executing).bright_magenta().bold(),
             tool_call[0].function.name.bright_white().bold()
         );
 
@@ -212,7 +344,7 @@ impl CliPrinter {
         // Create dynamic border strings
         let horizontal = "─".repeat(width);
         let empty_line = " ".repeat(width).to_string();
-        let title = " 📝 Python Code ";
+        let title = t(Msg::PythonCode);
         let title_padding = (width - title.chars().count()) / 2;
         let top_border = format!(
             "┌{}{}{}┐",
@@ -234,7 +366,7 @@ impl CliPrinter {
     }
 
     fn print_final_answer(answer: &str) -> Result<()> {
-        println!("\n{}", "✨ Final Answer:".bright_blue().bold());
+        println!("\n{}", t(Msg::FinalAnswer).bright_blue().bold());
         PrettyPrinter::new()
             .input(bat::Input::from_bytes(answer.as_bytes()))
             .language("Markdown")
@@ -243,4 +375,170 @@ impl CliPrinter {
         println!("\n");
         Ok(())
     }
+
+    /// Print the output of each stage of a pipeline, then its answer.
+    pub fn print_pipeline_output(output: &PipelineOutput) -> Result<()> {
+        for stage in &output.stages {
+            match &stage.output {
+                Some(output) => {
+                    println!(
+                        "\n{} {} ({})",
+                        format!("🔗 {}", t(Msg::Stage)).bright_cyan().bold(),
+                        stage.name.bright_white(),
+                        stage.agent
+                    );
+                    println!("{}", output);
+                }
+                None => println!(
+                    "\n{} {} {}",
+                    format!("⏭️  {}", t(Msg::Stage)).bright_cyan().bold(),
+                    stage.name.bright_white(),
+                    t(Msg::Skipped).yellow().italic()
+                ),
+            }
+        }
+        Self::print_final_answer(&output.answer)
+    }
+
+    /// Print how long the steps of a run took and the tokens they used.
+    pub fn print_timing_summary(steps: &[AgentStep]) {
+        if steps.is_empty() {
+            return;
+        }
+        let total_ms: u64 = steps.iter().filter_map(|step| step.duration_ms).sum();
+        let model_ms: u64 = steps.iter().filter_map(|step| step.model_latency_ms).sum();
+        let tool_ms: u64 = steps
+            .iter()
+            .flat_map(|step| &step.tool_timings)
+            .map(|timing| timing.duration_ms)
+            .sum();
+        let input_tokens: usize = steps.iter().filter_map(|step| step.input_tokens).sum();
+        let output_tokens: usize = steps.iter().filter_map(|step| step.output_tokens).sum();
+        let slowest_tool = steps
+            .iter()
+            .flat_map(|step| &step.tool_timings)
+            .max_by_key(|timing| timing.duration_ms);
+
+        println!(
+            "{} {}",
+            t(Msg::Timing).bright_blue().bold(),
+            tf(
+                Msg::TimingLine,
+                &[
+                    &steps.len(),
+                    &format_duration(total_ms).bright_white().bold(),
+                    &format_duration(model_ms),
+                    &format_duration(tool_ms),
+                    &input_tokens,
+                    &output_tokens,
+                ]
+            )
+        );
+        if let Some(timing) = slowest_tool {
+            println!(
+                "{}",
+                tf(
+                    Msg::SlowestToolCall,
+                    &[&timing.name.bright_white(), &format_duration(timing.duration_ms)]
+                )
+            );
+        }
+        println!();
+    }
+
+    /// Print the tool usage, errors and answer source of a run, for `--summary`.
+    pub fn print_run_summary(summary: &RunSummary) {
+        let answer = match summary.answer_source {
+            Some(AnswerSource::FinalAnswer) => t(Msg::AnswerFinal).bright_green(),
+            Some(AnswerSource::MaxStepsFallback) => t(Msg::AnswerMaxSteps).bright_yellow(),
+            None => t(Msg::NoAnswer).bright_red(),
+        };
+        println!(
+            "{} {}",
+            t(Msg::Summary).bright_blue().bold(),
+            tf(
+                Msg::SummaryLine,
+                &[
+                    &summary.steps,
+                    &summary.tool_calls(),
+                    &(summary.input_tokens + summary.output_tokens),
+                    &summary.errors.len(),
+                    &answer,
+                ]
+            )
+        );
+        for tool in &summary.tools {
+            println!(
+                "{}",
+                tf(
+                    Msg::ToolStats,
+                    &[
+                        &tool.name.bright_white(),
+                        &tool.calls,
+                        &format_duration(tool.average_duration_ms()),
+                        &format_duration(tool.max_duration_ms),
+                    ]
+                )
+            );
+        }
+        for error in &summary.errors {
+            println!("   {} {}", t(Msg::Error).bright_red(), error);
+        }
+        println!();
+    }
+
+    /// Print the title and summary of the conversation, for `/title`.
+    pub fn print_summary(summary: &ConversationSummary) {
+        println!("\n{} {}", t(Msg::Title).bright_blue().bold(), summary.title.bright_white().bold());
+        for bullet in &summary.summary {
+            println!("   • {}", bullet);
+        }
+        println!();
+    }
+
+    /// Print the models of the Ollama server, for `--list-models`.
+    pub fn print_models(models: &[OllamaModelInfo]) {
+        if models.is_empty() {
+            println!("{}", t(Msg::NoModels));
+            return;
+        }
+        println!("\n{}", t(Msg::OllamaModels).bright_blue().bold());
+        for model in models {
+            let details = model
+                .details
+                .iter()
+                .flat_map(|details| [&details.parameter_size, &details.quantization_level])
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!(
+                "   • {} {}",
+                model.name.bright_white().bold(),
+                format!("({} GB{}{})", decimal(model.size as f64 / 1e9, 1), if details.is_empty() { "" } else { ", " }, details).dimmed()
+            );
+        }
+        println!();
+    }
+
+    /// Print the progress of the pull of a model on a single line.
+    pub fn print_pull_progress(progress: &PullProgress) {
+        let percent = progress
+            .percent()
+            .map(|percent| format!(" {:.0}%", percent))
+            .unwrap_or_default();
+        print!("\r\x1b[2K{} {}{}", "⬇️".blue(), progress.status, percent);
+        if progress.status == "success" {
+            println!();
+        }
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
+}
+
+fn format_duration(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{}s", decimal(ms as f64 / 1000.0, 1))
+    }
 }
@@ -4,17 +4,79 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+use rmcp::{
+    service::RunningService,
+    transport::{
+        common::client_side_sse::ExponentialBackoff, sse_client::SseClientConfig,
+        streamable_http_client::StreamableHttpClientTransportConfig, SseClientTransport,
+        StreamableHttpClientTransport, TokioChildProcess,
+    },
+    RoleClient, ServiceExt,
+};
+use std::sync::Arc;
 
+use crate::step_log::LogConfig;
+
+/// Default time an MCP server has to start and complete the initialization handshake.
+const DEFAULT_STARTUP_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of times the connection to a hosted MCP server is retried when it drops.
+const DEFAULT_MAX_RECONNECTS: usize = 5;
+
+/// The transport of a hosted MCP server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum McpTransport {
+    #[default]
+    StreamableHttp,
+    Sse,
+}
+
+/// An MCP server, either a local process started with `command` or a hosted server at `url`. `${VAR}` and
+/// `${VAR:-default}` in the args, env values, working directory, url and headers are replaced with the environment
+/// of the parent process.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<String>,
+    /// Environment variables added to the inherited environment of the process.
     #[serde(default)]
     pub env: Option<HashMap<String, String>>,
+    /// Working directory of the process. Defaults to the working directory of the parent process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Seconds the server has to start and answer the initialization handshake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_timeout: Option<u64>,
+    /// URL of a hosted server, used instead of `command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Transport of the hosted server. Defaults to streamable HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<McpTransport>,
+    /// HTTP headers sent to the hosted server, e.g. `Authorization: "Bearer ${MCP_TOKEN}"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// How many times the connection to the hosted server is retried when it drops.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_reconnects: Option<usize>,
 }
 
 impl ServerConfig {
     pub fn validate(&self) -> Result<()> {
+        if let Some(url) = &self.url {
+            if !self.command.is_empty() {
+                return Err(anyhow!("Server cannot have both a command and a url"));
+            }
+            if !url.starts_with("http://") && !url.starts_with("https://") && !url.starts_with("${") {
+                return Err(anyhow!("Server url must start with http:// or https://"));
+            }
+            return Ok(());
+        }
         if self.command.is_empty() {
             return Err(anyhow!("Server command cannot be empty"));
         }
@@ -23,6 +85,109 @@ impl ServerConfig {
         }
         Ok(())
     }
+
+    /// The command that starts the server, with its args, environment and working directory.
+    pub fn command(&self) -> Result<Command> {
+        let mut command = Command::new(&self.command);
+        for arg in &self.args {
+            command.arg(expand_env_vars(arg)?);
+        }
+        for (key, value) in self.env.iter().flatten() {
+            command.env(key, expand_env_vars(value)?);
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(expand_env_vars(cwd)?);
+        }
+        Ok(command)
+    }
+
+    /// The HTTP client of a hosted server, sending the configured headers with every request.
+    fn http_client(&self) -> Result<reqwest::Client> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in self.headers.iter().flatten() {
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name '{}'", name))?,
+                reqwest::header::HeaderValue::from_str(&expand_env_vars(value)?)
+                    .with_context(|| format!("Invalid value for header '{}'", name))?,
+            );
+        }
+        Ok(reqwest::Client::builder().default_headers(headers).build()?)
+    }
+
+    /// Start or connect to the server and wait for the initialization handshake, failing if it does not complete
+    /// in time. Hosted servers reconnect with exponential backoff when the connection drops.
+    pub async fn connect(&self, name: &str) -> Result<RunningService<RoleClient, ()>> {
+        let timeout =
+            Duration::from_secs(self.startup_timeout.unwrap_or(DEFAULT_STARTUP_TIMEOUT_SECS));
+        tokio::time::timeout(timeout, self.start(name))
+            .await
+            .map_err(|_| anyhow!("MCP server '{}' did not start within {:?}", name, timeout))?
+    }
+
+    async fn start(&self, name: &str) -> Result<RunningService<RoleClient, ()>> {
+        let handshake_failed = || format!("MCP server '{}' failed the initialization handshake", name);
+        let Some(url) = &self.url else {
+            let transport = TokioChildProcess::new(self.command()?)
+                .with_context(|| format!("Failed to start MCP server '{}'", name))?;
+            return ().serve(transport).await.with_context(handshake_failed);
+        };
+
+        let url = expand_env_vars(url)?;
+        let client = self.http_client()?;
+        let retry_policy = Arc::new(ExponentialBackoff {
+            max_times: Some(self.max_reconnects.unwrap_or(DEFAULT_MAX_RECONNECTS)),
+            base_duration: ExponentialBackoff::DEFAULT_DURATION,
+        });
+        match self.transport.unwrap_or_default() {
+            McpTransport::StreamableHttp => {
+                let config = StreamableHttpClientTransportConfig {
+                    retry_config: retry_policy,
+                    ..StreamableHttpClientTransportConfig::with_uri(url)
+                };
+                let transport = StreamableHttpClientTransport::with_client(client, config);
+                ().serve(transport).await.with_context(handshake_failed)
+            }
+            McpTransport::Sse => {
+                let config = SseClientConfig {
+                    sse_endpoint: url.into(),
+                    retry_policy,
+                    ..Default::default()
+                };
+                let transport = SseClientTransport::start_with_client(client, config)
+                    .await
+                    .with_context(|| format!("Failed to connect to MCP server '{}'", name))?;
+                ().serve(transport).await.with_context(handshake_failed)
+            }
+        }
+    }
+}
+
+/// Replace `${VAR}` with the value of the environment variable `VAR`, or with `default` for `${VAR:-default}`.
+pub fn expand_env_vars(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed variable in '{}'", value))?;
+        let variable = &rest[start + 2..start + end];
+        let (name, default) = match variable.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (variable, None),
+        };
+        match (std::env::var(name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(default),
+            (Err(_), None) => {
+                return Err(anyhow!("Environment variable '{}' is not set", name));
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +196,9 @@ pub struct Servers {
     pub servers: HashMap<String, ServerConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system_prompt: Option<String>,
+    /// Where and how the steps of the session are logged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logs: Option<LogConfig>,
 }
 
 impl Servers {
@@ -88,4 +256,12 @@ impl Servers {
 
         Ok(proj_dirs.config_dir().join("servers.yaml"))
     }
+
+    /// The default directory of the tool plugins, next to servers.yaml.
+    pub fn plugins_dir() -> Result<PathBuf> {
+        let proj_dirs = ProjectDirs::from("com", "lumo", "lumo-cli")
+            .context("Failed to determine config directory")?;
+
+        Ok(proj_dirs.config_dir().join("plugins"))
+    }
 }
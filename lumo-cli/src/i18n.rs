@@ -0,0 +1,450 @@
+//! The language of the messages of the command line, from `--lang` or the locale of the system.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// The languages of the messages, the same as the languages of the built-in prompts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Fr,
+    De,
+    Es,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+impl Lang {
+    /// The language of a code such as `fr` or `fr-FR`, or of a locale such as `fr_FR.UTF-8`.
+    pub fn parse(code: &str) -> Option<Self> {
+        match code
+            .split(['-', '_', '.', '@'])
+            .next()?
+            .to_lowercase()
+            .as_str()
+        {
+            "en" => Some(Lang::En),
+            "fr" => Some(Lang::Fr),
+            "de" => Some(Lang::De),
+            "es" => Some(Lang::Es),
+            _ => None,
+        }
+    }
+
+    /// The ISO 639-1 code of the language.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Fr => "fr",
+            Lang::De => "de",
+            Lang::Es => "es",
+        }
+    }
+
+    /// The language of the locale of the system, from `LC_ALL`, `LC_MESSAGES` or `LANG`. `None` for the other
+    /// languages and the `C` locale.
+    pub fn from_locale() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Self::parse(&locale))
+    }
+}
+
+/// Set the language of the messages. Only the first call has an effect.
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+/// The language of the messages, English until it is set.
+pub fn lang() -> Lang {
+    LANG.get().copied().unwrap_or(Lang::En)
+}
+
+/// The messages of the command line. The `{}` of a message are filled in order with [`tf`].
+#[derive(Debug, Clone, Copy)]
+pub enum Msg {
+    Error,
+    ErrorWritingReport,
+    ErrorWritingManifest,
+    Goodbye,
+    EmptyTask,
+    ReportWritten,
+    Step,
+    Planning,
+    Facts,
+    Plan,
+    ExecutingTools,
+    Executing,
+    PythonCode,
+    FinalAnswer,
+    Stage,
+    Skipped,
+    Timing,
+    TimingLine,
+    SlowestToolCall,
+    Summary,
+    SummaryLine,
+    ToolStats,
+    AnswerFinal,
+    AnswerMaxSteps,
+    NoAnswer,
+    Title,
+    NoModels,
+    OllamaModels,
+    DebugPaused,
+    DebugHelpHint,
+    RunAborted,
+    DebugPromptAdded,
+    DebugForceTool,
+    DebugSkipTool,
+    DebugCommands,
+    SecretSaved,
+    SecretRemoved,
+    Tagline,
+    Version,
+    Config,
+    RunningModel,
+    TracingEndpoint,
+    TracingDisabled,
+    McpServers,
+    WaitingForAnswer,
+    Calling,
+    RunningCode,
+    Running,
+    TuiHelp,
+    Conversation,
+    ConversationTask,
+    TypeTask,
+    Steps,
+    ToolCalls,
+    Arguments,
+    Observation,
+    NoObservation,
+    StepOf,
+    TaskPane,
+    TaskRunning,
+    Task,
+    SessionReport,
+    Generated,
+    ToolCall,
+    Shortened,
+    PlanHeading,
+    FinalAnswerHeading,
+    ObservationHeading,
+    TurnSummary,
+    Aborted,
+}
+
+impl Msg {
+    /// The message in English, French, German and Spanish.
+    fn translations(self) -> [&'static str; 4] {
+        match self {
+            Msg::Error => ["Error:", "Erreur :", "Fehler:", "Error:"],
+            Msg::ErrorWritingReport => [
+                "Error writing the report: {}",
+                "Erreur lors de l'écriture du rapport : {}",
+                "Fehler beim Schreiben des Berichts: {}",
+                "Error al escribir el informe: {}",
+            ],
+            Msg::ErrorWritingManifest => [
+                "Error writing the manifest: {}",
+                "Erreur lors de l'écriture du manifeste : {}",
+                "Fehler beim Schreiben des Manifests: {}",
+                "Error al escribir el manifiesto: {}",
+            ],
+            Msg::Goodbye => ["👋 Goodbye!", "👋 Au revoir !", "👋 Auf Wiedersehen!", "👋 ¡Adiós!"],
+            Msg::EmptyTask => [
+                "⚠️  Please enter a task to execute",
+                "⚠️  Saisissez une tâche à exécuter",
+                "⚠️  Bitte gib eine Aufgabe ein",
+                "⚠️  Escribe una tarea para ejecutar",
+            ],
+            Msg::ReportWritten => [
+                "📝 Report written to",
+                "📝 Rapport écrit dans",
+                "📝 Bericht geschrieben nach",
+                "📝 Informe escrito en",
+            ],
+            Msg::Step => ["📍 Step:", "📍 Étape :", "📍 Schritt:", "📍 Paso:"],
+            Msg::Planning => ["Planning", "Planification", "Planung", "Planificación"],
+            Msg::Facts => ["📝 Facts:", "📝 Faits :", "📝 Fakten:", "📝 Hechos:"],
+            Msg::Plan => ["📝 Plan:", "📝 Plan :", "📝 Plan:", "📝 Plan:"],
+            Msg::ExecutingTools => [
+                "🔧 Executing Tools:",
+                "🔧 Exécution des outils :",
+                "🔧 Werkzeuge werden ausgeführt:",
+                "🔧 Ejecutando herramientas:",
+            ],
+            Msg::Executing => [
+                "🔧 Executing:",
+                "🔧 Exécution :",
+                "🔧 Wird ausgeführt:",
+                "🔧 Ejecutando:",
+            ],
+            Msg::PythonCode => [
+                " 📝 Python Code ",
+                " 📝 Code Python ",
+                " 📝 Python-Code ",
+                " 📝 Código Python ",
+            ],
+            Msg::FinalAnswer => [
+                "✨ Final Answer:",
+                "✨ Réponse finale :",
+                "✨ Endgültige Antwort:",
+                "✨ Respuesta final:",
+            ],
+            Msg::Stage => ["Stage:", "Étape :", "Stufe:", "Etapa:"],
+            Msg::Skipped => ["skipped", "ignorée", "übersprungen", "omitida"],
+            Msg::Timing => ["⏱️  Timing:", "⏱️  Durée :", "⏱️  Dauer:", "⏱️  Tiempo:"],
+            Msg::TimingLine => [
+                "{} steps in {} (model {}, tools {}) · ~{} input / ~{} output tokens",
+                "{} étapes en {} (modèle {}, outils {}) · ~{} tokens en entrée / ~{} en sortie",
+                "{} Schritte in {} (Modell {}, Werkzeuge {}) · ~{} Eingabe- / ~{} Ausgabe-Tokens",
+                "{} pasos en {} (modelo {}, herramientas {}) · ~{} tokens de entrada / ~{} de salida",
+            ],
+            Msg::SlowestToolCall => [
+                "   Slowest tool call: {} ({})",
+                "   Appel d'outil le plus lent : {} ({})",
+                "   Langsamster Werkzeugaufruf: {} ({})",
+                "   Llamada a herramienta más lenta: {} ({})",
+            ],
+            Msg::Summary => ["📊 Summary:", "📊 Résumé :", "📊 Zusammenfassung:", "📊 Resumen:"],
+            Msg::SummaryLine => [
+                "{} steps · {} tool calls · ~{} tokens · {} errors · answer from {}",
+                "{} étapes · {} appels d'outils · ~{} tokens · {} erreurs · réponse : {}",
+                "{} Schritte · {} Werkzeugaufrufe · ~{} Tokens · {} Fehler · Antwort aus {}",
+                "{} pasos · {} llamadas a herramientas · ~{} tokens · {} errores · respuesta de {}",
+            ],
+            Msg::ToolStats => [
+                "   {} × {} (avg {}, max {})",
+                "   {} × {} (moy. {}, max. {})",
+                "   {} × {} (Ø {}, max. {})",
+                "   {} × {} (media {}, máx. {})",
+            ],
+            Msg::AnswerFinal => ["final answer", "réponse finale", "endgültiger Antwort", "respuesta final"],
+            Msg::AnswerMaxSteps => [
+                "max steps fallback",
+                "repli après le nombre maximal d'étapes",
+                "Ersatz nach maximaler Schrittzahl",
+                "alternativa tras el máximo de pasos",
+            ],
+            Msg::NoAnswer => ["no answer", "aucune réponse", "keiner Antwort", "ninguna respuesta"],
+            Msg::Title => ["🏷️  Title:", "🏷️  Titre :", "🏷️  Titel:", "🏷️  Título:"],
+            Msg::NoModels => [
+                "No models found. Pull one with `ollama pull <model>` or `lumo -m ollama --model-id <model> --pull`.",
+                "Aucun modèle trouvé. Téléchargez-en un avec `ollama pull <model>` ou `lumo -m ollama --model-id <model> --pull`.",
+                "Keine Modelle gefunden. Lade eines mit `ollama pull <model>` oder `lumo -m ollama --model-id <model> --pull`.",
+                "No se encontraron modelos. Descarga uno con `ollama pull <model>` o `lumo -m ollama --model-id <model> --pull`.",
+            ],
+            Msg::OllamaModels => [
+                "📦 Ollama models:",
+                "📦 Modèles Ollama :",
+                "📦 Ollama-Modelle:",
+                "📦 Modelos de Ollama:",
+            ],
+            Msg::DebugPaused => [
+                "Paused after step {}.",
+                "En pause après l'étape {}.",
+                "Pausiert nach Schritt {}.",
+                "En pausa tras el paso {}.",
+            ],
+            Msg::DebugHelpHint => [
+                "Type h for the commands.",
+                "Tapez h pour les commandes.",
+                "Gib h für die Befehle ein.",
+                "Escribe h para ver los comandos.",
+            ],
+            Msg::RunAborted => [
+                "⏹️  Run aborted",
+                "⏹️  Exécution interrompue",
+                "⏹️  Lauf abgebrochen",
+                "⏹️  Ejecución interrumpida",
+            ],
+            Msg::DebugPromptAdded => [
+                "The prompt is added to the next step.",
+                "Le prompt est ajouté à l'étape suivante.",
+                "Der Prompt wird dem nächsten Schritt hinzugefügt.",
+                "El prompt se añade al paso siguiente.",
+            ],
+            Msg::DebugForceTool => [
+                "The next step can only use {}.",
+                "L'étape suivante ne peut utiliser que {}.",
+                "Der nächste Schritt kann nur {} verwenden.",
+                "El paso siguiente solo puede usar {}.",
+            ],
+            Msg::DebugSkipTool => [
+                "The next step cannot use {}.",
+                "L'étape suivante ne peut pas utiliser {}.",
+                "Der nächste Schritt kann {} nicht verwenden.",
+                "El paso siguiente no puede usar {}.",
+            ],
+            Msg::DebugCommands => [
+                "  c, Enter       continue
+  m              show the memory of the next step
+  p <prompt>     add a prompt to the next step
+  f <tool>       only let the next step use this tool
+  s <tool>       do not let the next step use this tool
+  a              abort the run",
+                "  c, Entrée      continuer
+  m              afficher la mémoire de l'étape suivante
+  p <prompt>     ajouter un prompt à l'étape suivante
+  f <outil>      ne laisser l'étape suivante utiliser que cet outil
+  s <outil>      interdire cet outil à l'étape suivante
+  a              interrompre l'exécution",
+                "  c, Enter       fortfahren
+  m              den Speicher des nächsten Schritts anzeigen
+  p <prompt>     dem nächsten Schritt einen Prompt hinzufügen
+  f <werkzeug>   den nächsten Schritt nur dieses Werkzeug verwenden lassen
+  s <werkzeug>   dem nächsten Schritt dieses Werkzeug verbieten
+  a              den Lauf abbrechen",
+                "  c, Intro       continuar
+  m              mostrar la memoria del paso siguiente
+  p <prompt>     añadir un prompt al paso siguiente
+  f <herram.>    dejar que el paso siguiente use solo esta herramienta
+  s <herram.>    no dejar que el paso siguiente use esta herramienta
+  a              interrumpir la ejecución",
+            ],
+            Msg::SecretSaved => [
+                "Saved {} in {}",
+                "{} enregistré dans {}",
+                "{} in {} gespeichert",
+                "{} guardado en {}",
+            ],
+            Msg::SecretRemoved => [
+                "Removed {} from {}",
+                "{} supprimé de {}",
+                "{} aus {} entfernt",
+                "{} eliminado de {}",
+            ],
+            Msg::Tagline => [
+                "Your AI-Powered Command Line Agent",
+                "Votre agent en ligne de commande propulsé par l'IA",
+                "Dein KI-gestützter Kommandozeilen-Agent",
+                "Tu agente de línea de comandos con IA",
+            ],
+            Msg::Version => ["Version:", "Version :", "Version:", "Versión:"],
+            Msg::Config => ["Config:", "Configuration :", "Konfiguration:", "Configuración:"],
+            Msg::RunningModel => ["Running Model:", "Modèle :", "Modell:", "Modelo:"],
+            Msg::TracingEndpoint => [
+                "Tracing Endpoint:",
+                "Point de collecte des traces :",
+                "Tracing-Endpunkt:",
+                "Endpoint de trazas:",
+            ],
+            Msg::TracingDisabled => [
+                "Tracing Disabled",
+                "Traces désactivées",
+                "Tracing deaktiviert",
+                "Trazas desactivadas",
+            ],
+            Msg::McpServers => [
+                "Available MCP Servers:",
+                "Serveurs MCP disponibles :",
+                "Verfügbare MCP-Server:",
+                "Servidores MCP disponibles:",
+            ],
+            Msg::WaitingForAnswer => [
+                "Waiting for your answer",
+                "En attente de votre réponse",
+                "Warte auf deine Antwort",
+                "Esperando tu respuesta",
+            ],
+            Msg::Calling => ["Calling {}", "Appel de {}", "Rufe {} auf", "Llamando a {}"],
+            Msg::RunningCode => [
+                "Running code",
+                "Exécution du code",
+                "Code wird ausgeführt",
+                "Ejecutando código",
+            ],
+            Msg::Running => ["Running {}", "Exécution de {}", "{} läuft", "Ejecutando {}"],
+            Msg::TuiHelp => [
+                "Enter: run · Tab: switch pane · ↑↓: tasks, scroll or tool calls · Space: expand · PgUp/PgDn: earlier tasks · Esc: stop or quit",
+                "Entrée : exécuter · Tab : changer de panneau · ↑↓ : tâches, défilement ou appels d'outils · Espace : déplier · PgUp/PgDn : tâches précédentes · Échap : arrêter ou quitter",
+                "Enter: ausführen · Tab: Bereich wechseln · ↑↓: Aufgaben, Scrollen oder Werkzeugaufrufe · Leertaste: aufklappen · Bild↑/Bild↓: frühere Aufgaben · Esc: stoppen oder beenden",
+                "Intro: ejecutar · Tab: cambiar de panel · ↑↓: tareas, desplazamiento o llamadas · Espacio: expandir · RePág/AvPág: tareas anteriores · Esc: detener o salir",
+            ],
+            Msg::Conversation => [" Conversation ", " Conversation ", " Unterhaltung ", " Conversación "],
+            Msg::ConversationTask => [
+                " Conversation · task {}/{} ",
+                " Conversation · tâche {}/{} ",
+                " Unterhaltung · Aufgabe {}/{} ",
+                " Conversación · tarea {}/{} ",
+            ],
+            Msg::TypeTask => [
+                "Type a task below and press Enter.",
+                "Saisissez une tâche ci-dessous et appuyez sur Entrée.",
+                "Gib unten eine Aufgabe ein und drücke Enter.",
+                "Escribe una tarea abajo y pulsa Intro.",
+            ],
+            Msg::Steps => [" Steps ", " Étapes ", " Schritte ", " Pasos "],
+            Msg::ToolCalls => [
+                " Tool calls ",
+                " Appels d'outils ",
+                " Werkzeugaufrufe ",
+                " Llamadas a herramientas ",
+            ],
+            Msg::Arguments => ["  arguments:", "  arguments :", "  Argumente:", "  argumentos:"],
+            Msg::Observation => ["  observation:", "  observation :", "  Beobachtung:", "  observación:"],
+            Msg::NoObservation => ["(none)", "(aucune)", "(keine)", "(ninguna)"],
+            Msg::StepOf => ["Step {}", "Étape {}", "Schritt {}", "Paso {}"],
+            Msg::TaskPane => [" Task ", " Tâche ", " Aufgabe ", " Tarea "],
+            Msg::TaskRunning => [
+                " Task · running, Esc to stop ",
+                " Tâche · en cours, Échap pour arrêter ",
+                " Aufgabe · läuft, Esc zum Stoppen ",
+                " Tarea · en curso, Esc para detener ",
+            ],
+            Msg::Task => ["Task {}", "Tâche {}", "Aufgabe {}", "Tarea {}"],
+            Msg::SessionReport => [
+                "Lumo Session Report",
+                "Rapport de session Lumo",
+                "Lumo-Sitzungsbericht",
+                "Informe de sesión de Lumo",
+            ],
+            Msg::Generated => ["Generated {}", "Généré le {}", "Erstellt am {}", "Generado el {}"],
+            Msg::ToolCall => ["Tool call:", "Appel d'outil :", "Werkzeugaufruf:", "Llamada a herramienta:"],
+            Msg::Shortened => ["(shortened)", "(raccourci)", "(gekürzt)", "(acortado)"],
+            Msg::PlanHeading => ["Plan", "Plan", "Plan", "Plan"],
+            Msg::FinalAnswerHeading => ["Final Answer", "Réponse finale", "Endgültige Antwort", "Respuesta final"],
+            Msg::TurnSummary => [
+                "{} steps · {} tool calls · {} input and {} output tokens · {}s",
+                "{} étapes · {} appels d'outils · {} tokens en entrée et {} en sortie · {} s",
+                "{} Schritte · {} Werkzeugaufrufe · {} Eingabe- und {} Ausgabe-Tokens · {} s",
+                "{} pasos · {} llamadas a herramientas · {} tokens de entrada y {} de salida · {} s",
+            ],
+            Msg::Aborted => [" · aborted", " · interrompue", " · abgebrochen", " · interrumpida"],
+            Msg::ObservationHeading => ["Observation", "Observation", "Beobachtung", "Observación"],
+        }
+    }
+}
+
+/// The message in the language of the command line.
+pub fn t(msg: Msg) -> &'static str {
+    msg.translations()[lang() as usize]
+}
+
+/// The message with its `{}` filled with the arguments, in order.
+pub fn tf(msg: Msg, args: &[&dyn Display]) -> String {
+    let mut parts = t(msg).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (part, arg) in parts.zip(
+        args.iter()
+            .map(|arg| arg.to_string())
+            .chain(std::iter::repeat_with(String::new)),
+    ) {
+        text.push_str(&arg);
+        text.push_str(part);
+    }
+    text
+}
+
+/// A number with `precision` decimals and the decimal separator of the language.
+pub fn decimal(value: f64, precision: usize) -> String {
+    let text = format!("{:.*}", precision, value);
+    match lang() {
+        Lang::En => text,
+        Lang::Fr | Lang::De | Lang::Es => text.replace('.', ","),
+    }
+}
@@ -1,43 +1,54 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 
 use futures::StreamExt;
 use lumo::agent::{
-    AgentStream, CodeAgent, CodeAgentBuilder, FunctionCallingAgent, FunctionCallingAgentBuilder,
-    McpAgentBuilder, StreamResult,
+    steps_to_messages, summarize, Agent, AgentStream, CodeAgent, ConversationSummary, CodeAgentBuilder, FunctionCallingAgent, FunctionCallingAgentBuilder,
+    McpAgentBuilder, StreamResult, ToolNamespacing,
+};
+use lumo::agent::{
+    McpAgent, Reproducibility, ResponseLanguage, RunManifest, RunSummary, Step, StepHook,
 };
-use lumo::agent::{McpAgent, Step};
 use lumo::errors::AgentError;
-use lumo::models::model_traits::{Model, ModelResponse};
+use lumo::models::model_traits::{Model, ModelResponse, ModelSampling};
 use lumo::models::ollama::{OllamaModel, OllamaModelBuilder};
 use lumo::models::openai::{OpenAIServerModel, OpenAIServerModelBuilder, Status};
+use lumo::models::providers::ProviderPreset;
+use lumo::models::tokenizer::TokenCounter;
 use lumo::models::types::Message;
+use lumo::pipeline::{default_tool, ModelProvider, ModelSpec, Pipeline, PipelineDefinition};
+use lumo::presets::{self, PresetAgentType};
+use lumo::secrets::EncryptedFileSecrets;
 use lumo::tools::exa_search::ExaSearchTool;
 use lumo::tools::{
-    AsyncTool, DuckDuckGoSearchTool, GoogleSearchTool, PythonInterpreterTool, ToolInfo,
-    VisitWebsiteTool, TavilySearchTool,
+    load_plugins, AskUserTool, AsyncTool, DateTimeTool, DuckDuckGoSearchTool, E2BInterpreterTool, GoogleSearchTool, PythonInterpreterTool,
+    ToolInfo,
+    VisitWebsiteTool, TavilySearchTool, WebScreenshotTool,
 };
 
+use opentelemetry::global::BoxedTracer;
 use opentelemetry::trace::{FutureExt, SpanKind, TraceContextExt, Tracer};
 use opentelemetry::{global, Context, KeyValue};
 use tokio::sync::broadcast;
-use std::{collections::HashMap, fs::File, io};
-use tokio::process::Command;
+use std::{collections::HashMap, io, path::{Path, PathBuf}};
 use tracing::Level;
 use tracing_subscriber::{fmt, EnvFilter};
 mod config;
 use config::Servers;
 mod cli_utils;
-use cli_utils::{CliPrinter, ToolCallsFormatter};
+use cli_utils::{CliPrinter, CliUserInput, StepDebugger, ToolCallsFormatter};
+mod i18n;
+use i18n::{t, tf, Lang, Msg};
+mod report;
+use report::write_report;
 mod splash;
 use splash::SplashScreen;
+mod step_log;
+use step_log::{LogFormat, LogRotation, StepLog};
 mod telemetry;
-use rmcp::{
-    transport::{ConfigureCommandExt, TokioChildProcess},
-    ServiceExt,
-};
 use telemetry::init_tracer;
+mod tui;
 
 #[derive(Debug, Clone, ValueEnum)]
 enum AgentType {
@@ -54,6 +65,10 @@ enum ToolType {
     PythonInterpreter,
     ExaSearchTool,
     TavilySearchTool,
+    E2BInterpreter,
+    WebScreenshot,
+    DateTime,
+    AskUser,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -61,6 +76,22 @@ enum ModelType {
     OpenAI,
     Ollama,
     Gemini,
+    /// Grok models of xAI, with XAI_API_KEY
+    #[value(name = "xai")]
+    Xai,
+    /// DeepSeek models, with DEEPSEEK_API_KEY
+    #[value(name = "deepseek")]
+    DeepSeek,
+}
+
+impl ModelType {
+    fn preset(&self) -> Option<ProviderPreset> {
+        match self {
+            ModelType::Xai => Some(ProviderPreset::Xai),
+            ModelType::DeepSeek => Some(ProviderPreset::DeepSeek),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -84,11 +115,55 @@ impl<
         task: &'a str,
         reset: bool,
         tx: Option<broadcast::Sender<Status>>,
+        hook: Option<Box<dyn StepHook + 'a>>,
     ) -> StreamResult<'a, Step> {
         match self {
-            AgentWrapper::FunctionCalling(agent) => agent.stream_run(task, reset, tx),
-            AgentWrapper::Code(agent) => agent.stream_run(task, reset, tx),
-            AgentWrapper::Mcp(agent) => agent.stream_run(task, reset, tx),
+            AgentWrapper::FunctionCalling(agent) => agent.stream_run_with_hook(task, reset, tx, hook),
+            AgentWrapper::Code(agent) => agent.stream_run_with_hook(task, reset, tx, hook),
+            AgentWrapper::Mcp(agent) => agent.stream_run_with_hook(task, reset, tx, hook),
+        }
+    }
+
+    /// Title and summarize the conversation so far.
+    async fn summarize(&mut self) -> Result<ConversationSummary, AgentError> {
+        let agent: &mut dyn Agent = match self {
+            AgentWrapper::FunctionCalling(agent) => agent,
+            AgentWrapper::Code(agent) => agent,
+            AgentWrapper::Mcp(agent) => agent,
+        };
+        let messages = steps_to_messages(agent.get_logs_mut());
+        summarize(agent.model(), &messages).await
+    }
+
+    fn run_summary(&mut self) -> RunSummary {
+        match self {
+            AgentWrapper::FunctionCalling(agent) => agent.run_summary(),
+            AgentWrapper::Code(agent) => agent.run_summary(),
+            AgentWrapper::Mcp(agent) => agent.run_summary(),
+        }
+    }
+
+    fn get_memory(&mut self) -> Result<Vec<Message>, AgentError> {
+        match self {
+            AgentWrapper::FunctionCalling(agent) => agent.get_memory(),
+            AgentWrapper::Code(agent) => agent.get_memory(),
+            AgentWrapper::Mcp(agent) => agent.get_memory(),
+        }
+    }
+
+    fn run_manifest(&self) -> Option<RunManifest> {
+        match self {
+            AgentWrapper::FunctionCalling(agent) => agent.run_manifest(),
+            AgentWrapper::Code(agent) => agent.run_manifest(),
+            AgentWrapper::Mcp(agent) => agent.run_manifest(),
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        match self {
+            AgentWrapper::FunctionCalling(agent) => agent.shutdown().await,
+            AgentWrapper::Code(agent) => agent.shutdown().await,
+            AgentWrapper::Mcp(agent) => agent.shutdown().await,
         }
     }
 }
@@ -131,6 +206,33 @@ impl Model for ModelWrapper {
                 .await?),
         }
     }
+    fn context_window(&self) -> Option<usize> {
+        match self {
+            ModelWrapper::OpenAI(m) => m.context_window(),
+            ModelWrapper::Ollama(m) => m.context_window(),
+        }
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        match self {
+            ModelWrapper::OpenAI(m) => m.max_output_tokens(),
+            ModelWrapper::Ollama(m) => m.max_output_tokens(),
+        }
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        match self {
+            ModelWrapper::OpenAI(m) => m.token_counter(),
+            ModelWrapper::Ollama(m) => m.token_counter(),
+        }
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        match self {
+            ModelWrapper::OpenAI(m) => m.sampling(),
+            ModelWrapper::Ollama(m) => m.sampling(),
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -141,7 +243,7 @@ struct Args {
     agent_type: AgentType,
 
     /// List of tools to use
-    #[arg(short = 'l', long = "tools", value_enum, num_args = 1.., value_delimiter = ',', default_values_t = [ToolType::DuckDuckGo, ToolType::VisitWebsite])]
+    #[arg(short = 'l', long = "tools", value_enum, num_args = 1.., value_delimiter = ',', default_values_t = [ToolType::DuckDuckGo, ToolType::VisitWebsite, ToolType::AskUser])]
     tools: Vec<ToolType>,
 
     /// The type of model to use
@@ -168,6 +270,18 @@ struct Args {
     #[arg(short = 'p', long)]
     planning_interval: Option<usize>,
 
+    /// Start the next model call while the slowest tool calls of a step still run (function-calling agent)
+    #[arg(long)]
+    pipelining: bool,
+
+    /// Language of the answers: auto for the language of the task, or a language code or name such as fr or French
+    #[arg(long)]
+    response_language: Option<ResponseLanguage>,
+
+    /// Language of the messages and of the built-in prompts of the agents: en, fr, de or es. The messages follow the locale of the system when it is not set
+    #[arg(long)]
+    lang: Option<String>,
+
     /// Logging level
     #[arg(short = 'v', long)]
     logging_level: Option<log::LevelFilter>,
@@ -175,9 +289,195 @@ struct Args {
     /// Context length of the model
     #[arg(short = 'c', long)]
     ctx_length: Option<usize>,
+
+    /// Ready-made agent configuration (researcher, coder, data-analyst). Sets the agent type and the tools.
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// List the models of the Ollama server and exit
+    #[arg(long)]
+    list_models: bool,
+
+    /// Pull the Ollama model when it is not available locally
+    #[arg(long)]
+    pull: bool,
+
+    /// Write a report of the session to this file after each task, as HTML for .html files and Markdown otherwise
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Pause after each step to inspect the memory, add a prompt, force or skip a tool, or abort the run
+    #[arg(long)]
+    debug_steps: bool,
+
+    /// Print a summary of each run: tool calls, tokens, errors and where the answer came from
+    #[arg(long)]
+    summary: bool,
+
+    /// Run in the terminal interface, with panes for the streamed output, the steps, the tool calls and the tasks
+    #[arg(long)]
+    tui: bool,
+
+    /// File the steps are logged to (default: logs.txt)
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Format of the step log
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Rotate the step log when it reaches this size, in MB
+    #[arg(long)]
+    log_max_size: Option<u64>,
+
+    /// Rotate the step log daily
+    #[arg(long, value_enum)]
+    log_rotation: Option<LogRotation>,
+
+    /// Do not log the steps
+    #[arg(long)]
+    no_log: bool,
+
+    /// Seed of the sampling of the model, for the providers that support it
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write a manifest of each run to this file: the model settings, the model responses and the tool results
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Answer the tool calls with the tool results of this manifest instead of calling the tools
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Directory of the tool plugins, each in a subdirectory with a plugin.yaml (default: plugins next to the config file)
+    #[arg(long)]
+    plugins_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Multi-agent pipelines defined in YAML or JSON
+    Pipeline {
+        #[command(subcommand)]
+        command: PipelineCommand,
+    },
+    /// The encrypted secrets file of LUMO_SECRETS_FILE, opened with the passphrase of LUMO_SECRETS_PASSPHRASE
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretsCommand {
+    /// Add or replace a secret, e.g. OPENAI_API_KEY. The value is read from the standard input
+    Set {
+        /// The name of the secret
+        name: String,
+    },
+    /// Remove a secret
+    Remove {
+        /// The name of the secret
+        name: String,
+    },
+    /// List the names of the secrets
+    List,
 }
 
-fn create_tool(tool_type: &ToolType) -> Box<dyn AsyncTool> {
+#[derive(Subcommand, Debug)]
+enum PipelineCommand {
+    /// Run a task through a pipeline. Agents without a model use the model of the command line
+    Run {
+        /// The pipeline definition, in YAML or JSON
+        file: PathBuf,
+        /// The task
+        task: String,
+    },
+}
+
+/// Where `/export` writes the report when no path is given and `--report` is not set.
+const DEFAULT_REPORT_PATH: &str = "lumo-report.md";
+
+/// The step log, report and spans of the tasks of a session, for the prompt and the terminal interface.
+struct Session {
+    step_log: Option<StepLog>,
+    /// Every task and step of the session, for the report.
+    steps: Vec<Step>,
+    report: Option<PathBuf>,
+    /// Where the manifest of each run is written, from `--record`.
+    record: Option<PathBuf>,
+    tracer: Option<BoxedTracer>,
+    context: Option<Context>,
+    tasks: usize,
+}
+
+impl Session {
+    /// Record a new task, and start its span in the conversation span.
+    fn start_task(&mut self, task: &str) -> Option<Context> {
+        self.tasks += 1;
+        self.steps.push(Step::TaskStep(task.to_string()));
+        let (tracer, context) = (self.tracer.as_ref()?, self.context.as_ref()?);
+        let span = tracer
+            .span_builder(format!("Task {}", self.tasks))
+            .with_kind(SpanKind::Internal)
+            .with_start_time(std::time::SystemTime::now())
+            .with_attributes(vec![
+                KeyValue::new("gen_ai.operation.name", "task"),
+                KeyValue::new("input.value", task.to_string()),
+            ])
+            .start_with_context(tracer, context);
+        Some(Context::current_with_span(span))
+    }
+
+    fn record_step(&mut self, step: &Step) -> Result<()> {
+        if let Some(step_log) = &mut self.step_log {
+            step_log.write_step(step)?;
+        }
+        self.steps.push(step.clone());
+        Ok(())
+    }
+
+    /// End the span of the task and update the report.
+    fn end_task(&mut self, context: Option<Context>, final_answer: String) -> Result<()> {
+        if let Some(context) = context {
+            context
+                .span()
+                .set_attribute(KeyValue::new("output.value", final_answer));
+            context.span().end();
+        }
+        match &self.report {
+            Some(path) => write_report(path, &self.steps),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the manifest of the run to the `--record` file.
+    fn save_manifest(&self, manifest: Option<RunManifest>) -> Result<()> {
+        match (&self.record, manifest) {
+            (Some(path), Some(manifest)) => manifest.save(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the report to the path of `/export`, the `--report` file when it is empty.
+    fn export(&self, path: &str) -> Result<PathBuf> {
+        let path = match path.trim() {
+            "" => self
+                .report
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_REPORT_PATH)),
+            path => PathBuf::from(path),
+        };
+        write_report(&path, &self.steps)?;
+        Ok(path)
+    }
+}
+
+fn create_tool(tool_type: &ToolType, ask_user: &dyn Fn() -> AskUserTool) -> Box<dyn AsyncTool> {
     match tool_type {
         ToolType::DuckDuckGo => Box::new(DuckDuckGoSearchTool::new()),
         ToolType::VisitWebsite => Box::new(VisitWebsiteTool::new()),
@@ -185,13 +485,118 @@ fn create_tool(tool_type: &ToolType) -> Box<dyn AsyncTool> {
         ToolType::PythonInterpreter => Box::new(PythonInterpreterTool::new()),
         ToolType::ExaSearchTool => Box::new(ExaSearchTool::new(3, None)),
         ToolType::TavilySearchTool => Box::new(TavilySearchTool::new(None)),
+        ToolType::E2BInterpreter => Box::new(E2BInterpreterTool::new(None)),
+        ToolType::WebScreenshot => Box::new(WebScreenshotTool::new()),
+        ToolType::DateTime => Box::new(DateTimeTool::new()),
+        ToolType::AskUser => Box::new(ask_user()),
+    }
+}
+
+/// The model of the command line, for the agents of a pipeline that do not have their own.
+fn model_spec(args: &Args) -> ModelSpec {
+    let mut spec = ModelSpec {
+        model_id: Some(args.model_id.clone()),
+        base_url: args.base_url.clone(),
+        api_key: args.api_key.clone(),
+        ..Default::default()
+    };
+    match args.model_type {
+        ModelType::OpenAI => {}
+        // Gemini is called through its OpenAI-compatible API, like the agents of the CLI.
+        ModelType::Gemini => {
+            spec.base_url = Some(spec.base_url.unwrap_or(
+                "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions".to_string(),
+            ));
+            spec.api_key_env = Some("GOOGLE_API_KEY".to_string());
+        }
+        ModelType::Ollama => spec.provider = ModelProvider::Ollama,
+        ModelType::Xai => spec.provider = ModelProvider::Xai,
+        ModelType::DeepSeek => spec.provider = ModelProvider::DeepSeek,
+    }
+    spec
+}
+
+async fn run_pipeline(args: &Args, path: &Path, task: &str) -> Result<()> {
+    let mut definition = PipelineDefinition::from_file(path)?;
+    if definition.model.is_none() {
+        definition.model = Some(model_spec(args));
+    }
+    let pipeline = Pipeline::new(definition)?.with_tool_factory(|name| match name {
+        "AskUser" => Ok(Box::new(AskUserTool::new(CliUserInput))),
+        name => default_tool(name),
+    });
+    let output = pipeline.run(task).await?;
+    CliPrinter::print_pipeline_output(&output)
+}
+
+fn run_secrets(command: &SecretsCommand) -> Result<()> {
+    let secrets = EncryptedFileSecrets::from_env()?
+        .ok_or_else(|| anyhow::anyhow!("Set LUMO_SECRETS_FILE to the path of the secrets file"))?;
+    match command {
+        SecretsCommand::Set { name } => {
+            let mut value = String::new();
+            io::stdin().read_line(&mut value)?;
+            let value = value.trim_end_matches(['\r', '\n']);
+            if value.is_empty() {
+                anyhow::bail!("The value of {} is empty", name);
+            }
+            secrets.set(name, value)?;
+            println!("{}", tf(Msg::SecretSaved, &[name, &secrets.path().display()]));
+        }
+        SecretsCommand::Remove { name } => {
+            if !secrets.remove(name)? {
+                anyhow::bail!("{} has no secret {}", secrets.path().display(), name);
+            }
+            println!("{}", tf(Msg::SecretRemoved, &[name, &secrets.path().display()]));
+        }
+        SecretsCommand::List => {
+            for name in secrets.names()? {
+                println!("{}", name);
+            }
+        }
     }
+    Ok(())
 }
 
 #[tracing::instrument]
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let lang = args
+        .lang
+        .as_deref()
+        .map(|code| {
+            Lang::parse(code).ok_or_else(|| {
+                anyhow::anyhow!("Unknown language {}, the languages are en, fr, de and es", code)
+            })
+        })
+        .transpose()?;
+    i18n::set_lang(lang.or_else(Lang::from_locale).unwrap_or(Lang::En));
+
+    if args.list_models {
+        if !matches!(args.model_type, ModelType::Ollama) {
+            anyhow::bail!("--list-models is only supported with --model-type ollama");
+        }
+        let model = OllamaModelBuilder::new()
+            .url(args.base_url.as_deref().unwrap_or("http://localhost:11434"))
+            .build();
+        CliPrinter::print_models(&model.list_models().await?);
+        return Ok(());
+    }
+
+    if let Some(Command::Pipeline {
+        command: PipelineCommand::Run { file, task },
+    }) = &args.command
+    {
+        lumo::logger::init(args.logging_level.unwrap_or(log::LevelFilter::Error));
+        return run_pipeline(&args, file, task).await;
+    }
+    if let Some(Command::Secrets { command }) = &args.command {
+        return run_secrets(command);
+    }
+    if args.tui && args.debug_steps {
+        anyhow::bail!("--debug-steps is not supported with --tui");
+    }
 
     // Initialize tracing subscriber with custom formatting
     let tracer_provider = init_tracer();
@@ -218,13 +623,21 @@ async fn main() -> Result<()> {
         (None, None)
     };
 
+    let tui = args.tui;
     let subscriber = fmt::Subscriber::builder()
         .with_env_filter(
             EnvFilter::from_default_env()
                 .add_directive(Level::INFO.into())
                 .add_directive("lumo=debug".parse().unwrap()),
         )
-        .with_writer(io::stdout)
+        // The terminal interface owns the screen.
+        .with_writer(move || -> Box<dyn io::Write> {
+            if tui {
+                Box::new(io::sink())
+            } else {
+                Box::new(io::stdout())
+            }
+        })
         .event_format(ToolCallsFormatter)
         .finish();
 
@@ -240,14 +653,58 @@ async fn main() -> Result<()> {
         None
     };
 
-    SplashScreen::display(
-        &config_path,
-        &servers.servers.keys().cloned().collect::<Vec<_>>(),
-        &args.model_id,
-        endpoint,
-    );
+    if !args.tui {
+        SplashScreen::display(
+            &config_path,
+            &servers.servers.keys().cloned().collect::<Vec<_>>(),
+            &args.model_id,
+            endpoint,
+        );
+    }
+
+    let preset = args
+        .preset
+        .as_deref()
+        .map(|name| {
+            presets::get(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown preset {}, available presets: {}",
+                    name,
+                    presets::names().join(", ")
+                )
+            })
+        })
+        .transpose()?;
+    // The questions of the agent are answered in the terminal interface when it runs, and at a prompt otherwise.
+    let (tui_input, questions) = tui::questions();
+    let ask_user = || match args.tui {
+        true => AskUserTool::new(tui_input.clone()),
+        false => AskUserTool::new(CliUserInput),
+    };
+    let (agent_type, mut tools, planning_interval) = match &preset {
+        Some(preset) => (
+            match preset.agent_type {
+                PresetAgentType::FunctionCalling => AgentType::FunctionCalling,
+                PresetAgentType::Code => AgentType::Code,
+            },
+            preset.tools()?,
+            args.planning_interval.or(preset.planning_interval),
+        ),
+        None => (
+            args.agent_type.clone(),
+            args.tools
+                .iter()
+                .map(|tool| create_tool(tool, &ask_user))
+                .collect::<Vec<Box<dyn AsyncTool>>>(),
+            args.planning_interval,
+        ),
+    };
 
-    let tools: Vec<Box<dyn AsyncTool>> = args.tools.iter().map(create_tool).collect();
+    let plugins_dir = match &args.plugins_dir {
+        Some(dir) => dir.clone(),
+        None => Servers::plugins_dir()?,
+    };
+    tools.extend(load_plugins(&plugins_dir)?);
 
     // Create model based on type
     let model = match args.model_type {
@@ -255,6 +712,7 @@ async fn main() -> Result<()> {
             OpenAIServerModelBuilder::new(&args.model_id)
                 .with_base_url(args.base_url.as_deref())
                 .with_api_key(args.api_key.as_deref())
+                .with_seed(args.seed)
                 .build()?,
         ),
         ModelType::Gemini => ModelWrapper::OpenAI(
@@ -268,6 +726,15 @@ async fn main() -> Result<()> {
                             .unwrap_or_else(|_| "Gemini API key not found".to_string()),
                     ),
                 ))
+                .with_seed(args.seed)
+                .build()?,
+        ),
+        ModelType::Xai | ModelType::DeepSeek => ModelWrapper::OpenAI(
+            OpenAIServerModelBuilder::new(&args.model_id)
+                .with_provider(args.model_type.preset().unwrap())
+                .with_base_url(args.base_url.as_deref())
+                .with_api_key(args.api_key.as_deref())
+                .with_seed(args.seed)
                 .build()?,
         ),
         ModelType::Ollama => ModelWrapper::Ollama(
@@ -277,11 +744,18 @@ async fn main() -> Result<()> {
                 .temperature(Some(0.1))
                 .url(args.base_url.as_deref().unwrap_or("http://localhost:11434"))
                 .with_native_tools(true)
+                .with_auto_pull(args.pull)
+                .with_seed(args.seed)
                 .build(),
         ),
     };
+    if let ModelWrapper::Ollama(model) = &model {
+        model.ensure_model(CliPrinter::print_pull_progress).await?;
+    }
 
+    // With --lang, the agents use the built-in prompts in the language.
     let system_prompt = match args.model_type {
+        ModelType::Ollama if args.lang.is_some() => None,
         ModelType::Ollama => Some(
             r#"You are a helpful assistant that can answer questions and help with tasks. You are given access tools which you can use to answer the user's question. 
         
@@ -295,23 +769,48 @@ The current time is {{current_time}}"#,
         ),
         _ => servers.system_prompt.as_deref(),
     };
+    let system_prompt = preset
+        .as_ref()
+        .and_then(|preset| preset.system_prompt)
+        .or(system_prompt);
+
+    // The colored logger prints the errors of the agent, and the steps with --logging-level info.
+    // The terminal interface shows the errors of the steps instead.
+    lumo::logger::init(match args.tui {
+        true => log::LevelFilter::Off,
+        false => args.logging_level.unwrap_or(log::LevelFilter::Error),
+    });
+
+    // A replayed run is recorded too, to compare it with the manifest.
+    let reproducibility = match (&args.replay, &args.record) {
+        (Some(path), _) => Some(Reproducibility::Replay(RunManifest::load(path)?)),
+        (None, Some(_)) => Some(Reproducibility::Record),
+        (None, None) => None,
+    };
 
-    let mut agent = match args.agent_type {
+    let mut agent = match agent_type {
         AgentType::FunctionCalling => AgentWrapper::FunctionCalling(
             FunctionCallingAgentBuilder::new(model)
                 .with_tools(tools)
                 .with_system_prompt(system_prompt)
                 .with_max_steps(args.max_steps)
-                .with_planning_interval(args.planning_interval)
+                .with_planning_interval(planning_interval)
                 .with_logging_level(args.logging_level)
+                .with_pipelining(args.pipelining)
+                .with_response_language(args.response_language.clone())
+                .with_prompt_language(args.lang.as_deref())
+                .with_reproducibility(reproducibility)
                 .build()?,
         ),
         AgentType::Code => AgentWrapper::Code(
             CodeAgentBuilder::new(model)
                 .with_tools(tools)
                 .with_max_steps(args.max_steps)
-                .with_planning_interval(args.planning_interval)
+                .with_planning_interval(planning_interval)
                 .with_logging_level(args.logging_level)
+                .with_response_language(args.response_language.clone())
+                .with_prompt_language(args.lang.as_deref())
+                .with_reproducibility(reproducibility)
                 .build()?,
         ),
         AgentType::Mcp => {
@@ -319,17 +818,11 @@ The current time is {{current_time}}"#,
             let mut clients = Vec::new();
             let servers = Servers::load()?;
             // Iterate through all server configurations
-            for (_, server_config) in servers.servers.iter() {
+            for (server_name, server_config) in servers.servers.iter() {
                 // Create transport for this server
-                let client = ()
-                    .serve(TokioChildProcess::new(
-                        Command::new(&server_config.command).configure(|cmd| {
-                            cmd.args(&server_config.args);
-                        }),
-                    )?)
-                    .await?;
-
-                clients.push(client);
+                let client = server_config.connect(server_name).await?;
+
+                clients.push((server_name.to_string(), client));
             }
 
             // Create MCP agent with all initialized clients
@@ -337,98 +830,144 @@ The current time is {{current_time}}"#,
                 McpAgentBuilder::new(model)
                     .with_system_prompt(system_prompt)
                     .with_max_steps(args.max_steps)
-                    .with_planning_interval(args.planning_interval)
-                    .with_mcp_clients(clients)
+                    .with_planning_interval(planning_interval)
+                    .with_named_mcp_clients(clients)
+                    .with_tool_namespacing(Some(ToolNamespacing::OnConflict))
+                    .with_response_language(args.response_language.clone())
+                    .with_prompt_language(args.lang.as_deref())
+                    .with_reproducibility(reproducibility)
                     .build()
                     .await?,
             )
         }
     };
 
-    let mut file: File = File::create("logs.txt")?;
-
-    let mut task_count = 1;
-    loop {
-        let mut cli_printer = CliPrinter::new()?;
-        let task = cli_printer.prompt_user()?;
+    // The flags override the logs section of the config file.
+    let mut log_config = servers.logs.clone().unwrap_or_default();
+    if args.no_log {
+        log_config.enabled = Some(false);
+    }
+    log_config.path = args.log_file.clone().or(log_config.path);
+    log_config.format = args.log_format.or(log_config.format);
+    log_config.max_size_mb = args.log_max_size.or(log_config.max_size_mb);
+    log_config.rotation = args.log_rotation.or(log_config.rotation);
+    let mut session = Session {
+        step_log: StepLog::from_config(&log_config)?,
+        steps: Vec::new(),
+        report: args.report.clone(),
+        record: args.record.clone(),
+        tracer,
+        context: cx.clone(),
+        tasks: 0,
+    };
 
-        let task_name = format!("Task {}", task_count);
+    if args.tui {
+        tui::run(&mut agent, &mut session, questions).await?;
+    } else {
+        loop {
+            let mut cli_printer = CliPrinter::new()?;
+            let task = cli_printer.prompt_user()?;
 
-        if task.is_empty() {
-            CliPrinter::handle_empty_input();
-            continue;
-        }
-        if task == "exit" {
-            if let (Some((provider, _)), Some(context)) = (&tracer_provider, &cx) {
-                context.span().end();
-                // Ensure all spans are exported before shutting down
-                provider.force_flush()?;
-                provider.shutdown()?;
+            if task.is_empty() {
+                CliPrinter::handle_empty_input();
+                continue;
             }
-            CliPrinter::print_goodbye();
-            break;
-        }
-        let cx2 = if let (Some(t), Some(context)) = (&tracer, &cx) {
-            let span = t
-                .span_builder(task_name)
-                .with_kind(SpanKind::Internal)
-                .with_start_time(std::time::SystemTime::now())
-                .with_attributes(vec![
-                    KeyValue::new("gen_ai.operation.name", "task"),
-                    KeyValue::new("input.value", task.clone()),
-                ])
-                .start_with_context(t, context);
-            Some(Context::current_with_span(span))
-        } else {
-            None
-        };
-
-        // let (tx,mut  rx) = broadcast::channel::<Status>(100); # Use if streaming is needed
-        let mut result = agent.stream_run(&task, false, None)?;
-
-        // # Use if streaming is needed
-        // Spawn a non-blocking task to handle status messages
-        // let status_handle = tokio::spawn(async move {
-        //     while let Ok(status) = rx.recv().await {
-        //         match status {
+            if task == "/title" {
+                match agent.summarize().await {
+                    Ok(summary) => CliPrinter::print_summary(&summary),
+                    Err(e) => println!("{} {}", t(Msg::Error), e),
+                }
+                continue;
+            }
+            if task == "/memory" {
+                match agent.get_memory() {
+                    Ok(memory) => CliPrinter::print_memory(&memory),
+                    Err(e) => println!("{} {}", t(Msg::Error), e),
+                }
+                continue;
+            }
+            if task == "/export" || task.starts_with("/export ") {
+                match session.export(task.trim_start_matches("/export")) {
+                    Ok(path) => CliPrinter::print_report_written(&path),
+                    Err(e) => println!("{} {}", t(Msg::Error), e),
+                }
+                continue;
+            }
+            if task == "exit" {
+                break;
+            }
+            let cx2 = session.start_task(&task);
+            // let (tx,mut  rx) = broadcast::channel::<Status>(100); # Use if streaming is needed
+            let hook = args
+                .debug_steps
+                .then(|| Box::new(StepDebugger) as Box<dyn StepHook>);
+            let mut result = agent.stream_run(&task, false, None, hook)?;
+
+            // # Use if streaming is needed
+            // Spawn a non-blocking task to handle status messages
+            // let status_handle = tokio::spawn(async move {
+            //     while let Ok(status) = rx.recv().await {
+            //         match status {
           
-        //             Status::Content(content) => {
-        //                 use std::io::Write;
-        //                 print!("{}", content);
-        //                 let _ = std::io::stdout().flush();
-        //             }
-        //             _ => {}
-        //         }
-        //     }
-        // });
-
-        // Process the stream and collect results (CLI prints)
-        let mut final_answer = String::new();
-        while let Some(step) = if let Some(context) = &cx2 {
-            result.next().with_context(context.clone()).await
-        } else {
-            result.next().await
-        } {
-            if let Ok(step) = step {
-
-                serde_json::to_writer_pretty(&mut file, &step)?;
-                let answer = CliPrinter::print_step(&step)?;
-                final_answer = answer;
+            //             Status::Content(content) => {
+            //                 use std::io::Write;
+            //                 print!("{}", content);
+            //                 let _ = std::io::stdout().flush();
+            //             }
+            //             _ => {}
+            //         }
+            //     }
+            // });
+
+            // Process the stream and collect results (CLI prints)
+            let mut final_answer = String::new();
+            let mut action_steps = Vec::new();
+            while let Some(step) = if let Some(context) = &cx2 {
+                result.next().with_context(context.clone()).await
             } else {
-                println!("Error: {:?}", step);
+                result.next().await
+            } {
+                if let Ok(step) = step {
+                    session.record_step(&step)?;
+                    let answer = CliPrinter::print_step(&step)?;
+                    final_answer = answer;
+                    if let Step::ActionStep(action_step) = step {
+                        action_steps.push(action_step);
+                    }
+                } else {
+                    println!("{} {:?}", t(Msg::Error), step);
+                }
+            }
+            drop(result);
+            CliPrinter::print_timing_summary(&action_steps);
+            if args.summary {
+                CliPrinter::print_run_summary(&agent.run_summary());
             }
-        }
 
-        // let _ = status_handle.await;
+            // let _ = status_handle.await;
 
-        if let Some(context) = &cx2 {
-            context
-                .span()
-                .set_attribute(KeyValue::new("output.value", final_answer));
-            context.span().end();
+            if let Err(e) = session.end_task(cx2, final_answer) {
+                println!("{}", tf(Msg::ErrorWritingReport, &[&e]));
+            }
+            if let Err(e) = session.save_manifest(agent.run_manifest()) {
+                println!("{}", tf(Msg::ErrorWritingManifest, &[&e]));
+            }
         }
-        task_count += 1;
     }
 
+    if let (Some((provider, _)), Some(context)) = (&tracer_provider, &cx) {
+        context.span().end();
+        // Ensure all spans are exported before shutting down
+        provider.force_flush()?;
+        provider.shutdown()?;
+    }
+    if let Some(path) = args.report.as_ref().filter(|_| !session.steps.is_empty()) {
+        CliPrinter::print_report_written(path);
+    }
+    if let Err(e) = agent.shutdown().await {
+        println!("{} {}", t(Msg::Error), e);
+    }
+    CliPrinter::print_goodbye();
+
     Ok(())
 }
@@ -0,0 +1,264 @@
+//! Markdown and HTML reports of a CLI session, for `--report` and `/export`.
+
+use std::path::Path;
+
+use anyhow::Result;
+use lumo::agent::{AgentStep, Step};
+
+use crate::i18n::{decimal, lang, t, tf, Msg};
+
+/// Observations longer than this are shortened in the report.
+const MAX_OBSERVATION_CHARS: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// HTML for `.html` and `.htm` files, Markdown otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                Self::Html
+            }
+            _ => Self::Markdown,
+        }
+    }
+}
+
+/// A part of the report, in the order of the session.
+enum Block<'a> {
+    Task(usize, &'a str),
+    Plan { facts: &'a str, plan: &'a str },
+    Action(&'a AgentStep),
+}
+
+fn blocks(steps: &[Step]) -> Vec<Block<'_>> {
+    let mut tasks = 0;
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::TaskStep(task) => {
+                tasks += 1;
+                Some(Block::Task(tasks, task))
+            }
+            Step::PlanningStep(facts, plan) => Some(Block::Plan { facts, plan }),
+            Step::ActionStep(step) => Some(Block::Action(step)),
+            Step::SystemPromptStep(_) | Step::ToolCall(_) => None,
+        })
+        .collect()
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() > MAX_OBSERVATION_CHARS {
+        format!(
+            "{}\n... {}",
+            text.chars().take(MAX_OBSERVATION_CHARS).collect::<String>(),
+            t(Msg::Shortened)
+        )
+    } else {
+        text.to_string()
+    }
+}
+
+fn arguments(arguments: &serde_json::Value) -> String {
+    match arguments {
+        serde_json::Value::String(arguments) => arguments.clone(),
+        arguments => serde_json::to_string_pretty(arguments).unwrap_or_default(),
+    }
+}
+
+/// A fence that does not appear in the text, so code blocks can hold any observation.
+fn fence(text: &str) -> String {
+    let mut fence = "```".to_string();
+    while text.contains(&fence) {
+        fence.push('`');
+    }
+    fence
+}
+
+fn code_block(text: &str, language: &str) -> String {
+    let fence = fence(text);
+    format!("{}{}\n{}\n{}\n\n", fence, language, text.trim_end(), fence)
+}
+
+pub fn render_markdown(steps: &[Step]) -> String {
+    let mut report = format!(
+        "# {}\n\n_{}_\n\n",
+        t(Msg::SessionReport),
+        tf(Msg::Generated, &[&chrono::Local::now().format("%Y-%m-%d %H:%M")])
+    );
+    for block in blocks(steps) {
+        match block {
+            Block::Task(number, task) => {
+                report.push_str(&format!("## {}\n\n{}\n\n", tf(Msg::Task, &[&number]), task.trim()))
+            }
+            Block::Plan { facts, plan } => {
+                report.push_str(&format!(
+                    "### {}\n\n{}\n\n{}\n\n",
+                    t(Msg::PlanHeading),
+                    facts.trim(),
+                    plan.trim()
+                ))
+            }
+            Block::Action(step) => {
+                report.push_str(&format!("### {}", tf(Msg::StepOf, &[&step.step])));
+                if let Some(duration_ms) = step.duration_ms {
+                    report.push_str(&format!(" ({}s)", decimal(duration_ms as f64 / 1000.0, 1)));
+                }
+                report.push_str("\n\n");
+                let is_answer = step.final_answer.is_some()
+                    && step.tool_call.as_ref().is_none_or(|calls| calls.is_empty());
+                if let Some(thought) = step.llm_output.as_deref().filter(|t| !t.trim().is_empty()) {
+                    if !is_answer {
+                        report.push_str(&format!("{}\n\n", thought.trim()));
+                    }
+                }
+                let observations = step.observation_contents();
+                for (i, call) in step.tool_call.iter().flatten().enumerate() {
+                    if call.function.name == "final_answer" {
+                        continue;
+                    }
+                    report.push_str(&format!("**{}** `{}`\n\n", t(Msg::ToolCall), call.function.name));
+                    report.push_str(&code_block(&arguments(&call.function.arguments), "json"));
+                    if let Some(observation) = step.observation_of(i) {
+                        report.push_str(&format!("**{}:**\n\n", t(Msg::ObservationHeading)));
+                        report.push_str(&code_block(&shorten(&observation.content), ""));
+                    }
+                }
+                if step.tool_call.is_none() && step.final_answer.is_none() {
+                    for observation in &observations {
+                        report.push_str(&format!("**{}:**\n\n", t(Msg::ObservationHeading)));
+                        report.push_str(&code_block(&shorten(observation), ""));
+                    }
+                }
+                if let Some(error) = &step.error {
+                    report.push_str(&format!("> **{}** {}\n\n", t(Msg::Error), error.message()));
+                }
+                if let Some(answer) = &step.final_answer {
+                    report.push_str(&format!(
+                        "### {}\n\n{}\n\n",
+                        t(Msg::FinalAnswerHeading),
+                        answer.trim()
+                    ));
+                }
+            }
+        }
+    }
+    report
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:960px;margin:2rem auto;padding:0 1rem;line-height:1.5;color:#222}\
+h2{border-bottom:2px solid #ddd;padding-bottom:.3rem;margin-top:2.5rem}\
+pre{background:#f6f8fa;padding:.8rem;overflow-x:auto;white-space:pre-wrap;border-radius:6px}\
+.text{white-space:pre-wrap}.error{color:#b00020}.answer{background:#eef7ee;padding:.8rem;border-radius:6px}\
+.meta{color:#777;font-size:.9rem}details{margin:.5rem 0}";
+
+pub fn render_html(steps: &[Step]) -> String {
+    let mut body = String::new();
+    for block in blocks(steps) {
+        match block {
+            Block::Task(number, task) => body.push_str(&format!(
+                "<h2>{}</h2>\n<div class=\"text\">{}</div>\n",
+                tf(Msg::Task, &[&number]),
+                escape(task.trim())
+            )),
+            Block::Plan { facts, plan } => body.push_str(&format!(
+                "<h3>{}</h3>\n<div class=\"text\">{}</div>\n<div class=\"text\">{}</div>\n",
+                t(Msg::PlanHeading),
+                escape(facts.trim()),
+                escape(plan.trim())
+            )),
+            Block::Action(step) => {
+                body.push_str(&format!("<h3>{}", tf(Msg::StepOf, &[&step.step])));
+                if let Some(duration_ms) = step.duration_ms {
+                    body.push_str(&format!(
+                        " <span class=\"meta\">({}s)</span>",
+                        decimal(duration_ms as f64 / 1000.0, 1)
+                    ));
+                }
+                body.push_str("</h3>\n");
+                let is_answer = step.final_answer.is_some()
+                    && step.tool_call.as_ref().is_none_or(|calls| calls.is_empty());
+                if let Some(thought) = step.llm_output.as_deref().filter(|t| !t.trim().is_empty()) {
+                    if !is_answer {
+                        body.push_str(&format!(
+                            "<div class=\"text\">{}</div>\n",
+                            escape(thought.trim())
+                        ));
+                    }
+                }
+                let observations = step.observation_contents();
+                for (i, call) in step.tool_call.iter().flatten().enumerate() {
+                    if call.function.name == "final_answer" {
+                        continue;
+                    }
+                    body.push_str(&format!(
+                        "<p><strong>{}</strong> <code>{}</code></p>\n<pre>{}</pre>\n",
+                        t(Msg::ToolCall),
+                        escape(&call.function.name),
+                        escape(&arguments(&call.function.arguments))
+                    ));
+                    if let Some(observation) = step.observation_of(i) {
+                        body.push_str(&format!(
+                            "<details><summary>{}</summary><pre>{}</pre></details>\n",
+                            t(Msg::ObservationHeading),
+                            escape(&shorten(&observation.content))
+                        ));
+                    }
+                }
+                if step.tool_call.is_none() && step.final_answer.is_none() {
+                    for observation in &observations {
+                        body.push_str(&format!(
+                            "<details><summary>{}</summary><pre>{}</pre></details>\n",
+                            t(Msg::ObservationHeading),
+                            escape(&shorten(observation))
+                        ));
+                    }
+                }
+                if let Some(error) = &step.error {
+                    body.push_str(&format!(
+                        "<p class=\"error\"><strong>{}</strong> {}</p>\n",
+                        t(Msg::Error),
+                        escape(error.message())
+                    ));
+                }
+                if let Some(answer) = &step.final_answer {
+                    body.push_str(&format!(
+                        "<h3>{}</h3>\n<div class=\"answer text\">{}</div>\n",
+                        t(Msg::FinalAnswerHeading),
+                        escape(answer.trim())
+                    ));
+                }
+            }
+        }
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<p class=\"meta\">{}</p>\n{}</body>\n</html>\n",
+        lang().code(),
+        t(Msg::SessionReport),
+        HTML_STYLE,
+        t(Msg::SessionReport),
+        tf(Msg::Generated, &[&chrono::Local::now().format("%Y-%m-%d %H:%M")]),
+        body
+    )
+}
+
+/// Write the report of the session to `path`, as HTML or Markdown depending on its extension.
+pub fn write_report(path: &Path, steps: &[Step]) -> Result<()> {
+    let report = match ReportFormat::from_path(path) {
+        ReportFormat::Markdown => render_markdown(steps),
+        ReportFormat::Html => render_html(steps),
+    };
+    std::fs::write(path, report)?;
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use colored::*;
 
+use crate::i18n::{t, Msg};
+
 pub struct SplashScreen;
 
 impl SplashScreen {
@@ -28,36 +30,36 @@ $$$$$$$$\\$$$$$$  |$$ | \_/ $$ | $$$$$$  |
         println!("{}", "━".repeat(60).bright_blue());
         println!(
             "{}",
-            "Your AI-Powered Command Line Agent"
+            t(Msg::Tagline)
                 .centered(60)
                 .bright_white()
                 .italic()
         );
 
-        println!("{} {}", "Version:".bright_yellow(), version.bright_white());
+        println!("{} {}", t(Msg::Version).bright_yellow(), version.bright_white());
 
         println!(
             "{} {}",
-            "Config:".bright_yellow(),
+            t(Msg::Config).bright_yellow(),
             config_path.display().to_string().bright_white()
         );
         println!(
             "\n{} {}",
-            "Running Model:".bright_yellow(),
+            t(Msg::RunningModel).bright_yellow(),
             model_id.to_string().bright_white()
         );
 
         if let Some(endpoint) = endpoint {
             println!(
                 "{} {}",
-                "Tracing Endpoint:".bright_yellow(),
+                t(Msg::TracingEndpoint).bright_yellow(),
                 endpoint.bright_white()
             );
         } else {
-            println!("{}\n", "Tracing Disabled".bright_yellow());
+            println!("{}\n", t(Msg::TracingDisabled).bright_yellow());
         }
 
-        println!("\n{}", "Available MCP Servers:".bright_yellow());
+        println!("\n{}", t(Msg::McpServers).bright_yellow());
         for server in servers {
             println!("  ├─ {}", server.bright_white());
         }
@@ -0,0 +1,157 @@
+//! The log of the steps of a CLI session, `logs.txt` in the working directory unless configured otherwise.
+//!
+//! The `logs` section of the config file sets the defaults, and the `--log-*` flags override them:
+//!
+//! ```yaml
+//! logs:
+//!   path: /var/log/lumo/steps.jsonl
+//!   format: jsonl
+//!   max_size_mb: 10
+//!   rotation: daily
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+use lumo::agent::Step;
+use serde::{Deserialize, Serialize};
+
+/// Where the steps are written when no path is configured.
+pub const DEFAULT_LOG_PATH: &str = "logs.txt";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Each step as indented JSON.
+    #[default]
+    Pretty,
+    /// Each step as a JSON object on its own line.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// One file, only rotated when it reaches the maximum size.
+    #[default]
+    Never,
+    /// A new file each day. The file of a previous day is renamed with its date.
+    Daily,
+}
+
+/// The `logs` section of the config file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// Set to false to not write the steps at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<LogFormat>,
+    /// The file is renamed with the current time and a new one started when it reaches this size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<LogRotation>,
+}
+
+/// The file the steps are written to, rotated by size or date.
+pub struct StepLog {
+    path: PathBuf,
+    format: LogFormat,
+    max_size: Option<u64>,
+    rotation: LogRotation,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl StepLog {
+    /// The log of the config, or `None` when it is disabled.
+    pub fn from_config(config: &LogConfig) -> Result<Option<Self>> {
+        if config.enabled == Some(false) {
+            return Ok(None);
+        }
+        let path = config
+            .path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LOG_PATH));
+        let (file, size) = open(&path)?;
+        Ok(Some(Self {
+            path,
+            format: config.format.unwrap_or_default(),
+            max_size: config.max_size_mb.map(|mb| mb * 1024 * 1024),
+            rotation: config.rotation.unwrap_or_default(),
+            file,
+            size,
+            opened_on: Local::now().date_naive(),
+        }))
+    }
+
+    pub fn write_step(&mut self, step: &Step) -> Result<()> {
+        let mut line = match self.format {
+            LogFormat::Pretty => serde_json::to_vec_pretty(step)?,
+            LogFormat::Jsonl => serde_json::to_vec(step)?,
+        };
+        line.push(b'\n');
+        self.rotate_if_needed(line.len() as u64)?;
+        self.file
+            .write_all(&line)
+            .with_context(|| format!("Failed to write to log file: {:?}", self.path))?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate_if_needed(&mut self, next_write: u64) -> Result<()> {
+        let today = Local::now().date_naive();
+        let suffix = if self.rotation == LogRotation::Daily && today != self.opened_on {
+            self.opened_on.format("%Y-%m-%d").to_string()
+        } else if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + next_write > max_size)
+        {
+            Local::now().format("%Y-%m-%dT%H-%M-%S%.3f").to_string()
+        } else {
+            return Ok(());
+        };
+        self.file.flush()?;
+        let rotated = rotated_path(&self.path, &suffix);
+        fs::rename(&self.path, &rotated)
+            .with_context(|| format!("Failed to rotate log file to {:?}", rotated))?;
+        (self.file, self.size) = open(&self.path)?;
+        self.opened_on = today;
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> Result<(File, u64)> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create log directory: {:?}", parent))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file: {:?}", path))?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// `logs.txt` rotated with the suffix `2025-01-31` is `logs.2025-01-31.txt`.
+fn rotated_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,410 @@
+//! The state of the terminal interface: the tasks of the session with their output, steps and tool calls, and the
+//! input line.
+
+use lumo::agent::{RunSummary, Step};
+use lumo::models::openai::Status;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::Question;
+use crate::i18n::{decimal, t, tf, Msg};
+
+/// Observations longer than this are shortened in the tool calls pane.
+const MAX_OBSERVATION_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    Input,
+    Output,
+    ToolCalls,
+}
+
+impl Focus {
+    fn next(self) -> Self {
+        match self {
+            Focus::Input => Focus::Output,
+            Focus::Output => Focus::ToolCalls,
+            Focus::ToolCalls => Focus::Input,
+        }
+    }
+}
+
+/// What the runner does after a key press.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    /// Run a task, or a command like `/export`.
+    Submit(String),
+    /// Stop the running task.
+    Abort,
+    Quit,
+}
+
+/// An entry of the step timeline.
+pub struct TimelineEntry {
+    pub label: String,
+    pub detail: String,
+    pub is_error: bool,
+}
+
+pub struct ToolCallEntry {
+    pub step: usize,
+    pub name: String,
+    pub arguments: String,
+    pub observation: Option<String>,
+    pub is_error: bool,
+    pub duration_ms: Option<u64>,
+    pub expanded: bool,
+}
+
+/// A task of the session and what the agent did for it.
+#[derive(Default)]
+pub struct Turn {
+    pub task: String,
+    pub reasoning: String,
+    /// The streamed output of the model, step after step.
+    pub output: String,
+    pub answer: Option<String>,
+    pub errors: Vec<String>,
+    pub timeline: Vec<TimelineEntry>,
+    pub tool_calls: Vec<ToolCallEntry>,
+    /// Steps, tool calls and tokens of the run, once it ended.
+    pub summary: Option<String>,
+}
+
+impl Turn {
+    fn new(task: String) -> Self {
+        Self {
+            task,
+            ..Default::default()
+        }
+    }
+
+    fn end_output_line(&mut self) {
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+    }
+}
+
+pub struct App {
+    pub turns: Vec<Turn>,
+    pub selected_turn: usize,
+    pub focus: Focus,
+    pub input: String,
+    /// The task of the history recalled in the input with Up and Down.
+    recalled_task: Option<usize>,
+    pub selected_tool_call: usize,
+    /// Lines scrolled from the top of the output. Follows the end of the output when `None`.
+    pub output_scroll: Option<u16>,
+    /// The scroll of the end of the output, set when it is drawn.
+    pub output_max_scroll: u16,
+    /// What the agent is doing, e.g. calling a tool.
+    pub activity: Option<String>,
+    pub running: bool,
+    /// The question of the agent waiting for an answer in the input.
+    pub question: Option<Question>,
+    /// A message of the last command, shown in the footer.
+    pub notice: Option<String>,
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self {
+            turns: Vec::new(),
+            selected_turn: 0,
+            focus: Focus::Input,
+            input: String::new(),
+            recalled_task: None,
+            selected_tool_call: 0,
+            output_scroll: None,
+            output_max_scroll: 0,
+            activity: None,
+            running: false,
+            question: None,
+            notice: None,
+        }
+    }
+
+    /// The turn shown in the panes.
+    pub fn turn(&self) -> Option<&Turn> {
+        self.turns.get(self.selected_turn)
+    }
+
+    fn current(&mut self) -> Option<&mut Turn> {
+        self.turns.last_mut()
+    }
+
+    /// Whether the panes show the running task, so they follow its output.
+    fn follows_current(&self) -> bool {
+        self.selected_turn + 1 >= self.turns.len()
+    }
+
+    pub fn start_turn(&mut self, task: String) {
+        self.turns.push(Turn::new(task));
+        self.select_turn(self.turns.len() - 1);
+        self.running = true;
+        self.notice = None;
+    }
+
+    pub fn end_turn(&mut self, summary: &RunSummary, aborted: bool) {
+        self.running = false;
+        self.activity = None;
+        if let Some(turn) = self.current() {
+            turn.summary = Some(format!(
+                "{}{}",
+                tf(
+                    Msg::TurnSummary,
+                    &[
+                        &summary.steps,
+                        &summary.tool_calls(),
+                        &summary.input_tokens,
+                        &summary.output_tokens,
+                        &decimal(summary.duration_ms as f64 / 1000.0, 1),
+                    ]
+                ),
+                if aborted { t(Msg::Aborted) } else { "" }
+            ));
+        }
+    }
+
+    pub fn ask(&mut self, question: Question) {
+        self.activity = Some(t(Msg::WaitingForAnswer).to_string());
+        self.focus = Focus::Input;
+        self.question = Some(question);
+    }
+
+    fn answer(&mut self, answer: Option<String>) {
+        if let Some(question) = self.question.take() {
+            let _ = question.answer.send(answer);
+            self.activity = None;
+        }
+    }
+
+    pub fn on_status(&mut self, status: Status) {
+        match status {
+            Status::FirstContent(content) | Status::Content(content) => {
+                if let Some(turn) = self.current() {
+                    turn.output.push_str(&content);
+                }
+            }
+            Status::Reasoning(content) => {
+                if let Some(turn) = self.current() {
+                    turn.reasoning.push_str(&content);
+                }
+            }
+            Status::ToolCallStart(name) => self.activity = Some(tf(Msg::Calling, &[&name])),
+            Status::CodeExecutionStart(_) => self.activity = Some(t(Msg::RunningCode).to_string()),
+            Status::ToolCallStarted { name, .. } => self.activity = Some(tf(Msg::Running, &[&name])),
+            Status::CodeExecutionEnd(_)
+            | Status::ToolCallResult { .. }
+            | Status::ToolCallFailed { .. } => self.activity = None,
+            Status::ManagedAgent { agent, .. } => {
+                self.activity = Some(tf(Msg::Running, &[&agent]))
+            }
+            Status::Error(message) => {
+                if let Some(turn) = self.current() {
+                    turn.errors.push(message);
+                }
+            }
+            Status::ToolCallContent(_)
+            | Status::ClarificationRequired(_)
+            | Status::Step(_)
+            | Status::Cancelled => {}
+        }
+    }
+
+    pub fn on_step(&mut self, step: &Step) {
+        let Some(turn) = self.turns.last_mut() else {
+            return;
+        };
+        match step {
+            Step::ActionStep(step) => {
+                turn.end_output_line();
+                let tool_calls = step.tool_call.as_deref().unwrap_or_default();
+                let mut detail = Vec::new();
+                if let Some(duration_ms) = step.duration_ms {
+                    detail.push(format!("{}s", decimal(duration_ms as f64 / 1000.0, 1)));
+                }
+                if !tool_calls.is_empty() {
+                    detail.push(
+                        tool_calls
+                            .iter()
+                            .map(|tool_call| tool_call.function.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    );
+                }
+                turn.timeline.push(TimelineEntry {
+                    label: tf(Msg::StepOf, &[&step.step]),
+                    detail: detail.join("  "),
+                    is_error: step.error.is_some(),
+                });
+                for (i, tool_call) in tool_calls.iter().enumerate() {
+                    let observation = step.observation_of(i);
+                    turn.tool_calls.push(ToolCallEntry {
+                        step: step.step,
+                        name: tool_call.function.name.clone(),
+                        arguments: serde_json::to_string_pretty(&tool_call.function.arguments)
+                            .unwrap_or_default(),
+                        observation: observation.map(|observation| shorten(&observation.content)),
+                        is_error: observation.is_some_and(|observation| observation.is_error),
+                        duration_ms: step
+                            .tool_timings
+                            .iter()
+                            .find(|timing| {
+                                timing.tool_call_id.is_some() && timing.tool_call_id == tool_call.id
+                            })
+                            .or(step.tool_timings.get(i))
+                            .map(|timing| timing.duration_ms),
+                        expanded: false,
+                    });
+                }
+                if let Some(error) = &step.error {
+                    turn.errors.push(error.to_string());
+                }
+                if let Some(answer) = &step.final_answer {
+                    turn.answer = Some(answer.clone());
+                }
+                self.activity = None;
+            }
+            Step::PlanningStep(_, plan) => {
+                turn.end_output_line();
+                turn.output.push_str(&format!("{}:\n{}\n", t(Msg::PlanHeading), plan));
+                turn.timeline.push(TimelineEntry {
+                    label: t(Msg::PlanHeading).to_string(),
+                    detail: String::new(),
+                    is_error: false,
+                });
+            }
+            Step::TaskStep(_) | Step::SystemPromptStep(_) | Step::ToolCall(_) => {}
+        }
+    }
+
+    fn select_turn(&mut self, index: usize) {
+        self.selected_turn = index;
+        self.selected_tool_call = 0;
+        self.output_scroll = None;
+    }
+
+    fn recall_task(&mut self, older: bool) {
+        if self.turns.is_empty() {
+            return;
+        }
+        let last = self.turns.len() - 1;
+        self.recalled_task = match (self.recalled_task, older) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.input = self
+            .recalled_task
+            .map(|i| self.turns[i].task.clone())
+            .unwrap_or_default();
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Action {
+        if (key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c'))
+            || key.code == KeyCode::Esc
+        {
+            if self.question.is_some() {
+                self.answer(None);
+                return Action::None;
+            }
+            return if self.running {
+                Action::Abort
+            } else {
+                Action::Quit
+            };
+        }
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = self.focus.next();
+                return Action::None;
+            }
+            KeyCode::PageUp => {
+                self.select_turn(self.selected_turn.saturating_sub(1));
+                return Action::None;
+            }
+            KeyCode::PageDown => {
+                if !self.follows_current() {
+                    self.select_turn(self.selected_turn + 1);
+                }
+                return Action::None;
+            }
+            _ => {}
+        }
+        match self.focus {
+            Focus::Input => match key.code {
+                KeyCode::Enter => {
+                    let input = std::mem::take(&mut self.input);
+                    self.recalled_task = None;
+                    if self.question.is_some() {
+                        self.answer(Some(input));
+                    } else if !self.running && !input.trim().is_empty() {
+                        return Action::Submit(input.trim().to_string());
+                    } else {
+                        self.input = input;
+                    }
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Up => self.recall_task(true),
+                KeyCode::Down => self.recall_task(false),
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            },
+            Focus::Output => match key.code {
+                KeyCode::Up => {
+                    let scroll = self.output_scroll.unwrap_or(self.output_max_scroll);
+                    self.output_scroll = Some(scroll.saturating_sub(1));
+                }
+                KeyCode::Down => {
+                    self.output_scroll = self
+                        .output_scroll
+                        .map(|scroll| scroll + 1)
+                        .filter(|scroll| *scroll < self.output_max_scroll);
+                }
+                KeyCode::Home => self.output_scroll = Some(0),
+                KeyCode::End => self.output_scroll = None,
+                _ => {}
+            },
+            Focus::ToolCalls => {
+                let count = self.turn().map_or(0, |turn| turn.tool_calls.len());
+                match key.code {
+                    KeyCode::Up => {
+                        self.selected_tool_call = self.selected_tool_call.saturating_sub(1)
+                    }
+                    KeyCode::Down if self.selected_tool_call + 1 < count => {
+                        self.selected_tool_call += 1
+                    }
+                    KeyCode::Enter | KeyCode::Char(' ') => {
+                        let selected = self.selected_tool_call;
+                        if let Some(tool_call) = self
+                            .turns
+                            .get_mut(self.selected_turn)
+                            .and_then(|turn| turn.tool_calls.get_mut(selected))
+                        {
+                            tool_call.expanded = !tool_call.expanded;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Action::None
+    }
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() > MAX_OBSERVATION_CHARS {
+        format!(
+            "{}\n... (shortened)",
+            text.chars().take(MAX_OBSERVATION_CHARS).collect::<String>()
+        )
+    } else {
+        text.to_string()
+    }
+}
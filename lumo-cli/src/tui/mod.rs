@@ -0,0 +1,205 @@
+//! The terminal interface of `lumo --tui`: the streamed output of the agent, the timeline of its steps, its tool calls
+//! with their arguments and observations, and the earlier tasks of the session, in panes that are redrawn as the
+//! agent runs.
+
+mod app;
+mod ui;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use lumo::models::model_traits::Model;
+use lumo::models::openai::Status;
+use lumo::tools::UserInput;
+use opentelemetry::trace::FutureExt;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::i18n::{t, tf, Msg};
+use crate::{AgentWrapper, Session};
+use app::{Action, App};
+
+/// A question of the `ask_user` tool, answered in the input line. The answer is `None` when the user dismisses it.
+pub struct Question {
+    pub text: String,
+    pub answer: oneshot::Sender<Option<String>>,
+}
+
+/// Sends the questions of the agent to the terminal interface.
+#[derive(Clone)]
+pub struct TuiUserInput {
+    questions: mpsc::UnboundedSender<Question>,
+}
+
+#[async_trait]
+impl UserInput for TuiUserInput {
+    async fn ask(&self, question: &str) -> Result<Option<String>> {
+        let (answer, answered) = oneshot::channel();
+        let question = Question {
+            text: question.to_string(),
+            answer,
+        };
+        if self.questions.send(question).is_err() {
+            return Ok(None);
+        }
+        Ok(answered.await.unwrap_or(None))
+    }
+}
+
+/// The input of the `ask_user` tool, and the questions it sends to [`run`].
+pub fn questions() -> (TuiUserInput, mpsc::UnboundedReceiver<Question>) {
+    let (questions, receiver) = mpsc::unbounded_channel();
+    (TuiUserInput { questions }, receiver)
+}
+
+/// Read the terminal events in a thread, until the interface stops.
+fn read_events() -> mpsc::UnboundedReceiver<Event> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while !tx.is_closed() {
+            match event::poll(Duration::from_millis(100)) {
+                Ok(true) => match event::read() {
+                    Ok(event) => {
+                        let _ = tx.send(event);
+                    }
+                    Err(_) => break,
+                },
+                Ok(false) => {}
+                Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+/// Run the tasks typed in the interface until the user quits.
+pub async fn run<M: Model + Send + Sync + std::fmt::Debug + 'static>(
+    agent: &mut AgentWrapper<M>,
+    session: &mut Session,
+    mut questions: mpsc::UnboundedReceiver<Question>,
+) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let mut events = read_events();
+    let result = run_app(&mut terminal, agent, session, &mut events, &mut questions).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_app<M: Model + Send + Sync + std::fmt::Debug + 'static>(
+    terminal: &mut DefaultTerminal,
+    agent: &mut AgentWrapper<M>,
+    session: &mut Session,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+    questions: &mut mpsc::UnboundedReceiver<Question>,
+) -> Result<()> {
+    let mut app = App::new();
+    loop {
+        terminal.draw(|frame| ui::draw(frame, &mut app))?;
+        let Some(event) = events.recv().await else {
+            return Ok(());
+        };
+        let Event::Key(key) = event else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.on_key(key) {
+            Action::Quit => return Ok(()),
+            Action::Submit(task) if task == "exit" => return Ok(()),
+            Action::Submit(task) if task == "/export" || task.starts_with("/export ") => {
+                app.notice = Some(match session.export(task.trim_start_matches("/export")) {
+                    Ok(path) => format!("{} {}", t(Msg::ReportWritten), path.display()),
+                    Err(e) => format!("{} {}", t(Msg::Error), e),
+                });
+            }
+            Action::Submit(task) => {
+                if run_task(terminal, &mut app, agent, session, events, questions, task).await? {
+                    return Ok(());
+                }
+            }
+            Action::Abort | Action::None => {}
+        }
+    }
+}
+
+/// Run a task while the interface shows its progress. Returns whether the user quit.
+async fn run_task<M: Model + Send + Sync + std::fmt::Debug + 'static>(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    agent: &mut AgentWrapper<M>,
+    session: &mut Session,
+    events: &mut mpsc::UnboundedReceiver<Event>,
+    questions: &mut mpsc::UnboundedReceiver<Question>,
+    task: String,
+) -> Result<bool> {
+    let context = session.start_task(&task);
+    app.start_turn(task.clone());
+    let (tx, mut rx) = broadcast::channel::<Status>(1024);
+    let mut stream = agent.stream_run(&task, false, Some(tx), None)?;
+    let mut final_answer = String::new();
+    let mut statuses_open = true;
+    let mut aborted = false;
+    let mut quit = false;
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        let next_step = async {
+            match &context {
+                Some(context) => stream.next().with_context(context.clone()).await,
+                None => stream.next().await,
+            }
+        };
+        tokio::select! {
+            step = next_step => match step {
+                Some(Ok(step)) => {
+                    session.record_step(&step)?;
+                    app.on_step(&step);
+                    if let lumo::agent::Step::ActionStep(step) = &step {
+                        if let Some(answer) = &step.final_answer {
+                            final_answer = answer.clone();
+                        }
+                    }
+                }
+                Some(Err(e)) => app.on_status(Status::Error(e.to_string())),
+                None => break,
+            },
+            status = rx.recv(), if statuses_open => match status {
+                Ok(status) => app.on_status(status),
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => statuses_open = false,
+            },
+            Some(question) = questions.recv() => app.ask(question),
+            event = events.recv() => match event {
+                Some(Event::Key(key)) if key.kind == KeyEventKind::Press => match app.on_key(key) {
+                    Action::Abort => {
+                        aborted = true;
+                        break;
+                    }
+                    Action::Quit => {
+                        quit = true;
+                        break;
+                    }
+                    Action::Submit(_) | Action::None => {}
+                },
+                Some(_) => {}
+                None => {
+                    quit = true;
+                    break;
+                }
+            },
+        }
+    }
+    // Dropping the stream stops the run.
+    drop(stream);
+    app.end_turn(&agent.run_summary(), aborted || quit);
+    if let Err(e) = session.end_task(context, final_answer) {
+        app.notice = Some(tf(Msg::ErrorWritingReport, &[&e]));
+    }
+    if let Err(e) = session.save_manifest(agent.run_manifest()) {
+        app.notice = Some(tf(Msg::ErrorWritingManifest, &[&e]));
+    }
+    Ok(quit)
+}
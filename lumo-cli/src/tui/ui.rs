@@ -0,0 +1,237 @@
+//! Drawing of the panes of the terminal interface.
+
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Wrap};
+use ratatui::Frame;
+
+use super::app::{App, Focus, Turn};
+use crate::i18n::{decimal, t, tf, Msg};
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, input, footer] = Layout::vertical([
+        Constraint::Min(5),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [conversation, side] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+    let [timeline, tool_calls] =
+        Layout::vertical([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(side);
+
+    draw_conversation(frame, app, conversation);
+    draw_timeline(frame, app, timeline);
+    draw_tool_calls(frame, app, tool_calls);
+    draw_input(frame, app, input);
+
+    let footer_text = match &app.notice {
+        Some(notice) => Line::from(notice.as_str()).yellow(),
+        None => Line::from(t(Msg::TuiHelp)).dark_gray(),
+    };
+    frame.render_widget(Paragraph::new(footer_text), footer);
+}
+
+fn pane(title: String, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(title);
+    if focused {
+        block.border_style(Style::default().fg(Color::Cyan))
+    } else {
+        block
+    }
+}
+
+/// The number of lines of the text once wrapped to the width.
+fn wrapped_height(text: &Text, width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    text.lines
+        .iter()
+        .map(|line| line.width().max(1).div_ceil(width) as u16)
+        .sum()
+}
+
+fn conversation_text(turn: &Turn) -> Text<'_> {
+    let mut lines = vec![Line::from(vec![
+        Span::from("> ").cyan().bold(),
+        Span::from(turn.task.as_str()).bold(),
+    ])];
+    lines.push(Line::default());
+    lines.extend(
+        turn.reasoning
+            .lines()
+            .map(|line| Line::from(line).dark_gray().italic()),
+    );
+    lines.extend(turn.output.lines().map(Line::from));
+    if let Some(answer) = turn
+        .answer
+        .as_deref()
+        .filter(|answer| !turn.output.trim_end().ends_with(answer.trim()))
+    {
+        lines.push(Line::default());
+        lines.extend(answer.lines().map(|line| Line::from(line).green()));
+    }
+    for error in &turn.errors {
+        lines.push(Line::from(format!("{} {}", t(Msg::Error), error)).red());
+    }
+    if let Some(summary) = &turn.summary {
+        lines.push(Line::default());
+        lines.push(Line::from(summary.as_str()).dark_gray());
+    }
+    Text::from(lines)
+}
+
+fn draw_conversation(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = match app.turns.len() {
+        0 => t(Msg::Conversation).to_string(),
+        count => tf(Msg::ConversationTask, &[&(app.selected_turn + 1), &count]),
+    };
+    let block = pane(title, app.focus == Focus::Output);
+    let text = match app.turn() {
+        Some(turn) => conversation_text(turn),
+        None => Text::from(t(Msg::TypeTask)).dark_gray(),
+    };
+    let inner = block.inner(area);
+    let max_scroll = wrapped_height(&text, inner.width).saturating_sub(inner.height);
+    let scroll = app
+        .output_scroll
+        .map_or(max_scroll, |scroll| scroll.min(max_scroll));
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll, 0));
+    frame.render_widget(paragraph, area);
+    app.output_max_scroll = max_scroll;
+}
+
+fn draw_timeline(frame: &mut Frame, app: &App, area: Rect) {
+    let mut items = app
+        .turn()
+        .map(|turn| {
+            turn.timeline
+                .iter()
+                .map(|entry| {
+                    let label = Span::from(format!("{:<8}", entry.label));
+                    let label = if entry.is_error {
+                        label.red().bold()
+                    } else {
+                        label.bold()
+                    };
+                    ListItem::new(Line::from(vec![label, Span::from(entry.detail.as_str())]))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if let Some(activity) = app.activity.as_ref().filter(|_| app.running) {
+        items.push(ListItem::new(
+            Line::from(format!("… {}", activity)).yellow(),
+        ));
+    }
+    // The latest steps stay visible.
+    let height = area.height.saturating_sub(2) as usize;
+    let items = items.split_off(items.len().saturating_sub(height));
+    frame.render_widget(
+        List::new(items).block(pane(t(Msg::Steps).to_string(), false)),
+        area,
+    );
+}
+
+fn draw_tool_calls(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::ToolCalls;
+    let block = pane(t(Msg::ToolCalls).to_string(), focused);
+    let inner = block.inner(area);
+    let mut lines = Vec::new();
+    let mut selected = (0, 0);
+    for (i, tool_call) in app
+        .turn()
+        .map(|turn| turn.tool_calls.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let start = lines.len();
+        let marker = if tool_call.expanded { "▾" } else { "▸" };
+        let duration = tool_call
+            .duration_ms
+            .map(|duration_ms| format!(" {}s", decimal(duration_ms as f64 / 1000.0, 1)))
+            .unwrap_or_default();
+        let mut header = Line::from(vec![
+            Span::from(format!("{} ", marker)),
+            Span::from(tool_call.name.as_str()).bold(),
+            Span::from(format!("  {}{}", tf(Msg::StepOf, &[&tool_call.step]), duration)).dark_gray(),
+        ]);
+        if tool_call.is_error {
+            header = header.red();
+        }
+        if focused && i == app.selected_tool_call {
+            header = header.add_modifier(Modifier::REVERSED);
+        }
+        lines.push(header);
+        if tool_call.expanded {
+            lines.push(Line::from(t(Msg::Arguments)).cyan());
+            lines.extend(
+                tool_call
+                    .arguments
+                    .lines()
+                    .map(|line| Line::from(format!("    {}", line))),
+            );
+            lines.push(Line::from(t(Msg::Observation)).cyan());
+            let observation = tool_call
+                .observation
+                .as_deref()
+                .unwrap_or(t(Msg::NoObservation));
+            lines.extend(
+                observation
+                    .lines()
+                    .map(|line| Line::from(format!("    {}", line))),
+            );
+        }
+        if i == app.selected_tool_call {
+            selected = (start, lines.len());
+        }
+    }
+    // Scroll so that the selected tool call is visible, from its start when it is longer than the pane.
+    let height = inner.height as usize;
+    let scroll = if selected.1 > height {
+        selected.0.min(selected.1 - height)
+    } else {
+        0
+    };
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .scroll((scroll as u16, 0));
+    frame.render_widget(paragraph, area);
+}
+
+fn draw_input(frame: &mut Frame, app: &App, area: Rect) {
+    let title = match &app.question {
+        Some(question) => format!(" {} ", question.text),
+        None if app.running => t(Msg::TaskRunning).to_string(),
+        None => t(Msg::TaskPane).to_string(),
+    };
+    let mut block = pane(title, app.focus == Focus::Input);
+    if app.question.is_some() {
+        block = block.title_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        );
+    }
+    let inner = block.inner(area);
+    // Only the end of a long input is shown.
+    let visible = app
+        .input
+        .chars()
+        .skip(
+            app.input
+                .chars()
+                .count()
+                .saturating_sub(inner.width.saturating_sub(1) as usize),
+        )
+        .collect::<String>();
+    let width = visible.chars().count() as u16;
+    frame.render_widget(Paragraph::new(visible).block(block), area);
+    if app.focus == Focus::Input {
+        frame.set_cursor_position(Position::new(inner.x + width, inner.y));
+    }
+}
@@ -39,6 +39,8 @@ async fn main() -> Result<(), anyhow::Error> {
         .run("List the directories in the current directory!", false)
         .await
         .unwrap();
+    // Stop the MCP server
+    agent.shutdown().await?;
 
     Ok(())
 }
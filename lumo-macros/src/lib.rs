@@ -0,0 +1,146 @@
+//! Derive macros for lumo. Use them through the `macros` feature of `lumo`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Expr, ExprLit, Lit, LitStr, Meta, Path, Type};
+
+/// Implement `lumo::tools::Tool` for a struct.
+///
+/// The struct must also implement `Clone` to be used as an `AnyTool` or `AsyncTool`. The tool is configured with
+/// the `#[tool(...)]` attribute:
+///
+/// - `params` (required): the parameters of the tool, a type deriving `Deserialize` and `JsonSchema`.
+/// - `name`: the name of the tool. Defaults to the struct name in snake case.
+/// - `description`: the description of the tool. Defaults to the doc comment of the struct.
+/// - `forward`: an async function `fn(&Self, Params) -> anyhow::Result<String>` called when the tool is used.
+///   Defaults to the `run` method of the struct.
+///
+/// ```rust,ignore
+/// use lumo::tools::LumoTool;
+/// use schemars::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// struct WeatherParams {
+///     #[schemars(description = "The city to get the weather for")]
+///     city: String,
+/// }
+///
+/// /// Get the current weather for a city.
+/// #[derive(Clone, LumoTool)]
+/// #[tool(name = "get_weather", params = WeatherParams)]
+/// struct WeatherTool;
+///
+/// impl WeatherTool {
+///     async fn run(&self, params: WeatherParams) -> anyhow::Result<String> {
+///         Ok(format!("It is sunny in {}", params.city))
+///     }
+/// }
+/// ```
+#[proc_macro_derive(LumoTool, attributes(tool))]
+pub fn derive_lumo_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_lumo_tool(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_lumo_tool(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut name: Option<LitStr> = None;
+    let mut description: Option<LitStr> = None;
+    let mut params: Option<Type> = None;
+    let mut forward: Option<Path> = None;
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("tool")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("description") {
+                description = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("params") {
+                params = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("forward") {
+                forward = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `name`, `description`, `params` or `forward`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let ident = &input.ident;
+    let params = params.ok_or_else(|| {
+        syn::Error::new_spanned(ident, "missing `#[tool(params = ...)]` attribute")
+    })?;
+    let name = name
+        .map(|name| name.value())
+        .unwrap_or_else(|| to_snake_case(&ident.to_string()));
+    let description = match description {
+        Some(description) => description.value(),
+        None => doc_comment(&input.attrs).ok_or_else(|| {
+            syn::Error::new_spanned(
+                ident,
+                "missing tool description: add a doc comment or `#[tool(description = \"...\")]`",
+            )
+        })?,
+    };
+    let forward = match forward {
+        Some(forward) => quote! { #forward(self, arguments).await },
+        None => quote! { self.run(arguments).await },
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #[cfg_attr(not(target_arch = "wasm32"), ::lumo::__private::async_trait::async_trait)]
+        #[cfg_attr(target_arch = "wasm32", ::lumo::__private::async_trait::async_trait(?Send))]
+        impl #impl_generics ::lumo::tools::Tool for #ident #ty_generics #where_clause {
+            type Params = #params;
+
+            fn name(&self) -> &'static str {
+                #name
+            }
+
+            fn description(&self) -> &'static str {
+                #description
+            }
+
+            async fn forward(&self, arguments: #params) -> ::lumo::__private::anyhow::Result<String> {
+                #forward
+            }
+        }
+    })
+}
+
+/// The doc comment of the item, with its lines joined.
+fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(doc.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let doc = lines.join(" ").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,377 @@
+//! Pool of built agents shared by the runs, so the agent of a request is not built again for each request with the
+//! same settings.
+//!
+//! The agents are keyed by a hash of the settings they were built from. An agent serves one run at a time: a run
+//! leases an idle agent of its key, or builds one and adds it to the pool. A reused agent starts from a clean memory
+//! with the history of its request. The least recently used idle agent is dropped when the pool is full, and the
+//! agents idle for longer than the idle timeout are dropped in the background.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lumo::{agent::Agent, models::types::Message};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OwnedMutexGuard;
+
+/// Agents kept, busy or idle, unless `max_agents` is set.
+const DEFAULT_MAX_AGENTS: usize = 32;
+
+/// Seconds an idle agent is kept, unless `idle_timeout` is set.
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+
+/// How often the idle agents are checked for expiry.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The `agent_pool` section of servers.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentPoolConfig {
+    /// Maximum number of agents kept, busy or idle. The runs beyond it build their own agent, dropped at the end of
+    /// the run. With `0`, the agents are not pooled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_agents: Option<usize>,
+    /// Seconds an idle agent is kept for the next runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u64>,
+}
+
+/// The key of the agents built from `settings`, e.g. the settings of a request serialized as JSON.
+pub fn key(settings: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    settings.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct PooledAgent<A> {
+    key: u64,
+    agent: Arc<tokio::sync::Mutex<A>>,
+    last_used: Arc<Mutex<Instant>>,
+}
+
+impl<A> PooledAgent<A> {
+    fn is_idle(&self) -> bool {
+        self.agent.try_lock().is_ok()
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap().elapsed()
+    }
+}
+
+/// The agents of a type, e.g. the function calling agents, by key.
+pub struct AgentPool<A> {
+    config: Arc<AgentPoolConfig>,
+    agents: Arc<Mutex<Vec<PooledAgent<A>>>>,
+}
+
+impl<A> Clone for AgentPool<A> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            agents: self.agents.clone(),
+        }
+    }
+}
+
+/// An agent in use by a run. It goes back to its pool, if it has one, when the lease is dropped.
+pub struct AgentLease<A> {
+    agent: OwnedMutexGuard<A>,
+    last_used: Arc<Mutex<Instant>>,
+}
+
+impl<A> AgentLease<A> {
+    /// A lease of an agent outside of the pools, dropped with the lease.
+    pub fn unpooled(agent: A) -> Self {
+        Self {
+            agent: Arc::new(tokio::sync::Mutex::new(agent))
+                .try_lock_owned()
+                .expect("a new mutex is unlocked"),
+            last_used: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+}
+
+impl<A> Deref for AgentLease<A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.agent
+    }
+}
+
+impl<A> DerefMut for AgentLease<A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.agent
+    }
+}
+
+impl<A> Drop for AgentLease<A> {
+    fn drop(&mut self) {
+        // The agent is idle from now on, its guard is dropped right after.
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+}
+
+impl<A: Send + 'static> AgentPool<A> {
+    pub fn new(config: AgentPoolConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            agents: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn max_agents(&self) -> usize {
+        self.config.max_agents.unwrap_or(DEFAULT_MAX_AGENTS)
+    }
+
+    fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.config
+                .idle_timeout
+                .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS),
+        )
+    }
+
+    /// Drop the agents idle for longer than the idle timeout, in the background.
+    pub fn start(&self) {
+        let pool = self.clone();
+        actix_web::rt::spawn(async move {
+            loop {
+                actix_web::rt::time::sleep(SWEEP_INTERVAL).await;
+                drop(pool.take_expired());
+            }
+        });
+    }
+
+    fn take_expired(&self) -> Vec<PooledAgent<A>> {
+        let idle_timeout = self.idle_timeout();
+        let mut agents = self.agents.lock().unwrap();
+        let (expired, kept) = std::mem::take(&mut *agents)
+            .into_iter()
+            .partition(|agent| agent.is_idle() && agent.idle_for() >= idle_timeout);
+        *agents = kept;
+        expired
+    }
+
+    /// An idle agent of the key that has not expired, the most recently used one.
+    pub fn lease(&self, key: u64) -> Option<AgentLease<A>> {
+        let idle_timeout = self.idle_timeout();
+        let mut agents = self.agents.lock().unwrap();
+        agents.retain(|agent| !(agent.is_idle() && agent.idle_for() >= idle_timeout));
+        agents
+            .iter()
+            .filter(|agent| agent.key == key)
+            .filter_map(|agent| {
+                let guard = agent.agent.clone().try_lock_owned().ok()?;
+                Some((agent.idle_for(), guard, agent.last_used.clone()))
+            })
+            .min_by_key(|(idle_for, _, _)| *idle_for)
+            .map(|(_, agent, last_used)| AgentLease { agent, last_used })
+    }
+
+    /// Add the agent to the pool and lease it. When the pool is full, the least recently used idle agent is dropped
+    /// for it. When all the agents are busy, the agent is not pooled and is dropped with the lease.
+    pub fn insert(&self, key: u64, agent: A) -> AgentLease<A> {
+        let max_agents = self.max_agents();
+        let mut agents = self.agents.lock().unwrap();
+        if max_agents == 0 {
+            return AgentLease::unpooled(agent);
+        }
+        if agents.len() >= max_agents {
+            let oldest = agents
+                .iter()
+                .enumerate()
+                .filter(|(_, agent)| agent.is_idle())
+                .max_by_key(|(_, agent)| agent.idle_for())
+                .map(|(i, _)| i);
+            match oldest {
+                Some(i) => {
+                    agents.swap_remove(i);
+                }
+                None => return AgentLease::unpooled(agent),
+            }
+        }
+        let pooled = PooledAgent {
+            key,
+            agent: Arc::new(tokio::sync::Mutex::new(agent)),
+            last_used: Arc::new(Mutex::new(Instant::now())),
+        };
+        let lease = AgentLease {
+            agent: pooled
+                .agent
+                .clone()
+                .try_lock_owned()
+                .expect("a new mutex is unlocked"),
+            last_used: pooled.last_used.clone(),
+        };
+        agents.push(pooled);
+        lease
+    }
+}
+
+impl<A: Agent + 'static> AgentPool<A> {
+    /// An agent for a run: an idle agent of the key, reset for the run with `history`, or else the agent of `build`
+    /// added to the pool. With no key, the agent is built for the run only.
+    pub fn get_or_build<E>(
+        &self,
+        key: Option<u64>,
+        history: Option<Vec<Message>>,
+        build: impl FnOnce() -> Result<A, E>,
+    ) -> Result<AgentLease<A>, E> {
+        let Some(key) = key else {
+            return build().map(AgentLease::unpooled);
+        };
+        match self.lease(key) {
+            Some(mut agent) => {
+                reset(&mut *agent, history);
+                Ok(agent)
+            }
+            None => Ok(self.insert(key, build()?)),
+        }
+    }
+}
+
+/// Clear what a run left in the agent, so the next run starts as with a new agent.
+fn reset<A: Agent>(agent: &mut A, history: Option<Vec<Message>>) {
+    agent.get_logs_mut().clear();
+    agent.reset_step_number();
+    agent.set_history(history);
+    agent.set_checkpoint_id(None);
+    agent.reset_session();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumo::{
+        agent::{FunctionCallingAgent, FunctionCallingAgentBuilder},
+        models::{
+            mock::{MockModel, MockResponse},
+            types::MessageRole,
+        },
+    };
+
+    impl<A> AgentPool<A> {
+        fn len(&self) -> usize {
+            self.agents.lock().unwrap().len()
+        }
+    }
+
+    fn pool(max_agents: usize) -> AgentPool<u32> {
+        AgentPool::new(AgentPoolConfig {
+            max_agents: Some(max_agents),
+            idle_timeout: None,
+        })
+    }
+
+    #[test]
+    fn test_lease() {
+        let pool = pool(2);
+        assert!(pool.lease(1).is_none());
+
+        let lease = pool.insert(1, 10);
+        assert_eq!(*lease, 10);
+        // A busy agent is not leased again.
+        assert!(pool.lease(1).is_none());
+        drop(lease);
+
+        let mut lease = pool.lease(1).unwrap();
+        *lease += 1;
+        drop(lease);
+        assert_eq!(*pool.lease(1).unwrap(), 11);
+        assert!(pool.lease(2).is_none());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let pool = pool(2);
+        drop(pool.insert(1, 10));
+        std::thread::sleep(Duration::from_millis(5));
+        let busy = pool.insert(2, 20);
+        // The least recently used idle agent makes room for the new one.
+        drop(pool.insert(3, 30));
+        assert_eq!(pool.len(), 2);
+        assert!(pool.lease(1).is_none());
+        assert_eq!(*pool.lease(3).unwrap(), 30);
+
+        // When all the agents are busy, the new one is not pooled.
+        let other = pool.lease(3).unwrap();
+        let unpooled = pool.insert(4, 40);
+        assert_eq!(*unpooled, 40);
+        assert_eq!(pool.len(), 2);
+        drop((busy, other, unpooled));
+        assert!(pool.lease(4).is_none());
+
+        let off = self::pool(0);
+        drop(off.insert(1, 10));
+        assert_eq!(off.len(), 0);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let pool = AgentPool::new(AgentPoolConfig {
+            max_agents: None,
+            idle_timeout: Some(0),
+        });
+        let busy = pool.insert(1, 10);
+        drop(pool.insert(2, 20));
+        assert!(pool.lease(2).is_none());
+        // Busy agents do not expire.
+        assert_eq!(pool.take_expired().len(), 0);
+        drop(busy);
+        assert_eq!(pool.take_expired().len(), 1);
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_build() {
+        let pool: AgentPool<FunctionCallingAgent<MockModel>> = AgentPool::new(Default::default());
+        let build = || {
+            FunctionCallingAgentBuilder::new(MockModel::new(vec![
+                MockResponse::text("Paris"),
+                MockResponse::text("Rome"),
+            ]))
+            .build()
+        };
+
+        let mut agent = pool.get_or_build(Some(1), None, build).unwrap();
+        assert_eq!(
+            agent
+                .run("What is the capital of France?", false)
+                .await
+                .unwrap(),
+            "Paris"
+        );
+        agent.set_checkpoint_id(Some("run-1".to_string()));
+        drop(agent);
+
+        let history = vec![Message::new(MessageRole::User, "Hello")];
+        let mut agent = pool
+            .get_or_build(Some(1), Some(history), || -> anyhow::Result<_> {
+                panic!("the pooled agent is reused")
+            })
+            .unwrap();
+        assert!(agent.get_logs_mut().is_empty());
+        assert!(agent.checkpoint_id().is_none());
+        assert_eq!(
+            agent
+                .run("What is the capital of Italy?", false)
+                .await
+                .unwrap(),
+            "Rome"
+        );
+        // The memory of the first run is gone.
+        let memory = agent.get_memory().unwrap();
+        assert!(!memory
+            .iter()
+            .any(|message| message.content.contains("France")));
+
+        // Without a key, the agent is not pooled.
+        drop(pool.get_or_build(None, None, build).unwrap());
+        assert_eq!(pool.len(), 1);
+    }
+}
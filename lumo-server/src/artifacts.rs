@@ -0,0 +1,131 @@
+//! Figures and dataframes produced by the code of a run, and spoken answers, downloadable from `/jobs/{id}/artifacts/{name}`.
+//!
+//! The artifacts are stored with their job. The job and `/run` responses only list them, with their download link.
+
+use actix_web::{get, http::header, web, HttpResponse, Responder};
+use base64::Engine;
+use lumo::agent::{Artifact, ArtifactKind};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{auth::Caller, jobs::JobQueue};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct JobArtifact {
+    pub name: String,
+    /// `image` for a figure, `table` for a dataframe, `audio` for a spoken answer.
+    #[schema(value_type = String)]
+    pub kind: ArtifactKind,
+    pub mime_type: String,
+    /// Size of the file in bytes.
+    pub size: usize,
+    /// A markdown preview of a table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// Where to download the file.
+    pub url: String,
+    /// The content of the artifact as produced by the run. Only stored, never returned.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    #[schema(ignore)]
+    pub data: String,
+}
+
+impl JobArtifact {
+    pub fn new(job_id: &str, artifact: Artifact) -> Self {
+        let mut artifact = Self {
+            url: format!("/jobs/{}/artifacts/{}", job_id, artifact.name),
+            name: artifact.name,
+            kind: artifact.kind,
+            mime_type: artifact.mime_type,
+            size: 0,
+            preview: artifact.preview,
+            data: artifact.data,
+        };
+        artifact.size = artifact.bytes().len();
+        artifact
+    }
+
+    /// The content of the file: the decoded PNG of an image or file of audio, the CSV of a table.
+    pub fn bytes(&self) -> Vec<u8> {
+        match self.kind {
+            ArtifactKind::Image | ArtifactKind::Audio => base64::engine::general_purpose::STANDARD
+                .decode(&self.data)
+                .unwrap_or_default(),
+            ArtifactKind::Table => self.data.as_bytes().to_vec(),
+        }
+    }
+
+    /// The artifact without its content, as listed in the responses.
+    pub fn link(&self) -> Self {
+        Self {
+            data: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(
+        ("id" = String, Path, description = "The job id"),
+        ("name" = String, Path, description = "The artifact name"),
+    ),
+    responses(
+        (status = 200, description = "The file, e.g. a PNG figure or a CSV table"),
+        (status = 404, description = "The job or the artifact does not exist"),
+    )
+)]
+#[get("/jobs/{id}/artifacts/{name}")]
+pub(crate) async fn get_artifact(
+    jobs: web::Data<JobQueue>,
+    caller: Caller,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder, actix_web::Error> {
+    let (id, name) = path.into_inner();
+    let job = jobs
+        .get(&id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some(artifact) = job
+        .filter(|job| caller.owns(job.request.caller.as_deref()))
+        .and_then(|job| job.artifacts.into_iter().find(|a| a.name == name))
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(artifact.mime_type.as_str())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", artifact.name),
+        ))
+        .body(artifact.bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_artifact() {
+        let figure = JobArtifact::new(
+            "job-1",
+            Artifact {
+                name: "figure_1.png".to_string(),
+                kind: ArtifactKind::Image,
+                mime_type: "image/png".to_string(),
+                data: "cG5n".to_string(),
+                preview: None,
+            },
+        );
+        assert_eq!(figure.url, "/jobs/job-1/artifacts/figure_1.png");
+        assert_eq!(figure.bytes(), b"png");
+        assert_eq!(figure.size, 3);
+
+        let link = serde_json::to_value(figure.link()).unwrap();
+        assert_eq!(link["kind"], "image");
+        assert!(link.get("data").is_none());
+        // The stored artifact keeps its content.
+        let stored: JobArtifact = serde_json::from_value(serde_json::to_value(&figure).unwrap()).unwrap();
+        assert_eq!(stored.data, "cG5n");
+    }
+}
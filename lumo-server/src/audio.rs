@@ -0,0 +1,229 @@
+//! Speech-to-text for the tasks of voice frontends, and text-to-speech for their answers.
+//!
+//! `POST /transcribe` turns a recording into text, to send as the `task` of a run. A run with `speak_answer` also
+//! renders its answer as speech, returned as an `audio` artifact. Both use the `audio` provider of servers.yaml.
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use futures::StreamExt;
+use lumo::agent::{Artifact, ArtifactKind};
+use reqwest::multipart;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::{AudioConfig, Servers};
+
+/// The largest recording accepted, the limit of the Whisper API.
+const MAX_AUDIO_BYTES: usize = 25 * 1024 * 1024;
+
+/// Calls the audio endpoints of the provider.
+pub(crate) struct AudioClient {
+    config: AudioConfig,
+    api_key: String,
+}
+
+impl AudioClient {
+    /// The client of the `audio` provider of servers.yaml.
+    pub fn from_config() -> Result<Self> {
+        let config = Servers::load()?.audio.unwrap_or_default();
+        Self::new(config)
+    }
+
+    pub fn new(config: AudioConfig) -> Result<Self> {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Audio is not configured: set audio.api_key in servers.yaml or OPENAI_API_KEY"
+                )
+            })?;
+        Ok(Self { config, api_key })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.config.base_url.trim_end_matches('/'), path)
+    }
+
+    /// The text of a recording. `extension` tells the provider its format.
+    pub async fn transcribe(
+        &self,
+        audio: Vec<u8>,
+        extension: &str,
+        language: Option<&str>,
+    ) -> Result<String> {
+        let mut form = multipart::Form::new()
+            .text("model", self.config.stt_model.clone())
+            .part(
+                "file",
+                multipart::Part::bytes(audio).file_name(format!("audio.{}", extension)),
+            );
+        if let Some(language) = language {
+            form = form.text("language", language.to_string());
+        }
+        let response = reqwest::Client::new()
+            .post(self.url("audio/transcriptions"))
+            .bearer_auth(&self.api_key)
+            .multipart(form)
+            .send()
+            .await
+            .context("Failed to call the transcription API")?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Transcription failed: HTTP {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let body: serde_json::Value = response.json().await?;
+        body["text"]
+            .as_str()
+            .map(|text| text.trim().to_string())
+            .ok_or_else(|| anyhow!("The transcription API returned no text"))
+    }
+
+    /// The answer spoken, as an artifact in the format of the config.
+    pub async fn speak(&self, text: &str) -> Result<Artifact> {
+        let response = reqwest::Client::new()
+            .post(self.url("audio/speech"))
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({
+                "model": self.config.tts_model,
+                "voice": self.config.voice,
+                "input": text,
+                "response_format": self.config.format,
+            }))
+            .send()
+            .await
+            .context("Failed to call the speech API")?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Speech failed: HTTP {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let audio = response.bytes().await?;
+        Ok(Artifact {
+            name: format!("answer.{}", self.config.format),
+            kind: ArtifactKind::Audio,
+            mime_type: mime_type(&self.config.format).to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(audio),
+            preview: None,
+        })
+    }
+}
+
+/// The file extension of a recording by its content type, for the formats the Whisper API accepts.
+fn extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    Some(match mime.to_lowercase().as_str() {
+        "audio/webm" | "video/webm" => "webm",
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" | "video/mp4" => "m4a",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/ogg" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        _ => return None,
+    })
+}
+
+fn mime_type(format: &str) -> &'static str {
+    match format {
+        "opus" => "audio/ogg",
+        "aac" => "audio/aac",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "pcm" => "audio/L16",
+        _ => "audio/mpeg",
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct TranscribeQuery {
+    /// The language of the recording as ISO-639-1, e.g. `en`. Detected when missing.
+    language: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct TranscribeResponse {
+    /// The text of the recording, to send as the `task` of a run.
+    text: String,
+}
+
+/// Transcribe a recording, sent as the request body with its audio content type.
+#[utoipa::path(
+    tag = "runs",
+    params(TranscribeQuery),
+    request_body(content = Vec<u8>, content_type = "audio/webm", description = "The recording: webm, mp3, m4a, wav, ogg or flac"),
+    responses(
+        (status = 200, description = "The text of the recording", body = TranscribeResponse),
+        (status = 400, description = "The recording is empty"),
+        (status = 413, description = "The recording is larger than 25 MB"),
+        (status = 415, description = "The content type is not a supported audio format"),
+    )
+)]
+#[post("/transcribe")]
+pub(crate) async fn transcribe_audio(
+    req: HttpRequest,
+    query: web::Query<TranscribeQuery>,
+    mut payload: web::Payload,
+) -> Result<impl Responder, actix_web::Error> {
+    let content_type = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let Some(extension) = extension(content_type) else {
+        return Ok(
+            HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "error": format!("Unsupported audio content type '{}'", content_type)
+            })),
+        );
+    };
+    let mut audio = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if audio.len() + chunk.len() > MAX_AUDIO_BYTES {
+            return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "error": "The recording is larger than 25 MB"
+            })));
+        }
+        audio.extend_from_slice(&chunk);
+    }
+    if audio.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("The recording is empty"));
+    }
+
+    let client = AudioClient::from_config().map_err(actix_web::error::ErrorInternalServerError)?;
+    let text = client
+        .transcribe(audio, extension, query.language.as_deref())
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?;
+    Ok(HttpResponse::Ok().json(TranscribeResponse { text }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_formats() {
+        assert_eq!(extension("audio/webm;codecs=opus"), Some("webm"));
+        assert_eq!(extension("audio/x-m4a"), Some("m4a"));
+        assert_eq!(extension("application/json"), None);
+        assert_eq!(mime_type("mp3"), "audio/mpeg");
+        assert_eq!(mime_type("opus"), "audio/ogg");
+
+        let config: AudioConfig = serde_yaml::from_str("voice: nova\napi_key: sk-test").unwrap();
+        assert_eq!(config.stt_model, "whisper-1");
+        assert_eq!(config.voice, "nova");
+        let client = AudioClient::new(config).unwrap();
+        assert_eq!(
+            client.url("audio/speech"),
+            "https://api.openai.com/v1/audio/speech"
+        );
+    }
+}
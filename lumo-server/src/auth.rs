@@ -1,31 +1,322 @@
+//! API keys of the server. Keys are configured in the `api_keys` section of servers.yaml, and `LUMO_API_KEY` is an
+//! admin key without scopes. Each key can be limited to some tools and models, a `max_steps` ceiling and a number of
+//! requests per minute, and its usage is counted for the admin endpoint. Keys with a monthly quota are refused new
+//! runs once it is reached, see [`crate::usage`].
+
 use actix_web::body::EitherBody;
-use actix_web::dev::{ServiceResponse, Transform};
+use actix_web::dev::{Payload, ServiceResponse, Transform};
 use actix_web::http::header;
 use actix_web::{dev::ServiceRequest, Error, HttpResponse};
+use actix_web::{get, web, FromRequest, HttpMessage, HttpRequest, Responder};
+use chrono::{DateTime, Utc};
 use futures::TryFutureExt;
+use serde::Serialize;
 use serde_json::json;
-use std::future::{ready, Future};
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
 
-pub struct ApiKeyAuth;
+use crate::config::{ApiKeyConfig, Servers, UsageQuota};
+use crate::usage::usage_ledger;
+use crate::RunTaskRequest;
 
-impl ApiKeyAuth {
-    fn validate_api_key(req: &ServiceRequest) -> Result<bool, Error> {
-        let api_key = std::env::var("LUMO_API_KEY").map_err(|_| {
-            actix_web::error::ErrorInternalServerError("Server API key not configured")
-        })?;
-
-        let auth_header = req.headers().get(header::AUTHORIZATION);
-        match auth_header {
-            Some(auth) => {
-                let auth_str = auth.to_str().map_err(|_| {
-                    actix_web::error::ErrorBadRequest("Invalid authorization header")
-                })?;
-                Ok(auth_str == format!("Bearer {}", api_key))
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// What a key has been used for since the server started.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct KeyUsage {
+    /// Authenticated requests, rate limited ones included.
+    pub requests: u64,
+    /// Runs started or scheduled.
+    pub runs: u64,
+    /// Requests rejected by the rate limit.
+    pub rate_limited: u64,
+    /// Runs rejected because they were out of the scopes of the key.
+    pub forbidden: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A key and its usage. The key itself is never shown.
+pub struct ApiKey {
+    pub name: String,
+    config: ApiKeyConfig,
+    usage: Mutex<KeyUsage>,
+    /// Start and number of requests of the current rate limit window.
+    window: Mutex<(Instant, u32)>,
+}
+
+impl ApiKey {
+    fn new(name: &str, config: ApiKeyConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            usage: Mutex::new(KeyUsage::default()),
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Count a request. Returns the seconds until the next window when the rate limit is reached.
+    fn record_request(&self) -> Result<(), u64> {
+        let mut usage = self.usage.lock().unwrap();
+        usage.requests += 1;
+        usage.last_used_at = Some(Utc::now());
+        let Some(limit) = self.config.rate_limit else {
+            return Ok(());
+        };
+        let mut window = self.window.lock().unwrap();
+        if window.0.elapsed() >= RATE_LIMIT_WINDOW {
+            *window = (Instant::now(), 0);
+        }
+        if window.1 >= limit {
+            usage.rate_limited += 1;
+            return Err(RATE_LIMIT_WINDOW
+                .saturating_sub(window.0.elapsed())
+                .as_secs()
+                .max(1));
+        }
+        window.1 += 1;
+        Ok(())
+    }
+
+    pub(crate) fn is_admin(&self) -> bool {
+        self.config.admin
+    }
+
+    pub(crate) fn monthly_quota(&self) -> Option<&UsageQuota> {
+        self.config.monthly_quota.as_ref()
+    }
+
+    /// Responds with 402 when the usage of the key this month reached one of the limits of its quota.
+    pub(crate) fn check_quota(&self) -> Result<(), Error> {
+        let Some(quota) = &self.config.monthly_quota else {
+            return Ok(());
+        };
+        let usage = usage_ledger().month(&self.name, Utc::now().date_naive());
+        match quota.reached(&usage) {
+            Some((limit_name, limit, used)) => Err(UsageQuota::error(limit_name, limit, used)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the run against the scopes of the key, and give it the `max_steps` ceiling when it has none.
+    fn check_scopes(&self, request: &mut RunTaskRequest) -> Result<(), String> {
+        if let Some(models) = &self.config.allowed_models {
+            if !models.contains(&request.model) {
+                return Err(format!("The API key may not use the model {}", request.model));
+            }
+        }
+        if let Some(tools) = &self.config.allowed_tools {
+            if request.preset.is_some() {
+                return Err("The API key may not use presets".to_string());
+            }
+            if request.agent_type.as_deref() == Some("mcp") {
+                return Err("The API key may not use the MCP agent".to_string());
+            }
+            if let Some(tool) = request.tools.iter().flatten().find(|tool| !tools.contains(tool)) {
+                return Err(format!("The API key may not use the tool {}", tool));
             }
-            None => Ok(false),
         }
+        if let Some(ceiling) = self.config.max_steps {
+            match request.max_steps {
+                Some(max_steps) if max_steps > ceiling => {
+                    return Err(format!(
+                        "The API key may not run more than {} steps",
+                        ceiling
+                    ))
+                }
+                Some(_) => {}
+                None => request.max_steps = Some(ceiling),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The API keys of the server.
+#[derive(Default)]
+pub struct KeyStore {
+    keys: Vec<Arc<ApiKey>>,
+}
+
+impl KeyStore {
+    /// The keys of servers.yaml, and `default_key` as an admin key without scopes.
+    pub fn new(keys: HashMap<String, ApiKeyConfig>, default_key: Option<String>) -> Self {
+        let mut keys = keys
+            .into_iter()
+            .map(|(name, config)| Arc::new(ApiKey::new(&name, config)))
+            .collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(key) = default_key.filter(|key| !key.is_empty()) {
+            let config = ApiKeyConfig {
+                key,
+                admin: true,
+                ..Default::default()
+            };
+            keys.insert(0, Arc::new(ApiKey::new("default", config)));
+        }
+        Self { keys }
+    }
+
+    /// The `api_keys` of servers.yaml and `LUMO_API_KEY`.
+    pub fn from_config() -> anyhow::Result<Self> {
+        let servers = Servers::load()?;
+        Ok(Self::new(
+            servers.api_keys.unwrap_or_default(),
+            std::env::var("LUMO_API_KEY").ok(),
+        ))
+    }
+
+    /// The key with this name.
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<ApiKey>> {
+        self.keys.iter().find(|key| key.name == name).cloned()
+    }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.keys.iter().map(|key| key.name.clone()).collect()
+    }
+
+    fn find(&self, token: &str) -> Option<Arc<ApiKey>> {
+        self.keys
+            .iter()
+            .find(|key| constant_time_eq(key.config.key.as_bytes(), token.as_bytes()))
+            .cloned()
+    }
+}
+
+/// Compare without returning at the first difference, so the time taken does not leak how much of a key matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The key of the request, when auth is enabled.
+#[derive(Default)]
+pub(crate) struct Caller(Option<Arc<ApiKey>>);
+
+impl FromRequest for Caller {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Caller(req.extensions().get::<Arc<ApiKey>>().cloned())))
+    }
+}
+
+impl std::fmt::Debug for Caller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Only the name of the key, never the key
+        f.debug_tuple("Caller")
+            .field(&self.0.as_ref().map(|key| &key.name))
+            .finish()
+    }
+}
+
+impl Caller {
+    /// The name of the key, when auth is enabled.
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.0.as_ref().map(|key| key.name.as_str())
+    }
+
+    /// The key, when auth is enabled.
+    pub(crate) fn key(&self) -> Option<&ApiKey> {
+        self.0.as_deref()
+    }
+
+    /// Whether the caller may see and change what the `owner` key created: any caller without auth, the owner and
+    /// the admin keys with auth.
+    pub(crate) fn owns(&self, owner: Option<&str>) -> bool {
+        match &self.0 {
+            None => true,
+            Some(key) => key.is_admin() || owner == Some(key.name.as_str()),
+        }
+    }
+
+    /// Check that the key may start the run, and count the usage of the run against it. Responds with 403 when the
+    /// run is out of its scopes, and with 402 when its monthly quota is reached.
+    pub(crate) fn authorize(&self, request: &mut RunTaskRequest) -> Result<(), Error> {
+        request.caller = self.name().map(str::to_string);
+        let Some(key) = &self.0 else {
+            return Ok(());
+        };
+        if let Err(message) = key.check_scopes(request) {
+            key.usage.lock().unwrap().forbidden += 1;
+            return Err(actix_web::error::ErrorForbidden(message));
+        }
+        key.check_quota()?;
+        key.usage.lock().unwrap().runs += 1;
+        Ok(())
+    }
+}
+
+/// A key for the tests of the endpoints, set as the caller of a test request with
+/// `request.extensions_mut().insert(key)`.
+#[cfg(test)]
+pub(crate) fn test_key(name: &str, admin: bool) -> Arc<ApiKey> {
+    let config = ApiKeyConfig {
+        key: format!("{}-key", name),
+        admin,
+        ..Default::default()
+    };
+    Arc::new(ApiKey::new(name, config))
+}
+
+/// The caller of a request with the key of [`test_key`].
+#[cfg(test)]
+pub(crate) fn test_caller(name: &str, admin: bool) -> Caller {
+    Caller(Some(test_key(name, admin)))
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct KeyReport {
+    name: String,
+    admin: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<u32>,
+    usage: KeyUsage,
+}
+
+/// The API keys of the server and their usage since it started. Needs an admin key.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "The keys and their usage", body = Vec<KeyReport>),
+        (status = 403, description = "Auth is disabled, or the key is not an admin key"),
+    )
+)]
+#[get("/admin/keys")]
+pub(crate) async fn list_keys(
+    caller: Caller,
+    keys: web::Data<KeyStore>,
+) -> Result<impl Responder, Error> {
+    if !caller.0.is_some_and(|key| key.config.admin) {
+        return Err(actix_web::error::ErrorForbidden(
+            "An admin API key is required, with ENABLE_AUTH=true",
+        ));
+    }
+    Ok(HttpResponse::Ok().json(
+        keys.keys
+            .iter()
+            .map(|key| KeyReport {
+                name: key.name.clone(),
+                admin: key.config.admin,
+                rate_limit: key.config.rate_limit,
+                usage: key.usage.lock().unwrap().clone(),
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+pub struct ApiKeyAuth {
+    keys: Arc<KeyStore>,
+}
+
+impl ApiKeyAuth {
+    pub fn new(keys: Arc<KeyStore>) -> Self {
+        Self { keys }
     }
 
     fn is_auth_enabled() -> bool {
@@ -48,12 +339,35 @@ where
     type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(ApiKeyAuthMiddleware { service }))
+        ready(Ok(ApiKeyAuthMiddleware {
+            service,
+            keys: self.keys.clone(),
+        }))
     }
 }
 
 pub struct ApiKeyAuthMiddleware<S> {
     service: S,
+    keys: Arc<KeyStore>,
+}
+
+impl<S> ApiKeyAuthMiddleware<S> {
+    fn authenticate(&self, req: &ServiceRequest) -> Result<Option<Arc<ApiKey>>, Error> {
+        if self.keys.keys.is_empty() {
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Server API key not configured",
+            ));
+        }
+        let Some(auth) = req.headers().get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let auth_str = auth
+            .to_str()
+            .map_err(|_| actix_web::error::ErrorBadRequest("Invalid authorization header"))?;
+        Ok(auth_str
+            .strip_prefix("Bearer ")
+            .and_then(|token| self.keys.find(token)))
+    }
 }
 
 impl<S, B> actix_web::dev::Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
@@ -71,8 +385,11 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        // Skip auth for health check endpoint
-        if req.path() == "/health_check" {
+        // Skip auth for the health check endpoint and the API docs
+        if req.path() == "/health_check"
+            || req.path() == crate::openapi::OPENAPI_PATH
+            || req.path().starts_with(crate::openapi::SWAGGER_UI_PATH)
+        {
             return Box::pin(
                 self.service
                     .call(req)
@@ -90,13 +407,26 @@ where
         }
 
         // Validate API key
-        match ApiKeyAuth::validate_api_key(&req) {
-            Ok(true) => Box::pin(
-                self.service
-                    .call(req)
-                    .map_ok(|res| res.map_into_left_body()),
-            ),
-            Ok(false) => {
+        match self.authenticate(&req) {
+            Ok(Some(key)) => {
+                if let Err(retry_after) = key.record_request() {
+                    let (http_req, _payload) = req.into_parts();
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                        .json(json!({
+                            "error": "Rate limit of the API key reached"
+                        }));
+                    let srv_resp = ServiceResponse::new(http_req, response).map_into_right_body();
+                    return Box::pin(ready(Ok(srv_resp)));
+                }
+                req.extensions_mut().insert(key);
+                Box::pin(
+                    self.service
+                        .call(req)
+                        .map_ok(|res| res.map_into_left_body()),
+                )
+            }
+            Ok(None) => {
                 let (http_req, _payload) = req.into_parts();
                 let response = HttpResponse::Unauthorized().json(json!({
                     "error": "Invalid or missing API key"
@@ -115,3 +445,110 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(tools: &[&str], max_steps: Option<usize>) -> RunTaskRequest {
+        serde_json::from_value(json!({
+            "task": "What is the capital of France?",
+            "model": "gpt-4o-mini",
+            "base_url": "https://api.openai.com/v1/chat/completions",
+            "tools": tools,
+            "max_steps": max_steps,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scopes() {
+        let key = ApiKey::new(
+            "tenant",
+            ApiKeyConfig {
+                key: "secret".to_string(),
+                allowed_tools: Some(vec!["DuckDuckGo".to_string()]),
+                allowed_models: Some(vec!["gpt-4o-mini".to_string()]),
+                max_steps: Some(5),
+                ..Default::default()
+            },
+        );
+        let mut allowed = request(&["DuckDuckGo"], None);
+        key.check_scopes(&mut allowed).unwrap();
+        assert_eq!(allowed.max_steps, Some(5));
+
+        assert!(key.check_scopes(&mut request(&["VisitWebsite"], None)).is_err());
+        assert!(key.check_scopes(&mut request(&[], Some(10))).is_err());
+        let mut other_model = request(&[], None);
+        other_model.model = "gpt-4o".to_string();
+        assert!(key.check_scopes(&mut other_model).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let key = ApiKey::new(
+            "tenant",
+            ApiKeyConfig {
+                key: "secret".to_string(),
+                rate_limit: Some(2),
+                ..Default::default()
+            },
+        );
+        assert!(key.record_request().is_ok());
+        assert!(key.record_request().is_ok());
+        assert!(key.record_request().is_err());
+        let usage = key.usage.lock().unwrap();
+        assert_eq!((usage.requests, usage.rate_limited), (3, 1));
+    }
+
+    #[test]
+    fn test_monthly_quota() {
+        let key = Arc::new(ApiKey::new(
+            &format!("quota-{}", nanoid::nanoid!()),
+            ApiKeyConfig {
+                key: "secret".to_string(),
+                monthly_quota: Some(UsageQuota {
+                    runs: Some(1),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ));
+        let caller = Caller(Some(key.clone()));
+        let mut run = request(&[], None);
+        run.caller = Some("someone-else".to_string());
+        caller.authorize(&mut run).unwrap();
+        assert_eq!(run.caller.as_deref(), Some(key.name.as_str()));
+
+        crate::usage::record_usage(run.caller.as_deref(), &crate::usage::Usage::run());
+        let error = caller.authorize(&mut request(&[], None)).unwrap_err();
+        assert_eq!(
+            error.as_response_error().status_code(),
+            actix_web::http::StatusCode::PAYMENT_REQUIRED
+        );
+        assert_eq!(key.usage.lock().unwrap().runs, 1);
+
+        // Without auth, the runs are not counted against any key.
+        let mut run = request(&[], None);
+        run.caller = Some(key.name.clone());
+        Caller(None).authorize(&mut run).unwrap();
+        assert!(run.caller.is_none());
+    }
+
+    #[test]
+    fn test_find_key() {
+        let keys = KeyStore::new(
+            HashMap::from([(
+                "tenant".to_string(),
+                ApiKeyConfig {
+                    key: "tenant-secret".to_string(),
+                    ..Default::default()
+                },
+            )]),
+            Some("admin-secret".to_string()),
+        );
+        assert_eq!(keys.find("tenant-secret").unwrap().name, "tenant");
+        assert!(keys.find("admin-secret").unwrap().config.admin);
+        assert!(keys.find("tenant-secre").is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Cancellation of the runs of the server.
+//!
+//! Each run gets a [`CancellationToken`] while it runs. A streamed run is cancelled with `POST /runs/{id}/cancel`,
+//! where the id is the `X-Stream-Id` of the stream, and all the runs are cancelled when the server shuts down. The
+//! pending model request of a cancelled run is aborted, so the provider stops generating, and the stream sends a
+//! `cancelled` event. With auth, a run can only be cancelled with the API key that started it.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::{post, web, HttpResponse, Responder};
+use lumo::models::cancellation::CancellationToken;
+
+use crate::auth::Caller;
+
+/// A running run: its registration, the name of the key that started it and its token.
+type RunningRun = (u64, Option<String>, CancellationToken);
+
+/// The tokens of the running runs, by run id.
+#[derive(Default)]
+pub struct RunCancellations {
+    runs: Mutex<HashMap<String, RunningRun>>,
+    next_registration: Mutex<u64>,
+}
+
+impl RunCancellations {
+    /// A token for the run of the `caller` key, cancellable by its id until the returned registration is dropped.
+    pub fn register(&self, run_id: &str, caller: Option<&str>) -> RunRegistration<'_> {
+        let registration = {
+            let mut next = self.next_registration.lock().unwrap();
+            *next += 1;
+            *next
+        };
+        let token = CancellationToken::new();
+        self.runs
+            .lock()
+            .unwrap()
+            .insert(
+                run_id.to_string(),
+                (registration, caller.map(str::to_string), token.clone()),
+            );
+        RunRegistration {
+            runs: self,
+            run_id: run_id.to_string(),
+            registration,
+            token,
+        }
+    }
+
+    /// Cancel the run. Returns `false` when no run of the caller has this id.
+    pub(crate) fn cancel(&self, run_id: &str, caller: &Caller) -> bool {
+        match self.runs.lock().unwrap().get(run_id) {
+            Some((_, owner, token)) if caller.owns(owner.as_deref()) => {
+                token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancel all the runs, returning how many there were.
+    pub fn cancel_all(&self) -> usize {
+        let runs = self.runs.lock().unwrap();
+        for (_, _, token) in runs.values() {
+            token.cancel();
+        }
+        runs.len()
+    }
+}
+
+/// The token of a running run. The run can not be cancelled by its id anymore once this is dropped.
+pub struct RunRegistration<'a> {
+    runs: &'a RunCancellations,
+    run_id: String,
+    registration: u64,
+    token: CancellationToken,
+}
+
+impl RunRegistration<'_> {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for RunRegistration<'_> {
+    fn drop(&mut self) {
+        let mut runs = self.runs.runs.lock().unwrap();
+        // A later run with the same id, such as the next turn of a session, keeps its own registration.
+        if runs
+            .get(&self.run_id)
+            .is_some_and(|(registration, _, _)| *registration == self.registration)
+        {
+            runs.remove(&self.run_id);
+        }
+    }
+}
+
+static RUNS: OnceLock<RunCancellations> = OnceLock::new();
+
+/// The running runs of the server.
+pub(crate) fn run_cancellations() -> &'static RunCancellations {
+    RUNS.get_or_init(RunCancellations::default)
+}
+
+/// Cancel the running runs when the server receives Ctrl-C or SIGTERM, so they do not keep their model requests
+/// running during the graceful shutdown.
+pub(crate) fn cancel_runs_on_shutdown() {
+    actix_web::rt::spawn(async {
+        #[cfg(unix)]
+        let terminate = async {
+            match actix_web::rt::signal::unix::signal(actix_web::rt::signal::unix::SignalKind::terminate()) {
+                Ok(mut signal) => {
+                    signal.recv().await;
+                }
+                Err(_) => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+        tokio::select! {
+            _ = actix_web::rt::signal::ctrl_c() => {}
+            _ = terminate => {}
+        }
+        let cancelled = run_cancellations().cancel_all();
+        if cancelled > 0 {
+            log::info!("Shutting down, cancelled {} runs", cancelled);
+        }
+    });
+}
+
+/// Cancel a streamed run. Its pending model request is aborted and the stream sends a `cancelled` event.
+#[utoipa::path(
+    tag = "runs",
+    params(("id" = String, Path, description = "The stream id of the run")),
+    responses(
+        (status = 202, description = "The run is cancelled"),
+        (status = 404, description = "No run with this id is running"),
+    )
+)]
+#[post("/runs/{id}/cancel")]
+pub(crate) async fn cancel_run(caller: Caller, id: web::Path<String>) -> impl Responder {
+    if run_cancellations().cancel(&id, &caller) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Run {} is not running", id)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::test_caller;
+
+    #[test]
+    fn test_run_cancellations() {
+        let runs = RunCancellations::default();
+        let caller = Caller::default();
+        assert!(!runs.cancel("run-1", &caller));
+
+        let first = runs.register("run-1", None);
+        let token = first.token();
+        assert!(runs.cancel("run-1", &caller));
+        assert!(token.is_cancelled());
+
+        // The next turn of the session has its own token, kept when the first registration is dropped.
+        let second = runs.register("run-1", None);
+        drop(first);
+        assert!(!second.token().is_cancelled());
+        assert_eq!(runs.cancel_all(), 1);
+        assert!(second.token().is_cancelled());
+        drop(second);
+        assert!(!runs.cancel("run-1", &caller));
+    }
+
+    #[test]
+    fn test_cancel_other_key() {
+        let runs = RunCancellations::default();
+        let run = runs.register("run-1", Some("team-a"));
+        assert!(!runs.cancel("run-1", &test_caller("team-b", false)));
+        assert!(!run.token().is_cancelled());
+        assert!(runs.cancel("run-1", &test_caller("admin", true)));
+        assert!(run.token().is_cancelled());
+    }
+}
@@ -0,0 +1,178 @@
+//! Checkpoints of the `/stream` runs, to resume a run after the server crashed.
+//!
+//! The agent of a stream saves its state after each step under the stream id, sent as the `checkpoint_id` of the
+//! step events. A `/stream` request with `resume_from` set to that id continues the run with its next step.
+//! Checkpoints are files in the data directory, or `CHECKPOINT_DIR` when set, or in Redis when the server is built
+//! with the `redis` feature and `REDIS_URL` is set, so that any server instance can resume the run.
+//!
+//! With auth, the checkpoints are stored within the API key of the run, so a key can only resume its own runs.
+
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use directories::ProjectDirs;
+use lumo::agent::{Checkpoint, CheckpointStore, FileCheckpointStore};
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use anyhow::{Context as _, Result};
+    use async_trait::async_trait;
+    use lumo::agent::{Checkpoint, CheckpointStore};
+    use redis::{aio::ConnectionManager, AsyncCommands};
+    use tokio::sync::OnceCell;
+
+    /// Checkpoints expire after a day, unless `CHECKPOINT_TTL_SECS` is set.
+    const DEFAULT_CHECKPOINT_TTL_SECS: u64 = 24 * 60 * 60;
+
+    /// Checkpoints shared by all the server instances using the same Redis.
+    pub struct RedisCheckpoints {
+        client: redis::Client,
+        connection: OnceCell<ConnectionManager>,
+        ttl_secs: u64,
+    }
+
+    fn checkpoint_key(id: &str) -> String {
+        format!("lumo:checkpoints:{}", id)
+    }
+
+    impl RedisCheckpoints {
+        pub fn new(url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(url).context("Invalid REDIS_URL")?,
+                connection: OnceCell::new(),
+                ttl_secs: std::env::var("CHECKPOINT_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_CHECKPOINT_TTL_SECS),
+            })
+        }
+
+        async fn connection(&self) -> Result<ConnectionManager> {
+            let connection = self
+                .connection
+                .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+                .await
+                .context("Failed to connect to Redis")?;
+            Ok(connection.clone())
+        }
+    }
+
+    #[async_trait]
+    impl CheckpointStore for RedisCheckpoints {
+        async fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+            let _: () = self
+                .connection()
+                .await?
+                .set_ex(
+                    checkpoint_key(&checkpoint.id),
+                    serde_json::to_string(checkpoint)?,
+                    self.ttl_secs,
+                )
+                .await?;
+            Ok(())
+        }
+
+        async fn load(&self, id: &str) -> Result<Option<Checkpoint>> {
+            let checkpoint: Option<String> =
+                self.connection().await?.get(checkpoint_key(id)).await?;
+            checkpoint
+                .map(|checkpoint| {
+                    serde_json::from_str(&checkpoint).context("Failed to parse checkpoint")
+                })
+                .transpose()
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use redis_backend::RedisCheckpoints;
+
+/// The checkpoints are stored in the Redis instance at `REDIS_URL` with the `redis` feature, and in files otherwise.
+pub fn from_env() -> Result<Arc<dyn CheckpointStore>> {
+    #[cfg(feature = "redis")]
+    if let Ok(url) = std::env::var("REDIS_URL") {
+        return Ok(Arc::new(RedisCheckpoints::new(&url)?));
+    }
+    let dir = match std::env::var("CHECKPOINT_DIR") {
+        Ok(dir) => dir.into(),
+        Err(_) => ProjectDirs::from("com", "lumo", "lumo-server")
+            .context("Failed to determine data directory")?
+            .data_dir()
+            .join("checkpoints"),
+    };
+    Ok(Arc::new(FileCheckpointStore::new(dir)))
+}
+
+/// The checkpoints of the API key of a request, in the store of the server.
+pub(crate) struct CallerCheckpoints {
+    store: Arc<dyn CheckpointStore>,
+    caller: Option<String>,
+}
+
+impl CallerCheckpoints {
+    pub(crate) fn new(store: Arc<dyn CheckpointStore>, caller: Option<&str>) -> Self {
+        Self {
+            store,
+            caller: caller.map(str::to_string),
+        }
+    }
+
+    /// The id in the store: the name of the key in hexadecimal, then the id. The hexadecimal name has no `-`, so two
+    /// keys never share an id, and it fits the ids of the checkpoint files.
+    fn key(&self, id: &str) -> String {
+        match &self.caller {
+            Some(caller) => {
+                let caller = caller
+                    .bytes()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                format!("{}-{}", caller, id)
+            }
+            None => id.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for CallerCheckpoints {
+    async fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let mut checkpoint = checkpoint.clone();
+        checkpoint.id = self.key(&checkpoint.id);
+        self.store.save(&checkpoint).await
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<Checkpoint>> {
+        Ok(self.store.load(&self.key(id)).await?.map(|mut checkpoint| {
+            checkpoint.id = id.to_string();
+            checkpoint
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_caller_checkpoints() {
+        let store: Arc<dyn CheckpointStore> = Arc::new(FileCheckpointStore::new(
+            std::env::temp_dir().join(format!("lumo-checkpoints-{}", nanoid::nanoid!())),
+        ));
+        let team_a = CallerCheckpoints::new(store.clone(), Some("team-a"));
+        let checkpoint = Checkpoint {
+            id: "run-1".to_string(),
+            task: "Plan a trip".to_string(),
+            step_number: 2,
+            logs: vec![],
+            created_at: chrono::Utc::now(),
+        };
+        team_a.save(&checkpoint).await.unwrap();
+
+        assert_eq!(team_a.load("run-1").await.unwrap().unwrap().id, "run-1");
+        let team_b = CallerCheckpoints::new(store.clone(), Some("team-b"));
+        assert!(team_b.load("run-1").await.unwrap().is_none());
+        assert!(store.load("run-1").await.unwrap().is_none());
+        assert!(store.load("7465616d2d61-run-1").await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,184 @@
+//! Clarification questions of the agents running on `/stream`.
+//!
+//! When the agent calls the `AskUser` tool, the stream emits a `clarification_required` event and the run waits
+//! until the client posts the answer to `/runs/{id}/input`, where the id is the `X-Stream-Id` of the stream. If no
+//! answer comes before the timeout, the run continues with the assumption of the agent. With auth, only the API key
+//! that started the run can answer.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::{post, web, HttpResponse, Responder};
+use anyhow::Result;
+use async_trait::async_trait;
+use lumo::{models::openai::Status, tools::UserInput};
+use serde::Deserialize;
+use tokio::sync::{broadcast, oneshot};
+use utoipa::ToSchema;
+
+use crate::auth::Caller;
+
+/// A run waiting for an answer: the name of the key that started it and the sender of the answer.
+type PendingInput = (Option<String>, oneshot::Sender<String>);
+
+/// The runs waiting for an answer of the user, by run id.
+#[derive(Clone)]
+pub struct PendingInputs {
+    pending: Arc<Mutex<HashMap<String, PendingInput>>>,
+    timeout: Duration,
+}
+
+impl PendingInputs {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            timeout,
+        }
+    }
+
+    /// The timeout is read from `ASK_USER_TIMEOUT_SECS` (default: 300).
+    pub fn from_env() -> Self {
+        let seconds = std::env::var("ASK_USER_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(300);
+        Self::new(Duration::from_secs(seconds))
+    }
+
+    /// How long a run waits for an answer.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn wait(&self, run_id: &str, caller: Option<&str>) -> oneshot::Receiver<String> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(run_id.to_string(), (caller.map(str::to_string), tx));
+        rx
+    }
+
+    /// Send the answer to the run. Returns `false` when no run of the caller is waiting for one.
+    pub(crate) fn answer(&self, run_id: &str, caller: &Caller, input: String) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending
+            .get(run_id)
+            .is_some_and(|(owner, _)| caller.owns(owner.as_deref()))
+        {
+            return false;
+        }
+        match pending.remove(run_id) {
+            Some((_, tx)) => tx.send(input).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Removes the pending question when the run stops waiting, e.g. on timeout.
+struct PendingGuard<'a> {
+    inputs: &'a PendingInputs,
+    run_id: &'a str,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.inputs.pending.lock().unwrap().remove(self.run_id);
+    }
+}
+
+/// Asks the questions of an agent through the events of its stream.
+pub struct StreamUserInput {
+    run_id: String,
+    caller: Option<String>,
+    inputs: PendingInputs,
+    tx: broadcast::Sender<Status>,
+}
+
+impl StreamUserInput {
+    /// Questions of the run of the `caller` key.
+    pub fn new(
+        run_id: &str,
+        caller: Option<&str>,
+        inputs: PendingInputs,
+        tx: broadcast::Sender<Status>,
+    ) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            caller: caller.map(str::to_string),
+            inputs,
+            tx,
+        }
+    }
+}
+
+#[async_trait]
+impl UserInput for StreamUserInput {
+    async fn ask(&self, question: &str) -> Result<Option<String>> {
+        let rx = self.inputs.wait(&self.run_id, self.caller.as_deref());
+        let _guard = PendingGuard {
+            inputs: &self.inputs,
+            run_id: &self.run_id,
+        };
+        let _ = self.tx.send(Status::ClarificationRequired(question.to_string()));
+        Ok(rx.await.ok())
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct RunInput {
+    /// The answer to the question of the agent.
+    input: String,
+}
+
+/// Answer the clarification question of a streamed run.
+#[utoipa::path(
+    tag = "runs",
+    params(("id" = String, Path, description = "The stream id of the run")),
+    request_body = RunInput,
+    responses(
+        (status = 202, description = "The run continues with the answer"),
+        (status = 404, description = "The run is not waiting for an answer"),
+    )
+)]
+#[post("/runs/{id}/input")]
+pub(crate) async fn submit_input(
+    caller: Caller,
+    id: web::Path<String>,
+    req: web::Json<RunInput>,
+    inputs: web::Data<PendingInputs>,
+) -> impl Responder {
+    if inputs.answer(&id, &caller, req.into_inner().input) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Run {} is not waiting for an answer", id)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::test_caller;
+
+    #[tokio::test]
+    async fn test_stream_user_input() {
+        let inputs = PendingInputs::new(Duration::from_secs(1));
+        let (tx, mut rx) = broadcast::channel(10);
+        let user_input = StreamUserInput::new("run-1", Some("team-a"), inputs.clone(), tx);
+        let caller = test_caller("team-a", false);
+        assert!(!inputs.answer("run-1", &caller, "too early".to_string()));
+
+        let answer = tokio::spawn(async move { user_input.ask("Which Paris?").await.unwrap() });
+        match rx.recv().await.unwrap() {
+            Status::ClarificationRequired(question) => assert_eq!(question, "Which Paris?"),
+            _ => panic!("Expected a clarification question"),
+        }
+        assert!(!inputs.answer("run-1", &test_caller("team-b", false), "Paris, Texas".to_string()));
+        assert!(inputs.answer("run-1", &caller, "Paris, France".to_string()));
+        assert_eq!(answer.await.unwrap(), Some("Paris, France".to_string()));
+        assert!(inputs.pending.lock().unwrap().is_empty());
+    }
+}
@@ -1,20 +1,92 @@
 use anyhow::{anyhow, Context, Result};
 use directories::ProjectDirs;
+use lumo::models::batching::TokenBatching;
+use lumo::tools::{ToolBudget, Webhook, Zone};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use utoipa::ToSchema;
+#[cfg(feature = "mcp")]
+use std::time::Duration;
+#[cfg(feature = "mcp")]
+use tokio::process::Command;
+#[cfg(feature = "mcp")]
+use rmcp::{
+    service::RunningService,
+    transport::{
+        common::client_side_sse::ExponentialBackoff, sse_client::SseClientConfig,
+        streamable_http_client::StreamableHttpClientTransportConfig, SseClientTransport,
+        StreamableHttpClientTransport, TokioChildProcess,
+    },
+    RoleClient, ServiceExt,
+};
+#[cfg(feature = "mcp")]
+use std::sync::Arc;
 
+/// Default time an MCP server has to start and complete the initialization handshake.
+#[cfg(feature = "mcp")]
+const DEFAULT_STARTUP_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of times the connection to a hosted MCP server is retried when it drops.
+#[cfg(feature = "mcp")]
+const DEFAULT_MAX_RECONNECTS: usize = 5;
+
+/// The transport of a hosted MCP server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum McpTransport {
+    #[default]
+    StreamableHttp,
+    Sse,
+}
+
+/// An MCP server, either a local process started with `command` or a hosted server at `url`. `${VAR}` and
+/// `${VAR:-default}` in the args, env values, working directory, url and headers are replaced with the environment
+/// of the parent process.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<String>,
+    /// Environment variables added to the inherited environment of the process.
     #[serde(default)]
     pub env: Option<HashMap<String, String>>,
+    /// Working directory of the process. Defaults to the working directory of the parent process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Seconds the server has to start and answer the initialization handshake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_timeout: Option<u64>,
+    /// URL of a hosted server, used instead of `command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Transport of the hosted server. Defaults to streamable HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<McpTransport>,
+    /// HTTP headers sent to the hosted server, e.g. `Authorization: "Bearer ${MCP_TOKEN}"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+    /// How many times the connection to the hosted server is retried when it drops.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_reconnects: Option<usize>,
+    /// Maximum number of clients of the server in the MCP pool, instead of the `max_clients` of `mcp_pool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clients: Option<usize>,
 }
 
 impl ServerConfig {
     pub fn validate(&self) -> Result<()> {
+        if let Some(url) = &self.url {
+            if !self.command.is_empty() {
+                return Err(anyhow!("Server cannot have both a command and a url"));
+            }
+            if !url.starts_with("http://") && !url.starts_with("https://") && !url.starts_with("${") {
+                return Err(anyhow!("Server url must start with http:// or https://"));
+            }
+            return Ok(());
+        }
         if self.command.is_empty() {
             return Err(anyhow!("Server command cannot be empty"));
         }
@@ -23,6 +95,340 @@ impl ServerConfig {
         }
         Ok(())
     }
+
+    /// The command that starts the server, with its args, environment and working directory.
+    #[cfg(feature = "mcp")]
+    pub fn command(&self) -> Result<Command> {
+        let mut command = Command::new(&self.command);
+        for arg in &self.args {
+            command.arg(expand_env_vars(arg)?);
+        }
+        for (key, value) in self.env.iter().flatten() {
+            command.env(key, expand_env_vars(value)?);
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(expand_env_vars(cwd)?);
+        }
+        Ok(command)
+    }
+
+    /// The HTTP client of a hosted server, sending the configured headers with every request.
+    #[cfg(feature = "mcp")]
+    fn http_client(&self) -> Result<reqwest::Client> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in self.headers.iter().flatten() {
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name '{}'", name))?,
+                reqwest::header::HeaderValue::from_str(&expand_env_vars(value)?)
+                    .with_context(|| format!("Invalid value for header '{}'", name))?,
+            );
+        }
+        Ok(reqwest::Client::builder().default_headers(headers).build()?)
+    }
+
+    /// Start or connect to the server and wait for the initialization handshake, failing if it does not complete
+    /// in time. Hosted servers reconnect with exponential backoff when the connection drops.
+    #[cfg(feature = "mcp")]
+    pub async fn connect(&self, name: &str) -> Result<RunningService<RoleClient, ()>> {
+        let timeout =
+            Duration::from_secs(self.startup_timeout.unwrap_or(DEFAULT_STARTUP_TIMEOUT_SECS));
+        tokio::time::timeout(timeout, self.start(name))
+            .await
+            .map_err(|_| anyhow!("MCP server '{}' did not start within {:?}", name, timeout))?
+    }
+
+    #[cfg(feature = "mcp")]
+    async fn start(&self, name: &str) -> Result<RunningService<RoleClient, ()>> {
+        let handshake_failed = || format!("MCP server '{}' failed the initialization handshake", name);
+        let Some(url) = &self.url else {
+            let transport = TokioChildProcess::new(self.command()?)
+                .with_context(|| format!("Failed to start MCP server '{}'", name))?;
+            return ().serve(transport).await.with_context(handshake_failed);
+        };
+
+        let url = expand_env_vars(url)?;
+        let client = self.http_client()?;
+        let retry_policy = Arc::new(ExponentialBackoff {
+            max_times: Some(self.max_reconnects.unwrap_or(DEFAULT_MAX_RECONNECTS)),
+            base_duration: ExponentialBackoff::DEFAULT_DURATION,
+        });
+        match self.transport.unwrap_or_default() {
+            McpTransport::StreamableHttp => {
+                let config = StreamableHttpClientTransportConfig {
+                    retry_config: retry_policy,
+                    ..StreamableHttpClientTransportConfig::with_uri(url)
+                };
+                let transport = StreamableHttpClientTransport::with_client(client, config);
+                ().serve(transport).await.with_context(handshake_failed)
+            }
+            McpTransport::Sse => {
+                let config = SseClientConfig {
+                    sse_endpoint: url.into(),
+                    retry_policy,
+                    ..Default::default()
+                };
+                let transport = SseClientTransport::start_with_client(client, config)
+                    .await
+                    .with_context(|| format!("Failed to connect to MCP server '{}'", name))?;
+                ().serve(transport).await.with_context(handshake_failed)
+            }
+        }
+    }
+}
+
+/// Replace `${VAR}` with the value of the environment variable `VAR`, or with `default` for `${VAR:-default}`.
+pub fn expand_env_vars(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed variable in '{}'", value))?;
+        let variable = &rest[start + 2..start + end];
+        let (name, default) = match variable.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (variable, None),
+        };
+        match (std::env::var(name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(default),
+            (Err(_), None) => {
+                return Err(anyhow!("Environment variable '{}' is not set", name));
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Settings of a tool, from the `tool_config` of servers.yaml or of the request, by tool name. The settings of the
+/// request take precedence.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ToolConfig {
+    /// API key of the tool, used instead of the one from the environment. `${VAR}` is expanded in servers.yaml.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Maximum number of search results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    /// Domains the tool may visit, including their subdomains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_domains: Option<Vec<String>>,
+    /// Maximum number of characters of a page read at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Maximum number of pages read by a crawl.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pages: Option<usize>,
+    /// Channels the tool may post to, by name or id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_channels: Option<Vec<String>>,
+    /// Maximum number of bytes of a response body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_response_size: Option<usize>,
+    /// The time zone of the dates that do not name one, e.g. `Asia/Tokyo` or `UTC+05:30`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+}
+
+impl ToolConfig {
+    /// These settings, overridden by the ones set in `other`.
+    pub fn merge(&self, other: &ToolConfig) -> ToolConfig {
+        ToolConfig {
+            api_key: other.api_key.clone().or_else(|| self.api_key.clone()),
+            max_results: other.max_results.or(self.max_results),
+            allowed_domains: other
+                .allowed_domains
+                .clone()
+                .or_else(|| self.allowed_domains.clone()),
+            max_length: other.max_length.or(self.max_length),
+            max_pages: other.max_pages.or(self.max_pages),
+            allowed_channels: other
+                .allowed_channels
+                .clone()
+                .or_else(|| self.allowed_channels.clone()),
+            max_response_size: other.max_response_size.or(self.max_response_size),
+            time_zone: other.time_zone.clone().or_else(|| self.time_zone.clone()),
+        }
+    }
+
+    /// Check that only the `supported` settings are set, and that they are valid.
+    pub fn validate(&self, supported: &[&str]) -> Result<()> {
+        let settings = [
+            ("api_key", self.api_key.is_some()),
+            ("max_results", self.max_results.is_some()),
+            ("allowed_domains", self.allowed_domains.is_some()),
+            ("max_length", self.max_length.is_some()),
+            ("max_pages", self.max_pages.is_some()),
+            ("allowed_channels", self.allowed_channels.is_some()),
+            ("max_response_size", self.max_response_size.is_some()),
+            ("time_zone", self.time_zone.is_some()),
+        ];
+        for (setting, is_set) in settings {
+            if is_set && !supported.contains(&setting) {
+                return Err(anyhow!("The tool does not support the '{}' setting", setting));
+            }
+        }
+        if self.max_results == Some(0) {
+            return Err(anyhow!("'max_results' must be at least 1"));
+        }
+        if self.max_length == Some(0) {
+            return Err(anyhow!("'max_length' must be at least 1"));
+        }
+        if self.max_pages == Some(0) {
+            return Err(anyhow!("'max_pages' must be at least 1"));
+        }
+        if self.max_response_size == Some(0) {
+            return Err(anyhow!("'max_response_size' must be at least 1"));
+        }
+        if self.api_key.as_ref().is_some_and(|key| key.trim().is_empty()) {
+            return Err(anyhow!("'api_key' cannot be empty"));
+        }
+        if let Some(time_zone) = &self.time_zone {
+            time_zone.parse::<Zone>()?;
+        }
+        Ok(())
+    }
+}
+
+/// Guardrails applied to every agent run by the server.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GuardrailsConfig {
+    /// Case-insensitive keywords that block the input, a tool call or the answer.
+    #[serde(default)]
+    pub blocked_keywords: Vec<String>,
+    /// Regex patterns that block the input, a tool call or the answer.
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+    /// Moderate the input and the answer with the request's model.
+    #[serde(default)]
+    pub moderation: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation_policy: Option<String>,
+}
+
+/// An API key of the server and what it may be used for. Keys without scopes may use everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiKeyConfig {
+    /// The key, sent as `Authorization: Bearer <key>`. `${VAR}` is expanded.
+    pub key: String,
+    /// The key may use the admin endpoints.
+    #[serde(default)]
+    pub admin: bool,
+    /// Tools the key may request. Keys with allowed tools cannot use presets or the MCP agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_tools: Option<Vec<String>>,
+    /// Models the key may request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_models: Option<Vec<String>>,
+    /// The most steps a run of the key may take. Runs without `max_steps` get this ceiling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_steps: Option<usize>,
+    /// Requests per minute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+    /// Limits of the usage of the key in a calendar month (UTC). New runs are refused once one is reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_quota: Option<UsageQuota>,
+}
+
+/// Limits of the usage of an API key. Limits that are not set are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct UsageQuota {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u64>,
+    /// Input and output tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<u64>,
+}
+
+impl ApiKeyConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.key.trim().is_empty() {
+            return Err(anyhow!("'key' cannot be empty"));
+        }
+        if self.max_steps == Some(0) {
+            return Err(anyhow!("'max_steps' must be at least 1"));
+        }
+        if self.rate_limit == Some(0) {
+            return Err(anyhow!("'rate_limit' must be at least 1"));
+        }
+        if let Some(quota) = &self.monthly_quota {
+            let limits = [
+                ("runs", quota.runs),
+                ("steps", quota.steps),
+                ("tokens", quota.tokens),
+                ("tool_calls", quota.tool_calls),
+            ];
+            if let Some((name, _)) = limits.iter().find(|(_, limit)| *limit == Some(0)) {
+                return Err(anyhow!("'monthly_quota.{}' must be at least 1", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The speech-to-text and text-to-speech provider of `/transcribe` and of spoken answers. Any provider with the
+/// audio endpoints of the OpenAI API works.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioConfig {
+    #[serde(default = "AudioConfig::default_base_url")]
+    pub base_url: String,
+    /// Defaults to `OPENAI_API_KEY`. `${VAR}` is expanded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(default = "AudioConfig::default_stt_model")]
+    pub stt_model: String,
+    #[serde(default = "AudioConfig::default_tts_model")]
+    pub tts_model: String,
+    #[serde(default = "AudioConfig::default_voice")]
+    pub voice: String,
+    /// The format of the spoken answers: `mp3`, `opus`, `aac`, `flac`, `wav` or `pcm`.
+    #[serde(default = "AudioConfig::default_format")]
+    pub format: String,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            base_url: Self::default_base_url(),
+            api_key: None,
+            stt_model: Self::default_stt_model(),
+            tts_model: Self::default_tts_model(),
+            voice: Self::default_voice(),
+            format: Self::default_format(),
+        }
+    }
+}
+
+impl AudioConfig {
+    fn default_base_url() -> String {
+        "https://api.openai.com/v1".to_string()
+    }
+
+    fn default_stt_model() -> String {
+        "whisper-1".to_string()
+    }
+
+    fn default_tts_model() -> String {
+        "tts-1".to_string()
+    }
+
+    fn default_voice() -> String {
+        "alloy".to_string()
+    }
+
+    fn default_format() -> String {
+        "mp3".to_string()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +437,39 @@ pub struct Servers {
     pub servers: HashMap<String, ServerConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system_prompt: Option<String>,
+    /// Default values of the `{{name}}` placeholders of the system prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_variables: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guardrails: Option<GuardrailsConfig>,
+    /// Default settings of the tools, by tool name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<HashMap<String, ToolConfig>>,
+    /// Calls per run and per day of the paid tools, by tool name. Once spent, DuckDuckGo is called instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_budgets: Option<HashMap<String, ToolBudget>>,
+    /// The token budget of the history of the requests, 64000 tokens by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_trimming: Option<crate::history::HistoryTrimming>,
+    /// The webhooks the `Webhook` tool may call, by name. Requests can not add webhooks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhooks: Option<HashMap<String, Webhook>>,
+    /// The API keys of the server by name, checked when `ENABLE_AUTH=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_keys: Option<HashMap<String, ApiKeyConfig>>,
+    /// The provider of `/transcribe` and of spoken answers. OpenAI with `OPENAI_API_KEY` when missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioConfig>,
+    /// How the tokens of `/stream` are grouped into events. Each token is its own event when missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_batching: Option<TokenBatching>,
+    /// How the built agents are reused across requests. Read when the server starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_pool: Option<crate::agent_pool::AgentPoolConfig>,
+    /// How the clients of the MCP servers are reused across requests. Read when the server starts.
+    #[cfg(feature = "mcp")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mcp_pool: Option<crate::mcp_pool::McpPoolConfig>,
 }
 
 impl Servers {
@@ -46,9 +485,39 @@ impl Servers {
         let config_str = fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
 
-        let servers: Servers =
+        let mut servers: Servers =
             serde_yaml::from_str(&config_str).with_context(|| "Failed to parse servers.yaml")?;
 
+        // Only the API keys of servers.yaml are expanded, never the ones of a request
+        for (name, config) in servers.tool_config.iter_mut().flatten() {
+            if let Some(api_key) = &config.api_key {
+                config.api_key = Some(
+                    expand_env_vars(api_key)
+                        .with_context(|| format!("Invalid api_key for tool '{}'", name))?,
+                );
+            }
+        }
+
+        if let Some(api_key) = servers.audio.as_mut().and_then(|audio| audio.api_key.as_mut()) {
+            *api_key = expand_env_vars(api_key).context("Invalid api_key for audio")?;
+        }
+
+        for (name, webhook) in servers.webhooks.iter_mut().flatten() {
+            webhook.url = expand_env_vars(&webhook.url)
+                .with_context(|| format!("Invalid url for webhook '{}'", name))?;
+            if let Some(secret) = &webhook.secret {
+                webhook.secret = Some(
+                    expand_env_vars(secret)
+                        .with_context(|| format!("Invalid secret for webhook '{}'", name))?,
+                );
+            }
+        }
+
+        for (name, config) in servers.api_keys.iter_mut().flatten() {
+            config.key = expand_env_vars(&config.key)
+                .with_context(|| format!("Invalid key for API key '{}'", name))?;
+        }
+
         // Validate all server configurations
         servers.validate()?;
 
@@ -61,6 +530,15 @@ impl Servers {
                 .validate()
                 .with_context(|| format!("Invalid configuration for server '{}'", name))?;
         }
+        let mut keys = std::collections::HashSet::new();
+        for (name, config) in self.api_keys.iter().flatten() {
+            config
+                .validate()
+                .with_context(|| format!("Invalid API key '{}'", name))?;
+            if !keys.insert(&config.key) {
+                return Err(anyhow!("API key '{}' has the same key as another API key", name));
+            }
+        }
 
         Ok(())
     }
@@ -87,3 +565,125 @@ impl Servers {
         Ok(proj_dirs.config_dir().join("servers.yaml"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env_vars() {
+        std::env::set_var("LUMO_TEST_MCP_DIR", "/data");
+        assert_eq!(
+            expand_env_vars("${LUMO_TEST_MCP_DIR}/files").unwrap(),
+            "/data/files"
+        );
+        assert_eq!(
+            expand_env_vars("${LUMO_TEST_MCP_UNSET:-/tmp}/files").unwrap(),
+            "/tmp/files"
+        );
+        assert_eq!(expand_env_vars("no variables").unwrap(), "no variables");
+        assert!(expand_env_vars("${LUMO_TEST_MCP_UNSET}").is_err());
+        assert!(expand_env_vars("${LUMO_TEST_MCP_DIR").is_err());
+    }
+
+    #[test]
+    fn test_hosted_server_config() {
+        let servers: Servers = serde_yaml::from_str(
+            r#"
+search:
+  url: "https://mcp.example.com/mcp"
+  headers:
+    Authorization: "Bearer ${MCP_TOKEN}"
+legacy:
+  url: "https://mcp.example.com/sse"
+  transport: sse
+  max_reconnects: 2
+"#,
+        )
+        .unwrap();
+        servers.validate().unwrap();
+        assert_eq!(servers.servers["search"].transport.unwrap_or_default(), McpTransport::StreamableHttp);
+        assert_eq!(servers.servers["legacy"].transport, Some(McpTransport::Sse));
+
+        let invalid: Servers =
+            serde_yaml::from_str("both:\n  command: npx\n  args: [server]\n  url: \"https://mcp.example.com\"").unwrap();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_prompt_variables() {
+        let servers: Servers = serde_yaml::from_str(
+            r#"
+system_prompt: "You are the assistant of {{company}}, answering in {{tone}}."
+prompt_variables:
+  company: Starlight
+  tone: a formal tone
+"#,
+        )
+        .unwrap();
+        assert!(servers.servers.is_empty());
+        let request: crate::RunTaskRequest = serde_json::from_value(serde_json::json!({
+            "task": "What is the capital of France?",
+            "model": "gpt-4o-mini",
+            "base_url": "https://api.openai.com/v1/chat/completions",
+            "prompt_variables": {"tone": "a casual tone"},
+        }))
+        .unwrap();
+        // The variables of the request win over the ones of servers.yaml.
+        assert_eq!(
+            request.system_prompt(servers.system_prompt.as_deref(), &servers).as_deref(),
+            Some("You are the assistant of Starlight, answering in a casual tone.")
+        );
+        assert_eq!(request.system_prompt(None, &servers), None);
+
+        let request: crate::RunTaskRequest = serde_json::from_value(serde_json::json!({
+            "task": "What is the capital of France?",
+            "model": "gpt-4o-mini",
+            "base_url": "https://api.openai.com/v1/chat/completions",
+            "system_prompt": "You work for {{company}}.",
+            "prompt_variables": {"bad name": "x"},
+        }))
+        .unwrap();
+        assert_eq!(
+            request.system_prompt(servers.system_prompt.as_deref(), &servers).as_deref(),
+            Some("You work for Starlight.")
+        );
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn test_webhooks() {
+        let servers: Servers = serde_yaml::from_str(
+            r#"
+webhooks:
+  deploy:
+    url: "https://hooks.example.com/deploy"
+    secret: "${DEPLOY_WEBHOOK_SECRET}"
+    description: "Notifies the deploy channel"
+    template:
+      text: "{{summary}}"
+"#,
+        )
+        .unwrap();
+        assert!(servers.servers.is_empty());
+        let webhook = &servers.webhooks.as_ref().unwrap()["deploy"];
+        assert_eq!(webhook.url, "https://hooks.example.com/deploy");
+        assert_eq!(webhook.variables().into_iter().collect::<Vec<_>>(), ["summary"]);
+    }
+
+    #[test]
+    fn test_agent_pool() {
+        let servers: Servers = serde_yaml::from_str(
+            r#"
+agent_pool:
+  max_agents: 8
+  idle_timeout: 60
+"#,
+        )
+        .unwrap();
+        let config = servers.agent_pool.unwrap();
+        assert_eq!(config.max_agents, Some(8));
+        assert_eq!(config.idle_timeout, Some(60));
+        assert!(serde_yaml::from_str::<Servers>("agent_pool:\n  max_agent: 8\n").is_err());
+    }
+}
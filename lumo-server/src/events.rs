@@ -0,0 +1,485 @@
+//! The events of the `/stream` endpoint, sent as the data of server-sent events.
+//!
+//! Every event is a JSON object with a `type` and a `schema_version`. Field names are snake_case and only change
+//! with a new schema version: fields can be added within a version, but not renamed or removed. Events without a
+//! `schema_version` are version 1, the format before versioning, and are still read by [`parse_event`].
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use lumo::agent::{AgentStep, RunSummary, Step};
+use lumo::models::openai::Status;
+use lumo::models::partial_json::PatchOperation;
+use lumo::models::reasoning::ContentChunk;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The version of the events sent by the server.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The version of the events sent before the schema was versioned.
+const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+fn legacy_schema_version() -> u32 {
+    LEGACY_SCHEMA_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// Tokens of the model output.
+    Token {
+        content: String,
+    },
+    /// Tokens of the reasoning of a reasoning model, apart from the output.
+    Reasoning {
+        content: String,
+    },
+    /// The changes to the JSON value of the answer parsed so far, as a JSON Patch (RFC 6902), sent with
+    /// `json_answer`. The first patch of each model output replaces the whole document.
+    PartialAnswer {
+        #[schema(value_type = Vec<Object>)]
+        json_patch: Vec<PatchOperation>,
+    },
+    /// An action step with tool calls.
+    Step {
+        step: StepPayload,
+    },
+    /// A tool call the agent starts. It ends with `tool_call_result` or `tool_call_failed`.
+    ToolCallStarted {
+        id: Option<String>,
+        name: String,
+        #[schema(value_type = Object)]
+        arguments: serde_json::Value,
+    },
+    /// A tool call that ran, with the first 1000 characters of its observation. `success` is false when the tool
+    /// returned an error.
+    ToolCallResult {
+        id: Option<String>,
+        name: String,
+        observation: String,
+        latency_ms: u64,
+        success: bool,
+    },
+    /// A tool call that did not run its tool: its arguments are invalid, it uses the result of a failed call, or its
+    /// tool is unavailable.
+    ToolCallFailed {
+        id: Option<String>,
+        name: String,
+        error: String,
+    },
+    CodeExecutionStart {
+        code: String,
+    },
+    CodeExecutionEnd {
+        output: String,
+    },
+    /// The agent asks the user a question. Answer it with `POST /runs/{id}/input`.
+    ClarificationRequired {
+        question: String,
+    },
+    Error {
+        message: String,
+    },
+    /// The run was cancelled with `POST /runs/{id}/cancel` or by the shutdown of the server. Its pending model
+    /// request is aborted.
+    Cancelled,
+    /// An event of a managed agent, while a tool call of the agent runs it. Managed agents of managed agents are
+    /// nested.
+    ManagedAgent {
+        agent: String,
+        #[schema(no_recursion)]
+        event: Box<StreamEvent>,
+    },
+    /// Sent to a client resuming after events that are no longer kept. The stream continues after
+    /// `last_event_id`, the events before are lost.
+    Reset {
+        last_event_id: usize,
+    },
+    /// Steps, tool calls, tokens and errors of the run, sent before `done`.
+    Summary {
+        #[schema(value_type = Object)]
+        summary: RunSummary,
+    },
+    Done,
+}
+
+impl StreamEvent {
+    /// The `partial_answer` event of the patch, `None` when the value did not change.
+    pub fn partial_answer(json_patch: Vec<PatchOperation>) -> Option<Self> {
+        (!json_patch.is_empty()).then_some(StreamEvent::PartialAnswer { json_patch })
+    }
+
+    /// The event of a status of the tool calls, `None` for the other statuses.
+    pub fn tool_call(status: Status) -> Option<Self> {
+        Some(match status {
+            Status::ToolCallStarted {
+                id,
+                name,
+                arguments,
+            } => StreamEvent::ToolCallStarted {
+                id,
+                name,
+                arguments,
+            },
+            Status::ToolCallResult {
+                id,
+                name,
+                observation,
+                latency_ms,
+                success,
+            } => StreamEvent::ToolCallResult {
+                id,
+                name,
+                observation,
+                latency_ms,
+                success,
+            },
+            Status::ToolCallFailed { id, name, error } => {
+                StreamEvent::ToolCallFailed { id, name, error }
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl From<ContentChunk> for StreamEvent {
+    fn from(chunk: ContentChunk) -> Self {
+        match chunk {
+            ContentChunk::Answer(content) => StreamEvent::Token { content },
+            ContentChunk::Reasoning(content) => StreamEvent::Reasoning { content },
+        }
+    }
+}
+
+/// An event with the version of its schema, as sent by the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct VersionedStreamEvent {
+    /// Incremented when a field is renamed or removed. `1` when missing.
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub event: StreamEvent,
+}
+
+impl From<StreamEvent> for VersionedStreamEvent {
+    fn from(event: StreamEvent) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            event,
+        }
+    }
+}
+
+/// The tool calls and timing of an action step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StepPayload {
+    pub step: usize,
+    pub tool_calls: Vec<ToolCallPayload>,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// Time from the start of the step to its end, model call and tool calls included.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// Time the model took to answer.
+    #[serde(default)]
+    pub model_latency_ms: Option<u64>,
+    /// Time each tool call took, in the order of the tool calls.
+    #[serde(default)]
+    pub tool_timings: Vec<ToolTimingPayload>,
+    /// Estimated tokens of the messages sent to the model.
+    #[serde(default)]
+    pub input_tokens: Option<usize>,
+    /// Estimated tokens of the model output.
+    #[serde(default)]
+    pub output_tokens: Option<usize>,
+    /// The checkpoint saved after the step, to resume the run from with `resume_from`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ToolCallPayload {
+    /// The id of the call, matching the `tool_call_id` of its timing. Missing in version 1.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[schema(value_type = Object)]
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ToolTimingPayload {
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    pub name: String,
+    pub duration_ms: u64,
+}
+
+impl StepPayload {
+    /// The payload of a step with tool calls. Steps without tool calls have no event.
+    pub fn from_step(step: &AgentStep) -> Option<Self> {
+        let tool_calls = step.tool_call.as_ref()?;
+        Some(Self {
+            step: step.step,
+            tool_calls: tool_calls
+                .iter()
+                .map(|tool_call| ToolCallPayload {
+                    id: tool_call.id.clone(),
+                    name: tool_call.function.name.clone(),
+                    arguments: tool_call.function.arguments.clone(),
+                })
+                .collect(),
+            started_at: step.started_at,
+            duration_ms: step.duration_ms,
+            model_latency_ms: step.model_latency_ms,
+            tool_timings: step
+                .tool_timings
+                .iter()
+                .map(|timing| ToolTimingPayload {
+                    tool_call_id: timing.tool_call_id.clone(),
+                    name: timing.name.clone(),
+                    duration_ms: timing.duration_ms,
+                })
+                .collect(),
+            input_tokens: step.input_tokens,
+            output_tokens: step.output_tokens,
+            checkpoint_id: None,
+        })
+    }
+
+    pub fn with_checkpoint_id(mut self, checkpoint_id: String) -> Self {
+        self.checkpoint_id = Some(checkpoint_id);
+        self
+    }
+}
+
+/// The event of a status of a managed agent, if it has one.
+pub fn managed_agent_event(agent: String, status: Status) -> Option<StreamEvent> {
+    let event = match status {
+        Status::FirstContent(content) | Status::Content(content) => StreamEvent::Token { content },
+        Status::Reasoning(content) => StreamEvent::Reasoning { content },
+        Status::ToolCallStart(tool_name) => StreamEvent::Token {
+            content: format!("[Using tool: {}]", tool_name),
+        },
+        status @ (Status::ToolCallStarted { .. }
+        | Status::ToolCallResult { .. }
+        | Status::ToolCallFailed { .. }) => StreamEvent::tool_call(status)?,
+        Status::CodeExecutionStart(code) => StreamEvent::CodeExecutionStart { code },
+        Status::CodeExecutionEnd(output) => StreamEvent::CodeExecutionEnd { output },
+        Status::Step(step) => match *step {
+            Step::ActionStep(step) => StreamEvent::Step {
+                step: StepPayload::from_step(&step)?,
+            },
+            _ => return None,
+        },
+        Status::ManagedAgent { agent, status } => managed_agent_event(agent, *status)?,
+        Status::ToolCallContent(_)
+        | Status::ClarificationRequired(_)
+        | Status::Error(_)
+        | Status::Cancelled => return None,
+    };
+    Some(StreamEvent::ManagedAgent {
+        agent,
+        event: Box::new(event),
+    })
+}
+
+/// The data of a server-sent event.
+pub fn to_data(event: StreamEvent) -> String {
+    serde_json::to_string(&VersionedStreamEvent::from(event)).unwrap_or_default()
+}
+
+/// Read the data of a server-sent event of any supported version.
+pub fn parse_event(data: &str) -> Result<StreamEvent> {
+    let event: VersionedStreamEvent = serde_json::from_str(data)?;
+    if event.schema_version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Unsupported stream event schema version {}, the latest is {}",
+            event.schema_version,
+            SCHEMA_VERSION
+        ));
+    }
+    Ok(event.event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumo::agent::ToolCallTiming;
+    use lumo::models::openai::{FunctionCall, ToolCall};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn step() -> AgentStep {
+        AgentStep {
+            step: 1,
+            tool_call: Some(vec![ToolCall {
+                id: Some("call_1".to_string()),
+                call_type: Some("function".to_string()),
+                function: FunctionCall {
+                    name: "search".to_string(),
+                    arguments: json!({ "query": "Paris" }),
+                },
+            }]),
+            duration_ms: Some(1200),
+            tool_timings: vec![ToolCallTiming {
+                tool_call_id: Some("call_1".to_string()),
+                name: "search".to_string(),
+                duration_ms: 800,
+            }],
+            input_tokens: Some(300),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let events = [
+            StreamEvent::Token {
+                content: "Par".to_string(),
+            },
+            StreamEvent::Reasoning {
+                content: "The capital of France".to_string(),
+            },
+            StreamEvent::PartialAnswer {
+                json_patch: vec![PatchOperation::Replace {
+                    path: String::new(),
+                    value: json!({ "capital": "Par" }),
+                }],
+            },
+            StreamEvent::Step {
+                step: StepPayload::from_step(&step()).unwrap(),
+            },
+            StreamEvent::ToolCallStarted {
+                id: Some("call_1".to_string()),
+                name: "search".to_string(),
+                arguments: json!({ "query": "Paris" }),
+            },
+            StreamEvent::ToolCallResult {
+                id: Some("call_1".to_string()),
+                name: "search".to_string(),
+                observation: "Paris is the capital of France".to_string(),
+                latency_ms: 800,
+                success: true,
+            },
+            StreamEvent::ToolCallFailed {
+                id: Some("call_2".to_string()),
+                name: "search".to_string(),
+                error: "missing `query`".to_string(),
+            },
+            StreamEvent::CodeExecutionStart {
+                code: "print(1)".to_string(),
+            },
+            StreamEvent::CodeExecutionEnd {
+                output: "1".to_string(),
+            },
+            StreamEvent::ClarificationRequired {
+                question: "Which Paris?".to_string(),
+            },
+            StreamEvent::ManagedAgent {
+                agent: "researcher".to_string(),
+                event: Box::new(StreamEvent::Token {
+                    content: "Par".to_string(),
+                }),
+            },
+            StreamEvent::Error {
+                message: "The model failed".to_string(),
+            },
+            StreamEvent::Cancelled,
+            StreamEvent::Reset { last_event_id: 10 },
+            StreamEvent::Summary {
+                summary: RunSummary::default(),
+            },
+            StreamEvent::Done,
+        ];
+        for event in events {
+            let data = to_data(event.clone());
+            let value: serde_json::Value = serde_json::from_str(&data).unwrap();
+            assert_eq!(value["schema_version"], SCHEMA_VERSION);
+            assert_eq!(parse_event(&data).unwrap(), event);
+        }
+        assert!(StepPayload::from_step(&AgentStep::default()).is_none());
+    }
+
+    #[test]
+    fn test_step_format() {
+        let data = to_data(StreamEvent::Step {
+            step: StepPayload::from_step(&step())
+                .unwrap()
+                .with_checkpoint_id("run-1".to_string()),
+        });
+        let value: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(value["type"], "step");
+        assert_eq!(value["step"]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            value["step"]["tool_calls"][0]["arguments"]["query"],
+            "Paris"
+        );
+        assert_eq!(value["step"]["tool_timings"][0]["tool_call_id"], "call_1");
+        assert_eq!(value["step"]["duration_ms"], 1200);
+        assert_eq!(value["step"]["checkpoint_id"], "run-1");
+    }
+
+    #[test]
+    fn test_managed_agent_event() {
+        let status = Status::ManagedAgent {
+            agent: "searcher".to_string(),
+            status: Box::new(Status::Step(Box::new(Step::ActionStep(step())))),
+        };
+        let event = managed_agent_event("researcher".to_string(), status).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&to_data(event)).unwrap();
+        assert_eq!(value["type"], "managed_agent");
+        assert_eq!(value["agent"], "researcher");
+        assert_eq!(value["event"]["agent"], "searcher");
+        assert_eq!(value["event"]["event"]["type"], "step");
+        assert_eq!(value["event"]["event"]["step"]["tool_calls"][0]["id"], "call_1");
+
+        // Steps without tool calls have no event, like the steps of the agent.
+        let status = Status::Step(Box::new(Step::ActionStep(AgentStep::default())));
+        assert!(managed_agent_event("researcher".to_string(), status).is_none());
+    }
+
+    #[test]
+    fn test_tool_call_events() {
+        let tool_call = &step().tool_call.unwrap()[0];
+        let observation = "Paris ".repeat(400);
+        let status = Status::tool_call_result(tool_call, &observation, Duration::from_millis(800), true);
+        let value: serde_json::Value =
+            serde_json::from_str(&to_data(StreamEvent::tool_call(status).unwrap())).unwrap();
+        assert_eq!(value["type"], "tool_call_result");
+        assert_eq!(value["id"], "call_1");
+        assert_eq!(value["latency_ms"], 800);
+        assert_eq!(value["success"], true);
+        // The observation is truncated.
+        assert_eq!(value["observation"].as_str().unwrap().chars().count(), 1003);
+
+        let event = StreamEvent::tool_call(Status::tool_call_started(tool_call)).unwrap();
+        assert!(matches!(event, StreamEvent::ToolCallStarted { ref arguments, .. } if arguments["query"] == "Paris"));
+        assert!(StreamEvent::tool_call(Status::Cancelled).is_none());
+
+        // The tool calls of managed agents are nested like their other events.
+        let status = Status::tool_call_failed(tool_call, "missing `query`");
+        let event = managed_agent_event("researcher".to_string(), status).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&to_data(event)).unwrap();
+        assert_eq!(value["event"]["type"], "tool_call_failed");
+        assert_eq!(value["event"]["error"], "missing `query`");
+    }
+
+    #[test]
+    fn test_legacy_events() {
+        // Version 1 events have no schema version, and no ids in the tool calls of steps.
+        let step = r#"{"type":"step","step":{"step":2,"tool_calls":[{"name":"search","arguments":{"query":"Paris"}}],"started_at":null,"duration_ms":null,"model_latency_ms":null,"tool_timings":[],"input_tokens":null,"output_tokens":null}}"#;
+        let StreamEvent::Step { step } = parse_event(step).unwrap() else {
+            panic!("not a step event");
+        };
+        assert_eq!(step.step, 2);
+        assert_eq!(step.tool_calls[0].id, None);
+        assert_eq!(
+            parse_event(r#"{"type":"done"}"#).unwrap(),
+            StreamEvent::Done
+        );
+
+        let future = r#"{"type":"done","schema_version":99}"#;
+        assert!(parse_event(future).is_err());
+    }
+}
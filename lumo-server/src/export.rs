@@ -0,0 +1,114 @@
+//! Export of finished runs as training data, to build fine-tuning datasets from the runs of the server.
+
+use actix_web::{post, web, HttpResponse, Responder};
+use lumo::agent::{export_run, messages_to_steps, to_jsonl, ExportFormat};
+use serde::Deserialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    jobs::{Job, JobQueue, JobStatus},
+    summarize::job_messages,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ExportRequest {
+    /// The ids of the jobs to export.
+    run_ids: Vec<String>,
+    /// `trajectory` (default) for the steps of the runs, or `openai` for the OpenAI chat fine-tuning format.
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    format: ExportFormat,
+    /// Also export the runs that failed. By default only completed runs are exported.
+    #[serde(default)]
+    include_failed: bool,
+}
+
+/// The run as a record, or `None` when it is left out of the export. Runs submitted without `include_transcript`
+/// are exported from their history, task and answer, without their steps.
+fn job_record(job: Job, format: ExportFormat, include_failed: bool) -> Option<Value> {
+    if job.status == JobStatus::Failed && !include_failed {
+        return None;
+    }
+    let steps = messages_to_steps(job_messages(job));
+    Some(export_run(&steps, format))
+}
+
+/// Export finished runs as JSON Lines, one record per run.
+#[utoipa::path(
+    tag = "runs",
+    request_body = ExportRequest,
+    responses(
+        (status = 200, description = "The runs as JSON Lines", content_type = "application/jsonl", body = String),
+        (status = 404, description = "A run does not exist"),
+        (status = 409, description = "A run has not finished"),
+    )
+)]
+#[post("/export")]
+pub(crate) async fn export_runs(
+    req: web::Json<ExportRequest>,
+    jobs: web::Data<JobQueue>,
+) -> Result<impl Responder, actix_web::Error> {
+    let req = req.into_inner();
+    let mut records = Vec::new();
+    for run_id in &req.run_ids {
+        let Some(job) = jobs
+            .get(run_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Run {} does not exist", run_id)
+            })));
+        };
+        if !job.is_finished() {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Run {} has not finished", run_id)
+            })));
+        }
+        records.extend(job_record(job, req.format, req.include_failed));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/jsonl")
+        .body(to_jsonl(&records)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobPriority;
+    use chrono::Utc;
+
+    fn job(status: JobStatus) -> Job {
+        Job {
+            id: nanoid::nanoid!(),
+            priority: JobPriority::Normal,
+            status,
+            request: serde_json::from_value(serde_json::json!({
+                "task": "What is the capital of France?",
+                "model": "gpt-4o-mini",
+                "base_url": "https://api.openai.com/v1/chat/completions",
+            }))
+            .unwrap(),
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            response: Some("Paris".to_string()),
+            transcript: None,
+            error: None,
+            artifacts: Vec::new(),
+            summary: None,
+            history_trimmed: None,
+        }
+    }
+
+    #[test]
+    fn test_job_record() {
+        let record = job_record(job(JobStatus::Completed), ExportFormat::OpenAI, false).unwrap();
+        assert_eq!(record["messages"][0]["content"], "What is the capital of France?");
+        assert_eq!(record["messages"][1]["content"], "Paris");
+
+        assert!(job_record(job(JobStatus::Failed), ExportFormat::Trajectory, false).is_none());
+        assert!(job_record(job(JobStatus::Failed), ExportFormat::Trajectory, true).is_some());
+    }
+}
@@ -0,0 +1,276 @@
+//! Trimming of the history of a request to a token budget before the agent is built, so that long conversations do not
+//! exceed the context window of the model. The oldest messages are removed first, and replaced by a notice or by a
+//! summary written by the model.
+
+use lumo::{
+    agent::summarize,
+    models::{
+        model_traits::Model,
+        tokenizer::TokenCounter,
+        types::{Message, MessageRole},
+    },
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::Servers;
+
+/// Tokens of history kept when neither the request nor servers.yaml set `history_trimming`.
+const DEFAULT_MAX_HISTORY_TOKENS: usize = 64_000;
+/// Tokens left for the summary of the removed messages.
+const SUMMARY_TOKENS: usize = 500;
+
+/// The token budget of the history of a request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoryTrimming {
+    /// Tokens of history sent to the model at most, 64000 by default.
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    /// Replace the removed messages by a summary written by the model of the run, instead of a notice.
+    #[serde(default)]
+    pub summarize: bool,
+}
+
+fn default_max_tokens() -> usize {
+    DEFAULT_MAX_HISTORY_TOKENS
+}
+
+impl Default for HistoryTrimming {
+    fn default() -> Self {
+        Self {
+            max_tokens: DEFAULT_MAX_HISTORY_TOKENS,
+            summarize: false,
+        }
+    }
+}
+
+/// What was removed from the history of a request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TrimmedHistory {
+    /// Messages removed from the start of the history.
+    pub removed_messages: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Whether the removed messages were replaced by a summary.
+    pub summarized: bool,
+}
+
+impl TrimmedHistory {
+    /// The value of the `X-History-Trimmed` header.
+    pub fn header_value(&self) -> String {
+        format!(
+            "removed_messages={}; tokens_before={}; tokens_after={}; summarized={}",
+            self.removed_messages, self.tokens_before, self.tokens_after, self.summarized
+        )
+    }
+}
+
+/// Remove the oldest messages of the history until it fits in `budget` tokens. Leading system messages and the
+/// latest message are kept, and an assistant message is removed together with its tool responses so the tool calls
+/// stay paired. Returns the kept and the removed messages.
+pub(crate) fn trim(
+    history: Vec<Message>,
+    budget: usize,
+    counter: &dyn TokenCounter,
+) -> (Vec<Message>, Vec<Message>) {
+    let mut total = counter.count_message_tokens(&history);
+    if total <= budget {
+        return (history, Vec::new());
+    }
+
+    // Group each message with the tool responses that follow it.
+    let mut blocks: Vec<Vec<Message>> = Vec::new();
+    for message in history {
+        match blocks.last_mut() {
+            Some(block) if message.role == MessageRole::ToolResponse => block.push(message),
+            _ => blocks.push(vec![message]),
+        }
+    }
+    let system = blocks
+        .iter()
+        .take_while(|block| block[0].role == MessageRole::System)
+        .count();
+    let last = blocks.len() - 1;
+
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for (i, block) in blocks.into_iter().enumerate() {
+        if total > budget && i >= system && i != last {
+            total -= counter.count_message_tokens(&block).min(total);
+            removed.extend(block);
+        } else {
+            kept.extend(block);
+        }
+    }
+    (kept, removed)
+}
+
+/// The message replacing the removed messages: their summary, or a notice when there is none.
+fn replacement(removed: usize, summary: Option<String>) -> Message {
+    let content = match summary {
+        Some(summary) => format!(
+            "[Summary of the {} earlier messages of the conversation, removed to fit the context window]\n{}",
+            removed, summary
+        ),
+        None => format!(
+            "[{} earlier messages of the conversation were removed to fit the context window]",
+            removed
+        ),
+    };
+    Message {
+        role: MessageRole::User,
+        content,
+        tool_call_id: None,
+        tool_calls: None,
+    }
+}
+
+/// Fit the history in the budget of `settings`, those of the request, or else of servers.yaml. The removed messages
+/// are summarized with `model` when asked, and replaced by a notice when the summary fails.
+pub(crate) async fn trim_history(
+    history: &mut Option<Vec<Message>>,
+    settings: Option<&HistoryTrimming>,
+    model: &dyn Model,
+) -> Result<Option<TrimmedHistory>, actix_web::Error> {
+    let Some(messages) = history.take() else {
+        return Ok(None);
+    };
+    let settings = match settings {
+        Some(settings) => settings.clone(),
+        None => Servers::load()
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .history_trimming
+            .unwrap_or_default(),
+    };
+    let counter = model.token_counter();
+    let tokens_before = counter.count_message_tokens(&messages);
+    let budget = if settings.summarize {
+        settings.max_tokens.saturating_sub(SUMMARY_TOKENS)
+    } else {
+        settings.max_tokens
+    };
+    let (mut kept, removed) = trim(messages, budget, counter.as_ref());
+    if removed.is_empty() {
+        *history = Some(kept);
+        return Ok(None);
+    }
+
+    let summary = if settings.summarize {
+        match summarize(model, &removed).await {
+            Ok(summary) => Some(format!(
+                "{}\n{}",
+                summary.title,
+                summary
+                    .summary
+                    .iter()
+                    .map(|bullet| format!("- {}", bullet))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+            Err(e) => {
+                log::warn!("Failed to summarize the trimmed history: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let summarized = summary.is_some();
+    let position = kept
+        .iter()
+        .take_while(|message| message.role == MessageRole::System)
+        .count();
+    kept.insert(position, replacement(removed.len(), summary));
+    let trimmed = TrimmedHistory {
+        removed_messages: removed.len(),
+        tokens_before,
+        tokens_after: counter.count_message_tokens(&kept),
+        summarized,
+    };
+    log::info!(
+        "Trimmed the history of the request: {}",
+        trimmed.header_value()
+    );
+    *history = Some(kept);
+    Ok(Some(trimmed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumo::models::{
+        openai::{FunctionCall, ToolCall},
+        tokenizer::HeuristicCounter,
+    };
+    use serde_json::json;
+
+    fn tool_call(id: &str, observation: &str) -> Vec<Message> {
+        vec![
+            Message {
+                role: MessageRole::Assistant,
+                content: String::new(),
+                tool_call_id: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: Some(id.to_string()),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name: "search".to_string(),
+                        arguments: json!({"query": id}),
+                    },
+                }]),
+            },
+            Message {
+                role: MessageRole::ToolResponse,
+                content: observation.to_string(),
+                tool_call_id: Some(id.to_string()),
+                tool_calls: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_trim() {
+        let counter = HeuristicCounter;
+        let mut history = vec![
+            Message::new(MessageRole::System, "You are a helpful assistant."),
+            Message::new(MessageRole::User, &"a".repeat(2000)),
+        ];
+        history.extend(tool_call("call_1", &"b".repeat(2000)));
+        history.push(Message::new(MessageRole::User, "And of Italy?"));
+
+        let (kept, removed) = trim(history.clone(), 100_000, &counter);
+        assert_eq!(kept.len(), history.len());
+        assert!(removed.is_empty());
+
+        let (kept, removed) = trim(history.clone(), 300, &counter);
+        assert!(counter.count_message_tokens(&kept) <= 300);
+        // The tool call is removed with its response.
+        let roles = removed.iter().map(|m| m.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            vec![
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::ToolResponse
+            ]
+        );
+        assert_eq!(kept[0].role, MessageRole::System);
+        assert_eq!(kept[1].content, "And of Italy?");
+
+        // The latest message is kept even when it does not fit.
+        let (kept, _) = trim(history, 1, &counter);
+        assert_eq!(kept.len(), 2);
+
+        let trimmed = TrimmedHistory {
+            removed_messages: 3,
+            tokens_before: 1200,
+            tokens_after: 40,
+            summarized: false,
+        };
+        assert_eq!(
+            trimmed.header_value(),
+            "removed_messages=3; tokens_before=1200; tokens_after=40; summarized=false"
+        );
+        assert!(replacement(3, None).content.contains("3 earlier messages"));
+    }
+}
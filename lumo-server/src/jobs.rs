@@ -0,0 +1,846 @@
+//! Queue of agent runs, executed by a bounded pool of workers.
+//!
+//! Jobs are kept in memory, or in Redis when the server is built with the `redis` feature and `REDIS_URL` is set,
+//! so that several server instances can share one queue.
+//!
+//! A run submitted with an idempotency key is only queued once: the key is stored with the id of its job, and
+//! submitting the key again gives that job back, running or finished, until the key expires.
+
+use std::cmp::Ordering;
+use std::collections::{hash_map::Entry, BinaryHeap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::{error::InternalError, get, post, web, HttpResponse, Responder};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use lumo::{agent::RunSummary, models::types::Message};
+use opentelemetry::{
+    global,
+    trace::{SpanKind, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+use crate::{
+    artifacts::JobArtifact,
+    auth::Caller,
+    history::TrimmedHistory,
+    sessions::{run_in_session, MemorySessions, SessionStore},
+    RunTaskRequest,
+};
+
+/// Number of finished jobs kept in memory. Older jobs are dropped.
+const MAX_FINISHED_JOBS: usize = 1000;
+
+/// How often idle workers and waiters check the queue, for jobs queued by another server instance.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long an idempotency key gives back its job, unless `IDEMPOTENCY_TTL_SECS` is set. Jobs stored in Redis expire
+/// after the same time.
+const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Job {
+    pub id: String,
+    pub priority: JobPriority,
+    pub status: JobStatus,
+    pub request: RunTaskRequest,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub transcript: Option<Vec<Message>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The figures and dataframes produced by the code of the run, and the spoken answer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<JobArtifact>,
+    /// Steps, tool calls, tokens and errors of the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub summary: Option<RunSummary>,
+    /// The messages removed from the history of the request to fit its token budget.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_trimmed: Option<TrimmedHistory>,
+}
+
+impl Job {
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(self.status, JobStatus::Completed | JobStatus::Failed)
+    }
+
+    /// The job without the API keys of its tool settings and the content of its artifacts, to show it in responses.
+    pub(crate) fn redacted(mut self) -> Self {
+        self.request = self.request.redacted();
+        self.artifacts = self.artifacts.iter().map(JobArtifact::link).collect();
+        self
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SubmitJobRequest {
+    #[serde(flatten)]
+    request: RunTaskRequest,
+    #[serde(default)]
+    priority: JobPriority,
+}
+
+/// Entry of the in-memory queue. Higher priorities come first, then older jobs.
+#[derive(PartialEq, Eq)]
+struct QueuedJob {
+    priority: JobPriority,
+    seq: u64,
+    id: String,
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Default)]
+struct MemoryJobs {
+    queue: BinaryHeap<QueuedJob>,
+    jobs: HashMap<String, Job>,
+    finished: VecDeque<String>,
+    seq: u64,
+    /// The job of each idempotency key, and when the key expires.
+    idempotency_keys: HashMap<String, (String, Instant)>,
+}
+
+impl MemoryJobs {
+    fn enqueue(&mut self, job: Job) {
+        self.seq += 1;
+        self.queue.push(QueuedJob {
+            priority: job.priority,
+            seq: self.seq,
+            id: job.id.clone(),
+        });
+        self.jobs.insert(job.id.clone(), job);
+    }
+
+    fn dequeue(&mut self) -> Option<Job> {
+        let queued = self.queue.pop()?;
+        self.jobs.get(&queued.id).cloned()
+    }
+
+    fn save(&mut self, job: Job) {
+        if job.is_finished() {
+            self.finished.push_back(job.id.clone());
+            while self.finished.len() > MAX_FINISHED_JOBS {
+                if let Some(id) = self.finished.pop_front() {
+                    self.jobs.remove(&id);
+                    // The key of a dropped job would give back nothing.
+                    self.idempotency_keys.retain(|_, (job_id, _)| *job_id != id);
+                }
+            }
+        }
+        self.jobs.insert(job.id.clone(), job);
+    }
+
+    fn claim_key(&mut self, key: &str, job_id: &str, ttl: Duration) -> Option<String> {
+        let now = Instant::now();
+        self.idempotency_keys
+            .retain(|_, (_, expires_at)| *expires_at > now);
+        match self.idempotency_keys.entry(key.to_string()) {
+            Entry::Occupied(entry) => Some(entry.get().0.clone()),
+            Entry::Vacant(entry) => {
+                entry.insert((job_id.to_string(), now + ttl));
+                None
+            }
+        }
+    }
+}
+
+enum JobBackend {
+    Memory(Mutex<MemoryJobs>),
+    #[cfg(feature = "redis")]
+    Redis(redis_backend::RedisJobs),
+}
+
+impl JobBackend {
+    async fn queue_length(&self) -> Result<usize> {
+        match self {
+            JobBackend::Memory(jobs) => Ok(jobs.lock().unwrap().queue.len()),
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.queue_length().await,
+        }
+    }
+
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        match self {
+            JobBackend::Memory(jobs) => {
+                jobs.lock().unwrap().enqueue(job);
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.enqueue(&job).await,
+        }
+    }
+
+    async fn dequeue(&self) -> Result<Option<Job>> {
+        match self {
+            JobBackend::Memory(jobs) => Ok(jobs.lock().unwrap().dequeue()),
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.dequeue().await,
+        }
+    }
+
+    async fn save(&self, job: Job) -> Result<()> {
+        match self {
+            JobBackend::Memory(jobs) => {
+                jobs.lock().unwrap().save(job);
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.save(&job).await,
+        }
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Job>> {
+        match self {
+            JobBackend::Memory(jobs) => Ok(jobs.lock().unwrap().jobs.get(id).cloned()),
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.get(id).await,
+        }
+    }
+
+    /// Store the idempotency key with the job, unless it is already stored. Returns the job of a stored key.
+    async fn claim_key(&self, key: &str, job_id: &str, ttl: Duration) -> Result<Option<String>> {
+        match self {
+            JobBackend::Memory(jobs) => Ok(jobs.lock().unwrap().claim_key(key, job_id, ttl)),
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.claim_key(key, job_id, ttl).await,
+        }
+    }
+
+    async fn release_key(&self, key: &str) -> Result<()> {
+        match self {
+            JobBackend::Memory(jobs) => {
+                jobs.lock().unwrap().idempotency_keys.remove(key);
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            JobBackend::Redis(jobs) => jobs.release_key(key).await,
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use anyhow::{Context as _, Result};
+    use std::time::Duration;
+
+    use redis::{aio::ConnectionManager, AsyncCommands};
+    use tokio::sync::OnceCell;
+
+    use super::{Job, JobPriority};
+
+    const QUEUE_KEY: &str = "lumo:jobs:queue";
+    /// Finished and queued jobs expire after a day.
+    const JOB_TTL_SECS: u64 = 24 * 60 * 60;
+
+    pub(super) struct RedisJobs {
+        client: redis::Client,
+        connection: OnceCell<ConnectionManager>,
+    }
+
+    fn job_key(id: &str) -> String {
+        format!("lumo:jobs:{}", id)
+    }
+
+    fn idempotency_key(key: &str) -> String {
+        format!("lumo:idempotency:{}", key)
+    }
+
+    /// Sorted set score: higher priorities first, then older jobs.
+    fn score(job: &Job) -> f64 {
+        let rank = match job.priority {
+            JobPriority::High => 0.0,
+            JobPriority::Normal => 1.0,
+            JobPriority::Low => 2.0,
+        };
+        rank * 1e13 + job.created_at.timestamp_millis() as f64
+    }
+
+    impl RedisJobs {
+        pub(super) fn new(url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(url).context("Invalid REDIS_URL")?,
+                connection: OnceCell::new(),
+            })
+        }
+
+        async fn connection(&self) -> Result<ConnectionManager> {
+            let connection = self
+                .connection
+                .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+                .await
+                .context("Failed to connect to Redis")?;
+            Ok(connection.clone())
+        }
+
+        pub(super) async fn queue_length(&self) -> Result<usize> {
+            Ok(self.connection().await?.zcard(QUEUE_KEY).await?)
+        }
+
+        pub(super) async fn enqueue(&self, job: &Job) -> Result<()> {
+            self.save(job).await?;
+            let _: () = self
+                .connection()
+                .await?
+                .zadd(QUEUE_KEY, &job.id, score(job))
+                .await?;
+            Ok(())
+        }
+
+        pub(super) async fn dequeue(&self) -> Result<Option<Job>> {
+            let popped: Vec<(String, f64)> = self.connection().await?.zpopmin(QUEUE_KEY, 1).await?;
+            match popped.into_iter().next() {
+                Some((id, _)) => self.get(&id).await,
+                None => Ok(None),
+            }
+        }
+
+        pub(super) async fn save(&self, job: &Job) -> Result<()> {
+            let _: () = self
+                .connection()
+                .await?
+                .set_ex(job_key(&job.id), serde_json::to_string(job)?, JOB_TTL_SECS)
+                .await?;
+            Ok(())
+        }
+
+        pub(super) async fn get(&self, id: &str) -> Result<Option<Job>> {
+            let job: Option<String> = self.connection().await?.get(job_key(id)).await?;
+            job.map(|job| serde_json::from_str(&job).context("Failed to parse job"))
+                .transpose()
+        }
+
+        pub(super) async fn claim_key(
+            &self,
+            key: &str,
+            job_id: &str,
+            ttl: Duration,
+        ) -> Result<Option<String>> {
+            let mut connection = self.connection().await?;
+            // SET NX, so that only one instance stores the key.
+            let stored: Option<String> = redis::cmd("SET")
+                .arg(idempotency_key(key))
+                .arg(job_id)
+                .arg("NX")
+                .arg("EX")
+                .arg(ttl.as_secs().max(1))
+                .query_async(&mut connection)
+                .await?;
+            if stored.is_some() {
+                return Ok(None);
+            }
+            Ok(connection.get(idempotency_key(key)).await?)
+        }
+
+        pub(super) async fn release_key(&self, key: &str) -> Result<()> {
+            let _: () = self.connection().await?.del(idempotency_key(key)).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Agent runs waiting for, or executed by, the worker pool.
+#[derive(Clone)]
+pub struct JobQueue {
+    backend: Arc<JobBackend>,
+    workers: usize,
+    capacity: usize,
+    /// Trace context of the jobs submitted to this instance, so their run is traced under the request.
+    contexts: Arc<Mutex<HashMap<String, Context>>>,
+    queued: Arc<Notify>,
+    finished: Arc<Notify>,
+    sessions: Arc<dyn SessionStore>,
+    idempotency_ttl: Duration,
+}
+
+impl JobQueue {
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        Self::with_backend(JobBackend::Memory(Mutex::new(MemoryJobs::default())), workers, capacity)
+    }
+
+    fn with_backend(backend: JobBackend, workers: usize, capacity: usize) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            workers: workers.max(1),
+            capacity,
+            contexts: Arc::new(Mutex::new(HashMap::new())),
+            queued: Arc::new(Notify::new()),
+            finished: Arc::new(Notify::new()),
+            sessions: Arc::new(MemorySessions::default()),
+            idempotency_ttl: Duration::from_secs(DEFAULT_IDEMPOTENCY_TTL_SECS),
+        }
+    }
+
+    /// Store the sessions of the jobs in `sessions` instead of the memory of this instance.
+    pub fn with_sessions(mut self, sessions: Arc<dyn SessionStore>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Configured with `JOB_WORKERS` (default 4), the number of jobs run at the same time,
+    /// `JOB_QUEUE_CAPACITY` (default 100), the number of jobs that can wait before new jobs are rejected, and
+    /// `IDEMPOTENCY_TTL_SECS` (default a day), how long idempotency keys are kept. With the `redis` feature, jobs are
+    /// stored in the Redis instance at `REDIS_URL`, when set.
+    pub fn from_env() -> Result<Self> {
+        let env = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        let workers = env("JOB_WORKERS", 4);
+        let capacity = env("JOB_QUEUE_CAPACITY", 100);
+        let idempotency_ttl =
+            Duration::from_secs(env("IDEMPOTENCY_TTL_SECS", DEFAULT_IDEMPOTENCY_TTL_SECS as usize) as u64);
+        #[cfg(feature = "redis")]
+        if let Ok(url) = std::env::var("REDIS_URL") {
+            let backend = JobBackend::Redis(redis_backend::RedisJobs::new(&url)?);
+            let mut queue = Self::with_backend(backend, workers, capacity);
+            queue.idempotency_ttl = idempotency_ttl;
+            return Ok(queue);
+        }
+        let mut queue = Self::new(workers, capacity);
+        queue.idempotency_ttl = idempotency_ttl;
+        Ok(queue)
+    }
+
+    /// Start the workers. They run until the server stops.
+    pub fn start(&self) {
+        for _ in 0..self.workers {
+            let queue = self.clone();
+            actix_web::rt::spawn(async move {
+                loop {
+                    match queue.backend.dequeue().await {
+                        Ok(Some(job)) => queue.run_job(job).await,
+                        Ok(None) => {
+                            let _ = actix_web::rt::time::timeout(POLL_INTERVAL, queue.queued.notified()).await;
+                        }
+                        Err(e) => {
+                            log::error!("Failed to dequeue job: {}", e);
+                            actix_web::rt::time::sleep(POLL_INTERVAL).await;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Queue a run. Responds with 400 when the preset or the tool settings are invalid, and with 503 and the queue length when the
+    /// queue is full.
+    pub(crate) async fn submit(
+        &self,
+        request: RunTaskRequest,
+        priority: JobPriority,
+        cx: Option<Context>,
+    ) -> Result<Job, actix_web::Error> {
+        self.submit_with_id(nanoid::nanoid!(), request, priority, cx)
+            .await
+    }
+
+    /// Queue a run once per idempotency key. Submitting a key again gives back its job, running or finished, with
+    /// `true`. Responds with 422 when the key was used for another request, and with 409 while its job is being
+    /// queued. A run that is not queued, e.g. because the queue is full, does not keep the key.
+    pub(crate) async fn submit_once(
+        &self,
+        key: &str,
+        request: RunTaskRequest,
+        priority: JobPriority,
+        cx: Option<Context>,
+    ) -> Result<(Job, bool), actix_web::Error> {
+        let id = nanoid::nanoid!();
+        let claimed = self
+            .backend
+            .claim_key(key, &id, self.idempotency_ttl)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(job_id) = claimed {
+            let job = self
+                .backend
+                .get(&job_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .ok_or_else(|| {
+                    actix_web::error::ErrorConflict(
+                        "The run of this Idempotency-Key is being queued, retry later",
+                    )
+                })?;
+            if serde_json::to_value(&job.request).ok() != serde_json::to_value(&request).ok() {
+                return Err(actix_web::error::ErrorUnprocessableEntity(
+                    "The Idempotency-Key was already used for another request",
+                ));
+            }
+            return Ok((job, true));
+        }
+        match self.submit_with_id(id, request, priority, cx).await {
+            Ok(job) => Ok((job, false)),
+            Err(e) => {
+                if let Err(e) = self.backend.release_key(key).await {
+                    log::error!("Failed to release idempotency key: {}", e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn submit_with_id(
+        &self,
+        id: String,
+        request: RunTaskRequest,
+        priority: JobPriority,
+        cx: Option<Context>,
+    ) -> Result<Job, actix_web::Error> {
+        request.validate()?;
+        if request.resume_from.is_some() {
+            return Err(actix_web::error::ErrorBadRequest(
+                "resume_from is only supported by /stream",
+            ));
+        }
+        let queue_length = self
+            .backend
+            .queue_length()
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if queue_length >= self.capacity {
+            let response = HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", POLL_INTERVAL.as_secs().max(1).to_string()))
+                .json(serde_json::json!({
+                    "error": "Job queue is full",
+                    "queue_length": queue_length,
+                }));
+            return Err(InternalError::from_response("Job queue is full", response).into());
+        }
+
+        let job = Job {
+            id,
+            priority,
+            status: JobStatus::Queued,
+            request,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            response: None,
+            transcript: None,
+            error: None,
+            artifacts: Vec::new(),
+            summary: None,
+            history_trimmed: None,
+        };
+        if let Some(cx) = cx {
+            self.contexts.lock().unwrap().insert(job.id.clone(), cx);
+        }
+        self.backend
+            .enqueue(job.clone())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        self.queued.notify_one();
+        Ok(job)
+    }
+
+    pub(crate) async fn get(&self, id: &str) -> Result<Option<Job>> {
+        self.backend.get(id).await
+    }
+
+    /// Wait until the job is finished.
+    pub(crate) async fn wait(&self, id: &str) -> Result<Job> {
+        loop {
+            // Register for notifications before checking, so a job finished in between is not missed.
+            let notified = self.finished.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            match self.backend.get(id).await? {
+                Some(job) if job.is_finished() => return Ok(job),
+                Some(_) => {}
+                None => anyhow::bail!("Job {} not found", id),
+            }
+            let _ = actix_web::rt::time::timeout(POLL_INTERVAL, notified).await;
+        }
+    }
+
+    async fn run_job(&self, mut job: Job) {
+        // The span of a job submitted with a trace context is ended by the submitter.
+        let submitted_cx = self.contexts.lock().unwrap().remove(&job.id);
+        let owns_span = submitted_cx.is_none();
+        let cx = submitted_cx.unwrap_or_else(|| {
+            let tracer = global::tracer("lumo");
+            let span = tracer
+                .span_builder("job_run")
+                .with_kind(SpanKind::Internal)
+                .with_start_time(std::time::SystemTime::now())
+                .with_attributes(vec![
+                    KeyValue::new("gen_ai.operation.name", "job_run"),
+                    KeyValue::new("job.id", job.id.clone()),
+                    KeyValue::new("input.value", job.request.task.clone()),
+                ])
+                .start(&tracer);
+            let cx = Context::current_with_span(span);
+            cx.span().set_attributes(job.request.run_metadata().attributes());
+            cx
+        });
+
+        job.status = JobStatus::Running;
+        job.started_at = Some(Utc::now());
+        if let Err(e) = self.backend.save(job.clone()).await {
+            log::error!("Failed to save job {}: {}", job.id, e);
+        }
+
+        match run_in_session(self.sessions.as_ref(), &job.request, &job.id, &cx).await {
+            Ok(output) => {
+                job.status = JobStatus::Completed;
+                job.response = Some(output.response);
+                job.transcript = output.transcript;
+                job.summary = Some(output.summary);
+                job.history_trimmed = output.history_trimmed;
+                job.artifacts = output
+                    .artifacts
+                    .into_iter()
+                    .map(|artifact| JobArtifact::new(&job.id, artifact))
+                    .collect();
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            }
+        }
+        job.finished_at = Some(Utc::now());
+        if owns_span {
+            cx.span().end_with_timestamp(std::time::SystemTime::now());
+        }
+
+        if let Err(e) = self.backend.save(job.clone()).await {
+            log::error!("Failed to save job {}: {}", job.id, e);
+        }
+        self.finished.notify_waiters();
+    }
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    request_body = SubmitJobRequest,
+    responses(
+        (status = 202, description = "The job is queued", body = Job),
+        (status = 400, description = "resume_from is set"),
+        (status = 422, description = "A field of the request is invalid, e.g. an unknown tool, preset or agent type", body = crate::validation::ValidationErrors),
+        (status = 503, description = "The job queue is full"),
+    )
+)]
+#[post("/jobs")]
+pub(crate) async fn submit_job(
+    jobs: web::Data<JobQueue>,
+    caller: Caller,
+    req: web::Json<SubmitJobRequest>,
+) -> Result<impl Responder, actix_web::Error> {
+    let mut req = req.into_inner();
+    caller.authorize(&mut req.request)?;
+    let job = jobs.submit(req.request, req.priority, None).await?;
+    Ok(HttpResponse::Accepted().json(job.redacted()))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = String, Path, description = "The job id")),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 404, description = "The job does not exist"),
+    )
+)]
+#[get("/jobs/{id}")]
+pub(crate) async fn get_job(
+    jobs: web::Data<JobQueue>,
+    caller: Caller,
+    id: web::Path<String>,
+) -> Result<impl Responder, actix_web::Error> {
+    match jobs.get(&id).await.map_err(actix_web::error::ErrorInternalServerError)? {
+        Some(job) if caller.owns(job.request.caller.as_deref()) => {
+            Ok(HttpResponse::Ok().json(job.redacted()))
+        }
+        _ => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToolConfig;
+
+    fn job(priority: JobPriority) -> Job {
+        let request = serde_json::from_value(serde_json::json!({
+            "task": "What is the capital of France?",
+            "model": "gpt-4o-mini",
+            "base_url": "https://api.openai.com/v1/chat/completions",
+        }))
+        .unwrap();
+        Job {
+            id: nanoid::nanoid!(),
+            priority,
+            status: JobStatus::Queued,
+            request,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            response: None,
+            transcript: None,
+            error: None,
+            artifacts: Vec::new(),
+            summary: None,
+            history_trimmed: None,
+        }
+    }
+
+    #[test]
+    fn test_memory_queue_order() {
+        let mut jobs = MemoryJobs::default();
+        let low = job(JobPriority::Low);
+        let first = job(JobPriority::Normal);
+        let second = job(JobPriority::Normal);
+        let high = job(JobPriority::High);
+        for job in [&low, &first, &second, &high] {
+            jobs.enqueue(job.clone());
+        }
+
+        let order = std::iter::from_fn(|| jobs.dequeue().map(|job| job.id)).collect::<Vec<_>>();
+        assert_eq!(order, vec![high.id, first.id, second.id, low.id]);
+    }
+
+    #[actix_web::test]
+    async fn test_queue_full() {
+        let queue = JobQueue::new(1, 1);
+        let request = job(JobPriority::Normal).request;
+        assert!(queue.submit(request.clone(), JobPriority::Normal, None).await.is_ok());
+
+        let error = queue.submit(request, JobPriority::Normal, None).await.unwrap_err();
+        let response = error.error_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_web::test]
+    async fn test_idempotency_key() {
+        let queue = JobQueue::new(1, 2);
+        let request = job(JobPriority::Normal).request;
+        let (first, replayed) = queue
+            .submit_once("key", request.clone(), JobPriority::Normal, None)
+            .await
+            .unwrap();
+        assert!(!replayed);
+        let (again, replayed) = queue
+            .submit_once("key", request.clone(), JobPriority::Normal, None)
+            .await
+            .unwrap();
+        assert!(replayed);
+        assert_eq!(again.id, first.id);
+        assert_eq!(queue.backend.queue_length().await.unwrap(), 1);
+
+        let mut other = request.clone();
+        other.preset = Some("researcher".to_string());
+        let error = queue
+            .submit_once("key", other, JobPriority::Normal, None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.error_response().status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+
+        // A run that is not queued does not keep its key.
+        queue.submit(request.clone(), JobPriority::Normal, None).await.unwrap();
+        assert!(queue
+            .submit_once("full", request.clone(), JobPriority::Normal, None)
+            .await
+            .is_err());
+        queue.backend.dequeue().await.unwrap();
+        let (_, replayed) = queue
+            .submit_once("full", request, JobPriority::Normal, None)
+            .await
+            .unwrap();
+        assert!(!replayed);
+    }
+
+    #[test]
+    fn test_idempotency_key_expiry() {
+        let mut jobs = MemoryJobs::default();
+        assert_eq!(jobs.claim_key("key", "a", Duration::ZERO), None);
+        // Expired keys are claimed again.
+        assert_eq!(jobs.claim_key("key", "b", Duration::from_secs(60)), None);
+        assert_eq!(
+            jobs.claim_key("key", "c", Duration::from_secs(60)).as_deref(),
+            Some("b")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_preset() {
+        let queue = JobQueue::new(1, 1);
+        let mut request = job(JobPriority::Normal).request;
+        request.preset = Some("astronaut".to_string());
+
+        let error = queue.submit(request, JobPriority::Normal, None).await.unwrap_err();
+        let response = error.error_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(queue.backend.queue_length().await.unwrap(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_tool_config() {
+        let queue = JobQueue::new(1, 1);
+        let mut request = job(JobPriority::Normal).request;
+        let config = ToolConfig {
+            api_key: Some("secret".to_string()),
+            ..Default::default()
+        };
+        request.tool_config = Some(HashMap::from([("DuckDuckGo".to_string(), config.clone())]));
+
+        let error = queue.submit(request.clone(), JobPriority::Normal, None).await.unwrap_err();
+        let response = error.error_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::UNPROCESSABLE_ENTITY);
+
+        request.tool_config = Some(HashMap::from([("ExaSearchTool".to_string(), config)]));
+        let job = queue.submit(request, JobPriority::Normal, None).await.unwrap();
+        let request = job.request.redacted();
+        assert_eq!(
+            request.tool_config.unwrap()["ExaSearchTool"].api_key.as_deref(),
+            Some("***")
+        );
+    }
+}
@@ -1,43 +1,95 @@
+pub mod agent_pool;
+pub mod artifacts;
+pub mod audio;
 pub mod auth;
+pub mod cancellation;
+pub mod checkpoints;
+pub mod clarification;
 pub mod config;
-use actix_web::{dev::Server, get, post, web::Json, App, HttpResponse, HttpServer, Responder};
+pub mod events;
+pub mod export;
+pub mod history;
+pub mod jobs;
+#[cfg(feature = "mcp")]
+pub mod mcp_pool;
+pub mod memories;
+pub mod openapi;
+pub mod pipeline;
+pub mod request_log;
+pub mod scheduler;
+pub mod sessions;
+pub mod sse;
+pub mod summarize;
+pub mod usage;
+pub mod validation;
+use actix_web::{
+    dev::Server, get, post, web, web::Json, App, HttpMessage, HttpRequest, HttpResponse, HttpServer,
+    Responder,
+};
+use agent_pool::{AgentLease, AgentPool};
 use anyhow::Result;
-use base64::{self, Engine};
+use artifacts::JobArtifact;
+use auth::Caller;
+use cancellation::run_cancellations;
 use std::pin::Pin;
-use config::Servers;
+use std::sync::{Arc, OnceLock};
+use clarification::{PendingInputs, StreamUserInput};
+use config::{Servers, ToolConfig};
+use history::{HistoryTrimming, TrimmedHistory};
+use events::{managed_agent_event, StepPayload, StreamEvent, VersionedStreamEvent};
+use jobs::{JobPriority, JobQueue, JobStatus};
+use scheduler::Scheduler;
+use sessions::{SessionInboxes, SessionStream};
+use sse::StreamRegistry;
+use usage::{record_usage, Usage};
+use validation::{ValidationErrors, MAX_STEPS};
 use lumo::{
-    agent::{Agent, AgentStream, FunctionCallingAgentBuilder, Step},
-    models::{openai::{OpenAIServerModelBuilder, Status}, types::Message},
+    agent::{
+        Agent, AgentStream, Artifact, Checkpoint, CheckpointStore, FunctionCallingAgent,
+        FunctionCallingAgentBuilder,
+        LongTermMemory, ResponseLanguage, RunSummary, Step,
+    },
+    errors::AgentError,
+    guardrails::{Guardrail, KeywordFilter, LlmModerationFilter},
+    telemetry::RunMetadata,
+    models::{
+        batching::{TokenBatcher, TokenBatching},
+        openai::{OpenAIServerModel, OpenAIServerModelBuilder, Status},
+        partial_json::PartialJson,
+        providers::ProviderPreset,
+        reasoning::ContentChunk,
+        types::Message,
+    },
+    presets::{self, AgentPreset},
+    secrets::{EnvSecrets, SecretChain, SecretKey, SecretProvider},
     tools::{
-        exa_search::ExaSearchTool, AsyncTool, DuckDuckGoSearchTool, GoogleSearchTool,
-        VisitWebsiteTool,
+        exa_search::ExaSearchTool, AskUserTool, AsyncTool, BudgetedTool, DateTimeTool, DuckDuckGoSearchTool, ToolBudget, E2BInterpreterTool,
+        GoogleCredentials, GoogleDriveTool, GoogleSearchTool, HttpRequestTool, SlackTool,
+        VisitWebsiteTool, WebCrawlTool, WebhookTool, Zone,
     },
 };
 #[cfg(feature = "code")]
-use lumo::agent::CodeAgentBuilder;
+use lumo::agent::{CodeAgent, CodeAgentBuilder};
 use opentelemetry::trace::FutureExt;
 use opentelemetry::trace::Tracer;
-use opentelemetry::trace::TracerProvider;
 use opentelemetry::Context;
 use opentelemetry::KeyValue;
 use opentelemetry::{
     global,
     trace::{SpanKind, TraceContextExt},
 };
-use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
 use opentelemetry_sdk::trace::SdkTracerProvider;
-use opentelemetry_sdk::trace::{self as sdktrace, BatchConfigBuilder, BatchSpanProcessor};
 use tracing::instrument;
 use tokio::sync::broadcast;
-use actix_web::web::Bytes;
 use futures::StreamExt;
 
 #[cfg(feature = "code")]
 use lumo::tools::PythonInterpreterTool;
 #[cfg(feature = "mcp")]
 use {
-    lumo::agent::McpAgentBuilder,
-
+    lumo::agent::{McpAgent, McpAgentBuilder, ToolNamespacing},
+    mcp_pool::{McpLeases, McpPool},
+    rmcp::{service::RunningService, RoleClient},
 };
 
 use actix_cors::Cors;
@@ -45,10 +97,12 @@ use actix_web::http::header;
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use std::net::TcpListener;
+use std::collections::HashMap;
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
-struct RunTaskRequest {
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct RunTaskRequest {
     task: String,
     model: String,
     base_url: String,
@@ -57,26 +111,419 @@ struct RunTaskRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     max_steps: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Object>>)]
     history: Option<Vec<Message>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     agent_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_results: Option<usize>,
+    #[serde(default)]
+    include_transcript: bool,
+    /// Tags added to the telemetry spans of the run, to filter traces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    /// Metadata added to the telemetry spans of the run, e.g. a user or tenant id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<HashMap<String, String>>,
+    /// Settings of the tools by tool name, e.g. `{"ExaSearchTool": {"api_key": "...", "max_results": 3}}`. They
+    /// override the `tool_config` of servers.yaml.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<HashMap<String, ToolConfig>>)]
+    pub(crate) tool_config: Option<HashMap<String, ToolConfig>>,
+    /// A ready-made agent configuration, e.g. `researcher`. Sets the agent type, the tools and the planning
+    /// interval; `agent_type` and `tools` are applied on top of it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preset: Option<String>,
+    /// Continue the conversation of this session: the run starts with the history of the session, and its task and
+    /// answer are added to it. On `/stream`, the stream stays open after the run for the next messages of the
+    /// session, posted to `/sessions/{id}/message`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) session_id: Option<String>,
+    /// Replaces the system prompt of the preset and of servers.yaml for this run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_prompt: Option<String>,
+    /// Values of the `{{name}}` placeholders of the system prompt, e.g. `{"company": "Acme"}`. They override the
+    /// `prompt_variables` of servers.yaml.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_variables: Option<HashMap<String, String>>,
+    /// Render the answer as speech with the `audio` provider of servers.yaml, returned as an `audio` artifact.
+    /// Supported by `/run`, `/jobs` and schedules.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    speak_answer: bool,
+    /// The language of the answer: `auto` for the language of the task, or a language code or name such as `fr` or
+    /// `French`. An answer in another language is sent back once to be written again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    response_language: Option<ResponseLanguage>,
+    /// Ask for the answer as a single JSON value, e.g. an array of objects for a table. On `/stream`, the value
+    /// parsed so far is also sent while the answer is written, as JSON Patches in `partial_answer` events.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    json_answer: bool,
+    /// Continue the run of this checkpoint, the `checkpoint_id` of its step events, from its next step instead of
+    /// starting the task. The tool calls of the completed steps are not made again. Only supported by `/stream`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) resume_from: Option<String>,
+    /// The token budget of the `history`, and of the history of the session, overriding the `history_trimming` of
+    /// servers.yaml. The oldest messages are removed first, and replaced by a notice or a summary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history_trimming: Option<HistoryTrimming>,
+    /// The time zone of the current time in the system prompt, and the default one of the `DateTime` tool: an IANA
+    /// name such as `Europe/Paris`, `UTC` or an offset such as `UTC+9`. The local time zone of the server is used
+    /// without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_zone: Option<String>,
+    /// Recall the memories of this scope relevant to the task, e.g. of a user id, into the system prompt, and
+    /// remember the facts of the run in it for its next runs, whatever their session. With auth, each API key has
+    /// its own scopes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_scope: Option<String>,
+    /// The name of the API key the usage of the run is counted against. Set by the server when the run is
+    /// authorized, whatever the request says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(ignore)]
+    pub(crate) caller: Option<String>,
+}
+
+impl RunTaskRequest {
+    fn run_metadata(&self) -> RunMetadata {
+        RunMetadata::new(
+            self.tags.clone().unwrap_or_default(),
+            self.metadata.clone().unwrap_or_default(),
+        )
+    }
+
+    /// The long-term memory of the `memory_scope` of the request, within the scopes of its caller.
+    fn long_term_memory(&self) -> Option<LongTermMemory> {
+        self.memory_scope
+            .as_deref()
+            .map(|scope| memories::long_term_memory(self.caller.as_deref(), scope))
+    }
+
+    /// The preset of the request. Responds with 400 when the preset does not exist.
+    pub(crate) fn preset(&self) -> Result<Option<AgentPreset>, actix_web::Error> {
+        self.preset
+            .as_deref()
+            .map(|name| {
+                presets::get(name).ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest(format!(
+                        "Unknown preset {}, available presets: {}",
+                        name,
+                        presets::names().join(", ")
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// The agent type of the request, defaulting to the one of its preset.
+    fn agent_type<'a>(&'a self, preset: Option<&AgentPreset>) -> Option<&'a str> {
+        self.agent_type
+            .as_deref()
+            .or(preset.map(|preset| preset.agent_type.as_str()))
+    }
+
+    /// The system prompt of the run: the one of the request, or else `default`, with its `{{name}}` placeholders
+    /// filled from the `prompt_variables` of servers.yaml and of the request.
+    fn system_prompt(&self, default: Option<&str>, servers: &Servers) -> Option<String> {
+        let mut prompt = self.system_prompt.as_deref().or(default)?.to_string();
+        let mut variables = servers.prompt_variables.clone().unwrap_or_default();
+        variables.extend(self.prompt_variables.clone().unwrap_or_default());
+        for (name, value) in variables {
+            prompt = prompt.replace(&format!("{{{{{}}}}}", name), &value);
+        }
+        Some(prompt)
+    }
+
+    /// The tools of the preset, followed by the requested tools that the preset does not have.
+    fn tools(
+        &self,
+        preset: Option<&AgentPreset>,
+        ask_user: Option<&AskUserTool>,
+    ) -> Result<Vec<Box<dyn AsyncTool>>, actix_web::Error> {
+        let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
+        let default_config = servers.tool_config.unwrap_or_default();
+        let budgets = servers.tool_budgets.unwrap_or_default();
+        let mut tools = match preset {
+            Some(preset) => preset
+                .tools()
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .into_iter()
+                .map(|tool| {
+                    let budget = budgets.get(tool.name());
+                    with_budget(tool, budget, self.max_results)
+                })
+                .collect(),
+            None => vec![],
+        };
+        for tool in self.tools.iter().flatten() {
+            let tool_type = ToolType::from_str(tool)?;
+            let mut config = default_config
+                .get(tool)
+                .cloned()
+                .unwrap_or_default()
+                .merge(&self.tool_config(tool));
+            config.validate(tool_type.settings()).map_err(|e| {
+                actix_web::error::ErrorBadRequest(format!("Invalid tool_config for {}: {}", tool, e))
+            })?;
+            config.max_results = config.max_results.or(self.max_results);
+            config.time_zone = config.time_zone.or_else(|| self.time_zone.clone());
+            let budget = budgets.get(tool);
+            let tool = create_tool(&tool_type, &config, ask_user)?;
+            let budget = budget.or_else(|| budgets.get(tool.name()));
+            let tool = with_budget(tool, budget, config.max_results);
+            if !tools.iter().any(|t| t.name() == tool.name()) {
+                tools.push(tool);
+            }
+        }
+        Ok(tools)
+    }
+
+    /// The key of the pooled agents that can run the request, the same for the requests whose agents are built the
+    /// same way. `None` when the agent can not be shared across runs, because one of its tools keeps state between
+    /// its calls or servers.yaml counts the calls of the tools per run.
+    fn agent_pool_key(
+        &self,
+        agent_type: &str,
+        tools: &[Box<dyn AsyncTool>],
+        servers: &Servers,
+        stream: bool,
+    ) -> Option<u64> {
+        if tools.iter().any(|tool| STATEFUL_TOOLS.contains(&tool.name()))
+            || servers
+                .tool_budgets
+                .iter()
+                .flat_map(HashMap::values)
+                .any(|budget| budget.calls_per_run.is_some())
+        {
+            return None;
+        }
+        // What only changes the run, not the agent, is left out.
+        let settings = RunTaskRequest {
+            task: String::new(),
+            history: None,
+            include_transcript: false,
+            session_id: None,
+            speak_answer: false,
+            memory_scope: None,
+            resume_from: None,
+            history_trimming: None,
+            caller: None,
+            ..self.clone()
+        };
+        let settings = serde_json::to_string(&(agent_type, stream, settings, servers)).ok()?;
+        Some(agent_pool::key(&settings))
+    }
+
+    /// The time zone of the request, checked by [`RunTaskRequest::validate`].
+    fn time_zone(&self) -> Option<Zone> {
+        self.time_zone.as_deref().and_then(|time_zone| time_zone.parse().ok())
+    }
+
+    /// The settings of the request for a tool.
+    fn tool_config(&self, tool: &str) -> ToolConfig {
+        self.tool_config
+            .as_ref()
+            .and_then(|tool_config| tool_config.get(tool))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Check the fields of the request, before it is queued or run. All the problems are reported at once, and
+    /// respond with 422.
+    pub(crate) fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let preset = self.preset().unwrap_or_else(|e| {
+            errors.add("preset", e.to_string());
+            None
+        });
+        if let Some(agent_type) = &self.agent_type {
+            if !AGENT_TYPES.contains(&agent_type.as_str()) {
+                errors.add(
+                    "agent_type",
+                    validation::unknown("agent types", &[agent_type], AGENT_TYPES),
+                );
+            }
+        }
+        if let Some(tools) = &self.tools {
+            // The tools of the MCP agent are the MCP servers of servers.yaml.
+            let available = if self.agent_type(preset.as_ref()) == Some("mcp") {
+                Servers::load()
+                    .map(|servers| servers.servers.into_keys().collect::<Vec<_>>())
+                    .ok()
+            } else {
+                Some(ToolType::names().iter().map(|name| name.to_string()).collect())
+            };
+            if let Some(available) = available {
+                let unknown = tools
+                    .iter()
+                    .filter(|tool| !available.contains(tool))
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                if !unknown.is_empty() {
+                    let mut available = available.iter().map(String::as_str).collect::<Vec<_>>();
+                    available.sort_unstable();
+                    errors.add("tools", validation::unknown("tools", &unknown, &available));
+                }
+            }
+        }
+        if let Some(max_steps) = self.max_steps {
+            if max_steps == 0 || max_steps > MAX_STEPS {
+                errors.add(
+                    "max_steps",
+                    format!("max_steps must be between 1 and {}, got {}", MAX_STEPS, max_steps),
+                );
+            }
+        }
+        if let Some(session_id) = &self.session_id {
+            let valid = !session_id.is_empty()
+                && session_id.len() <= 128
+                && session_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+            if !valid {
+                errors.add(
+                    "session_id",
+                    "session_id must be 1 to 128 letters, digits, '-', '_', '.' or ':'",
+                );
+            }
+        }
+        if let Some(scope) = &self.memory_scope {
+            let valid = !scope.is_empty()
+                && scope.len() <= 128
+                && scope
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+            if !valid {
+                errors.add(
+                    "memory_scope",
+                    "memory_scope must be 1 to 128 letters, digits, '-', '_', '.' or ':'",
+                );
+            }
+        }
+        for name in self.prompt_variables.iter().flat_map(HashMap::keys) {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                errors.add(
+                    format!("prompt_variables.{}", name),
+                    format!("Invalid prompt variable '{}': use letters, digits and '_'", name),
+                );
+            }
+        }
+        if self
+            .history_trimming
+            .as_ref()
+            .is_some_and(|trimming| trimming.max_tokens == 0)
+        {
+            errors.add(
+                "history_trimming.max_tokens",
+                "history_trimming.max_tokens must be greater than 0",
+            );
+        }
+        if let Some(Err(e)) = self.time_zone.as_deref().map(Zone::from_str) {
+            errors.add("time_zone", e.to_string());
+        }
+        let mut tool_config = self.tool_config.iter().flatten().collect::<Vec<_>>();
+        tool_config.sort_by_key(|(tool, _)| tool.as_str());
+        for (tool, config) in tool_config {
+            let field = format!("tool_config.{}", tool);
+            match ToolType::from_str(tool) {
+                Ok(tool_type) => {
+                    if let Err(e) = config.validate(tool_type.settings()) {
+                        errors.add(field, format!("Invalid tool_config for {}: {}", tool, e));
+                    }
+                }
+                Err(_) => errors.add(field, validation::unknown("tools", &[tool], ToolType::names())),
+            }
+        }
+        errors.into_result()
+    }
+
+    /// The request without the API keys of its tool settings, to show it in responses.
+    pub(crate) fn redacted(mut self) -> Self {
+        for config in self.tool_config.iter_mut().flat_map(HashMap::values_mut) {
+            if config.api_key.is_some() {
+                config.api_key = Some("***".to_string());
+            }
+        }
+        self
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct RunTaskResponse {
     response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Vec<Object>>)]
+    transcript: Option<Vec<Message>>,
+    /// The figures and dataframes produced by the code of the run, and the spoken answer, with their download links.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    artifacts: Vec<JobArtifact>,
+    /// Steps, tool calls, tokens and errors of the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    summary: Option<RunSummary>,
+    /// The messages removed from the history to fit its token budget, also sent in the `X-History-Trimmed` header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history_trimmed: Option<TrimmedHistory>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 enum ToolType {
     DuckDuckGo,
     VisitWebsite,
+    WebCrawl,
     GoogleSearchTool,
     ExaSearchTool,
+    E2BInterpreter,
+    Slack,
+    HttpRequest,
+    GoogleDrive,
+    Webhook,
+    DateTime,
     #[cfg(feature = "code")]
     PythonInterpreter,
+    #[cfg(feature = "screenshot")]
+    WebScreenshot,
+    AskUser,
+}
+
+impl ToolType {
+    /// The names of the tools the server can create.
+    fn names() -> &'static [&'static str] {
+        &[
+            "DuckDuckGo",
+            "VisitWebsite",
+            "WebCrawl",
+            "GoogleSearchTool",
+            "ExaSearchTool",
+            "E2BInterpreter",
+            "Slack",
+            "HttpRequest",
+            "GoogleDrive",
+            "Webhook",
+            "DateTime",
+            #[cfg(feature = "code")]
+            "PythonInterpreter",
+            #[cfg(feature = "screenshot")]
+            "WebScreenshot",
+            "AskUser",
+        ]
+    }
+
+    /// The `tool_config` settings the tool supports.
+    fn settings(&self) -> &'static [&'static str] {
+        match self {
+            ToolType::DuckDuckGo => &["max_results"],
+            ToolType::VisitWebsite => &["allowed_domains", "max_length"],
+            ToolType::WebCrawl => &["max_pages", "max_length"],
+            ToolType::GoogleSearchTool | ToolType::E2BInterpreter => &["api_key"],
+            ToolType::ExaSearchTool => &["api_key", "max_results"],
+            ToolType::Slack => &["api_key", "allowed_channels"],
+            ToolType::HttpRequest => &["api_key", "allowed_domains", "max_response_size"],
+            ToolType::GoogleDrive => &["api_key", "max_length"],
+            ToolType::DateTime => &["time_zone"],
+            _ => &[],
+        }
+    }
 }
 
 impl FromStr for ToolType {
@@ -86,10 +533,20 @@ impl FromStr for ToolType {
         match s {
             "DuckDuckGo" => Ok(ToolType::DuckDuckGo),
             "VisitWebsite" => Ok(ToolType::VisitWebsite),
+            "WebCrawl" => Ok(ToolType::WebCrawl),
             "GoogleSearchTool" => Ok(ToolType::GoogleSearchTool),
             "ExaSearchTool" => Ok(ToolType::ExaSearchTool),
+            "E2BInterpreter" => Ok(ToolType::E2BInterpreter),
+            "Slack" => Ok(ToolType::Slack),
+            "HttpRequest" => Ok(ToolType::HttpRequest),
+            "GoogleDrive" => Ok(ToolType::GoogleDrive),
+            "Webhook" => Ok(ToolType::Webhook),
+            "DateTime" => Ok(ToolType::DateTime),
             #[cfg(feature = "code")]
             "PythonInterpreter" => Ok(ToolType::PythonInterpreter),
+            #[cfg(feature = "screenshot")]
+            "WebScreenshot" => Ok(ToolType::WebScreenshot),
+            "AskUser" => Ok(ToolType::AskUser),
             _ => Err(actix_web::error::ErrorBadRequest(format!(
                 "Invalid tool type: {}",
                 s
@@ -98,117 +555,219 @@ impl FromStr for ToolType {
     }
 }
 
-fn create_tool(tool_type: &ToolType, max_results: Option<usize>) -> Box<dyn AsyncTool> {
-    match tool_type {
-        ToolType::DuckDuckGo => Box::new(DuckDuckGoSearchTool::new()),
-        ToolType::VisitWebsite => Box::new(VisitWebsiteTool::new()),
-        ToolType::GoogleSearchTool => Box::new(GoogleSearchTool::new(None)),
-        ToolType::ExaSearchTool => Box::new(ExaSearchTool::new(max_results.unwrap_or(5), None)),
-        #[cfg(feature = "code")]
-        ToolType::PythonInterpreter => Box::new(PythonInterpreterTool::new()),
+/// The agent types of `agent_type`. The function calling agent also runs the requests without one.
+const AGENT_TYPES: &[&str] = &[
+    "function-calling",
+    #[cfg(feature = "code")]
+    "code-agent",
+    #[cfg(feature = "mcp")]
+    "mcp",
+];
+
+/// The tools holding a session, a sandbox or the stream of their run, whose agents are not pooled.
+const STATEFUL_TOOLS: [&str; 3] = ["python_interpreter", "e2b_interpreter", "ask_user"];
+
+/// Wrap a tool with its budget from servers.yaml, if it has one. Once the budget is spent, DuckDuckGo is called
+/// instead.
+fn with_budget(
+    tool: Box<dyn AsyncTool>,
+    budget: Option<&ToolBudget>,
+    max_results: Option<usize>,
+) -> Box<dyn AsyncTool> {
+    let Some(budget) = budget else {
+        return tool;
+    };
+    let fallback = DuckDuckGoSearchTool::new().with_max_results(max_results);
+    let is_free = tool.name() == fallback.tool.name;
+    let budgeted = BudgetedTool::new(tool, *budget);
+    if is_free {
+        Box::new(budgeted)
+    } else {
+        Box::new(budgeted.with_fallback(Box::new(fallback)))
     }
 }
 
-pub fn init_tracer() -> Option<SdkTracerProvider> {
-    dotenv().ok();
-
-    let (langfuse_public_key, langfuse_secret_key, endpoint) = if cfg!(debug_assertions) {
-        match (
-            std::env::var("LANGFUSE_PUBLIC_KEY_DEV"),
-            std::env::var("LANGFUSE_SECRET_KEY_DEV"),
-            std::env::var("LANGFUSE_HOST_DEV"),
-        ) {
-            (Ok(public_key), Ok(secret_key), Ok(host)) => (
-                public_key,
-                secret_key,
-                format!("{}/api/public/otel/v1/traces", host),
-            ),
-            _ => return None, // If any key is missing, return None to disable tracing
+/// Create the guardrails configured in `servers.yaml`. The moderation filter uses the request's model.
+fn create_guardrails(
+    model: &OpenAIServerModel,
+) -> Result<Vec<Box<dyn Guardrail>>, actix_web::Error> {
+    let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut guardrails: Vec<Box<dyn Guardrail>> = Vec::new();
+    if let Some(config) = servers.guardrails {
+        if !config.blocked_keywords.is_empty() || !config.blocked_patterns.is_empty() {
+            let filter = KeywordFilter::new(config.blocked_keywords, config.blocked_patterns)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            guardrails.push(Box::new(filter));
         }
-    } else {
-        match (
-            std::env::var("LANGFUSE_PUBLIC_KEY"),
-            std::env::var("LANGFUSE_SECRET_KEY"),
-            std::env::var("LANGFUSE_HOST"),
-        ) {
-            (Ok(public_key), Ok(secret_key), Ok(host)) => (
-                public_key,
-                secret_key,
-                format!("{}/api/public/otel/v1/traces", host),
-            ),
-            _ => return None, // If any key is missing, return None to disable tracing
+        if config.moderation {
+            let mut filter = LlmModerationFilter::new(model.clone());
+            if let Some(policy) = &config.moderation_policy {
+                filter = filter.with_policy(policy);
+            }
+            guardrails.push(Box::new(filter));
         }
-    };
-
-    // Basic Auth: base64(public_key:secret_key)
-    let auth_header = format!(
-        "Basic {}",
-        base64::engine::general_purpose::STANDARD
-            .encode(format!("{}:{}", langfuse_public_key, langfuse_secret_key))
-    );
-
-    let mut headers = std::collections::HashMap::new();
-    headers.insert("Authorization".to_string(), auth_header);
-
-    let otlp_exporter = match opentelemetry_otlp::SpanExporter::builder()
-        .with_http()
-        .with_endpoint(endpoint)
-        .with_protocol(opentelemetry_otlp::Protocol::HttpBinary)
-        .with_headers(headers)
-        .build()
-    {
-        Ok(exporter) => exporter,
-        Err(_) => return None,
-    };
-
-    let batch = BatchSpanProcessor::builder(otlp_exporter)
-        .with_batch_config(
-            BatchConfigBuilder::default()
-                .with_max_queue_size(512)
-                .build(),
-        )
-        .build();
-
-    let tracer_provider = sdktrace::SdkTracerProvider::builder()
-        .with_span_processor(batch)
-        .with_resource(
-            opentelemetry_sdk::resource::Resource::builder()
-                .with_service_name("lumo")
-                .with_attributes(vec![
-                    KeyValue::new(
-                        "deployment.environment",
-                        if cfg!(debug_assertions) {
-                            "development".to_string()
-                        } else {
-                            std::env::var("ENVIRONMENT")
-                                .unwrap_or_else(|_| "production".to_string())
-                        },
-                    ),
-                    KeyValue::new("deployment.name", "lumo"),
-                    KeyValue::new("deployment.version", env!("CARGO_PKG_VERSION")),
-                ])
-                .build(),
-        )
-        .build();
+    }
+    Ok(guardrails)
+}
 
-    // Initialize the tracer
-    let _ = tracer_provider.tracer("lumo");
+/// Map an agent error to a response. Guardrail violations are client errors and carry the violation as JSON.
+fn agent_error(e: AgentError) -> actix_web::Error {
+    match e {
+        AgentError::Guardrail(violation) => {
+            let body = serde_json::from_str::<serde_json::Value>(&violation)
+                .unwrap_or_else(|_| serde_json::json!({ "reason": violation }));
+            actix_web::error::InternalError::from_response(
+                violation,
+                HttpResponse::BadRequest().json(serde_json::json!({ "guardrail_violation": body })),
+            )
+            .into()
+        }
+        e => actix_web::error::ErrorInternalServerError(e),
+    }
+}
 
-    // Set the global tracer provider
-    opentelemetry::global::set_tracer_provider(tracer_provider.clone());
+/// Create a tool of the request with its settings. `ask_user` is the tool asking the client of the run, which only
+/// `/stream` has.
+fn create_tool(
+    tool_type: &ToolType,
+    config: &ToolConfig,
+    ask_user: Option<&AskUserTool>,
+) -> Result<Box<dyn AsyncTool>, actix_web::Error> {
+    let api_key = config.api_key.clone();
+    Ok(match tool_type {
+        ToolType::DuckDuckGo => Box::new(DuckDuckGoSearchTool::new().with_max_results(config.max_results)),
+        ToolType::VisitWebsite => {
+            let tool = VisitWebsiteTool::new()
+                .with_allowed_domains(config.allowed_domains.clone().unwrap_or_default());
+            Box::new(match config.max_length {
+                Some(max_length) => tool.with_max_length(max_length),
+                None => tool,
+            })
+        }
+        ToolType::WebCrawl => {
+            let mut tool = WebCrawlTool::new();
+            if let Some(max_pages) = config.max_pages {
+                tool = tool.with_max_pages(max_pages);
+            }
+            if let Some(max_length) = config.max_length {
+                tool = tool.with_max_length(max_length);
+            }
+            Box::new(tool)
+        }
+        ToolType::GoogleSearchTool => Box::new(match api_key {
+            Some(api_key) => GoogleSearchTool::new(Some(api_key)),
+            None => GoogleSearchTool::from_secret(SecretKey::new("SERPAPI_API_KEY", secrets())),
+        }),
+        ToolType::ExaSearchTool => {
+            let max_results = config.max_results.unwrap_or(5);
+            Box::new(match api_key {
+                Some(api_key) => ExaSearchTool::new(max_results, Some(api_key)),
+                None => ExaSearchTool::from_secret(max_results, SecretKey::new("EXA_API_KEY", secrets())),
+            })
+        }
+        ToolType::E2BInterpreter => Box::new(E2BInterpreterTool::new(api_key)),
+        ToolType::Slack => {
+            let tool = match api_key {
+                Some(token) => SlackTool::new(&token),
+                None => SlackTool::from_env().map_err(actix_web::error::ErrorBadRequest)?,
+            };
+            Box::new(match &config.allowed_channels {
+                Some(channels) => tool.with_allowed_channels(channels.clone()),
+                None => tool,
+            })
+        }
+        ToolType::HttpRequest => {
+            let allowed_domains = config
+                .allowed_domains
+                .clone()
+                .filter(|domains| !domains.is_empty())
+                .ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest("HttpRequest needs 'allowed_domains' in its tool_config")
+                })?;
+            let mut tool = HttpRequestTool::new(allowed_domains);
+            if let Some(token) = api_key {
+                tool = tool.with_headers(HashMap::from([(
+                    "Authorization".to_string(),
+                    format!("Bearer {}", token),
+                )]));
+            }
+            if let Some(max_response_size) = config.max_response_size {
+                tool = tool.with_max_response_size(max_response_size);
+            }
+            Box::new(tool)
+        }
+        ToolType::GoogleDrive => {
+            let tool = match api_key {
+                Some(token) => GoogleDriveTool::new(GoogleCredentials::AccessToken(token)),
+                None => GoogleDriveTool::from_env().map_err(actix_web::error::ErrorBadRequest)?,
+            };
+            Box::new(match config.max_length {
+                Some(max_length) => tool.with_max_length(max_length),
+                None => tool,
+            })
+        }
+        ToolType::Webhook => {
+            let webhooks = Servers::load()
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .webhooks
+                .filter(|webhooks| !webhooks.is_empty())
+                .ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest("Webhook needs 'webhooks' in servers.yaml")
+                })?;
+            Box::new(WebhookTool::new(webhooks))
+        }
+        ToolType::DateTime => {
+            let tool = DateTimeTool::new();
+            Box::new(match &config.time_zone {
+                Some(time_zone) => tool.with_time_zone(
+                    time_zone
+                        .parse()
+                        .map_err(actix_web::error::ErrorBadRequest)?,
+                ),
+                None => tool,
+            })
+        }
+        #[cfg(feature = "code")]
+        ToolType::PythonInterpreter => Box::new(PythonInterpreterTool::new()),
+        #[cfg(feature = "screenshot")]
+        ToolType::WebScreenshot => Box::new(lumo::tools::WebScreenshotTool::new()),
+        ToolType::AskUser => Box::new(ask_user.cloned().ok_or_else(|| {
+            actix_web::error::ErrorBadRequest("AskUser is only available on /stream")
+        })?),
+    })
+}
 
-    Some(tracer_provider)
+/// Start tracing from the environment, see [`lumo::telemetry::export`] for the exporters and the sampling.
+pub fn init_tracer() -> Option<SdkTracerProvider> {
+    dotenv().ok();
+    lumo::telemetry::export::init_tracer().map(|(tracer_provider, _)| tracer_provider)
 }
 
+#[utoipa::path(tag = "health", responses((status = 200, description = "The server is up")))]
 #[get("/health_check")]
 #[instrument]
 async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
 
+#[utoipa::path(
+    tag = "runs",
+    request_body = RunTaskRequest,
+    responses(
+        (status = 200, description = "The answer of the agent", body = RunTaskResponse,
+            headers(("X-History-Trimmed" = String, description = "What was removed from the history to fit its token budget, when something was"))),
+        (status = 400, description = "The Idempotency-Key is invalid"),
+        (status = 409, description = "The run of the Idempotency-Key is being queued"),
+        (status = 422, description = "A field of the request is invalid, e.g. an unknown tool or agent type, or the Idempotency-Key was used for another request", body = ValidationErrors),
+        (status = 503, description = "The job queue is full"),
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Run the request once: retries with the same key get the result of the first run, up to 255 characters"),
+    )
+)]
 #[post("/run")]
 #[instrument(
-    skip(req),
+    skip(req, http_req, jobs),
     fields(
         task = %req.task,
         model = %req.model,
@@ -219,7 +778,15 @@ async fn health_check() -> impl Responder {
     )
 )]
 
-async fn run_task(req: Json<RunTaskRequest>) -> Result<impl Responder, actix_web::Error> {
+async fn run_task(
+    req: Json<RunTaskRequest>,
+    http_req: HttpRequest,
+    caller: Caller,
+    jobs: web::Data<JobQueue>,
+) -> Result<impl Responder, actix_web::Error> {
+    let mut req = req.into_inner();
+    let idempotency_key = idempotency_key(&http_req, &caller)?;
+    caller.authorize(&mut req)?;
     let tracer = global::tracer("lumo");
     let span = tracer
         .span_builder("run_task")
@@ -234,151 +801,383 @@ async fn run_task(req: Json<RunTaskRequest>) -> Result<impl Responder, actix_web
         ])
         .start(&tracer);
     let cx = Context::current_with_span(span);
-    // use base url to get the right key from environment variables
-    let api_key = if req.base_url == "https://api.openai.com/v1/chat/completions" {
-        std::env::var("OPENAI_API_KEY").ok()
-    } else if req.base_url
-        == "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions"
-    {
-        std::env::var("GOOGLE_API_KEY").ok()
-    } else if req.base_url.to_lowercase().contains("groq") {
-        std::env::var("GROQ_API_KEY").ok()
-    } else if req.base_url.to_lowercase().contains("anthropic") {
-        std::env::var("ANTHROPIC_API_KEY").ok()
+    cx.span().set_attributes(req.run_metadata().attributes());
+    // Run through the job queue, so that bursts of requests wait for a worker instead of all running at once.
+    let (job, replayed) = match &idempotency_key {
+        Some(key) => {
+            jobs.submit_once(key, req, JobPriority::Normal, Some(cx.clone()))
+                .await?
+        }
+        None => (
+            jobs.submit(req, JobPriority::Normal, Some(cx.clone()))
+                .await?,
+            false,
+        ),
+    };
+    let job = jobs
+        .wait(&job.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if job.status == JobStatus::Failed {
+        return Err(actix_web::error::ErrorInternalServerError(
+            job.error.unwrap_or_default(),
+        ));
+    }
+    let job = job.redacted();
+    let response = job.response.unwrap_or_default();
+    cx.span()
+        .set_attribute(KeyValue::new("output.value", response.clone()));
+    cx.span().end_with_timestamp(std::time::SystemTime::now());
+
+    let mut builder = HttpResponse::Ok();
+    if replayed {
+        builder.insert_header(("Idempotent-Replayed", "true"));
+    }
+    if let Some(trimmed) = &job.history_trimmed {
+        builder.insert_header(("X-History-Trimmed", trimmed.header_value()));
+    }
+    Ok(builder.json(RunTaskResponse {
+        response,
+        transcript: job.transcript,
+        artifacts: job.artifacts,
+        summary: job.summary,
+        history_trimmed: job.history_trimmed,
+    }))
+}
+
+/// The `Idempotency-Key` header of the request, scoped to the API key of the caller so that keys chosen by different
+/// clients do not collide.
+fn idempotency_key(req: &HttpRequest, caller: &Caller) -> Result<Option<String>, actix_web::Error> {
+    let Some(key) = req.headers().get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= 255)
+        .ok_or_else(|| {
+            actix_web::error::ErrorBadRequest("The Idempotency-Key must be 1 to 255 visible ASCII characters")
+        })?;
+    Ok(Some(format!("{}:{}", caller.name().unwrap_or_default(), key)))
+}
+
+#[cfg(feature = "mcp")]
+static MCP_POOL: OnceLock<McpPool> = OnceLock::new();
+
+/// The pool of MCP clients, with the `mcp_pool` settings of servers.yaml when the server started.
+#[cfg(feature = "mcp")]
+fn mcp_pool() -> &'static McpPool {
+    MCP_POOL.get_or_init(|| {
+        let config = Servers::load()
+            .map(|servers| servers.mcp_pool.unwrap_or_default())
+            .unwrap_or_else(|e| {
+                log::error!("Failed to load the MCP pool settings, using the defaults: {:#}", e);
+                Default::default()
+            });
+        McpPool::new(config)
+    })
+}
+
+/// A client from the pool for each MCP server of the request, all of them when the request names no tools.
+#[cfg(feature = "mcp")]
+async fn mcp_clients(
+    req: &RunTaskRequest,
+    servers: &Servers,
+) -> Result<(McpLeases, Vec<(String, RunningService<RoleClient, ()>)>), actix_web::Error> {
+    let requested = servers
+        .servers
+        .iter()
+        .filter(|(name, _)| req.tools.as_ref().is_none_or(|tools| tools.contains(name)));
+    McpLeases::acquire(mcp_pool(), requested)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+/// The `agent_pool` settings of servers.yaml when the server started.
+fn agent_pool_config() -> agent_pool::AgentPoolConfig {
+    Servers::load()
+        .map(|servers| servers.agent_pool.unwrap_or_default())
+        .unwrap_or_else(|e| {
+            log::error!("Failed to load the agent pool settings, using the defaults: {:#}", e);
+            Default::default()
+        })
+}
+
+static FUNCTION_CALLING_AGENTS: OnceLock<AgentPool<FunctionCallingAgent<OpenAIServerModel>>> =
+    OnceLock::new();
+
+/// The pool of function calling agents.
+fn function_calling_agents() -> &'static AgentPool<FunctionCallingAgent<OpenAIServerModel>> {
+    FUNCTION_CALLING_AGENTS.get_or_init(|| AgentPool::new(agent_pool_config()))
+}
+
+#[cfg(feature = "code")]
+static CODE_AGENTS: OnceLock<AgentPool<CodeAgent<OpenAIServerModel>>> = OnceLock::new();
+
+/// The pool of code agents.
+#[cfg(feature = "code")]
+fn code_agents() -> &'static AgentPool<CodeAgent<OpenAIServerModel>> {
+    CODE_AGENTS.get_or_init(|| AgentPool::new(agent_pool_config()))
+}
+
+static SECRETS: OnceLock<Arc<dyn SecretProvider>> = OnceLock::new();
+
+/// The provider of the API keys of the models and tools: Vault, then the encrypted secrets file, then the environment
+/// variables. The keys are read on each request, so they can be rotated without restarting the server.
+fn secrets() -> Arc<dyn SecretProvider> {
+    SECRETS
+        .get_or_init(|| {
+            Arc::new(SecretChain::from_env().unwrap_or_else(|e| {
+                log::error!("Invalid secrets configuration, using the environment variables: {:#}", e);
+                SecretChain::new().with_provider(EnvSecrets)
+            }))
+        })
+        .clone()
+}
+
+/// The secret of the API key of the model server at `base_url`.
+fn api_key_for(base_url: &str) -> Option<SecretKey> {
+    let url = base_url.to_lowercase();
+    let name = if base_url == "https://api.openai.com/v1/chat/completions" {
+        "OPENAI_API_KEY"
+    } else if base_url == "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions" {
+        "GOOGLE_API_KEY"
+    } else if url.contains("groq") {
+        "GROQ_API_KEY"
+    } else if url.contains("anthropic") {
+        "ANTHROPIC_API_KEY"
+    } else if let Some(provider) = ProviderPreset::from_base_url(base_url) {
+        provider.api_key_env()
+    } else if url.contains(".endpoints.huggingface.cloud") || url.contains("huggingface.co") {
+        match std::env::var("HF_TOKEN") {
+            Err(_) if std::env::var("HUGGINGFACEHUB_API_TOKEN").is_ok() => "HUGGINGFACEHUB_API_TOKEN",
+            _ => "HF_TOKEN",
+        }
     } else {
-        None
+        return None;
     };
+    Some(SecretKey::new(name, secrets()))
+}
+
+/// The builder of the model at `base_url`, with its API key. The base URL may also be the name of a provider preset,
+/// e.g. `xai` or `deepseek`.
+fn model_builder(model_id: &str, base_url: &str) -> OpenAIServerModelBuilder {
+    let base_url = base_url
+        .parse::<ProviderPreset>()
+        .map_or(base_url, |provider| provider.base_url());
+    let builder = OpenAIServerModelBuilder::new(model_id).with_base_url(Some(base_url));
+    match api_key_for(base_url) {
+        Some(secret) => builder.with_secret(secret),
+        None => builder,
+    }
+}
+
+/// What a run produced.
+pub(crate) struct TaskOutput {
+    pub response: String,
+    /// The message transcript, when `include_transcript` is set.
+    pub transcript: Option<Vec<Message>>,
+    /// The figures and dataframes produced by the code of the run.
+    pub artifacts: Vec<Artifact>,
+    /// Steps, tool calls, tokens and errors of the run.
+    pub summary: RunSummary,
+    /// The memory of the agent at the end of the run, as its next step would send it to the model.
+    pub memory: Vec<Message>,
+    /// The messages removed from the history of the request to fit its token budget.
+    pub history_trimmed: Option<TrimmedHistory>,
+}
 
+/// Build the agent described by the request and run it to completion.
+pub(crate) async fn execute_task(
+    req: &RunTaskRequest,
+    cx: &Context,
+) -> Result<TaskOutput, actix_web::Error> {
     cx.span()
         .set_attribute(KeyValue::new("gen_ai.system", req.base_url.clone()));
+    record_usage(req.caller.as_deref(), &Usage::run());
 
-    let model = OpenAIServerModelBuilder::new(&req.model)
-        .with_base_url(Some(&req.base_url))
-        .with_api_key(api_key.as_deref())
+    // The API key of the base URL is read from the secrets on each model call
+    let model = model_builder(&req.model, &req.base_url)
         .build()
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let response = match req.agent_type.as_deref() {
+    let mut req = req.clone();
+    let history_trimmed =
+        history::trim_history(&mut req.history, req.history_trimming.as_ref(), &model).await?;
+    let req = &req;
+    // Only cancelled by the shutdown of the server, the runs of `/run`, `/jobs` and schedules have no stream id.
+    let cancellation = run_cancellations().register(&nanoid::nanoid!(), req.caller.as_deref());
+
+    let preset = req.preset()?;
+    let (response, transcript, artifacts, summary, memory) = match req.agent_type(preset.as_ref()) {
         #[cfg(feature = "mcp")]
         Some("mcp") => {
-            // Create fresh clients for this request
-            use rmcp::{transport::{ConfigureCommandExt, TokioChildProcess}, ServiceExt};
-            use tokio::process::Command;
-            let mut clients = Vec::new();
             let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
-
-            // Only create clients for requested tools
-            for (server_name, server_config) in servers.servers.iter() {
-                // Skip this server if its tools aren't requested
-
-                if let Some(tools) = &req.tools {
-                    if !tools.contains(&server_name.to_string()) {
-                        continue;
-                    }
-                }
-
-                let client = ().serve(TokioChildProcess::new(Command::new(&server_config.command).configure(|cmd| {
-                    cmd.args(&server_config.args);
-                })).map_err(actix_web::error::ErrorInternalServerError)?)
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-                clients.push(client);
-            }
+            let (leases, clients) = mcp_clients(req, &servers).await?;
 
             // Create and run MCP agent with filtered clients
+            let system_prompt = req.system_prompt(servers.system_prompt.as_deref(), &servers);
+            let guardrails = create_guardrails(&model)?;
             let mut agent = McpAgentBuilder::new(model)
-                .with_system_prompt(servers.system_prompt.as_deref())
+                .with_system_prompt(system_prompt.as_deref())
                 .with_max_steps(req.max_steps)
                 .with_history(req.history.clone())
-                .with_mcp_clients(clients)
+                .with_named_mcp_clients(clients)
+                .with_tool_namespacing(Some(ToolNamespacing::OnConflict))
                 .with_logging_level(Some(log::LevelFilter::Info))
+                .with_guardrails(guardrails)
+                .with_tags(req.tags.clone().unwrap_or_default())
+                .with_metadata(req.metadata.clone().unwrap_or_default())
+                .with_response_language(req.response_language.clone())
+                .with_json_answer(req.json_answer)
+                .with_time_zone(req.time_zone())
+                .with_cancellation(Some(cancellation.token()))
+                .with_long_term_memory(req.long_term_memory())
                 .build()
                 .await
                 .map_err(actix_web::error::ErrorInternalServerError)?;
 
-            agent
-                .run(&req.task, false)
-                .with_context(cx.clone())
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?
+            let response = agent.run(&req.task, false).with_context(cx.clone()).await;
+            // Give the MCP clients back to the pool, whether the run succeeded or not.
+            leases.release(agent.take_mcp_clients()).await;
+            // A failed run is counted up to where it failed.
+            record_usage(req.caller.as_deref(), &Usage::of_run(&agent.run_summary()));
+            let response = response.map_err(agent_error)?;
+            (
+                response,
+                req.include_transcript.then(|| agent.export_messages()),
+                agent.artifacts(),
+                agent.run_summary(),
+                agent.get_memory().unwrap_or_default(),
+            )
         }
 
         #[cfg(feature = "code")]
         Some("code-agent") => {
-            let tools = if let Some(tools) = &req.tools {
-                tools
-                    .iter()
-                    .map(|tool| ToolType::from_str(tool).map(|t| create_tool(&t, req.max_results)))
-                    .collect::<Result<Vec<_>, _>>()?
-            } else {
-                vec![]
-            };
-            let mut agent = CodeAgentBuilder::new(model)
-                .with_tools(tools)
-                .with_max_steps(req.max_steps)
-                .with_history(req.history.clone())
-                .with_logging_level(Some(log::LevelFilter::Info))
-                .build()
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-
-            agent
-                .run(&req.task, false)
-                .with_context(cx.clone())
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?
+            let tools = req.tools(preset.as_ref(), None)?;
+            // The default prompt of servers.yaml is written for tool calling, code agents keep their own.
+            let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
+            let key = req.agent_pool_key("code-agent", &tools, &servers, false);
+            let mut agent = code_agents().get_or_build(key, req.history.clone(), || {
+                let system_prompt = req.system_prompt(None, &servers);
+                let guardrails = create_guardrails(&model)?;
+                CodeAgentBuilder::new(model)
+                    .with_tools(tools)
+                    .with_system_prompt(system_prompt.as_deref())
+                    .with_max_steps(req.max_steps)
+                    .with_planning_interval(preset.as_ref().and_then(|preset| preset.planning_interval))
+                    .with_history(req.history.clone())
+                    .with_logging_level(Some(log::LevelFilter::Info))
+                    .with_guardrails(guardrails)
+                    .with_tags(req.tags.clone().unwrap_or_default())
+                    .with_metadata(req.metadata.clone().unwrap_or_default())
+                    .with_response_language(req.response_language.clone())
+                    .with_json_answer(req.json_answer)
+                    .with_time_zone(req.time_zone())
+                    .build()
+                    .map_err(actix_web::error::ErrorInternalServerError)
+            })?;
+            agent.set_cancellation(Some(cancellation.token()));
+            agent.set_long_term_memory(req.long_term_memory());
+
+            let response = agent.run(&req.task, false).with_context(cx.clone()).await;
+            // A failed run is counted up to where it failed.
+            record_usage(req.caller.as_deref(), &Usage::of_run(&agent.run_summary()));
+            let response = response.map_err(agent_error)?;
+            (
+                response,
+                req.include_transcript.then(|| agent.export_messages()),
+                agent.artifacts(),
+                agent.run_summary(),
+                agent.get_memory().unwrap_or_default(),
+            )
         }
         _ => {
             // Default function calling agent logic...
             let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
 
-            let tools = if let Some(tools) = &req.tools {
-                tools
-                    .iter()
-                    .map(|tool| ToolType::from_str(tool).map(|t| create_tool(&t, req.max_results)))
-                    .collect::<Result<Vec<_>, _>>()?
-            } else {
-                vec![]
-            };
-
-            let mut agent = FunctionCallingAgentBuilder::new(model)
-                .with_tools(tools)
-                .with_max_steps(req.max_steps)
-                .with_history(req.history.clone())
-                .with_system_prompt(servers.system_prompt.as_deref())
-                .with_logging_level(Some(log::LevelFilter::Info))
-                .build()
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-
-            agent
-                .run(&req.task, false)
-                .with_context(cx.clone())
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?
+            let tools = req.tools(preset.as_ref(), None)?;
+            let key = req.agent_pool_key("function-calling", &tools, &servers, false);
+            let mut agent = function_calling_agents().get_or_build(key, req.history.clone(), || {
+                let system_prompt = req.system_prompt(
+                    preset
+                        .as_ref()
+                        .and_then(|preset| preset.system_prompt)
+                        .or(servers.system_prompt.as_deref()),
+                    &servers,
+                );
+                let guardrails = create_guardrails(&model)?;
+                FunctionCallingAgentBuilder::new(model)
+                    .with_tools(tools)
+                    .with_max_steps(req.max_steps)
+                    .with_planning_interval(preset.as_ref().and_then(|preset| preset.planning_interval))
+                    .with_history(req.history.clone())
+                    .with_system_prompt(system_prompt.as_deref())
+                    .with_logging_level(Some(log::LevelFilter::Info))
+                    .with_guardrails(guardrails)
+                    .with_tags(req.tags.clone().unwrap_or_default())
+                    .with_metadata(req.metadata.clone().unwrap_or_default())
+                    .with_response_language(req.response_language.clone())
+                    .with_json_answer(req.json_answer)
+                    .with_time_zone(req.time_zone())
+                    .build()
+                    .map_err(actix_web::error::ErrorInternalServerError)
+            })?;
+            agent.set_cancellation(Some(cancellation.token()));
+            agent.set_long_term_memory(req.long_term_memory());
+
+            let response = agent.run(&req.task, false).with_context(cx.clone()).await;
+            // A failed run is counted up to where it failed.
+            record_usage(req.caller.as_deref(), &Usage::of_run(&agent.run_summary()));
+            let response = response.map_err(agent_error)?;
+            (
+                response,
+                req.include_transcript.then(|| agent.export_messages()),
+                agent.artifacts(),
+                agent.run_summary(),
+                agent.get_memory().unwrap_or_default(),
+            )
         }
     };
-    cx.span()
-        .set_attribute(KeyValue::new("output.value", response.clone()));
-    cx.span().end_with_timestamp(std::time::SystemTime::now());
-
-    Ok(Json(RunTaskResponse { response }))
-}
-
-#[derive(Serialize)]
-#[serde(tag = "type")]
-enum StreamEvent {
-    #[serde(rename = "token")]
-    Token { content: String },
-    #[serde(rename = "step")]
-    Step { step: serde_json::Value },
-    #[serde(rename = "error")]
-    Error { message: String },
-    #[serde(rename = "done")]
-    Done,
+    let mut artifacts = artifacts;
+    if req.speak_answer {
+        // The answer is still returned when it cannot be spoken.
+        match audio::AudioClient::from_config() {
+            Ok(client) => match client.speak(&response).await {
+                Ok(speech) => artifacts.push(speech),
+                Err(e) => log::warn!("Failed to speak the answer: {}", e),
+            },
+            Err(e) => log::warn!("Failed to speak the answer: {}", e),
+        }
+    }
+    Ok(TaskOutput {
+        response,
+        transcript,
+        artifacts,
+        summary,
+        memory,
+        history_trimmed,
+    })
 }
 
+#[utoipa::path(
+    tag = "runs",
+    request_body = RunTaskRequest,
+    responses(
+        (status = 200, description = "Server-sent events of the run", content_type = "text/event-stream", body = VersionedStreamEvent,
+            headers(
+                ("X-Stream-Id" = String, description = "The id to resume the stream with"),
+                ("X-History-Trimmed" = String, description = "What was removed from the history to fit its token budget, when something was"),
+            )),
+        (status = 400, description = "The checkpoint of resume_from can not be loaded"),
+        (status = 409, description = "A stream with the run id already exists"),
+        (status = 422, description = "A field of the request is invalid, e.g. an unknown tool or agent type", body = ValidationErrors),
+    )
+)]
 #[post("/stream")]
 #[instrument(
-    skip(req),
+    skip(req, http_req, streams, inputs, checkpoints, inboxes),
     fields(
         task = %req.task,
         model = %req.model,
@@ -388,7 +1187,17 @@ enum StreamEvent {
         agent_type = ?req.agent_type
     )
 )]
-async fn stream_task(req: Json<RunTaskRequest>) -> Result<HttpResponse, actix_web::Error> {
+async fn stream_task(
+    req: Json<RunTaskRequest>,
+    caller: Caller,
+    http_req: HttpRequest,
+    streams: web::Data<StreamRegistry>,
+    inputs: web::Data<PendingInputs>,
+    checkpoints: web::Data<dyn CheckpointStore>,
+    inboxes: web::Data<SessionInboxes>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut req = req.into_inner();
+    caller.authorize(&mut req)?;
     let tracer = global::tracer("lumo");
     let span = tracer
         .span_builder("stream_task")
@@ -403,281 +1212,631 @@ async fn stream_task(req: Json<RunTaskRequest>) -> Result<HttpResponse, actix_we
         ])
         .start(&tracer);
     let cx = Context::current_with_span(span);
+    cx.span().set_attributes(req.run_metadata().attributes());
 
-    // Get API key based on base URL
-    let api_key = if req.base_url == "https://api.openai.com/v1/chat/completions" {
-        std::env::var("OPENAI_API_KEY").ok()
-    } else if req.base_url
-        == "https://generativelanguage.googleapis.com/v1beta/openai/chat/completions"
-    {
-        std::env::var("GOOGLE_API_KEY").ok()
-    } else if req.base_url.to_lowercase().contains("groq") {
-        std::env::var("GROQ_API_KEY").ok()
-    } else if req.base_url.to_lowercase().contains("anthropic") {
-        std::env::var("ANTHROPIC_API_KEY").ok()
-    } else {
-        None
+    req.validate()?;
+    if req.session_id.is_some() && req.resume_from.is_some() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "resume_from can not be combined with session_id",
+        ));
+    }
+    // The stream of a session stays open for its next messages, and starts with its history.
+    let session = match &req.session_id {
+        Some(session_id) => {
+            let key = memories::store_scope(req.caller.as_deref(), session_id);
+            let session = inboxes.open(&key).ok_or_else(|| {
+                actix_web::error::ErrorConflict(format!(
+                    "Session {} already has an open stream",
+                    session_id
+                ))
+            })?;
+            let mut history = session
+                .history()
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            history.extend(req.history.take().unwrap_or_default());
+            req.history = Some(history).filter(|history| !history.is_empty());
+            Some(session)
+        }
+        None => None,
+    };
+    let preset = req.preset()?;
+    let checkpoints: Arc<dyn CheckpointStore> = Arc::new(checkpoints::CallerCheckpoints::new(
+        checkpoints.into_inner(),
+        req.caller.as_deref(),
+    ));
+    let resume = match &req.resume_from {
+        Some(id) => Some(
+            checkpoints
+                .load(id)
+                .await
+                .map_err(actix_web::error::ErrorBadRequest)?
+                .ok_or_else(|| {
+                    actix_web::error::ErrorNotFound(format!("Checkpoint {} not found", id))
+                })?,
+        ),
+        None => None,
     };
 
     cx.span()
         .set_attribute(KeyValue::new("gen_ai.system", req.base_url.clone()));
 
-    let model = OpenAIServerModelBuilder::new(&req.model)
-        .with_base_url(Some(&req.base_url))
-        .with_api_key(api_key.as_deref())
+    let model = model_builder(&req.model, &req.base_url)
         .build()
         .map_err(actix_web::error::ErrorInternalServerError)?;
+    let history_trimmed =
+        history::trim_history(&mut req.history, req.history_trimming.as_ref(), &model).await?;
 
     // Create broadcast channel for token-level streaming
     let (tx, rx) = broadcast::channel::<Status>(2000);
     let task_str = req.task.clone();
 
+    // The run id doubles as the stream id, so a client can resume the stream with GET /stream/{id}
+    // and answer the questions of the agent with POST /runs/{id}/input
+    let stream_id = http_req
+        .extensions()
+        .get::<request_log::RunId>()
+        .map(|run_id| run_id.0.clone())
+        .unwrap_or_else(|| nanoid::nanoid!());
+    let ask_user = AskUserTool::new(StreamUserInput::new(
+        &stream_id,
+        req.caller.as_deref(),
+        inputs.get_ref().clone(),
+        tx.clone(),
+    ))
+        .with_timeout(Some(inputs.timeout()));
+
     // Create SSE stream - construct the entire stream inside async_stream to own the agent
-    let sse_stream = match req.agent_type.as_deref() {
+    let sse_stream = match req.agent_type(preset.as_ref()) {
         #[cfg(feature = "mcp")]
         Some("mcp") => {
-            use lumo::agent::McpAgentBuilder;
-
-            use rmcp::{transport::{ConfigureCommandExt, TokioChildProcess}, ServiceExt};
-            use tokio::process::Command;
-            // Create fresh clients for this request
-            let mut clients = Vec::new();
             let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
-
-            // Only create clients for requested tools
-            for (server_name, server_config) in servers.servers.iter() {
-                // Skip this server if its tools aren't requested
-
-                if let Some(tools) = &req.tools {
-                    if !tools.contains(&server_name.to_string()) {
-                        continue;
-                    }
-                }
-
-                let client = ().serve(TokioChildProcess::new(Command::new(&server_config.command).configure(|cmd| {
-                    cmd.args(&server_config.args);
-                })).map_err(actix_web::error::ErrorInternalServerError)?)
-                .await
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-
-                clients.push(client);
-            }
+            let (leases, clients) = mcp_clients(&req, &servers).await?;
 
             // Create and run MCP agent with filtered clients
+            let system_prompt = req.system_prompt(servers.system_prompt.as_deref(), &servers);
+            let guardrails = create_guardrails(&model)?;
             let agent = McpAgentBuilder::new(model)
-                .with_system_prompt(servers.system_prompt.as_deref())
+                .with_system_prompt(system_prompt.as_deref())
                 .with_max_steps(req.max_steps)
                 .with_history(req.history.clone())
-                .with_mcp_clients(clients)
+                .with_named_mcp_clients(clients)
+                .with_tool_namespacing(Some(ToolNamespacing::OnConflict))
                 .with_logging_level(Some(log::LevelFilter::Info))
+                .with_guardrails(guardrails)
+                .with_tags(req.tags.clone().unwrap_or_default())
+                .with_metadata(req.metadata.clone().unwrap_or_default())
+                .with_response_language(req.response_language.clone())
+                .with_json_answer(req.json_answer)
+                .with_time_zone(req.time_zone())
+                .with_checkpoint_store(checkpoints.clone())
+                .with_long_term_memory(req.long_term_memory())
                 .build()
                 .await
                 .map_err(actix_web::error::ErrorInternalServerError)?;
 
-            create_agent_stream(agent, task_str, tx, rx, cx)
+            let batching = servers.token_batching.unwrap_or_default();
+            create_agent_stream(
+                AgentLease::unpooled(agent),
+                task_str,
+                resume,
+                session,
+                &stream_id,
+                tx,
+                rx,
+                cx,
+                batching,
+                req.json_answer,
+                req.caller.clone(),
+                Some(Box::new(move |agent: &mut McpAgent<_>| {
+                    Box::pin(leases.release(agent.take_mcp_clients()))
+                })),
+            )
         }
 
         #[cfg(feature = "code")]
         Some("code-agent") => {
-            let tools = if let Some(tools) = &req.tools {
-                tools
-                    .iter()
-                    .map(|tool| ToolType::from_str(tool).map(|t| create_tool(&t, req.max_results)))
-                    .collect::<Result<Vec<_>, _>>()?
-            } else {
-                vec![]
-            };
-            let agent = CodeAgentBuilder::new(model)
-                .with_tools(tools)
-                .with_max_steps(req.max_steps)
-                .with_history(req.history.clone())
-                .with_logging_level(Some(log::LevelFilter::Info))
-                .build()
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-
-            create_agent_stream(agent, task_str, tx, rx, cx)
+            let tools = req.tools(preset.as_ref(), Some(&ask_user))?;
+            // The default prompt of servers.yaml is written for tool calling, code agents keep their own.
+            let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
+            let key = req.agent_pool_key("code-agent", &tools, &servers, true);
+            let mut agent = code_agents().get_or_build(key, req.history.clone(), || {
+                let system_prompt = req.system_prompt(None, &servers);
+                let guardrails = create_guardrails(&model)?;
+                CodeAgentBuilder::new(model)
+                    .with_tools(tools)
+                    .with_system_prompt(system_prompt.as_deref())
+                    .with_max_steps(req.max_steps)
+                    .with_planning_interval(preset.as_ref().and_then(|preset| preset.planning_interval))
+                    .with_history(req.history.clone())
+                    .with_logging_level(Some(log::LevelFilter::Info))
+                    .with_guardrails(guardrails)
+                    .with_tags(req.tags.clone().unwrap_or_default())
+                    .with_metadata(req.metadata.clone().unwrap_or_default())
+                    .with_response_language(req.response_language.clone())
+                    .with_json_answer(req.json_answer)
+                    .with_time_zone(req.time_zone())
+                    .with_checkpoint_store(checkpoints.clone())
+                    .build()
+                    .map_err(actix_web::error::ErrorInternalServerError)
+            })?;
+
+            agent.set_long_term_memory(req.long_term_memory());
+
+            let batching = servers.token_batching.unwrap_or_default();
+            create_agent_stream(agent, task_str, resume, session, &stream_id, tx, rx, cx, batching, req.json_answer, req.caller.clone(), None)
         }
         _ => {
             // Default function calling agent logic
             let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
 
-            let tools = if let Some(tools) = &req.tools {
-                tools
-                    .iter()
-                    .map(|tool| ToolType::from_str(tool).map(|t| create_tool(&t, req.max_results)))
-                    .collect::<Result<Vec<_>, _>>()?
-            } else {
-                vec![]
-            };
-
-            let agent = FunctionCallingAgentBuilder::new(model)
-                .with_tools(tools)
-                .with_max_steps(req.max_steps)
-                .with_history(req.history.clone())
-                .with_system_prompt(servers.system_prompt.as_deref())
-                .with_logging_level(Some(log::LevelFilter::Info))
-                .build()
-                .map_err(actix_web::error::ErrorInternalServerError)?;
-
-            create_agent_stream(agent, task_str, tx, rx, cx)
+            let tools = req.tools(preset.as_ref(), Some(&ask_user))?;
+            let key = req.agent_pool_key("function-calling", &tools, &servers, true);
+            let mut agent = function_calling_agents().get_or_build(key, req.history.clone(), || {
+                let system_prompt = req.system_prompt(
+                    preset
+                        .as_ref()
+                        .and_then(|preset| preset.system_prompt)
+                        .or(servers.system_prompt.as_deref()),
+                    &servers,
+                );
+                let guardrails = create_guardrails(&model)?;
+                FunctionCallingAgentBuilder::new(model)
+                    .with_tools(tools)
+                    .with_max_steps(req.max_steps)
+                    .with_planning_interval(preset.as_ref().and_then(|preset| preset.planning_interval))
+                    .with_history(req.history.clone())
+                    .with_system_prompt(system_prompt.as_deref())
+                    .with_logging_level(Some(log::LevelFilter::Info))
+                    .with_guardrails(guardrails)
+                    .with_tags(req.tags.clone().unwrap_or_default())
+                    .with_metadata(req.metadata.clone().unwrap_or_default())
+                    .with_response_language(req.response_language.clone())
+                    .with_json_answer(req.json_answer)
+                    .with_time_zone(req.time_zone())
+                    .with_checkpoint_store(checkpoints.clone())
+                    .build()
+                    .map_err(actix_web::error::ErrorInternalServerError)
+            })?;
+
+            agent.set_long_term_memory(req.long_term_memory());
+
+            let batching = servers.token_batching.unwrap_or_default();
+            create_agent_stream(agent, task_str, resume, session, &stream_id, tx, rx, cx, batching, req.json_answer, req.caller.clone(), None)
         }
     };
 
-    Ok(HttpResponse::Ok()
+    let events = sse_stream.map(events::to_data);
+    let log = streams
+        .start(&stream_id, req.caller.as_deref(), Box::pin(events))
+        .ok_or_else(|| actix_web::error::ErrorConflict(format!("Stream {} already exists", stream_id)))?;
+
+    let mut response = sse_response(&stream_id);
+    if let Some(trimmed) = &history_trimmed {
+        response.insert_header(("X-History-Trimmed", trimmed.header_value()));
+    }
+    Ok(response.streaming(streams.subscribe(log, sse::last_event_id(&http_req))))
+}
+
+/// Resume a stream, replaying the events after the `Last-Event-ID` header. With auth, only the key that started the
+/// stream can resume it.
+#[utoipa::path(
+    tag = "runs",
+    params(
+        ("id" = String, Path, description = "The stream id"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "The id of the last event received"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events of the run", content_type = "text/event-stream", body = VersionedStreamEvent),
+        (status = 404, description = "The stream does not exist or has expired"),
+    )
+)]
+#[get("/stream/{id}")]
+async fn resume_stream(
+    id: web::Path<String>,
+    http_req: HttpRequest,
+    caller: Caller,
+    streams: web::Data<StreamRegistry>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let log = streams
+        .get(&id)
+        .filter(|log| caller.owns(log.owner()))
+        .ok_or_else(|| actix_web::error::ErrorNotFound(format!("Stream {} not found", id)))?;
+    Ok(sse_response(&id).streaming(streams.subscribe(log, sse::last_event_id(&http_req))))
+}
+
+fn sse_response(stream_id: &str) -> actix_web::HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response
         .content_type("text/event-stream")
         .insert_header(("Cache-Control", "no-cache"))
         .insert_header(("Connection", "keep-alive"))
         .insert_header(("X-Accel-Buffering", "no"))
-        .streaming(sse_stream))
+        .insert_header(("X-Stream-Id", stream_id.to_string()));
+    response
 }
 
+/// Called with the agent when its stream ends, e.g. to give its MCP clients back to the pool.
+type StreamFinish<A> = Box<dyn FnOnce(&mut A) -> futures::future::BoxFuture<'static, ()>>;
+
+/// The events of the run of the task, or of the resumed run of the checkpoint. The checkpoints of a new run are
+/// saved under the stream id. The stream of a session continues with a run for each of its next messages. The tokens
+/// are grouped into events as set by `batching`, and `finish` is called when the stream ends. With `json_answer`, the
+/// tokens of each model output are also parsed as JSON, and sent as `partial_answer` events while they parse. Each
+/// run is counted in the usage of the `caller` key. A pooled agent goes back to its pool when the stream ends.
+#[allow(clippy::too_many_arguments)]
 fn create_agent_stream<A>(
-    mut agent: A,
+    mut agent: AgentLease<A>,
     task: String,
+    resume: Option<Checkpoint>,
+    mut session: Option<SessionStream>,
+    stream_id: &str,
     tx: broadcast::Sender<Status>,
     mut rx: broadcast::Receiver<Status>,
     cx: Context,
-) -> Pin<Box<dyn futures::Stream<Item = Result<Bytes, std::io::Error>>>>
+    batching: TokenBatching,
+    json_answer: bool,
+    caller: Option<String>,
+    finish: Option<StreamFinish<A>>,
+) -> Pin<Box<dyn futures::Stream<Item = StreamEvent>>>
 where
     A: AgentStream + 'static,
 {
+    // A resumed run keeps saving its checkpoints under the id of the checkpoint.
+    let checkpoint_id = resume
+        .as_ref()
+        .map_or_else(|| stream_id.to_string(), |checkpoint| checkpoint.id.clone());
+    agent.set_checkpoint_id(Some(checkpoint_id.clone()));
+    let finished_answer = resume
+        .as_ref()
+        .and_then(Checkpoint::final_answer)
+        .map(str::to_string);
+    let run_id = stream_id.to_string();
     Box::pin(
     async_stream::stream! {
-        // Get the stream from the agent
-        let stream = match agent.stream_run(&task, false, Some(tx)) {
-            Ok(s) => s,
-            Err(e) => {
-                let event = StreamEvent::Error { 
-                    message: e.to_string() 
-                };
-                if let Ok(json) = serde_json::to_string(&event) {
-                    yield Ok(Bytes::from(format!("data: {}\n\n", json)));
+        // The model does not run for a finished run, its answer is sent as the tokens would have been.
+        if let Some(content) = finished_answer {
+            if json_answer {
+                if let Some(event) = StreamEvent::partial_answer(PartialJson::new().complete(&content)) {
+                    yield event;
                 }
-                return;
             }
-        };
-
-        // Pin the stream for iteration
-        tokio::pin!(stream);
-
-        // Use select to poll both the step stream and token receiver simultaneously
+            yield StreamEvent::Token { content };
+        }
+        let mut task = task;
+        let mut resume = resume;
         loop {
-            tokio::select! {
-                // Poll for tokens continuously
-                status = rx.recv() => {
-                    match status {
-                        Ok(Status::FirstContent(content)) | Ok(Status::Content(content)) => {
-                            let event = StreamEvent::Token { content };
-                            if let Ok(json) = serde_json::to_string(&event) {
-                                yield Ok(Bytes::from(format!("data: {}\n\n", json)));
+            if let Some(session) = &session {
+                session.start_turn(&run_id).await;
+            }
+            record_usage(caller.as_deref(), &Usage::run());
+            let cancellation = run_cancellations().register(&run_id, caller.as_deref());
+            agent.set_cancellation(Some(cancellation.token()));
+            let mut answer = None;
+            let mut error = None;
+            let mut batcher = TokenBatcher::new(batching);
+            // The output of the current model call, parsed as JSON.
+            let mut partial = json_answer.then(PartialJson::new);
+
+            // Get the stream from the agent. The next turns of a session continue its conversation.
+            match match resume.take() {
+                Some(checkpoint) => agent.stream_resume(checkpoint, Some(tx.clone())),
+                None => agent.stream_run(&task, false, Some(tx.clone())),
+            } {
+                Err(e) => {
+                    yield StreamEvent::Error { 
+                        message: e.to_string() 
+                    };
+                    if session.is_none() {
+                        break;
+                    }
+                    error = Some(e.to_string());
+                }
+                Ok(mut stream) => {
+                // Use select to poll both the step stream and token receiver simultaneously
+                loop {
+                    let deadline = batcher.deadline();
+                    tokio::select! {
+                        // Poll for tokens continuously
+                        status = rx.recv() => {
+                            // The pending tokens are sent before any other event
+                            if !matches!(status, Ok(Status::FirstContent(_) | Status::Content(_) | Status::Reasoning(_))) {
+                                if let Some(chunk) = batcher.flush() {
+                                    yield chunk.into();
+                                }
                             }
-                        }
-                        Ok(Status::ToolCallStart(tool_name)) => {
-                            let event = StreamEvent::Token { 
-                                content: format!("[Using tool: {}]", tool_name) 
-                            };
-                            if let Ok(json) = serde_json::to_string(&event) {
-                                yield Ok(Bytes::from(format!("data: {}\n\n", json)));
+                            match status {
+                                Ok(Status::FirstContent(content)) | Ok(Status::Content(content)) => {
+                                    let partial_answer = partial
+                                        .as_mut()
+                                        .and_then(|partial| StreamEvent::partial_answer(partial.push(&content)));
+                                    for chunk in batcher.push(ContentChunk::Answer(content)) {
+                                        yield chunk.into();
+                                    }
+                                    if let Some(event) = partial_answer {
+                                        yield event;
+                                    }
+                                }
+                                Ok(Status::Reasoning(content)) => {
+                                    for chunk in batcher.push(ContentChunk::Reasoning(content)) {
+                                        yield chunk.into();
+                                    }
+                                }
+                                Ok(Status::ToolCallStart(tool_name)) => {
+                                    yield StreamEvent::Token { 
+                                        content: format!("[Using tool: {}]", tool_name) 
+                                    };
+                                }
+                                Ok(status @ (Status::ToolCallStarted { .. } | Status::ToolCallResult { .. } | Status::ToolCallFailed { .. })) => {
+                                    if let Some(event) = StreamEvent::tool_call(status) {
+                                        yield event;
+                                    }
+                                }
+                                Ok(Status::CodeExecutionStart(code)) => {
+                                    yield StreamEvent::CodeExecutionStart { code };
+                                }
+                                Ok(Status::CodeExecutionEnd(output)) => {
+                                    yield StreamEvent::CodeExecutionEnd { output };
+                                }
+                                Ok(Status::ClarificationRequired(question)) => {
+                                    yield StreamEvent::ClarificationRequired { question };
+                                }
+                                Ok(Status::ManagedAgent { agent, status }) => {
+                                    if let Some(event) = managed_agent_event(agent, *status) {
+                                        yield event;
+                                    }
+                                }
+                                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                    // Log that we skipped some messages but continue
+                                    log::warn!("Skipped {} messages due to lag", skipped);
+                                }
+                                Err(broadcast::error::RecvError::Closed) => {
+                                    // Channel closed, break to drain steps
+                                    break;
+                                }
+                                _ => {}
                             }
                         }
-                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
-                            // Log that we skipped some messages but continue
-                            log::warn!("Skipped {} messages due to lag", skipped);
+                        // Send the pending tokens when no token comes before they are due
+                        _ = tokio::time::sleep_until(deadline.unwrap_or_else(std::time::Instant::now).into()), if deadline.is_some() => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
+                            }
                         }
-                        Err(broadcast::error::RecvError::Closed) => {
-                            // Channel closed, break to drain steps
-                            break;
+                        // Poll for steps
+                        step_result = stream.next() => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
+                            }
+                            match step_result {
+                                Some(Ok(step)) => {
+                                    // Send the step event
+                                    match step {
+                                         Step::ActionStep(agent_step) => {
+                                             if agent_step.final_answer.is_some() {
+                                                 answer = agent_step.final_answer.clone();
+                                             }
+                                             // The tokens of the answer may come after its step, the patches end
+                                             // with the whole answer. The next model call starts a new answer.
+                                             if let Some(partial) = &mut partial {
+                                                 match &agent_step.final_answer {
+                                                     Some(final_answer) => {
+                                                         if let Some(event) = StreamEvent::partial_answer(partial.complete(final_answer)) {
+                                                             yield event;
+                                                         }
+                                                     }
+                                                     None => *partial = PartialJson::new(),
+                                                 }
+                                             }
+                                             if let Some(step) = StepPayload::from_step(&agent_step) {
+                                                 yield StreamEvent::Step {
+                                                     step: step.with_checkpoint_id(checkpoint_id.clone()),
+                                                 };
+                                             }
+                                         }  
+                                        _ => {}
+                                    }
+                                }
+                                Some(Err(e)) => {
+                                    error = Some(e.to_string());
+                                    if matches!(e.downcast_ref::<AgentError>(), Some(AgentError::Cancelled(_))) {
+                                        yield StreamEvent::Cancelled;
+                                    } else {
+                                        yield StreamEvent::Error {
+                                            message: e.to_string()
+                                        };
+                                    }
+                                    break;
+                                }
+                                None => {
+                                    // Step stream ended
+                                    break;
+                                }
+                            }
                         }
-                        _ => {}
                     }
                 }
-                // Poll for steps
-                step_result = stream.next() => {
-                    match step_result {
-                        Some(Ok(step)) => {
-                            // Send the step event
-                            match step {
-                                 Step::ActionStep(agent_step) => {
-                                     if let Some(tool_calls) = &agent_step.tool_call {
-                                         let step_data = serde_json::json!({
-                                             "step": agent_step.step,
-                                             "tool_calls": tool_calls.iter().map(|tc| {
-                                                 serde_json::json!({
-                                                     "name": &tc.function.name,
-                                                     "arguments": &tc.function.arguments
-                                                 })
-                                             }).collect::<Vec<_>>()
-                                         });
-                                         let event = StreamEvent::Step { step: step_data };
-                                         if let Ok(json) = serde_json::to_string(&event) {
-                                             yield Ok(Bytes::from(format!("data: {}\n\n", json)));
-                                         }
-                                     }
-                                 }  
-                                _ => {}
+
+                // Drain any remaining tokens after steps complete
+                while let Ok(status) = rx.try_recv() {
+                    match status {
+                        Status::FirstContent(content) | Status::Content(content) => {
+                            for chunk in batcher.push(ContentChunk::Answer(content)) {
+                                yield chunk.into();
+                            }
+                        }
+                        Status::Reasoning(content) => {
+                            for chunk in batcher.push(ContentChunk::Reasoning(content)) {
+                                yield chunk.into();
+                            }
+                        }
+                        Status::CodeExecutionStart(code) => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
                             }
+                            yield StreamEvent::CodeExecutionStart { code };
                         }
-                        Some(Err(e)) => {
-                            let event = StreamEvent::Error { 
-                                message: e.to_string() 
-                            };
-                            if let Ok(json) = serde_json::to_string(&event) {
-                                yield Ok(Bytes::from(format!("data: {}\n\n", json)));
+                        Status::CodeExecutionEnd(output) => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
                             }
-                            break;
+                            yield StreamEvent::CodeExecutionEnd { output };
                         }
-                        None => {
-                            // Step stream ended
-                            break;
+                        status @ (Status::ToolCallStarted { .. } | Status::ToolCallResult { .. } | Status::ToolCallFailed { .. }) => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
+                            }
+                            if let Some(event) = StreamEvent::tool_call(status) {
+                                yield event;
+                            }
+                        }
+                        Status::ManagedAgent { agent, status } => {
+                            if let Some(chunk) = batcher.flush() {
+                                yield chunk.into();
+                            }
+                            if let Some(event) = managed_agent_event(agent, *status) {
+                                yield event;
+                            }
                         }
+                        _ => {}
                     }
                 }
-            }
-        }
+                if let Some(chunk) = batcher.flush() {
+                    yield chunk.into();
+                }
 
-        // Drain any remaining tokens after steps complete
-        while let Ok(status) = rx.try_recv() {
-            match status {
-                Status::FirstContent(content) | Status::Content(content) => {
-                    let event = StreamEvent::Token { content };
-                    if let Ok(json) = serde_json::to_string(&event) {
-                        yield Ok(Bytes::from(format!("data: {}\n\n", json)));
-                    }
+                // The stream borrows the agent until it is dropped
+                drop(stream);
                 }
-                _ => {}
             }
-        }
+            let summary = agent.run_summary();
+            record_usage(caller.as_deref(), &Usage::of_run(&summary));
+            yield StreamEvent::Summary { summary: summary.clone() };
+
+            // Send done event
+            yield StreamEvent::Done;
 
-        // Send done event
-        let event = StreamEvent::Done;
-        if let Ok(json) = serde_json::to_string(&event) {
-            yield Ok(Bytes::from(format!("data: {}\n\n", json)));
+            // The stream of a session waits for the next message.
+            let Some(session) = &mut session else {
+                break;
+            };
+            let result = match (error, answer) {
+                (None, Some(response)) => Ok(TaskOutput {
+                    response,
+                    transcript: None,
+                    artifacts: Vec::new(),
+                    summary,
+                    memory: agent.get_memory().unwrap_or_default(),
+                    history_trimmed: None,
+                }),
+                (Some(error), _) => Err(error),
+                (None, None) => Err("The run ended without an answer".to_string()),
+            };
+            session
+                .finish_turn(&run_id, &task, result.as_ref().map_err(Clone::clone))
+                .await;
+            match session.next_message().await {
+                Some(message) => task = message,
+                None => break,
+            }
         }
 
+        if let Some(finish) = finish {
+            finish(&mut *agent).await;
+        }
+        // The MCP servers of the agent are stopped before the stream ends rather than whenever it is dropped.
+        if let Err(e) = agent.shutdown().await {
+            log::warn!("Failed to shut down the agent: {}", e);
+        }
         cx.span().end_with_timestamp(std::time::SystemTime::now());
     })
 }
 
 pub fn run(listener: TcpListener) -> std::io::Result<Server> {
+    let secrets = SecretChain::from_env().map_err(std::io::Error::other)?;
+    let _ = SECRETS.set(Arc::new(secrets));
+    usage::init_usage_ledger().map_err(std::io::Error::other)?;
+    let keys = Arc::new(auth::KeyStore::from_config().map_err(std::io::Error::other)?);
+    let sessions = sessions::from_env().map_err(std::io::Error::other)?;
+    let scheduler = Scheduler::load()
+        .map_err(std::io::Error::other)?
+        .with_sessions(sessions.clone())
+        .with_keys(keys.clone());
+    scheduler.start();
+    cancellation::cancel_runs_on_shutdown();
+    let scheduler = web::Data::new(scheduler);
+    let request_logger = request_log::RequestLogger::from_env();
+    let streams = web::Data::new(StreamRegistry::from_env());
+    let checkpoints = web::Data::from(checkpoints::from_env().map_err(std::io::Error::other)?);
+    let inputs = web::Data::new(PendingInputs::from_env());
+    let jobs = JobQueue::from_env()
+        .map_err(std::io::Error::other)?
+        .with_sessions(sessions.clone());
+    jobs.start();
+    #[cfg(feature = "mcp")]
+    mcp_pool().start();
+    function_calling_agents().start();
+    #[cfg(feature = "code")]
+    code_agents().start();
+    let inboxes = web::Data::new(SessionInboxes::from_env(sessions.clone()));
+    let sessions = web::Data::from(sessions);
+    let jobs = web::Data::new(jobs);
     Ok(HttpServer::new(move || {
         println!("Config File Path: {:?}", Servers::config_path().unwrap());
         let _ = Servers::load().map_err(actix_web::error::ErrorInternalServerError);
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "DELETE"])
             .allowed_headers(vec![
                 header::AUTHORIZATION,
                 header::ACCEPT,
                 header::CONTENT_TYPE,
+                header::HeaderName::from_static(request_log::RUN_ID_HEADER),
+                header::HeaderName::from_static("last-event-id"),
             ])
+            .expose_headers(vec![header::HeaderName::from_static("x-stream-id")])
             .max_age(3600);
 
         App::new()
             .wrap(cors)
-            .wrap(auth::ApiKeyAuth)
+            .wrap(auth::ApiKeyAuth::new(keys.clone()))
+            .wrap(request_logger.clone())
             .service(health_check)
+            .app_data(jobs.clone())
             .service(run_task)
+            .service(jobs::submit_job)
+            .service(jobs::get_job)
+            .service(artifacts::get_artifact)
+            .app_data(streams.clone())
+            .app_data(checkpoints.clone())
             .service(stream_task)
+            .service(resume_stream)
+            .app_data(inputs.clone())
+            .service(clarification::submit_input)
+            .service(cancellation::cancel_run)
+            .service(summarize::summarize_conversation)
+            .service(pipeline::run_pipeline)
+            .service(audio::transcribe_audio)
+            .service(export::export_runs)
+            .app_data(sessions.clone())
+            .service(sessions::get_session)
+            .service(sessions::get_session_memory)
+            .service(sessions::delete_session)
+            .app_data(inboxes.clone())
+            .service(sessions::post_message)
+            .app_data(web::Data::from(keys.clone()))
+            .service(auth::list_keys)
+            .service(usage::get_usage)
+            .service(memories::list_memories)
+            .service(memories::delete_memory)
+            .service(memories::clear_memories)
+            .app_data(scheduler.clone())
+            .service(scheduler::create_schedule)
+            .service(scheduler::list_schedules)
+            .service(scheduler::get_schedule)
+            .service(scheduler::delete_schedule)
+            .service(scheduler::list_schedule_runs)
+            .service(openapi::swagger_ui())
     })
     .listen(listener)?
     .run())
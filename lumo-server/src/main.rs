@@ -2,18 +2,23 @@ use std::net::TcpListener;
 
 use lumo_server::{init_tracer, run};
 use tracing_opentelemetry;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
 #[actix_web::main]
 #[tracing::instrument]
 async fn main() -> std::io::Result<()> {
-    if let Some(_) = init_tracer() {
-        tracing_subscriber::registry()
-            .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
-            .with(fmt::layer())
-            .with(tracing_opentelemetry::layer())
-            .init();
-    }
+    let otel_layer = init_tracer().map(|_| tracing_opentelemetry::layer());
+    // Set LOG_FORMAT=json to write the logs, including the request logs, as JSON lines.
+    let fmt_layer = if std::env::var("LOG_FORMAT").is_ok_and(|v| v == "json") {
+        fmt::layer().json().boxed()
+    } else {
+        fmt::layer().boxed()
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
 
     let listener = TcpListener::bind("0.0.0.0:8080")?;
     println!("Listening on 0.0.0.0:8080");
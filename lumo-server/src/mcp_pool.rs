@@ -0,0 +1,462 @@
+//! Pool of MCP clients shared by the runs, so the MCP servers are not started again for each request.
+//!
+//! A client serves one run at a time. When the run ends, it goes back to the pool and the next run that needs its
+//! server takes it, once a ping shows it still answers. Clients idle for longer than the idle timeout are stopped,
+//! as are the clients of a server whose configuration changed in servers.yaml.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use lumo::agent::McpClient;
+use rmcp::{
+    model::{ClientRequest, PingRequest},
+    service::RunningService,
+    RoleClient,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::ServerConfig;
+
+/// Seconds an idle client is kept, unless `idle_timeout` is set.
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 300;
+
+/// Seconds a run waits for a free client, unless `acquire_timeout` is set.
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+
+/// Seconds an idle client has to answer the ping, unless `health_check_timeout` is set.
+const DEFAULT_HEALTH_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/// How often the idle clients are checked for expiry.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The `mcp_pool` section of servers.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct McpPoolConfig {
+    /// Seconds an idle client is kept for the next runs. With `0`, the clients are stopped at the end of each run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_timeout: Option<u64>,
+    /// Maximum number of clients of each server, busy or idle. The runs wait for a free client beyond it. Unlimited
+    /// when missing. The `max_clients` of a server takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clients: Option<usize>,
+    /// Seconds a run waits for a free client before failing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acquire_timeout: Option<u64>,
+    /// Seconds an idle client has to answer the ping of the health check before it is replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check_timeout: Option<u64>,
+}
+
+struct IdleClient {
+    service: RunningService<RoleClient, ()>,
+    since: Instant,
+}
+
+/// The clients of a server.
+struct ServerPool {
+    /// The configuration the clients were started with.
+    fingerprint: String,
+    idle: Vec<IdleClient>,
+    /// One permit per client in use, when the number of clients is limited.
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl ServerPool {
+    fn new(fingerprint: &str, max_clients: Option<usize>) -> Self {
+        Self {
+            fingerprint: fingerprint.to_string(),
+            idle: Vec::new(),
+            slots: max_clients.map(|max_clients| Arc::new(Semaphore::new(max_clients))),
+        }
+    }
+}
+
+/// The MCP clients of the server, by MCP server name.
+#[derive(Clone)]
+pub struct McpPool {
+    config: Arc<McpPoolConfig>,
+    servers: Arc<Mutex<HashMap<String, ServerPool>>>,
+}
+
+/// A client in use by a run. The client counts against the `max_clients` of its server until the lease is dropped.
+pub struct McpLease {
+    name: String,
+    fingerprint: String,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl McpPool {
+    pub fn new(config: McpPoolConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            servers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.config
+                .idle_timeout
+                .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS),
+        )
+    }
+
+    /// Stop the clients idle for longer than the idle timeout, in the background.
+    pub fn start(&self) {
+        let pool = self.clone();
+        actix_web::rt::spawn(async move {
+            loop {
+                actix_web::rt::time::sleep(SWEEP_INTERVAL).await;
+                for service in pool.take_expired() {
+                    stop(service).await;
+                }
+            }
+        });
+    }
+
+    fn take_expired(&self) -> Vec<RunningService<RoleClient, ()>> {
+        let idle_timeout = self.idle_timeout();
+        let mut expired = Vec::new();
+        let mut servers = self.servers.lock().unwrap();
+        for pool in servers.values_mut() {
+            let (stale, fresh) = std::mem::take(&mut pool.idle)
+                .into_iter()
+                .partition(|client| client.since.elapsed() >= idle_timeout);
+            pool.idle = fresh;
+            expired.extend(stale.into_iter().map(|client: IdleClient| client.service));
+        }
+        expired
+    }
+
+    /// A client of the MCP server `name`: an idle one that answers the ping, or a new one.
+    pub async fn acquire(
+        &self,
+        name: &str,
+        config: &ServerConfig,
+    ) -> Result<(RunningService<RoleClient, ()>, McpLease)> {
+        let fingerprint = serde_json::to_string(config)?;
+        let max_clients = config.max_clients.or(self.config.max_clients);
+        self.acquire_with(name, &fingerprint, max_clients, || config.connect(name))
+            .await
+    }
+
+    async fn acquire_with<F, Fut>(
+        &self,
+        name: &str,
+        fingerprint: &str,
+        max_clients: Option<usize>,
+        connect: F,
+    ) -> Result<(RunningService<RoleClient, ()>, McpLease)>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<RunningService<RoleClient, ()>>>,
+    {
+        let slots = {
+            let mut servers = self.servers.lock().unwrap();
+            let pool = servers
+                .entry(name.to_string())
+                .or_insert_with(|| ServerPool::new(fingerprint, max_clients));
+            if pool.fingerprint != fingerprint {
+                // The clients of the previous configuration are stopped as they come back.
+                let stale = std::mem::replace(pool, ServerPool::new(fingerprint, max_clients));
+                stop_in_background(stale.idle.into_iter().map(|client| client.service));
+            }
+            pool.slots.clone()
+        };
+        let permit = match slots {
+            Some(slots) => {
+                let timeout = Duration::from_secs(
+                    self.config
+                        .acquire_timeout
+                        .unwrap_or(DEFAULT_ACQUIRE_TIMEOUT_SECS),
+                );
+                let permit = tokio::time::timeout(timeout, slots.acquire_owned())
+                    .await
+                    .map_err(|_| {
+                        anyhow!(
+                            "MCP server '{}' had no free client within {:?}",
+                            name,
+                            timeout
+                        )
+                    })?;
+                Some(permit?)
+            }
+            None => None,
+        };
+        let lease = McpLease {
+            name: name.to_string(),
+            fingerprint: fingerprint.to_string(),
+            _permit: permit,
+        };
+
+        while let Some(service) = self.take_idle(&lease) {
+            if self.is_healthy(&service).await {
+                return Ok((service, lease));
+            }
+            log::info!(
+                "Replacing the MCP client of '{}', which did not answer the ping",
+                name
+            );
+            stop_in_background([service]);
+        }
+        Ok((connect().await?, lease))
+    }
+
+    /// The most recently used idle client of the server of the lease that has not expired.
+    fn take_idle(&self, lease: &McpLease) -> Option<RunningService<RoleClient, ()>> {
+        let idle_timeout = self.idle_timeout();
+        let mut servers = self.servers.lock().unwrap();
+        let pool = servers
+            .get_mut(&lease.name)
+            .filter(|pool| pool.fingerprint == lease.fingerprint)?;
+        while let Some(client) = pool.idle.pop() {
+            if client.since.elapsed() < idle_timeout {
+                return Some(client.service);
+            }
+            stop_in_background([client.service]);
+        }
+        None
+    }
+
+    async fn is_healthy(&self, service: &RunningService<RoleClient, ()>) -> bool {
+        if service.is_transport_closed() {
+            return false;
+        }
+        let timeout = Duration::from_secs(
+            self.config
+                .health_check_timeout
+                .unwrap_or(DEFAULT_HEALTH_CHECK_TIMEOUT_SECS),
+        );
+        let ping = service.send_request(ClientRequest::PingRequest(PingRequest::default()));
+        matches!(tokio::time::timeout(timeout, ping).await, Ok(Ok(_)))
+    }
+
+    /// Give a client back to the pool for the next runs. It is stopped when the pool keeps no idle clients, when its
+    /// connection is closed or when the configuration of its server changed.
+    pub async fn release(&self, lease: McpLease, service: RunningService<RoleClient, ()>) {
+        let rejected = if self.idle_timeout().is_zero() || service.is_transport_closed() {
+            Some(service)
+        } else {
+            let mut servers = self.servers.lock().unwrap();
+            match servers
+                .get_mut(&lease.name)
+                .filter(|pool| pool.fingerprint == lease.fingerprint)
+            {
+                Some(pool) => {
+                    pool.idle.push(IdleClient {
+                        service,
+                        since: Instant::now(),
+                    });
+                    None
+                }
+                None => Some(service),
+            }
+        };
+        // The client is idle before its slot is freed, for the run waiting for the slot.
+        drop(lease);
+        if let Some(service) = rejected {
+            stop(service).await;
+        }
+    }
+}
+
+/// Wait for the client to stop, which kills the process of a local server.
+async fn stop(service: RunningService<RoleClient, ()>) {
+    if let Err(e) = service.cancel().await {
+        log::warn!("Failed to stop an MCP client: {}", e);
+    }
+}
+
+fn stop_in_background(services: impl IntoIterator<Item = RunningService<RoleClient, ()>>) {
+    for service in services {
+        tokio::spawn(stop(service));
+    }
+}
+
+/// The clients of the MCP servers of a run, given back to the pool when the run ends.
+pub struct McpLeases {
+    pool: McpPool,
+    leases: HashMap<String, McpLease>,
+}
+
+impl McpLeases {
+    /// A client of each server, with the name of the server, as taken by `McpAgentBuilder::with_named_mcp_clients`.
+    /// The clients already acquired go back to the pool when one of the servers fails to start.
+    pub async fn acquire<'a>(
+        pool: &McpPool,
+        servers: impl IntoIterator<Item = (&'a String, &'a ServerConfig)>,
+    ) -> Result<(Self, Vec<(String, RunningService<RoleClient, ()>)>)> {
+        let mut leases = Self {
+            pool: pool.clone(),
+            leases: HashMap::new(),
+        };
+        let mut clients = Vec::new();
+        for (name, config) in servers {
+            match pool.acquire(name, config).await {
+                Ok((service, lease)) => {
+                    leases.leases.insert(name.clone(), lease);
+                    clients.push((name.clone(), service));
+                }
+                Err(e) => {
+                    let clients = clients
+                        .into_iter()
+                        .map(|(name, service)| McpClient::new(&name, service))
+                        .collect();
+                    leases.release(clients).await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok((leases, clients))
+    }
+
+    /// Give the clients back to the pool, e.g. those of `McpAgent::take_mcp_clients`.
+    pub async fn release(mut self, clients: Vec<McpClient>) {
+        for client in clients {
+            match self.leases.remove(&client.name) {
+                Some(lease) => self.pool.release(lease, client.service).await,
+                None => stop(client.service).await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::{ServerHandler, ServiceExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct TestServer;
+
+    impl ServerHandler for TestServer {}
+
+    /// A client connected to an in-process server, and the server to stop it.
+    async fn connect() -> (RunningService<RoleClient, ()>, tokio::task::JoinHandle<()>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let service = TestServer.serve(server).await.unwrap();
+            let _ = service.waiting().await;
+        });
+        (().serve(client).await.unwrap(), server)
+    }
+
+    fn pool(config: McpPoolConfig) -> McpPool {
+        McpPool::new(McpPoolConfig {
+            acquire_timeout: Some(1),
+            ..config
+        })
+    }
+
+    async fn acquire(
+        pool: &McpPool,
+        fingerprint: &str,
+        max_clients: Option<usize>,
+        started: &AtomicUsize,
+    ) -> Result<(RunningService<RoleClient, ()>, McpLease)> {
+        pool.acquire_with("test", fingerprint, max_clients, || async {
+            started.fetch_add(1, Ordering::SeqCst);
+            Ok(connect().await.0)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_reuse() {
+        let pool = pool(McpPoolConfig::default());
+        let started = AtomicUsize::new(0);
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        pool.release(lease, service).await;
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        // A second run at the same time starts another client.
+        let (other, other_lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+        pool.release(lease, service).await;
+        pool.release(other_lease, other).await;
+
+        // The clients of a changed configuration are not reused.
+        let (service, lease) = acquire(&pool, "b", None, &started).await.unwrap();
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+        pool.release(lease, service).await;
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        assert_eq!(started.load(Ordering::SeqCst), 4);
+        pool.release(lease, service).await;
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let pool = pool(McpPoolConfig::default());
+        let (service, server) = connect().await;
+        let lease = McpLease {
+            name: "test".to_string(),
+            fingerprint: "a".to_string(),
+            _permit: None,
+        };
+        pool.servers
+            .lock()
+            .unwrap()
+            .insert("test".to_string(), ServerPool::new("a", None));
+        pool.release(lease, service).await;
+        assert_eq!(pool.servers.lock().unwrap()["test"].idle.len(), 1);
+
+        // The server is gone, so the idle client is replaced.
+        server.abort();
+        let _ = server.await;
+        let started = AtomicUsize::new(0);
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert!(pool.is_healthy(&service).await);
+        pool.release(lease, service).await;
+    }
+
+    #[tokio::test]
+    async fn test_max_clients() {
+        let pool = pool(McpPoolConfig::default());
+        let started = AtomicUsize::new(0);
+        let (service, lease) = acquire(&pool, "a", Some(1), &started).await.unwrap();
+        let error = acquire(&pool, "a", Some(1), &started).await.err().unwrap();
+        assert!(error.to_string().contains("no free client"));
+
+        // The waiting run takes the client when it comes back.
+        let waiting = async {
+            let (service, lease) = acquire(&pool, "a", Some(1), &started).await.unwrap();
+            pool.release(lease, service).await;
+        };
+        let release = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            pool.release(lease, service).await;
+        };
+        tokio::join!(waiting, release);
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let pool = pool(McpPoolConfig {
+            idle_timeout: Some(0),
+            ..Default::default()
+        });
+        let started = AtomicUsize::new(0);
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        pool.release(lease, service).await;
+        assert!(pool.servers.lock().unwrap()["test"].idle.is_empty());
+
+        let pool = McpPool::new(McpPoolConfig {
+            idle_timeout: Some(1),
+            ..Default::default()
+        });
+        let (service, lease) = acquire(&pool, "a", None, &started).await.unwrap();
+        pool.release(lease, service).await;
+        assert!(pool.take_expired().is_empty());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(pool.take_expired().len(), 1);
+        assert!(pool.servers.lock().unwrap()["test"].idle.is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+//! Long-term memories of the runs, across sessions.
+//!
+//! A run with a `memory_scope`, e.g. a user id, starts with the memories of the scope relevant to its task in the
+//! system prompt, and the facts worth remembering are extracted from it once it has its answer. With auth, each API
+//! key has its own scopes. The memories are saved in `memories.json` in the data directory, or `MEMORY_FILE` when
+//! set, and can be listed and deleted with `/memories`.
+
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use actix_web::{delete, get, web, HttpResponse, Responder};
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use lumo::agent::{FileMemoryStore, LongTermMemory, Memory, MemoryStore};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::Caller;
+
+static MEMORIES: OnceLock<Arc<dyn MemoryStore>> = OnceLock::new();
+
+fn from_env() -> Result<FileMemoryStore> {
+    let path = match std::env::var("MEMORY_FILE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => ProjectDirs::from("com", "lumo", "lumo-server")
+            .context("Failed to determine data directory")?
+            .data_dir()
+            .join("memories.json"),
+    };
+    FileMemoryStore::new(path)
+}
+
+/// The memories of the server. Kept in memory when the memory file can not be read.
+pub(crate) fn memory_store() -> Arc<dyn MemoryStore> {
+    MEMORIES
+        .get_or_init(|| {
+            // The tests do not remember their runs in the memories of the server.
+            if cfg!(test) {
+                return Arc::new(FileMemoryStore::in_memory());
+            }
+            let store = from_env().unwrap_or_else(|e| {
+                log::error!(
+                    "Invalid memory file, the memories are kept in memory: {:#}",
+                    e
+                );
+                FileMemoryStore::in_memory()
+            });
+            Arc::new(store)
+        })
+        .clone()
+}
+
+/// The scope of the store: the scope of the request, within the API key of the caller when auth is enabled.
+pub(crate) fn store_scope(caller: Option<&str>, scope: &str) -> String {
+    match caller {
+        Some(caller) => format!("{}/{}", caller, scope),
+        None => scope.to_string(),
+    }
+}
+
+/// The long-term memory of the runs of the scope.
+pub(crate) fn long_term_memory(caller: Option<&str>, scope: &str) -> LongTermMemory {
+    LongTermMemory::new(memory_store(), store_scope(caller, scope))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct MemoryQuery {
+    /// The `memory_scope` of the runs.
+    scope: String,
+}
+
+/// A fact remembered from a run.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct MemoryReport {
+    id: String,
+    fact: String,
+    /// The names of the people, organizations, places and things the fact is about.
+    entities: Vec<String>,
+    /// The task of the run the fact was learned in.
+    task: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<Memory> for MemoryReport {
+    fn from(memory: Memory) -> Self {
+        Self {
+            id: memory.id,
+            fact: memory.fact,
+            entities: memory.entities,
+            task: memory.task,
+            created_at: memory.created_at,
+        }
+    }
+}
+
+/// The memories of a scope, oldest first.
+#[utoipa::path(
+    tag = "memories",
+    params(MemoryQuery),
+    responses(
+        (status = 200, description = "The memories of the scope", body = Vec<MemoryReport>),
+    )
+)]
+#[get("/memories")]
+pub(crate) async fn list_memories(
+    caller: Caller,
+    query: web::Query<MemoryQuery>,
+) -> Result<impl Responder, actix_web::Error> {
+    let memories = memory_store()
+        .list(&store_scope(caller.name(), &query.scope))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(
+        memories
+            .into_iter()
+            .map(MemoryReport::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Forget a memory of a scope.
+#[utoipa::path(
+    tag = "memories",
+    params(("id" = String, Path, description = "The memory id"), MemoryQuery),
+    responses(
+        (status = 204, description = "The memory is deleted"),
+        (status = 404, description = "The scope has no memory with this id"),
+    )
+)]
+#[delete("/memories/{id}")]
+pub(crate) async fn delete_memory(
+    caller: Caller,
+    id: web::Path<String>,
+    query: web::Query<MemoryQuery>,
+) -> Result<impl Responder, actix_web::Error> {
+    if memory_store()
+        .delete(&store_scope(caller.name(), &query.scope), &id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+/// Forget all the memories of a scope.
+#[utoipa::path(
+    tag = "memories",
+    params(MemoryQuery),
+    responses(
+        (status = 200, description = "The memories are deleted, `deleted` is how many there were"),
+    )
+)]
+#[delete("/memories")]
+pub(crate) async fn clear_memories(
+    caller: Caller,
+    query: web::Query<MemoryQuery>,
+) -> Result<impl Responder, actix_web::Error> {
+    let deleted = memory_store()
+        .clear(&store_scope(caller.name(), &query.scope))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "deleted": deleted })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_memories() {
+        assert_eq!(store_scope(Some("team-a"), "alice"), "team-a/alice");
+        assert_eq!(store_scope(None, "alice"), "alice");
+
+        let memory = |fact: &str| Memory {
+            id: nanoid::nanoid!(),
+            scope: "memories-test".to_string(),
+            fact: fact.to_string(),
+            entities: vec![],
+            task: "Plan a trip".to_string(),
+            created_at: Utc::now(),
+        };
+        let first = memory("The user lives in Lyon");
+        memory_store()
+            .add(vec![first.clone(), memory("The user prefers trains")])
+            .await
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .service(list_memories)
+                .service(delete_memory)
+                .service(clear_memories),
+        )
+        .await;
+        let request = test::TestRequest::get()
+            .uri("/memories?scope=memories-test")
+            .to_request();
+        let memories: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(memories.as_array().unwrap().len(), 2);
+        assert_eq!(memories[0]["fact"], "The user lives in Lyon");
+
+        let request = test::TestRequest::delete()
+            .uri(&format!("/memories/{}?scope=memories-test", first.id))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 204);
+        let request = test::TestRequest::delete()
+            .uri(&format!("/memories/{}?scope=memories-test", first.id))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+        let request = test::TestRequest::delete()
+            .uri("/memories?scope=memories-test")
+            .to_request();
+        let deleted: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(deleted["deleted"], 1);
+    }
+}
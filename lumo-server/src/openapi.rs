@@ -0,0 +1,194 @@
+//! OpenAPI description of the server, served at `/openapi.json` with a Swagger UI at `/swagger-ui/`.
+//!
+//! The schema is generated from the handlers and the request and response types, so clients can be generated from
+//! it, e.g. with `openapi-generator-cli generate -i http://localhost:8080/openapi.json -g typescript-fetch`.
+
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{
+    artifacts::{self, JobArtifact},
+    audio::{self, TranscribeResponse},
+    auth::{self, KeyReport, KeyUsage},
+    cancellation,
+    clarification::{self, RunInput},
+    config::{ToolConfig, UsageQuota},
+    events::{StepPayload, StreamEvent, ToolCallPayload, ToolTimingPayload, VersionedStreamEvent},
+    export::{self, ExportRequest},
+    history::{HistoryTrimming, TrimmedHistory},
+    jobs::{self, Job, JobPriority, JobStatus, SubmitJobRequest},
+    memories::{self, MemoryReport},
+    pipeline::{self, RunPipelineRequest, RunPipelineResponse},
+    scheduler::{self, CreateScheduleRequest, RunStatus, Schedule, ScheduleRun},
+    sessions::{self, ArtifactMetadata, Session, SessionMemory, SessionMessage, SessionState},
+    summarize::{self, SummarizeRequest, SummarizeResponse},
+    usage::{self, DayUsage, KeyUsageReport, Usage, UsageReport},
+    validation::{FieldError, ValidationErrors},
+    RunTaskRequest, RunTaskResponse,
+};
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+pub const SWAGGER_UI_PATH: &str = "/swagger-ui";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Lumo Server", description = "Run lumo agents over HTTP."),
+    paths(
+        crate::health_check,
+        crate::run_task,
+        crate::stream_task,
+        crate::resume_stream,
+        clarification::submit_input,
+        cancellation::cancel_run,
+        summarize::summarize_conversation,
+        pipeline::run_pipeline,
+        audio::transcribe_audio,
+        export::export_runs,
+        jobs::submit_job,
+        jobs::get_job,
+        artifacts::get_artifact,
+        sessions::get_session,
+        sessions::get_session_memory,
+        sessions::delete_session,
+        sessions::post_message,
+        auth::list_keys,
+        usage::get_usage,
+        memories::list_memories,
+        memories::delete_memory,
+        memories::clear_memories,
+        scheduler::create_schedule,
+        scheduler::list_schedules,
+        scheduler::get_schedule,
+        scheduler::delete_schedule,
+        scheduler::list_schedule_runs,
+    ),
+    components(schemas(
+        RunTaskRequest,
+        RunTaskResponse,
+        ToolConfig,
+        HistoryTrimming,
+        TrimmedHistory,
+        StreamEvent,
+        VersionedStreamEvent,
+        StepPayload,
+        ToolCallPayload,
+        ToolTimingPayload,
+        RunInput,
+        SummarizeRequest,
+        SummarizeResponse,
+        RunPipelineRequest,
+        RunPipelineResponse,
+        TranscribeResponse,
+        ExportRequest,
+        SubmitJobRequest,
+        Job,
+        JobPriority,
+        JobStatus,
+        JobArtifact,
+        Session,
+        SessionState,
+        SessionMemory,
+        SessionMessage,
+        ArtifactMetadata,
+        KeyReport,
+        KeyUsage,
+        UsageReport,
+        KeyUsageReport,
+        DayUsage,
+        Usage,
+        UsageQuota,
+        MemoryReport,
+        CreateScheduleRequest,
+        Schedule,
+        ScheduleRun,
+        RunStatus,
+        ValidationErrors,
+        FieldError,
+    )),
+    modifiers(&ApiKeySecurity),
+    security(("api_key" = [])),
+)]
+pub struct ApiDoc;
+
+/// The `Authorization: Bearer <key>` header checked when `ENABLE_AUTH=true`, with `LUMO_API_KEY` or a key of servers.yaml.
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// The Swagger UI, which also serves the OpenAPI document.
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new(format!("{}/{{_:.*}}", SWAGGER_UI_PATH)).url(OPENAPI_PATH, ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_event_schema() {
+        let openapi = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schema = &openapi["components"]["schemas"]["StreamEvent"];
+        let types = schema["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|variant| variant["properties"]["type"]["enum"].as_array().unwrap().clone())
+            .collect::<Vec<_>>();
+
+        // Every event the server sends must be described in the schema.
+        let events = [
+            StreamEvent::Token { content: String::new() },
+            StreamEvent::Reasoning { content: String::new() },
+            StreamEvent::PartialAnswer { json_patch: Vec::new() },
+            StreamEvent::Step {
+                step: StepPayload {
+                    step: 1,
+                    tool_calls: Vec::new(),
+                    started_at: None,
+                    duration_ms: None,
+                    model_latency_ms: None,
+                    tool_timings: Vec::new(),
+                    input_tokens: None,
+                    output_tokens: None,
+                    checkpoint_id: None,
+                },
+            },
+            StreamEvent::ToolCallStarted { id: None, name: String::new(), arguments: serde_json::Value::Null },
+            StreamEvent::ToolCallResult { id: None, name: String::new(), observation: String::new(), latency_ms: 0, success: true },
+            StreamEvent::ToolCallFailed { id: None, name: String::new(), error: String::new() },
+            StreamEvent::CodeExecutionStart { code: String::new() },
+            StreamEvent::CodeExecutionEnd { output: String::new() },
+            StreamEvent::ClarificationRequired { question: String::new() },
+            StreamEvent::ManagedAgent { agent: String::new(), event: Box::new(StreamEvent::Done) },
+            StreamEvent::Error { message: String::new() },
+            StreamEvent::Cancelled,
+            StreamEvent::Reset { last_event_id: 0 },
+            StreamEvent::Summary { summary: Default::default() },
+            StreamEvent::Done,
+        ];
+        assert_eq!(types.len(), events.len());
+        for event in events {
+            let event = serde_json::to_value(event).unwrap();
+            assert!(types.contains(&event["type"]), "{} is not in the schema", event["type"]);
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let openapi = ApiDoc::openapi();
+        for path in ["/run", "/stream", "/stream/{id}", "/jobs", "/jobs/{id}", "/jobs/{id}/artifacts/{name}", "/runs/{id}/input", "/runs/{id}/cancel", "/summarize", "/export", "/sessions/{id}", "/sessions/{id}/memory", "/sessions/{id}/message", "/admin/keys", "/memories", "/memories/{id}", "/schedules/{id}/runs"] {
+            assert!(openapi.paths.paths.contains_key(path), "{} is missing", path);
+        }
+    }
+}
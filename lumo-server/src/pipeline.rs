@@ -0,0 +1,192 @@
+//! Runs of multi-agent pipelines defined in YAML or JSON, see `lumo::pipeline`.
+
+use std::str::FromStr;
+
+use actix_web::{post, web, HttpResponse, Responder};
+use anyhow::anyhow;
+use lumo::pipeline::{ModelProvider, ModelSpec, Pipeline, PipelineDefinition, StageOutput};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{config::Servers, create_tool, model_builder, with_budget, ToolType};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1/chat/completions";
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct RunPipelineRequest {
+    /// The definition of the pipeline, as an object or as a YAML or JSON string.
+    #[schema(value_type = Object)]
+    pipeline: serde_json::Value,
+    task: String,
+    /// The model of the agents that have none, when the pipeline has no model either.
+    model: Option<String>,
+    /// The base URL of the models that have none. Defaults to OpenAI.
+    base_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct RunPipelineResponse {
+    /// The output of the last stage that ran.
+    answer: String,
+    /// The output of each stage, `null` for the skipped stages.
+    #[schema(value_type = Vec<Object>)]
+    stages: Vec<StageOutput>,
+}
+
+/// Parse the definition of the request and check that the server can run it. Models are served through the
+/// OpenAI-compatible API with the API keys of the server, so definitions cannot choose an API key.
+fn parse_definition(req: &RunPipelineRequest) -> Result<PipelineDefinition, actix_web::Error> {
+    let mut definition = match &req.pipeline {
+        serde_json::Value::String(definition) => PipelineDefinition::from_yaml(definition),
+        definition => serde_json::from_value::<PipelineDefinition>(definition.clone())
+            .map_err(|e| anyhow!("Invalid pipeline definition: {}", e))
+            .and_then(|definition| definition.validate().map(|_| definition)),
+    }
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+
+    if definition.model.is_none() {
+        definition.model = Some(ModelSpec {
+            model_id: req.model.clone(),
+            base_url: req.base_url.clone(),
+            ..Default::default()
+        });
+    }
+    let models = definition.model.iter().chain(
+        definition
+            .agents
+            .iter()
+            .filter_map(|agent| agent.model.as_ref()),
+    );
+    for model in models {
+        if model.provider != ModelProvider::OpenAI && model.provider.preset().is_none() {
+            return Err(actix_web::error::ErrorBadRequest(
+                "The server only runs models with the openai, xai and deepseek providers, use the base_url of an OpenAI-compatible API",
+            ));
+        }
+        if model.api_key_env.is_some() {
+            return Err(actix_web::error::ErrorBadRequest(
+                "api_key_env is not supported by the server, the API keys come from its environment",
+            ));
+        }
+    }
+    for tool in definition.agents.iter().flat_map(|agent| &agent.tools) {
+        if matches!(ToolType::from_str(tool)?, ToolType::AskUser) {
+            return Err(actix_web::error::ErrorBadRequest(
+                "AskUser is only available on /stream",
+            ));
+        }
+    }
+    Ok(definition)
+}
+
+/// Run a task through a multi-agent pipeline: agents with their models, tools and prompts, the agents they manage,
+/// and the stages the task goes through.
+#[utoipa::path(
+    tag = "runs",
+    request_body = RunPipelineRequest,
+    responses(
+        (status = 200, description = "The answer of the pipeline and the output of its stages", body = RunPipelineResponse),
+        (status = 400, description = "The definition is invalid, or uses a model or tool the server cannot run"),
+    )
+)]
+#[post("/pipeline/run")]
+pub(crate) async fn run_pipeline(
+    req: web::Json<RunPipelineRequest>,
+) -> Result<impl Responder, actix_web::Error> {
+    let definition = parse_definition(&req)?;
+    let servers = Servers::load().map_err(actix_web::error::ErrorInternalServerError)?;
+    let tool_config = servers.tool_config.unwrap_or_default();
+    let budgets = servers.tool_budgets.unwrap_or_default();
+
+    let pipeline = Pipeline::new(definition)
+        .map_err(actix_web::error::ErrorBadRequest)?
+        .with_model_factory(|spec| {
+            let preset = spec.provider.preset();
+            let base_url = spec
+                .base_url
+                .as_deref()
+                .or(preset.map(|preset| preset.base_url()))
+                .unwrap_or(DEFAULT_BASE_URL);
+            let model_id = spec
+                .model_id
+                .as_deref()
+                .or(preset.map(|preset| preset.default_model()))
+                .unwrap_or("gpt-4.1-mini");
+            Ok(Box::new(
+                model_builder(model_id, base_url)
+                    .with_temperature(spec.temperature)
+                    .build()?,
+            ))
+        })
+        .with_tool_factory(move |name| {
+            let tool_type = ToolType::from_str(name).map_err(|e| anyhow!("{}", e))?;
+            let config = tool_config.get(name).cloned().unwrap_or_default();
+            let tool = create_tool(&tool_type, &config, None).map_err(|e| anyhow!("{}", e))?;
+            let budget = budgets.get(name).or_else(|| budgets.get(tool.name()));
+            Ok(with_budget(tool, budget, config.max_results))
+        });
+    let output = pipeline
+        .run(&req.task)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("{:#}", e)))?;
+    Ok(HttpResponse::Ok().json(RunPipelineResponse {
+        answer: output.answer,
+        stages: output.stages,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(pipeline: serde_json::Value) -> RunPipelineRequest {
+        RunPipelineRequest {
+            pipeline,
+            task: "Summarize the news".to_string(),
+            model: Some("gpt-4.1".to_string()),
+            base_url: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        let definition = parse_definition(&request(serde_json::json!(
+            "agents:\n  - name: researcher\n    tools: [DuckDuckGo]"
+        )))
+        .unwrap();
+        assert_eq!(definition.agents[0].name, "researcher");
+        assert_eq!(
+            definition.model.unwrap().model_id.as_deref(),
+            Some("gpt-4.1")
+        );
+
+        let error = |pipeline| {
+            parse_definition(&request(pipeline))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert!(error(serde_json::json!({"agents": []})).contains("no agents"));
+        assert!(error(serde_json::json!({
+            "agents": [{"name": "a", "model": {"api_key_env": "AWS_SECRET_ACCESS_KEY"}}]
+        }))
+        .contains("api_key_env is not supported"));
+        assert!(error(serde_json::json!({
+            "model": {"provider": "ollama"},
+            "agents": [{"name": "a"}]
+        }))
+        .contains("openai, xai and deepseek providers"));
+        assert!(parse_definition(&request(serde_json::json!({
+            "agents": [{"name": "a", "model": {"provider": "deepseek"}}]
+        })))
+        .is_ok());
+        assert!(
+            error(serde_json::json!({"agents": [{"name": "a", "tools": ["Unknown"]}]}))
+                .contains("Invalid tool type")
+        );
+        assert!(
+            error(serde_json::json!({"agents": [{"name": "a", "tools": ["AskUser"]}]}))
+                .contains("only available on /stream")
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::web::BytesMut;
+use actix_web::{Error, HttpMessage};
+use futures::StreamExt;
+use serde_json::{Map, Value};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Header used to pass a run id to the server. A new id is generated when the client doesn't send one.
+pub const RUN_ID_HEADER: &str = "x-run-id";
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Bodies larger than this are not logged.
+const MAX_LOGGED_BODY_BYTES: usize = 64 * 1024;
+
+/// The limit of the JSON bodies of the handlers, the default of `web::JsonConfig`. Larger bodies are not buffered,
+/// and left to the handlers to reject.
+const MAX_JSON_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "x-api-key"];
+
+/// Keys, or ends of keys after an underscore, naming credentials, e.g. `token`, `access_token` or `OPENAI_API_KEY`.
+const SENSITIVE_KEYS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "access_key",
+    "private_key",
+    "secret_key",
+    "authorization",
+    "password",
+    "secret",
+    "token",
+];
+
+/// The run id of a request, stored in the request extensions.
+#[derive(Debug, Clone)]
+pub struct RunId(pub String);
+
+/// Logs the method, path, status, latency, run id and model of each request as a structured `tracing` event.
+///
+/// Configured with environment variables:
+/// - `REQUEST_LOG_SAMPLE_RATE`: fraction of requests to log, between 0 and 1. Defaults to 1. Server errors are
+///   always logged.
+/// - `REQUEST_LOG_BODIES`: set to `false` to not log request bodies. Defaults to `true`.
+///
+/// Authorization headers and anything that looks like a credential in JSON bodies are redacted.
+#[derive(Clone)]
+pub struct RequestLogger {
+    sample_rate: f64,
+    log_bodies: bool,
+    counter: Arc<AtomicU64>,
+}
+
+impl RequestLogger {
+    pub fn new(sample_rate: f64, log_bodies: bool) -> Self {
+        Self {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            log_bodies,
+            counter: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let sample_rate = std::env::var("REQUEST_LOG_SAMPLE_RATE")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(1.0);
+        let log_bodies = std::env::var("REQUEST_LOG_BODIES")
+            .map(|v| v != "false")
+            .unwrap_or(true);
+        Self::new(sample_rate, log_bodies)
+    }
+
+    /// Sample requests evenly, so a rate of 0.25 logs exactly every fourth request.
+    fn sample(&self) -> bool {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.sample_rate).floor() > (n * self.sample_rate).floor()
+    }
+}
+
+/// Whether the key is one of the sensitive keys or ends with one, so `max_tokens` or `input_tokens` are logged.
+fn is_sensitive_key(key: &str) -> bool {
+    let key = snake_case(key);
+    SENSITIVE_KEYS.iter().any(|sensitive| {
+        key == *sensitive
+            || key
+                .strip_suffix(sensitive)
+                .is_some_and(|prefix| prefix.ends_with('_'))
+    })
+}
+
+/// The key in lowercase with underscores between its words, e.g. `accessToken` or `X-Api-Key` to `access_token` or
+/// `x_api_key`.
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    let mut after_lowercase = false;
+    for c in key.chars() {
+        if c.is_uppercase() && after_lowercase {
+            snake.push('_');
+        }
+        after_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        snake.extend(c.to_lowercase());
+    }
+    snake.replace('-', "_")
+}
+
+fn looks_like_credential(value: &str) -> bool {
+    value.starts_with("Bearer ") || value.starts_with("sk-") || value.starts_with("gsk_")
+}
+
+/// Replace the values of credential-like keys, and strings that look like API keys, with `[REDACTED]`.
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive_key(key) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        Value::String(s) if looks_like_credential(s) => *s = REDACTED.to_string(),
+        _ => {}
+    }
+}
+
+fn redact_headers(req: &ServiceRequest) -> Value {
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                value.to_str().unwrap_or_default().to_string()
+            };
+            (name.to_string(), Value::String(value))
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(headers)
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLogger
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestLoggerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestLoggerMiddleware {
+            service: Rc::new(service),
+            logger: self.clone(),
+        }))
+    }
+}
+
+pub struct RequestLoggerMiddleware<S> {
+    service: Rc<S>,
+    logger: RequestLogger,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let logger = self.logger.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let run_id = req
+                .headers()
+                .get(RUN_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| nanoid::nanoid!());
+            req.extensions_mut().insert(RunId(run_id.clone()));
+
+            let sampled = logger.sample();
+            let method = req.method().to_string();
+            let path = req.path().to_string();
+            let headers = if sampled { Some(redact_headers(&req)) } else { None };
+
+            let is_json = req
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.starts_with("application/json"));
+            let mut model = None;
+            let mut body = None;
+            if sampled && is_json {
+                // The body is buffered to read it, then put back for the handler.
+                let mut payload = req.take_payload();
+                let mut buffer = BytesMut::new();
+                let mut complete = true;
+                while let Some(chunk) = payload.next().await {
+                    buffer.extend_from_slice(&chunk?);
+                    if buffer.len() > MAX_JSON_BODY_BYTES {
+                        complete = false;
+                        break;
+                    }
+                }
+                let bytes = buffer.freeze();
+                if complete && bytes.len() <= MAX_LOGGED_BODY_BYTES {
+                    if let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) {
+                        model = value.get("model").and_then(Value::as_str).map(String::from);
+                        if logger.log_bodies {
+                            redact_json(&mut value);
+                            body = Some(value);
+                        }
+                    }
+                }
+                if complete {
+                    req.set_payload(Payload::from(bytes));
+                } else {
+                    let rest = futures::stream::once(async move { Ok(bytes) }).chain(payload);
+                    req.set_payload(Payload::Stream {
+                        payload: Box::pin(rest),
+                    });
+                }
+            }
+
+            let result = service.call(req).await;
+            let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+            let status = match &result {
+                Ok(res) => res.status().as_u16(),
+                Err(e) => e.as_response_error().status_code().as_u16(),
+            };
+
+            if sampled || status >= 500 {
+                tracing::info!(
+                    target: "lumo_server::request",
+                    run_id = %run_id,
+                    method = %method,
+                    path = %path,
+                    status,
+                    latency_ms,
+                    model = model.as_deref().unwrap_or_default(),
+                    headers = %headers.unwrap_or_default(),
+                    body = %body.unwrap_or_default(),
+                    "request"
+                );
+            }
+
+            let mut res = result?;
+            if let Ok(value) = HeaderValue::from_str(&run_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(RUN_ID_HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_json() {
+        let mut body = json!({
+            "task": "What is the weather in London?",
+            "model": "gpt-4o-mini",
+            "api_key": "abc123",
+            "config": {"OPENAI_API_KEY": "abc", "headers": ["Bearer xyz", "text/plain"]},
+            "history": [{"role": "user", "content": "my key is sk-proj-123"}],
+            "token": null,
+            "max_tokens": 512,
+            "usage": {"input_tokens": 10, "output_tokens": 20, "total_tokens": 30},
+            "auth": {"access_token": "abc", "refreshToken": "def", "client_secret": "ghi"},
+            "tools": [{"name": "search", "X-Api-Key": "jkl", "EXA_API_KEY": "mno"}],
+            "tokenizer": "cl100k_base",
+        });
+        redact_json(&mut body);
+        assert_eq!(
+            body,
+            json!({
+                "task": "What is the weather in London?",
+                "model": "gpt-4o-mini",
+                "api_key": REDACTED,
+                "config": {"OPENAI_API_KEY": REDACTED, "headers": [REDACTED, "text/plain"]},
+                "history": [{"role": "user", "content": "my key is sk-proj-123"}],
+                "token": null,
+                "max_tokens": 512,
+                "usage": {"input_tokens": 10, "output_tokens": 20, "total_tokens": 30},
+                "auth": {"access_token": REDACTED, "refreshToken": REDACTED, "client_secret": REDACTED},
+                "tools": [{"name": "search", "X-Api-Key": REDACTED, "EXA_API_KEY": REDACTED}],
+                "tokenizer": "cl100k_base",
+            })
+        );
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let logger = RequestLogger::new(0.25, true);
+        let sampled = (0..100).filter(|_| logger.sample()).count();
+        assert_eq!(sampled, 25);
+        let logger = RequestLogger::new(0.0, true);
+        assert!(!(0..10).any(|_| logger.sample()));
+    }
+
+    #[actix_web::test]
+    async fn test_logged_body_limit() {
+        use actix_web::{test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new().wrap(RequestLogger::new(1.0, true)).route(
+            "/run",
+            web::post().to(|body: web::Json<Value>| async move {
+                HttpResponse::Ok().body(body["task"].as_str().unwrap_or_default().len().to_string())
+            }),
+        ))
+        .await;
+        // Bodies over the default payload limit but within the JSON limit reach the handler whole.
+        for (size, status) in [(300 * 1024, 200), (3 * 1024 * 1024, 413)] {
+            let req = test::TestRequest::post()
+                .uri("/run")
+                .set_json(json!({"task": "a".repeat(size)}))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status().as_u16(), status);
+            if status == 200 {
+                assert_eq!(test::read_body(res).await, size.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use cron::Schedule as CronSchedule;
+use directories::ProjectDirs;
+use opentelemetry::{
+    global,
+    trace::{SpanKind, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+use lumo::{agent::RunSummary, tools::check_public_host};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::{Caller, KeyStore},
+    sessions::{run_in_session, MemorySessions, SessionStore},
+    RunTaskRequest,
+};
+
+/// Number of runs kept per schedule. Older runs are dropped.
+const MAX_RUNS_PER_SCHEDULE: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Schedule {
+    pub id: String,
+    pub cron: String,
+    #[serde(flatten)]
+    pub request: RunTaskRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_deserializing)]
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+impl Schedule {
+    /// The schedule without the API keys of its tool settings, to show it in responses.
+    fn redacted(mut self) -> Self {
+        self.request = self.request.redacted();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Success,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScheduleRun {
+    pub id: String,
+    pub schedule_id: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Steps, tool calls, tokens and errors of the run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub summary: Option<RunSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduleStore {
+    schedules: Vec<Schedule>,
+    runs: HashMap<String, Vec<ScheduleRun>>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CreateScheduleRequest {
+    cron: String,
+    #[serde(flatten)]
+    request: RunTaskRequest,
+    webhook_url: Option<String>,
+}
+
+/// Parse a cron expression. Standard 5-field expressions are accepted and run at second 0.
+pub fn parse_cron(expression: &str) -> Result<CronSchedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    CronSchedule::from_str(&expression)
+        .with_context(|| format!("Invalid cron expression: {}", expression))
+}
+
+fn next_run(cron: &str) -> Option<DateTime<Utc>> {
+    parse_cron(cron).ok()?.upcoming(Utc).next()
+}
+
+/// Parse the url of a webhook, refusing the ones that are not http or https or that reach the local network.
+async fn check_webhook_url(url: &str) -> Result<Url> {
+    let url = Url::parse(url).with_context(|| format!("Invalid webhook url: {}", url))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("Only http and https webhooks can be called, not {}", url);
+    }
+    check_public_host(&url).await?;
+    Ok(url)
+}
+
+/// The schedules saved at `path`. A file that can not be read is moved to `schedules.json.corrupt`, so the server
+/// starts without the schedules instead of not starting, and the file is not overwritten by the next save.
+fn read_store(path: &Path) -> ScheduleStore {
+    if !path.exists() {
+        return ScheduleStore::default();
+    }
+    let store = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read schedules file: {:?}", path))
+        .and_then(|content| {
+            serde_json::from_str::<ScheduleStore>(&content).context("Failed to parse schedules file")
+        });
+    match store {
+        Ok(store) => store,
+        Err(e) => {
+            let corrupt = path.with_extension("json.corrupt");
+            log::error!("{:#}, starting without schedules and moving it to {:?}", e, corrupt);
+            if let Err(e) = fs::rename(path, &corrupt) {
+                log::error!("Failed to move the schedules file to {:?}: {}", corrupt, e);
+            }
+            ScheduleStore::default()
+        }
+    }
+}
+
+/// Write the schedules next to the file and rename it, so a crash while writing keeps the previous schedules.
+fn write_store(path: &Path, content: &str) -> Result<()> {
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write schedules: {:?}", partial))?;
+    fs::rename(&partial, path).with_context(|| format!("Failed to write schedules: {:?}", path))
+}
+
+/// Persisted schedules and their runs, shared between the HTTP handlers and the scheduler loop.
+#[derive(Clone)]
+pub struct Scheduler {
+    store: Arc<Mutex<ScheduleStore>>,
+    path: PathBuf,
+    sessions: Arc<dyn SessionStore>,
+    /// The keys the scheduled runs are counted against, to check their monthly quotas.
+    keys: Arc<KeyStore>,
+}
+
+impl Scheduler {
+    pub fn load() -> Result<Self> {
+        let path = Self::store_path()?;
+        let mut store = read_store(&path);
+        for schedule in store.schedules.iter_mut() {
+            schedule.next_run = next_run(&schedule.cron);
+        }
+        Ok(Self {
+            store: Arc::new(Mutex::new(store)),
+            path,
+            sessions: Arc::new(MemorySessions::default()),
+            keys: Arc::new(KeyStore::default()),
+        })
+    }
+
+    /// Store the sessions of the scheduled runs in `sessions` instead of the memory of this instance.
+    pub fn with_sessions(mut self, sessions: Arc<dyn SessionStore>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Check the monthly quotas of `keys` before each scheduled run of their schedules.
+    pub fn with_keys(mut self, keys: Arc<KeyStore>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn store_path() -> Result<PathBuf> {
+        let proj_dirs = ProjectDirs::from("com", "lumo", "lumo-server")
+            .context("Failed to determine data directory")?;
+        if !proj_dirs.data_dir().exists() {
+            fs::create_dir_all(proj_dirs.data_dir())?;
+        }
+        Ok(proj_dirs.data_dir().join("schedules.json"))
+    }
+
+    /// Write the schedules off the executor. Called with the store locked, so the writes keep their order.
+    async fn save(&self, store: &ScheduleStore) {
+        let path = self.path.clone();
+        let result = match serde_json::to_string_pretty(store) {
+            Ok(content) => tokio::task::spawn_blocking(move || write_store(&path, &content))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to save schedules: {:#}", e);
+        }
+    }
+
+    async fn add(&self, schedule: Schedule) {
+        let mut store = self.store.lock().await;
+        store.schedules.push(schedule);
+        self.save(&store).await;
+    }
+
+    async fn remove(&self, id: &str) -> bool {
+        let mut store = self.store.lock().await;
+        let len = store.schedules.len();
+        store.schedules.retain(|schedule| schedule.id != id);
+        store.runs.remove(id);
+        let removed = store.schedules.len() != len;
+        if removed {
+            self.save(&store).await;
+        }
+        removed
+    }
+
+    async fn get(&self, id: &str) -> Option<Schedule> {
+        let store = self.store.lock().await;
+        store.schedules.iter().find(|s| s.id == id).cloned()
+    }
+
+    async fn list(&self) -> Vec<Schedule> {
+        self.store.lock().await.schedules.clone()
+    }
+
+    async fn runs(&self, id: &str) -> Vec<ScheduleRun> {
+        let store = self.store.lock().await;
+        store.runs.get(id).cloned().unwrap_or_default()
+    }
+
+    async fn record_run(&self, run: ScheduleRun) {
+        let mut store = self.store.lock().await;
+        // The schedule may have been deleted while it was running.
+        if !store.schedules.iter().any(|s| s.id == run.schedule_id) {
+            return;
+        }
+        let runs = store.runs.entry(run.schedule_id.clone()).or_default();
+        runs.push(run);
+        if runs.len() > MAX_RUNS_PER_SCHEDULE {
+            let excess = runs.len() - MAX_RUNS_PER_SCHEDULE;
+            runs.drain(..excess);
+        }
+        self.save(&store).await;
+    }
+
+    /// Return the schedules that are due and move their next run forward.
+    async fn take_due(&self, now: DateTime<Utc>) -> Vec<Schedule> {
+        let mut store = self.store.lock().await;
+        let mut due = Vec::new();
+        for schedule in store.schedules.iter_mut() {
+            if schedule.next_run.is_some_and(|next| next <= now) {
+                due.push(schedule.clone());
+                schedule.next_run = parse_cron(&schedule.cron)
+                    .ok()
+                    .and_then(|cron| cron.after(&now).next());
+            }
+        }
+        due
+    }
+
+    /// Run due schedules until the server stops.
+    pub fn start(&self) {
+        let scheduler = self.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                for schedule in scheduler.take_due(Utc::now()).await {
+                    let scheduler = scheduler.clone();
+                    actix_web::rt::spawn(async move {
+                        scheduler.run_schedule(schedule).await;
+                    });
+                }
+            }
+        });
+    }
+
+    async fn run_schedule(&self, schedule: Schedule) {
+        let tracer = global::tracer("lumo");
+        let span = tracer
+            .span_builder("scheduled_run")
+            .with_kind(SpanKind::Internal)
+            .with_start_time(std::time::SystemTime::now())
+            .with_attributes(vec![
+                KeyValue::new("gen_ai.operation.name", "scheduled_run"),
+                KeyValue::new("schedule.id", schedule.id.clone()),
+                KeyValue::new("input.value", schedule.request.task.clone()),
+            ])
+            .start(&tracer);
+        let cx = Context::current_with_span(span);
+        let run_metadata = schedule.request.run_metadata();
+        cx.span().set_attributes(run_metadata.attributes());
+
+        let run_id = nanoid::nanoid!();
+        let started_at = Utc::now();
+        // The schedule was authorized when it was created, but the quota of its key may have been reached since.
+        let quota = match schedule.request.caller.as_deref().and_then(|name| self.keys.get(name)) {
+            Some(key) => key.check_quota(),
+            None => Ok(()),
+        };
+        let result = match quota {
+            Ok(()) => run_in_session(self.sessions.as_ref(), &schedule.request, &run_id, &cx).await,
+            Err(e) => Err(e),
+        };
+        let (status, response, error, summary) = match result {
+            Ok(output) => (RunStatus::Success, Some(output.response), None, Some(output.summary)),
+            Err(e) => (RunStatus::Error, None, Some(e.to_string()), None),
+        };
+        cx.span().end_with_timestamp(std::time::SystemTime::now());
+
+        let run = ScheduleRun {
+            id: run_id,
+            schedule_id: schedule.id.clone(),
+            started_at,
+            finished_at: Utc::now(),
+            status,
+            response,
+            error,
+            summary,
+            tags: run_metadata.tags,
+            metadata: run_metadata.metadata,
+        };
+        if let Some(webhook_url) = &schedule.webhook_url {
+            if let Err(e) = notify_webhook(webhook_url, &run).await {
+                log::warn!("Failed to notify webhook for schedule {}: {:#}", schedule.id, e);
+            }
+        }
+        self.record_run(run).await;
+    }
+}
+
+/// Post the run to the webhook. Its host is checked again, since its address may have changed since the schedule
+/// was created, and redirects are not followed.
+async fn notify_webhook(url: &str, run: &ScheduleRun) -> Result<()> {
+    let url = check_webhook_url(url).await?;
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?
+        .post(url)
+        .json(run)
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    tag = "schedules",
+    request_body = CreateScheduleRequest,
+    responses(
+        (status = 201, description = "The schedule is created", body = Schedule),
+        (status = 400, description = "The cron expression or the webhook url is invalid"),
+        (status = 422, description = "A field of the request is invalid, e.g. an unknown tool, preset or agent type", body = crate::validation::ValidationErrors),
+    )
+)]
+#[post("/schedules")]
+pub(crate) async fn create_schedule(
+    scheduler: web::Data<Scheduler>,
+    caller: Caller,
+    req: web::Json<CreateScheduleRequest>,
+) -> Result<impl Responder, actix_web::Error> {
+    let mut req = req.into_inner();
+    caller.authorize(&mut req.request)?;
+    let cron = parse_cron(&req.cron).map_err(actix_web::error::ErrorBadRequest)?;
+    req.request.validate()?;
+    if req.request.resume_from.is_some() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "resume_from is only supported by /stream",
+        ));
+    }
+    if let Some(webhook_url) = &req.webhook_url {
+        check_webhook_url(webhook_url)
+            .await
+            .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+    }
+    let schedule = Schedule {
+        id: nanoid::nanoid!(),
+        cron: req.cron,
+        request: req.request,
+        webhook_url: req.webhook_url,
+        created_at: Utc::now(),
+        next_run: cron.upcoming(Utc).next(),
+    };
+    scheduler.add(schedule.clone()).await;
+    Ok(HttpResponse::Created().json(schedule.redacted()))
+}
+
+#[utoipa::path(tag = "schedules", responses((status = 200, description = "The schedules of the API key, or all schedules for an admin key", body = Vec<Schedule>)))]
+#[get("/schedules")]
+pub(crate) async fn list_schedules(scheduler: web::Data<Scheduler>, caller: Caller) -> impl Responder {
+    HttpResponse::Ok().json(
+        scheduler
+            .list()
+            .await
+            .into_iter()
+            .filter(|schedule| caller.owns(schedule.request.caller.as_deref()))
+            .map(Schedule::redacted)
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[utoipa::path(
+    tag = "schedules",
+    params(("id" = String, Path, description = "The schedule id")),
+    responses(
+        (status = 200, description = "The schedule", body = Schedule),
+        (status = 404, description = "The schedule does not exist"),
+    )
+)]
+#[get("/schedules/{id}")]
+pub(crate) async fn get_schedule(
+    scheduler: web::Data<Scheduler>,
+    caller: Caller,
+    id: web::Path<String>,
+) -> impl Responder {
+    match scheduler.get(&id).await {
+        Some(schedule) if caller.owns(schedule.request.caller.as_deref()) => {
+            HttpResponse::Ok().json(schedule.redacted())
+        }
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[utoipa::path(
+    tag = "schedules",
+    params(("id" = String, Path, description = "The schedule id")),
+    responses(
+        (status = 204, description = "The schedule is deleted"),
+        (status = 404, description = "The schedule does not exist"),
+    )
+)]
+#[delete("/schedules/{id}")]
+pub(crate) async fn delete_schedule(
+    scheduler: web::Data<Scheduler>,
+    caller: Caller,
+    id: web::Path<String>,
+) -> impl Responder {
+    let owned = scheduler
+        .get(&id)
+        .await
+        .is_some_and(|schedule| caller.owns(schedule.request.caller.as_deref()));
+    if owned && scheduler.remove(&id).await {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[utoipa::path(
+    tag = "schedules",
+    params(("id" = String, Path, description = "The schedule id")),
+    responses(
+        (status = 200, description = "The latest runs of the schedule", body = Vec<ScheduleRun>),
+        (status = 404, description = "The schedule does not exist"),
+    )
+)]
+#[get("/schedules/{id}/runs")]
+pub(crate) async fn list_schedule_runs(
+    scheduler: web::Data<Scheduler>,
+    caller: Caller,
+    id: web::Path<String>,
+) -> impl Responder {
+    match scheduler.get(&id).await {
+        Some(schedule) if caller.owns(schedule.request.caller.as_deref()) => {
+            HttpResponse::Ok().json(scheduler.runs(&id).await)
+        }
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(parse_cron("0 9 * * *").is_ok());
+        assert!(parse_cron("0 0 9 * * Mon-Fri").is_ok());
+        assert!(parse_cron("every day").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_check_webhook_url() {
+        assert!(check_webhook_url("https://93.184.216.34/hooks/lumo").await.is_ok());
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://169.254.169.254/latest",
+            "ftp://93.184.216.34/",
+            "hooks",
+        ] {
+            assert!(check_webhook_url(url).await.is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_read_corrupt_store() {
+        let dir = std::env::temp_dir().join(format!("lumo-schedules-{}", nanoid::nanoid!()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("schedules.json");
+        fs::write(&path, "{\"schedules\": [").unwrap();
+
+        assert!(read_store(&path).schedules.is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("schedules.json.corrupt")).unwrap(),
+            "{\"schedules\": ["
+        );
+    }
+}
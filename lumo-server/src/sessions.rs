@@ -0,0 +1,737 @@
+//! Conversations that span several runs.
+//!
+//! A run with a `session_id` starts with the history of the session, and its task and answer are added to it when
+//! it finishes. Sessions are kept in memory, or in Redis when the server is built with the `redis` feature and
+//! `REDIS_URL` is set, so that any server instance can continue the conversation.
+//!
+//! A `/stream` with a `session_id` stays open after the `done` event of its run: the messages posted to
+//! `/sessions/{id}/message` are the next turns of the conversation, run by the same agent and streamed as the
+//! events of the same stream. The stream closes when the session is deleted, or when no message comes within the
+//! idle timeout.
+//!
+//! With auth, each API key has its own sessions: the same `session_id` used by two keys names two sessions, and a
+//! key gets a 404 for the sessions of the others.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lumo::models::types::{Message, MessageRole};
+use opentelemetry::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use crate::{
+    artifacts::JobArtifact, auth::Caller, execute_task, memories::store_scope, RunTaskRequest,
+    TaskOutput,
+};
+
+/// Number of sessions kept in memory. The least recently updated sessions are dropped.
+const MAX_SESSIONS: usize = 1000;
+
+/// How long an open session stream waits for the next message, unless `SESSION_STREAM_IDLE_SECS` is set.
+const DEFAULT_STREAM_IDLE_SECS: u64 = 600;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+    #[default]
+    Idle,
+    Running,
+}
+
+/// A file produced by a run of the session. Only its metadata is stored with the session.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ArtifactMetadata {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    pub size: usize,
+    pub run_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Session {
+    pub id: String,
+    /// The messages of the conversation, oldest first.
+    #[schema(value_type = Vec<Object>)]
+    pub history: Vec<Message>,
+    pub state: SessionState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run_id: Option<String>,
+    /// The error of the last run, if it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<ArtifactMetadata>,
+    /// The memory of the agent at the end of the last successful run, served by `/sessions/{id}/memory`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub memory: Vec<Message>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: id.to_string(),
+            history: Vec::new(),
+            state: SessionState::Idle,
+            last_run_id: None,
+            last_error: None,
+            artifacts: Vec::new(),
+            memory: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn start_run(&mut self, run_id: &str) {
+        self.state = SessionState::Running;
+        self.last_run_id = Some(run_id.to_string());
+        self.updated_at = Utc::now();
+    }
+
+    /// Add the task and the answer of a finished run to the history, and its artifacts to the artifacts of the
+    /// session. A failed run only records its error.
+    fn finish_run(&mut self, run_id: &str, task: &str, result: Result<&TaskOutput, String>) {
+        let message = |role, content: &str| Message {
+            role,
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        };
+        match result {
+            Ok(output) => {
+                self.history.push(message(MessageRole::User, task));
+                self.history.push(message(MessageRole::Assistant, &output.response));
+                self.last_error = None;
+                self.memory = output.memory.clone();
+                self.artifacts.extend(output.artifacts.iter().map(|artifact| {
+                    let artifact = JobArtifact::new(run_id, artifact.clone());
+                    ArtifactMetadata {
+                        name: artifact.name,
+                        mime_type: Some(artifact.mime_type),
+                        size: artifact.size,
+                        run_id: run_id.to_string(),
+                        created_at: Utc::now(),
+                    }
+                }));
+            }
+            Err(error) => self.last_error = Some(error),
+        }
+        self.state = SessionState::Idle;
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Where sessions are kept. Implementations must be shareable between the server workers.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn get(&self, id: &str) -> Result<Option<Session>>;
+    async fn save(&self, session: &Session) -> Result<()>;
+    /// Returns `false` when the session does not exist.
+    async fn delete(&self, id: &str) -> Result<bool>;
+}
+
+/// Sessions of this server instance only.
+#[derive(Default)]
+pub struct MemorySessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+#[async_trait]
+impl SessionStore for MemorySessions {
+    async fn get(&self, id: &str) -> Result<Option<Session>> {
+        Ok(self.sessions.lock().unwrap().get(id).cloned())
+    }
+
+    async fn save(&self, session: &Session) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(session.id.clone(), session.clone());
+        if sessions.len() > MAX_SESSIONS {
+            let oldest = sessions
+                .values()
+                .min_by_key(|session| session.updated_at)
+                .map(|session| session.id.clone());
+            if let Some(id) = oldest {
+                sessions.remove(&id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        Ok(self.sessions.lock().unwrap().remove(id).is_some())
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use anyhow::{Context as _, Result};
+    use async_trait::async_trait;
+    use redis::{aio::ConnectionManager, AsyncCommands};
+    use tokio::sync::OnceCell;
+
+    use super::{Session, SessionStore};
+
+    /// Sessions expire after a week without runs, unless `SESSION_TTL_SECS` is set.
+    const DEFAULT_SESSION_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+    /// Sessions shared by all the server instances using the same Redis.
+    pub struct RedisSessions {
+        client: redis::Client,
+        connection: OnceCell<ConnectionManager>,
+        ttl_secs: u64,
+    }
+
+    fn session_key(id: &str) -> String {
+        format!("lumo:sessions:{}", id)
+    }
+
+    impl RedisSessions {
+        pub fn new(url: &str) -> Result<Self> {
+            Ok(Self {
+                client: redis::Client::open(url).context("Invalid REDIS_URL")?,
+                connection: OnceCell::new(),
+                ttl_secs: std::env::var("SESSION_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_SESSION_TTL_SECS),
+            })
+        }
+
+        async fn connection(&self) -> Result<ConnectionManager> {
+            let connection = self
+                .connection
+                .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+                .await
+                .context("Failed to connect to Redis")?;
+            Ok(connection.clone())
+        }
+    }
+
+    #[async_trait]
+    impl SessionStore for RedisSessions {
+        async fn get(&self, id: &str) -> Result<Option<Session>> {
+            let session: Option<String> = self.connection().await?.get(session_key(id)).await?;
+            session
+                .map(|session| serde_json::from_str(&session).context("Failed to parse session"))
+                .transpose()
+        }
+
+        async fn save(&self, session: &Session) -> Result<()> {
+            let _: () = self
+                .connection()
+                .await?
+                .set_ex(
+                    session_key(&session.id),
+                    serde_json::to_string(session)?,
+                    self.ttl_secs,
+                )
+                .await?;
+            Ok(())
+        }
+
+        async fn delete(&self, id: &str) -> Result<bool> {
+            let deleted: usize = self.connection().await?.del(session_key(id)).await?;
+            Ok(deleted > 0)
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use redis_backend::RedisSessions;
+
+/// The sessions are stored in the Redis instance at `REDIS_URL` with the `redis` feature, and in memory otherwise.
+pub fn from_env() -> Result<Arc<dyn SessionStore>> {
+    #[cfg(feature = "redis")]
+    if let Ok(url) = std::env::var("REDIS_URL") {
+        return Ok(Arc::new(RedisSessions::new(&url)?));
+    }
+    Ok(Arc::new(MemorySessions::default()))
+}
+
+/// The sessions with an open `/stream`, by session id, and the messages queued for their next turns.
+#[derive(Clone)]
+pub struct SessionInboxes {
+    inboxes: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>,
+    store: Arc<dyn SessionStore>,
+    idle_timeout: Duration,
+}
+
+impl SessionInboxes {
+    pub fn new(store: Arc<dyn SessionStore>, idle_timeout: Duration) -> Self {
+        Self {
+            inboxes: Arc::new(Mutex::new(HashMap::new())),
+            store,
+            idle_timeout,
+        }
+    }
+
+    /// The idle timeout is read from `SESSION_STREAM_IDLE_SECS` (default: 600).
+    pub fn from_env(store: Arc<dyn SessionStore>) -> Self {
+        let seconds = std::env::var("SESSION_STREAM_IDLE_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_STREAM_IDLE_SECS);
+        Self::new(store, Duration::from_secs(seconds))
+    }
+
+    /// Open the stream of the session. Returns `None` when the session already has an open stream.
+    pub fn open(&self, session_id: &str) -> Option<SessionStream> {
+        let mut inboxes = self.inboxes.lock().unwrap();
+        if inboxes.get(session_id).is_some_and(|tx| !tx.is_closed()) {
+            return None;
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        inboxes.insert(session_id.to_string(), tx);
+        Some(SessionStream {
+            session_id: session_id.to_string(),
+            inboxes: self.clone(),
+            rx,
+        })
+    }
+
+    /// Queue the message as the next turn of the open stream of the session. Returns `false` when the session has
+    /// no open stream.
+    pub fn send(&self, session_id: &str, message: String) -> bool {
+        match self.inboxes.lock().unwrap().get(session_id) {
+            Some(tx) => tx.send(message).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Close the stream of the session after its current turn. Returns `false` when it has no open stream.
+    pub fn close(&self, session_id: &str) -> bool {
+        self.inboxes.lock().unwrap().remove(session_id).is_some()
+    }
+}
+
+/// The open stream of a session: the messages of its next turns, and the session the turns are added to.
+pub struct SessionStream {
+    session_id: String,
+    inboxes: SessionInboxes,
+    rx: mpsc::UnboundedReceiver<String>,
+}
+
+impl SessionStream {
+    /// The history of the session, oldest first.
+    pub(crate) async fn history(&self) -> Result<Vec<Message>> {
+        Ok(self
+            .inboxes
+            .store
+            .get(&self.session_id)
+            .await?
+            .map(|session| session.history)
+            .unwrap_or_default())
+    }
+
+    /// The next message of the user, or `None` when the session is closed or no message came in time.
+    pub async fn next_message(&mut self) -> Option<String> {
+        tokio::time::timeout(self.inboxes.idle_timeout, self.rx.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    pub(crate) async fn start_turn(&self, run_id: &str) {
+        let result = async {
+            let mut session = self.session().await?;
+            session.start_run(run_id);
+            self.inboxes.store.save(&session).await
+        };
+        if let Err(e) = result.await {
+            log::warn!("Failed to update session {}: {}", self.session_id, e);
+        }
+    }
+
+    /// Add the message and the answer of the turn to the session.
+    pub(crate) async fn finish_turn(&self, run_id: &str, task: &str, result: Result<&TaskOutput, String>) {
+        let result = async {
+            let mut session = self.session().await?;
+            session.finish_run(run_id, task, result);
+            self.inboxes.store.save(&session).await
+        };
+        if let Err(e) = result.await {
+            log::warn!("Failed to update session {}: {}", self.session_id, e);
+        }
+    }
+
+    async fn session(&self) -> Result<Session> {
+        Ok(self
+            .inboxes
+            .store
+            .get(&self.session_id)
+            .await?
+            .unwrap_or_else(|| Session::new(&self.session_id)))
+    }
+}
+
+impl Drop for SessionStream {
+    fn drop(&mut self) {
+        // Only remove the inbox of this stream, the session may have been closed and opened again.
+        self.rx.close();
+        let mut inboxes = self.inboxes.inboxes.lock().unwrap();
+        if inboxes.get(&self.session_id).is_some_and(|tx| tx.is_closed()) {
+            inboxes.remove(&self.session_id);
+        }
+    }
+}
+
+/// The key of a session in the store and the inboxes: its id within the API key of the caller. `None` for the ids
+/// a request can not have created, which have no session.
+pub(crate) fn session_key(caller: Option<&str>, id: &str) -> Option<String> {
+    (!id.is_empty() && !id.contains('/')).then(|| store_scope(caller, id))
+}
+
+/// Run the task of the request. With a `session_id`, the run starts with the history of the session, followed by
+/// the history of the request, and its task and answer are added to the session.
+pub(crate) async fn run_in_session(
+    sessions: &dyn SessionStore,
+    request: &RunTaskRequest,
+    run_id: &str,
+    cx: &Context,
+) -> Result<TaskOutput, actix_web::Error> {
+    let Some(session_id) = &request.session_id else {
+        return execute_task(request, cx).await;
+    };
+    let session_id = &store_scope(request.caller.as_deref(), session_id);
+    let mut session = sessions
+        .get(session_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .unwrap_or_else(|| Session::new(session_id));
+    session.start_run(run_id);
+    sessions
+        .save(&session)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut request = request.clone();
+    let mut history = session.history;
+    history.extend(request.history.take().unwrap_or_default());
+    request.history = Some(history).filter(|history| !history.is_empty());
+    let result = execute_task(&request, cx).await;
+
+    // Read the session again, in case another run of the session finished in the meantime.
+    let mut session = sessions
+        .get(session_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .unwrap_or_else(|| Session::new(session_id));
+    let outcome = match &result {
+        Ok(output) => Ok(output),
+        Err(e) => Err(e.to_string()),
+    };
+    session.finish_run(run_id, &request.task, outcome);
+    sessions
+        .save(&session)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    result
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    params(("id" = String, Path, description = "The session id")),
+    responses(
+        (status = 200, description = "The session", body = Session),
+        (status = 404, description = "The session does not exist"),
+    )
+)]
+#[get("/sessions/{id}")]
+pub(crate) async fn get_session(
+    caller: Caller,
+    sessions: web::Data<dyn SessionStore>,
+    id: web::Path<String>,
+) -> Result<impl Responder, actix_web::Error> {
+    let Some(key) = session_key(caller.name(), &id) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    match sessions
+        .get(&key)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(mut session) => {
+            // The memory is served apart, it is much larger than the history.
+            session.memory.clear();
+            session.id = id.into_inner();
+            Ok(HttpResponse::Ok().json(session))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SessionMemory {
+    /// The messages the next step of the agent would send to the model: the system prompt, the tasks, and the steps
+    /// with their tool calls, observations and errors. Empty until a run of the session succeeds.
+    #[schema(value_type = Vec<Object>)]
+    messages: Vec<Message>,
+}
+
+/// The memory of the agent at the end of the last successful run of the session, to see what the model was given.
+#[utoipa::path(
+    tag = "sessions",
+    params(("id" = String, Path, description = "The session id")),
+    responses(
+        (status = 200, description = "The memory of the agent", body = SessionMemory),
+        (status = 404, description = "The session does not exist"),
+    )
+)]
+#[get("/sessions/{id}/memory")]
+pub(crate) async fn get_session_memory(
+    caller: Caller,
+    sessions: web::Data<dyn SessionStore>,
+    id: web::Path<String>,
+) -> Result<impl Responder, actix_web::Error> {
+    let Some(key) = session_key(caller.name(), &id) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    match sessions
+        .get(&key)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(session) => Ok(HttpResponse::Ok().json(SessionMemory {
+            messages: session.memory,
+        })),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    params(("id" = String, Path, description = "The session id")),
+    responses(
+        (status = 204, description = "The session is deleted"),
+        (status = 404, description = "The session does not exist"),
+    )
+)]
+#[delete("/sessions/{id}")]
+pub(crate) async fn delete_session(
+    caller: Caller,
+    sessions: web::Data<dyn SessionStore>,
+    inboxes: web::Data<SessionInboxes>,
+    id: web::Path<String>,
+) -> Result<impl Responder, actix_web::Error> {
+    let Some(key) = session_key(caller.name(), &id) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // The open stream of the session closes after its current turn.
+    let closed = inboxes.close(&key);
+    if sessions
+        .delete(&key)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        || closed
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SessionMessage {
+    /// The next message of the user.
+    message: String,
+}
+
+/// Queue the next turn of the conversation on the open `/stream` of the session.
+#[utoipa::path(
+    tag = "sessions",
+    params(("id" = String, Path, description = "The session id")),
+    request_body = SessionMessage,
+    responses(
+        (status = 202, description = "The message is queued, its run is streamed on the open stream of the session"),
+        (status = 404, description = "The session has no open stream"),
+    )
+)]
+#[post("/sessions/{id}/message")]
+pub(crate) async fn post_message(
+    caller: Caller,
+    id: web::Path<String>,
+    req: web::Json<SessionMessage>,
+    inboxes: web::Data<SessionInboxes>,
+) -> impl Responder {
+    let sent = session_key(caller.name(), &id)
+        .is_some_and(|key| inboxes.send(&key, req.into_inner().message));
+    if sent {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Session {} has no open stream", id)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lumo::agent::{Artifact, ArtifactKind};
+
+    #[actix_web::test]
+    async fn test_memory_sessions() {
+        let sessions = MemorySessions::default();
+        assert!(sessions.get("chat-1").await.unwrap().is_none());
+
+        let mut session = Session::new("chat-1");
+        session.start_run("run-1");
+        assert_eq!(session.state, SessionState::Running);
+        let output = TaskOutput {
+            response: "Paris".to_string(),
+            transcript: None,
+            artifacts: vec![Artifact {
+                name: "figure_1.png".to_string(),
+                kind: ArtifactKind::Image,
+                mime_type: "image/png".to_string(),
+                data: "cG5n".to_string(),
+                preview: None,
+            }],
+            summary: Default::default(),
+            history_trimmed: None,
+            memory: vec![
+                Message::new(MessageRole::System, "You are a helpful assistant."),
+                Message::new(MessageRole::User, "New Task: What is the capital of France?"),
+                Message::new(MessageRole::Assistant, "Paris"),
+            ],
+        };
+        session.finish_run("run-1", "What is the capital of France?", Ok(&output));
+        session.start_run("run-2");
+        session.finish_run("run-2", "And of Italy?", Err("The model failed".to_string()));
+        sessions.save(&session).await.unwrap();
+
+        let session = sessions.get("chat-1").await.unwrap().unwrap();
+        assert_eq!(session.state, SessionState::Idle);
+        assert_eq!(session.last_run_id.as_deref(), Some("run-2"));
+        assert_eq!(session.last_error.as_deref(), Some("The model failed"));
+        // The failed run is not part of the conversation.
+        let history = session.history.iter().map(|m| m.content.as_str()).collect::<Vec<_>>();
+        assert_eq!(history, vec!["What is the capital of France?", "Paris"]);
+        assert_eq!(session.artifacts.len(), 1);
+        assert_eq!(session.artifacts[0].run_id, "run-1");
+        assert_eq!(session.artifacts[0].size, 3);
+        // The memory is the one of the last successful run.
+        assert_eq!(session.memory.len(), 3);
+        assert_eq!(session.memory[1].content, "New Task: What is the capital of France?");
+
+        assert!(sessions.delete("chat-1").await.unwrap());
+        assert!(!sessions.delete("chat-1").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_session_owners() {
+        use actix_web::{test, App, HttpMessage};
+
+        assert_eq!(
+            session_key(Some("team-a"), "support").as_deref(),
+            Some("team-a/support")
+        );
+        assert_eq!(session_key(None, "support").as_deref(), Some("support"));
+        assert_eq!(session_key(Some("team-a"), "team-b/support"), None);
+
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessions::default());
+        store.save(&Session::new("team-a/support")).await.unwrap();
+        let inboxes = SessionInboxes::new(store.clone(), Duration::from_secs(1));
+        let _stream = inboxes.open("team-a/support").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store.clone()))
+                .app_data(web::Data::new(inboxes))
+                .service(get_session)
+                .service(get_session_memory)
+                .service(delete_session)
+                .service(post_message),
+        )
+        .await;
+        let call = |request: test::TestRequest, caller: &str| {
+            let request = request.to_request();
+            request
+                .extensions_mut()
+                .insert(crate::auth::test_key(caller, false));
+            test::call_service(&app, request)
+        };
+
+        // Another key using the same id has no session.
+        let message = serde_json::json!({ "message": "Hello" });
+        for (request, status) in [
+            (test::TestRequest::get().uri("/sessions/support"), 404),
+            (test::TestRequest::get().uri("/sessions/support/memory"), 404),
+            (
+                test::TestRequest::post()
+                    .uri("/sessions/support/message")
+                    .set_json(&message),
+                404,
+            ),
+            (test::TestRequest::delete().uri("/sessions/support"), 404),
+        ] {
+            assert_eq!(call(request, "team-b").await.status(), status);
+        }
+        let response = call(test::TestRequest::get().uri("/sessions/support"), "team-a").await;
+        assert_eq!(response.status(), 200);
+        let session: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(session["id"], "support");
+        let request = test::TestRequest::post()
+            .uri("/sessions/support/message")
+            .set_json(&message);
+        assert_eq!(call(request, "team-a").await.status(), 202);
+        let request = test::TestRequest::delete().uri("/sessions/support");
+        assert_eq!(call(request, "team-a").await.status(), 204);
+        assert!(store.get("team-a/support").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_session_stream() {
+        let store: Arc<dyn SessionStore> = Arc::new(MemorySessions::default());
+        let inboxes = SessionInboxes::new(store.clone(), Duration::from_millis(100));
+        assert!(!inboxes.send("chat-1", "Hello".to_string()));
+
+        let mut stream = inboxes.open("chat-1").unwrap();
+        assert!(inboxes.open("chat-1").is_none());
+        stream.start_turn("run-1").await;
+        assert_eq!(
+            store.get("chat-1").await.unwrap().unwrap().state,
+            SessionState::Running
+        );
+        let output = TaskOutput {
+            response: "Paris".to_string(),
+            transcript: None,
+            artifacts: vec![],
+            summary: Default::default(),
+            memory: vec![],
+            history_trimmed: None,
+        };
+        stream
+            .finish_turn("run-1", "What is the capital of France?", Ok(&output))
+            .await;
+        assert_eq!(stream.history().await.unwrap().len(), 2);
+
+        // Messages are queued while a turn runs, and the stream ends when no message comes in time.
+        assert!(inboxes.send("chat-1", "And of Italy?".to_string()));
+        assert!(inboxes.send("chat-1", "And of Spain?".to_string()));
+        assert_eq!(stream.next_message().await.as_deref(), Some("And of Italy?"));
+        assert_eq!(stream.next_message().await.as_deref(), Some("And of Spain?"));
+        assert_eq!(stream.next_message().await, None);
+
+        // Closing the session ends the stream, and a new stream can be opened once it is dropped.
+        assert!(inboxes.close("chat-1"));
+        assert_eq!(stream.next_message().await, None);
+        assert!(!inboxes.send("chat-1", "Hello".to_string()));
+        let reopened = inboxes.open("chat-1").unwrap();
+        drop(stream);
+        assert!(inboxes.send("chat-1", "Hello".to_string()));
+        drop(reopened);
+        assert!(!inboxes.send("chat-1", "Hello".to_string()));
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use tokio::sync::Notify;
+
+use crate::events::{to_data, StreamEvent};
+
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+/// The latest events of a run, after the `dropped` first ones.
+#[derive(Default)]
+struct Events {
+    kept: VecDeque<String>,
+    dropped: usize,
+}
+
+/// The events of a streamed run, kept so that a client can resume a dropped stream with `Last-Event-ID`.
+///
+/// Event ids are sequence numbers starting at 1. Only the last `max_events` events are kept; a client resuming
+/// before them gets a `reset` event, then the kept events.
+pub struct EventLog {
+    events: Mutex<Events>,
+    max_events: usize,
+    /// The name of the key that started the run, when auth is enabled.
+    owner: Option<String>,
+    finished: Mutex<bool>,
+    notify: Notify,
+}
+
+impl EventLog {
+    fn new(max_events: usize, owner: Option<&str>) -> Self {
+        Self {
+            events: Mutex::new(Events::default()),
+            max_events: max_events.max(1),
+            owner: owner.map(str::to_string),
+            finished: Mutex::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    /// The name of the key that started the run, when auth is enabled.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    fn push(&self, data: String) {
+        {
+            let mut events = self.events.lock().unwrap();
+            events.kept.push_back(data);
+            if events.kept.len() > self.max_events {
+                events.kept.pop_front();
+                events.dropped += 1;
+            }
+        }
+        self.notify.notify_waiters();
+    }
+
+    fn finish(&self) {
+        *self.finished.lock().unwrap() = true;
+        self.notify.notify_waiters();
+    }
+
+    /// The events after `last_event_id` formatted for SSE, preceded by a `reset` event when some of them are no
+    /// longer kept, the id of the last one, and whether the run is finished.
+    fn read_after(&self, last_event_id: usize) -> (Vec<Bytes>, usize, bool) {
+        let finished = *self.finished.lock().unwrap();
+        let events = self.events.lock().unwrap();
+        let mut chunks = Vec::new();
+        let mut last_event_id = last_event_id;
+        if last_event_id < events.dropped {
+            last_event_id = events.dropped;
+            let reset = to_data(StreamEvent::Reset { last_event_id });
+            chunks.push(Bytes::from(format!("id: {}\ndata: {}\n\n", last_event_id, reset)));
+        }
+        for data in events.kept.iter().skip(last_event_id - events.dropped) {
+            last_event_id += 1;
+            chunks.push(Bytes::from(format!("id: {}\ndata: {}\n\n", last_event_id, data)));
+        }
+        (chunks, last_event_id, finished)
+    }
+}
+
+/// Runs streams in the background and keeps their events, so that a run continues when the client disconnects.
+#[derive(Clone)]
+pub struct StreamRegistry {
+    logs: Arc<Mutex<HashMap<String, Arc<EventLog>>>>,
+    heartbeat: Duration,
+    retention: Duration,
+    max_events: usize,
+}
+
+impl StreamRegistry {
+    pub fn new(heartbeat: Duration, retention: Duration) -> Self {
+        Self {
+            logs: Arc::new(Mutex::new(HashMap::new())),
+            heartbeat,
+            retention,
+            max_events: 10_000,
+        }
+    }
+
+    /// How many of the latest events of each run are kept for resuming (default 10000).
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        self.max_events = max_events;
+        self
+    }
+
+    /// Configured with `SSE_HEARTBEAT_SECS` (default 15), the interval of the keep-alive comments sent while no
+    /// events are produced, `SSE_RETENTION_SECS` (default 300), how long the events of a finished run can be
+    /// resumed, and `SSE_MAX_EVENTS` (default 10000), how many of the latest events of a run are kept.
+    pub fn from_env() -> Self {
+        let seconds = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(default))
+        };
+        let registry = Self::new(
+            seconds("SSE_HEARTBEAT_SECS", 15),
+            seconds("SSE_RETENTION_SECS", 300),
+        );
+        match std::env::var("SSE_MAX_EVENTS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+        {
+            Some(max_events) => registry.with_max_events(max_events),
+            None => registry,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<EventLog>> {
+        self.logs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Drive the `events` of the run of the `owner` key to completion in the background. Returns `None` if a stream
+    /// with this id already exists.
+    pub fn start(
+        &self,
+        id: &str,
+        owner: Option<&str>,
+        mut events: Pin<Box<dyn Stream<Item = String>>>,
+    ) -> Option<Arc<EventLog>> {
+        let log = {
+            let mut logs = self.logs.lock().unwrap();
+            if logs.contains_key(id) {
+                return None;
+            }
+            let log = Arc::new(EventLog::new(self.max_events, owner));
+            logs.insert(id.to_string(), log.clone());
+            log
+        };
+
+        let registry = self.clone();
+        let id = id.to_string();
+        let event_log = log.clone();
+        actix_web::rt::spawn(async move {
+            while let Some(data) = events.next().await {
+                event_log.push(data);
+            }
+            event_log.finish();
+            actix_web::rt::time::sleep(registry.retention).await;
+            registry.logs.lock().unwrap().remove(&id);
+        });
+        Some(log)
+    }
+
+    /// Stream the events after `last_event_id`, then the new events until the run finishes, with keep-alive
+    /// comments in between.
+    pub fn subscribe(
+        &self,
+        log: Arc<EventLog>,
+        last_event_id: usize,
+    ) -> impl Stream<Item = Result<Bytes, std::io::Error>> + 'static {
+        let heartbeat = self.heartbeat;
+        async_stream::stream! {
+            let mut next = last_event_id;
+            loop {
+                // Register for notifications before reading, so an event pushed in between is not missed.
+                let notified = log.notify.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+
+                let (chunks, last_event_id, finished) = log.read_after(next);
+                next = last_event_id;
+                for chunk in chunks {
+                    yield Ok(chunk);
+                }
+                if finished {
+                    break;
+                }
+                if actix_web::rt::time::timeout(heartbeat, notified).await.is_err() {
+                    yield Ok(Bytes::from_static(KEEP_ALIVE));
+                }
+            }
+        }
+    }
+}
+
+/// Parse the `Last-Event-ID` header sent by a reconnecting client.
+pub fn last_event_id(req: &actix_web::HttpRequest) -> usize {
+    req.headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_resume_stream() {
+        let registry = StreamRegistry::new(Duration::from_millis(50), Duration::from_secs(1));
+        let (tx, rx) = futures::channel::mpsc::unbounded::<String>();
+        let log = registry.start("run", None, rx.boxed_local()).unwrap();
+        assert!(registry.start("run", None, futures::stream::empty().boxed_local()).is_none());
+
+        tx.unbounded_send("\"first\"".to_string()).unwrap();
+        tx.unbounded_send("\"second\"".to_string()).unwrap();
+
+        // A client that received the first event resumes from the second, then gets a keep-alive while idle.
+        let mut stream = Box::pin(registry.subscribe(log, 1));
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            Bytes::from("id: 2\ndata: \"second\"\n\n")
+        );
+        assert_eq!(stream.next().await.unwrap().unwrap(), Bytes::from_static(KEEP_ALIVE));
+
+        tx.unbounded_send("\"third\"".to_string()).unwrap();
+        drop(tx);
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            Bytes::from("id: 3\ndata: \"third\"\n\n")
+        );
+        assert!(stream.next().await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_resume_dropped_events() {
+        let registry = StreamRegistry::new(Duration::from_millis(50), Duration::from_secs(1)).with_max_events(2);
+        let events = ["\"first\"", "\"second\"", "\"third\""].map(str::to_string);
+        let log = registry
+            .start("run", Some("team-a"), futures::stream::iter(events).boxed_local())
+            .unwrap();
+        assert_eq!(log.owner(), Some("team-a"));
+        actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+
+        // The first event is no longer kept, a client that did not receive it is told so.
+        let stream = registry.subscribe(log, 0);
+        let chunks = stream.map(Result::unwrap).collect::<Vec<_>>().await;
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from(format!("id: 1\ndata: {}\n\n", to_data(StreamEvent::Reset { last_event_id: 1 }))),
+                Bytes::from("id: 2\ndata: \"second\"\n\n"),
+                Bytes::from("id: 3\ndata: \"third\"\n\n"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Titles and summaries of conversations, for chat UIs listing the conversations of their users.
+
+use actix_web::{post, web, HttpResponse, Responder};
+use lumo::{
+    agent::summarize,
+    models::types::{Message, MessageRole},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    jobs::{Job, JobQueue},
+    model_builder,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SummarizeRequest {
+    /// The messages of the conversation. Either `history` or `run_id` is required.
+    #[schema(value_type = Option<Vec<Object>>)]
+    history: Option<Vec<Message>>,
+    /// The id of a job whose conversation is summarized.
+    run_id: Option<String>,
+    /// The model writing the summary. Defaults to the model of the run.
+    model: Option<String>,
+    /// The base URL of the model. Defaults to the base URL of the run.
+    base_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SummarizeResponse {
+    /// A short title of the conversation.
+    title: String,
+    /// A few bullets summarizing the conversation.
+    summary: Vec<String>,
+}
+
+/// The messages of a finished job: its transcript, or its history, task and answer when the transcript was not kept.
+pub(crate) fn job_messages(job: Job) -> Vec<Message> {
+    if let Some(transcript) = job.transcript {
+        return transcript;
+    }
+    let message = |role, content| Message {
+        role,
+        content,
+        tool_call_id: None,
+        tool_calls: None,
+    };
+    let mut messages = job.request.history.unwrap_or_default();
+    messages.push(message(MessageRole::User, job.request.task));
+    if let Some(response) = job.response {
+        messages.push(message(MessageRole::Assistant, response));
+    }
+    messages
+}
+
+/// Write a short title and a bullet summary of a conversation with the model.
+#[utoipa::path(
+    tag = "runs",
+    request_body = SummarizeRequest,
+    responses(
+        (status = 200, description = "The title and summary", body = SummarizeResponse),
+        (status = 400, description = "Neither history nor run_id is given, or the model is missing"),
+        (status = 404, description = "The run does not exist"),
+        (status = 409, description = "The run has not finished"),
+    )
+)]
+#[post("/summarize")]
+pub(crate) async fn summarize_conversation(
+    req: web::Json<SummarizeRequest>,
+    jobs: web::Data<JobQueue>,
+) -> Result<impl Responder, actix_web::Error> {
+    let req = req.into_inner();
+    let (messages, model, base_url) = match (req.history, req.run_id) {
+        (Some(history), _) => (history, req.model, req.base_url),
+        (None, Some(run_id)) => {
+            let Some(job) = jobs
+                .get(&run_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+            else {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": format!("Run {} does not exist", run_id)
+                })));
+            };
+            if !job.is_finished() {
+                return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": format!("Run {} has not finished", run_id)
+                })));
+            }
+            let model = req.model.or_else(|| Some(job.request.model.clone()));
+            let base_url = req.base_url.or_else(|| Some(job.request.base_url.clone()));
+            (job_messages(job), model, base_url)
+        }
+        (None, None) => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Either history or run_id is required",
+            ))
+        }
+    };
+    if messages.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("There are no messages to summarize"));
+    }
+    let (Some(model), Some(base_url)) = (model, base_url) else {
+        return Err(actix_web::error::ErrorBadRequest(
+            "model and base_url are required to summarize a history",
+        ));
+    };
+
+    let model = model_builder(&model, &base_url)
+        .build()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let summary = summarize(&model, &messages)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(SummarizeResponse {
+        title: summary.title,
+        summary: summary.summary,
+    }))
+}
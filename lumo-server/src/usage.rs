@@ -0,0 +1,420 @@
+//! Usage accounting of the API keys: the runs, steps, tokens and tool calls of each key, by day. A run is counted
+//! when it starts, and its steps, tokens and tool calls when it finishes, also when it fails. The usage is kept in
+//! `usage.json` in the data directory, or in `USAGE_FILE` when set, so it survives restarts.
+//!
+//! Keys with a `monthly_quota` in servers.yaml are refused new runs with `402 Payment Required` once their usage of
+//! the calendar month (UTC) reaches one of its limits.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::{error::InternalError, get, web, HttpResponse, Responder};
+use anyhow::{Context as _, Result};
+use chrono::{Datelike, NaiveDate, Utc};
+use directories::ProjectDirs;
+use lumo::agent::RunSummary;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::{Caller, KeyStore},
+    config::UsageQuota,
+};
+
+/// What the runs of a key used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    #[serde(default)]
+    pub runs: u64,
+    #[serde(default)]
+    pub steps: u64,
+    /// Estimated tokens of the messages sent to the model.
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Estimated tokens of the model outputs.
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub tool_calls: u64,
+}
+
+impl Usage {
+    /// A run that starts.
+    pub fn run() -> Self {
+        Self {
+            runs: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The steps, tokens and tool calls of a finished run. The run itself was counted when it started.
+    pub fn of_run(summary: &RunSummary) -> Self {
+        Self {
+            runs: 0,
+            steps: summary.steps as u64,
+            input_tokens: summary.input_tokens as u64,
+            output_tokens: summary.output_tokens as u64,
+            tool_calls: summary.tools.iter().map(|tool| tool.calls as u64).sum(),
+        }
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.runs += other.runs;
+        self.steps += other.steps;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.tool_calls += other.tool_calls;
+    }
+}
+
+impl UsageQuota {
+    /// The first limit the usage has reached, with the limit and the usage, e.g. `("tokens", 1000000, 1000250)`.
+    pub fn reached(&self, usage: &Usage) -> Option<(&'static str, u64, u64)> {
+        [
+            ("runs", self.runs, usage.runs),
+            ("steps", self.steps, usage.steps),
+            ("tokens", self.tokens, usage.tokens()),
+            ("tool_calls", self.tool_calls, usage.tool_calls),
+        ]
+        .into_iter()
+        .find_map(|(name, limit, used)| {
+            limit
+                .filter(|limit| used >= *limit)
+                .map(|limit| (name, limit, used))
+        })
+    }
+
+    /// The response of a run refused because the limit was reached.
+    pub(crate) fn error(name: &str, limit: u64, used: u64) -> actix_web::Error {
+        let message = format!("The monthly {} quota of the API key is reached", name);
+        let response = HttpResponse::PaymentRequired().json(json!({
+            "error": message,
+            "quota": name,
+            "limit": limit,
+            "used": used,
+        }));
+        InternalError::from_response(message, response).into()
+    }
+}
+
+/// The usage of the keys by day, shared by all the requests of the server.
+pub struct UsageLedger {
+    /// The file the usage is written to after each change. In memory only when `None`.
+    path: Option<PathBuf>,
+    keys: Mutex<BTreeMap<String, BTreeMap<NaiveDate, Usage>>>,
+    /// Held while the file is written, so the writes keep the order of the changes without blocking the readers.
+    file: Mutex<()>,
+}
+
+impl UsageLedger {
+    /// A ledger kept in memory only.
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            keys: Mutex::new(BTreeMap::new()),
+            file: Mutex::new(()),
+        }
+    }
+
+    /// The ledger of the file, empty when the file does not exist yet.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let keys = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read usage: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse usage: {:?}", path))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            keys: Mutex::new(keys),
+            file: Mutex::new(()),
+        })
+    }
+
+    /// The ledger of `USAGE_FILE`, or of `usage.json` in the data directory.
+    pub fn from_env() -> Result<Self> {
+        let path = match std::env::var("USAGE_FILE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let proj_dirs = ProjectDirs::from("com", "lumo", "lumo-server")
+                    .context("Failed to determine data directory")?;
+                fs::create_dir_all(proj_dirs.data_dir())?;
+                proj_dirs.data_dir().join("usage.json")
+            }
+        };
+        Self::load(path)
+    }
+
+    /// Add the usage to the day of the key.
+    pub fn record(&self, key: &str, date: NaiveDate, usage: &Usage) {
+        let mut keys = self.keys.lock().unwrap();
+        keys.entry(key.to_string())
+            .or_default()
+            .entry(date)
+            .or_default()
+            .add(usage);
+        let Some(path) = &self.path else {
+            return;
+        };
+        let content = serde_json::to_string(&*keys);
+        let _file = self.file.lock().unwrap();
+        drop(keys);
+        let result = content
+            .map_err(anyhow::Error::from)
+            .and_then(|content| save(path, &content));
+        if let Err(e) = result {
+            log::error!("Failed to save usage to {:?}: {:#}", path, e);
+        }
+    }
+
+    /// The usage of the key by day, from `from` to `to` included.
+    pub fn days(&self, key: &str, from: NaiveDate, to: NaiveDate) -> Vec<(NaiveDate, Usage)> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|days| {
+                days.range(from..=to)
+                    .map(|(date, usage)| (*date, *usage))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The usage of the key in the month of `date`, up to `date`.
+    pub fn month(&self, key: &str, date: NaiveDate) -> Usage {
+        total(&self.days(key, first_of_month(date), date))
+    }
+}
+
+impl Default for UsageLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write the usage next to the file and rename it, so a crash while writing keeps the previous usage.
+fn save(path: &Path, content: &str) -> Result<()> {
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write usage: {:?}", partial))?;
+    fs::rename(&partial, path).with_context(|| format!("Failed to write usage: {:?}", path))
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+fn total(days: &[(NaiveDate, Usage)]) -> Usage {
+    let mut total = Usage::default();
+    for (_, usage) in days {
+        total.add(usage);
+    }
+    total
+}
+
+static USAGE: OnceLock<UsageLedger> = OnceLock::new();
+
+/// Load the usage file of the server when it starts. A file that can not be read fails the start rather than
+/// counting the usage, and the quotas, from zero again.
+pub(crate) fn init_usage_ledger() -> Result<()> {
+    let _ = USAGE.set(UsageLedger::from_env()?);
+    Ok(())
+}
+
+/// The usage of the keys of the server. Kept in memory when the server did not load the usage file, e.g. in the
+/// tests.
+pub(crate) fn usage_ledger() -> &'static UsageLedger {
+    USAGE.get_or_init(UsageLedger::new)
+}
+
+/// Add the usage to today's usage of the key of the run. Runs without a key, when auth is disabled, are not counted.
+pub(crate) fn record_usage(caller: Option<&str>, usage: &Usage) {
+    if let Some(caller) = caller {
+        usage_ledger().record(caller, Utc::now().date_naive(), usage);
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct UsageQuery {
+    /// The first day, e.g. `2025-06-01`. The first day of the current month by default.
+    from: Option<NaiveDate>,
+    /// The last day, included. Today by default.
+    to: Option<NaiveDate>,
+    /// Only this key. Admin keys see all the keys, other keys only themselves.
+    key: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DayUsage {
+    date: NaiveDate,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct KeyUsageReport {
+    name: String,
+    /// The usage from `from` to `to`.
+    total: Usage,
+    /// The days of the range the key was used.
+    days: Vec<DayUsage>,
+    /// The usage of the current month, counted against the quota.
+    month: Usage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    monthly_quota: Option<UsageQuota>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct UsageReport {
+    from: NaiveDate,
+    to: NaiveDate,
+    keys: Vec<KeyUsageReport>,
+}
+
+/// The runs, steps, tokens and tool calls of the API keys by day. Needs auth.
+#[utoipa::path(
+    tag = "admin",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "The usage of the keys", body = UsageReport),
+        (status = 400, description = "`from` is after `to`"),
+        (status = 403, description = "Auth is disabled, or a key that is not an admin key asked for another key"),
+    )
+)]
+#[get("/usage")]
+pub(crate) async fn get_usage(
+    caller: Caller,
+    keys: web::Data<KeyStore>,
+    query: web::Query<UsageQuery>,
+) -> Result<impl Responder, actix_web::Error> {
+    let Some(key) = caller.key() else {
+        return Err(actix_web::error::ErrorForbidden(
+            "An API key is required, with ENABLE_AUTH=true",
+        ));
+    };
+    let today = Utc::now().date_naive();
+    let from = query.from.unwrap_or_else(|| first_of_month(today));
+    let to = query.to.unwrap_or(today);
+    if from > to {
+        return Err(actix_web::error::ErrorBadRequest("'from' is after 'to'"));
+    }
+    let names = match (&query.key, key.is_admin()) {
+        (Some(name), false) if *name != key.name => {
+            return Err(actix_web::error::ErrorForbidden(
+                "Only admin keys may see the usage of other keys",
+            ))
+        }
+        (Some(name), _) => vec![name.clone()],
+        (None, true) => keys.names(),
+        (None, false) => vec![key.name.clone()],
+    };
+    let ledger = usage_ledger();
+    let reports = names
+        .into_iter()
+        .map(|name| {
+            let days = ledger.days(&name, from, to);
+            KeyUsageReport {
+                total: total(&days),
+                days: days
+                    .into_iter()
+                    .map(|(date, usage)| DayUsage { date, usage })
+                    .collect(),
+                month: ledger.month(&name, today),
+                monthly_quota: keys.get(&name).and_then(|key| key.monthly_quota().cloned()),
+                name,
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(UsageReport {
+        from,
+        to,
+        keys: reports,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: &str) -> NaiveDate {
+        day.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ledger() {
+        let path = std::env::temp_dir().join(format!("lumo-usage-{}.json", nanoid::nanoid!()));
+        let ledger = UsageLedger::load(path.clone()).unwrap();
+        let run = Usage {
+            runs: 1,
+            ..Default::default()
+        };
+        let finished = Usage {
+            steps: 3,
+            input_tokens: 1000,
+            output_tokens: 200,
+            tool_calls: 2,
+            ..Default::default()
+        };
+        ledger.record("tenant", date("2025-05-31"), &run);
+        ledger.record("tenant", date("2025-06-01"), &run);
+        ledger.record("tenant", date("2025-06-01"), &finished);
+        ledger.record("tenant", date("2025-06-02"), &run);
+        ledger.record("other", date("2025-06-01"), &run);
+
+        // Persisted after each change, without a partial file left behind.
+        assert!(!path.with_extension("json.partial").exists());
+        let ledger = UsageLedger::load(path.clone()).unwrap();
+
+        // A corrupt file is an error rather than an empty ledger.
+        std::fs::write(&path, "{\"tenant\": ").unwrap();
+        assert!(UsageLedger::load(path.clone()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let days = ledger.days("tenant", date("2025-06-01"), date("2025-06-01"));
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].1.runs, 1);
+        assert_eq!(days[0].1.tokens(), 1200);
+
+        let month = ledger.month("tenant", date("2025-06-15"));
+        assert_eq!((month.runs, month.steps, month.tool_calls), (2, 3, 2));
+        assert_eq!(
+            ledger.month("unknown", date("2025-06-15")),
+            Usage::default()
+        );
+    }
+
+    #[test]
+    fn test_quota() {
+        let quota = UsageQuota {
+            runs: Some(100),
+            tokens: Some(1000),
+            ..Default::default()
+        };
+        let usage = Usage {
+            runs: 10,
+            input_tokens: 900,
+            ..Default::default()
+        };
+        assert_eq!(quota.reached(&usage), None);
+        let usage = Usage {
+            output_tokens: 100,
+            ..usage
+        };
+        assert_eq!(quota.reached(&usage), Some(("tokens", 1000, 1000)));
+
+        let response = UsageQuota::error("tokens", 1000, 1000).error_response();
+        assert_eq!(
+            response.status(),
+            actix_web::http::StatusCode::PAYMENT_REQUIRED
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Validation of the run requests before they are queued or run. All the problems of a request are reported at once,
+//! by field, in a 422 response:
+//!
+//! ```json
+//! {
+//!   "error": "Invalid request",
+//!   "errors": [
+//!     {"field": "tools", "message": "Unknown tools: Bing. Available tools: DuckDuckGo, VisitWebsite, ..."},
+//!     {"field": "max_steps", "message": "max_steps must be between 1 and 100, got 0"}
+//!   ]
+//! }
+//! ```
+
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+
+/// Steps a run may take at most.
+pub const MAX_STEPS: usize = 100;
+
+/// A problem with a field of the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// The field, e.g. `tools` or `tool_config.ExaSearchTool`.
+    pub field: String,
+    pub message: String,
+}
+
+/// The problems of a request, sent as a 422 response.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The fields with a problem.
+    pub fn fields(&self) -> Vec<&str> {
+        self.errors
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect()
+    }
+
+    /// `Ok` when no problem was found.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid request")?;
+        for (i, error) in self.errors.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{}{}: {}", separator, error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for ValidationErrors {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::UnprocessableEntity().json(json!({
+            "error": "Invalid request",
+            "errors": self.errors,
+        }))
+    }
+}
+
+/// The message of an unknown value, with the values that are available.
+pub(crate) fn unknown(kind: &str, names: &[&str], available: &[&str]) -> String {
+    format!(
+        "Unknown {}: {}. Available {}: {}",
+        kind,
+        names.join(", "),
+        kind,
+        if available.is_empty() {
+            "none".to_string()
+        } else {
+            available.join(", ")
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+
+    #[test]
+    fn test_validation_errors() {
+        let mut errors = ValidationErrors::default();
+        assert!(errors.clone().into_result().is_ok());
+
+        errors.add(
+            "tools",
+            unknown("tools", &["Bing", "Yahoo"], &["DuckDuckGo"]),
+        );
+        errors.add("max_steps", "max_steps must be between 1 and 100, got 0");
+        assert_eq!(errors.fields(), ["tools", "max_steps"]);
+        assert_eq!(
+            errors.to_string(),
+            "Invalid request: tools: Unknown tools: Bing, Yahoo. Available tools: DuckDuckGo; max_steps: max_steps must be between 1 and 100, got 0"
+        );
+
+        let response = errors.error_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.into_body().try_into_bytes().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "Invalid request");
+        assert_eq!(body["errors"][0]["field"], "tools");
+        assert_eq!(body["errors"][1]["field"], "max_steps");
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let request = |fields: serde_json::Value| -> crate::RunTaskRequest {
+            let mut request = json!({
+                "task": "What is the capital of France?",
+                "model": "gpt-4o-mini",
+                "base_url": "https://api.openai.com/v1/chat/completions",
+            });
+            request
+                .as_object_mut()
+                .unwrap()
+                .extend(fields.as_object().unwrap().clone());
+            serde_json::from_value(request).unwrap()
+        };
+        request(json!({"tools": ["DuckDuckGo", "VisitWebsite"], "max_steps": 5}))
+            .validate()
+            .unwrap();
+
+        // All the problems are reported at once.
+        let errors = request(json!({
+            "tools": ["DuckDuckGo", "Bing", "Yahoo"],
+            "agent_type": "planner",
+            "max_steps": 0,
+            "tool_config": {"Bing": {}, "DuckDuckGo": {"api_key": "secret"}},
+        }))
+        .validate()
+        .unwrap_err();
+        assert_eq!(
+            errors.fields(),
+            [
+                "agent_type",
+                "tools",
+                "max_steps",
+                "tool_config.Bing",
+                "tool_config.DuckDuckGo"
+            ]
+        );
+        assert!(errors.errors[0]
+            .message
+            .starts_with("Unknown agent types: planner. Available agent types: function-calling"));
+        assert!(errors.errors[1]
+            .message
+            .starts_with("Unknown tools: Bing, Yahoo. Available tools: AskUser, DateTime, DuckDuckGo"));
+        assert!(errors.errors[2].message.contains("between 1 and 100"));
+
+        let errors = request(json!({"max_steps": MAX_STEPS + 1, "preset": "astronaut"}))
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.fields(), ["preset", "max_steps"]);
+
+        let errors = request(json!({
+            "time_zone": "Mars/Olympus",
+            "tools": ["DateTime"],
+            "tool_config": {"DateTime": {"time_zone": "UTC+20"}},
+        }))
+        .validate()
+        .unwrap_err();
+        assert_eq!(errors.fields(), ["time_zone", "tool_config.DateTime"]);
+        request(json!({"time_zone": "Asia/Tokyo", "tools": ["DateTime"]}))
+            .validate()
+            .unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+//! Benchmarks of the content pipeline of the web tools, on a generated page with menus, an article, a data table and
+//! a footer. Run them with `cargo bench -p lumo --bench web_content`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lumo::tools::{html_to_text, readable_content, remove_boilerplate, WebContentPipeline};
+
+/// A page of about 60 KB, the size of a news article or a documentation page.
+fn page() -> String {
+    let mut html = String::from(
+        "<html><head><title>Benchmark</title><style>p { margin: 0 }</style></head><body>\
+         <nav><a href=\"/\">Home</a> <a href=\"/docs\">Docs</a> <a href=\"/blog\">Blog</a></nav>\
+         <p>We use cookies to improve your experience.</p>",
+    );
+    html.push_str("<div id=\"sidebar\">");
+    for i in 0..30 {
+        html.push_str(&format!(
+            "<p><a href=\"/post/{i}\">A related post with a long title {i}</a></p>"
+        ));
+    }
+    html.push_str("</div><article><h1>The article</h1>");
+    for i in 0..200 {
+        html.push_str(&format!(
+            "<p>Paragraph {i} of the article, long enough to be scored as content by readability. \
+             It links to <a href=\"/ref/{i}\">a reference</a> and has <b>some</b> <i>inline</i> markup.</p>"
+        ));
+    }
+    html.push_str("<table><tr><th>Name</th><th>Value</th></tr>");
+    for i in 0..50 {
+        html.push_str(&format!("<tr><td>Row {i}</td><td>{}</td></tr>", i * 7));
+    }
+    html.push_str("</table></article><footer>© 2025 Lumo. All rights reserved.</footer>");
+    html.push_str("<script>track()</script></body></html>");
+    html
+}
+
+fn bench_stages(c: &mut Criterion) {
+    let html = page();
+    let text = html_to_text(&html);
+    c.bench_function("readability", |b| {
+        b.iter(|| readable_content(black_box(&html)))
+    });
+    c.bench_function("html_to_text", |b| {
+        b.iter(|| html_to_text(black_box(&html)))
+    });
+    c.bench_function("remove_boilerplate", |b| {
+        b.iter(|| remove_boilerplate(black_box(&text)))
+    });
+}
+
+fn bench_pipelines(c: &mut Criterion) {
+    let html = page();
+    let pipelines = [
+        ("markdown", WebContentPipeline::new()),
+        (
+            "readable_markdown",
+            WebContentPipeline::new().with_readability(true),
+        ),
+        (
+            "readable_text",
+            WebContentPipeline::new()
+                .with_readability(true)
+                .with_markdown(false),
+        ),
+        (
+            "capped_text",
+            WebContentPipeline::new()
+                .with_markdown(false)
+                .with_max_length(Some(2_000)),
+        ),
+    ];
+    let mut group = c.benchmark_group("pipeline");
+    for (name, pipeline) in pipelines {
+        group.bench_function(name, |b| b.iter(|| pipeline.extract(black_box(&html))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stages, bench_pipelines);
+criterion_main!(benches);
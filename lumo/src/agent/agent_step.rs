@@ -1,11 +1,17 @@
-use serde::Serialize;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    agent::{AnswerValidation, SynthesisTrigger},
     errors::AgentError,
-    models::{openai::ToolCall, types::Message},
+    models::{openai::ToolCall, tokenizer::TokenCounter, types::Message},
 };
 
-#[derive(Debug, Serialize, Clone)]
+// Action steps are by far the most common steps, boxing them would not save memory.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Step {
     PlanningStep(String, String),
     TaskStep(String),
@@ -28,16 +34,163 @@ impl std::fmt::Display for Step {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
+/// How long a tool call of a step took.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallTiming {
+    pub tool_call_id: Option<String>,
+    pub name: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    /// A figure, as PNG.
+    Image,
+    /// A dataframe, as CSV.
+    Table,
+    /// Speech, e.g. a spoken answer.
+    Audio,
+}
+
+/// A result of executed code that does not fit in its printed output, like a matplotlib figure or a dataframe.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub name: String,
+    pub kind: ArtifactKind,
+    pub mime_type: String,
+    /// The PNG encoded in base64 for an image, the CSV for a table, the audio file encoded in base64 for audio.
+    pub data: String,
+    /// A markdown preview of a table.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+impl Artifact {
+    /// A short description of the artifact for the model, with the preview of a table.
+    pub fn describe(&self) -> String {
+        match (&self.kind, &self.preview) {
+            (ArtifactKind::Table, Some(preview)) => format!("[Table {}]\n{}", self.name, preview),
+            (ArtifactKind::Table, None) => format!("[Table {}]", self.name),
+            (ArtifactKind::Image, _) => format!("[Image {}]", self.name),
+            (ArtifactKind::Audio, _) => format!("[Audio {}]", self.name),
+        }
+    }
+}
+
+/// The result of a tool call, or the output of a step without tool calls, e.g. the printed output of code or a
+/// final answer given as text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(from = "ObservationRepr")]
+pub struct ToolObservation {
+    /// The id of the tool call it answers. `None` for the output of a step without tool calls, and for the calls of
+    /// models that don't give ids, which are answered in the order of the calls.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    pub content: String,
+    /// The tool call failed, and `content` is its error.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl ToolObservation {
+    /// An observation that answers no tool call.
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: None,
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    /// The result of the tool call.
+    pub fn success(tool_call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: tool_call.id.clone().filter(|id| !id.is_empty()),
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    /// The error of the tool call.
+    pub fn error(tool_call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            is_error: true,
+            ..Self::success(tool_call, content)
+        }
+    }
+}
+
+/// Observations were stored as plain strings, which are still read from older checkpoints and logs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ObservationRepr {
+    Text(String),
+    Observation {
+        #[serde(default)]
+        tool_call_id: Option<String>,
+        content: String,
+        #[serde(default)]
+        is_error: bool,
+    },
+}
+
+impl From<ObservationRepr> for ToolObservation {
+    fn from(repr: ObservationRepr) -> Self {
+        match repr {
+            ObservationRepr::Text(content) => Self::new(content),
+            ObservationRepr::Observation {
+                tool_call_id,
+                content,
+                is_error,
+            } => Self {
+                tool_call_id,
+                content,
+                is_error,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AgentStep {
     pub agent_memory: Option<Vec<Message>>,
     pub llm_output: Option<String>,
+    /// The reasoning of a reasoning model, kept out of `llm_output` and so out of the replayed memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
     pub tool_call: Option<Vec<ToolCall>>,
     pub error: Option<AgentError>,
-    pub observations: Option<Vec<String>>,
+    /// One observation per tool call, paired with the calls by id. A step without tool calls has its output here.
+    pub observations: Option<Vec<ToolObservation>>,
     pub final_answer: Option<String>,
     pub step: usize,
     pub task: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    /// Time from the start of the step to its end, model call and tool calls included.
+    pub duration_ms: Option<u64>,
+    /// Time the model took to answer.
+    pub model_latency_ms: Option<u64>,
+    /// Time each tool call took, in the order of the tool calls.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_timings: Vec<ToolCallTiming>,
+    /// Estimated tokens of the messages sent to the model.
+    pub input_tokens: Option<usize>,
+    /// Estimated tokens of the model output.
+    pub output_tokens: Option<usize>,
+    /// The model output was generated while the tool calls of the previous step were still running.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub speculative: bool,
+    /// The verdict on the final answer of the step, when answer validation is on. A rejected answer is not kept
+    /// in `final_answer`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Box<AnswerValidation>>,
+    /// The figures and dataframes produced by the code executed in the step.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+    /// Why the final answer of the step was written from the observations by a model call without tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synthesis: Option<SynthesisTrigger>,
 }
 
 impl AgentStep {
@@ -45,12 +198,91 @@ impl AgentStep {
         Self {
             agent_memory: None,
             llm_output: None,
+            reasoning: None,
             tool_call: None,
             error: None,
             observations: None,
             final_answer: None,
             step,
             task,
+            started_at: Some(Utc::now()),
+            duration_ms: None,
+            model_latency_ms: None,
+            tool_timings: Vec::new(),
+            input_tokens: None,
+            output_tokens: None,
+            speculative: false,
+            validation: None,
+            artifacts: Vec::new(),
+            synthesis: None,
+        }
+    }
+
+    /// Record the latency of the model call, and estimate the tokens of the agent memory and the model output.
+    pub fn record_model_call(&mut self, latency: Duration, token_counter: &dyn TokenCounter) {
+        self.model_latency_ms = Some(latency.as_millis() as u64);
+        self.input_tokens = self
+            .agent_memory
+            .as_ref()
+            .map(|memory| token_counter.count_message_tokens(memory));
+        self.output_tokens = self
+            .llm_output
+            .as_ref()
+            .map(|output| token_counter.count_tokens(output));
+    }
+
+    pub fn record_tool_call(&mut self, tool_call: &ToolCall, duration: Duration) {
+        self.tool_timings.push(ToolCallTiming {
+            tool_call_id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            duration_ms: duration.as_millis() as u64,
+        });
+    }
+
+    /// The observation of the tool call at `index` of `tool_call`, found by the id of the call. Calls without an id,
+    /// and observations read from older logs, are paired by position.
+    pub fn observation_of(&self, index: usize) -> Option<&ToolObservation> {
+        let observations = self.observations.as_ref()?;
+        let id = self
+            .tool_call
+            .as_ref()
+            .and_then(|calls| calls.get(index))
+            .and_then(|call| call.id.as_deref())
+            .filter(|id| !id.is_empty());
+        id.and_then(|id| {
+            observations
+                .iter()
+                .find(|observation| observation.tool_call_id.as_deref() == Some(id))
+        })
+        .or_else(|| {
+            observations
+                .get(index)
+                .filter(|observation| observation.tool_call_id.is_none())
+        })
+    }
+
+    /// The content of the observations, in the order they were made.
+    pub fn observation_contents(&self) -> Vec<String> {
+        self.observations
+            .iter()
+            .flatten()
+            .map(|observation| observation.content.clone())
+            .collect()
+    }
+
+    /// Set the duration of the step from its start.
+    pub fn finish(&mut self) {
+        if let Some(started_at) = self.started_at {
+            self.duration_ms = Some((Utc::now() - started_at).num_milliseconds().max(0) as u64);
+        }
+    }
+}
+
+impl Step {
+    /// Set the duration of an action step from its start.
+    pub fn finish(&mut self) {
+        if let Step::ActionStep(step) = self {
+            step.finish();
         }
     }
 }
@@ -1,16 +1,40 @@
-use super::agent_step::Step;
+use super::agent_step::{Artifact, Step};
+use super::checkpoint::{Checkpoint, CheckpointStore};
+use super::context_window::fit_to_context_window;
+use super::export::{export_run, ExportFormat};
+use super::long_term_memory::{memories_prompt, LongTermMemory};
+use super::observation_processor::ObservationProcessor;
+use super::orchestrator::Scratchpad;
+use super::reproducibility::{RunManifest, RunRecorder};
+use super::response_language::ResponseLanguage;
+use super::run_summary::RunSummary;
+use super::step_hook::StepOverrides;
+#[cfg(feature = "stream")]
+use super::step_hook::{StepDecision, StepHook};
+use super::transcript::{messages_to_steps, steps_to_messages};
 use crate::{
-    agent::agent_step::AgentStep,
+    agent::{
+        agent_step::AgentStep,
+        answer_validation::{validate_answer_recorded, AnswerValidation},
+    },
     errors::AgentError,
+    guardrails::Guardrails,
     models::{
+        cancellation::{cancellable, cancelled_error, CancellationToken},
         model_traits::Model,
         openai::Status,
         types::{Message, MessageRole},
     },
+    prompts::{ErrorKind, RetryPrompts, JSON_ANSWER_PROMPT},
+    telemetry::log_answer_validation,
+    tools::time_zone::{current_time, Zone},
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use log::info;
+use chrono::Utc;
+use log::{info, warn};
+use std::path::Path;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 
 #[cfg(feature = "stream")]
@@ -19,7 +43,8 @@ use {futures::Stream, std::pin::Pin};
 #[cfg(feature = "stream")]
 pub type StreamResult<'a, T> = Result<Pin<Box<dyn Stream<Item = Result<T>> + 'a>>>;
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait Agent: Send + Sync {
     fn name(&self) -> &'static str;
     fn get_max_steps(&self) -> usize;
@@ -41,6 +66,92 @@ pub trait Agent: Send + Sync {
     ) -> Result<Option<Step>>;
     fn description(&self) -> &'static str;
     fn model(&self) -> &dyn Model;
+    /// The guardrails checked before the final answer is returned.
+    fn guardrails(&self) -> Option<&Guardrails> {
+        None
+    }
+    /// The number of tokens the memory must fit in, response included. Falls back to the model's context window.
+    fn context_window(&self) -> Option<usize> {
+        None
+    }
+    /// Whether final answers are checked against the task before they are returned.
+    fn answer_validation(&self) -> bool {
+        false
+    }
+    /// The language of the final answers. Off when `None`.
+    fn response_language(&self) -> Option<&ResponseLanguage> {
+        None
+    }
+    /// Whether the final answers are JSON, so they can be parsed while they are streamed.
+    fn json_answer(&self) -> bool {
+        false
+    }
+    /// The time zone of the `{{current_time}}` of the system prompt. The time zone of the system when `None`.
+    fn time_zone(&self) -> Option<&Zone> {
+        None
+    }
+    /// Records the model responses and the tool calls of the runs into a manifest. Off when `None`.
+    fn recorder(&self) -> Option<&RunRecorder> {
+        None
+    }
+    /// The manifest of the last run, when the agent records its runs.
+    fn run_manifest(&self) -> Option<RunManifest> {
+        self.recorder().map(RunRecorder::manifest)
+    }
+    /// The guidance written to the memory after errors, by kind of error. The defaults when `None`.
+    fn retry_prompts(&self) -> Option<&RetryPrompts> {
+        None
+    }
+    /// Deduplicates and compresses the observations written to the memory.
+    fn observation_processor(&self) -> Option<&ObservationProcessor> {
+        None
+    }
+    /// Cancels the model requests and the next steps of the runs. Off when `None`.
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        None
+    }
+    /// Replace the cancellation token, e.g. with a new one for each run of a pooled agent.
+    fn set_cancellation(&mut self, _cancellation: Option<CancellationToken>) {}
+    /// Fails with `AgentError::Cancelled`, after sending `Status::Cancelled` to `tx`, when the run was cancelled.
+    fn check_cancelled(&self, tx: Option<&broadcast::Sender<Status>>) -> Result<(), AgentError> {
+        match self.cancellation() {
+            Some(token) if token.is_cancelled() => Err(cancelled_error(tx)),
+            _ => Ok(()),
+        }
+    }
+    /// Recalls the memories of earlier runs into the system prompt and remembers the facts of the runs. Off when
+    /// `None`.
+    fn long_term_memory(&self) -> Option<&LongTermMemory> {
+        None
+    }
+    /// Replace the long-term memory, e.g. with the one of the user of each run of a pooled agent.
+    fn set_long_term_memory(&mut self, _long_term_memory: Option<LongTermMemory>) {}
+    /// The notes shared with the workers of an orchestrator, cleared when a run starts with `reset = true`.
+    fn scratchpad(&self) -> Option<&Scratchpad> {
+        None
+    }
+    /// Changes to the next step, e.g. from the `StepHook` of `stream_run_with_hook`.
+    fn set_step_overrides(&mut self, _overrides: StepOverrides) {}
+    /// Clear the state kept between runs besides the logs, such as the Python session of a code agent.
+    /// Called when a run starts with `reset = true`.
+    fn reset_session(&mut self) {}
+    /// Release what the agent holds outside of memory, such as the Python session of a code agent, the MCP servers of
+    /// an MCP agent and the same for its managed agents. The agent can not call its MCP tools afterwards. Calling it
+    /// again does nothing.
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        Ok(())
+    }
+    /// Where the state of the agent is saved after each step. Off when `None`.
+    fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        None
+    }
+    /// The id the checkpoints of the agent are saved under. Generated by the first checkpoint when not set.
+    fn checkpoint_id(&self) -> Option<&str> {
+        None
+    }
+    fn set_checkpoint_id(&mut self, _checkpoint_id: Option<String>) {}
+    /// Replace the messages of an earlier conversation sent to the model before the task.
+    fn set_history(&mut self, _history: Option<Vec<Message>>) {}
     async fn step(
         &mut self,
         log_entry: &mut Step,
@@ -50,10 +161,12 @@ pub trait Agent: Send + Sync {
     async fn direct_run(
         &mut self,
         task: &str,
-        _tx: Option<broadcast::Sender<Status>>,
+        tx: Option<broadcast::Sender<Status>>,
     ) -> Result<String, AgentError> {
+        self.save_checkpoint().await;
         let mut final_answer: Option<String> = None;
         while final_answer.is_none() && self.get_step_number() <= self.get_max_steps() {
+            self.check_cancelled(tx.as_ref())?;
             let mut step_log = Step::ActionStep(AgentStep::new(
                 self.get_step_number(),
                 Some(task.to_string()),
@@ -63,19 +176,40 @@ pub trait Agent: Send + Sync {
                 if self.get_step_number() % planning_interval == 1 {
                     self.planning_step(task, self.get_step_number() == 1, self.get_step_number())
                         .await
-                        .unwrap();
+                        .map_err(|e| match e.downcast::<AgentError>() {
+                            Ok(e) => e,
+                            Err(e) => AgentError::Execution(e.to_string()),
+                        })?;
                 }
             }
 
-            if let Some(step) = self.step(&mut step_log, None).await? {
-                final_answer = step.final_answer;
+            if self.step(&mut step_log, tx.clone()).await?.is_some() {
+                if let Step::ActionStep(step) = &mut step_log {
+                    self.validate_final_answer(task, step).await?;
+                    final_answer = step.final_answer.clone();
+                }
+            }
+            step_log.finish();
+            if let Some(tx) = &tx {
+                let _ = tx.send(Status::Step(Box::new(step_log.clone())));
             }
             self.get_logs_mut().push(step_log);
             self.increment_step_number();
+            self.save_checkpoint().await;
         }
 
         if final_answer.is_none() && self.get_step_number() > self.get_max_steps() {
-            final_answer = self.provide_final_answer(task, None).await?;
+            self.check_cancelled(tx.as_ref())?;
+            final_answer = self.provide_final_answer(task, tx.clone()).await?;
+        }
+        if let (Some(guardrails), Some(answer)) = (self.guardrails(), &final_answer) {
+            guardrails.check_output(answer).await?;
+        }
+        if let (Some(recorder), Some(answer)) = (self.recorder(), &final_answer) {
+            recorder.record_answer(answer);
+        }
+        if let Some(answer) = &final_answer {
+            self.remember_run(task, answer).await;
         }
         info!(
             "Final answer: {}",
@@ -87,13 +221,28 @@ pub trait Agent: Send + Sync {
     }
 
     async fn run(&mut self, task: &str, reset: bool) -> Result<String, AgentError> {
+        self.run_with_status(task, reset, None).await
+    }
+
+    /// Like `run`, sending the tokens of the model and each finished step to `tx`. Agents run by a tool call of
+    /// another agent are run this way, so their activity shows up live in the stream of the calling agent.
+    async fn run_with_status(
+        &mut self,
+        task: &str,
+        reset: bool,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
         self.set_task(task);
         self.set_step_number(1);
-        let system_prompt_step = Step::SystemPromptStep(self.get_system_prompt().to_string());
+        let system_prompt_step = Step::SystemPromptStep(self.system_prompt_for(task));
         if reset {
             self.get_logs_mut().clear();
             self.get_logs_mut().push(system_prompt_step);
             self.reset_step_number();
+            self.reset_session();
+            if let Some(scratchpad) = self.scratchpad() {
+                scratchpad.clear();
+            }
         } else if self.get_logs_mut().is_empty() {
             self.get_logs_mut().push(system_prompt_step);
             self.reset_step_number();
@@ -104,8 +253,119 @@ pub trait Agent: Send + Sync {
         self.get_logs_mut().push(Step::TaskStep(task.to_string()));
         self.set_task(task);
         self.set_step_number(1);
+        if let Some(recorder) = self.recorder() {
+            recorder.start(task, self.model().sampling());
+        }
+        self.recall_memories(task).await;
+
+        self.direct_run(task, tx).await
+    }
+
+    /// Add the memories relevant to the task to the system prompt of the logs. A failed recall is logged and the run
+    /// goes on without memories.
+    async fn recall_memories(&mut self, task: &str) {
+        let Some(long_term_memory) = self.long_term_memory().cloned() else {
+            return;
+        };
+        let memories = match long_term_memory.recall(task).await {
+            Ok(memories) => memories,
+            Err(e) => {
+                warn!("Failed to recall the memories of {}: {}", long_term_memory.scope(), e);
+                return;
+            }
+        };
+        if memories.is_empty() {
+            return;
+        }
+        if let Some(Step::SystemPromptStep(prompt)) = self.get_logs_mut().first_mut() {
+            *prompt = format!("{}\n\n{}", prompt, memories_prompt(&memories));
+        }
+    }
+
+    /// Remember the facts of the current task once it has its answer. A failed extraction is logged and the answer
+    /// is kept.
+    async fn remember_run(&mut self, task: &str, answer: &str) {
+        let Some(long_term_memory) = self.long_term_memory().cloned() else {
+            return;
+        };
+        let mut messages = match self.write_inner_memory_from_logs(None) {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("Failed to remember the run: {}", e);
+                return;
+            }
+        };
+        // Only the messages of the current task, the earlier ones were remembered by their own runs.
+        let task_start = messages
+            .iter()
+            .rposition(|message| {
+                message.role == MessageRole::User && message.content.starts_with("New Task: ")
+            })
+            .unwrap_or(0);
+        messages.drain(..task_start);
+        messages.push(Message {
+            role: MessageRole::Assistant,
+            content: answer.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        });
+        match long_term_memory.remember(self.model(), task, &messages).await {
+            Ok(memories) => info!("Remembered {} facts in {}", memories.len(), long_term_memory.scope()),
+            Err(e) => warn!("Failed to remember the run in {}: {}", long_term_memory.scope(), e),
+        }
+    }
+
+    /// Save the state of the agent to the checkpoint store, if any. A failed save is logged and the run goes on.
+    async fn save_checkpoint(&mut self) {
+        let Some(store) = self.checkpoint_store() else {
+            return;
+        };
+        let id = match self.checkpoint_id() {
+            Some(id) => id.to_string(),
+            None => {
+                let id = nanoid::nanoid!();
+                self.set_checkpoint_id(Some(id.clone()));
+                id
+            }
+        };
+        let checkpoint = Checkpoint {
+            id,
+            task: self.get_task().to_string(),
+            step_number: self.get_step_number(),
+            logs: self.get_logs_mut().clone(),
+            created_at: Utc::now(),
+        };
+        if let Err(e) = store.save(&checkpoint).await {
+            warn!("Failed to save checkpoint {}: {}", checkpoint.id, e);
+        }
+    }
+
+    /// Restore the state saved in the checkpoint. The next checkpoints replace it.
+    fn restore_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.set_task(&checkpoint.task);
+        self.set_step_number(checkpoint.step_number);
+        *self.get_logs_mut() = checkpoint.logs;
+        self.set_checkpoint_id(Some(checkpoint.id));
+    }
 
-        self.direct_run(task, None).await
+    /// Continue the run saved in the checkpoint with its next step. The tool calls of the completed steps are not
+    /// made again. Returns the answer right away when the run had finished.
+    ///
+    /// Only the logs are restored: the Python session of a code agent starts empty.
+    async fn resume(&mut self, checkpoint: Checkpoint) -> Result<String, AgentError> {
+        let answer = checkpoint.final_answer().map(str::to_string);
+        let task = checkpoint.task.clone();
+        self.restore_checkpoint(checkpoint);
+        match answer {
+            Some(answer) => Ok(answer),
+            None => self.direct_run(&task, None).await,
+        }
+    }
+
+    /// Continue the run saved in the checkpoint file of a `FileCheckpointStore`.
+    async fn resume_from_checkpoint(&mut self, path: &Path) -> Result<String, AgentError> {
+        let checkpoint = Checkpoint::load(path).map_err(|e| AgentError::Execution(e.to_string()))?;
+        self.resume(checkpoint).await
     }
 
     async fn provide_final_answer(
@@ -128,19 +388,152 @@ pub trait Agent: Send + Sync {
             tool_calls: None,
         });
 
-        let response = match tx {
-            None => self
-                .model()
-                .run(input_messages, None, vec![], None, None)
-                .await?
-                .get_response()?,
-            Some(tx) => self
-                .model()
-                .run_stream(input_messages, None, vec![], None, None, tx)
-                .await?
-                .get_response()?,
+        let request = async {
+            match tx.clone() {
+                None => self.model().run(input_messages, None, vec![], None, None).await,
+                Some(tx) => {
+                    self.model()
+                        .run_stream(input_messages, None, vec![], None, None, tx)
+                        .await
+                }
+            }
         };
-        Ok(Some(response))
+        let response = cancellable(self.cancellation(), tx.as_ref(), request).await?;
+        if let Some(recorder) = self.recorder() {
+            recorder.record_response(response.as_ref());
+        }
+        Ok(Some(response.get_response()?))
+    }
+
+    /// The system prompt of a run of `task`, with the current time and the directives of the response language and of
+    /// JSON answers.
+    fn system_prompt_for(&self, task: &str) -> String {
+        let mut system_prompt = self
+            .get_system_prompt()
+            .replace("{{current_time}}", &current_time(self.time_zone()));
+        if let Some(language) = self.response_language() {
+            system_prompt = format!("{}\n\n{}", system_prompt, language.directive(task));
+        }
+        if self.json_answer() {
+            system_prompt = format!("{}\n\n{}", system_prompt, JSON_ANSWER_PROMPT);
+        }
+        system_prompt
+    }
+
+    /// Check the final answer of the step against the task when answer validation is on, and its language when a
+    /// response language is set. A rejected answer is removed from the step, and the verdict is added to the memory
+    /// so the agent keeps working on the task. An answer in the wrong language is only rejected once per task.
+    async fn validate_final_answer(
+        &mut self,
+        task: &str,
+        step_log: &mut AgentStep,
+    ) -> Result<(), AgentError> {
+        let Some(answer) = step_log.final_answer.clone() else {
+            return Ok(());
+        };
+        let language = self.response_language().cloned();
+        // The steps of the current task, without the answers of the earlier ones.
+        let logs = self.get_logs_mut();
+        let task_start = logs
+            .iter()
+            .rposition(|step| matches!(step, Step::TaskStep(_)))
+            .unwrap_or(0);
+        if let Some(language) = language {
+            let retried = logs[task_start..].iter().any(|step| match step {
+                Step::ActionStep(step) => step
+                    .validation
+                    .as_ref()
+                    .is_some_and(|validation| ResponseLanguage::is_mismatch(&validation.reason)),
+                _ => false,
+            });
+            if let Some(reason) = language.check_answer(task, &answer).filter(|_| !retried) {
+                info!("Final answer rejected: {}", reason);
+                step_log.final_answer = None;
+                step_log.validation = Some(Box::new(AnswerValidation {
+                    valid: false,
+                    reason,
+                    supporting_observations: vec![],
+                }));
+                return Ok(());
+            }
+        }
+        if !self.answer_validation() {
+            return Ok(());
+        }
+        let logs = self.get_logs_mut();
+        let observations = logs[task_start..]
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) if step.final_answer.is_none() && step.validation.is_none() => {
+                    Some(step.observation_contents())
+                }
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        let validation = cancellable(
+            self.cancellation(),
+            None,
+            validate_answer_recorded(self.model(), task, &observations, &answer, self.recorder()),
+        )
+        .await?;
+        log_answer_validation(&validation);
+        if !validation.valid {
+            info!("Final answer rejected: {}", validation.reason);
+            step_log.final_answer = None;
+        }
+        step_log.validation = Some(Box::new(validation));
+        Ok(())
+    }
+
+    /// Export the logs as OpenAI-style chat messages, including tool calls and tool responses.
+    fn export_messages(&mut self) -> Vec<Message> {
+        steps_to_messages(self.get_logs_mut())
+    }
+
+    /// Export the run as a training example, e.g. to build a fine-tuning dataset from successful runs.
+    fn export_training_data(&mut self, format: ExportFormat) -> serde_json::Value {
+        export_run(self.get_logs_mut(), format)
+    }
+
+    /// The figures and dataframes produced by the code executed during the run, in the order of the steps.
+    fn artifacts(&mut self) -> Vec<Artifact> {
+        self.get_logs_mut()
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step_log) => Some(step_log.artifacts.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Summary of the last run: steps, tool calls, tokens, errors and where the answer came from.
+    fn run_summary(&mut self) -> RunSummary {
+        let max_steps = self.get_max_steps();
+        RunSummary::from_logs(self.get_logs_mut(), max_steps)
+    }
+
+    /// The memory the next step sends to the model: the system prompt, the tasks, and the steps of the logs with
+    /// their tool calls, observations and errors, fitted to the context window. The prompt and tool overrides of
+    /// the next step are not included.
+    fn get_memory(&mut self) -> Result<Vec<Message>, AgentError> {
+        self.write_inner_memory_from_logs(None)
+    }
+
+    /// Replace the logs with the steps rebuilt from OpenAI-style chat messages.
+    ///
+    /// The next call to `run` with `reset = false` continues from the imported conversation.
+    fn import_messages(&mut self, messages: Vec<Message>) {
+        let steps = messages_to_steps(messages);
+        let task = steps.iter().rev().find_map(|step| match step {
+            Step::TaskStep(task) => Some(task.clone()),
+            _ => None,
+        });
+        *self.get_logs_mut() = steps;
+        if let Some(task) = task {
+            self.set_task(&task);
+        }
     }
 
     fn write_inner_memory_from_logs(
@@ -149,6 +542,14 @@ pub trait Agent: Send + Sync {
     ) -> Result<Vec<Message>, AgentError> {
         let mut memory = Vec::new();
         let summary_mode = summary_mode.unwrap_or(false);
+        let processor = self.observation_processor().cloned();
+        let retry_prompts = self.retry_prompts().cloned().unwrap_or_default();
+        let task = self.get_task().to_string();
+        let mut observation_session = processor.as_ref().map(|processor| processor.session(&task));
+        let mut process = |observation: &str| match observation_session.as_mut() {
+            Some(session) => session.process(observation),
+            None => observation.to_string(),
+        };
         for log in self.get_logs_mut() {
             match log {
                 Step::ToolCall(_) => {}
@@ -210,11 +611,15 @@ pub trait Agent: Send + Sync {
                         });
                     }
 
-                    if let (Some(tool_calls), Some(observations)) =
+                    if let (Some(tool_calls), Some(_)) =
                         (&step_log.tool_call, &step_log.observations)
                     {
                         for (i, tool_call) in tool_calls.iter().enumerate() {
-                            let message_content = format!("Observation: {}", observations[i]);
+                            let observation = step_log
+                                .observation_of(i)
+                                .map(|observation| process(&observation.content))
+                                .unwrap_or("The tool call returned no observation.".to_string());
+                            let message_content = format!("Observation: {}", observation);
 
                             let id = if tool_call.id.is_some() {
                                 if tool_call.id.as_ref().unwrap().is_empty() {
@@ -242,22 +647,61 @@ pub trait Agent: Send + Sync {
                             //     });
                             // }
                         }
-                    } else if let Some(observations) = &step_log.observations {
+                        // Failed calls with a known cause get guidance after all the tool responses, which must follow
+                        // the tool calls.
+                        let guidance = tool_calls
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, tool_call)| {
+                                let observation = step_log.observation_of(i).filter(|o| o.is_error)?;
+                                let kind = ErrorKind::classify(&observation.content);
+                                (kind != ErrorKind::Other).then(|| {
+                                    format!(
+                                        "The call to {} failed. {}",
+                                        tool_call.function.name,
+                                        retry_prompts.get(kind)
+                                    )
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        if !guidance.is_empty() {
+                            memory.push(Message {
+                                role: MessageRole::User,
+                                content: guidance.join("\n"),
+                                tool_call_id: None,
+                                tool_calls: None,
+                            });
+                        }
+                    } else if step_log.observations.is_some() {
                         memory.push(Message {
                             role: MessageRole::User,
-                            content: format!("Observations: {}", observations.join("\n")),
+                            content: format!(
+                                "Observations: {}",
+                                process(&step_log.observation_contents().join("\n"))
+                            ),
                             tool_call_id: None,
                             tool_calls: None,
                         });
                     }
-                    if step_log.error.is_some() {
-                        let error_string =
-                            "Error: ".to_owned() + step_log.error.clone().unwrap().message(); // Its fine to unwrap because we check for None above
-
-                        let error_string = error_string + "\nNow let's retry: take care not to repeat previous errors! If you have retried several times, try a completely different approach.\n";
+                    if let Some(error) = &step_log.error {
                         memory.push(Message {
                             role: MessageRole::User,
-                            content: error_string,
+                            content: format!(
+                                "Error: {}\n{}\n",
+                                error.message(),
+                                retry_prompts.for_error(error.message())
+                            ),
+                            tool_call_id: None,
+                            tool_calls: None,
+                        });
+                    }
+                    if let Some(validation) = step_log.validation.as_ref().filter(|v| !v.valid) {
+                        memory.push(Message {
+                            role: MessageRole::User,
+                            content: format!(
+                                "Your final answer was rejected: {}\nKeep working on the task and answer again once the answer addresses it.",
+                                validation.reason
+                            ),
                             tool_call_id: None,
                             tool_calls: None,
                         });
@@ -265,6 +709,10 @@ pub trait Agent: Send + Sync {
                 }
             }
         }
+        if let Some(context_window) = self.context_window().or(self.model().context_window()) {
+            let budget = context_window.saturating_sub(self.model().max_output_tokens());
+            memory = fit_to_context_window(memory, budget, self.model().token_counter().as_ref());
+        }
         Ok(memory)
     }
 }
@@ -277,11 +725,26 @@ pub trait AgentStream: Agent {
         reset: bool,
         tx: Option<broadcast::Sender<Status>>,
     ) -> StreamResult<'a, Step> {
-        let system_prompt_step = Step::SystemPromptStep(self.get_system_prompt().to_string());
+        self.stream_run_with_hook(task, reset, tx, None)
+    }
+
+    /// Like `stream_run`, with a hook called between the steps that can change the next step or abort the run.
+    fn stream_run_with_hook<'a>(
+        &'a mut self,
+        task: &'a str,
+        reset: bool,
+        tx: Option<broadcast::Sender<Status>>,
+        hook: Option<Box<dyn StepHook + 'a>>,
+    ) -> StreamResult<'a, Step> {
+        let system_prompt_step = Step::SystemPromptStep(self.system_prompt_for(task));
         if reset {
             self.get_logs_mut().clear();
             self.get_logs_mut().push(system_prompt_step);
             self.reset_step_number();
+            self.reset_session();
+            if let Some(scratchpad) = self.scratchpad() {
+                scratchpad.clear();
+            }
         } else if self.get_logs_mut().is_empty() {
             self.get_logs_mut().push(system_prompt_step);
             self.reset_step_number();
@@ -292,11 +755,68 @@ pub trait AgentStream: Agent {
         self.get_logs_mut().push(Step::TaskStep(task.to_string()));
         self.set_task(task);
         self.set_step_number(1);
+        if let Some(recorder) = self.recorder() {
+            recorder.start(task, self.model().sampling());
+        }
+        if self.long_term_memory().is_none() {
+            return self.stream_steps(task.to_string(), tx, hook);
+        }
+
+        // The memories are recalled when the stream is first polled.
+        let stream = async_stream::stream! {
+            self.recall_memories(task).await;
+            let mut steps = match self.stream_steps(task.to_string(), tx, hook) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+            while let Some(step) = futures::StreamExt::next(&mut steps).await {
+                yield step;
+            }
+        };
+        Ok(Box::pin(stream))
+    }
+
+    /// Like `resume`, streaming the steps that follow the checkpoint. When the run had finished, the stream only
+    /// has the step with its answer.
+    fn stream_resume<'a>(
+        &'a mut self,
+        checkpoint: Checkpoint,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> StreamResult<'a, Step> {
+        let answer = checkpoint.final_answer().map(str::to_string);
+        let task = checkpoint.task.clone();
+        self.restore_checkpoint(checkpoint);
+        if let Some(answer) = answer {
+            let step = Step::ActionStep(AgentStep {
+                final_answer: Some(answer),
+                step: self.get_step_number().saturating_sub(1),
+                ..Default::default()
+            });
+            return Ok(Box::pin(futures::stream::once(async move { Ok(step) })));
+        }
+        self.stream_steps(task, tx, None)
+    }
 
+    /// The steps of the run from the current step number until the final answer or `max_steps`.
+    fn stream_steps<'a>(
+        &'a mut self,
+        task: String,
+        tx: Option<broadcast::Sender<Status>>,
+        mut hook: Option<Box<dyn StepHook + 'a>>,
+    ) -> StreamResult<'a, Step> {
         let mut final_answer: Option<String> = None;
 
         let stream = async_stream::stream! {
+            let task = task.as_str();
+            self.save_checkpoint().await;
             while final_answer.is_none() && self.get_step_number() <= self.get_max_steps() {
+                if let Err(e) = self.check_cancelled(tx.as_ref()) {
+                    yield Err(e.into());
+                    return;
+                }
                 let mut step_log = Step::ActionStep(AgentStep::new(self.get_step_number(), Some(task.to_string())));
 
                 if let Some(planning_interval) = self.get_planning_interval() {
@@ -313,13 +833,51 @@ pub trait AgentStream: Agent {
                 }
 
                 match self.step(&mut step_log, tx.clone()).await {
-                    Ok(Some(step)) => {
+                    Ok(Some(_)) => {
+                        let mut answer = None;
+                        if let Step::ActionStep(step) = &mut step_log {
+                            if let Err(e) = self.validate_final_answer(task, step).await {
+                                yield Err(e.into());
+                                break;
+                            }
+                            answer = step.final_answer.clone();
+                        }
+                        step_log.finish();
                         self.get_logs_mut().push(step_log.clone());
                         self.increment_step_number();
-                        if let Some(answer) = step.final_answer.clone() {
+                        self.save_checkpoint().await;
+                        if let Some(answer) = answer {
+                            if let Some(guardrails) = self.guardrails() {
+                                if let Err(e) = guardrails.check_output(&answer).await {
+                                    yield Err(e.into());
+                                    break;
+                                }
+                            }
+                            if let Some(recorder) = self.recorder() {
+                                recorder.record_answer(&answer);
+                            }
                             final_answer = Some(answer);
                         }
-                        yield Ok(step_log);
+                        yield Ok(step_log.clone());
+                        // Runs when the next step is requested, once the consumer has handled this one.
+                        if let (Some(hook), Step::ActionStep(step), None) = (hook.as_mut(), &step_log, &final_answer) {
+                            if self.get_step_number() <= self.get_max_steps() {
+                                let memory = match self.write_inner_memory_from_logs(None) {
+                                    Ok(memory) => memory,
+                                    Err(e) => {
+                                        yield Err(e.into());
+                                        break;
+                                    }
+                                };
+                                match hook.after_step(step, &memory).await {
+                                    StepDecision::Continue(overrides) => self.set_step_overrides(overrides),
+                                    StepDecision::Abort => {
+                                        info!("Run aborted after step {}", step.step);
+                                        return;
+                                    }
+                                }
+                            }
+                        }
                     }
                     Ok(None) => {},
                     Err(e) => {
@@ -330,8 +888,22 @@ pub trait AgentStream: Agent {
             }
 
             if final_answer.is_none() && self.get_step_number() > self.get_max_steps() {
+                if let Err(e) = self.check_cancelled(tx.as_ref()) {
+                    yield Err(e.into());
+                    return;
+                }
                 match self.provide_final_answer(task, tx.clone()).await {
                     Ok(Some(answer)) => {
+                        if let Some(guardrails) = self.guardrails() {
+                            if let Err(e) = guardrails.check_output(&answer).await {
+                                yield Err(e.into());
+                                return;
+                            }
+                        }
+                        if let Some(recorder) = self.recorder() {
+                            recorder.record_answer(&answer);
+                        }
+                        final_answer = Some(answer.clone());
                         yield Ok(Step::ActionStep(AgentStep {
                             final_answer: Some(answer),
                             step: self.get_step_number(),
@@ -342,6 +914,10 @@ pub trait AgentStream: Agent {
                     Err(e) => yield Err(e.into()),
                 }
             }
+            // After the step with the answer, so remembering the run does not hold the answer back.
+            if let Some(answer) = &final_answer {
+                self.remember_run(task, answer).await;
+            }
         };
 
         Ok(Box::pin(stream))
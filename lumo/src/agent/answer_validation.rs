@@ -0,0 +1,157 @@
+//! Check the final answer of an agent against its task before it is returned.
+
+use serde::{Deserialize, Serialize};
+
+use super::reproducibility::RunRecorder;
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::Model,
+        types::{Message, MessageRole},
+    },
+    prompts::ANSWER_VALIDATION_SYSTEM_PROMPT,
+};
+
+/// Observations longer than this are cut in the validation prompt.
+const MAX_OBSERVATION_CHARS: usize = 2000;
+/// Cited observations are kept in the verdict up to this length.
+const MAX_CITATION_CHARS: usize = 200;
+
+/// The verdict on a final answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswerValidation {
+    /// Whether the answer addresses the task and is supported.
+    pub valid: bool,
+    /// Why the model accepted or rejected the answer.
+    pub reason: String,
+    /// The beginning of the observations supporting the answer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supporting_observations: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Verdict {
+    valid: bool,
+    #[serde(default)]
+    reason: String,
+    #[serde(default)]
+    supporting_observations: Vec<usize>,
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Ask the model whether `answer` addresses `task`, given the observations of the run.
+///
+/// A response that can't be parsed accepts the answer, so a model that ignores the format doesn't keep the agent
+/// from answering.
+pub async fn validate_answer(
+    model: &dyn Model,
+    task: &str,
+    observations: &[String],
+    answer: &str,
+) -> Result<AnswerValidation, AgentError> {
+    validate_answer_recorded(model, task, observations, answer, None).await
+}
+
+/// Like `validate_answer`, adding the response of the model to the manifest of the recorder.
+pub(crate) async fn validate_answer_recorded(
+    model: &dyn Model,
+    task: &str,
+    observations: &[String],
+    answer: &str,
+    recorder: Option<&RunRecorder>,
+) -> Result<AnswerValidation, AgentError> {
+    let numbered = if observations.is_empty() {
+        "No observations.".to_string()
+    } else {
+        observations
+            .iter()
+            .enumerate()
+            .map(|(i, observation)| format!("[{}] {}", i + 1, truncate(observation, MAX_OBSERVATION_CHARS)))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    let messages = vec![
+        Message {
+            role: MessageRole::System,
+            content: ANSWER_VALIDATION_SYSTEM_PROMPT.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        },
+        Message {
+            role: MessageRole::User,
+            content: format!(
+                "Task:\n{}\n\nObservations:\n{}\n\nFinal answer:\n{}",
+                task, numbered, answer
+            ),
+            tool_call_id: None,
+            tool_calls: None,
+        },
+    ];
+    let response = model.run(messages, None, vec![], Some(500), None).await?;
+    if let Some(recorder) = recorder {
+        recorder.record_response(response.as_ref());
+    }
+    let response = response.get_response()?;
+
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => response.as_str(),
+    };
+    Ok(match serde_json::from_str::<Verdict>(json) {
+        Ok(verdict) => AnswerValidation {
+            valid: verdict.valid,
+            reason: verdict.reason,
+            supporting_observations: verdict
+                .supporting_observations
+                .iter()
+                .filter_map(|i| observations.get(i.checked_sub(1)?))
+                .map(|observation| truncate(observation, MAX_CITATION_CHARS))
+                .collect(),
+        },
+        Err(e) => {
+            tracing::warn!(error = %e, response = %response, "Could not parse the answer validation");
+            AnswerValidation {
+                valid: true,
+                reason: "The validation could not be parsed, the answer is accepted.".to_string(),
+                supporting_observations: vec![],
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+
+    #[tokio::test]
+    async fn test_validate_answer() {
+        let model = MockModel::new(vec![
+            MockResponse::text(
+                r#"{"valid": true, "reason": "The search result says Paris.", "supporting_observations": [2, 7]}"#,
+            ),
+            MockResponse::text("I think the answer is fine."),
+        ]);
+        let observations = vec![
+            "Weather in Paris: sunny".to_string(),
+            "Paris is the capital of France".to_string(),
+        ];
+        let validation = validate_answer(&model, "What is the capital of France?", &observations, "Paris")
+            .await
+            .unwrap();
+        assert!(validation.valid);
+        assert_eq!(validation.supporting_observations, vec!["Paris is the capital of France"]);
+        assert!(model.requests()[0][1].content.contains("[2] Paris is the capital of France"));
+
+        let validation = validate_answer(&model, "What is the capital of France?", &observations, "Paris")
+            .await
+            .unwrap();
+        assert!(validation.valid);
+    }
+}
@@ -0,0 +1,160 @@
+//! Checkpoints of long runs, to resume them after a crash.
+//!
+//! With a [`CheckpointStore`], the agent saves its state after each step: the task, the number of the next step
+//! and the logs, observations of the tool calls included. Resuming a run from its last checkpoint continues with
+//! the next step, so the tool calls already made are not made again.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::agent_step::Step;
+
+/// The state of an agent after a step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The id of the run, the same for all its checkpoints.
+    pub id: String,
+    pub task: String,
+    /// The step the run continues with.
+    pub step_number: usize,
+    pub logs: Vec<Step>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint saved by a [`FileCheckpointStore`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse checkpoint: {:?}", path))
+    }
+
+    /// The answer of the task, when the run finished before the checkpoint was saved.
+    pub fn final_answer(&self) -> Option<&str> {
+        let task_start = self
+            .logs
+            .iter()
+            .rposition(|step| matches!(step, Step::TaskStep(_)))
+            .unwrap_or(0);
+        self.logs[task_start..]
+            .iter()
+            .rev()
+            .find_map(|step| match step {
+                Step::ActionStep(step) => step.final_answer.as_deref(),
+                _ => None,
+            })
+    }
+}
+
+/// Where checkpoints are saved. A checkpoint replaces the previous checkpoint with the same id.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait CheckpointStore: Send + Sync {
+    async fn save(&self, checkpoint: &Checkpoint) -> Result<()>;
+    async fn load(&self, id: &str) -> Result<Option<Checkpoint>>;
+}
+
+/// Checkpoints saved as `<id>.json` files in a directory.
+#[derive(Debug, Clone)]
+pub struct FileCheckpointStore {
+    dir: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file of the checkpoint. Ids are limited to letters, digits, `-` and `_`, so they can not leave the
+    /// directory.
+    pub fn path(&self, id: &str) -> Result<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(anyhow!("Invalid checkpoint id '{}'", id));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl CheckpointStore for FileCheckpointStore {
+    async fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let path = self.path(&checkpoint.id)?;
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create checkpoint directory: {:?}", self.dir))?;
+        // Written next to the checkpoint and renamed, so a crash while writing keeps the previous checkpoint.
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_string(checkpoint)?)
+            .with_context(|| format!("Failed to write checkpoint: {:?}", partial))?;
+        std::fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to write checkpoint: {:?}", path))
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<Checkpoint>> {
+        let path = self.path(id)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Checkpoint::load(path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{AgentStep, ToolObservation};
+
+    #[tokio::test]
+    async fn test_file_checkpoint_store() {
+        let store = FileCheckpointStore::new(
+            std::env::temp_dir().join(format!("lumo-checkpoints-{}", nanoid::nanoid!())),
+        );
+        assert!(store.load("run-1").await.unwrap().is_none());
+        assert!(store.path("../run-1").is_err());
+
+        let mut checkpoint = Checkpoint {
+            id: "run-1".to_string(),
+            task: "What is the capital of France?".to_string(),
+            step_number: 2,
+            logs: vec![
+                Step::SystemPromptStep("You are a helpful assistant".to_string()),
+                Step::TaskStep("What is the capital of France?".to_string()),
+                Step::ActionStep(AgentStep {
+                    step: 1,
+                    observations: Some(vec![ToolObservation::new("Paris is the capital of France")]),
+                    ..Default::default()
+                }),
+            ],
+            created_at: Utc::now(),
+        };
+        store.save(&checkpoint).await.unwrap();
+        let saved = store.load("run-1").await.unwrap().unwrap();
+        assert_eq!(saved.step_number, 2);
+        assert_eq!(saved.logs.len(), 3);
+        assert_eq!(saved.final_answer(), None);
+
+        // The answer of an earlier task is not the answer of the current one.
+        checkpoint.logs.push(Step::ActionStep(AgentStep {
+            step: 2,
+            final_answer: Some("Paris".to_string()),
+            ..Default::default()
+        }));
+        store.save(&checkpoint).await.unwrap();
+        let saved = Checkpoint::load(store.path("run-1").unwrap()).unwrap();
+        assert_eq!(saved.final_answer(), Some("Paris"));
+        checkpoint
+            .logs
+            .push(Step::TaskStep("And of Italy?".to_string()));
+        assert_eq!(checkpoint.final_answer(), None);
+    }
+}
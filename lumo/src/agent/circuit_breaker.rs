@@ -0,0 +1,100 @@
+//! A per-run circuit breaker for tools that keep failing, e.g. a search tool with an expired API key.
+//!
+//! After `threshold` consecutive failures, the tool is no longer advertised to the model and its calls are answered
+//! with an observation saying it is unavailable, until the next run.
+
+use std::collections::HashMap;
+
+/// Consecutive failures after which a tool is made unavailable, unless set with `with_circuit_breaker`.
+pub const DEFAULT_FAILURE_THRESHOLD: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    failures: HashMap<String, usize>,
+    /// The tools that are unavailable, in the order they were opened.
+    open: Vec<String>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            failures: HashMap::new(),
+            open: Vec::new(),
+        }
+    }
+
+    /// Make every tool available again, e.g. when a run starts.
+    pub fn reset(&mut self) {
+        self.failures.clear();
+        self.open.clear();
+    }
+
+    pub fn is_open(&self, tool: &str) -> bool {
+        self.open.iter().any(|open| open == tool)
+    }
+
+    /// The tools that are unavailable.
+    pub fn open_tools(&self) -> &[String] {
+        &self.open
+    }
+
+    pub fn consecutive_failures(&self, tool: &str) -> usize {
+        self.failures.get(tool).copied().unwrap_or_default()
+    }
+
+    pub fn record_success(&mut self, tool: &str) {
+        self.failures.remove(tool);
+    }
+
+    /// Returns `true` when the failure makes the tool unavailable.
+    pub fn record_failure(&mut self, tool: &str) -> bool {
+        let failures = self.failures.entry(tool.to_string()).or_default();
+        *failures += 1;
+        if *failures >= self.threshold && !self.is_open(tool) {
+            self.open.push(tool.to_string());
+            return true;
+        }
+        false
+    }
+
+    /// The observation for a call to an unavailable tool.
+    pub fn unavailable_message(&self, tool: &str) -> String {
+        format!(
+            "The tool {} is unavailable: it failed {} times in a row. Do not call it again, use the other tools or answer with what you know.",
+            tool,
+            self.consecutive_failures(tool)
+        )
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut breaker = CircuitBreaker::new(2);
+        assert!(!breaker.record_failure("search"));
+        breaker.record_success("search");
+        assert!(!breaker.record_failure("search"));
+        assert!(breaker.record_failure("search"));
+        // Only the failure that opens the breaker reports it.
+        assert!(!breaker.record_failure("search"));
+        assert!(breaker.is_open("search"));
+        assert!(!breaker.is_open("visit_website"));
+        assert_eq!(breaker.open_tools(), ["search"]);
+        assert!(breaker.unavailable_message("search").contains("failed 3 times"));
+
+        breaker.reset();
+        assert!(!breaker.is_open("search"));
+        assert_eq!(breaker.consecutive_failures("search"), 0);
+    }
+}
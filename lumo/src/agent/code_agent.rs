@@ -1,23 +1,34 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use opentelemetry::trace::{FutureExt, TraceContextExt};
-use std::{collections::HashMap, mem::ManuallyDrop};
+use std::{collections::HashMap, sync::Arc};
 use tracing::{instrument, Span};
 
 use crate::{
     errors::{AgentError, InterpreterError},
+    guardrails::{Guardrail, Guardrails},
     local_python_interpreter::LocalPythonInterpreter,
     models::{
+        cancellation::CancellationToken,
         model_traits::Model,
         openai::{FunctionCall, Status, ToolCall},
         types::Message,
     },
-    prompts::CODE_SYSTEM_PROMPT,
-    telemetry::AgentTelemetry,
-    tools::{AsyncTool, FinalAnswerTool},
+    prompts::{Prompts, RetryPrompts, CODE_SYSTEM_PROMPT, ENGLISH_PROMPTS},
+    telemetry::{
+        otel::{
+            trace::{FutureExt, TraceContextExt},
+            KeyValue,
+        },
+        AgentTelemetry, RunMetadata,
+    },
+    tools::{time_zone::Zone, AsyncTool, FinalAnswerTool},
 };
 
-use super::{agent_step::Step, agent_trait::Agent, multistep_agent::MultiStepAgent, AgentStep};
+use super::{
+    agent_step::Step, agent_trait::Agent, checkpoint::CheckpointStore,
+    multistep_agent::MultiStepAgent, AgentStep, LongTermMemory, ObservationProcessor, Scratchpad, Reproducibility, ResponseLanguage,
+    RunRecorder, StepOverrides, ToolObservation,
+};
 
 #[cfg(feature = "stream")]
 use super::agent_trait::AgentStream;
@@ -25,7 +36,7 @@ use super::agent_trait::AgentStream;
 #[cfg(feature = "code-agent")]
 pub struct CodeAgent<M: Model> {
     base_agent: MultiStepAgent<M>,
-    local_python_interpreter: ManuallyDrop<LocalPythonInterpreter>,
+    local_python_interpreter: LocalPythonInterpreter,
     telemetry: AgentTelemetry,
 }
 
@@ -66,7 +77,7 @@ impl<M: Model + Send + Sync + 'static> CodeAgent<M> {
 
         Ok(Self {
             base_agent,
-            local_python_interpreter: ManuallyDrop::new(local_python_interpreter),
+            local_python_interpreter,
             telemetry: AgentTelemetry::new("lumo"),
         })
     }
@@ -83,6 +94,23 @@ pub struct CodeAgentBuilder<'a, M: Model> {
     planning_interval: Option<usize>,
     history: Option<Vec<Message>>,
     logging_level: Option<log::LevelFilter>,
+    guardrails: Vec<Box<dyn Guardrail>>,
+    context_window: Option<usize>,
+    answer_validation: bool,
+    response_language: Option<ResponseLanguage>,
+    json_answer: bool,
+    time_zone: Option<Zone>,
+    retry_prompts: Option<RetryPrompts>,
+    observation_processor: Option<ObservationProcessor>,
+    checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    planning_model: Option<Box<dyn Model>>,
+    fallback_model: Option<Box<dyn Model>>,
+    reproducibility: Option<Reproducibility>,
+    cancellation: Option<CancellationToken>,
+    long_term_memory: Option<LongTermMemory>,
+    prompt_language: Option<&'a str>,
+    tags: Vec<String>,
+    metadata: HashMap<String, String>,
 }
 
 impl<'a, M: Model + Send + Sync + 'static> CodeAgentBuilder<'a, M> {
@@ -98,6 +126,23 @@ impl<'a, M: Model + Send + Sync + 'static> CodeAgentBuilder<'a, M> {
             planning_interval: None,
             history: None,
             logging_level: None,
+            guardrails: vec![],
+            context_window: None,
+            answer_validation: false,
+            response_language: None,
+            json_answer: false,
+            time_zone: None,
+            retry_prompts: None,
+            observation_processor: None,
+            checkpoint_store: None,
+            planning_model: None,
+            fallback_model: None,
+            reproducibility: None,
+            cancellation: None,
+            long_term_memory: None,
+            prompt_language: None,
+            tags: Vec::new(),
+            metadata: HashMap::new(),
         }
     }
     pub fn with_name(mut self, name: Option<&'a str>) -> Self {
@@ -132,23 +177,150 @@ impl<'a, M: Model + Send + Sync + 'static> CodeAgentBuilder<'a, M> {
         self.history = history;
         self
     }
+    /// Print the steps with the colored logger of the crate at this level. Without a level the agent does not
+    /// install a `log` logger, so the application can use its own.
     pub fn with_logging_level(mut self, logging_level: Option<log::LevelFilter>) -> Self {
         self.logging_level = logging_level;
         self
     }
+    pub fn with_guardrails(mut self, guardrails: Vec<Box<dyn Guardrail>>) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+    /// The number of tokens the model accepts. The memory is trimmed to fit it, minus the tokens reserved for the
+    /// response. Defaults to the context window reported by the model, if any.
+    pub fn with_context_window(mut self, context_window: usize) -> Self {
+        self.context_window = Some(context_window);
+        self
+    }
+    /// Ask the model to check that each final answer addresses the task and is supported by the observations. A
+    /// rejected answer is not returned; the agent keeps stepping until `max_steps`.
+    pub fn with_answer_validation(mut self, answer_validation: bool) -> Self {
+        self.answer_validation = answer_validation;
+        self
+    }
+    /// Answer in the language, or in the language of the task with `ResponseLanguage::Auto`. The language is added to
+    /// the system prompt, and a final answer in another language is sent back once to be written again. Off when
+    /// `None`.
+    pub fn with_response_language(mut self, response_language: Option<ResponseLanguage>) -> Self {
+        self.response_language = response_language;
+        self
+    }
+    /// Ask for the final answer as a single JSON value, e.g. an array of objects for a table, so it can be parsed and
+    /// rendered while it is streamed with [`PartialJson`](crate::models::partial_json::PartialJson).
+    pub fn with_json_answer(mut self, json_answer: bool) -> Self {
+        self.json_answer = json_answer;
+        self
+    }
+    /// The time zone of the `{{current_time}}` of the system prompt, e.g. `Some("Asia/Tokyo".parse()?)`. The time
+    /// zone of the system when `None`.
+    pub fn with_time_zone(mut self, time_zone: Option<Zone>) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+    /// Replace the guidance written to the memory after errors, e.g. to tell the agent which tool to search with after
+    /// a page is not found.
+    pub fn with_retry_prompts(mut self, retry_prompts: RetryPrompts) -> Self {
+        self.retry_prompts = Some(retry_prompts);
+        self
+    }
+    /// Remove the passages already seen in earlier observations from the memory, and compress the long observations
+    /// if the processor is set up for it.
+    pub fn with_observation_processor(mut self, processor: ObservationProcessor) -> Self {
+        self.observation_processor = Some(processor);
+        self
+    }
+    /// Save the state of the agent to the store after each step, to resume the run after a crash with
+    /// `Agent::resume`.
+    pub fn with_checkpoint_store(mut self, store: Arc<dyn CheckpointStore>) -> Self {
+        self.checkpoint_store = Some(store);
+        self
+    }
+    /// Use this model for the planning steps, e.g. a cheap and fast one while the action steps use the model of the
+    /// agent.
+    pub fn with_planning_model(mut self, model: impl Model) -> Self {
+        self.planning_model = Some(Box::new(model));
+        self
+    }
+    /// Call this model when the model of a step fails, with the same messages.
+    pub fn with_fallback_model(mut self, model: impl Model) -> Self {
+        self.fallback_model = Some(Box::new(model));
+        self
+    }
+    /// Record the model responses and the tool calls of each run into a manifest, read with `Agent::run_manifest`,
+    /// or answer the tool calls with the outputs of a recorded manifest to execute its run again. Off when `None`.
+    pub fn with_reproducibility(mut self, reproducibility: Option<Reproducibility>) -> Self {
+        self.reproducibility = reproducibility;
+        self
+    }
+    /// Cancels the model requests and the next steps of the runs, e.g. when the user stops the run.
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+    /// Recall the memories of earlier runs relevant to each task into its system prompt, and remember the facts of
+    /// each run once it has its answer. Off when `None`.
+    pub fn with_long_term_memory(mut self, long_term_memory: Option<LongTermMemory>) -> Self {
+        self.long_term_memory = long_term_memory;
+        self
+    }
+    /// Use the built-in prompts in the language, e.g. `Some("fr")`, for the planning steps and for the system prompt
+    /// when none is set. English when `None`, see [`Prompts::languages`] for the others.
+    pub fn with_prompt_language(mut self, prompt_language: Option<&'a str>) -> Self {
+        self.prompt_language = prompt_language;
+        self
+    }
+    /// Tags added to the telemetry spans of the agent, to filter traces.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+    /// Metadata added to the telemetry spans of the agent, e.g. a user or tenant id.
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata.extend(metadata);
+        self
+    }
     pub fn build(self) -> Result<CodeAgent<M>> {
-        CodeAgent::new(
+        let prompts = match self.prompt_language {
+            Some(language) => Prompts::try_for_language(language)?,
+            None => &ENGLISH_PROMPTS,
+        };
+        let recorder = self.reproducibility.map(RunRecorder::new);
+        let tools = match &recorder {
+            Some(recorder) => recorder.wrap_tools(self.tools),
+            None => self.tools,
+        };
+        let mut agent = CodeAgent::new(
             self.name,
             self.model,
-            self.tools,
-            self.system_prompt,
+            tools,
+            self.system_prompt.or(Some(prompts.code_system_prompt)),
             self.managed_agents,
             self.description,
             self.max_steps,
             self.planning_interval,
             self.history,
             self.logging_level,
-        )
+        )?;
+        agent.base_agent.guardrails = Guardrails::new(self.guardrails);
+        agent.base_agent.context_window = self.context_window;
+        agent.base_agent.answer_validation = self.answer_validation;
+        agent.base_agent.response_language = self.response_language;
+        agent.base_agent.json_answer = self.json_answer;
+        agent.base_agent.time_zone = self.time_zone;
+        agent.base_agent.retry_prompts = self.retry_prompts;
+        agent.base_agent.observation_processor = self.observation_processor;
+        agent.base_agent.checkpoint_store = self.checkpoint_store;
+        agent.base_agent.planning_model = self.planning_model;
+        agent.base_agent.fallback_model = self.fallback_model;
+        agent.base_agent.recorder = recorder;
+        agent.base_agent.cancellation = self.cancellation;
+        agent.base_agent.long_term_memory = self.long_term_memory;
+        agent.base_agent.prompts = prompts;
+        agent
+            .telemetry
+            .set_run_metadata(RunMetadata::new(self.tags, self.metadata));
+        Ok(agent)
     }
 }
 
@@ -179,6 +351,9 @@ impl<M: Model + Send + Sync + 'static> Agent for CodeAgent<M> {
     fn reset_step_number(&mut self) {
         self.base_agent.reset_step_number()
     }
+    fn reset_session(&mut self) {
+        self.local_python_interpreter.reset();
+    }
     fn set_task(&mut self, task: &str) {
         self.base_agent.set_task(task);
     }
@@ -207,41 +382,112 @@ impl<M: Model + Send + Sync + 'static> Agent for CodeAgent<M> {
     fn model(&self) -> &dyn Model {
         self.base_agent.model()
     }
+    fn guardrails(&self) -> Option<&Guardrails> {
+        self.base_agent.guardrails()
+    }
+    fn context_window(&self) -> Option<usize> {
+        self.base_agent.context_window()
+    }
+    fn answer_validation(&self) -> bool {
+        self.base_agent.answer_validation()
+    }
+    fn response_language(&self) -> Option<&ResponseLanguage> {
+        self.base_agent.response_language()
+    }
+    fn json_answer(&self) -> bool {
+        self.base_agent.json_answer()
+    }
+    fn time_zone(&self) -> Option<&Zone> {
+        self.base_agent.time_zone()
+    }
+    fn recorder(&self) -> Option<&RunRecorder> {
+        self.base_agent.recorder()
+    }
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.base_agent.cancellation()
+    }
+    fn set_cancellation(&mut self, cancellation: Option<CancellationToken>) {
+        self.base_agent.set_cancellation(cancellation);
+    }
+    fn long_term_memory(&self) -> Option<&LongTermMemory> {
+        self.base_agent.long_term_memory()
+    }
+    fn set_long_term_memory(&mut self, long_term_memory: Option<LongTermMemory>) {
+        self.base_agent.set_long_term_memory(long_term_memory);
+    }
+    fn scratchpad(&self) -> Option<&Scratchpad> {
+        self.base_agent.scratchpad()
+    }
+    fn retry_prompts(&self) -> Option<&RetryPrompts> {
+        self.base_agent.retry_prompts()
+    }
+    fn observation_processor(&self) -> Option<&ObservationProcessor> {
+        self.base_agent.observation_processor()
+    }
+    fn set_step_overrides(&mut self, overrides: StepOverrides) {
+        self.base_agent.set_step_overrides(overrides);
+    }
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        self.local_python_interpreter.reset();
+        self.base_agent.shutdown().await
+    }
+    fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.base_agent.checkpoint_store()
+    }
+    fn checkpoint_id(&self) -> Option<&str> {
+        self.base_agent.checkpoint_id()
+    }
+    fn set_checkpoint_id(&mut self, checkpoint_id: Option<String>) {
+        self.base_agent.set_checkpoint_id(checkpoint_id);
+    }
+    fn set_history(&mut self, history: Option<Vec<Message>>) {
+        self.base_agent.set_history(history);
+    }
     #[instrument(skip(self, log_entry), fields(step = ?self.get_step_number()))]
     async fn step(
         &mut self,
         log_entry: &mut Step,
-        _tx: Option<tokio::sync::broadcast::Sender<Status>>,
+        tx: Option<tokio::sync::broadcast::Sender<Status>>,
     ) -> Result<Option<AgentStep>, AgentError> {
         let step_result = match log_entry {
             Step::ActionStep(step_log) => {
                 let cx = self.telemetry.start_step(self.get_step_number() as i64);
                 let span = Span::current();
                 span.record("step_type", "action");
-                let agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                let overrides = self.base_agent.take_step_overrides();
+                let mut agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                overrides.apply_to_memory(&mut agent_memory);
+                if self.get_step_number() == 1 {
+                    self.base_agent.check_input_guardrails().await?;
+                }
                 self.base_agent.input_messages = Some(agent_memory.clone());
                 step_log.agent_memory = Some(agent_memory.clone());
                 self.telemetry
                     .log_agent_memory(&serde_json::to_value(&agent_memory).unwrap_or_default());
 
+                let stop_sequences = Some(HashMap::from([(
+                    "stop".to_string(),
+                    vec!["Observation:".to_string(), "<end_code>".to_string()],
+                )]));
+                let model_start = crate::runtime::Instant::now();
                 let llm_output = self
                     .base_agent
-                    .model
-                    .run(
+                    .run_model(
                         self.base_agent.input_messages.as_ref().unwrap().clone(),
-                        self.base_agent.history.clone(),
                         vec![],
-                        None,
-                        Some(HashMap::from([(
-                            "stop".to_string(),
-                            vec!["Observation:".to_string(), "<end_code>".to_string()],
-                        )])),
+                        stop_sequences,
+                        tx.clone(),
                     )
                     .with_context(cx.clone())
                     .await?;
 
                 let response = llm_output.get_response()?;
                 step_log.llm_output = Some(response.clone());
+                step_log.reasoning = llm_output.get_reasoning();
+                step_log.record_model_call(
+                    model_start.elapsed(),
+                    self.base_agent.model.token_counter().as_ref(),
+                );
 
                 let code = match parse_code_blobs(&response) {
                     Ok(code) => code,
@@ -265,18 +511,40 @@ impl<M: Model + Send + Sync + 'static> Agent for CodeAgent<M> {
                 step_log.tool_call = Some(tool_call.clone());
                 self.telemetry.log_tool_calls(&tool_call, &cx);
 
+                self.base_agent
+                    .guardrails
+                    .check_tool_call(&tool_call[0].function)
+                    .await?;
+                if let Some(tx) = &tx {
+                    let _ = tx.send(Status::CodeExecutionStart(code.clone()));
+                }
+                let execution_start = crate::runtime::Instant::now();
                 let result = self.local_python_interpreter.forward(&code);
+                step_log.record_tool_call(&tool_call[0], execution_start.elapsed());
                 match result {
                     Ok(result) => {
                         let (result, execution_logs) = result;
+                        let artifacts = self.local_python_interpreter.take_artifacts();
                         let mut observation = match (execution_logs.is_empty(), result.is_empty()) {
                             (false, false) => {
                                 format!("Execution logs: {}\nResult: {}", execution_logs, result)
                             }
                             (false, true) => format!("Execution logs: {}", execution_logs),
                             (true, false) => format!("Result: {}", result),
+                            (true, true) if !artifacts.is_empty() => String::new(),
                             (true, true) => String::from("No output or logs generated"),
                         };
+                        if !artifacts.is_empty() {
+                            let artifacts = artifacts
+                                .iter()
+                                .map(|artifact| artifact.describe())
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            observation = format!("{}\nArtifacts:\n{}", observation, artifacts)
+                                .trim_start()
+                                .to_string();
+                        }
+                        step_log.artifacts = artifacts;
                         if observation.len() > 30000 {
                             observation = observation.chars().take(30000).collect::<String>();
                             observation = format!("{} \n....This content has been truncated due to the 30000 character limit.....", observation);
@@ -284,22 +552,35 @@ impl<M: Model + Send + Sync + 'static> Agent for CodeAgent<M> {
                             observation = observation.to_string();
                         }
                         tracing::info!("Observation: {}", observation);
+                        if let Some(tx) = &tx {
+                            let _ = tx.send(Status::CodeExecutionEnd(observation.clone()));
+                        }
                         self.telemetry.log_tool_result(&observation, true, &cx);
-                        step_log.observations = Some(vec![observation]);
+                        step_log.observations = Some(vec![ToolObservation::new(observation)]);
                     }
                     Err(e) => match e {
                         InterpreterError::FinalAnswer(answer) => {
+                            if let Some(tx) = &tx {
+                                let _ = tx.send(Status::CodeExecutionEnd(format!(
+                                    "Final answer: {}",
+                                    answer
+                                )));
+                            }
                             step_log.final_answer = Some(answer.clone());
-                            step_log.observations = Some(vec![format!("Final answer: {}", answer)]);
+                            step_log.observations =
+                                Some(vec![ToolObservation::new(format!("Final answer: {}", answer))]);
                             self.telemetry.log_final_answer(&answer);
-                            cx.span().set_attribute(opentelemetry::KeyValue::new(
+                            cx.span().set_attribute(KeyValue::new(
                                 "end_time",
                                 chrono::Utc::now().to_rfc3339(),
                             ));
-                            cx.span().end_with_timestamp(std::time::SystemTime::now());
+                            cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                             return Ok(Some(step_log.clone()));
                         }
                         _ => {
+                            if let Some(tx) = &tx {
+                                let _ = tx.send(Status::CodeExecutionEnd(format!("Error: {}", e)));
+                            }
                             step_log.error = Some(AgentError::Execution(e.to_string()));
                             tracing::info!("Error: {}", e);
                             self.telemetry.log_tool_result(&e.to_string(), false, &cx);
@@ -307,12 +588,12 @@ impl<M: Model + Send + Sync + 'static> Agent for CodeAgent<M> {
                     },
                 }
                 self.telemetry
-                    .log_observations(&step_log.observations.clone().unwrap_or_default());
-                cx.span().set_attribute(opentelemetry::KeyValue::new(
+                    .log_observations(&step_log.observation_contents());
+                cx.span().set_attribute(KeyValue::new(
                     "end_time",
                     chrono::Local::now().to_rfc3339(),
                 ));
-                cx.span().end_with_timestamp(std::time::SystemTime::now());
+                cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                 step_log
             }
             _ => {
@@ -0,0 +1,166 @@
+//! Fit the agent memory in the context window of the model.
+
+use crate::models::{
+    tokenizer::TokenCounter,
+    types::{Message, MessageRole},
+};
+
+/// Messages are not truncated below this number of tokens.
+const MIN_TRUNCATED_TOKENS: usize = 200;
+
+const TRUNCATION_NOTICE: &str = "\n....This content has been truncated to fit the context window.....";
+
+/// Drop and truncate messages until the memory fits in `budget` tokens.
+///
+/// The system prompt, the latest task and the latest step are kept. Older messages are dropped first, and an
+/// assistant message is always dropped together with its tool responses so the tool calls stay paired. If the
+/// memory still doesn't fit, the longest messages are truncated.
+pub fn fit_to_context_window(
+    messages: Vec<Message>,
+    budget: usize,
+    counter: &dyn TokenCounter,
+) -> Vec<Message> {
+    let mut total = counter.count_message_tokens(&messages);
+    if total <= budget {
+        return messages;
+    }
+
+    // Group each message with the tool responses that follow it.
+    let mut blocks: Vec<Vec<Message>> = Vec::new();
+    for message in messages {
+        match blocks.last_mut() {
+            Some(block) if message.role == MessageRole::ToolResponse => block.push(message),
+            _ => blocks.push(vec![message]),
+        }
+    }
+
+    let latest_task = blocks.iter().rposition(|block| {
+        block[0].role == MessageRole::User && block[0].content.starts_with("New Task: ")
+    });
+    let last = blocks.len() - 1;
+    let is_pinned = |i: usize, block: &[Message]| {
+        (i == 0 && block[0].role == MessageRole::System) || Some(i) == latest_task || i == last
+    };
+
+    let mut dropped = 0;
+    let mut kept = Vec::new();
+    for (i, block) in blocks.into_iter().enumerate() {
+        if total > budget && !is_pinned(i, &block) {
+            total -= counter.count_message_tokens(&block);
+            dropped += block.len();
+        } else {
+            kept.push(block);
+        }
+    }
+    let mut memory = kept.into_iter().flatten().collect::<Vec<_>>();
+
+    if dropped > 0 {
+        let notice = Message {
+            role: MessageRole::User,
+            content: format!(
+                "[{} earlier messages were removed to fit the context window]",
+                dropped
+            ),
+            tool_call_id: None,
+            tool_calls: None,
+        };
+        total += counter.count_message_tokens(std::slice::from_ref(&notice));
+        let position = usize::from(memory.first().is_some_and(|m| m.role == MessageRole::System));
+        memory.insert(position, notice);
+    }
+
+    // Halve the longest message until the memory fits. The system prompt is never truncated.
+    while total > budget {
+        let longest = memory
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.role != MessageRole::System)
+            .map(|(i, m)| (i, counter.count_tokens(&m.content)))
+            .max_by_key(|(_, tokens)| *tokens);
+        let Some((index, tokens)) = longest else {
+            break;
+        };
+        if tokens <= MIN_TRUNCATED_TOKENS {
+            break;
+        }
+        let message = &mut memory[index];
+        let chars = message.content.chars().count();
+        let content = message.content.chars().take(chars / 2).collect::<String>() + TRUNCATION_NOTICE;
+        let removed = tokens - counter.count_tokens(&content);
+        message.content = content;
+        total -= removed.min(total);
+    }
+    memory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        openai::{FunctionCall, ToolCall},
+        tokenizer::HeuristicCounter,
+        types::MessageBuilder,
+    };
+    use serde_json::json;
+
+    fn tool_call_step(id: &str, observation: &str) -> Vec<Message> {
+        vec![
+            Message {
+                role: MessageRole::Assistant,
+                content: String::new(),
+                tool_call_id: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: Some(id.to_string()),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name: "search".to_string(),
+                        arguments: json!({"query": id}),
+                    },
+                }]),
+            },
+            Message {
+                role: MessageRole::ToolResponse,
+                content: observation.to_string(),
+                tool_call_id: Some(id.to_string()),
+                tool_calls: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_fit_to_context_window() {
+        let counter = HeuristicCounter;
+        let mut messages = vec![
+            MessageBuilder::new(MessageRole::System, "You are a helpful agent").build(),
+            MessageBuilder::new(MessageRole::User, "New Task: What is the capital of France?").build(),
+        ];
+        messages.extend(tool_call_step("call_1", &"a".repeat(4000)));
+        messages.extend(tool_call_step("call_2", &"b".repeat(400)));
+
+        let untouched = fit_to_context_window(messages.clone(), 10_000, &counter);
+        assert_eq!(untouched.len(), messages.len());
+
+        let memory = fit_to_context_window(messages.clone(), 300, &counter);
+        assert!(counter.count_message_tokens(&memory) <= 300);
+        let roles = memory.iter().map(|m| m.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            vec![
+                MessageRole::System,
+                MessageRole::User,
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::ToolResponse,
+            ]
+        );
+        assert!(memory[1].content.contains("2 earlier messages were removed"));
+        assert_eq!(memory[4].tool_call_id.as_deref(), Some("call_2"));
+
+        // The latest step is truncated when dropping older steps is not enough.
+        messages.truncate(4);
+        messages.extend(tool_call_step("call_2", &"b".repeat(2000)));
+        let memory = fit_to_context_window(messages, 300, &counter);
+        assert!(counter.count_message_tokens(&memory) <= 300);
+        assert!(memory[4].content.ends_with(TRUNCATION_NOTICE));
+    }
+}
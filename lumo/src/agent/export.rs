@@ -0,0 +1,263 @@
+//! Export of agent runs as training data: JSONL trajectories, or conversations in the OpenAI chat fine-tuning
+//! format, to build datasets from successful runs.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::agent_step::{AgentStep, Step};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One record per run with its task, its steps, their tool calls and observations, and its answer.
+    #[default]
+    Trajectory,
+    /// One `{"messages": [...]}` record per run for OpenAI chat fine-tuning. Planning steps and steps that failed
+    /// are left out, so the model only learns from the steps that worked.
+    OpenAI,
+}
+
+/// Whether the run ended with a final answer.
+pub fn is_successful(steps: &[Step]) -> bool {
+    steps.iter().rev().find_map(|step| match step {
+        Step::ActionStep(step_log) => Some(step_log.final_answer.is_some()),
+        _ => None,
+    }) == Some(true)
+}
+
+/// The run as a record of the format.
+pub fn export_run(steps: &[Step], format: ExportFormat) -> Value {
+    match format {
+        ExportFormat::Trajectory => trajectory(steps),
+        ExportFormat::OpenAI => fine_tuning_example(steps),
+    }
+}
+
+/// The records as JSON Lines, one record per line.
+pub fn to_jsonl(records: &[Value]) -> String {
+    records
+        .iter()
+        .map(|record| format!("{}\n", record))
+        .collect()
+}
+
+fn trajectory(steps: &[Step]) -> Value {
+    let mut system_prompt = None;
+    let mut task = None;
+    let mut final_answer = None;
+    let mut records = Vec::new();
+    for step in steps {
+        match step {
+            Step::SystemPromptStep(prompt) => system_prompt = Some(prompt),
+            Step::TaskStep(content) => task = Some(content),
+            Step::PlanningStep(facts, plan) => records.push(json!({
+                "type": "planning",
+                "facts": facts,
+                "plan": plan,
+            })),
+            Step::ActionStep(step_log) => {
+                let observations = step_log.observation_contents();
+                let tool_calls = step_log
+                    .tool_call
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .map(|(i, call)| {
+                        json!({
+                            "name": call.function.name,
+                            "arguments": call.function.arguments,
+                            "observation": step_log.observation_of(i).map(|observation| &observation.content),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let mut record = json!({
+                    "type": "action",
+                    "step": step_log.step,
+                    "thought": step_log.llm_output,
+                    "tool_calls": tool_calls,
+                    "error": step_log.error.as_ref().map(|e| e.message()),
+                    "final_answer": step_log.final_answer,
+                    "duration_ms": step_log.duration_ms,
+                });
+                if tool_calls.is_empty() && !observations.is_empty() {
+                    record["observations"] = json!(observations);
+                }
+                records.push(record);
+                if step_log.final_answer.is_some() {
+                    final_answer = step_log.final_answer.as_ref();
+                }
+            }
+            Step::ToolCall(_) => {}
+        }
+    }
+    json!({
+        "task": task,
+        "system_prompt": system_prompt,
+        "steps": records,
+        "final_answer": final_answer,
+        "success": is_successful(steps),
+    })
+}
+
+fn fine_tuning_example(steps: &[Step]) -> Value {
+    let mut messages = Vec::new();
+    for step in steps {
+        match step {
+            Step::SystemPromptStep(prompt) => {
+                messages.push(json!({"role": "system", "content": prompt}))
+            }
+            Step::TaskStep(task) => messages.push(json!({"role": "user", "content": task})),
+            Step::ActionStep(step_log) if step_log.error.is_none() => {
+                action_messages(step_log, &mut messages)
+            }
+            Step::ActionStep(_) | Step::PlanningStep(..) | Step::ToolCall(_) => {}
+        }
+    }
+    json!({ "messages": messages })
+}
+
+/// The messages of a step: the tool calls and their results, then the final answer as a plain assistant message.
+fn action_messages(step_log: &AgentStep, messages: &mut Vec<Value>) {
+    let observations = step_log.observation_contents();
+    let tool_calls = step_log
+        .tool_call
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, call)| call.function.name != "final_answer")
+        .map(|(i, call)| {
+            let id = call
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("call_{}_{}", step_log.step, i));
+            (id, i, call)
+        })
+        .collect::<Vec<_>>();
+
+    if !tool_calls.is_empty() {
+        messages.push(json!({
+            "role": "assistant",
+            "content": step_log.llm_output.as_deref().filter(|output| !output.trim().is_empty()),
+            "tool_calls": tool_calls
+                .iter()
+                .map(|(id, _, call)| json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {
+                        "name": call.function.name,
+                        // Fine-tuning expects the arguments as a JSON string, like the API returns them.
+                        "arguments": match &call.function.arguments {
+                            Value::String(arguments) => arguments.clone(),
+                            arguments => arguments.to_string(),
+                        },
+                    },
+                }))
+                .collect::<Vec<_>>(),
+        }));
+        for (id, i, _) in &tool_calls {
+            messages.push(json!({
+                "role": "tool",
+                "tool_call_id": id,
+                "content": step_log
+                    .observation_of(*i)
+                    .map(|observation| observation.content.clone())
+                    .unwrap_or_default(),
+            }));
+        }
+    } else if step_log.final_answer.is_none() {
+        if let Some(output) = &step_log.llm_output {
+            messages.push(json!({"role": "assistant", "content": output}));
+        }
+        if !observations.is_empty() {
+            messages.push(json!({
+                "role": "user",
+                "content": format!("Observations: {}", observations.join("\n")),
+            }));
+        }
+    }
+    if let Some(answer) = &step_log.final_answer {
+        messages.push(json!({"role": "assistant", "content": answer}));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::ToolObservation,
+        errors::AgentError,
+        models::openai::{FunctionCall, ToolCall},
+    };
+
+    fn tool_call(id: &str, name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: Some(id.to_string()),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments,
+            },
+        }
+    }
+
+    fn steps() -> Vec<Step> {
+        let mut failed = AgentStep::new(1, None);
+        failed.tool_call = Some(vec![tool_call("call_0", "search", json!({}))]);
+        failed.error = Some(AgentError::Parsing("Missing query".to_string()));
+
+        let mut search = AgentStep::new(2, None);
+        search.tool_call = Some(vec![tool_call("call_1", "search", json!({"query": "capital of France"}))]);
+        search.observations = Some(vec![ToolObservation::success(
+            &search.tool_call.as_ref().unwrap()[0],
+            "Paris is the capital of France",
+        )]);
+
+        let mut answer = AgentStep::new(3, None);
+        answer.tool_call = Some(vec![tool_call("call_2", "final_answer", json!({"answer": "Paris"}))]);
+        answer.final_answer = Some("Paris".to_string());
+
+        vec![
+            Step::SystemPromptStep("You are Lumo".to_string()),
+            Step::TaskStep("What is the capital of France?".to_string()),
+            Step::ActionStep(failed),
+            Step::ActionStep(search),
+            Step::ActionStep(answer),
+        ]
+    }
+
+    #[test]
+    fn test_export_trajectory() {
+        let record = export_run(&steps(), ExportFormat::Trajectory);
+        assert_eq!(record["task"], "What is the capital of France?");
+        assert_eq!(record["final_answer"], "Paris");
+        assert_eq!(record["success"], true);
+        assert_eq!(record["steps"].as_array().unwrap().len(), 3);
+        assert_eq!(record["steps"][0]["error"], "Missing query");
+        assert_eq!(
+            record["steps"][1]["tool_calls"][0]["observation"],
+            "Paris is the capital of France"
+        );
+    }
+
+    #[test]
+    fn test_export_fine_tuning() {
+        let record = export_run(&steps(), ExportFormat::OpenAI);
+        let messages = record["messages"].as_array().unwrap();
+        let roles = messages
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        // The failed step is left out, and the final answer is a plain assistant message.
+        assert_eq!(roles, vec!["system", "user", "assistant", "tool", "assistant"]);
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            r#"{"query":"capital of France"}"#
+        );
+        assert_eq!(messages[3]["tool_call_id"], "call_1");
+        assert_eq!(messages[4]["content"], "Paris");
+
+        let jsonl = to_jsonl(&[record.clone(), record]);
+        assert_eq!(jsonl.lines().count(), 2);
+        assert!(!is_successful(&steps()[..4]));
+    }
+}
@@ -1,26 +1,40 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use futures::future::join_all;
-use opentelemetry::trace::{FutureExt, TraceContextExt};
-use serde_json::json;
+use futures::StreamExt;
 use std::collections::HashMap;
+use std::sync::Arc;
 use tokio::sync::broadcast;
 
 use crate::{
     agent::Agent,
     errors::AgentError,
+    guardrails::{Guardrail, Guardrails},
     models::{
-        model_traits::Model,
+        cancellation::CancellationToken,
+        model_traits::{Model, ModelResponse},
         openai::{FunctionCall, Status, ToolCall},
-        types::Message,
+        types::{Message, MessageRole},
     },
-    prompts::TOOL_CALLING_SYSTEM_PROMPT,
-    telemetry::AgentTelemetry,
-    tools::{AsyncTool, ToolFunctionInfo, ToolGroup, ToolInfo, ToolType},
+    prompts::{Prompts, RetryPrompts, ENGLISH_PROMPTS, TOOL_CALLING_SYSTEM_PROMPT},
+    telemetry::{
+        otel::{
+            trace::{FutureExt, TraceContextExt},
+            KeyValue,
+        },
+        AgentTelemetry, RunMetadata,
+    },
+    tools::{time_zone::Zone, AsyncTool, ToolGroup},
 };
 use tracing::instrument;
 
-use super::{agent_step::Step, multistep_agent::MultiStepAgent, AgentStep};
+use super::{
+    agent_step::Step, checkpoint::CheckpointStore, circuit_breaker::CircuitBreaker,
+    multistep_agent::MultiStepAgent,
+    tool_calling::ToolCallingMode,
+    tool_dependencies::{execution_waves, resolve_tool_call},
+    AgentStep, LongTermMemory, ObservationProcessor, Reproducibility, ResponseLanguage, RunRecorder, Scratchpad, StepOverrides, Synthesis,
+    ToolObservation, DEFAULT_FAILURE_THRESHOLD,
+};
 
 #[cfg(feature = "stream")]
 use super::agent_trait::AgentStream;
@@ -31,6 +45,17 @@ where
 {
     base_agent: MultiStepAgent<M>,
     telemetry: AgentTelemetry,
+    pipelining: bool,
+    speculation: Option<Speculation>,
+}
+
+/// A model response for the next step, generated while the tool calls of the current step were still running.
+struct Speculation {
+    step: usize,
+    /// The number of logs when the next step starts. A different number means something, e.g. a planning step,
+    /// was added that the response did not see.
+    logs_len: usize,
+    response: Box<dyn ModelResponse>,
 }
 
 impl<M: Model + Send + Sync + 'static> FunctionCallingAgent<M> {
@@ -63,8 +88,65 @@ impl<M: Model + Send + Sync + 'static> FunctionCallingAgent<M> {
         Ok(Self {
             base_agent,
             telemetry: AgentTelemetry::new("lumo"),
+            pipelining: false,
+            speculation: None,
         })
     }
+
+    /// The speculative response for the current step, if it was made for it and nothing was logged since.
+    fn take_speculation(&mut self) -> Option<Box<dyn ModelResponse>> {
+        self.speculation
+            .take()
+            .filter(|speculation| {
+                speculation.step == self.base_agent.get_step_number()
+                    && speculation.logs_len == self.base_agent.logs.len()
+            })
+            .map(|speculation| speculation.response)
+    }
+}
+
+/// The memory of the next step while some tool calls are still running: the pending calls get a placeholder
+/// observation, so every tool call still has a response.
+fn speculative_memory(
+    mut memory: Vec<Message>,
+    llm_output: &str,
+    tool_calls: &[ToolCall],
+    observations: &[Option<String>],
+) -> Vec<Message> {
+    memory.push(Message {
+        role: MessageRole::Assistant,
+        content: llm_output.to_string(),
+        tool_call_id: None,
+        tool_calls: Some(tool_calls.to_vec()),
+    });
+    for (tool_call, observation) in tool_calls.iter().zip(observations) {
+        let observation = observation.as_deref().unwrap_or(
+            "This tool call is still running. Its result will be available in the next step, do not call it again.",
+        );
+        memory.push(Message {
+            role: MessageRole::ToolResponse,
+            content: format!("Observation: {}", observation),
+            tool_call_id: tool_call.id.clone().filter(|id| !id.is_empty()),
+            tool_calls: None,
+        });
+    }
+    memory
+}
+
+/// A speculative response is only used when it continues with tool calls that don't repeat the pending ones. A
+/// final answer or a repeated call may depend on the results that were missing.
+fn is_usable_speculation(response: &dyn ModelResponse, pending: &[&ToolCall]) -> bool {
+    let Ok(tool_calls) = response.get_tools_used() else {
+        return false;
+    };
+    !tool_calls.is_empty()
+        && tool_calls.iter().all(|call| {
+            call.function.name != "final_answer"
+                && !pending.iter().any(|pending| {
+                    pending.function.name == call.function.name
+                        && pending.function.arguments == call.function.arguments
+                })
+        })
 }
 
 pub struct FunctionCallingAgentBuilder<'a, M>
@@ -81,6 +163,30 @@ where
     planning_interval: Option<usize>,
     history: Option<Vec<Message>>,
     logging_level: Option<log::LevelFilter>,
+    guardrails: Vec<Box<dyn Guardrail>>,
+    context_window: Option<usize>,
+    answer_validation: bool,
+    response_language: Option<ResponseLanguage>,
+    json_answer: bool,
+    time_zone: Option<Zone>,
+    retry_prompts: Option<RetryPrompts>,
+    observation_processor: Option<ObservationProcessor>,
+    checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    planning_model: Option<Box<dyn Model>>,
+    fallback_model: Option<Box<dyn Model>>,
+    reproducibility: Option<Reproducibility>,
+    cancellation: Option<CancellationToken>,
+    long_term_memory: Option<LongTermMemory>,
+    prompt_language: Option<&'a str>,
+    scratchpad: Option<Scratchpad>,
+    parse_retries: usize,
+    tool_calling: ToolCallingMode,
+    synthesis: Option<Synthesis>,
+    circuit_breaker: Option<usize>,
+    max_parallel_tool_calls: Option<usize>,
+    tags: Vec<String>,
+    metadata: HashMap<String, String>,
+    pipelining: bool,
 }
 
 impl<'a, M: Model + std::fmt::Debug + Send + Sync + 'static> FunctionCallingAgentBuilder<'a, M> {
@@ -96,6 +202,30 @@ impl<'a, M: Model + std::fmt::Debug + Send + Sync + 'static> FunctionCallingAgen
             planning_interval: None,
             history: None,
             logging_level: None,
+            guardrails: vec![],
+            context_window: None,
+            answer_validation: false,
+            response_language: None,
+            json_answer: false,
+            time_zone: None,
+            retry_prompts: None,
+            observation_processor: None,
+            checkpoint_store: None,
+            planning_model: None,
+            fallback_model: None,
+            reproducibility: None,
+            cancellation: None,
+            long_term_memory: None,
+            prompt_language: None,
+            scratchpad: None,
+            parse_retries: 0,
+            tool_calling: ToolCallingMode::default(),
+            synthesis: None,
+            circuit_breaker: Some(DEFAULT_FAILURE_THRESHOLD),
+            max_parallel_tool_calls: None,
+            tags: Vec::new(),
+            metadata: HashMap::new(),
+            pipelining: false,
         }
     }
     pub fn with_name(mut self, name: Option<&'a str>) -> Self {
@@ -130,27 +260,204 @@ impl<'a, M: Model + std::fmt::Debug + Send + Sync + 'static> FunctionCallingAgen
         self.history = history;
         self
     }
+    /// Print the steps with the colored logger of the crate at this level. Without a level the agent does not
+    /// install a `log` logger, so the application can use its own.
     pub fn with_logging_level(mut self, logging_level: Option<log::LevelFilter>) -> Self {
         self.logging_level = logging_level;
         self
     }
+    pub fn with_guardrails(mut self, guardrails: Vec<Box<dyn Guardrail>>) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+    /// The number of tokens the model accepts. The memory is trimmed to fit it, minus the tokens reserved for the
+    /// response. Defaults to the context window reported by the model, if any.
+    pub fn with_context_window(mut self, context_window: usize) -> Self {
+        self.context_window = Some(context_window);
+        self
+    }
+    /// Limit the number of tool calls of a step run at the same time.
+    pub fn with_max_parallel_tool_calls(mut self, max_parallel_tool_calls: usize) -> Self {
+        self.max_parallel_tool_calls = Some(max_parallel_tool_calls);
+        self
+    }
+    /// Ask the model to check that each final answer addresses the task and is supported by the observations. A
+    /// rejected answer is not returned; the agent keeps stepping until `max_steps`.
+    pub fn with_answer_validation(mut self, answer_validation: bool) -> Self {
+        self.answer_validation = answer_validation;
+        self
+    }
+    /// Answer in the language, or in the language of the task with `ResponseLanguage::Auto`. The language is added to
+    /// the system prompt, and a final answer in another language is sent back once to be written again. Off when
+    /// `None`.
+    pub fn with_response_language(mut self, response_language: Option<ResponseLanguage>) -> Self {
+        self.response_language = response_language;
+        self
+    }
+    /// Ask for the final answer as a single JSON value, e.g. an array of objects for a table, so it can be parsed and
+    /// rendered while it is streamed with [`PartialJson`](crate::models::partial_json::PartialJson).
+    pub fn with_json_answer(mut self, json_answer: bool) -> Self {
+        self.json_answer = json_answer;
+        self
+    }
+    /// The time zone of the `{{current_time}}` of the system prompt, e.g. `Some("Asia/Tokyo".parse()?)`. The time
+    /// zone of the system when `None`.
+    pub fn with_time_zone(mut self, time_zone: Option<Zone>) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+    /// Replace the guidance written to the memory after errors, e.g. to tell the agent which tool to search with after
+    /// a page is not found.
+    pub fn with_retry_prompts(mut self, retry_prompts: RetryPrompts) -> Self {
+        self.retry_prompts = Some(retry_prompts);
+        self
+    }
+    /// Remove the passages already seen in earlier observations from the memory, and compress the long observations
+    /// if the processor is set up for it.
+    pub fn with_observation_processor(mut self, processor: ObservationProcessor) -> Self {
+        self.observation_processor = Some(processor);
+        self
+    }
+    /// Save the state of the agent to the store after each step, to resume the run after a crash with
+    /// `Agent::resume`.
+    pub fn with_checkpoint_store(mut self, store: Arc<dyn CheckpointStore>) -> Self {
+        self.checkpoint_store = Some(store);
+        self
+    }
+    /// Use this model for the planning steps, e.g. a cheap and fast one while the action steps use the model of the
+    /// agent.
+    pub fn with_planning_model(mut self, model: impl Model) -> Self {
+        self.planning_model = Some(Box::new(model));
+        self
+    }
+    /// Call this model when the model of a step fails, with the same messages.
+    pub fn with_fallback_model(mut self, model: impl Model) -> Self {
+        self.fallback_model = Some(Box::new(model));
+        self
+    }
+    /// Record the model responses and the tool calls of each run into a manifest, read with `Agent::run_manifest`,
+    /// or answer the tool calls with the outputs of a recorded manifest to execute its run again. Off when `None`.
+    pub fn with_reproducibility(mut self, reproducibility: Option<Reproducibility>) -> Self {
+        self.reproducibility = reproducibility;
+        self
+    }
+    /// Cancels the model requests and the next steps of the runs, e.g. when the user stops the run.
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+    /// Recall the memories of earlier runs relevant to each task into its system prompt, and remember the facts of
+    /// each run once it has its answer. Off when `None`.
+    pub fn with_long_term_memory(mut self, long_term_memory: Option<LongTermMemory>) -> Self {
+        self.long_term_memory = long_term_memory;
+        self
+    }
+    /// Use the built-in prompts in the language, e.g. `Some("fr")`, for the planning steps and for the system prompt
+    /// when none is set. English when `None`, see [`Prompts::languages`] for the others.
+    pub fn with_prompt_language(mut self, prompt_language: Option<&'a str>) -> Self {
+        self.prompt_language = prompt_language;
+        self
+    }
+    /// Keep the notes shared with the workers the agent coordinates, read with `Agent::scratchpad`. Set by
+    /// [`OrchestratorAgentBuilder`](super::OrchestratorAgentBuilder).
+    pub fn with_scratchpad(mut self, scratchpad: Scratchpad) -> Self {
+        self.scratchpad = Some(scratchpad);
+        self
+    }
+    /// When the text of a response is not a valid tool call, send it back to the model with a prompt about what is
+    /// wrong, up to `parse_retries` times, instead of taking the text as the final answer. Off by default.
+    pub fn with_parse_retries(mut self, parse_retries: usize) -> Self {
+        self.parse_retries = parse_retries;
+        self
+    }
+    /// Send the tools in the request, or describe them in the prompt and parse the tool calls from the text of the
+    /// responses, for models without native tool calling. `Auto` by default, which asks the model.
+    pub fn with_tool_calling(mut self, tool_calling: ToolCallingMode) -> Self {
+        self.tool_calling = tool_calling;
+        self
+    }
+    /// Write the final answer from the observations with a model call without tools once the agent has gathered
+    /// enough of them, e.g. when the model repeats its tool calls. Off by default.
+    pub fn with_synthesis(mut self, synthesis: Synthesis) -> Self {
+        self.synthesis = Some(synthesis);
+        self
+    }
+    /// Stop offering a tool to the model for the rest of the run after this many consecutive failures, e.g. when
+    /// its API key expired. On by default after 3 failures, `None` turns it off.
+    pub fn with_circuit_breaker(mut self, threshold: Option<usize>) -> Self {
+        self.circuit_breaker = threshold;
+        self
+    }
+    /// Tags added to the telemetry spans of the agent, to filter traces.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+    /// Metadata added to the telemetry spans of the agent, e.g. a user or tenant id.
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata.extend(metadata);
+        self
+    }
+    /// Overlap the model and the tool calls: once the first tool call of a step returns, the model call of the next
+    /// step starts with the observations so far while the other tool calls run. Its response is used when it
+    /// continues with other tool calls, and discarded when it answers or repeats a pending call.
+    pub fn with_pipelining(mut self, pipelining: bool) -> Self {
+        self.pipelining = pipelining;
+        self
+    }
     pub fn build(self) -> Result<FunctionCallingAgent<M>> {
-        FunctionCallingAgent::new(
+        let prompts = match self.prompt_language {
+            Some(language) => Prompts::try_for_language(language)?,
+            None => &ENGLISH_PROMPTS,
+        };
+        let recorder = self.reproducibility.map(RunRecorder::new);
+        let tools = match &recorder {
+            Some(recorder) => recorder.wrap_tools(self.tools),
+            None => self.tools,
+        };
+        let mut agent = FunctionCallingAgent::new(
             self.name,
             self.model,
-            self.tools,
-            self.system_prompt,
+            tools,
+            self.system_prompt.or(Some(prompts.tool_calling_system_prompt)),
             self.managed_agents,
             self.description,
             self.max_steps,
             self.planning_interval,
             self.history,
             self.logging_level,
-        )
+        )?;
+        agent.base_agent.guardrails = Guardrails::new(self.guardrails);
+        agent.base_agent.context_window = self.context_window;
+        agent.base_agent.answer_validation = self.answer_validation;
+        agent.base_agent.response_language = self.response_language;
+        agent.base_agent.json_answer = self.json_answer;
+        agent.base_agent.time_zone = self.time_zone;
+        agent.base_agent.retry_prompts = self.retry_prompts;
+        agent.base_agent.observation_processor = self.observation_processor;
+        agent.base_agent.checkpoint_store = self.checkpoint_store;
+        agent.base_agent.planning_model = self.planning_model;
+        agent.base_agent.fallback_model = self.fallback_model;
+        agent.base_agent.recorder = recorder;
+        agent.base_agent.cancellation = self.cancellation;
+        agent.base_agent.long_term_memory = self.long_term_memory;
+        agent.base_agent.prompts = prompts;
+        agent.base_agent.scratchpad = self.scratchpad;
+        agent.base_agent.parse_retries = self.parse_retries;
+        agent.base_agent.tool_calling = self.tool_calling;
+        agent.base_agent.synthesis = self.synthesis;
+        agent.base_agent.circuit_breaker = self.circuit_breaker.map(CircuitBreaker::new);
+        agent.base_agent.max_parallel_tool_calls = self.max_parallel_tool_calls;
+        agent.pipelining = self.pipelining;
+        agent
+            .telemetry
+            .set_run_metadata(RunMetadata::new(self.tags, self.metadata));
+        Ok(agent)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl<M: Model + std::fmt::Debug + Send + Sync + 'static> Agent for FunctionCallingAgent<M> {
     fn name(&self) -> &'static str {
         self.base_agent.name()
@@ -191,6 +498,66 @@ impl<M: Model + std::fmt::Debug + Send + Sync + 'static> Agent for FunctionCalli
     fn model(&self) -> &dyn Model {
         self.base_agent.model()
     }
+    fn guardrails(&self) -> Option<&Guardrails> {
+        self.base_agent.guardrails()
+    }
+    fn context_window(&self) -> Option<usize> {
+        self.base_agent.context_window()
+    }
+    fn answer_validation(&self) -> bool {
+        self.base_agent.answer_validation()
+    }
+    fn response_language(&self) -> Option<&ResponseLanguage> {
+        self.base_agent.response_language()
+    }
+    fn json_answer(&self) -> bool {
+        self.base_agent.json_answer()
+    }
+    fn time_zone(&self) -> Option<&Zone> {
+        self.base_agent.time_zone()
+    }
+    fn recorder(&self) -> Option<&RunRecorder> {
+        self.base_agent.recorder()
+    }
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.base_agent.cancellation()
+    }
+    fn set_cancellation(&mut self, cancellation: Option<CancellationToken>) {
+        self.base_agent.set_cancellation(cancellation);
+    }
+    fn long_term_memory(&self) -> Option<&LongTermMemory> {
+        self.base_agent.long_term_memory()
+    }
+    fn set_long_term_memory(&mut self, long_term_memory: Option<LongTermMemory>) {
+        self.base_agent.set_long_term_memory(long_term_memory);
+    }
+    fn scratchpad(&self) -> Option<&Scratchpad> {
+        self.base_agent.scratchpad()
+    }
+    fn retry_prompts(&self) -> Option<&RetryPrompts> {
+        self.base_agent.retry_prompts()
+    }
+    fn observation_processor(&self) -> Option<&ObservationProcessor> {
+        self.base_agent.observation_processor()
+    }
+    fn set_step_overrides(&mut self, overrides: StepOverrides) {
+        self.base_agent.set_step_overrides(overrides);
+    }
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        self.base_agent.shutdown().await
+    }
+    fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.base_agent.checkpoint_store()
+    }
+    fn checkpoint_id(&self) -> Option<&str> {
+        self.base_agent.checkpoint_id()
+    }
+    fn set_checkpoint_id(&mut self, checkpoint_id: Option<String>) {
+        self.base_agent.set_checkpoint_id(checkpoint_id);
+    }
+    fn set_history(&mut self, history: Option<Vec<Message>>) {
+        self.base_agent.set_history(history);
+    }
     fn set_planning_interval(&mut self, planning_interval: Option<usize>) {
         self.base_agent.set_planning_interval(planning_interval);
     }
@@ -217,78 +584,77 @@ impl<M: Model + std::fmt::Debug + Send + Sync + 'static> Agent for FunctionCalli
         match log_entry {
             Step::ActionStep(step_log) => {
                 let cx = self.telemetry.start_step(self.get_step_number() as i64);
+                if self.get_step_number() == 1 {
+                    self.base_agent.reset_circuit_breaker();
+                }
 
-                let agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                let overrides = self.base_agent.take_step_overrides();
+                let mut agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                overrides.apply_to_memory(&mut agent_memory);
+                if self.get_step_number() == 1 {
+                    self.base_agent.check_input_guardrails().await?;
+                }
                 self.base_agent.input_messages = Some(agent_memory.clone());
                 step_log.agent_memory = Some(agent_memory.clone());
                 self.telemetry
                     .log_agent_memory(&serde_json::to_value(&agent_memory).unwrap_or_default());
 
-                let mut tools = self
+                let tool_infos = self
                     .base_agent
                     .tools
                     .iter()
-                    .map(|tool| tool.tool_info())
-                    .collect::<Vec<_>>();
-                let managed_agents = self
-                    .base_agent
-                    .managed_agents
-                    .iter()
-                    .map(|agent| ToolInfo {
-                        tool_type: ToolType::Function,
-                        function: ToolFunctionInfo {
-                            name: agent.name().to_string(),
-                            description: agent.description().to_string(),
-                            parameters: json!({
-                                "type": "object",
-                                "properties": {
-                                    "task": {
-                                        "type": "string",
-                                        "description": "The task to perform"
-                                    }
-                                }
-                            }),
-                        },
+                    .filter(|tool| {
+                        !self.base_agent.is_tool_unavailable(tool.name())
+                            && overrides.allows(tool.name())
                     })
+                    .map(|tool| tool.tool_info())
                     .collect::<Vec<_>>();
+                if let Some(breaker) = &self.base_agent.circuit_breaker {
+                    self.telemetry.log_unavailable_tools(breaker.open_tools(), &cx);
+                }
 
-                tools.extend(managed_agents);
-
-                let model_message = match tx {
-                    None => {
-                        self.base_agent
-                            .model
-                            .run(
-                                self.base_agent.input_messages.as_ref().unwrap().clone(),
-                                self.base_agent.history.clone(),
-                                tools,
-                                None,
-                                Some(HashMap::from([(
-                                    "stop".to_string(),
-                                    vec!["Observation:".to_string()],
-                                )])),
-                            )
-                            .with_context(cx.clone())
-                            .await?
-                    }
-                    Some(tx) => {
+                let model_start = crate::runtime::Instant::now();
+                // A speculative response was started without the overrides.
+                let speculation = self.take_speculation().filter(|_| overrides.is_empty());
+                // Enough was gathered: the answer is written from the observations instead of calling the tools.
+                if let Some(trigger) = self
+                    .base_agent
+                    .synthesis_before_step()
+                    .filter(|_| overrides.is_empty())
+                {
+                    let answer = self
+                        .base_agent
+                        .synthesize(step_log, agent_memory, trigger, tx)
+                        .with_context(cx.clone())
+                        .await?;
+                    self.telemetry.log_final_answer(&answer);
+                    cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
+                    return Ok(Some(step_log.clone()));
+                }
+                step_log.speculative = speculation.is_some();
+                let model_message = match (speculation, tx.clone()) {
+                    (Some(response), _) => response,
+                    (None, tx) => {
                         self.base_agent
-                            .model
-                            .run_stream(
+                            .run_model(
                                 self.base_agent.input_messages.as_ref().unwrap().clone(),
-                                self.base_agent.history.clone(),
-                                tools,
-                                None,
+                                tool_infos.clone(),
                                 Some(HashMap::from([(
                                     "stop".to_string(),
                                     vec!["Observation:".to_string()],
                                 )])),
                                 tx,
                             )
+                            .with_context(cx.clone())
                             .await?
                     }
                 };
                 step_log.llm_output = Some(model_message.get_response().unwrap_or_default());
+                step_log.reasoning = model_message.get_reasoning();
+                step_log.record_model_call(
+                    model_start.elapsed(),
+                    self.base_agent.model.token_counter().as_ref(),
+                );
                 let mut observations = Vec::new();
                 let mut tools = model_message.get_tools_used()?;
                 step_log.tool_call = if tools.is_empty() {
@@ -299,136 +665,301 @@ impl<M: Model + std::fmt::Debug + Send + Sync + 'static> Agent for FunctionCalli
 
                 self.telemetry.log_tool_calls(&tools, &cx);
 
-                if let Ok(response) = model_message.get_response() {
-                    if !response.trim().is_empty() {
-                        if let Ok(action) = parse_response(&response) {
-                            tools = vec![ToolCall {
-                                id: Some(format!("call_{}", nanoid::nanoid!())),
-                                call_type: Some("function".to_string()),
-                                function: FunctionCall {
-                                    name: action["name"].as_str().unwrap_or_default().to_string(),
-                                    arguments: action["arguments"].clone(),
-                                },
-                            }];
-                            step_log.tool_call = Some(tools.clone());
-                            self.telemetry.log_tool_calls(&tools, &cx);
+                if let Ok(mut response) = model_message.get_response() {
+                    let mut synthesis = self.base_agent.synthesis_for_response(&tools, &response);
+                    let parsed = match parse_tool_calls(&response) {
+                        Ok(tool_calls) => Some(tool_calls),
+                        Err(failure)
+                            if tools.is_empty()
+                                && synthesis.is_none()
+                                && self.base_agent.parse_retries > 0 =>
+                        {
+                            let (repaired, tool_calls) = self
+                                .base_agent
+                                .repair_tool_call(response, failure, tool_infos)
+                                .with_context(cx.clone())
+                                .await?;
+                            response = repaired;
+                            step_log.llm_output = Some(response.clone());
+                            Some(tool_calls).filter(|tool_calls| !tool_calls.is_empty())
                         }
+                        Err(_) => None,
+                    };
+                    if let Some(parsed) = parsed {
+                        tools = parsed;
+                        step_log.tool_call = Some(tools.clone());
+                        self.telemetry.log_tool_calls(&tools, &cx);
+                        synthesis =
+                            synthesis.or_else(|| self.base_agent.synthesis_for_response(&tools, &response));
+                    }
+                    if let Some(trigger) = synthesis {
+                        let answer = self
+                            .base_agent
+                            .synthesize(step_log, agent_memory, trigger, tx)
+                            .with_context(cx.clone())
+                            .await?;
+                        self.telemetry.log_final_answer(&answer);
+                        cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
+                        return Ok(Some(step_log.clone()));
                     }
                     if tools.is_empty() {
                         self.base_agent.write_inner_memory_from_logs(None)?;
                         step_log.final_answer = Some(response.clone());
-                        step_log.observations = Some(vec![response.clone()]);
+                        step_log.observations = Some(vec![ToolObservation::new(response.clone())]);
                         self.telemetry.log_final_answer(&response);
-                        cx.span().set_attribute(opentelemetry::KeyValue::new(
+                        cx.span().set_attribute(KeyValue::new(
                             "end_time",
                             chrono::Utc::now().to_rfc3339(),
                         ));
-                        cx.span().end_with_timestamp(std::time::SystemTime::now());
+                        cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                         return Ok(Some(step_log.clone()));
                     }
                 }
 
                 if tools.is_empty() {
                     step_log.tool_call = None;
-                    observations = vec!["No tool call was made. If this is the final answer, use the final_answer tool to return your answer.".to_string()];
+                    observations = vec![ToolObservation::new("No tool call was made. If this is the final answer, use the final_answer tool to return your answer.")];
                 } else {
                     let tools_ref = &self.base_agent.tools;
-                    let mut futures = vec![];
-                    let managed_agent_names = self
-                        .base_agent
-                        .managed_agents
-                        .iter()
-                        .map(|agent| agent.name())
-                        .collect::<Vec<_>>();
-
+                    // The calls that use the results of other calls of the step run after them, in waves.
+                    let waves = execution_waves(&tools);
+                    let wave_count = waves.iter().flatten().max().map_or(1, |last| last + 1);
+                    let mut outputs: Vec<Option<Result<String, String>>> = vec![None; tools.len()];
                     let mut called_tools = Vec::new();
-                    for tool in &tools {
-                        let function_name = tool.function.name.clone();
-                        match function_name.as_str() {
-                            "final_answer" => {
-                                let answer = tools_ref.call(&tool.function).await?;
-                                step_log.final_answer = Some(answer.clone());
-                                step_log.observations = Some(vec![answer.clone()]);
-                                self.telemetry.log_final_answer(&answer);
-                                cx.span().set_attribute(opentelemetry::KeyValue::new(
-                                    "end_time",
-                                    chrono::Utc::now().to_rfc3339(),
-                                ));
-                                cx.span().end_with_timestamp(std::time::SystemTime::now());
-                                return Ok(Some(step_log.clone()));
+                    let mut called_positions = Vec::new();
+                    let mut results = Vec::new();
+                    for wave in 0..wave_count {
+                        // The calls of the wave, with the results of the earlier waves in their arguments.
+                        let mut wave_tools = Vec::new();
+                        for (position, (tool, tool_wave)) in tools.iter().zip(&waves).enumerate() {
+                            let resolved = match tool_wave {
+                                Ok(tool_wave) if *tool_wave == wave => resolve_tool_call(tool, &outputs),
+                                // The calls that cannot run are answered in the first wave.
+                                Err(e) if wave == 0 => Err(e.clone()),
+                                _ => continue,
+                            };
+                            wave_tools.push((position, resolved));
+                        }
+
+                        let offset = called_tools.len();
+                        let mut futures = vec![];
+                        for (position, resolved) in &wave_tools {
+                            let tool = resolved.as_ref().unwrap_or(&tools[*position]);
+                            if resolved.is_ok() {
+                                self.base_agent
+                                    .guardrails
+                                    .check_tool_call(&tool.function)
+                                    .await?;
                             }
-                            _ => {
-                                if !managed_agent_names.contains(&function_name.as_str()) {
-                                    let tool_call = tools_ref.call(&tool.function);
+                            let function_name = tool.function.name.clone();
+                            match function_name.as_str() {
+                                "final_answer" if resolved.is_ok() => {
+                                    let answer = tools_ref.call(&tool.function).await?;
+                                    step_log.final_answer = Some(answer.clone());
+                                    step_log.observations =
+                                        Some(vec![ToolObservation::success(tool, answer.clone())]);
+                                    self.telemetry.log_final_answer(&answer);
+                                    cx.span().set_attribute(KeyValue::new(
+                                        "end_time",
+                                        chrono::Utc::now().to_rfc3339(),
+                                    ));
+                                    cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
+                                    return Ok(Some(step_log.clone()));
+                                }
+                                _ => {
+                                    // A call to an unavailable tool, or that cannot run, is answered without
+                                    // running it.
+                                    let unavailable = match resolved {
+                                        Err(e) => Some(AgentError::Parsing(e.clone())),
+                                        Ok(_) => self
+                                            .base_agent
+                                            .circuit_breaker
+                                            .as_ref()
+                                            .filter(|breaker| breaker.is_open(&function_name))
+                                            .map(|breaker| {
+                                                AgentError::Execution(breaker.unavailable_message(&function_name))
+                                            }),
+                                    };
+                                    let call = tools_ref.call_with_status(&tool.function, tx.clone());
+                                    let index = offset + futures.len();
+                                    let events = tx.clone();
+                                    let called = tool.clone();
+                                    let tool_call = async move {
+                                        if let Some(tx) = &events {
+                                            let _ = tx.send(Status::tool_call_started(&called));
+                                        }
+                                        let start = crate::runtime::Instant::now();
+                                        let ran = unavailable.is_none();
+                                        let result = match unavailable {
+                                            Some(e) => Err(e),
+                                            None => call.await,
+                                        };
+                                        let latency = start.elapsed();
+                                        if let Some(tx) = &events {
+                                            let _ = tx.send(match &result {
+                                                Ok(observation) => {
+                                                    Status::tool_call_result(&called, observation, latency, true)
+                                                }
+                                                // The arguments are checked before the tool runs.
+                                                Err(e) if !ran || matches!(e, AgentError::Parsing(_)) => {
+                                                    Status::tool_call_failed(&called, e.to_string())
+                                                }
+                                                Err(e) => {
+                                                    Status::tool_call_result(&called, &e.to_string(), latency, false)
+                                                }
+                                            });
+                                        }
+                                        (index, (result, latency))
+                                    };
                                     tracing::info!(
                                         tool = %function_name,
                                         args = ?tool.function.arguments,
                                         "Executing tool call:"
                                     );
-                                    called_tools.push(tool.function.clone());
+                                    called_tools.push(tool.clone());
+                                    called_positions.push(*position);
                                     futures.push(tool_call);
-                                } else {
-                                    let task = tool.function.arguments.get("task");
-                                    if let Some(task) = task {
-                                        if let Some(task_str) = task.as_str() {
-                                            tracing::info!(
-                                                tool = %function_name,
-                                                args = ?tool.function.arguments,
-                                                "Executing tool call: Agent Selected {}",
-                                                function_name
-                                            );
-                                            let result = self
-                                                .base_agent
-                                                .managed_agents
-                                                .iter_mut()
-                                                .find(|agent| {
-                                                    agent.name() == function_name.as_str()
-                                                })
-                                                .unwrap()
-                                                .run(task_str, true)
-                                                .await?;
-                                            observations.push(result);
-                                        }
-                                    }
                                 }
                             }
                         }
-                    }
-                    // }
 
-                    let results = join_all(futures).await;
-                    for (i, result) in results.into_iter().enumerate() {
+                        // `buffer_unordered` limits the calls run at the same time. The results are put back in the
+                        // order of the tool calls, so each observation is paired with the id of its call.
+                        let limit = self
+                            .base_agent
+                            .max_parallel_tool_calls
+                            .unwrap_or(futures.len())
+                            .max(1);
+                        results.extend(futures.iter().map(|_| None));
+                        let mut pending = futures::stream::iter(futures).buffer_unordered(limit);
+                        if self.pipelining && wave_count == 1 && called_tools.len() > 1 {
+                            if let Some((i, result)) = pending.next().await {
+                                results[i] = Some(result);
+                            }
+                            // Start the model call of the next step with the observations so far, while the other
+                            // tool calls run.
+                            let observations = results
+                                .iter()
+                                .map(|result| {
+                                    result.as_ref().map(|(result, _)| match result {
+                                        Ok(observation) => observation.clone(),
+                                        Err(e) => e.to_string(),
+                                    })
+                                })
+                                .collect::<Vec<_>>();
+                            let still_running = called_tools
+                                .iter()
+                                .zip(&observations)
+                                .filter(|(_, observation)| observation.is_none())
+                                .map(|(tool_call, _)| tool_call)
+                                .collect::<Vec<_>>();
+                            let memory = speculative_memory(
+                                agent_memory.clone(),
+                                step_log.llm_output.as_deref().unwrap_or_default(),
+                                &called_tools,
+                                &observations,
+                            );
+                            let tool_infos = tools_ref
+                                .iter()
+                                .filter(|tool| !self.base_agent.is_tool_unavailable(tool.name()))
+                                .map(|tool| tool.tool_info())
+                                .collect();
+                            let speculation = self.base_agent.run_model(
+                                memory,
+                                tool_infos,
+                                Some(HashMap::from([(
+                                    "stop".to_string(),
+                                    vec!["Observation:".to_string()],
+                                )])),
+                                None,
+                            );
+                            let (rest, response) =
+                                futures::join!(pending.collect::<Vec<_>>(), speculation);
+                            for (i, result) in rest {
+                                results[i] = Some(result);
+                            }
+                            match response {
+                                Ok(response) if is_usable_speculation(response.as_ref(), &still_running) => {
+                                    tracing::info!("Using the speculative response for the next step");
+                                    self.speculation = Some(Speculation {
+                                        step: self.base_agent.get_step_number() + 1,
+                                        logs_len: self.base_agent.logs.len() + 1,
+                                        response,
+                                    });
+                                }
+                                Ok(_) => tracing::info!(
+                                    "Discarding the speculative response, it may depend on the pending tool calls"
+                                ),
+                                Err(e) => tracing::warn!(error = %e, "The speculative model call failed"),
+                            }
+                        } else {
+                            for (i, result) in pending.collect::<Vec<_>>().await {
+                                results[i] = Some(result);
+                            }
+                        }
+
+                        for i in offset..results.len() {
+                            outputs[called_positions[i]] = results[i].as_ref().map(|(result, _)| match result {
+                                Ok(output) => Ok(output.clone()),
+                                Err(e) => Err(e.to_string()),
+                            });
+                        }
+                    }
+                    // The observations are in the order of the tool calls, whatever the wave they ran in.
+                    let mut calls = called_positions
+                        .into_iter()
+                        .zip(called_tools)
+                        .zip(results)
+                        .collect::<Vec<_>>();
+                    calls.sort_by_key(|((position, _), _)| *position);
+                    let (called_tools, results): (Vec<_>, Vec<_>) = calls
+                        .into_iter()
+                        .map(|((_, tool), result)| (tool, result))
+                        .unzip();
+                    for (i, (result, duration)) in results.into_iter().flatten().enumerate() {
+                        let name = &called_tools[i].function.name;
+                        step_log.record_tool_call(&called_tools[i], duration);
                         let cx = self.telemetry.log_tool_execution(
-                            &called_tools[i].name,
-                            &called_tools[i].arguments,
+                            name,
+                            &called_tools[i].function.arguments,
                             &cx,
                         );
+                        // Invalid arguments are a mistake of the model, not a failure of the tool.
+                        let failed = matches!(&result, Err(e) if !matches!(e, AgentError::Parsing(_)));
                         match result {
                             Ok(result) => {
-                                observations.push(result.clone());
+                                observations.push(ToolObservation::success(&called_tools[i], result.clone()));
                                 self.telemetry.log_tool_result(&result, true, &cx);
                             }
                             Err(e) => {
-                                observations.push(e.to_string());
+                                observations.push(ToolObservation::error(&called_tools[i], e.to_string()));
                                 self.telemetry.log_tool_result(&e.to_string(), false, &cx);
                             }
                         }
-                        cx.span().set_attribute(opentelemetry::KeyValue::new(
+                        if let Some((failures, message)) =
+                            self.base_agent.record_tool_outcome(name, failed)
+                        {
+                            self.telemetry.log_circuit_breaker_open(name, failures, &cx);
+                            if let Some(observation) = observations.last_mut() {
+                                observation.content.push_str(&format!("\n{}", message));
+                            }
+                        }
+                        cx.span().set_attribute(KeyValue::new(
                             "end_time",
                             chrono::Local::now().to_rfc3339(),
                         ));
-                        cx.span().end_with_timestamp(std::time::SystemTime::now());
+                        cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                     }
                 }
 
                 step_log.observations = Some(observations);
                 self.telemetry
-                    .log_observations(&step_log.observations.clone().unwrap_or_default());
-                cx.span().set_attribute(opentelemetry::KeyValue::new(
+                    .log_observations(&step_log.observation_contents());
+                cx.span().set_attribute(KeyValue::new(
                     "end_time",
                     chrono::Local::now().to_rfc3339(),
                 ));
-                cx.span().end_with_timestamp(std::time::SystemTime::now());
+                cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                 Ok(Some(step_log.clone()))
             }
             _ => {
@@ -438,35 +969,101 @@ impl<M: Model + std::fmt::Debug + Send + Sync + 'static> Agent for FunctionCalli
     }
 }
 
+/// The end of the JSON object at the start of `text`, after its closing brace. Braces in strings do not count.
+fn json_object_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The JSON objects written after each `Action:` and in each `<tool_call>` block of the text, in order. An object
+/// that is not closed runs to the end of the text.
+fn extract_action_jsons(text: &str) -> Vec<&str> {
+    let mut markers = ["Action:", "<tool_call>"]
+        .iter()
+        .flat_map(|marker| {
+            text.match_indices(marker)
+                .map(move |(i, _)| i + marker.len())
+        })
+        .collect::<Vec<_>>();
+    markers.sort_unstable();
+    let mut objects = Vec::new();
+    let mut end = 0;
+    for marker in markers {
+        let Some(start) = text[marker..].find('{').map(|start| marker + start) else {
+            continue;
+        };
+        // `Action: <tool_call>{...}</tool_call>` is a single call.
+        if start < end {
+            continue;
+        }
+        end = json_object_end(&text[start..]).map_or(text.len(), |length| start + length);
+        objects.push(&text[start..end]);
+    }
+    objects
+}
+
 fn extract_action_json(text: &str) -> Option<String> {
-    // First try to extract from Action: format
-    if let Some(action_part) = text.split("Action:").nth(1) {
-        let start = action_part.find('{');
-        let end = action_part.rfind('}');
-        if let (Some(start_idx), Some(end_idx)) = (start, end) {
-            let json_str = action_part[start_idx..=end_idx].to_string();
-            // Clean and escape the string
-            return Some(json_str.replace('\n', "\\n").replace('\r', "\\r"));
-        }
-    }
-
-    // If no Action: format found, try extracting from tool_call tags
-    if let Some(tool_call_part) = text.split("<tool_call>").nth(1) {
-        if let Some(content) = tool_call_part.split("</tool_call>").next() {
-            let trimmed = content.trim();
-            if trimmed.starts_with('{') && trimmed.ends_with('}') {
-                // Clean and escape the string
-                return Some(trimmed.replace('\n', "\\n").replace('\r', "\\r"));
+    extract_action_jsons(text).first().map(|json| json.to_string())
+}
+
+/// Escape the line breaks and tabs written as they are in the strings of the JSON, which models often do.
+fn escape_control_characters(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut backslash = false;
+    for c in json.chars() {
+        match c {
+            '\n' if in_string => escaped.push_str("\\n"),
+            '\r' if in_string => escaped.push_str("\\r"),
+            '\t' if in_string => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+        if in_string {
+            match c {
+                _ if backslash => backslash = false,
+                '\\' => backslash = true,
+                '"' => in_string = false,
+                _ => {}
             }
+        } else if c == '"' {
+            in_string = true;
         }
     }
+    escaped
+}
 
-    None
+fn parse_action_json(json: &str) -> Result<serde_json::Value, serde_json::Error> {
+    serde_json::from_str(json)
+        .or_else(|e| serde_json::from_str(&escape_control_characters(json)).map_err(|_| e))
 }
-// Example usage in your parse_response function:
+
 pub fn parse_response(response: &str) -> Result<serde_json::Value, AgentError> {
     if let Some(json_str) = extract_action_json(response) {
-        serde_json::from_str(&json_str).map_err(|e| AgentError::Parsing(e.to_string()))
+        parse_action_json(&json_str).map_err(|e| AgentError::Parsing(e.to_string()))
     } else {
         Err(AgentError::Parsing(
             "No valid action JSON found".to_string(),
@@ -474,12 +1071,1225 @@ pub fn parse_response(response: &str) -> Result<serde_json::Value, AgentError> {
     }
 }
 
+/// Why the text of a model response is not a tool call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseFailure {
+    /// The response is empty.
+    Empty,
+    /// The response has no `Action:` or `<tool_call>` with a JSON object.
+    NoAction,
+    /// The JSON of the tool call is malformed.
+    InvalidJson(String),
+    /// The tool call has no tool name.
+    MissingName,
+}
+
+impl ParseFailure {
+    /// The message asking the model to fix its response.
+    pub fn corrective_prompt(&self, tool_names: &[String]) -> String {
+        let format = "Reply with `Action:` followed by a JSON object with the \"name\" of the tool and its \"arguments\", e.g. Action: {\"name\": \"final_answer\", \"arguments\": {\"answer\": \"...\"}}.";
+        let problem = match self {
+            ParseFailure::Empty => "Your response was empty.".to_string(),
+            ParseFailure::NoAction => "Your response did not contain a tool call. If it is your answer, give it with the final_answer tool.".to_string(),
+            ParseFailure::InvalidJson(error) => format!("The tool call in your response is not valid JSON: {}.", error),
+            ParseFailure::MissingName => "The tool call in your response has no \"name\".".to_string(),
+        };
+        format!("{} {} Available tools: {}.", problem, format, tool_names.join(", "))
+    }
+}
+
+impl std::fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseFailure::Empty => write!(f, "empty response"),
+            ParseFailure::NoAction => write!(f, "no action found"),
+            ParseFailure::InvalidJson(error) => write!(f, "invalid JSON: {}", error),
+            ParseFailure::MissingName => write!(f, "missing tool name"),
+        }
+    }
+}
+
+/// The tool call of an action. Models also write the name as `tool_name`, the arguments as `parameters`, and the
+/// arguments as a string of JSON.
+fn tool_call_from_action(action: &serde_json::Value) -> Result<ToolCall, ParseFailure> {
+    let name = ["name", "tool_name", "tool"]
+        .iter()
+        .find_map(|key| action[*key].as_str())
+        .filter(|name| !name.is_empty())
+        .ok_or(ParseFailure::MissingName)?;
+    let arguments = ["arguments", "parameters", "args"]
+        .iter()
+        .map(|key| &action[*key])
+        .find(|arguments| !arguments.is_null())
+        .cloned()
+        .unwrap_or_default();
+    let arguments = match arguments {
+        serde_json::Value::String(arguments) => {
+            match serde_json::from_str::<serde_json::Value>(&arguments) {
+                Ok(object) if object.is_object() => object,
+                _ => serde_json::Value::String(arguments),
+            }
+        }
+        arguments => arguments,
+    };
+    Ok(ToolCall {
+        id: Some(format!("call_{}", nanoid::nanoid!())),
+        call_type: Some("function".to_string()),
+        function: FunctionCall {
+            name: name.to_string(),
+            arguments,
+        },
+    })
+}
+
+/// The tool calls written in the text of a response, for models without native tool calling: one for each
+/// `Action:` or `<tool_call>` block.
+pub fn parse_tool_calls(response: &str) -> Result<Vec<ToolCall>, ParseFailure> {
+    if response.trim().is_empty() {
+        return Err(ParseFailure::Empty);
+    }
+    let actions = extract_action_jsons(response);
+    if actions.is_empty() {
+        return Err(ParseFailure::NoAction);
+    }
+    actions
+        .into_iter()
+        .map(|json| {
+            let action =
+                parse_action_json(json).map_err(|e| ParseFailure::InvalidJson(e.to_string()))?;
+            tool_call_from_action(&action)
+        })
+        .collect()
+}
+
+/// The first tool call written in the text of a response, for models without native tool calling.
+pub fn parse_tool_call(response: &str) -> Result<ToolCall, ParseFailure> {
+    parse_tool_calls(response).map(|mut tool_calls| tool_calls.remove(0))
+}
+
 #[cfg(feature = "stream")]
 impl<M: Model + std::fmt::Debug + Send + Sync + 'static> AgentStream for FunctionCallingAgent<M> {}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::{FileMemoryStore, MemoryStore, SynthesisTrigger, SYNTHESIS_PROMPT};
+    use crate::models::mock::{MockModel, MockResponse};
+    use crate::tools::{AnyTool, Tool, ToolFunctionInfo, ToolInfo, ToolType};
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Deserialize, JsonSchema)]
+    #[schemars(title = "SleepToolParams")]
+    struct SleepToolParams {
+        millis: u64,
+    }
+
+    /// Sleeps and records how many calls run at the same time.
+    #[derive(Clone, Default)]
+    struct SleepTool {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        type Params = SleepToolParams;
+
+        fn name(&self) -> &'static str {
+            "sleep"
+        }
+
+        fn description(&self) -> &'static str {
+            "Sleep for some milliseconds."
+        }
+
+        async fn forward(&self, arguments: SleepToolParams) -> anyhow::Result<String> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(arguments.millis)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("slept {}ms", arguments.millis))
+        }
+    }
+
+    /// Fails like a search tool with an expired API key.
+    #[derive(Clone, Default)]
+    struct BrokenSearchTool {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for BrokenSearchTool {
+        type Params = SleepToolParams;
+
+        fn name(&self) -> &'static str {
+            "search"
+        }
+
+        fn description(&self) -> &'static str {
+            "Search the web."
+        }
+
+        async fn forward(&self, _: SleepToolParams) -> anyhow::Result<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow::anyhow!("401 Unauthorized: invalid API key"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let search = || MockResponse::tool_call("search", serde_json::json!({ "millis": 0 }));
+        let model = MockModel::new(vec![search(), search(), search(), MockResponse::text("I could not search")]);
+        let tool = BrokenSearchTool::default();
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(tool.clone())])
+            .with_circuit_breaker(Some(2))
+            .with_max_steps(Some(4))
+            .build()
+            .unwrap();
+        agent.run("Search the news", true).await.unwrap();
+
+        // The third call is answered without running the tool.
+        assert_eq!(tool.calls.load(Ordering::SeqCst), 2);
+        let observations = agent
+            .base_agent
+            .logs
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) if step.tool_call.is_some() => Some(step.observation_contents()),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        assert!(!observations[0].contains("unavailable"));
+        assert!(observations[1].contains("The tool search is unavailable"));
+        assert!(observations[2].starts_with("The tool search is unavailable"));
+        assert!(agent.base_agent.is_tool_unavailable("search"));
+
+        // A new run can use the tool again.
+        agent.base_agent.model = MockModel::new(vec![search(), MockResponse::text("Done")]);
+        agent.run("Search again", true).await.unwrap();
+        assert_eq!(tool.calls.load(Ordering::SeqCst), 3);
+    }
+
+    /// Counts the messages it checks at the input stage, and blocks the tasks about passwords.
+    #[derive(Clone, Default)]
+    struct CountingGuardrail {
+        checked: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl crate::guardrails::Guardrail for CountingGuardrail {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn check_input(
+            &self,
+            messages: &[Message],
+        ) -> Result<(), crate::guardrails::GuardrailViolation> {
+            self.checked.fetch_add(messages.len(), Ordering::SeqCst);
+            if messages.iter().any(|m| m.content.contains("password")) {
+                return Err(crate::guardrails::GuardrailViolation {
+                    guardrail: self.name().to_string(),
+                    stage: crate::guardrails::GuardrailStage::Input,
+                    reason: "asks for a password".to_string(),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_input_guardrails() {
+        let search = || MockResponse::tool_call("search", serde_json::json!({ "millis": 0 }));
+        let model = MockModel::new(vec![search(), search(), MockResponse::text("Done")]);
+        let guardrail = CountingGuardrail::default();
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(BrokenSearchTool::default())])
+            .with_guardrails(vec![Box::new(guardrail.clone())])
+            .with_max_steps(Some(4))
+            .build()
+            .unwrap();
+
+        // The task is checked once, not at each step with the observations.
+        agent.run("Search the news", true).await.unwrap();
+        assert_eq!(guardrail.checked.load(Ordering::SeqCst), 1);
+
+        agent.base_agent.model = MockModel::new(vec![MockResponse::text("hunter2")]);
+        let error = agent.run("Find the admin password", true).await.err().unwrap();
+        assert!(matches!(error, AgentError::Guardrail(_)));
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_statuses() {
+        let model = MockModel::new(vec![
+            MockResponse::tool_call("search", serde_json::json!({ "millis": 0 })),
+            MockResponse::tool_call("search", serde_json::json!({ "millis": "soon" })),
+            MockResponse::text("I could not search"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(BrokenSearchTool::default())])
+            .build()
+            .unwrap();
+        let (tx, mut rx) = broadcast::channel(100);
+        agent
+            .run_with_status("Search the news", true, Some(tx))
+            .await
+            .unwrap();
+
+        let mut statuses = Vec::new();
+        while let Ok(status) = rx.try_recv() {
+            match status {
+                Status::ToolCallStarted { name, arguments, .. } => {
+                    statuses.push(format!("started {} {}", name, arguments))
+                }
+                Status::ToolCallResult { success, observation, .. } => {
+                    statuses.push(format!("result {} {}", success, observation))
+                }
+                Status::ToolCallFailed { error, .. } => statuses.push(format!("failed {}", error)),
+                _ => {}
+            }
+        }
+        // The tool ran and failed, then the call with invalid arguments did not run it.
+        assert_eq!(statuses.len(), 4);
+        assert_eq!(statuses[0], r#"started search {"millis":0}"#);
+        assert_eq!(statuses[1], "result false 401 Unauthorized: invalid API key");
+        assert_eq!(statuses[2], r#"started search {"millis":"soon"}"#);
+        assert!(statuses[3].starts_with("failed "), "{}", statuses[3]);
+    }
+
+    /// Returns the same result for every search.
+    #[derive(Clone, Default)]
+    struct RepeatingSearchTool;
+
+    #[async_trait]
+    impl Tool for RepeatingSearchTool {
+        type Params = SleepToolParams;
+
+        fn name(&self) -> &'static str {
+            "search"
+        }
+
+        fn description(&self) -> &'static str {
+            "Search the web."
+        }
+
+        async fn forward(&self, _: SleepToolParams) -> anyhow::Result<String> {
+            Ok("[Paris](https://en.wikipedia.org/wiki/Paris)\nParis is the capital and most populous city of France, with 2.1 million residents.".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observation_processor() {
+        let search = || MockResponse::tool_call("search", serde_json::json!({ "millis": 0 }));
+        let model = MockModel::new(vec![search(), search(), MockResponse::text("Done")]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(RepeatingSearchTool)])
+            .with_observation_processor(ObservationProcessor::new())
+            .build()
+            .unwrap();
+        agent.run("Search Paris", true).await.unwrap();
+
+        let requests = agent.base_agent.model.requests();
+        let observations = requests[2]
+            .iter()
+            .filter(|m| m.role == MessageRole::ToolResponse)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>();
+        assert!(observations[0].contains("capital"));
+        assert!(observations[1].contains("already seen in an earlier observation"));
+        // The logs keep the observations.
+        assert!(agent.base_agent.logs.iter().all(|step| match step {
+            Step::ActionStep(step) => step.observation_contents().iter().all(|o| !o.contains("already seen")),
+            _ => true,
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let store = Arc::new(crate::agent::FileCheckpointStore::new(
+            std::env::temp_dir().join(format!("lumo-checkpoints-{}", nanoid::nanoid!())),
+        ));
+        // The model fails after the first step, like a crashed run.
+        let model = MockModel::new(vec![MockResponse::tool_call("search", serde_json::json!({ "millis": 0 }))]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(RepeatingSearchTool)])
+            .with_checkpoint_store(store.clone())
+            .build()
+            .unwrap();
+        assert!(agent.run("What is the capital of France?", true).await.is_err());
+        let id = agent.checkpoint_id().unwrap().to_string();
+
+        let model = MockModel::new(vec![MockResponse::text("Paris")]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(RepeatingSearchTool)])
+            .with_checkpoint_store(store.clone())
+            .build()
+            .unwrap();
+        let answer = agent
+            .resume_from_checkpoint(&store.path(&id).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(answer, "Paris");
+
+        // The run continues with the second step and the observation of the first one.
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .iter()
+            .any(|m| m.role == MessageRole::ToolResponse && m.content.contains("capital")));
+        assert_eq!(agent.get_step_number(), 3);
+
+        // The checkpoint of the finished run returns its answer without calling the model.
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .build()
+            .unwrap();
+        let checkpoint = store.load(&id).await.unwrap().unwrap();
+        assert_eq!(agent.resume(checkpoint).await.unwrap(), "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_parse_retries() {
+        let model = MockModel::new(vec![
+            MockResponse::text(r#"Action: {"name": "final_answer", "arguments": {"answer": 42,}}"#),
+            MockResponse::text("The answer is 42."),
+            MockResponse::tool_call("final_answer", serde_json::json!({ "answer": "42" })),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(crate::tools::FinalAnswerTool::new())])
+            .with_parse_retries(2)
+            .build()
+            .unwrap();
+        let answer = agent.run("What is 6 times 7?", true).await.unwrap();
+        assert_eq!(answer, "42");
+
+        // Each retry tells the model what was wrong with its last response.
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].last().unwrap().content.contains("not valid JSON"));
+        assert!(requests[2].last().unwrap().content.contains("did not contain a tool call"));
+        assert_eq!(requests[2][requests[2].len() - 2].content, "The answer is 42.");
+
+        // Without retries the text is the answer.
+        let model = MockModel::new(vec![MockResponse::text("The answer is 42.")]);
+        let mut agent = FunctionCallingAgentBuilder::new(model).build().unwrap();
+        assert_eq!(agent.run("What is 6 times 7?", true).await.unwrap(), "The answer is 42.");
+    }
+
+    #[tokio::test]
+    async fn test_planning_and_fallback_models() {
+        // The primary model has no responses, so every call to it fails.
+        let planning_model = MockModel::new(vec![
+            MockResponse::text("The capital of France is asked."),
+            MockResponse::text("1. Answer the question."),
+        ]);
+        let fallback_model = MockModel::new(vec![MockResponse::tool_call(
+            "final_answer",
+            serde_json::json!({ "answer": "Paris" }),
+        )]);
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_tools(vec![Box::new(crate::tools::FinalAnswerTool::new())])
+            .with_planning_interval(Some(3))
+            .with_planning_model(planning_model)
+            .with_fallback_model(fallback_model)
+            .build()
+            .unwrap();
+        let answer = agent.run("What is the capital of France?", true).await.unwrap();
+        assert_eq!(answer, "Paris");
+
+        // The plan comes from the planning model, the action from the fallback model.
+        assert!(agent.base_agent.logs.iter().any(|step| matches!(
+            step,
+            Step::PlanningStep(plan, facts) if format!("{}{}", plan, facts).contains("Answer the question")
+        )));
+        assert_eq!(agent.base_agent.model.requests().len(), 1);
+
+        // Without a fallback model the error of the primary model is returned.
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .build()
+            .unwrap();
+        assert!(agent.run("What is the capital of France?", true).await.is_err());
+    }
+
+    #[test]
+    fn test_observations_paired_by_id() {
+        let call = |id: &str, name: &str| ToolCall {
+            id: Some(id.to_string()),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: serde_json::json!({}),
+            },
+        };
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .build()
+            .unwrap();
+        let mut step = AgentStep::new(1, None);
+        step.llm_output = Some(String::new());
+        step.tool_call = Some(vec![
+            call("call_1", "researcher"),
+            call("call_2", "search"),
+            call("call_3", "search"),
+        ]);
+        // The observations are in a different order than the calls, and one is missing.
+        step.observations = Some(vec![
+            ToolObservation::error(&call("call_3", "search"), "Rate limited"),
+            ToolObservation::success(&call("call_1", "researcher"), "Paris"),
+        ]);
+        agent.base_agent.logs.push(Step::ActionStep(step));
+
+        let memory = agent.base_agent.write_inner_memory_from_logs(None).unwrap();
+        let responses = memory
+            .iter()
+            .filter(|m| m.role == MessageRole::ToolResponse)
+            .map(|m| (m.tool_call_id.as_deref().unwrap(), m.content.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![
+                ("call_1", "Observation: Paris"),
+                ("call_2", "Observation: The tool call returned no observation."),
+                ("call_3", "Observation: Rate limited"),
+            ]
+        );
+
+        // Observations saved as plain strings are paired by position.
+        let step: AgentStep = serde_json::from_value(serde_json::json!({
+            "step": 1,
+            "tool_call": [call("call_1", "search")],
+            "observations": ["Paris"],
+        }))
+        .unwrap();
+        assert_eq!(step.observation_of(0).unwrap().content, "Paris");
+        assert!(step.observation_of(1).is_none());
+    }
+
+    #[test]
+    fn test_parse_tool_call() {
+        let tool_call = parse_tool_call(r#"Action: {"name": "search", "arguments": {"query": "Paris"}}"#).unwrap();
+        assert_eq!(tool_call.function.name, "search");
+        assert_eq!(parse_tool_call("  ").unwrap_err(), ParseFailure::Empty);
+        assert_eq!(parse_tool_call("Paris").unwrap_err(), ParseFailure::NoAction);
+        assert_eq!(
+            parse_tool_call(r#"Action: {"arguments": {}}"#).unwrap_err(),
+            ParseFailure::MissingName
+        );
+        assert!(matches!(
+            parse_tool_call(r#"Action: {"name": "search", }"#).unwrap_err(),
+            ParseFailure::InvalidJson(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_tool_calls() {
+        // Pretty-printed, with a line break in a string and a brace in another.
+        let tool_calls = parse_tool_calls(
+            "Action:\n{\n  \"name\": \"python_interpreter\",\n  \"arguments\": {\"code\": \"for i in range(3):\n    print('}')\"}\n}\nObservation:",
+        )
+        .unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(
+            tool_calls[0].function.arguments["code"],
+            "for i in range(3):\n    print('}')"
+        );
+
+        let tool_calls = parse_tool_calls(
+            "<tool_call>\n{\"tool_name\": \"search\", \"parameters\": {\"query\": \"Paris\"}}\n</tool_call>\n<tool_call>\n{\"name\": \"search\", \"arguments\": \"{\\\"query\\\": \\\"Rome\\\"}\"}\n</tool_call>",
+        )
+        .unwrap();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].function.name, "search");
+        assert_eq!(tool_calls[0].function.arguments["query"], "Paris");
+        // Arguments written as a string of JSON.
+        assert_eq!(tool_calls[1].function.arguments["query"], "Rome");
+
+        // A call in a `<tool_call>` block after `Action:` is parsed once.
+        let tool_calls = parse_tool_calls(
+            "Action: <tool_call>{\"name\": \"final_answer\", \"arguments\": {\"answer\": \"Paris\"}}</tool_call>",
+        )
+        .unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert!(matches!(
+            parse_tool_calls("Action: {\"name\": \"search\", \"arguments\": {\"query\"").unwrap_err(),
+            ParseFailure::InvalidJson(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_prompt_tool_calling() {
+        let model = MockModel::new(vec![
+            MockResponse::text("Action:\n{\n  \"name\": \"sleep\",\n  \"arguments\": {\"millis\": 1}\n}"),
+            MockResponse::text("Action: {\"name\": \"final_answer\", \"arguments\": {\"answer\": \"Done\"}}"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![
+                Box::new(SleepTool::default()),
+                Box::new(crate::tools::FinalAnswerTool::new()),
+            ])
+            .with_system_prompt(Some("You are a helpful assistant."))
+            .with_tool_calling(ToolCallingMode::Prompt)
+            .build()
+            .unwrap();
+        assert!(agent.base_agent.uses_prompt_tool_calling());
+        assert_eq!(agent.run("Sleep", true).await.unwrap(), "Done");
+
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 2);
+        // The system prompt has no `{{tool_descriptions}}`, so the tools are described after it.
+        assert!(requests[0][0].content.contains("sleep:"));
+        // The tool call and its observation are sent as text.
+        let second = &requests[1];
+        assert!(second.iter().all(|message| message.tool_calls.is_none()
+            && message.role != MessageRole::ToolResponse));
+        assert!(second.iter().any(|message| message.role == MessageRole::Assistant
+            && message.content.matches("Action:").count() == 1));
+        assert!(second
+            .iter()
+            .any(|message| message.role == MessageRole::User && message.content.starts_with("Observation:")));
+
+        // Native tool calls by default for models that support them.
+        let agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .build()
+            .unwrap();
+        assert!(!agent.base_agent.uses_prompt_tool_calling());
+    }
+
+    /// Returns its decisions in order.
+    #[cfg(feature = "stream")]
+    struct ScriptedHook(Vec<crate::agent::StepDecision>);
+
+    #[cfg(feature = "stream")]
+    #[async_trait]
+    impl crate::agent::StepHook for ScriptedHook {
+        async fn after_step(
+            &mut self,
+            _: &AgentStep,
+            _: &[Message],
+        ) -> crate::agent::StepDecision {
+            self.0.remove(0)
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_step_hook() {
+        use crate::agent::{AgentStream, StepDecision};
+        use futures::StreamExt;
+
+        let sleep = || MockResponse::tool_call("sleep", serde_json::json!({ "millis": 1 }));
+        let model = MockModel::new(vec![sleep(), sleep(), MockResponse::text("Done")]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(SleepTool::default())])
+            .build()
+            .unwrap();
+        let hook = ScriptedHook(vec![
+            StepDecision::Continue(StepOverrides {
+                prompt: Some("Sleep again.".to_string()),
+                force_tool: Some("sleep".to_string()),
+                skip_tools: vec![],
+            }),
+            StepDecision::Abort,
+        ]);
+        let steps = agent
+            .stream_run_with_hook("Sleep", true, None, Some(Box::new(hook)))
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        // The run stops after the second step, without an answer.
+        assert_eq!(steps.len(), 2);
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].last().unwrap().content,
+            "Sleep again.\nUse the tool sleep in this step."
+        );
+        // The overrides only apply to the next step.
+        assert!(agent.base_agent.step_overrides.is_empty());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_managed_agent_streaming() {
+        use crate::agent::AgentStream;
+        use futures::StreamExt;
+
+        let researcher = FunctionCallingAgentBuilder::new(MockModel::new(vec![MockResponse::text(
+            "Paris is the capital",
+        )]))
+        .with_name(Some("researcher"))
+        .with_description(Some("Finds facts"))
+        .build()
+        .unwrap();
+        let model = MockModel::new(vec![
+            MockResponse::tool_call("researcher", serde_json::json!({ "task": "Find the capital" })),
+            MockResponse::text("Paris"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_managed_agents(vec![Box::new(researcher)])
+            .build()
+            .unwrap();
+        let (tx, mut rx) = broadcast::channel(100);
+        agent
+            .stream_run("What is the capital of France?", true, Some(tx))
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut tokens = String::new();
+        let mut steps = Vec::new();
+        while let Ok(status) = rx.try_recv() {
+            if let Status::ManagedAgent { agent, status } = status {
+                assert_eq!(agent, "researcher");
+                match *status {
+                    Status::FirstContent(token) | Status::Content(token) => tokens.push_str(&token),
+                    Status::Step(step) => steps.push(step),
+                    _ => {}
+                }
+            }
+        }
+        // The tokens and the steps of the managed agent arrive while the step of the agent runs.
+        assert_eq!(tokens, "Paris is the capital");
+        assert!(matches!(
+            steps.as_slice(),
+            [step] if matches!(step.as_ref(), Step::ActionStep(step) if step.final_answer.as_deref() == Some("Paris is the capital"))
+        ));
+    }
+
+    /// Counts how often it is shut down.
+    #[derive(Clone, Default)]
+    struct ReleasingTool {
+        shutdowns: Arc<AtomicUsize>,
+    }
+
+    impl AnyTool for ReleasingTool {
+        fn name(&self) -> &'static str {
+            "release"
+        }
+
+        fn description(&self) -> &'static str {
+            "Holds a resource until shut down."
+        }
+
+        fn tool_info(&self) -> ToolInfo {
+            ToolInfo {
+                tool_type: ToolType::Function,
+                function: ToolFunctionInfo {
+                    name: "release".to_string(),
+                    description: self.description().to_string(),
+                    parameters: serde_json::json!({ "type": "object", "properties": {} }),
+                    strict: None,
+                },
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AsyncTool for ReleasingTool {
+        async fn forward_json(&self, _json_args: serde_json::Value) -> Result<String, AgentError> {
+            Ok("released".to_string())
+        }
+
+        async fn shutdown(&self) -> Result<(), AgentError> {
+            self.shutdowns.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn clone_box(&self) -> Box<dyn AsyncTool> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_managed_agents() {
+        let tool = ReleasingTool::default();
+        let researcher = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_name(Some("researcher"))
+            .with_tools(vec![Box::new(tool.clone())])
+            .build()
+            .unwrap();
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_managed_agents(vec![Box::new(researcher)])
+            .build()
+            .unwrap();
+        agent.shutdown().await.unwrap();
+        // The managed agent is called through a tool, which shuts it down with the agent.
+        assert_eq!(tool.shutdowns.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_managed_agents_in_plan() {
+        let researcher = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_name(Some("researcher"))
+            .with_description(Some("Finds facts"))
+            .build()
+            .unwrap();
+        let model = MockModel::new(vec![
+            MockResponse::text("The capital of France is asked."),
+            MockResponse::text("1. Ask the researcher."),
+            MockResponse::text("Paris"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_managed_agents(vec![Box::new(researcher)])
+            .with_planning_interval(Some(3))
+            .build()
+            .unwrap();
+        agent.run("What is the capital of France?", true).await.unwrap();
+
+        // The managed agent is a tool of the agent, and still described to the planning step.
+        assert!(agent.base_agent.tools.iter().any(|tool| tool.name() == "researcher"));
+        let requests = agent.base_agent.model.requests();
+        assert!(requests[1][1].content.contains("researcher: \"Finds facts\""));
+    }
+
+    #[tokio::test]
+    async fn test_max_parallel_tool_calls() {
+        let tool_calls = [30, 10, 20, 5]
+            .iter()
+            .enumerate()
+            .map(|(i, millis)| ToolCall {
+                id: Some(format!("call_{}", i)),
+                call_type: Some("function".to_string()),
+                function: FunctionCall {
+                    name: "sleep".to_string(),
+                    arguments: serde_json::json!({ "millis": millis }),
+                },
+            })
+            .collect::<Vec<_>>();
+        let model = MockModel::new(vec![
+            MockResponse {
+                content: String::new(),
+                tool_calls,
+            },
+            MockResponse::text("Done"),
+        ]);
+        let tool = SleepTool::default();
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(tool.clone())])
+            .with_max_parallel_tool_calls(2)
+            .with_max_steps(Some(2))
+            .build()
+            .unwrap();
+        agent.run("Sleep", true).await.unwrap();
+
+        assert_eq!(tool.max_running.load(Ordering::SeqCst), 2);
+        let step = agent
+            .base_agent
+            .logs
+            .iter()
+            .find_map(|step| match step {
+                Step::ActionStep(step) if step.tool_call.is_some() => Some(step),
+                _ => None,
+            })
+            .unwrap();
+        // Observations are in the order of the tool calls, not the order the calls finished.
+        assert_eq!(
+            step.observation_contents(),
+            vec!["slept 30ms", "slept 10ms", "slept 20ms", "slept 5ms"]
+        );
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    #[schemars(title = "EchoToolParams")]
+    struct EchoToolParams {
+        text: String,
+    }
+
+    /// Returns its text.
+    #[derive(Clone)]
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        type Params = EchoToolParams;
+
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn description(&self) -> &'static str {
+            "Echo a text."
+        }
+
+        async fn forward(&self, arguments: EchoToolParams) -> anyhow::Result<String> {
+            Ok(format!("echo: {}", arguments.text))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_dependencies() {
+        let tool_calls = ["$tool_call[1].result", "hello", "$tool_call[5].result"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| ToolCall {
+                id: Some(format!("call_{}", i)),
+                call_type: Some("function".to_string()),
+                function: FunctionCall {
+                    name: "echo".to_string(),
+                    arguments: serde_json::json!({ "text": text }),
+                },
+            })
+            .collect::<Vec<_>>();
+        let model = MockModel::new(vec![
+            MockResponse {
+                content: String::new(),
+                tool_calls,
+            },
+            MockResponse::text("Done"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(EchoTool)])
+            .with_max_steps(Some(2))
+            .build()
+            .unwrap();
+        agent.run("Echo", true).await.unwrap();
+
+        let step = agent
+            .base_agent
+            .logs
+            .iter()
+            .find_map(|step| match step {
+                Step::ActionStep(step) if step.tool_call.is_some() => Some(step),
+                _ => None,
+            })
+            .unwrap();
+        // The first call runs after the second one, with its result.
+        let observations = step.observations.clone().unwrap();
+        assert_eq!(observations[0].content, "echo: echo: hello");
+        assert_eq!(observations[0].tool_call_id.as_deref(), Some("call_0"));
+        assert_eq!(observations[1].content, "echo: hello");
+        assert_eq!(observations[1].tool_call_id.as_deref(), Some("call_1"));
+        assert!(observations[2].is_error);
+        assert!(observations[2].content.contains("does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_get_memory() {
+        let model = MockModel::new(vec![
+            MockResponse {
+                content: String::new(),
+                tool_calls: vec![ToolCall {
+                    id: Some("call_0".to_string()),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name: "echo".to_string(),
+                        arguments: serde_json::json!({ "text": "hi" }),
+                    },
+                }],
+            },
+            MockResponse::text("Done"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(EchoTool)])
+            .with_max_steps(Some(2))
+            .build()
+            .unwrap();
+        agent.run("Echo hi", true).await.unwrap();
+
+        let memory = agent.get_memory().unwrap();
+        assert_eq!(memory[0].role, MessageRole::System);
+        assert_eq!(memory[1].content, "New Task: Echo hi");
+        assert!(memory.iter().any(|message| message.role == MessageRole::ToolResponse
+            && message.tool_call_id.as_deref() == Some("call_0")
+            && message.content == "Observation: echo: hi"));
+        // The memory sent at the last step is where the memory starts.
+        let sent = agent
+            .base_agent
+            .logs
+            .iter()
+            .rev()
+            .find_map(|step| match step {
+                Step::ActionStep(step) => step.agent_memory.clone(),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&memory[..sent.len()]).unwrap(),
+            serde_json::to_value(&sent).unwrap()
+        );
+    }
+
+    fn sleep_calls(millis: &[u64]) -> MockResponse {
+        MockResponse {
+            content: String::new(),
+            tool_calls: millis
+                .iter()
+                .enumerate()
+                .map(|(i, millis)| ToolCall {
+                    id: Some(format!("call_{}", i)),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name: "sleep".to_string(),
+                        arguments: serde_json::json!({ "millis": millis }),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipelining() {
+        let model = MockModel::new(vec![
+            sleep_calls(&[1, 50]),
+            // The speculative response, made while the 50ms call runs.
+            sleep_calls(&[2]),
+            MockResponse::text("Done"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(SleepTool::default())])
+            .with_pipelining(true)
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert_eq!(agent.run("Sleep", true).await.unwrap(), "Done");
+
+        let steps = agent
+            .base_agent
+            .logs
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) => Some(step),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), 3);
+        assert!(!steps[0].speculative);
+        assert!(steps[1].speculative);
+        assert_eq!(steps[1].observation_contents(), vec!["slept 2ms"]);
+        // The last step sees every observation of the first one.
+        assert_eq!(
+            steps[0].observation_contents(),
+            vec!["slept 1ms", "slept 50ms"]
+        );
+
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 3);
+        let speculative_memory = &requests[1];
+        assert!(speculative_memory.iter().any(|m| m.content == "Observation: slept 1ms"));
+        assert!(speculative_memory
+            .iter()
+            .any(|m| m.content.contains("This tool call is still running")));
+    }
+
+    #[tokio::test]
+    async fn test_pipelining_discards_answer() {
+        let model = MockModel::new(vec![
+            sleep_calls(&[1, 50]),
+            // A final answer without the result of the 50ms call is discarded.
+            MockResponse::text("Too early"),
+            MockResponse::text("Done"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(SleepTool::default())])
+            .with_pipelining(true)
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert_eq!(agent.run("Sleep", true).await.unwrap(), "Done");
+        assert_eq!(agent.base_agent.model.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_answer_validation() {
+        let model = MockModel::new(vec![
+            MockResponse::text("I would search for it."),
+            MockResponse::text(r#"{"valid": false, "reason": "The answer does not say how long to sleep."}"#),
+            MockResponse::tool_call("sleep", serde_json::json!({ "millis": 1 })),
+            MockResponse::text("Slept 1ms"),
+            MockResponse::text(r#"{"valid": true, "reason": "The tool slept.", "supporting_observations": [1]}"#),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(SleepTool::default())])
+            .with_answer_validation(true)
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert_eq!(agent.run("Sleep", true).await.unwrap(), "Slept 1ms");
+
+        let steps = agent
+            .base_agent
+            .logs
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) => Some(step),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), 3);
+        assert!(steps[0].final_answer.is_none());
+        assert!(!steps[0].validation.as_ref().unwrap().valid);
+        let validation = steps[2].validation.as_ref().unwrap();
+        assert!(validation.valid);
+        assert_eq!(validation.supporting_observations, vec!["slept 1ms"]);
+
+        // The rejection is in the memory of the next step.
+        let requests = agent.base_agent.model.requests();
+        assert!(requests[2]
+            .iter()
+            .any(|m| m.content.starts_with("Your final answer was rejected")));
+    }
+
+    #[tokio::test]
+    async fn test_response_language() {
+        let task = "Quelle est la capitale de la France ?";
+        let model = MockModel::new(vec![
+            MockResponse::text("The capital of France is Paris, and it is a large city."),
+            MockResponse::text("La capitale de la France est Paris."),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_response_language(Some(ResponseLanguage::Auto))
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert_eq!(
+            agent.run(task, true).await.unwrap(),
+            "La capitale de la France est Paris."
+        );
+        let requests = agent.base_agent.model.requests();
+        assert!(requests[0][0].content.ends_with(
+            "Always write your final answer in French, whatever the language of the sources and tool results."
+        ));
+        assert!(requests[1]
+            .iter()
+            .any(|m| m.content.contains("The answer is in English, but it must be in French")));
+
+        // The answer is only sent back once.
+        let model = MockModel::new(vec![
+            MockResponse::text("The capital of France is Paris, and it is a large city."),
+            MockResponse::text("The capital is Paris, and it is in the north of the country."),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_response_language(Some(ResponseLanguage::Language("fr".to_string())))
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert_eq!(
+            agent.run(task, true).await.unwrap(),
+            "The capital is Paris, and it is in the north of the country."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prompt_language() {
+        let model = MockModel::new(vec![
+            MockResponse::text("La capitale de la France est demandée."),
+            MockResponse::text("1. Répondre à la question."),
+            MockResponse::tool_call("final_answer", serde_json::json!({ "answer": "Paris" })),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(crate::tools::FinalAnswerTool::new())])
+            .with_planning_interval(Some(3))
+            .with_prompt_language(Some("fr"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            agent.run("Quelle est la capitale de la France ?", true).await.unwrap(),
+            "Paris"
+        );
+        let requests = agent.base_agent.model.requests();
+        assert!(requests[0].last().unwrap().content.starts_with("Voici la tâche :"));
+        assert!(requests[1][0].content.starts_with("Tu es un expert de renommée mondiale"));
+        assert!(requests[2][0].content.starts_with("Tu es un assistant expert"));
+        assert!(requests[2]
+            .iter()
+            .any(|m| m.content.contains("Voici le plan d'action")));
+
+        // A system prompt that is set is kept.
+        let agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_system_prompt(Some("Réponds en une phrase."))
+            .with_prompt_language(Some("fr"))
+            .build()
+            .unwrap();
+        assert!(agent.get_system_prompt().starts_with("Réponds en une phrase."));
+
+        assert!(FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_prompt_language(Some("xx"))
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_answer() {
+        let model = MockModel::new(vec![MockResponse::text(r#"{"capital": "Paris"}"#)]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_json_answer(true)
+            .with_response_language(Some(ResponseLanguage::Language("en".to_string())))
+            .build()
+            .unwrap();
+        assert_eq!(
+            agent.run("What is the capital of France?", true).await.unwrap(),
+            r#"{"capital": "Paris"}"#
+        );
+        let system_prompt = &agent.base_agent.model.requests()[0][0].content;
+        assert!(system_prompt.ends_with(crate::prompts::JSON_ANSWER_PROMPT));
+        assert!(system_prompt.contains("Always write your final answer in English"));
+    }
+
+    #[test]
+    fn test_retry_prompts() {
+        use crate::prompts::{ErrorKind, RATE_LIMITED_RETRY_PROMPT, RETRY_PROMPT};
+
+        let call = |id: &str, name: &str| ToolCall {
+            id: Some(id.to_string()),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: serde_json::json!({}),
+            },
+        };
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![]))
+            .with_retry_prompts(
+                RetryPrompts::default().with_prompt(ErrorKind::NotFound, "Search for the page again."),
+            )
+            .build()
+            .unwrap();
+        let mut step = AgentStep::new(1, None);
+        step.llm_output = Some(String::new());
+        step.tool_call = Some(vec![
+            call("call_1", "search"),
+            call("call_2", "visit_website"),
+            call("call_3", "calculator"),
+        ]);
+        step.observations = Some(vec![
+            ToolObservation::error(&call("call_1", "search"), "429 Too Many Requests"),
+            ToolObservation::error(&call("call_2", "visit_website"), "404 Not Found"),
+            ToolObservation::error(&call("call_3", "calculator"), "Division by zero"),
+        ]);
+        agent.base_agent.logs.push(Step::ActionStep(step));
+        let mut step = AgentStep::new(2, None);
+        step.error = Some(AgentError::Execution("Division by zero".to_string()));
+        agent.base_agent.logs.push(Step::ActionStep(step));
+
+        let memory = agent.base_agent.write_inner_memory_from_logs(None).unwrap();
+        let roles = memory.iter().map(|m| m.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            vec![
+                MessageRole::Assistant,
+                MessageRole::ToolResponse,
+                MessageRole::ToolResponse,
+                MessageRole::ToolResponse,
+                MessageRole::User,
+                MessageRole::User,
+            ]
+        );
+        // Errors of unknown cause get no guidance of their own after the tool responses.
+        assert_eq!(
+            memory[4].content,
+            format!(
+                "The call to search failed. {}\nThe call to visit_website failed. Search for the page again.",
+                RATE_LIMITED_RETRY_PROMPT
+            )
+        );
+        assert_eq!(
+            memory[5].content,
+            format!("Error: Division by zero\n{}\n", RETRY_PROMPT)
+        );
+    }
 
     #[test]
     fn test_extract_action_json() {
@@ -504,4 +2314,123 @@ mod tests {
         );
         // assert_eq!(json_str, serde_json::json!({"name": "final_answer", "arguments": {"answer": "This is the final answer"}}));
     }
+
+    #[tokio::test]
+    async fn test_step_timing() {
+        let model = MockModel::new(vec![
+            MockResponse::tool_call("sleep", serde_json::json!({ "millis": 20 })),
+            MockResponse::text("Done"),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(SleepTool::default())])
+            .with_max_steps(Some(2))
+            .build()
+            .unwrap();
+        agent.run("Sleep", true).await.unwrap();
+
+        let steps = agent
+            .base_agent
+            .logs
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) => Some(step),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), 2);
+        for step in &steps {
+            assert!(step.started_at.is_some());
+            assert!(step.model_latency_ms.is_some());
+            assert!(step.input_tokens.unwrap() > 0);
+        }
+        assert_eq!(steps[0].tool_timings.len(), 1);
+        assert_eq!(steps[0].tool_timings[0].name, "sleep");
+        assert!(steps[0].tool_timings[0].duration_ms >= 20);
+        assert!(steps[0].duration_ms.unwrap() >= 20);
+    }
+
+    #[tokio::test]
+    async fn test_synthesis() {
+        let search = || MockResponse::tool_call("search", serde_json::json!({ "millis": 0 }));
+        let model = MockModel::new(vec![
+            search(),
+            search(),
+            MockResponse::text("Paris is the capital of France."),
+        ]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(RepeatingSearchTool)])
+            .with_synthesis(Synthesis::default())
+            .build()
+            .unwrap();
+        let answer = agent.run("What is the capital of France?", true).await.unwrap();
+        assert_eq!(answer, "Paris is the capital of France.");
+
+        // The repeated search is not run, the answer is written without tools.
+        let steps = agent
+            .base_agent
+            .logs
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step) => Some(step),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].synthesis, Some(SynthesisTrigger::RepeatedCall));
+        assert!(steps[1].tool_call.is_none());
+        let requests = agent.base_agent.model.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].last().unwrap().content, SYNTHESIS_PROMPT);
+
+        // The last step writes the answer from the observations of the earlier ones.
+        let model = MockModel::new(vec![search(), MockResponse::text("Paris")]);
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![Box::new(RepeatingSearchTool)])
+            .with_max_steps(Some(2))
+            .with_synthesis(Synthesis::default())
+            .build()
+            .unwrap();
+        assert_eq!(agent.run("What is the capital of France?", true).await.unwrap(), "Paris");
+        let last = agent.base_agent.logs.last().unwrap();
+        assert!(matches!(last, Step::ActionStep(step) if step.synthesis == Some(SynthesisTrigger::LastStep)));
+    }
+
+    #[tokio::test]
+    async fn test_long_term_memory() {
+        let store = Arc::new(FileMemoryStore::in_memory());
+        let memory = LongTermMemory::new(store.clone(), "alice");
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![
+            MockResponse::text("Noted, I will use metric units."),
+            MockResponse::text(r#"{"memories": [{"fact": "The user prefers metric units", "entities": []}]}"#),
+            MockResponse::text("It is 21 degrees in Lyon."),
+            MockResponse::text(r#"{"memories": []}"#),
+        ]))
+        .with_long_term_memory(Some(memory))
+        .build()
+        .unwrap();
+        agent.run("I prefer metric units", true).await.unwrap();
+        assert_eq!(store.list("alice").await.unwrap()[0].fact, "The user prefers metric units");
+
+        // A new conversation of the same scope recalls the memory into its system prompt.
+        agent.run("What is the temperature in Lyon?", true).await.unwrap();
+        let requests = agent.base_agent.model.requests();
+        assert!(!requests[0][0].content.contains("The user prefers metric units"));
+        assert!(requests[2][0].content.ends_with("- The user prefers metric units"));
+        assert!(requests[3][1].content.contains("User: New Task: What is the temperature in Lyon?"));
+        assert!(!requests[3][1].content.contains("I prefer metric units\n"));
+    }
+
+    #[tokio::test]
+    async fn test_time_zone() {
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(vec![MockResponse::text("Tuesday")]))
+            .with_time_zone(Some("UTC+09:00".parse().unwrap()))
+            .build()
+            .unwrap();
+        assert!(agent.get_system_prompt().contains("{{current_time}}"));
+        agent.run("What day is it in Tokyo?", true).await.unwrap();
+        // Filled when the run starts, in the time zone of the agent.
+        let system_prompt = &agent.base_agent.model.requests()[0][0].content;
+        assert!(!system_prompt.contains("{{current_time}}"));
+        assert!(system_prompt.contains(" UTC+09:00."));
+    }
 }
@@ -0,0 +1,556 @@
+//! Long-term memory of the facts learned in the runs, across sessions.
+//!
+//! With a [`LongTermMemory`], the agent asks the model for the salient facts of each run once it has its answer, e.g.
+//! the preferences of the user or facts about the people and organizations of the task, and saves them in a
+//! [`MemoryStore`] under the scope of the memory, such as a user or a session. The memories of the scope most
+//! relevant to the next tasks are added to their system prompt.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::observation_processor::{is_content_word, words};
+use super::summary::format_transcript;
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::Model,
+        types::{Message, MessageRole},
+    },
+    prompts::{MEMORIES_PROMPT, MEMORY_EXTRACTION_SYSTEM_PROMPT},
+};
+
+/// The memories added to the system prompt of a task, unless set with `with_max_recalled`.
+pub const DEFAULT_MAX_RECALLED: usize = 10;
+/// The memories a [`FileMemoryStore`] keeps per scope, unless set with `with_max_per_scope`.
+pub const DEFAULT_MAX_MEMORIES_PER_SCOPE: usize = 500;
+
+/// A fact remembered from a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Memory {
+    pub id: String,
+    /// The user, session or other scope the memory belongs to.
+    pub scope: String,
+    pub fact: String,
+    /// The names of the people, organizations, places and things the fact is about.
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// The task of the run the fact was learned in.
+    pub task: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where memories are saved, by scope.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait MemoryStore: Send + Sync {
+    async fn add(&self, memories: Vec<Memory>) -> Result<()>;
+    /// The memories of the scope, oldest first.
+    async fn list(&self, scope: &str) -> Result<Vec<Memory>>;
+    /// Delete a memory of the scope. Returns `false` when the scope has no memory with this id.
+    async fn delete(&self, scope: &str, id: &str) -> Result<bool>;
+    /// Delete all the memories of the scope, returning how many there were.
+    async fn clear(&self, scope: &str) -> Result<usize>;
+}
+
+/// Memories saved in a JSON file, or only kept in memory. The file is written on the blocking threads of tokio, one
+/// write at a time.
+#[derive(Debug)]
+pub struct FileMemoryStore {
+    path: Option<PathBuf>,
+    scopes: Mutex<BTreeMap<String, Vec<Memory>>>,
+    /// The memories kept per scope, the oldest ones being forgotten first.
+    max_per_scope: usize,
+}
+
+impl Default for FileMemoryStore {
+    fn default() -> Self {
+        Self {
+            path: None,
+            scopes: Mutex::default(),
+            max_per_scope: DEFAULT_MAX_MEMORIES_PER_SCOPE,
+        }
+    }
+}
+
+impl FileMemoryStore {
+    /// The memories of the file, which is created on the first memory.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let scopes = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read memories: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse memories: {:?}", path))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            scopes: Mutex::new(scopes),
+            ..Self::default()
+        })
+    }
+
+    /// Memories that are lost when the store is dropped.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// The number of memories kept per scope, 500 by default. Adding more forgets the oldest memories of the scope.
+    pub fn with_max_per_scope(mut self, max_per_scope: usize) -> Self {
+        self.max_per_scope = max_per_scope.max(1);
+        self
+    }
+
+    /// Write the memories to the file. Called with the lock of the memories held, so the writes are in order.
+    async fn save(&self, scopes: &BTreeMap<String, Vec<Memory>>) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let content = serde_json::to_string(scopes)?;
+        write_in_background(path, content).await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn write_in_background(path: PathBuf, content: String) -> Result<()> {
+    tokio::task::spawn_blocking(move || write_memories(&path, &content)).await?
+}
+
+/// There are no blocking threads on wasm32, where the memories are kept in memory anyway.
+#[cfg(target_arch = "wasm32")]
+async fn write_in_background(path: PathBuf, content: String) -> Result<()> {
+    write_memories(&path, &content)
+}
+
+fn write_memories(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create memory directory: {:?}", dir))?;
+    }
+    // Written next to the file and renamed, so a crash while writing keeps the previous memories.
+    let partial = path.with_extension("json.partial");
+    std::fs::write(&partial, content)
+        .with_context(|| format!("Failed to write memories: {:?}", partial))?;
+    std::fs::rename(&partial, path).with_context(|| format!("Failed to write memories: {:?}", path))
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl MemoryStore for FileMemoryStore {
+    async fn add(&self, memories: Vec<Memory>) -> Result<()> {
+        let mut scopes = self.scopes.lock().await;
+        for memory in memories {
+            let memories = scopes.entry(memory.scope.clone()).or_default();
+            memories.push(memory);
+            if memories.len() > self.max_per_scope {
+                memories.drain(..memories.len() - self.max_per_scope);
+            }
+        }
+        self.save(&scopes).await
+    }
+
+    async fn list(&self, scope: &str) -> Result<Vec<Memory>> {
+        Ok(self
+            .scopes
+            .lock()
+            .await
+            .get(scope)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn delete(&self, scope: &str, id: &str) -> Result<bool> {
+        let mut scopes = self.scopes.lock().await;
+        let Some(memories) = scopes.get_mut(scope) else {
+            return Ok(false);
+        };
+        let Some(position) = memories.iter().position(|memory| memory.id == id) else {
+            return Ok(false);
+        };
+        memories.remove(position);
+        if memories.is_empty() {
+            scopes.remove(scope);
+        }
+        self.save(&scopes).await?;
+        Ok(true)
+    }
+
+    async fn clear(&self, scope: &str) -> Result<usize> {
+        let mut scopes = self.scopes.lock().await;
+        let Some(memories) = scopes.remove(scope) else {
+            return Ok(0);
+        };
+        self.save(&scopes).await?;
+        Ok(memories.len())
+    }
+}
+
+/// The memories of a scope, recalled into the system prompt of the tasks and extended after each run.
+#[derive(Clone)]
+pub struct LongTermMemory {
+    store: Arc<dyn MemoryStore>,
+    scope: String,
+    max_recalled: usize,
+}
+
+impl std::fmt::Debug for LongTermMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LongTermMemory")
+            .field("scope", &self.scope)
+            .field("max_recalled", &self.max_recalled)
+            .finish()
+    }
+}
+
+impl LongTermMemory {
+    pub fn new(store: Arc<dyn MemoryStore>, scope: impl Into<String>) -> Self {
+        Self {
+            store,
+            scope: scope.into(),
+            max_recalled: DEFAULT_MAX_RECALLED,
+        }
+    }
+
+    /// The number of memories added to the system prompt of a task at most.
+    pub fn with_max_recalled(mut self, max_recalled: usize) -> Self {
+        self.max_recalled = max_recalled;
+        self
+    }
+
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub fn store(&self) -> &Arc<dyn MemoryStore> {
+        &self.store
+    }
+
+    /// The memories most relevant to the task: those sharing the most words and entities with it, then the most
+    /// recent ones.
+    pub async fn recall(&self, task: &str) -> Result<Vec<Memory>> {
+        let task_words = words(task)
+            .filter(|word| is_content_word(word))
+            .collect::<HashSet<_>>();
+        let task = task.to_lowercase();
+        let mut memories = self
+            .store
+            .list(&self.scope)
+            .await?
+            .into_iter()
+            .enumerate()
+            .map(|(i, memory)| {
+                let shared_words = words(&memory.fact)
+                    .filter(|word| task_words.contains(word))
+                    .collect::<HashSet<_>>()
+                    .len();
+                let mentioned_entities = memory
+                    .entities
+                    .iter()
+                    .filter(|entity| !entity.is_empty() && task.contains(&entity.to_lowercase()))
+                    .count();
+                (shared_words + 2 * mentioned_entities, i, memory)
+            })
+            .collect::<Vec<_>>();
+        memories.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+        Ok(memories
+            .into_iter()
+            .take(self.max_recalled)
+            .map(|(_, _, memory)| memory)
+            .collect())
+    }
+
+    /// Extract the facts worth remembering from the messages of the run with the model, and save those the scope
+    /// does not know yet.
+    pub async fn remember(
+        &self,
+        model: &dyn Model,
+        task: &str,
+        messages: &[Message],
+    ) -> Result<Vec<Memory>, AgentError> {
+        let known = self
+            .store
+            .list(&self.scope)
+            .await
+            .map_err(|e| AgentError::Execution(e.to_string()))?;
+        let mut content = format!("Conversation:\n{}", format_transcript(messages));
+        if !known.is_empty() {
+            content = format!(
+                "Already known:\n{}\n\n{}",
+                known
+                    .iter()
+                    .map(|memory| format!("- {}", memory.fact))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                content
+            );
+        }
+        let prompt = vec![
+            Message {
+                role: MessageRole::System,
+                content: MEMORY_EXTRACTION_SYSTEM_PROMPT.to_string(),
+                tool_call_id: None,
+                tool_calls: None,
+            },
+            Message {
+                role: MessageRole::User,
+                content,
+                tool_call_id: None,
+                tool_calls: None,
+            },
+        ];
+        let response = model
+            .run(prompt, None, vec![], Some(500), None)
+            .await?
+            .get_response()?;
+
+        let mut seen = known
+            .iter()
+            .map(|memory| normalize(&memory.fact))
+            .collect::<HashSet<_>>();
+        let memories = parse_memories(&response)
+            .into_iter()
+            .filter(|extracted| seen.insert(normalize(&extracted.fact)))
+            .map(|extracted| Memory {
+                id: nanoid::nanoid!(),
+                scope: self.scope.clone(),
+                fact: extracted.fact,
+                entities: extracted.entities,
+                task: task.to_string(),
+                created_at: Utc::now(),
+            })
+            .collect::<Vec<_>>();
+        if !memories.is_empty() {
+            self.store
+                .add(memories.clone())
+                .await
+                .map_err(|e| AgentError::Execution(e.to_string()))?;
+        }
+        Ok(memories)
+    }
+}
+
+/// The part of the system prompt listing the memories.
+pub fn memories_prompt(memories: &[Memory]) -> String {
+    let facts = memories
+        .iter()
+        .map(|memory| format!("- {}", memory.fact))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("{}\n{}", MEMORIES_PROMPT, facts)
+}
+
+#[derive(Deserialize)]
+struct ExtractedMemory {
+    fact: String,
+    #[serde(default)]
+    entities: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExtractedMemories {
+    memories: Vec<ExtractedMemory>,
+}
+
+/// The facts of the JSON answer of the model. An answer that is not JSON has no facts, rather than facts made of
+/// its text.
+fn parse_memories(response: &str) -> Vec<ExtractedMemory> {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => return vec![],
+    };
+    serde_json::from_str::<ExtractedMemories>(json)
+        .map(|extracted| extracted.memories)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|memory| ExtractedMemory {
+            fact: memory.fact.trim().to_string(),
+            entities: memory.entities,
+        })
+        .filter(|memory| !memory.fact.is_empty())
+        .collect()
+}
+
+fn normalize(fact: &str) -> String {
+    words(fact).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+
+    fn memory(scope: &str, fact: &str, entities: &[&str]) -> Memory {
+        Memory {
+            id: nanoid::nanoid!(),
+            scope: scope.to_string(),
+            fact: fact.to_string(),
+            entities: entities.iter().map(|entity| entity.to_string()).collect(),
+            task: String::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_memory_store() {
+        let path = std::env::temp_dir()
+            .join(format!("lumo-memories-{}", nanoid::nanoid!()))
+            .join("memories.json");
+        let store = FileMemoryStore::new(&path).unwrap();
+        let first = memory("alice", "Alice lives in Lyon", &["Alice", "Lyon"]);
+        store
+            .add(vec![
+                first.clone(),
+                memory("alice", "Alice prefers metric units", &["Alice"]),
+                memory("bob", "Bob works at Acme", &["Bob", "Acme"]),
+            ])
+            .await
+            .unwrap();
+
+        let store = FileMemoryStore::new(&path).unwrap();
+        assert_eq!(store.list("alice").await.unwrap().len(), 2);
+        assert!(!store.delete("bob", &first.id).await.unwrap());
+        assert!(store.delete("alice", &first.id).await.unwrap());
+        assert_eq!(store.list("alice").await.unwrap().len(), 1);
+        assert_eq!(store.clear("bob").await.unwrap(), 1);
+
+        let store = FileMemoryStore::new(&path).unwrap();
+        assert!(store.list("bob").await.unwrap().is_empty());
+        assert_eq!(
+            store.list("alice").await.unwrap()[0].fact,
+            "Alice prefers metric units"
+        );
+
+        // The writes of concurrent adds are in order, the file has all the memories.
+        futures::future::join_all(
+            (0..10).map(|i| store.add(vec![memory("carol", &format!("Fact {}", i), &[])])),
+        )
+        .await;
+        let store = FileMemoryStore::new(&path).unwrap();
+        assert_eq!(store.list("carol").await.unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_max_per_scope() {
+        let store = FileMemoryStore::in_memory().with_max_per_scope(2);
+        store
+            .add(vec![
+                memory("alice", "Alice lives in Lyon", &[]),
+                memory("alice", "Alice prefers metric units", &[]),
+                memory("bob", "Bob works at Acme", &[]),
+            ])
+            .await
+            .unwrap();
+        store
+            .add(vec![memory("alice", "Alice is vegetarian", &[])])
+            .await
+            .unwrap();
+        let facts = |memories: Vec<Memory>| {
+            memories
+                .into_iter()
+                .map(|memory| memory.fact)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            facts(store.list("alice").await.unwrap()),
+            vec!["Alice prefers metric units", "Alice is vegetarian"]
+        );
+        assert_eq!(store.list("bob").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recall() {
+        let store = Arc::new(FileMemoryStore::in_memory());
+        store
+            .add(vec![
+                memory("alice", "The project deadline is in March", &[]),
+                memory("alice", "Alice lives in Lyon", &["Alice", "Lyon"]),
+                memory("alice", "Alice prefers metric units", &["Alice"]),
+                memory("bob", "Bob lives in Lyon", &["Bob", "Lyon"]),
+            ])
+            .await
+            .unwrap();
+        let memory = LongTermMemory::new(store, "alice").with_max_recalled(2);
+        let recalled = memory
+            .recall("What will the weather be in Lyon tomorrow?")
+            .await
+            .unwrap();
+        let facts = recalled
+            .iter()
+            .map(|memory| memory.fact.as_str())
+            .collect::<Vec<_>>();
+        // The memory about Lyon first, then the most recent one.
+        assert_eq!(
+            facts,
+            vec!["Alice lives in Lyon", "Alice prefers metric units"]
+        );
+        assert!(memories_prompt(&recalled)
+            .ends_with("- Alice lives in Lyon\n- Alice prefers metric units"));
+    }
+
+    #[tokio::test]
+    async fn test_remember() {
+        let store = Arc::new(FileMemoryStore::in_memory());
+        store
+            .add(vec![memory(
+                "alice",
+                "Alice lives in Lyon",
+                &["Alice", "Lyon"],
+            )])
+            .await
+            .unwrap();
+        let model = MockModel::new(vec![MockResponse::text(
+            r#"```json
+{"memories": [
+  {"fact": "Alice lives in Lyon.", "entities": ["Alice", "Lyon"]},
+  {"fact": "Alice is allergic to peanuts", "entities": ["Alice"]}
+]}
+```"#,
+        )]);
+        let memory = LongTermMemory::new(store.clone(), "alice");
+        let messages = vec![
+            Message {
+                role: MessageRole::User,
+                content: "Find a restaurant in Lyon, I am allergic to peanuts".to_string(),
+                tool_call_id: None,
+                tool_calls: None,
+            },
+            Message {
+                role: MessageRole::Assistant,
+                content: "Le Bouchon des Filles serves dishes without peanuts.".to_string(),
+                tool_call_id: None,
+                tool_calls: None,
+            },
+        ];
+        let remembered = memory
+            .remember(&model, "Find a restaurant in Lyon", &messages)
+            .await
+            .unwrap();
+        // The fact already known is not saved again.
+        assert_eq!(remembered.len(), 1);
+        assert_eq!(remembered[0].fact, "Alice is allergic to peanuts");
+        assert_eq!(store.list("alice").await.unwrap().len(), 2);
+
+        let request = &model.requests()[0][1].content;
+        assert!(request.starts_with("Already known:\n- Alice lives in Lyon"));
+        assert!(request.contains("User: Find a restaurant in Lyon"));
+    }
+
+    #[test]
+    fn test_parse_memories() {
+        assert!(parse_memories("Nothing worth remembering.").is_empty());
+        assert!(parse_memories(r#"{"memories": []}"#).is_empty());
+        let memories =
+            parse_memories(r#"{"memories": [{"fact": " Bob works at Acme "}, {"fact": ""}]}"#);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].fact, "Bob works at Acme");
+        assert!(memories[0].entities.is_empty());
+    }
+}
@@ -1,29 +1,38 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::{
-    agent::parse_response,
+    agent::parse_tool_calls,
     errors::AgentError,
+    guardrails::{Guardrail, Guardrails},
     models::{
+        cancellation::CancellationToken,
         model_traits::Model,
-        openai::{FunctionCall, Status, ToolCall},
+        openai::Status,
         types::Message,
     },
-    prompts::TOOL_CALLING_SYSTEM_PROMPT,
-    telemetry::AgentTelemetry,
-    tools::{ToolFunctionInfo, ToolGroup, ToolInfo, ToolType},
+    prompts::{Prompts, RetryPrompts, ENGLISH_PROMPTS, TOOL_CALLING_SYSTEM_PROMPT},
+    telemetry::{
+        otel::trace::{FutureExt, TraceContextExt},
+        AgentTelemetry, RunMetadata,
+    },
+    tools::{time_zone::Zone, ToolFunctionInfo, ToolGroup, ToolInfo, ToolType},
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::future::join_all;
 use rmcp::{
     model::{CallToolRequestParam, RawContent, Tool}, service::RunningService, RoleClient
 };
-use opentelemetry::trace::{FutureExt, TraceContextExt};
-use serde_json::json;
 use tokio::sync::broadcast;
 use tracing::instrument;
 
-use super::{Agent, AgentStep, MultiStepAgent, Step};
+use super::{
+    tool_dependencies::{execution_waves, resolve_tool_call},
+    Agent, AgentStep, CheckpointStore, CircuitBreaker, LongTermMemory, MultiStepAgent, ObservationProcessor,
+    Reproducibility, ResponseLanguage, RunRecorder, Scratchpad, Step, StepOverrides, Synthesis, ToolCallingMode, ToolObservation,
+    DEFAULT_FAILURE_THRESHOLD,
+};
 
 #[cfg(feature = "stream")]
 use super::agent_trait::AgentStream;
@@ -36,17 +45,129 @@ fn initialize_system_prompt(system_prompt: String, tools: Vec<Tool>) -> Result<S
     let tool_description = serde_json::to_string(&tools)?;
     let mut system_prompt = system_prompt.replace("{{tool_names}}", &tool_names.join(", "));
     system_prompt = system_prompt.replace("{{tool_descriptions}}", &tool_description);
-    system_prompt = system_prompt.replace("{{current_time}}", &chrono::Local::now().to_string());
     Ok(system_prompt)
 }
 
+/// An MCP client and the name of its server. The name is used to namespace the tools of the server.
+pub struct McpClient {
+    pub name: String,
+    pub service: RunningService<RoleClient, ()>,
+}
+
+impl McpClient {
+    pub fn new(name: &str, service: RunningService<RoleClient, ()>) -> Self {
+        Self {
+            name: name.to_string(),
+            service,
+        }
+    }
+}
+
+impl From<RunningService<RoleClient, ()>> for McpClient {
+    /// Use the name the server reported during initialization.
+    fn from(service: RunningService<RoleClient, ()>) -> Self {
+        let name = service
+            .peer_info()
+            .map(|info| info.server_info.name.clone())
+            .unwrap_or_else(|| "mcp".to_string());
+        Self { name, service }
+    }
+}
+
+/// How the tools of the MCP servers are named for the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolNamespacing {
+    /// Keep the tool names. Building the agent fails if two servers expose a tool with the same name.
+    #[default]
+    Never,
+    /// Namespace only the tools whose name is exposed by more than one server.
+    OnConflict,
+    /// Namespace every tool.
+    Always,
+}
+
+/// The client and the original name of a tool exposed to the model.
+#[derive(Debug, Clone, PartialEq)]
+struct ToolRoute {
+    client: usize,
+    name: String,
+}
+
+/// The name of a namespaced tool in the tool schema.
+///
+/// The qualified name is `server_name.tool_name`, but function names can't contain dots for most providers, so the
+/// schema uses `server_name__tool_name`. Both names are routed to the tool.
+pub fn namespaced_tool_name(server_name: &str, tool_name: &str) -> String {
+    let server_name = server_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect::<String>();
+    format!("{}__{}", server_name, tool_name)
+}
+
+/// Name the tools of each server for the model and map each name to the client that registered the tool.
+fn route_tools(
+    servers: &[(String, Vec<Tool>)],
+    namespacing: ToolNamespacing,
+) -> Result<(Vec<Tool>, HashMap<String, ToolRoute>)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, tools) in servers {
+        for tool in tools {
+            *counts.entry(tool.name.as_ref()).or_default() += 1;
+        }
+    }
+
+    let mut exposed = Vec::new();
+    let mut routes: HashMap<String, ToolRoute> = HashMap::new();
+    let mut owners: HashMap<String, &str> = HashMap::new();
+    for (client, (server_name, tools)) in servers.iter().enumerate() {
+        for tool in tools {
+            let namespaced = match namespacing {
+                ToolNamespacing::Never => false,
+                ToolNamespacing::OnConflict => counts[tool.name.as_ref()] > 1,
+                ToolNamespacing::Always => true,
+            };
+            let alias = if namespaced {
+                namespaced_tool_name(server_name, &tool.name)
+            } else {
+                tool.name.to_string()
+            };
+            if let Some(owner) = owners.get(&alias) {
+                return Err(anyhow!(
+                    "Tool '{}' is exposed by both MCP servers '{}' and '{}'. Give the servers distinct names and set the tool namespacing to `OnConflict` or `Always`",
+                    alias,
+                    owner,
+                    server_name
+                ));
+            }
+            let route = ToolRoute {
+                client,
+                name: tool.name.to_string(),
+            };
+            if namespaced {
+                routes
+                    .entry(format!("{}.{}", server_name, tool.name))
+                    .or_insert(route.clone());
+            }
+            routes.insert(alias.clone(), route);
+            owners.insert(alias.clone(), server_name);
+
+            let mut tool = tool.clone();
+            tool.name = alias.into();
+            exposed.push(tool);
+        }
+    }
+    Ok((exposed, routes))
+}
+
 pub struct McpAgent<M>
 where
     M: Model + Send + Sync + 'static{ 
         
     base_agent: MultiStepAgent<M>,
-    mcp_clients: Vec<RunningService<RoleClient, ()>>,
+    mcp_clients: Vec<McpClient>,
     tools: Vec<Tool>,
+    tool_routes: HashMap<String, ToolRoute>,
     telemetry: AgentTelemetry,
 }
 
@@ -59,6 +180,7 @@ impl From<Tool> for ToolInfo {
                 name: tool.name.to_string(),
                 description: tool.description.unwrap_or_default().to_string(),
                 parameters: schema,
+                strict: None,
             },
         }
     }
@@ -90,24 +212,28 @@ where
         managed_agents: Vec<Box<dyn Agent>>,
         description: Option<&str>,
         max_steps: Option<usize>,
-        mcp_clients: Vec<RunningService<RoleClient, ()>>,
+        mcp_clients: Vec<McpClient>,
         planning_interval: Option<usize>,
         history: Option<Vec<Message>>,
         logging_level: Option<log::LevelFilter>,
+        tool_namespacing: Option<ToolNamespacing>,
     ) -> Result<Self> {
         let system_prompt = match system_prompt {
             Some(prompt) => prompt.to_string(),
             None => TOOL_CALLING_SYSTEM_PROMPT.to_string(),
         };
-        let mut tools = Vec::new();
+        let mut servers = Vec::new();
         for client in &mcp_clients {
-            tools.extend(client.list_tools(None).await?.tools);
+            let tools = client.service.list_tools(None).await?.tools;
+            servers.push((client.name.clone(), tools));
         }
+        let (tools, tool_routes) = route_tools(&servers, tool_namespacing.unwrap_or_default())?;
         let description = match description {
             Some(desc) => desc.to_string(),
             None => "A multi-step agent that can solve tasks using a series of tools".to_string(),
         };
-        let base_agent = MultiStepAgent::new(
+        let tool_descriptions_in_prompt = system_prompt.contains("{{tool_descriptions}}");
+        let mut base_agent = MultiStepAgent::new(
             name,
             model,
             vec![],
@@ -119,13 +245,24 @@ where
             history,
             logging_level,
         )?;
+        // The tools of the servers were described before the base agent was built.
+        base_agent.tool_descriptions_in_prompt = tool_descriptions_in_prompt;
         Ok(Self {
             base_agent,
             mcp_clients,
             tools: tools.to_vec(),
+            tool_routes,
             telemetry: AgentTelemetry::new("lumo"),
         })
     }
+
+    /// Take back the MCP clients, e.g. to reuse them in another agent. The agent can not call its MCP tools
+    /// afterwards.
+    pub fn take_mcp_clients(&mut self) -> Vec<McpClient> {
+        self.tools.clear();
+        self.tool_routes.clear();
+        std::mem::take(&mut self.mcp_clients)
+    }
 }
 
 pub struct McpAgentBuilder<'a, M>
@@ -140,8 +277,31 @@ where
     max_steps: Option<usize>,
     planning_interval: Option<usize>,
     history: Option<Vec<Message>>,
-    mcp_clients: Vec<RunningService<RoleClient, ()>>,
+    mcp_clients: Vec<McpClient>,
     logging_level: Option<log::LevelFilter>,
+    guardrails: Vec<Box<dyn Guardrail>>,
+    context_window: Option<usize>,
+    answer_validation: bool,
+    response_language: Option<ResponseLanguage>,
+    json_answer: bool,
+    time_zone: Option<Zone>,
+    retry_prompts: Option<RetryPrompts>,
+    observation_processor: Option<ObservationProcessor>,
+    checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    planning_model: Option<Box<dyn Model>>,
+    fallback_model: Option<Box<dyn Model>>,
+    reproducibility: Option<Reproducibility>,
+    cancellation: Option<CancellationToken>,
+    long_term_memory: Option<LongTermMemory>,
+    prompt_language: Option<&'a str>,
+    parse_retries: usize,
+    tool_calling: ToolCallingMode,
+    synthesis: Option<Synthesis>,
+    circuit_breaker: Option<usize>,
+    max_parallel_tool_calls: Option<usize>,
+    tool_namespacing: Option<ToolNamespacing>,
+    tags: Vec<String>,
+    metadata: HashMap<String, String>,
 }
 
 impl<'a, M> McpAgentBuilder<'a, M>
@@ -160,6 +320,29 @@ where
             history: None,
             mcp_clients: vec![],
             logging_level: None,
+            guardrails: vec![],
+            context_window: None,
+            answer_validation: false,
+            response_language: None,
+            json_answer: false,
+            time_zone: None,
+            retry_prompts: None,
+            observation_processor: None,
+            checkpoint_store: None,
+            planning_model: None,
+            fallback_model: None,
+            reproducibility: None,
+            cancellation: None,
+            long_term_memory: None,
+            prompt_language: None,
+            parse_retries: 0,
+            tool_calling: ToolCallingMode::default(),
+            synthesis: None,
+            circuit_breaker: Some(DEFAULT_FAILURE_THRESHOLD),
+            max_parallel_tool_calls: None,
+            tool_namespacing: None,
+            tags: Vec::new(),
+            metadata: HashMap::new(),
         }
     }
     pub fn with_name(mut self, name: Option<&'a str>) -> Self {
@@ -190,19 +373,169 @@ where
         self.history = history;
         self
     }
+    /// Add MCP clients, named after the server name they reported during initialization.
     pub fn with_mcp_clients(mut self, mcp_clients: Vec<RunningService<RoleClient, ()>>) -> Self {
-        self.mcp_clients = mcp_clients;
+        self.mcp_clients
+            .extend(mcp_clients.into_iter().map(McpClient::from));
+        self
+    }
+    /// Add MCP clients with the name used to namespace their tools, e.g. the name of the server in the config file.
+    pub fn with_named_mcp_clients(
+        mut self,
+        mcp_clients: Vec<(String, RunningService<RoleClient, ()>)>,
+    ) -> Self {
+        self.mcp_clients.extend(
+            mcp_clients
+                .into_iter()
+                .map(|(name, service)| McpClient::new(&name, service)),
+        );
+        self
+    }
+    pub fn with_tool_namespacing(mut self, tool_namespacing: Option<ToolNamespacing>) -> Self {
+        self.tool_namespacing = tool_namespacing;
         self
     }
+    /// Print the steps with the colored logger of the crate at this level. Without a level the agent does not
+    /// install a `log` logger, so the application can use its own.
     pub fn with_logging_level(mut self, logging_level: Option<log::LevelFilter>) -> Self {
         self.logging_level = logging_level;
         self
     }
+    pub fn with_guardrails(mut self, guardrails: Vec<Box<dyn Guardrail>>) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+    /// The number of tokens the model accepts. The memory is trimmed to fit it, minus the tokens reserved for the
+    /// response. Defaults to the context window reported by the model, if any.
+    pub fn with_context_window(mut self, context_window: usize) -> Self {
+        self.context_window = Some(context_window);
+        self
+    }
+    /// Limit the number of tool calls of a step run at the same time.
+    pub fn with_max_parallel_tool_calls(mut self, max_parallel_tool_calls: usize) -> Self {
+        self.max_parallel_tool_calls = Some(max_parallel_tool_calls);
+        self
+    }
+    /// Ask the model to check that each final answer addresses the task and is supported by the observations. A
+    /// rejected answer is not returned; the agent keeps stepping until `max_steps`.
+    pub fn with_answer_validation(mut self, answer_validation: bool) -> Self {
+        self.answer_validation = answer_validation;
+        self
+    }
+    /// Answer in the language, or in the language of the task with `ResponseLanguage::Auto`. The language is added to
+    /// the system prompt, and a final answer in another language is sent back once to be written again. Off when
+    /// `None`.
+    pub fn with_response_language(mut self, response_language: Option<ResponseLanguage>) -> Self {
+        self.response_language = response_language;
+        self
+    }
+    /// Ask for the final answer as a single JSON value, e.g. an array of objects for a table, so it can be parsed and
+    /// rendered while it is streamed with [`PartialJson`](crate::models::partial_json::PartialJson).
+    pub fn with_json_answer(mut self, json_answer: bool) -> Self {
+        self.json_answer = json_answer;
+        self
+    }
+    /// The time zone of the `{{current_time}}` of the system prompt, e.g. `Some("Asia/Tokyo".parse()?)`. The time
+    /// zone of the system when `None`.
+    pub fn with_time_zone(mut self, time_zone: Option<Zone>) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+    /// Replace the guidance written to the memory after errors, e.g. to tell the agent which tool to search with after
+    /// a page is not found.
+    pub fn with_retry_prompts(mut self, retry_prompts: RetryPrompts) -> Self {
+        self.retry_prompts = Some(retry_prompts);
+        self
+    }
+    /// Remove the passages already seen in earlier observations from the memory, and compress the long observations
+    /// if the processor is set up for it.
+    pub fn with_observation_processor(mut self, processor: ObservationProcessor) -> Self {
+        self.observation_processor = Some(processor);
+        self
+    }
+    /// Save the state of the agent to the store after each step, to resume the run after a crash with
+    /// `Agent::resume`.
+    pub fn with_checkpoint_store(mut self, store: Arc<dyn CheckpointStore>) -> Self {
+        self.checkpoint_store = Some(store);
+        self
+    }
+    /// Use this model for the planning steps, e.g. a cheap and fast one while the action steps use the model of the
+    /// agent.
+    pub fn with_planning_model(mut self, model: impl Model) -> Self {
+        self.planning_model = Some(Box::new(model));
+        self
+    }
+    /// Call this model when the model of a step fails, with the same messages.
+    pub fn with_fallback_model(mut self, model: impl Model) -> Self {
+        self.fallback_model = Some(Box::new(model));
+        self
+    }
+    /// Record the model responses and the tool calls of each run into a manifest, read with `Agent::run_manifest`,
+    /// or answer the tool calls with the outputs of a recorded manifest to execute its run again. Off when `None`.
+    pub fn with_reproducibility(mut self, reproducibility: Option<Reproducibility>) -> Self {
+        self.reproducibility = reproducibility;
+        self
+    }
+    /// Cancels the model requests and the next steps of the runs, e.g. when the user stops the run.
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+    /// Recall the memories of earlier runs relevant to each task into its system prompt, and remember the facts of
+    /// each run once it has its answer. Off when `None`.
+    pub fn with_long_term_memory(mut self, long_term_memory: Option<LongTermMemory>) -> Self {
+        self.long_term_memory = long_term_memory;
+        self
+    }
+    /// Use the built-in prompts in the language, e.g. `Some("fr")`, for the planning steps and for the system prompt
+    /// when none is set. English when `None`, see [`Prompts::languages`] for the others.
+    pub fn with_prompt_language(mut self, prompt_language: Option<&'a str>) -> Self {
+        self.prompt_language = prompt_language;
+        self
+    }
+    /// When the text of a response is not a valid tool call, send it back to the model with a prompt about what is
+    /// wrong, up to `parse_retries` times, instead of taking the text as the final answer. Off by default.
+    pub fn with_parse_retries(mut self, parse_retries: usize) -> Self {
+        self.parse_retries = parse_retries;
+        self
+    }
+    /// Send the tools in the request, or describe them in the prompt and parse the tool calls from the text of the
+    /// responses, for models without native tool calling. `Auto` by default, which asks the model.
+    pub fn with_tool_calling(mut self, tool_calling: ToolCallingMode) -> Self {
+        self.tool_calling = tool_calling;
+        self
+    }
+    /// Write the final answer from the observations with a model call without tools once the agent has gathered
+    /// enough of them, e.g. when the model repeats its tool calls. Off by default.
+    pub fn with_synthesis(mut self, synthesis: Synthesis) -> Self {
+        self.synthesis = Some(synthesis);
+        self
+    }
+    /// Stop offering a tool to the model for the rest of the run after this many consecutive failures, e.g. when
+    /// its API key expired. On by default after 3 failures, `None` turns it off.
+    pub fn with_circuit_breaker(mut self, threshold: Option<usize>) -> Self {
+        self.circuit_breaker = threshold;
+        self
+    }
+    /// Tags added to the telemetry spans of the agent, to filter traces.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+    /// Metadata added to the telemetry spans of the agent, e.g. a user or tenant id.
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata.extend(metadata);
+        self
+    }
     pub async fn build(self) -> Result<McpAgent<M>> {
-        McpAgent::new(
+        let prompts = match self.prompt_language {
+            Some(language) => Prompts::try_for_language(language)?,
+            None => &ENGLISH_PROMPTS,
+        };
+        let mut agent = McpAgent::new(
             self.name,
             self.model,
-            self.system_prompt,
+            self.system_prompt.or(Some(prompts.tool_calling_system_prompt)),
             self.managed_agents,
             self.description,
             self.max_steps,
@@ -210,8 +543,33 @@ where
             self.planning_interval,
             self.history,
             self.logging_level,
+            self.tool_namespacing,
         )
-        .await
+        .await?;
+        agent.base_agent.guardrails = Guardrails::new(self.guardrails);
+        agent.base_agent.context_window = self.context_window;
+        agent.base_agent.answer_validation = self.answer_validation;
+        agent.base_agent.response_language = self.response_language;
+        agent.base_agent.json_answer = self.json_answer;
+        agent.base_agent.time_zone = self.time_zone;
+        agent.base_agent.retry_prompts = self.retry_prompts;
+        agent.base_agent.observation_processor = self.observation_processor;
+        agent.base_agent.checkpoint_store = self.checkpoint_store;
+        agent.base_agent.planning_model = self.planning_model;
+        agent.base_agent.fallback_model = self.fallback_model;
+        agent.base_agent.recorder = self.reproducibility.map(RunRecorder::new);
+        agent.base_agent.cancellation = self.cancellation;
+        agent.base_agent.long_term_memory = self.long_term_memory;
+        agent.base_agent.prompts = prompts;
+        agent.base_agent.parse_retries = self.parse_retries;
+        agent.base_agent.tool_calling = self.tool_calling;
+        agent.base_agent.synthesis = self.synthesis;
+        agent.base_agent.circuit_breaker = self.circuit_breaker.map(CircuitBreaker::new);
+        agent.base_agent.max_parallel_tool_calls = self.max_parallel_tool_calls;
+        agent
+            .telemetry
+            .set_run_metadata(RunMetadata::new(self.tags, self.metadata));
+        Ok(agent)
     }
 }
 
@@ -256,6 +614,72 @@ where
     fn model(&self) -> &dyn Model {
         self.base_agent.model()
     }
+    fn guardrails(&self) -> Option<&Guardrails> {
+        self.base_agent.guardrails()
+    }
+    fn context_window(&self) -> Option<usize> {
+        self.base_agent.context_window()
+    }
+    fn answer_validation(&self) -> bool {
+        self.base_agent.answer_validation()
+    }
+    fn response_language(&self) -> Option<&ResponseLanguage> {
+        self.base_agent.response_language()
+    }
+    fn json_answer(&self) -> bool {
+        self.base_agent.json_answer()
+    }
+    fn time_zone(&self) -> Option<&Zone> {
+        self.base_agent.time_zone()
+    }
+    fn recorder(&self) -> Option<&RunRecorder> {
+        self.base_agent.recorder()
+    }
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.base_agent.cancellation()
+    }
+    fn set_cancellation(&mut self, cancellation: Option<CancellationToken>) {
+        self.base_agent.set_cancellation(cancellation);
+    }
+    fn long_term_memory(&self) -> Option<&LongTermMemory> {
+        self.base_agent.long_term_memory()
+    }
+    fn set_long_term_memory(&mut self, long_term_memory: Option<LongTermMemory>) {
+        self.base_agent.set_long_term_memory(long_term_memory);
+    }
+    fn scratchpad(&self) -> Option<&Scratchpad> {
+        self.base_agent.scratchpad()
+    }
+    fn retry_prompts(&self) -> Option<&RetryPrompts> {
+        self.base_agent.retry_prompts()
+    }
+    fn observation_processor(&self) -> Option<&ObservationProcessor> {
+        self.base_agent.observation_processor()
+    }
+    fn set_step_overrides(&mut self, overrides: StepOverrides) {
+        self.base_agent.set_step_overrides(overrides);
+    }
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        for client in self.take_mcp_clients() {
+            // Cancelling waits for the service to stop, which kills the child process of a stdio server.
+            if let Err(e) = client.service.cancel().await {
+                tracing::warn!(server = %client.name, error = %e, "Failed to shut down the MCP server");
+            }
+        }
+        self.base_agent.shutdown().await
+    }
+    fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.base_agent.checkpoint_store()
+    }
+    fn checkpoint_id(&self) -> Option<&str> {
+        self.base_agent.checkpoint_id()
+    }
+    fn set_checkpoint_id(&mut self, checkpoint_id: Option<String>) {
+        self.base_agent.set_checkpoint_id(checkpoint_id);
+    }
+    fn set_history(&mut self, history: Option<Vec<Message>>) {
+        self.base_agent.set_history(history);
+    }
     fn get_planning_interval(&self) -> Option<usize> {
         self.base_agent.get_planning_interval()
     }
@@ -279,47 +703,40 @@ where
     async fn step(
         &mut self,
         log_entry: &mut Step,
-        _tx: Option<broadcast::Sender<Status>>,
+        tx: Option<broadcast::Sender<Status>>,
     ) -> Result<Option<AgentStep>, AgentError> {
         match log_entry {
             Step::ActionStep(step_log) => {
                 let cx = self.telemetry.start_step(self.get_step_number() as i64);
+                if self.get_step_number() == 1 {
+                    self.base_agent.reset_circuit_breaker();
+                }
 
-                let agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                let overrides = self.base_agent.take_step_overrides();
+                let mut agent_memory = self.base_agent.write_inner_memory_from_logs(None)?;
+                overrides.apply_to_memory(&mut agent_memory);
+                if self.get_step_number() == 1 {
+                    self.base_agent.check_input_guardrails().await?;
+                }
                 self.base_agent.input_messages = Some(agent_memory.clone());
                 step_log.agent_memory = Some(agent_memory.clone());
                 self.telemetry
                     .log_agent_memory(&serde_json::to_value(&agent_memory).unwrap_or_default());
-                let mut tools = self
+                let mut tool_infos = self
                     .tools
                     .iter()
                     .cloned()
                     .map(ToolInfo::from)
                     .collect::<Vec<_>>();
 
-                let managed_agents = self
-                    .base_agent
-                    .managed_agents
-                    .iter()
-                    .map(|agent| ToolInfo {
-                        tool_type: ToolType::Function,
-                        function: ToolFunctionInfo {
-                            name: agent.name().to_string(),
-                            description: agent.description().to_string(),
-                            parameters: json!({
-                                "type": "object",
-                                "properties": {
-                                    "task": {
-                                        "type": "string",
-                                        "description": "The task to perform"
-                                    }
-                                }
-                            }),
-                        },
-                    })
-                    .collect::<Vec<_>>();
-
-                tools.extend(managed_agents);
+                tool_infos.extend(self.base_agent.tools.tool_info());
+                tool_infos.retain(|tool| {
+                    !self.base_agent.is_tool_unavailable(&tool.function.name)
+                        && overrides.allows(&tool.function.name)
+                });
+                if let Some(breaker) = &self.base_agent.circuit_breaker {
+                    self.telemetry.log_unavailable_tools(breaker.open_tools(), &cx);
+                }
 
                 // Add final answer tool
                 // let final_answer_tool = ToolInfo::from(Tool::new(
@@ -338,24 +755,44 @@ where
                 // ));
                 // tools.push(final_answer_tool);
 
-                tracing::debug!("Starting model inference with {} tools", tools.len());
+                // Enough was gathered: the answer is written from the observations instead of calling the tools.
+                if let Some(trigger) = self
+                    .base_agent
+                    .synthesis_before_step()
+                    .filter(|_| overrides.is_empty())
+                {
+                    let answer = self
+                        .base_agent
+                        .synthesize(step_log, agent_memory, trigger, None)
+                        .with_context(cx.clone())
+                        .await?;
+                    self.telemetry.log_final_answer(&answer);
+                    cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
+                    return Ok(Some(step_log.clone()));
+                }
+
+                tracing::debug!("Starting model inference with {} tools", tool_infos.len());
+                let model_start = crate::runtime::Instant::now();
                 let model_message = self
                     .base_agent
-                    .model
-                    .run(
+                    .run_model(
                         self.base_agent.input_messages.as_ref().unwrap().clone(),
-                        self.base_agent.history.clone(),
-                        tools,
-                        None,
+                        tool_infos.clone(),
                         Some(HashMap::from([(
                             "stop".to_string(),
                             vec!["Observation:".to_string()],
                         )])),
+                        None,
                     )
                     .with_context(cx.clone())
                     .await?;
 
                 step_log.llm_output = Some(model_message.get_response().unwrap_or_default());
+                step_log.reasoning = model_message.get_reasoning();
+                step_log.record_model_call(
+                    model_start.elapsed(),
+                    self.base_agent.model.token_counter().as_ref(),
+                );
                 let mut observations = Vec::new();
                 let mut tools = model_message.get_tools_used()?;
 
@@ -367,113 +804,223 @@ where
 
                 self.telemetry.log_tool_calls(&tools, &cx);
 
-                if let Ok(response) = model_message.get_response() {
-                    if !response.trim().is_empty() {
-                        if let Ok(action) = parse_response(&response) {
-                            tools = vec![ToolCall {
-                                id: Some(format!("call_{}", nanoid::nanoid!())),
-                                call_type: Some("function".to_string()),
-                                function: FunctionCall {
-                                    name: action["name"].as_str().unwrap_or_default().to_string(),
-                                    arguments: action["arguments"].clone(),
-                                },
-                            }];
-
-                            step_log.tool_call = Some(tools.clone());
-                            self.telemetry.log_tool_calls(&tools, &cx);
+                if let Ok(mut response) = model_message.get_response() {
+                    let mut synthesis = self.base_agent.synthesis_for_response(&tools, &response);
+                    let parsed = match parse_tool_calls(&response) {
+                        Ok(tool_calls) => Some(tool_calls),
+                        Err(failure)
+                            if tools.is_empty()
+                                && synthesis.is_none()
+                                && self.base_agent.parse_retries > 0 =>
+                        {
+                            let (repaired, tool_calls) = self
+                                .base_agent
+                                .repair_tool_call(response, failure, tool_infos)
+                                .with_context(cx.clone())
+                                .await?;
+                            response = repaired;
+                            step_log.llm_output = Some(response.clone());
+                            Some(tool_calls).filter(|tool_calls| !tool_calls.is_empty())
                         }
+                        Err(_) => None,
+                    };
+                    if let Some(parsed) = parsed {
+                        tools = parsed;
+                        step_log.tool_call = Some(tools.clone());
+                        self.telemetry.log_tool_calls(&tools, &cx);
+                        synthesis =
+                            synthesis.or_else(|| self.base_agent.synthesis_for_response(&tools, &response));
+                    }
+                    if let Some(trigger) = synthesis {
+                        let answer = self
+                            .base_agent
+                            .synthesize(step_log, agent_memory, trigger, None)
+                            .with_context(cx.clone())
+                            .await?;
+                        self.telemetry.log_final_answer(&answer);
+                        cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
+                        return Ok(Some(step_log.clone()));
                     }
                     if tools.is_empty() {
                         self.base_agent.write_inner_memory_from_logs(None)?;
                         step_log.final_answer = Some(response.clone());
-                        step_log.observations = Some(vec![response.clone()]);
+                        step_log.observations = Some(vec![ToolObservation::new(response.clone())]);
                         self.telemetry.log_final_answer(&response);
-                        cx.span().end_with_timestamp(std::time::SystemTime::now());
+                        cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                         return Ok(Some(step_log.clone()));
                     }
                 }
 
+                // The managed agents are the tools of the base agent, the other tools are on the MCP servers.
                 let managed_agent_names = self
                     .base_agent
-                    .managed_agents
+                    .tools
                     .iter()
                     .map(|agent| agent.name())
                     .collect::<Vec<_>>();
 
-                let mut called_tools = Vec::new();
-                for tool in &tools {
+                // The calls that use the results of other calls of the step run after them.
+                let waves = execution_waves(&tools);
+                let mut order = (0..tools.len()).collect::<Vec<_>>();
+                order.sort_by_key(|&position| *waves[position].as_ref().unwrap_or(&0));
+                let mut outputs: Vec<Option<Result<String, String>>> = vec![None; tools.len()];
+                let mut observation_positions = Vec::new();
+                let send_status = |status: Status| {
+                    if let Some(tx) = &tx {
+                        let _ = tx.send(status);
+                    }
+                };
+                for position in order {
+                    let resolved = match waves[position]
+                        .clone()
+                        .and_then(|_| resolve_tool_call(&tools[position], &outputs))
+                    {
+                        Ok(resolved) => resolved,
+                        Err(e) => {
+                            send_status(Status::tool_call_started(&tools[position]));
+                            send_status(Status::tool_call_failed(&tools[position], e.clone()));
+                            observations.push(ToolObservation::error(&tools[position], e.clone()));
+                            observation_positions.push(position);
+                            outputs[position] = Some(Err(e));
+                            continue;
+                        }
+                    };
+                    let tool = &resolved;
+                    self.base_agent
+                        .guardrails
+                        .check_tool_call(&tool.function)
+                        .await?;
                     let function_name = tool.clone().function.name;
 
                     match function_name.as_str() {
                         "final_answer" => {
                             tracing::info!(answer = ?tool.function.arguments, "Final answer received");
                             let answer = self.base_agent.tools.call(&tool.function).await?;
-                            step_log.observations = Some(vec![answer.clone()]);
+                            step_log.observations =
+                                Some(vec![ToolObservation::success(tool, answer.clone())]);
                             step_log.final_answer = Some(answer.clone());
                             return Ok(Some(step_log.clone()));
                         }
+                        _ if self.base_agent.is_tool_unavailable(&function_name) => {
+                            let breaker = self.base_agent.circuit_breaker.as_ref().unwrap();
+                            send_status(Status::tool_call_started(tool));
+                            send_status(Status::tool_call_failed(
+                                tool,
+                                breaker.unavailable_message(&function_name),
+                            ));
+                            observations.push(ToolObservation::error(
+                                tool,
+                                breaker.unavailable_message(&function_name),
+                            ));
+                        }
                         _ => {
                             tracing::info!(
                                 tool = %function_name,
                                 args = ?tool.function.arguments,
                                 "Executing tool call:"
                             );
-                            called_tools.push(tool.function.clone());
 
+                            send_status(Status::tool_call_started(tool));
+                            let tool_start = crate::runtime::Instant::now();
                             let mut futures = Vec::new();
 
                             if !managed_agent_names.contains(&function_name.as_str()) {
-                                // Run tool
-                                {
-                                    for client in &self.mcp_clients {
-                                        if client
-                                            .list_tools(None)
-                                            .await
-                                            .map_err(|e| AgentError::Execution(e.to_string()))?
-                                            .tools
-                                            .iter()
-                                            .any(|t| t.name == tool.function.name)
-                                        {
-                                            futures.push(client.call_tool(
-                                                CallToolRequestParam {
-                                                    name: tool.function.name.clone().into(),
-                                                    arguments: tool.function.arguments.as_object().cloned(),
-                                                },
-                                            ));
+                                // Run tool on the client that registered it
+                                let replayed = self
+                                    .base_agent
+                                    .recorder
+                                    .as_ref()
+                                    .and_then(|recorder| {
+                                        recorder.replayed_output(&function_name, &tool.function.arguments)
+                                    });
+                                match (self.tool_routes.get(&function_name), replayed) {
+                                    (Some(_), Some(result)) => {
+                                        send_status(match &result {
+                                            Ok(text) => {
+                                                Status::tool_call_result(tool, text, tool_start.elapsed(), true)
+                                            }
+                                            Err(e) => Status::tool_call_result(
+                                                tool,
+                                                &e.to_string(),
+                                                tool_start.elapsed(),
+                                                false,
+                                            ),
+                                        });
+                                        observations.push(match &result {
+                                            Ok(text) => ToolObservation::success(
+                                                tool,
+                                                format!("Observation from {}: {}", function_name, text),
+                                            ),
+                                            Err(e) => ToolObservation::error(
+                                                tool,
+                                                format!("Error from {}: {}", function_name, e),
+                                            ),
+                                        });
+                                        if let Some(recorder) = &self.base_agent.recorder {
+                                            recorder.record_tool_call(
+                                                &function_name,
+                                                tool.function.arguments.clone(),
+                                                &result,
+                                            );
                                         }
                                     }
-                                }
-                            } else {
-                                // Run managed agent
-                                let task = tool.function.arguments.get("task");
-                                if let Some(task) = task {
-                                    if let Some(task_str) = task.as_str() {
-                                        tracing::info!(
-                                            tool = %function_name,
-                                            args = ?tool.function.arguments,
-                                            "Executing tool call: Agent Selected {}",
-                                            function_name
+                                    (Some(route), None) => {
+                                        futures.push(self.mcp_clients[route.client].service.call_tool(
+                                            CallToolRequestParam {
+                                                name: route.name.clone().into(),
+                                                arguments: tool.function.arguments.as_object().cloned(),
+                                            },
+                                        ));
+                                    }
+                                    (None, _) => {
+                                        let error_msg = format!(
+                                            "Error: tool '{}' does not exist. Available tools: {}",
+                                            function_name,
+                                            self.tools
+                                                .iter()
+                                                .map(|t| t.name.as_ref())
+                                                .collect::<Vec<_>>()
+                                                .join(", ")
                                         );
-                                        let result = self
-                                            .base_agent
-                                            .managed_agents
-                                            .iter_mut()
-                                            .find(|agent| agent.name() == function_name.as_str())
-                                            .unwrap()
-                                            .run(task_str, true)
-                                            .await?;
-                                        observations.push(result);
+                                        tracing::error!(tool = %function_name, "Tool not found");
+                                        send_status(Status::tool_call_failed(tool, error_msg.clone()));
+                                        observations.push(ToolObservation::error(tool, error_msg));
                                     }
                                 }
+                            } else {
+                                // Run managed agent, with its arguments checked like the arguments of a tool
+                                let result = self
+                                    .base_agent
+                                    .tools
+                                    .call_with_status(&tool.function, tx.clone())
+                                    .await;
+                                send_status(match &result {
+                                    Ok(result) => Status::tool_call_result(tool, result, tool_start.elapsed(), true),
+                                    Err(e) => Status::tool_call_result(
+                                        tool,
+                                        &e.to_string(),
+                                        tool_start.elapsed(),
+                                        false,
+                                    ),
+                                });
+                                // A failed managed agent is answered like a failed tool, so the other
+                                // calls of the step keep their observations.
+                                observations.push(match result {
+                                    Ok(result) => ToolObservation::success(tool, result),
+                                    Err(e) => ToolObservation::error(
+                                        tool,
+                                        format!("Error from {}: {}", function_name, e),
+                                    ),
+                                });
                             }
                             let results = join_all(futures).await;
-                            for (i, result) in results.into_iter().enumerate() {
+                            for result in results {
                                 let cx = self.telemetry.log_tool_execution(
-                                    &called_tools[i].name,
-                                    &called_tools[i].arguments,
+                                    &tool.function.name,
+                                    &tool.function.arguments,
                                     &cx,
                                 );
-                                match result {
+                                let failed = match result {
                                     Ok(observation) => {
                                         let text = observation
                                             .content
@@ -494,9 +1041,33 @@ where
                                             observation = %formatted,
                                             "Tool call succeeded"
                                         );
-                                        self.telemetry.log_tool_result(&text, true, &cx);
+                                        let failed = observation.is_error == Some(true);
+                                        send_status(Status::tool_call_result(
+                                            tool,
+                                            &text,
+                                            tool_start.elapsed(),
+                                            !failed,
+                                        ));
+                                        self.telemetry.log_tool_result(&text, !failed, &cx);
+                                        if let Some(recorder) = &self.base_agent.recorder {
+                                            let result = if failed {
+                                                Err(AgentError::Execution(text.clone()))
+                                            } else {
+                                                Ok(text.clone())
+                                            };
+                                            recorder.record_tool_call(
+                                                &function_name,
+                                                tool.function.arguments.clone(),
+                                                &result,
+                                            );
+                                        }
 
-                                        observations.push(formatted);
+                                        observations.push(if failed {
+                                            ToolObservation::error(tool, formatted)
+                                        } else {
+                                            ToolObservation::success(tool, formatted)
+                                        });
+                                        failed
                                     }
                                     Err(e) => {
                                         let error_msg =
@@ -506,38 +1077,73 @@ where
                                             error = %e,
                                             "Tool call failed"
                                         );
+                                        send_status(Status::tool_call_result(
+                                            tool,
+                                            &error_msg,
+                                            tool_start.elapsed(),
+                                            false,
+                                        ));
                                         self.telemetry.log_tool_result(&error_msg, false, &cx);
+                                        if let Some(recorder) = &self.base_agent.recorder {
+                                            recorder.record_tool_call(
+                                                &function_name,
+                                                tool.function.arguments.clone(),
+                                                &Err(AgentError::Execution(e.to_string())),
+                                            );
+                                        }
 
-                                        observations.push(error_msg);
+                                        observations.push(ToolObservation::error(tool, error_msg));
+                                        true
+                                    }
+                                };
+                                if let Some((failures, message)) =
+                                    self.base_agent.record_tool_outcome(&function_name, failed)
+                                {
+                                    self.telemetry
+                                        .log_circuit_breaker_open(&function_name, failures, &cx);
+                                    if let Some(observation) = observations.last_mut() {
+                                        observation.content.push_str(&format!("\n{}", message));
                                     }
                                 }
-                                cx.span().end_with_timestamp(std::time::SystemTime::now());
+                                cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                             }
+                            step_log.record_tool_call(tool, tool_start.elapsed());
                         }
                     }
+                    observation_positions.push(position);
+                    outputs[position] = observations.last().map(|observation| {
+                        let prefix = format!("Observation from {}: ", function_name);
+                        let content = observation.content.strip_prefix(&prefix).unwrap_or(&observation.content);
+                        if observation.is_error {
+                            Err(content.to_string())
+                        } else {
+                            Ok(content.to_string())
+                        }
+                    });
                 }
-                step_log.observations = Some(observations);
-
-                if step_log
-                    .observations
-                    .clone()
-                    .unwrap_or_default()
-                    .join("\n")
-                    .trim()
-                    .len()
-                    > 30000
-                {
+                // The observations are in the order of the tool calls, whatever the order they ran in.
+                let mut observations = observation_positions
+                    .into_iter()
+                    .zip(observations)
+                    .collect::<Vec<_>>();
+                observations.sort_by_key(|(position, _)| *position);
+                step_log.observations = Some(
+                    observations
+                        .into_iter()
+                        .map(|(_, observation)| observation)
+                        .collect(),
+                );
+
+                let observation_text = step_log.observation_contents().join("\n");
+                if observation_text.trim().len() > 30000 {
                     tracing::debug!(
                         "Observation: {} \n ....This content has been truncated due to the 30000 character limit.....",
-                        step_log.observations.clone().unwrap_or_default().join("\n").trim().chars().take(30000).collect::<String>()
+                        observation_text.trim().chars().take(30000).collect::<String>()
                     );
                 } else {
-                    tracing::debug!(
-                        "Observation: {}",
-                        step_log.observations.clone().unwrap_or_default().join("\n")
-                    );
+                    tracing::debug!("Observation: {}", observation_text);
                 }
-                cx.span().end_with_timestamp(std::time::SystemTime::now());
+                cx.span().end_with_timestamp(crate::runtime::SystemTime::now());
                 Ok(Some(step_log.clone()))
             }
             _ => {
@@ -553,3 +1159,49 @@ where
     M: Model + std::fmt::Debug + Send + Sync,
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn tool(name: &str) -> Tool {
+        Tool::new(name.to_string(), "", Arc::new(serde_json::Map::new()))
+    }
+
+    fn servers() -> Vec<(String, Vec<Tool>)> {
+        vec![
+            ("exa".to_string(), vec![tool("search"), tool("crawl")]),
+            ("brave search".to_string(), vec![tool("search")]),
+        ]
+    }
+
+    fn names(tools: &[Tool]) -> Vec<&str> {
+        tools.iter().map(|t| t.name.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_route_tools() {
+        assert!(route_tools(&servers(), ToolNamespacing::Never).is_err());
+
+        let (tools, routes) = route_tools(&servers(), ToolNamespacing::OnConflict).unwrap();
+        assert_eq!(names(&tools), vec!["exa__search", "crawl", "brave_search__search"]);
+        let route = ToolRoute {
+            client: 1,
+            name: "search".to_string(),
+        };
+        assert_eq!(routes["brave_search__search"], route);
+        assert_eq!(routes["brave search.search"], route);
+        assert_eq!(routes["crawl"].client, 0);
+
+        let (tools, _) = route_tools(&servers(), ToolNamespacing::Always).unwrap();
+        assert_eq!(names(&tools), vec!["exa__search", "exa__crawl", "brave_search__search"]);
+
+        // Two clients for the same server can't be told apart.
+        let duplicated = vec![
+            ("exa".to_string(), vec![tool("search")]),
+            ("exa".to_string(), vec![tool("search")]),
+        ];
+        assert!(route_tools(&duplicated, ToolNamespacing::Always).is_err());
+    }
+}
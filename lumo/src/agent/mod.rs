@@ -1,16 +1,49 @@
 pub mod agent_step;
 pub mod agent_trait;
+pub mod answer_validation;
+pub mod checkpoint;
+pub mod circuit_breaker;
 #[cfg(feature = "code-agent")]
 pub mod code_agent;
+pub mod context_window;
+pub mod export;
 pub mod function_calling_agent;
+pub mod long_term_memory;
 #[cfg(feature = "mcp")]
 pub mod mcp_agent;
 pub mod multistep_agent;
+pub mod observation_processor;
+pub mod orchestrator;
+pub mod reproducibility;
+pub mod response_language;
+pub mod run_summary;
+pub mod step_hook;
+pub mod summary;
+pub mod synthesis;
+pub mod tool_calling;
+pub mod tool_dependencies;
+pub mod transcript;
 pub use agent_step::*;
 pub use agent_trait::*;
+pub use answer_validation::*;
+pub use checkpoint::*;
+pub use circuit_breaker::*;
 #[cfg(feature = "code-agent")]
 pub use code_agent::*;
+pub use export::*;
 pub use function_calling_agent::*;
+pub use long_term_memory::*;
 #[cfg(feature = "mcp")]
 pub use mcp_agent::*;
 pub use multistep_agent::*;
+pub use observation_processor::*;
+pub use orchestrator::*;
+pub use reproducibility::*;
+pub use response_language::*;
+pub use run_summary::*;
+pub use step_hook::*;
+pub use summary::*;
+pub use synthesis::*;
+pub use tool_calling::*;
+pub use tool_dependencies::*;
+pub use transcript::*;
@@ -1,14 +1,15 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::errors::AgentError;
-use crate::logger::LOGGER;
-use crate::models::model_traits::Model;
-use crate::models::openai::Status;
+use crate::guardrails::Guardrails;
+use crate::logger;
+use crate::models::cancellation::{cancellable, CancellationToken};
+use crate::models::model_traits::{Model, ModelResponse};
+use crate::models::openai::{Status, ToolCall};
 use crate::models::types::{Message, MessageRole};
-use crate::prompts::{
-    user_prompt_plan, SYSTEM_PROMPT_FACTS, SYSTEM_PROMPT_PLAN, TOOL_CALLING_SYSTEM_PROMPT,
-};
-use crate::tools::{AsyncTool, ToolGroup, ToolInfo};
+use crate::prompts::{Prompts, RetryPrompts, ENGLISH_PROMPTS, TOOL_CALLING_SYSTEM_PROMPT};
+use crate::tools::{time_zone::Zone, AgentTool, AsyncTool, ToolGroup, ToolInfo};
 use anyhow::Result;
 use async_trait::async_trait;
 use colored::Colorize;
@@ -17,7 +18,18 @@ use tokio::sync::broadcast;
 
 use super::agent_step::Step;
 use super::agent_trait::Agent;
-use super::AgentStep;
+use super::checkpoint::CheckpointStore;
+use super::circuit_breaker::CircuitBreaker;
+use super::long_term_memory::LongTermMemory;
+use super::observation_processor::ObservationProcessor;
+use super::orchestrator::Scratchpad;
+use super::reproducibility::RunRecorder;
+use super::response_language::ResponseLanguage;
+use super::function_calling_agent::{parse_tool_calls, ParseFailure};
+use super::step_hook::StepOverrides;
+use super::synthesis::{Synthesis, SynthesisTrigger, SYNTHESIS_PROMPT};
+use super::tool_calling::{prompt_tool_messages, ToolCallingMode};
+use super::{AgentStep, ToolObservation};
 
 const DEFAULT_TOOL_DESCRIPTION_TEMPLATE: &str = r#"
 {{ tool.name }}: {{ tool.description }}
@@ -55,17 +67,23 @@ pub fn format_prompt_with_tools(tools: Vec<ToolInfo>, prompt_template: &str) ->
     prompt
 }
 
-pub fn show_agents_description(managed_agents: &Vec<Box<dyn Agent>>) -> String {
+/// The name and description of a managed agent. The agent itself is called through an [`AgentTool`] among the tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManagedAgentInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub fn show_agents_description(managed_agents: &[ManagedAgentInfo]) -> String {
     let mut managed_agent_description = r#"You can also give requests to team members.
-Calling a team member works the same as for calling a tool: simply, the only argument you can give in the call is 'request', a long string explaining your request.
+Calling a team member works the same as for calling a tool: simply, the only argument you can give in the call is 'task', a long string explaining your request.
 Given that this team member is a real human, you should be very verbose in your request.
 Here is a list of the team members that you can call:"#.to_string();
 
     for agent in managed_agents.iter() {
         managed_agent_description.push_str(&format!(
             "{}: {:?}\n",
-            agent.name(),
-            agent.description()
+            agent.name, agent.description
         ));
     }
     managed_agent_description
@@ -73,7 +91,7 @@ Here is a list of the team members that you can call:"#.to_string();
 
 pub fn format_prompt_with_managed_agent_description(
     prompt_template: String,
-    managed_agents: &Vec<Box<dyn Agent>>,
+    managed_agents: &[ManagedAgentInfo],
     agent_descriptions_placeholder: Option<&str>,
 ) -> Result<String> {
     let agent_descriptions_placeholder =
@@ -97,7 +115,8 @@ where
     pub tools: Vec<Box<dyn AsyncTool>>,
     pub system_prompt_template: String,
     pub name: &'static str,
-    pub managed_agents: Vec<Box<dyn Agent>>,
+    /// The agents given to the agent, which are called through the [`AgentTool`]s added to `tools`.
+    pub managed_agents: Vec<ManagedAgentInfo>,
     pub description: &'static str,
     pub max_steps: usize,
     pub step_number: usize,
@@ -107,9 +126,113 @@ where
     pub planning_interval: Option<usize>,
     pub history: Option<Vec<Message>>,
     pub logging_level: Option<log::LevelFilter>,
+    pub guardrails: Guardrails,
+    pub context_window: Option<usize>,
+    /// The maximum number of tool calls of a step run at the same time. Unlimited when `None`.
+    pub max_parallel_tool_calls: Option<usize>,
+    /// Check final answers against the task before returning them.
+    pub answer_validation: bool,
+    /// The language of the final answers, added to the system prompt and checked. Off when `None`.
+    pub response_language: Option<ResponseLanguage>,
+    /// Ask for final answers in JSON, added to the system prompt.
+    pub json_answer: bool,
+    /// The guidance written to the memory after errors. The defaults of [`RetryPrompts`] when `None`.
+    pub retry_prompts: Option<RetryPrompts>,
+    /// Stop offering the tools that keep failing for the rest of the run. Off when `None`.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Deduplicate and compress the observations written to the memory. Off when `None`.
+    pub observation_processor: Option<ObservationProcessor>,
+    /// Changes to the next step, taken by the step.
+    pub step_overrides: StepOverrides,
+    /// Times a response whose text is not a valid tool call is sent back to the model to be fixed, before the text
+    /// is taken as the final answer.
+    pub parse_retries: usize,
+    /// Where the state of the agent is saved after each step. Off when `None`.
+    pub checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    /// The id the checkpoints are saved under.
+    pub checkpoint_id: Option<String>,
+    /// The model of the planning steps, e.g. a cheaper one. The model of the agent when `None`.
+    pub planning_model: Option<Box<dyn Model>>,
+    /// The model called again when the model of a step fails.
+    pub fallback_model: Option<Box<dyn Model>>,
+    /// How the tools are given to the model: in the request, or described in the prompt.
+    pub tool_calling: ToolCallingMode,
+    /// Whether the system prompt describes the tools, from its `{{tool_descriptions}}`. They are added to it for
+    /// prompt-based tool calling when it does not.
+    pub tool_descriptions_in_prompt: bool,
+    /// When the steps write the final answer from the observations with a model call without tools. Off when `None`.
+    pub synthesis: Option<Synthesis>,
+    /// The time zone of the `{{current_time}}` of the system prompt. The time zone of the system when `None`.
+    pub time_zone: Option<Zone>,
+    /// Records the model responses and the tool calls of the runs into a manifest. Off when `None`.
+    pub recorder: Option<RunRecorder>,
+    /// Cancels the model requests and the next steps of the runs. Off when `None`.
+    pub cancellation: Option<CancellationToken>,
+    /// Recalls the memories of earlier runs into the system prompt and remembers the facts of the runs. Off when
+    /// `None`.
+    pub long_term_memory: Option<LongTermMemory>,
+    /// The prompts of the planning steps, in the language of the agent.
+    pub prompts: &'static Prompts,
+    /// The notes shared with the workers when the agent coordinates them. Off when `None`.
+    pub scratchpad: Option<Scratchpad>,
 }
 
-#[async_trait]
+/// Call the model, streaming its response to `tx` if set.
+async fn run_once(
+    model: &dyn Model,
+    input_messages: Vec<Message>,
+    history: Option<Vec<Message>>,
+    tools: Vec<ToolInfo>,
+    max_tokens: Option<usize>,
+    args: Option<HashMap<String, Vec<String>>>,
+    tx: Option<broadcast::Sender<Status>>,
+) -> Result<Box<dyn ModelResponse>, AgentError> {
+    match tx {
+        None => model.run(input_messages, history, tools, max_tokens, args).await,
+        Some(tx) => {
+            model
+                .run_stream(input_messages, history, tools, max_tokens, args, tx)
+                .await
+        }
+    }
+}
+
+/// Call the model, and call the fallback model with the same request if it fails.
+#[allow(clippy::too_many_arguments)]
+async fn run_with_fallback(
+    model: &dyn Model,
+    fallback_model: Option<&dyn Model>,
+    input_messages: Vec<Message>,
+    history: Option<Vec<Message>>,
+    tools: Vec<ToolInfo>,
+    max_tokens: Option<usize>,
+    args: Option<HashMap<String, Vec<String>>>,
+    tx: Option<broadcast::Sender<Status>>,
+) -> Result<Box<dyn ModelResponse>, AgentError> {
+    let Some(fallback_model) = fallback_model else {
+        return run_once(model, input_messages, history, tools, max_tokens, args, tx).await;
+    };
+    let result = run_once(
+        model,
+        input_messages.clone(),
+        history.clone(),
+        tools.clone(),
+        max_tokens,
+        args.clone(),
+        tx.clone(),
+    )
+    .await;
+    match result {
+        Err(e) => {
+            log::warn!("The model failed, calling the fallback model: {}", e);
+            run_once(fallback_model, input_messages, history, tools, max_tokens, args, tx).await
+        }
+        result => result,
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl<M> Agent for MultiStepAgent<M>
 where
     M: Model + Send + Sync + 'static,
@@ -156,6 +279,70 @@ where
     fn model(&self) -> &dyn Model {
         &self.model
     }
+    fn guardrails(&self) -> Option<&Guardrails> {
+        Some(&self.guardrails)
+    }
+    fn context_window(&self) -> Option<usize> {
+        self.context_window
+    }
+    fn answer_validation(&self) -> bool {
+        self.answer_validation
+    }
+    fn response_language(&self) -> Option<&ResponseLanguage> {
+        self.response_language.as_ref()
+    }
+    fn json_answer(&self) -> bool {
+        self.json_answer
+    }
+    fn time_zone(&self) -> Option<&Zone> {
+        self.time_zone.as_ref()
+    }
+    fn recorder(&self) -> Option<&RunRecorder> {
+        self.recorder.as_ref()
+    }
+    fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+    fn set_cancellation(&mut self, cancellation: Option<CancellationToken>) {
+        self.cancellation = cancellation;
+    }
+    fn long_term_memory(&self) -> Option<&LongTermMemory> {
+        self.long_term_memory.as_ref()
+    }
+    fn set_long_term_memory(&mut self, long_term_memory: Option<LongTermMemory>) {
+        self.long_term_memory = long_term_memory;
+    }
+    fn scratchpad(&self) -> Option<&Scratchpad> {
+        self.scratchpad.as_ref()
+    }
+    fn retry_prompts(&self) -> Option<&RetryPrompts> {
+        self.retry_prompts.as_ref()
+    }
+    fn observation_processor(&self) -> Option<&ObservationProcessor> {
+        self.observation_processor.as_ref()
+    }
+    fn set_step_overrides(&mut self, overrides: StepOverrides) {
+        self.step_overrides = overrides;
+    }
+    async fn shutdown(&mut self) -> Result<(), AgentError> {
+        // Shuts down the managed agents too, as they are wrapped in tools.
+        for tool in &self.tools {
+            tool.shutdown().await?;
+        }
+        Ok(())
+    }
+    fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.checkpoint_store.clone()
+    }
+    fn checkpoint_id(&self) -> Option<&str> {
+        self.checkpoint_id.as_deref()
+    }
+    fn set_checkpoint_id(&mut self, checkpoint_id: Option<String>) {
+        self.checkpoint_id = checkpoint_id;
+    }
+    fn set_history(&mut self, history: Option<Vec<Message>>) {
+        self.history = history;
+    }
     async fn planning_step(
         &mut self,
         task: &str,
@@ -194,10 +381,10 @@ where
         history: Option<Vec<Message>>,
         logging_level: Option<log::LevelFilter>,
     ) -> Result<Self> {
-        // Initialize logger
-        let _ = log::set_logger(&LOGGER).map(|()| {
-            log::set_max_level(logging_level.unwrap_or(log::LevelFilter::Error));
-        });
+        // Only install the logger when a level is set, so that applications can use their own `log` logger.
+        if let Some(logging_level) = logging_level {
+            logger::init(logging_level);
+        }
 
         let name: &'static str = match name {
             Some(n) => Box::leak(n.to_string().into_boxed_str()),
@@ -216,6 +403,20 @@ where
         // let final_answer_tool = FinalAnswerTool::new();
         // tools.push(Box::new(final_answer_tool));
 
+        // Managed agents are called like any other tool.
+        let mut tools = tools;
+        let managed_agents = managed_agents
+            .into_iter()
+            .map(|agent| {
+                let info = ManagedAgentInfo {
+                    name: agent.name(),
+                    description: agent.description(),
+                };
+                tools.push(Box::new(AgentTool::new(agent)));
+                info
+            })
+            .collect();
+
         let mut agent = MultiStepAgent {
             model,
             tools,
@@ -231,23 +432,280 @@ where
             planning_interval,
             history,
             logging_level,
+            guardrails: Guardrails::default(),
+            context_window: None,
+            max_parallel_tool_calls: None,
+            answer_validation: false,
+            response_language: None,
+            json_answer: false,
+            retry_prompts: None,
+            circuit_breaker: Some(CircuitBreaker::default()),
+            observation_processor: None,
+            step_overrides: StepOverrides::default(),
+            parse_retries: 0,
+            checkpoint_store: None,
+            checkpoint_id: None,
+            planning_model: None,
+            fallback_model: None,
+            tool_calling: ToolCallingMode::default(),
+            tool_descriptions_in_prompt: false,
+            synthesis: None,
+            time_zone: None,
+            recorder: None,
+            cancellation: None,
+            long_term_memory: None,
+            prompts: &ENGLISH_PROMPTS,
+            scratchpad: None,
         };
 
         agent.initialize_system_prompt()?;
         Ok(agent)
     }
 
+    /// Whether the tools are described in the prompt and the tool calls parsed from the text of the responses.
+    pub fn uses_prompt_tool_calling(&self) -> bool {
+        self.tool_calling.uses_prompt(&self.model)
+    }
+
+    /// Call the model of the action steps, or the fallback model if it fails. The response is streamed to `tx` if
+    /// set; the tokens streamed before a failure are followed by the ones of the fallback model. With prompt-based
+    /// tool calling, the tools are described in the prompt instead of sent. The request is dropped when the run is
+    /// cancelled.
+    pub async fn run_model(
+        &self,
+        input_messages: Vec<Message>,
+        tools: Vec<ToolInfo>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let (input_messages, history, tools) = if self.uses_prompt_tool_calling() {
+            let described = if self.tool_descriptions_in_prompt {
+                &[][..]
+            } else {
+                &tools[..]
+            };
+            (
+                prompt_tool_messages(input_messages, described),
+                self.history
+                    .clone()
+                    .map(|history| prompt_tool_messages(history, &[])),
+                vec![],
+            )
+        } else {
+            (input_messages, self.history.clone(), tools)
+        };
+        let status_tx = tx.clone();
+        let request = run_with_fallback(
+            &self.model,
+            self.fallback_model.as_deref(),
+            input_messages,
+            history,
+            tools,
+            None,
+            args,
+            tx,
+        );
+        let response = cancellable(self.cancellation.as_ref(), status_tx.as_ref(), request).await?;
+        self.record_response(response.as_ref());
+        Ok(response)
+    }
+
+    /// Call the model of the planning steps, or the fallback model if it fails.
+    pub async fn run_planning_model(
+        &self,
+        input_messages: Vec<Message>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let model = self.planning_model.as_deref().unwrap_or(&self.model);
+        let request = run_with_fallback(
+            model,
+            self.fallback_model.as_deref(),
+            input_messages,
+            None,
+            vec![],
+            None,
+            args,
+            None,
+        );
+        let response = cancellable(self.cancellation.as_ref(), None, request).await?;
+        self.record_response(response.as_ref());
+        Ok(response)
+    }
+
+    /// Add the response to the manifest of the run, when the run is recorded.
+    fn record_response(&self, response: &dyn ModelResponse) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_response(response);
+        }
+    }
+
+    /// Why the step that starts should write the final answer from the observations rather than call the model with
+    /// the tools.
+    pub fn synthesis_before_step(&self) -> Option<SynthesisTrigger> {
+        self.synthesis.as_ref()?.trigger_before_step(
+            &self.logs,
+            self.step_number,
+            self.max_steps,
+            self.model.token_counter().as_ref(),
+        )
+    }
+
+    /// Why the response of the model should be replaced by a final answer written from the observations.
+    pub fn synthesis_for_response(
+        &self,
+        tool_calls: &[ToolCall],
+        response: &str,
+    ) -> Option<SynthesisTrigger> {
+        self.synthesis
+            .as_ref()?
+            .trigger_for_response(&self.logs, tool_calls, response)
+    }
+
+    /// Write the final answer of the step from the memory, with a model call without tools.
+    pub async fn synthesize(
+        &self,
+        step_log: &mut AgentStep,
+        mut memory: Vec<Message>,
+        trigger: SynthesisTrigger,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
+        info!("Writing the final answer from the observations ({:?})", trigger);
+        memory.push(Message {
+            role: MessageRole::User,
+            content: SYNTHESIS_PROMPT.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        });
+        let model_start = crate::runtime::Instant::now();
+        let model_message = self.run_model(memory, vec![], None, tx).await?;
+        let answer = model_message.get_response()?;
+        step_log.llm_output = Some(answer.clone());
+        step_log.reasoning = model_message.get_reasoning();
+        step_log.tool_call = None;
+        step_log.record_model_call(model_start.elapsed(), self.model.token_counter().as_ref());
+        step_log.final_answer = Some(answer.clone());
+        step_log.observations = Some(vec![ToolObservation::new(answer.clone())]);
+        step_log.synthesis = Some(trigger);
+        Ok(answer)
+    }
+
+    /// Run the input guardrails on what the user wrote: the history and the task. Called at the first step of a run,
+    /// so the tool observations and the messages the agent adds later are not checked, and each message is checked
+    /// once.
+    pub async fn check_input_guardrails(&self) -> Result<(), AgentError> {
+        if self.guardrails.is_empty() {
+            return Ok(());
+        }
+        let mut messages = self.history.clone().unwrap_or_default();
+        messages.push(Message {
+            role: MessageRole::User,
+            content: self.task.clone(),
+            tool_call_id: None,
+            tool_calls: None,
+        });
+        self.guardrails.check_input(&messages).await
+    }
+
+    /// The overrides of the step that starts, which only apply to it.
+    pub fn take_step_overrides(&mut self) -> StepOverrides {
+        std::mem::take(&mut self.step_overrides)
+    }
+
+    /// Send a response whose text could not be parsed as a tool call back to the model, with a prompt about what is
+    /// wrong with it, up to `parse_retries` times. Returns the last response and its tool calls, which are empty
+    /// when every attempt failed.
+    pub async fn repair_tool_call(
+        &self,
+        mut response: String,
+        mut failure: ParseFailure,
+        tools: Vec<ToolInfo>,
+    ) -> Result<(String, Vec<ToolCall>), AgentError> {
+        let tool_names = tools
+            .iter()
+            .map(|tool| tool.function.name.clone())
+            .collect::<Vec<_>>();
+        let mut messages = self.input_messages.clone().unwrap_or_default();
+        for attempt in 1..=self.parse_retries {
+            info!(
+                "Could not parse a tool call ({}), retry {} of {}",
+                failure, attempt, self.parse_retries
+            );
+            messages.push(Message {
+                role: MessageRole::Assistant,
+                content: response.clone(),
+                tool_call_id: None,
+                tool_calls: None,
+            });
+            messages.push(Message {
+                role: MessageRole::User,
+                content: failure.corrective_prompt(&tool_names),
+                tool_call_id: None,
+                tool_calls: None,
+            });
+            let model_message = self
+                .run_model(
+                    messages.clone(),
+                    tools.clone(),
+                    Some(HashMap::from([(
+                        "stop".to_string(),
+                        vec!["Observation:".to_string()],
+                    )])),
+                    None,
+                )
+                .await?;
+            response = model_message.get_response().unwrap_or_default();
+            let tool_calls = model_message.get_tools_used()?;
+            if !tool_calls.is_empty() {
+                return Ok((response, tool_calls));
+            }
+            match parse_tool_calls(&response) {
+                Ok(tool_calls) => return Ok((response, tool_calls)),
+                Err(e) => failure = e,
+            }
+        }
+        Ok((response, Vec::new()))
+    }
+
+    /// Make every tool available again. Called by the first step of a run.
+    pub fn reset_circuit_breaker(&mut self) {
+        if let Some(breaker) = &mut self.circuit_breaker {
+            breaker.reset();
+        }
+    }
+
+    /// Whether the tool failed too many times in a row to be offered to the model again during this run.
+    pub fn is_tool_unavailable(&self, tool: &str) -> bool {
+        self.circuit_breaker
+            .as_ref()
+            .is_some_and(|breaker| breaker.is_open(tool))
+    }
+
+    /// Record whether a call of the tool failed. When the failure makes the tool unavailable, returns the number
+    /// of consecutive failures and the message telling the model to stop calling it.
+    pub fn record_tool_outcome(&mut self, tool: &str, failed: bool) -> Option<(usize, String)> {
+        let breaker = self.circuit_breaker.as_mut()?;
+        if !failed {
+            breaker.record_success(tool);
+            return None;
+        }
+        breaker.record_failure(tool).then(|| {
+            (
+                breaker.consecutive_failures(tool),
+                breaker.unavailable_message(tool),
+            )
+        })
+    }
+
     fn initialize_system_prompt(&mut self) -> Result<String> {
         let tools = self.tools.tool_info();
+        self.tool_descriptions_in_prompt = self.system_prompt_template.contains("{{tool_descriptions}}");
         self.system_prompt_template = format_prompt_with_tools(tools, &self.system_prompt_template);
         self.system_prompt_template = format_prompt_with_managed_agent_description(
             self.system_prompt_template.clone(),
             &self.managed_agents,
             None,
         )?;
-        self.system_prompt_template = self
-            .system_prompt_template
-            .replace("{{current_time}}", &chrono::Local::now().to_string());
+        // `{{current_time}}` is filled when a run starts, see `Agent::system_prompt_for`.
         Ok(self.system_prompt_template.clone())
     }
 
@@ -260,20 +718,13 @@ where
         if is_first_step {
             let message_prompt_facts = Message {
                 role: MessageRole::User,
-                content: SYSTEM_PROMPT_FACTS.to_string(),
+                content: self.prompts.facts_system_prompt.to_string(),
                 tool_call_id: None,
                 tool_calls: None,
             };
             let message_prompt_task = Message {
                 role: MessageRole::User,
-                content: format!(
-                    "Here is the task: ```
-                    {}
-                    ```
-                    Now Begin!
-                    ",
-                    task
-                ),
+                content: self.prompts.user_prompt_facts(task),
                 tool_call_id: None,
                 tool_calls: None,
             };
@@ -284,14 +735,13 @@ where
                 .chain(vec![message_prompt_facts, message_prompt_task])
                 .collect();
             let answer_facts = self
-                .model
-                .run(input_messages, None, vec![], None, None)
+                .run_planning_model(input_messages, None)
                 .await?
                 .get_response()?;
             log::info!("Facts: {}", answer_facts);
             let message_system_prompt_plan = Message {
                 role: MessageRole::System,
-                content: SYSTEM_PROMPT_PLAN.to_string(),
+                content: self.prompts.plan_system_prompt.to_string(),
                 tool_call_id: None,
                 tool_calls: None,
             };
@@ -305,7 +755,7 @@ where
             .unwrap();
             let message_user_prompt_plan = Message {
                 role: MessageRole::User,
-                content: user_prompt_plan(
+                content: self.prompts.user_prompt_plan(
                     task,
                     &tool_descriptions,
                     &show_agents_description(&self.managed_agents),
@@ -315,12 +765,8 @@ where
                 tool_calls: None,
             };
             let answer_plan = self
-                .model
-                .run(
+                .run_planning_model(
                     vec![message_system_prompt_plan, message_user_prompt_plan],
-                    None,
-                    vec![],
-                    None,
                     Some(HashMap::from([(
                         "stop".to_string(),
                         vec!["Observation:".to_string(), "<end_plan>".to_string()],
@@ -328,12 +774,8 @@ where
                 )
                 .await?
                 .get_response()?;
-            let final_plan_redaction = format!(
-                "Here is the plan of action that I will follow for the task: \n{}",
-                answer_plan
-            );
-            let final_facts_redaction =
-                format!("Here are the facts that I know so far: \n{}", answer_facts);
+            let final_plan_redaction = format!("{}\n{}", self.prompts.plan_heading, answer_plan);
+            let final_facts_redaction = format!("{}\n{}", self.prompts.facts_heading, answer_facts);
             self.logs.push(Step::PlanningStep(
                 final_facts_redaction.clone(),
                 final_plan_redaction.clone(),
@@ -0,0 +1,317 @@
+//! Deduplicate and compress the observations before they enter the agent memory.
+//!
+//! Repeated searches often return overlapping content. A passage that is identical or nearly identical to one of an
+//! earlier observation is replaced with a short notice, and observations that are still too long can be compressed to
+//! the sentences most relevant to the task. Only the memory sent to the model changes, the logs keep the observations.
+
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Passages with at least this Jaccard similarity to an earlier passage are removed, unless set with
+/// `with_similarity_threshold`.
+pub const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.8;
+
+/// Shorter passages are always kept: headings, links and short list items repeat for good reasons.
+const MIN_PASSAGE_CHARS: usize = 80;
+
+/// Number of words in the shingles compared between passages.
+const SHINGLE_WORDS: usize = 3;
+
+/// Words ignored when scoring the sentences of a compressed observation.
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "also", "been", "before", "being", "between", "both", "could", "does",
+    "each", "from", "have", "here", "into", "just", "more", "most", "other", "over", "some",
+    "such", "than", "that", "their", "them", "then", "there", "these", "they", "this", "those",
+    "through", "very", "were", "what", "when", "where", "which", "while", "will", "with", "would",
+    "your",
+];
+
+#[derive(Debug, Clone)]
+pub struct ObservationProcessor {
+    similarity_threshold: f32,
+    max_chars: Option<usize>,
+}
+
+impl ObservationProcessor {
+    /// Deduplicates the observations, without compression.
+    pub fn new() -> Self {
+        Self {
+            similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD,
+            max_chars: None,
+        }
+    }
+
+    /// The similarity, from 0 to 1, above which a passage is a duplicate. `1.0` only removes the passages that are
+    /// identical once whitespace and case are normalized.
+    pub fn with_similarity_threshold(mut self, threshold: f32) -> Self {
+        self.similarity_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Compress the observations longer than `max_chars` characters to their most relevant sentences.
+    pub fn with_compression(mut self, max_chars: usize) -> Self {
+        self.max_chars = Some(max_chars);
+        self
+    }
+
+    /// Start processing the observations of a memory, in the order of the steps.
+    pub fn session(&self, task: &str) -> ObservationSession<'_> {
+        ObservationSession {
+            processor: self,
+            task_words: words(task).collect(),
+            hashes: HashSet::new(),
+            shingles: Vec::new(),
+        }
+    }
+}
+
+impl Default for ObservationProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The passages seen in the observations processed so far.
+pub struct ObservationSession<'a> {
+    processor: &'a ObservationProcessor,
+    task_words: HashSet<String>,
+    hashes: HashSet<u64>,
+    shingles: Vec<HashSet<u64>>,
+}
+
+impl ObservationSession<'_> {
+    /// The observation without the passages of the earlier observations, compressed if it is still too long.
+    pub fn process(&mut self, observation: &str) -> String {
+        let mut kept = Vec::new();
+        let mut removed = 0;
+        let mut seen = Vec::new();
+        for passage in observation.split("\n\n") {
+            if passage.trim().chars().count() < MIN_PASSAGE_CHARS {
+                kept.push(passage.to_string());
+                continue;
+            }
+            let normalized = words(passage).collect::<Vec<_>>();
+            let hash = hash(&normalized);
+            let shingles = shingles(&normalized);
+            if self.is_duplicate(hash, &shingles) {
+                removed += 1;
+                continue;
+            }
+            if removed > 0 {
+                kept.push(removed_notice(removed));
+                removed = 0;
+            }
+            kept.push(passage.to_string());
+            seen.push((hash, shingles));
+        }
+        if removed > 0 {
+            kept.push(removed_notice(removed));
+        }
+        // Passages are compared with the earlier observations only, an observation may repeat itself.
+        for (hash, shingles) in seen {
+            self.hashes.insert(hash);
+            self.shingles.push(shingles);
+        }
+
+        let observation = kept.join("\n\n");
+        match self.processor.max_chars {
+            Some(max_chars) if observation.chars().count() > max_chars => {
+                compress(&observation, max_chars, &self.task_words)
+            }
+            _ => observation,
+        }
+    }
+
+    fn is_duplicate(&self, hash: u64, shingles: &HashSet<u64>) -> bool {
+        if self.hashes.contains(&hash) {
+            return true;
+        }
+        if self.processor.similarity_threshold >= 1.0 || shingles.is_empty() {
+            return false;
+        }
+        self.shingles
+            .iter()
+            .any(|seen| jaccard(seen, shingles) >= self.processor.similarity_threshold)
+    }
+}
+
+fn removed_notice(removed: usize) -> String {
+    if removed == 1 {
+        "[1 passage already seen in an earlier observation was removed]".to_string()
+    } else {
+        format!(
+            "[{} passages already seen in earlier observations were removed]",
+            removed
+        )
+    }
+}
+
+/// The lowercase words of the text, without punctuation.
+pub(crate) fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn hash(value: &(impl Hash + ?Sized)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn shingles(words: &[String]) -> HashSet<u64> {
+    words.windows(SHINGLE_WORDS).map(hash).collect()
+}
+
+fn jaccard(a: &HashSet<u64>, b: &HashSet<u64>) -> f32 {
+    let intersection = a.intersection(b).count();
+    let union = a.len() + b.len() - intersection;
+    if union == 0 {
+        return 0.0;
+    }
+    intersection as f32 / union as f32
+}
+
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let end = match c {
+            '\n' => Some(i),
+            '.' | '!' | '?' if chars.peek().is_none_or(|(_, next)| next.is_whitespace()) => {
+                Some(i + c.len_utf8())
+            }
+            _ => None,
+        };
+        if let Some(end) = end {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+pub(crate) fn is_content_word(word: &str) -> bool {
+    word.chars().count() > 3 && !STOP_WORDS.contains(&word)
+}
+
+/// An extractive summary: the sentences with the most frequent words of the observation and the words of the task,
+/// in their original order, within `max_chars` characters.
+fn compress(observation: &str, max_chars: usize, task_words: &HashSet<String>) -> String {
+    let sentences = sentences(observation);
+    let mut frequencies: HashMap<String, usize> = HashMap::new();
+    for word in words(observation).filter(|word| is_content_word(word)) {
+        *frequencies.entry(word).or_default() += 1;
+    }
+    let max_frequency = frequencies.values().copied().max().unwrap_or(1) as f32;
+
+    let mut scored = sentences
+        .iter()
+        .enumerate()
+        .map(|(i, sentence)| {
+            let words = words(sentence)
+                .filter(|word| is_content_word(word))
+                .collect::<HashSet<_>>();
+            if words.is_empty() {
+                return (i, 0.0);
+            }
+            let frequency = words
+                .iter()
+                .map(|word| {
+                    frequencies.get(word).copied().unwrap_or_default() as f32 / max_frequency
+                })
+                .sum::<f32>()
+                / words.len() as f32;
+            let task = words
+                .iter()
+                .filter(|word| task_words.contains(*word))
+                .count() as f32;
+            (i, frequency + task)
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let best = scored.first().map(|(i, _)| *i);
+    let mut selected = Vec::new();
+    let mut length = 0;
+    for (i, _) in scored {
+        let sentence_length = sentences[i].chars().count() + 1;
+        if length + sentence_length <= max_chars {
+            selected.push(i);
+            length += sentence_length;
+        }
+    }
+    selected.sort_unstable();
+    let summary = if selected.is_empty() {
+        // Not even one sentence fits, keep the beginning of the best one.
+        best.map(|i| sentences[i].chars().take(max_chars).collect())
+            .unwrap_or_default()
+    } else {
+        selected
+            .into_iter()
+            .map(|i| sentences[i])
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    format!(
+        "[Compressed from {} characters to the most relevant sentences]\n{}",
+        observation.chars().count(),
+        summary
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULT: &str = "Paris is the capital and most populous city of France, with an estimated population of 2,102,650 residents in January 2023.";
+
+    #[test]
+    fn test_deduplication() {
+        let processor = ObservationProcessor::new();
+        let mut session = processor.session("What is the population of Paris?");
+        let first = format!("## Paris\n\n{}\n\n{}", RESULT, RESULT);
+        // An observation may repeat itself.
+        assert_eq!(session.process(&first), first);
+
+        // The same passage, reformatted, and a near duplicate are removed from the next observations.
+        let second = format!(
+            "## Paris\n\n{}\n\nThe Eiffel Tower was completed in 1889 for the World's Fair and is now the most visited monument.",
+            RESULT.to_uppercase().replace(' ', "  ")
+        );
+        let second = session.process(&second);
+        assert!(second.starts_with("## Paris\n\n[1 passage already seen"));
+        assert!(second.contains("Eiffel Tower"));
+
+        let near = RESULT.replace("2023", "2024");
+        assert!(session.process(&near).contains("already seen"));
+
+        let exact = ObservationProcessor::new().with_similarity_threshold(1.0);
+        let mut session = exact.session("");
+        session.process(RESULT);
+        assert_eq!(session.process(&near), near);
+    }
+
+    #[test]
+    fn test_compression() {
+        let filler = "The city hosts many museums and galleries that attract visitors from around the world every year.";
+        let observation = format!("{} {} {} {}", filler, filler, RESULT, filler);
+        let processor = ObservationProcessor::new().with_compression(150);
+        let compressed = processor
+            .session("What is the population of Paris?")
+            .process(&observation);
+        assert!(compressed.starts_with("[Compressed from"));
+        assert!(compressed.contains(RESULT));
+        assert!(!compressed.contains("museums"));
+
+        // Short observations are left alone.
+        let short = processor.session("").process(RESULT);
+        assert_eq!(short, RESULT);
+    }
+}
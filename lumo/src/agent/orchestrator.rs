@@ -0,0 +1,847 @@
+//! This module contains the orchestrator: a coordinator agent that splits a task into subtasks, runs them on its
+//! worker agents at the same time, and gathers their results in a scratchpad it shares with the workers.
+//!
+//! Unlike managed agents, which the agent calls one tool call at a time, the coordinator hands out a batch of
+//! subtasks in a single call. The subtasks of different workers run concurrently, the ones given to the same worker
+//! run in turn. Each result is written to the scratchpad, which the workers read before their subtasks and the
+//! coordinator reads to write the final answer.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use crate::{
+    errors::AgentError,
+    models::{model_traits::Model, openai::Status, types::Message},
+    prompts::TOOL_CALLING_SYSTEM_PROMPT,
+    tools::{
+        run_managed_agent, AnyTool, AsyncTool, BaseTool, Tool, ToolFunctionInfo, ToolInfo, ToolType,
+    },
+};
+
+use super::{Agent, FunctionCallingAgent, FunctionCallingAgentBuilder};
+
+/// Subtasks given at most in one call of the dispatch tool.
+const DEFAULT_MAX_SUBTASKS: usize = 8;
+/// Characters of the scratchpad given at most to a worker with its subtask.
+const DEFAULT_MAX_NOTES_LENGTH: usize = 20_000;
+
+/// The instructions added to the system prompt of the coordinator, `{{workers}}` being the list of its workers.
+pub const ORCHESTRATOR_PROMPT: &str = r#"You are the coordinator of a team of worker agents. Split the task into subtasks that can be done independently, and give them to the workers with the `dispatch_subtasks` tool. The subtasks of one call run at the same time, so give all the independent subtasks in a single call, and only call the tool again for the subtasks that need the results of earlier ones. Each subtask must hold all the context its worker needs: the workers do not see the task nor this conversation.
+
+The results of the workers are written to the scratchpad, which the workers read before each subtask. Read it with the `scratchpad` tool, and write notes to it for the next subtasks. Once the scratchpad has everything the task needs, combine the results of the workers into the final answer.
+
+Your workers:
+{{workers}}"#;
+
+/// A note of the scratchpad.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScratchpadEntry {
+    pub key: String,
+    /// The name of the agent that wrote the note.
+    pub author: String,
+    pub content: String,
+}
+
+/// Notes shared by the agents of a team, in the order they were first written. Clones share the same notes.
+#[derive(Debug, Clone, Default)]
+pub struct Scratchpad {
+    entries: Arc<Mutex<Vec<ScratchpadEntry>>>,
+}
+
+impl Scratchpad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the note, replacing the note with the same key.
+    pub fn write(&self, key: &str, author: &str, content: &str) {
+        let entry = ScratchpadEntry {
+            key: key.to_string(),
+            author: author.to_string(),
+            content: content.to_string(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        match entries.iter_mut().find(|entry| entry.key == key) {
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+    }
+
+    pub fn read(&self, key: &str) -> Option<ScratchpadEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|entry| entry.key == key)
+            .cloned()
+    }
+
+    pub fn entries(&self) -> Vec<ScratchpadEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The notes as markdown, one section per note.
+    pub fn render(&self) -> String {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| format!("### {} (by {})\n{}", entry.key, entry.author, entry.content))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScratchpadAction {
+    Read,
+    Write,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "ScratchpadToolParams")]
+pub struct ScratchpadToolParams {
+    #[schemars(description = "Read the notes, or write a note")]
+    action: ScratchpadAction,
+    #[schemars(
+        description = "The key of the note to write, or of the note to read. All the notes are read without a key"
+    )]
+    key: Option<String>,
+    #[schemars(description = "The content of the note to write")]
+    content: Option<String>,
+}
+
+/// Reads and writes the notes of a scratchpad. The coordinator of an orchestrator has one, and workers built with
+/// one on the same scratchpad can share their findings while they run.
+#[derive(Debug, Clone)]
+pub struct ScratchpadTool {
+    pub tool: BaseTool,
+    pub scratchpad: Scratchpad,
+    /// The author of the notes written with the tool.
+    pub author: String,
+}
+
+impl ScratchpadTool {
+    pub fn new(scratchpad: Scratchpad, author: &str) -> Self {
+        ScratchpadTool {
+            tool: BaseTool {
+                name: "scratchpad",
+                description: "Reads the notes shared by the team, with the results of the subtasks, or writes a note for the others to read",
+            },
+            scratchpad,
+            author: author.to_string(),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for ScratchpadTool {
+    type Params = ScratchpadToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: ScratchpadToolParams) -> Result<String> {
+        match (arguments.action, arguments.key) {
+            (ScratchpadAction::Read, None) if self.scratchpad.is_empty() => {
+                Ok("The scratchpad is empty.".to_string())
+            }
+            (ScratchpadAction::Read, None) => Ok(self.scratchpad.render()),
+            (ScratchpadAction::Read, Some(key)) => match self.scratchpad.read(&key) {
+                Some(entry) => Ok(entry.content),
+                None => Err(anyhow!(
+                    "No note named {}. The notes are: {}",
+                    key,
+                    self.scratchpad
+                        .entries()
+                        .iter()
+                        .map(|entry| entry.key.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+            (ScratchpadAction::Write, Some(key)) => {
+                let content = arguments
+                    .content
+                    .ok_or_else(|| anyhow!("Give the content of the note to write"))?;
+                self.scratchpad.write(&key, &self.author, &content);
+                Ok(format!("Wrote the note {}.", key))
+            }
+            (ScratchpadAction::Write, None) => Err(anyhow!("Give the key of the note to write")),
+        }
+    }
+}
+
+/// A subtask given by the coordinator.
+#[derive(Debug, Deserialize)]
+struct Subtask {
+    worker: String,
+    task: String,
+    key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DispatchArguments {
+    subtasks: Vec<Subtask>,
+}
+
+#[derive(Clone)]
+struct Worker {
+    name: &'static str,
+    description: &'static str,
+    agent: Arc<futures::lock::Mutex<Box<dyn Agent>>>,
+}
+
+/// Runs the subtasks of the coordinator on its workers, the workers at the same time and the subtasks of a worker in
+/// turn, and writes their results to the scratchpad. A result without a key is written under the name of its worker
+/// and the numbers of the call and of the subtask, e.g. `researcher 2.1`.
+#[derive(Clone)]
+pub struct DispatchTool {
+    workers: Vec<Worker>,
+    scratchpad: Scratchpad,
+    max_subtasks: usize,
+    max_notes_length: usize,
+    /// Calls of the tool so far, numbering the default keys of the results.
+    calls: Arc<AtomicUsize>,
+}
+
+impl DispatchTool {
+    pub fn new(workers: Vec<Box<dyn Agent>>, scratchpad: Scratchpad) -> Self {
+        Self {
+            workers: workers
+                .into_iter()
+                .map(|agent| Worker {
+                    name: agent.name(),
+                    description: agent.description(),
+                    agent: Arc::new(futures::lock::Mutex::new(agent)),
+                })
+                .collect(),
+            scratchpad,
+            max_subtasks: DEFAULT_MAX_SUBTASKS,
+            max_notes_length: DEFAULT_MAX_NOTES_LENGTH,
+            calls: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn with_max_subtasks(mut self, max_subtasks: usize) -> Self {
+        self.max_subtasks = max_subtasks.max(1);
+        self
+    }
+
+    /// Limit the characters of the scratchpad given to a worker with its subtask. The latest notes are kept.
+    pub fn with_max_notes_length(mut self, max_notes_length: usize) -> Self {
+        self.max_notes_length = max_notes_length;
+        self
+    }
+
+    /// The names and descriptions of the workers, one per line.
+    pub fn describe_workers(&self) -> String {
+        self.workers
+            .iter()
+            .map(|worker| format!("- {}: {}", worker.name, worker.description))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The task given to a worker: the subtask, followed by the notes of the scratchpad. Only the end of long notes
+    /// is given, as the subtasks mostly build on the latest results.
+    fn worker_task(&self, task: &str) -> String {
+        if self.scratchpad.is_empty() {
+            return task.to_string();
+        }
+        let notes = self.scratchpad.render();
+        let length = notes.chars().count();
+        let notes = if length > self.max_notes_length {
+            format!(
+                "[{} characters of earlier notes cut]\n{}",
+                length - self.max_notes_length,
+                notes
+                    .chars()
+                    .skip(length - self.max_notes_length)
+                    .collect::<String>()
+            )
+        } else {
+            notes
+        };
+        format!("{}\n\nNotes of the team so far:\n{}", task, notes)
+    }
+
+    fn parse(&self, json_args: Value) -> Result<Vec<Subtask>, AgentError> {
+        let arguments = serde_json::from_value::<DispatchArguments>(json_args.clone())
+            .map_err(|e| AgentError::Parsing(format!("Invalid subtasks {}: {}", json_args, e)))?;
+        if arguments.subtasks.is_empty() {
+            return Err(AgentError::Parsing("Give at least one subtask".to_string()));
+        }
+        if arguments.subtasks.len() > self.max_subtasks {
+            return Err(AgentError::Parsing(format!(
+                "Give at most {} subtasks at a time, not {}",
+                self.max_subtasks,
+                arguments.subtasks.len()
+            )));
+        }
+        if let Some(subtask) = arguments.subtasks.iter().find(|subtask| {
+            !self
+                .workers
+                .iter()
+                .any(|worker| worker.name == subtask.worker)
+        }) {
+            return Err(AgentError::Parsing(format!(
+                "There is no worker named {}. The workers are: {}",
+                subtask.worker,
+                self.workers
+                    .iter()
+                    .map(|worker| worker.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+        Ok(arguments.subtasks)
+    }
+
+    /// Run the subtasks and return the result of each of them, in the order of the subtasks.
+    async fn dispatch(
+        &self,
+        subtasks: Vec<Subtask>,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Vec<(String, String, Result<String, AgentError>)> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut queues: HashMap<&str, Vec<(usize, Subtask)>> = HashMap::new();
+        for (i, subtask) in subtasks.into_iter().enumerate() {
+            let worker = self
+                .workers
+                .iter()
+                .find(|worker| worker.name == subtask.worker)
+                .map(|worker| worker.name)
+                .unwrap_or_default();
+            queues.entry(worker).or_default().push((i, subtask));
+        }
+        let runs = queues.into_iter().map(|(name, queue)| {
+            let tx = tx.clone();
+            async move {
+                let worker = self.workers.iter().find(|worker| worker.name == name);
+                let mut agent = worker.expect("the workers were checked").agent.lock().await;
+                let mut results = Vec::with_capacity(queue.len());
+                for (i, subtask) in queue {
+                    let key = subtask
+                        .key
+                        .unwrap_or_else(|| format!("{} {}.{}", name, call, i + 1));
+                    let task = self.worker_task(&subtask.task);
+                    let result = run_managed_agent(agent.as_mut(), name, &task, tx.clone()).await;
+                    if let Ok(result) = &result {
+                        self.scratchpad.write(&key, name, result);
+                    }
+                    results.push((i, (key, name.to_string(), result)));
+                }
+                results
+            }
+        });
+        let mut results = join_all(runs)
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+impl AnyTool for DispatchTool {
+    fn name(&self) -> &'static str {
+        "dispatch_subtasks"
+    }
+
+    fn description(&self) -> &'static str {
+        "Gives subtasks to the workers of the team. The subtasks of different workers run at the same time, and their results are written to the scratchpad under their key"
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        let workers = self
+            .workers
+            .iter()
+            .map(|worker| worker.name)
+            .collect::<Vec<_>>();
+        ToolInfo {
+            tool_type: ToolType::Function,
+            function: ToolFunctionInfo {
+                name: self.name().to_string(),
+                description: self.description().to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "subtasks": {
+                            "type": "array",
+                            "description": format!("The subtasks, at most {}", self.max_subtasks),
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "worker": {
+                                        "type": "string",
+                                        "enum": workers,
+                                        "description": "The name of the worker doing the subtask"
+                                    },
+                                    "task": {
+                                        "type": "string",
+                                        "description": "The subtask, with all the context the worker needs"
+                                    },
+                                    "key": {
+                                        "type": "string",
+                                        "description": "Optionally the key of the scratchpad note the result is written to"
+                                    }
+                                },
+                                "required": ["worker", "task"]
+                            }
+                        }
+                    },
+                    "required": ["subtasks"]
+                }),
+                strict: None,
+            },
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncTool for DispatchTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        self.forward_json_with_status(json_args, None).await
+    }
+
+    async fn forward_json_with_status(
+        &self,
+        json_args: Value,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
+        let subtasks = self.parse(json_args)?;
+        let count = subtasks.len();
+        let results = self.dispatch(subtasks, tx).await;
+        let failed = results
+            .iter()
+            .filter(|(_, _, result)| result.is_err())
+            .count();
+        let mut report = format!(
+            "Ran {} subtasks, {} failed. The results were written to the scratchpad.",
+            count, failed
+        );
+        for (key, worker, result) in results {
+            let result = result.unwrap_or_else(|e| format!("Failed: {}", e));
+            report.push_str(&format!("\n\n### {} (by {})\n{}", key, worker, result));
+        }
+        Ok(report)
+    }
+
+    async fn shutdown(&self) -> Result<(), AgentError> {
+        for worker in &self.workers {
+            worker.agent.lock().await.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(self.clone())
+    }
+}
+
+/// Builds a coordinator agent with its workers. The coordinator is a function calling agent with a dispatch tool for
+/// the workers and a scratchpad tool, and keeps the scratchpad, read with `Agent::scratchpad`. The scratchpad is
+/// cleared at the start of each run with `reset`.
+pub struct OrchestratorAgentBuilder<'a, M>
+where
+    M: Model + std::fmt::Debug + Send + Sync + 'static,
+{
+    name: Option<&'a str>,
+    model: M,
+    workers: Vec<Box<dyn Agent>>,
+    tools: Vec<Box<dyn AsyncTool>>,
+    system_prompt: Option<&'a str>,
+    description: Option<&'a str>,
+    max_steps: Option<usize>,
+    planning_interval: Option<usize>,
+    history: Option<Vec<Message>>,
+    logging_level: Option<log::LevelFilter>,
+    scratchpad: Scratchpad,
+    max_subtasks: usize,
+    max_notes_length: usize,
+}
+
+impl<'a, M: Model + std::fmt::Debug + Send + Sync + 'static> OrchestratorAgentBuilder<'a, M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            name: None,
+            model,
+            workers: vec![],
+            tools: vec![],
+            system_prompt: None,
+            description: None,
+            max_steps: None,
+            planning_interval: None,
+            history: None,
+            logging_level: None,
+            scratchpad: Scratchpad::new(),
+            max_subtasks: DEFAULT_MAX_SUBTASKS,
+            max_notes_length: DEFAULT_MAX_NOTES_LENGTH,
+        }
+    }
+    pub fn with_name(mut self, name: Option<&'a str>) -> Self {
+        self.name = name;
+        self
+    }
+    /// The agents the subtasks are given to, by their name.
+    pub fn with_workers(mut self, workers: Vec<Box<dyn Agent>>) -> Self {
+        self.workers = workers;
+        self
+    }
+    /// Tools of the coordinator, besides the dispatch and scratchpad tools.
+    pub fn with_tools(mut self, tools: Vec<Box<dyn AsyncTool>>) -> Self {
+        self.tools = tools;
+        self
+    }
+    /// Replace the system prompt, which is the tool calling prompt followed by [`ORCHESTRATOR_PROMPT`].
+    pub fn with_system_prompt(mut self, system_prompt: Option<&'a str>) -> Self {
+        self.system_prompt = system_prompt;
+        self
+    }
+    pub fn with_description(mut self, description: Option<&'a str>) -> Self {
+        self.description = description;
+        self
+    }
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+    pub fn with_planning_interval(mut self, planning_interval: Option<usize>) -> Self {
+        self.planning_interval = planning_interval;
+        self
+    }
+    pub fn with_history(mut self, history: Option<Vec<Message>>) -> Self {
+        self.history = history;
+        self
+    }
+    pub fn with_logging_level(mut self, logging_level: Option<log::LevelFilter>) -> Self {
+        self.logging_level = logging_level;
+        self
+    }
+    /// Share this scratchpad, e.g. one the workers were built with a [`ScratchpadTool`] on, so they can read and
+    /// write notes while they run.
+    pub fn with_scratchpad(mut self, scratchpad: Scratchpad) -> Self {
+        self.scratchpad = scratchpad;
+        self
+    }
+    /// Limit the number of subtasks given in one call of the dispatch tool. 8 by default.
+    pub fn with_max_subtasks(mut self, max_subtasks: usize) -> Self {
+        self.max_subtasks = max_subtasks.max(1);
+        self
+    }
+    /// Limit the characters of the scratchpad given to a worker with each subtask, the latest notes being kept.
+    /// 20000 by default.
+    pub fn with_max_notes_length(mut self, max_notes_length: usize) -> Self {
+        self.max_notes_length = max_notes_length;
+        self
+    }
+    pub fn build(self) -> Result<FunctionCallingAgent<M>> {
+        if self.workers.is_empty() {
+            return Err(anyhow!("An orchestrator needs at least one worker"));
+        }
+        // The subtasks are given to the workers by their name.
+        let mut names = HashSet::new();
+        if let Some(worker) = self
+            .workers
+            .iter()
+            .find(|worker| !names.insert(worker.name()))
+        {
+            return Err(anyhow!(
+                "Two workers are named {}, the names of the workers must be unique",
+                worker.name()
+            ));
+        }
+        let dispatch = DispatchTool::new(self.workers, self.scratchpad.clone())
+            .with_max_subtasks(self.max_subtasks)
+            .with_max_notes_length(self.max_notes_length);
+        let system_prompt = match self.system_prompt {
+            Some(system_prompt) => system_prompt.to_string(),
+            None => format!(
+                "{}\n\n{}",
+                TOOL_CALLING_SYSTEM_PROMPT,
+                ORCHESTRATOR_PROMPT.replace("{{workers}}", &dispatch.describe_workers())
+            ),
+        };
+        let author = self.name.unwrap_or("coordinator");
+        let mut tools: Vec<Box<dyn AsyncTool>> = vec![
+            Box::new(dispatch),
+            Box::new(ScratchpadTool::new(self.scratchpad.clone(), author)),
+        ];
+        tools.extend(self.tools);
+        FunctionCallingAgentBuilder::new(self.model)
+            .with_name(self.name)
+            .with_tools(tools)
+            .with_system_prompt(Some(&system_prompt))
+            .with_description(self.description)
+            .with_max_steps(self.max_steps)
+            .with_planning_interval(self.planning_interval)
+            .with_history(self.history)
+            .with_logging_level(self.logging_level)
+            .with_scratchpad(self.scratchpad)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+
+    #[derive(Deserialize, JsonSchema)]
+    #[schemars(title = "WaitToolParams")]
+    struct WaitToolParams {
+        millis: u64,
+    }
+
+    /// Waits and records how many calls run at the same time.
+    #[derive(Clone, Default)]
+    struct WaitTool {
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for WaitTool {
+        type Params = WaitToolParams;
+
+        fn name(&self) -> &'static str {
+            "wait"
+        }
+
+        fn description(&self) -> &'static str {
+            "Wait for some milliseconds."
+        }
+
+        async fn forward(&self, arguments: WaitToolParams) -> Result<String> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(arguments.millis)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok("done".to_string())
+        }
+    }
+
+    fn worker(name: &str, tool: &WaitTool, answers: &[&str]) -> Box<dyn Agent> {
+        let mut responses = Vec::new();
+        for answer in answers {
+            responses.push(MockResponse::tool_call("wait", json!({ "millis": 50 })));
+            responses.push(MockResponse::text(answer));
+        }
+        Box::new(
+            FunctionCallingAgentBuilder::new(MockModel::new(responses))
+                .with_name(Some(name))
+                .with_description(Some("A worker"))
+                .with_tools(vec![Box::new(tool.clone())])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_scratchpad() {
+        let scratchpad = Scratchpad::new();
+        let shared = scratchpad.clone();
+        scratchpad.write("weather", "researcher", "Sunny");
+        shared.write("capital", "researcher", "Paris");
+        shared.write("weather", "checker", "Rainy");
+        assert_eq!(scratchpad.read("weather").unwrap().author, "checker");
+        assert_eq!(
+            scratchpad.render(),
+            "### weather (by checker)\nRainy\n\n### capital (by researcher)\nParis"
+        );
+        scratchpad.clear();
+        assert!(shared.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scratchpad_tool() {
+        let tool = ScratchpadTool::new(Scratchpad::new(), "coordinator");
+        let params = |action, key: Option<&str>, content: Option<&str>| ScratchpadToolParams {
+            action,
+            key: key.map(str::to_string),
+            content: content.map(str::to_string),
+        };
+        assert_eq!(
+            Tool::forward(&tool, params(ScratchpadAction::Read, None, None))
+                .await
+                .unwrap(),
+            "The scratchpad is empty."
+        );
+        Tool::forward(
+            &tool,
+            params(ScratchpadAction::Write, Some("plan"), Some("Ask")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            Tool::forward(&tool, params(ScratchpadAction::Read, Some("plan"), None))
+                .await
+                .unwrap(),
+            "Ask"
+        );
+        assert!(
+            Tool::forward(&tool, params(ScratchpadAction::Read, Some("x"), None))
+                .await
+                .unwrap_err()
+                .to_string()
+                .contains("The notes are: plan")
+        );
+        assert!(
+            Tool::forward(&tool, params(ScratchpadAction::Write, None, Some("Ask")))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_arguments() {
+        let tool = DispatchTool::new(
+            vec![worker("researcher", &WaitTool::default(), &[])],
+            Scratchpad::new(),
+        )
+        .with_max_subtasks(1);
+        let error = |args| tool.parse(args).unwrap_err().to_string();
+        assert!(error(json!({ "subtasks": [] })).contains("at least one"));
+        assert!(
+            error(json!({ "subtasks": [{ "worker": "writer", "task": "Write" }] }))
+                .contains("no worker named writer. The workers are: researcher")
+        );
+        assert!(error(json!({ "subtasks": [
+            { "worker": "researcher", "task": "Find" },
+            { "worker": "researcher", "task": "Find more" }
+        ] }))
+        .contains("at most 1"));
+        assert!(error(json!({ "task": "Find" })).contains("Invalid subtasks"));
+    }
+
+    #[test]
+    fn test_worker_task() {
+        let scratchpad = Scratchpad::new();
+        let tool = DispatchTool::new(
+            vec![worker("researcher", &WaitTool::default(), &[])],
+            scratchpad.clone(),
+        )
+        .with_max_notes_length(40);
+        assert_eq!(tool.worker_task("Find"), "Find");
+        scratchpad.write("capital", "researcher", "Paris");
+        assert_eq!(
+            tool.worker_task("Find"),
+            "Find\n\nNotes of the team so far:\n### capital (by researcher)\nParis"
+        );
+        scratchpad.write("population", "researcher", "2 million");
+        assert_eq!(
+            tool.worker_task("Find"),
+            "Find\n\nNotes of the team so far:\n[35 characters of earlier notes cut]\n### population (by researcher)\n2 million"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_workers() {
+        let tool = WaitTool::default();
+        let error = OrchestratorAgentBuilder::new(MockModel::new(vec![]))
+            .with_workers(vec![
+                worker("researcher", &tool, &[]),
+                worker("researcher", &tool, &[]),
+            ])
+            .build()
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("Two workers are named researcher"));
+    }
+
+    #[tokio::test]
+    async fn test_orchestrator() {
+        let tool = WaitTool::default();
+        let model = MockModel::new(vec![
+            MockResponse::tool_call(
+                "dispatch_subtasks",
+                json!({ "subtasks": [
+                    { "worker": "researcher", "task": "Find the capital of France", "key": "capital" },
+                    { "worker": "historian", "task": "Find when Paris was founded" },
+                    { "worker": "researcher", "task": "Find the population of Paris" }
+                ] }),
+            ),
+            MockResponse::text("Paris, founded in the 3rd century BC, has 2 million inhabitants"),
+        ]);
+        let mut agent = OrchestratorAgentBuilder::new(model)
+            .with_workers(vec![
+                worker("researcher", &tool, &["Paris", "2 million"]),
+                worker("historian", &tool, &["The 3rd century BC"]),
+            ])
+            .with_max_steps(Some(3))
+            .build()
+            .unwrap();
+        assert!(agent
+            .get_system_prompt()
+            .contains("Your workers:\n- researcher: A worker\n- historian: A worker"));
+        agent.run("Tell me about Paris", true).await.unwrap();
+
+        // The researcher and the historian worked at the same time, the subtasks of the researcher in turn.
+        assert_eq!(tool.max_running.load(Ordering::SeqCst), 2);
+        let scratchpad = agent.scratchpad().unwrap();
+        assert_eq!(scratchpad.read("capital").unwrap().content, "Paris");
+        assert_eq!(
+            scratchpad.read("historian 1.2").unwrap().content,
+            "The 3rd century BC"
+        );
+        assert_eq!(
+            scratchpad.read("researcher 1.3").unwrap().author,
+            "researcher"
+        );
+        let report = agent
+            .get_logs_mut()
+            .iter()
+            .find_map(|step| match step {
+                crate::agent::Step::ActionStep(step) if step.tool_call.is_some() => {
+                    Some(step.observation_contents().join("\n"))
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert!(report.starts_with("Ran 3 subtasks, 0 failed."));
+        assert!(report.contains("### capital (by researcher)\nParis\n\n### historian 1.2"));
+
+        // A new run starts with an empty scratchpad.
+        let tool = WaitTool::default();
+        let mut agent =
+            OrchestratorAgentBuilder::new(MockModel::new(vec![MockResponse::text("Hello")]))
+                .with_workers(vec![worker("researcher", &tool, &[])])
+                .with_scratchpad(Scratchpad::new())
+                .build()
+                .unwrap();
+        agent
+            .scratchpad()
+            .unwrap()
+            .write("old", "researcher", "Stale");
+        agent.run("Say hello", true).await.unwrap();
+        assert!(agent.scratchpad().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,457 @@
+//! Reproducible runs, to debug a change of the prompts or of the parsing against a recorded run.
+//!
+//! An agent with a [`RunRecorder`] records a manifest of each run: the task, the model id, temperature and seed of
+//! its model, the responses of the model and the outputs of the tool calls, and the answer. An agent replaying a
+//! manifest answers the tool calls with the recorded outputs instead of calling the tools, so the run does not
+//! depend on the web or on the time. With the recorded model responses too, from [`RunManifest::mock_model`], the
+//! run is executed again without any network call.
+//!
+//! ```yaml
+//! version: 1
+//! task: What is the capital of France?
+//! model: { model_id: gpt-4o-mini, temperature: 0.0, seed: 42 }
+//! model_responses:
+//!   - tool_calls:
+//!       - function: { name: duckduckgo_search, arguments: { query: capital of France } }
+//!   - content: The capital of France is Paris.
+//! tool_calls:
+//!   - name: duckduckgo_search
+//!     arguments: { query: capital of France }
+//!     output: Paris is the capital and largest city of France.
+//! answer: The capital of France is Paris.
+//! ```
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{
+    errors::AgentError,
+    models::{
+        mock::{MockModel, MockResponse},
+        model_traits::{ModelResponse, ModelSampling},
+        openai::Status,
+    },
+    tools::{AnyTool, AsyncTool, ToolInfo},
+};
+
+/// The version of the manifests written by this version of lumo.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A call of a tool and what it returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedToolCall {
+    pub name: String,
+    pub arguments: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The error of the call, returned again when the call is replayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a run was executed with and what it produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub version: u32,
+    pub task: String,
+    pub created_at: DateTime<Utc>,
+    /// The sampling settings of the model of the agent.
+    #[serde(default)]
+    pub model: ModelSampling,
+    /// The responses of the model, in the order of the calls.
+    #[serde(default)]
+    pub model_responses: Vec<MockResponse>,
+    /// The tool calls, in the order they were made.
+    #[serde(default)]
+    pub tool_calls: Vec<RecordedToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+}
+
+impl RunManifest {
+    /// Read a manifest, as YAML or JSON depending on the extension of `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {:?}", path))?;
+        if is_json(path) {
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest: {:?}", path))
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse manifest: {:?}", path))
+        }
+    }
+
+    /// Write the manifest, as YAML or JSON depending on the extension of `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            serde_yaml::to_string(self)?
+        };
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write manifest: {:?}", path))
+    }
+
+    /// A model answering with the recorded responses, to execute the run again without calling the model.
+    pub fn mock_model(&self) -> MockModel {
+        MockModel::new(self.model_responses.clone())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+/// Whether the runs of an agent are recorded, or replay the tool outputs of a manifest.
+#[derive(Debug, Clone)]
+pub enum Reproducibility {
+    /// Record a manifest of each run.
+    Record,
+    /// Answer the tool calls with the outputs of the manifest, and record the run as well.
+    Replay(RunManifest),
+}
+
+/// The recording of the run in progress.
+#[derive(Debug, Default)]
+struct Recording {
+    task: String,
+    created_at: Option<DateTime<Utc>>,
+    model: ModelSampling,
+    model_responses: Vec<MockResponse>,
+    tool_calls: Vec<RecordedToolCall>,
+    answer: Option<String>,
+    /// Which tool calls of the replayed manifest were already used in the run.
+    replayed: Vec<bool>,
+}
+
+/// Records the model responses and the tool calls of the runs of an agent. The tools of the agent share it, and
+/// answer from it when a manifest is replayed.
+#[derive(Debug, Clone)]
+pub struct RunRecorder {
+    replay: Option<Arc<Vec<RecordedToolCall>>>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl RunRecorder {
+    pub fn new(reproducibility: Reproducibility) -> Self {
+        Self {
+            replay: match reproducibility {
+                Reproducibility::Record => None,
+                Reproducibility::Replay(manifest) => Some(Arc::new(manifest.tool_calls)),
+            },
+            recording: Arc::new(Mutex::new(Recording::default())),
+        }
+    }
+
+    /// Whether the tool calls are answered from a manifest.
+    pub fn is_replay(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Start the recording of a run, dropping the one of the previous run.
+    pub fn start(&self, task: &str, model: ModelSampling) {
+        *self.recording.lock().unwrap() = Recording {
+            task: task.to_string(),
+            created_at: Some(Utc::now()),
+            model,
+            ..Default::default()
+        };
+    }
+
+    /// Record a response of the model. Responses that can not be read are left out.
+    pub fn record_response(&self, response: &dyn ModelResponse) {
+        let (Ok(content), Ok(tool_calls)) = (response.get_response(), response.get_tools_used())
+        else {
+            return;
+        };
+        self.recording
+            .lock()
+            .unwrap()
+            .model_responses
+            .push(MockResponse {
+                content,
+                tool_calls,
+            });
+    }
+
+    /// Record the final answer of the run.
+    pub fn record_answer(&self, answer: &str) {
+        self.recording.lock().unwrap().answer = Some(answer.to_string());
+    }
+
+    /// When a manifest is replayed, the output of the first call of the tool with the same arguments that the run did
+    /// not replay yet, or an error when the manifest has none. `None` when the run is only recorded.
+    pub(crate) fn replayed_output(
+        &self,
+        name: &str,
+        arguments: &Value,
+    ) -> Option<Result<String, AgentError>> {
+        let calls = self.replay.as_ref()?;
+        let mut recording = self.recording.lock().unwrap();
+        recording.replayed.resize(calls.len(), false);
+        let found = calls.iter().enumerate().position(|(i, call)| {
+            !recording.replayed[i] && call.name == name && call.arguments == *arguments
+        });
+        let Some(i) = found else {
+            return Some(Err(AgentError::Execution(format!(
+                "The replayed run has no recorded output for {} with the arguments {}",
+                name, arguments
+            ))));
+        };
+        recording.replayed[i] = true;
+        Some(match &calls[i].error {
+            Some(error) => Err(AgentError::Execution(error.clone())),
+            None => Ok(calls[i].output.clone().unwrap_or_default()),
+        })
+    }
+
+    pub(crate) fn record_tool_call(
+        &self,
+        name: &str,
+        arguments: Value,
+        result: &Result<String, AgentError>,
+    ) {
+        self.recording
+            .lock()
+            .unwrap()
+            .tool_calls
+            .push(RecordedToolCall {
+                name: name.to_string(),
+                arguments,
+                output: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| e.message().to_string()),
+            });
+    }
+
+    /// The manifest of the run recorded so far.
+    pub fn manifest(&self) -> RunManifest {
+        let recording = self.recording.lock().unwrap();
+        RunManifest {
+            version: MANIFEST_VERSION,
+            task: recording.task.clone(),
+            created_at: recording.created_at.unwrap_or_else(Utc::now),
+            model: recording.model.clone(),
+            model_responses: recording.model_responses.clone(),
+            tool_calls: recording.tool_calls.clone(),
+            answer: recording.answer.clone(),
+        }
+    }
+
+    /// The tools recording their calls with this recorder, or answering them from the replayed manifest.
+    /// `final_answer` is left as is, as it only returns its argument.
+    pub fn wrap_tools(&self, tools: Vec<Box<dyn AsyncTool>>) -> Vec<Box<dyn AsyncTool>> {
+        tools
+            .into_iter()
+            .map(|tool| -> Box<dyn AsyncTool> {
+                if tool.name() == "final_answer" {
+                    tool
+                } else {
+                    Box::new(RecordedTool {
+                        tool,
+                        recorder: self.clone(),
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// A tool whose calls are recorded, or answered from a replayed manifest.
+struct RecordedTool {
+    tool: Box<dyn AsyncTool>,
+    recorder: RunRecorder,
+}
+
+impl AnyTool for RecordedTool {
+    fn name(&self) -> &'static str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description()
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        self.tool.tool_info()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncTool for RecordedTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        self.forward_json_with_status(json_args, None).await
+    }
+
+    async fn forward_json_with_status(
+        &self,
+        json_args: Value,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
+        let result = match self.recorder.replayed_output(self.name(), &json_args) {
+            Some(result) => result,
+            None => {
+                self.tool
+                    .forward_json_with_status(json_args.clone(), tx)
+                    .await
+            }
+        };
+        self.recorder
+            .record_tool_call(self.name(), json_args, &result);
+        result
+    }
+
+    async fn shutdown(&self) -> Result<(), AgentError> {
+        self.tool.shutdown().await
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(Self {
+            tool: self.tool.clone_box(),
+            recorder: self.recorder.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, FunctionCallingAgentBuilder};
+    use crate::tools::{FinalAnswerTool, Tool};
+    use schemars::JsonSchema;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Deserialize, JsonSchema)]
+    #[schemars(title = "SearchParams")]
+    struct SearchParams {
+        query: String,
+    }
+
+    /// A search whose results change with each call, like the web.
+    #[derive(Clone, Default)]
+    struct SearchTool {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for SearchTool {
+        type Params = SearchParams;
+
+        fn name(&self) -> &'static str {
+            "search"
+        }
+
+        fn description(&self) -> &'static str {
+            "Search the web."
+        }
+
+        async fn forward(&self, params: SearchParams) -> Result<String> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(format!("Result {} for {}", call, params.query))
+        }
+    }
+
+    fn responses() -> Vec<MockResponse> {
+        vec![
+            MockResponse::tool_call("search", json!({"query": "capital of France"})),
+            MockResponse::tool_call("final_answer", json!({"answer": "Paris"})),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let tool = SearchTool::default();
+        let mut agent = FunctionCallingAgentBuilder::new(MockModel::new(responses()))
+            .with_tools(vec![
+                Box::new(tool.clone()),
+                Box::new(FinalAnswerTool::new()),
+            ])
+            .with_reproducibility(Some(Reproducibility::Record))
+            .build()
+            .unwrap();
+        agent
+            .run("What is the capital of France?", true)
+            .await
+            .unwrap();
+        let manifest = agent.run_manifest().unwrap();
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        assert_eq!(manifest.task, "What is the capital of France?");
+        assert_eq!(manifest.model_responses.len(), 2);
+        assert_eq!(
+            manifest.tool_calls,
+            [RecordedToolCall {
+                name: "search".to_string(),
+                arguments: json!({"query": "capital of France"}),
+                output: Some("Result 1 for capital of France".to_string()),
+                error: None,
+            }]
+        );
+        assert_eq!(manifest.answer.as_deref(), Some("Paris"));
+
+        let path = std::env::temp_dir().join(format!("lumo-manifest-{}.yaml", nanoid::nanoid!()));
+        manifest.save(&path).unwrap();
+        let manifest = RunManifest::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The replayed run gets the recorded output, without calling the tool.
+        let model = manifest.mock_model();
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_tools(vec![
+                Box::new(tool.clone()),
+                Box::new(FinalAnswerTool::new()),
+            ])
+            .with_reproducibility(Some(Reproducibility::Replay(manifest.clone())))
+            .build()
+            .unwrap();
+        agent
+            .run("What is the capital of France?", true)
+            .await
+            .unwrap();
+        assert_eq!(tool.calls.load(Ordering::SeqCst), 1);
+        let replayed = agent.run_manifest().unwrap();
+        assert_eq!(replayed.tool_calls, manifest.tool_calls);
+        assert_eq!(replayed.model_responses.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_replay_without_recorded_output() {
+        let recorder = RunRecorder::new(Reproducibility::Replay(RunManifest {
+            version: MANIFEST_VERSION,
+            task: "What is the capital of France?".to_string(),
+            created_at: Utc::now(),
+            model: ModelSampling::default(),
+            model_responses: vec![],
+            tool_calls: vec![RecordedToolCall {
+                name: "search".to_string(),
+                arguments: json!({"query": "capital of France"}),
+                output: None,
+                error: Some("Request timed out".to_string()),
+            }],
+            answer: None,
+        }));
+        let tools = recorder.wrap_tools(vec![Box::new(SearchTool::default())]);
+        let error = tools[0]
+            .forward_json(json!({"query": "capital of France"}))
+            .await
+            .unwrap_err();
+        assert_eq!(error.message(), "Request timed out");
+        // A recorded call is only replayed once.
+        let error = tools[0]
+            .forward_json(json!({"query": "capital of France"}))
+            .await
+            .unwrap_err();
+        assert!(error.message().contains("no recorded output for search"));
+    }
+}
@@ -0,0 +1,377 @@
+//! The language of the final answer.
+//!
+//! Models tend to answer in English, whatever the language of the task or of the sources they read. With a
+//! [`ResponseLanguage`], the system prompt tells the agent which language to answer in, and a final answer in
+//! another language is sent back once to be written again. [`ResponseLanguage::Auto`] answers in the language of the
+//! task, found by [`detect_language`].
+
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The languages [`detect_language`] recognizes: ISO 639-1 code and English name.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("it", "Italian"),
+    ("pt", "Portuguese"),
+    ("nl", "Dutch"),
+    ("ru", "Russian"),
+    ("uk", "Ukrainian"),
+    ("el", "Greek"),
+    ("he", "Hebrew"),
+    ("ar", "Arabic"),
+    ("hi", "Hindi"),
+    ("th", "Thai"),
+    ("ko", "Korean"),
+    ("ja", "Japanese"),
+    ("zh", "Chinese"),
+];
+
+/// Frequent words of the languages written in the Latin script.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "in", "that", "it", "with", "for", "was",
+            "this", "you", "be", "on", "not", "have", "what", "which",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "de", "des", "est", "et", "une", "un", "du", "que", "qui", "dans",
+            "pour", "pas", "sur", "au", "avec", "ce", "sont", "il",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "den", "mit", "von",
+            "sich", "auf", "für", "im", "dem", "auch", "es", "sind",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "es", "y", "de", "que", "en", "un", "una", "por", "con",
+            "para", "del", "se", "no", "está", "son", "lo",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "la", "gli", "le", "di", "che", "è", "e", "un", "una", "per", "non", "con",
+            "del", "della", "sono", "sul", "nel", "anche",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "os", "as", "de", "que", "é", "e", "um", "uma", "para", "com", "não", "do",
+            "da", "em", "no", "na", "são", "mais",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "is", "van", "dat", "niet", "op", "te", "zijn", "met",
+            "voor", "ook", "er", "die", "maar", "wat", "aan", "bij",
+        ],
+    ),
+];
+
+/// Letters only used by one of the languages written in the Latin script.
+const DISTINCTIVE_LETTERS: &[(char, &str)] = &[
+    ('ñ', "es"),
+    ('¿', "es"),
+    ('¡', "es"),
+    ('ã', "pt"),
+    ('õ', "pt"),
+    ('ß', "de"),
+];
+
+/// How a rejected answer starts its reason, to retry only once per task.
+const MISMATCH_REASON: &str = "The answer is in";
+
+/// The English name of a language, from its ISO 639-1 code.
+pub fn language_name(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(language, _)| *language == code)
+        .map(|(_, name)| *name)
+}
+
+fn language_code(language: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| {
+            code.eq_ignore_ascii_case(language) || name.eq_ignore_ascii_case(language)
+        })
+        .map(|(code, _)| *code)
+}
+
+/// The ISO 639-1 code of the language of the text, `None` when the text is too short or too mixed to tell.
+///
+/// The script gives the language of the texts that are not written in the Latin script. The others are told apart by
+/// their most frequent words, so a single word or a name is not enough.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let mut scripts: HashMap<&str, usize> = HashMap::new();
+    let mut latin = 0;
+    let mut kana = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        let script = match c as u32 {
+            0x0370..=0x03FF => "el",
+            0x0400..=0x04FF => match c {
+                'і' | 'ї' | 'є' | 'ґ' | 'І' | 'Ї' | 'Є' | 'Ґ' => "uk",
+                _ => "ru",
+            },
+            0x0590..=0x05FF => "he",
+            0x0600..=0x06FF => "ar",
+            0x0900..=0x097F => "hi",
+            0x0E00..=0x0E7F => "th",
+            0x1100..=0x11FF | 0xAC00..=0xD7AF => "ko",
+            0x3040..=0x30FF => {
+                kana += 1;
+                "ja"
+            }
+            0x4E00..=0x9FFF => "zh",
+            _ => {
+                latin += 1;
+                continue;
+            }
+        };
+        *scripts.entry(script).or_default() += 1;
+    }
+    // Japanese mixes kana with Chinese characters, and Ukrainian shares most letters with Russian.
+    if kana > 0 {
+        let han = scripts.remove("zh").unwrap_or_default();
+        *scripts.entry("ja").or_default() += han;
+    }
+    if let Some(ukrainian) = scripts.remove("uk") {
+        let russian = scripts.remove("ru").unwrap_or_default();
+        scripts.insert("uk", ukrainian + russian);
+    }
+    let (script, count) = scripts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .unwrap_or_default();
+    if count > latin {
+        return Some(script);
+    }
+
+    let text = text.to_lowercase();
+    let mut scores: HashMap<&str, usize> = HashMap::new();
+    for word in text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+    {
+        for (language, stopwords) in STOPWORDS {
+            if stopwords.contains(&word) {
+                *scores.entry(language).or_default() += 1;
+            }
+        }
+    }
+    for (letter, language) in DISTINCTIVE_LETTERS {
+        if text.contains(*letter) {
+            *scores.entry(language).or_default() += 2;
+        }
+    }
+    let mut scores = scores.into_iter().collect::<Vec<_>>();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    match scores.as_slice() {
+        [(language, best), rest @ ..]
+            if *best >= 2 && rest.first().is_none_or(|(_, second)| best > second) =>
+        {
+            Some(language)
+        }
+        _ => None,
+    }
+}
+
+/// The language the agent answers in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ResponseLanguage {
+    /// The language of the task.
+    Auto,
+    /// A language, by ISO 639-1 code, e.g. `fr`. Written as an English name like `French`, it is kept as its code
+    /// when [`detect_language`] knows it. The answers in the other languages are only checked against the known
+    /// ones.
+    Language(String),
+}
+
+impl ResponseLanguage {
+    /// The code of the language the answer to `task` is written in, `None` when it is the language of a task that
+    /// can not be detected.
+    pub fn language_for(&self, task: &str) -> Option<String> {
+        match self {
+            ResponseLanguage::Auto => detect_language(task).map(str::to_string),
+            ResponseLanguage::Language(language) => Some(language.clone()),
+        }
+    }
+
+    /// The instruction added to the system prompt for `task`.
+    pub fn directive(&self, task: &str) -> String {
+        match self.language_for(task) {
+            Some(language) => format!(
+                "Always write your final answer in {}, whatever the language of the sources and tool results.",
+                language_name(&language).unwrap_or(&language)
+            ),
+            None => "Write your final answer in the language of the task.".to_string(),
+        }
+    }
+
+    /// Why the answer to `task` is in the wrong language, `None` when it is in the right one or when either
+    /// language can not be detected.
+    pub fn check_answer(&self, task: &str, answer: &str) -> Option<String> {
+        let expected = self.language_for(task)?;
+        let expected_name = language_name(&expected)?;
+        let detected = detect_language(answer)?;
+        (detected != expected).then(|| {
+            format!(
+                "{} {}, but it must be in {}. Write the final answer again in {}.",
+                MISMATCH_REASON,
+                language_name(detected).unwrap_or(detected),
+                expected_name,
+                expected_name
+            )
+        })
+    }
+
+    /// Whether the reason of a rejected answer is its language.
+    pub fn is_mismatch(reason: &str) -> bool {
+        reason.starts_with(MISMATCH_REASON)
+    }
+}
+
+impl FromStr for ResponseLanguage {
+    type Err = String;
+
+    fn from_str(language: &str) -> Result<Self, Self::Err> {
+        let language = language.trim();
+        if language.is_empty() {
+            return Err("The response language is empty".to_string());
+        }
+        if language.eq_ignore_ascii_case("auto") {
+            return Ok(ResponseLanguage::Auto);
+        }
+        Ok(ResponseLanguage::Language(
+            language_code(language).map_or_else(|| language.to_string(), str::to_string),
+        ))
+    }
+}
+
+impl TryFrom<String> for ResponseLanguage {
+    type Error = String;
+
+    fn try_from(language: String) -> Result<Self, Self::Error> {
+        language.parse()
+    }
+}
+
+impl From<ResponseLanguage> for String {
+    fn from(language: ResponseLanguage) -> Self {
+        match language {
+            ResponseLanguage::Auto => "auto".to_string(),
+            ResponseLanguage::Language(language) => language,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let texts = [
+            (
+                "What is the capital of France and how large is it?",
+                Some("en"),
+            ),
+            (
+                "Quelle est la capitale de la France et combien d'habitants a-t-elle ?",
+                Some("fr"),
+            ),
+            (
+                "Was ist die Hauptstadt von Deutschland und wie groß ist sie?",
+                Some("de"),
+            ),
+            (
+                "¿Cuál es la capital de España y cuántos habitantes tiene?",
+                Some("es"),
+            ),
+            (
+                "Qual è la capitale dell'Italia e quanti abitanti ha la città?",
+                Some("it"),
+            ),
+            (
+                "Qual é a capital do Brasil e quantos habitantes tem a cidade?",
+                Some("pt"),
+            ),
+            (
+                "Wat is de hoofdstad van Nederland en hoe groot is het?",
+                Some("nl"),
+            ),
+            ("Какая столица России?", Some("ru")),
+            ("Яка столиця України?", Some("uk")),
+            ("日本の首都はどこですか？", Some("ja")),
+            ("中国的首都是哪里？", Some("zh")),
+            ("한국의 수도는 어디입니까?", Some("ko")),
+            ("ما هي عاصمة مصر؟", Some("ar")),
+            ("Paris", None),
+            ("42", None),
+        ];
+        for (text, language) in texts {
+            assert_eq!(detect_language(text), language, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_response_language() {
+        assert_eq!("auto".parse(), Ok(ResponseLanguage::Auto));
+        assert_eq!(
+            "French".parse(),
+            Ok(ResponseLanguage::Language("fr".to_string()))
+        );
+        assert_eq!(
+            "sw".parse(),
+            Ok(ResponseLanguage::Language("sw".to_string()))
+        );
+        assert!("".parse::<ResponseLanguage>().is_err());
+        let language: ResponseLanguage = serde_json::from_str("\"German\"").unwrap();
+        assert_eq!(serde_json::to_string(&language).unwrap(), "\"de\"");
+
+        let task = "Quelle est la capitale de la France ?";
+        assert!(ResponseLanguage::Auto.directive(task).contains("in French"));
+        assert!(ResponseLanguage::Auto
+            .directive("42")
+            .contains("language of the task"));
+        let reason = ResponseLanguage::Auto
+            .check_answer(
+                task,
+                "The capital of France is Paris, and it is a large city.",
+            )
+            .unwrap();
+        assert_eq!(
+            reason,
+            "The answer is in English, but it must be in French. Write the final answer again in French."
+        );
+        assert!(ResponseLanguage::is_mismatch(&reason));
+        assert_eq!(
+            ResponseLanguage::Auto.check_answer(task, "La capitale de la France est Paris."),
+            None
+        );
+        // Short answers and unknown languages are not checked.
+        assert_eq!(ResponseLanguage::Auto.check_answer(task, "Paris"), None);
+        let swahili = ResponseLanguage::Language("sw".to_string());
+        assert!(swahili.directive(task).contains("in sw"));
+        assert_eq!(
+            swahili.check_answer(task, "The capital is Paris and it is big."),
+            None
+        );
+    }
+}
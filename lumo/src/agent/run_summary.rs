@@ -0,0 +1,173 @@
+//! A summary of a run: steps, tool usage, tokens, errors and where the answer came from.
+
+use serde::{Deserialize, Serialize};
+
+use super::agent_step::Step;
+
+/// Where the answer of a run came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerSource {
+    /// The agent answered with the final answer tool.
+    FinalAnswer,
+    /// The agent ran out of steps, and the model answered from its memory.
+    MaxStepsFallback,
+}
+
+/// How often a tool was called in a run, and how long its calls took.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolUsage {
+    pub name: String,
+    pub calls: usize,
+    pub total_duration_ms: u64,
+    pub max_duration_ms: u64,
+}
+
+impl ToolUsage {
+    pub fn average_duration_ms(&self) -> u64 {
+        self.total_duration_ms / self.calls.max(1) as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Number of action steps.
+    pub steps: usize,
+    /// The tools called, in the order of their first call.
+    pub tools: Vec<ToolUsage>,
+    /// Estimated tokens of the messages sent to the model.
+    pub input_tokens: usize,
+    /// Estimated tokens of the model outputs.
+    pub output_tokens: usize,
+    /// Total time of the steps.
+    pub duration_ms: u64,
+    /// The errors of the steps, in order.
+    pub errors: Vec<String>,
+    /// Where the answer came from. `None` when the run stopped without an answer, e.g. when it was aborted.
+    pub answer_source: Option<AnswerSource>,
+}
+
+impl RunSummary {
+    /// Summarize the steps of the last task of the logs. A run that used all its `max_steps` without a final answer
+    /// was answered by the fallback, which is not logged.
+    pub fn from_logs(logs: &[Step], max_steps: usize) -> Self {
+        let task_start = logs
+            .iter()
+            .rposition(|step| matches!(step, Step::TaskStep(_)))
+            .unwrap_or(0);
+        let mut summary = RunSummary::default();
+        for step in &logs[task_start..] {
+            let Step::ActionStep(step) = step else {
+                continue;
+            };
+            summary.steps += 1;
+            summary.input_tokens += step.input_tokens.unwrap_or_default();
+            summary.output_tokens += step.output_tokens.unwrap_or_default();
+            summary.duration_ms += step.duration_ms.unwrap_or_default();
+            if let Some(error) = &step.error {
+                summary.errors.push(error.to_string());
+            }
+            for timing in &step.tool_timings {
+                match summary
+                    .tools
+                    .iter_mut()
+                    .find(|tool| tool.name == timing.name)
+                {
+                    Some(tool) => {
+                        tool.calls += 1;
+                        tool.total_duration_ms += timing.duration_ms;
+                        tool.max_duration_ms = tool.max_duration_ms.max(timing.duration_ms);
+                    }
+                    None => summary.tools.push(ToolUsage {
+                        name: timing.name.clone(),
+                        calls: 1,
+                        total_duration_ms: timing.duration_ms,
+                        max_duration_ms: timing.duration_ms,
+                    }),
+                }
+            }
+            if step.final_answer.is_some() {
+                summary.answer_source = Some(AnswerSource::FinalAnswer);
+            }
+        }
+        if summary.answer_source.is_none() && max_steps > 0 && summary.steps >= max_steps {
+            summary.answer_source = Some(AnswerSource::MaxStepsFallback);
+        }
+        summary
+    }
+
+    /// Total number of tool calls.
+    pub fn tool_calls(&self) -> usize {
+        self.tools.iter().map(|tool| tool.calls).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent::{AgentStep, ToolCallTiming},
+        errors::AgentError,
+    };
+
+    fn step(number: usize, tools: &[(&str, u64)]) -> Step {
+        Step::ActionStep(AgentStep {
+            step: number,
+            duration_ms: Some(1000),
+            input_tokens: Some(100),
+            output_tokens: Some(10),
+            tool_timings: tools
+                .iter()
+                .map(|(name, duration_ms)| ToolCallTiming {
+                    tool_call_id: None,
+                    name: name.to_string(),
+                    duration_ms: *duration_ms,
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut answered = step(3, &[("final_answer", 0)]);
+        if let Step::ActionStep(step) = &mut answered {
+            step.final_answer = Some("Paris".to_string());
+        }
+        let mut failed = step(2, &[]);
+        if let Step::ActionStep(step) = &mut failed {
+            step.error = Some(AgentError::Parsing("No tool call".to_string()));
+        }
+        let logs = vec![
+            Step::SystemPromptStep("You are an agent".to_string()),
+            Step::TaskStep("An earlier task".to_string()),
+            step(1, &[("search", 500)]),
+            Step::TaskStep("What is the capital of France?".to_string()),
+            step(
+                1,
+                &[("search", 300), ("visit_website", 900), ("search", 500)],
+            ),
+            failed,
+            answered,
+        ];
+
+        let summary = RunSummary::from_logs(&logs, 10);
+        assert_eq!(summary.steps, 3);
+        assert_eq!(summary.input_tokens, 300);
+        assert_eq!(summary.output_tokens, 30);
+        assert_eq!(summary.duration_ms, 3000);
+        assert_eq!(summary.tool_calls(), 4);
+        assert_eq!(summary.tools[0].name, "search");
+        assert_eq!(summary.tools[0].calls, 2);
+        assert_eq!(summary.tools[0].max_duration_ms, 500);
+        assert_eq!(summary.tools[0].average_duration_ms(), 400);
+        assert_eq!(summary.errors.len(), 1);
+        assert_eq!(summary.answer_source, Some(AnswerSource::FinalAnswer));
+
+        // Out of steps without an answer.
+        let summary = RunSummary::from_logs(&logs[..6], 2);
+        assert_eq!(summary.answer_source, Some(AnswerSource::MaxStepsFallback));
+        let summary = RunSummary::from_logs(&logs[..6], 10);
+        assert_eq!(summary.answer_source, None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Pause a streamed run between steps, e.g. to debug an agent step by step.
+//!
+//! The hook of `stream_run_with_hook` is called after each action step that did not answer, with the memory the next
+//! step will start from. It decides whether the run goes on, and can change the next step: add a prompt, force or
+//! skip a tool.
+
+use async_trait::async_trait;
+
+use crate::models::types::{Message, MessageRole};
+
+use super::agent_step::AgentStep;
+
+/// Changes to the next step of a run. They only apply to that step.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StepOverrides {
+    /// A message from the user added to the end of the memory.
+    pub prompt: Option<String>,
+    /// The only tool offered to the model.
+    pub force_tool: Option<String>,
+    /// Tools not offered to the model.
+    pub skip_tools: Vec<String>,
+}
+
+impl StepOverrides {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Whether the tool is offered to the model in the step.
+    pub fn allows(&self, tool: &str) -> bool {
+        self.force_tool.as_deref().is_none_or(|forced| forced == tool)
+            && !self.skip_tools.iter().any(|skipped| skipped == tool)
+    }
+
+    /// Add the prompt, and the instructions about the forced and skipped tools, to the memory of the step. The
+    /// instructions are what the code agent goes by, since it is not given a list of tools.
+    pub fn apply_to_memory(&self, memory: &mut Vec<Message>) {
+        let mut content = Vec::new();
+        if let Some(prompt) = &self.prompt {
+            content.push(prompt.clone());
+        }
+        if let Some(tool) = &self.force_tool {
+            content.push(format!("Use the tool {} in this step.", tool));
+        }
+        if !self.skip_tools.is_empty() {
+            content.push(format!(
+                "Do not use the tools {} in this step.",
+                self.skip_tools.join(", ")
+            ));
+        }
+        if !content.is_empty() {
+            memory.push(Message {
+                role: MessageRole::User,
+                content: content.join("\n"),
+                tool_call_id: None,
+                tool_calls: None,
+            });
+        }
+    }
+}
+
+/// What a run does after a step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepDecision {
+    /// Run the next step, with the overrides.
+    Continue(StepOverrides),
+    /// Stop the run without an answer.
+    Abort,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait StepHook: Send {
+    /// Called after each action step that did not answer. `memory` is the memory the next step starts from.
+    async fn after_step(&mut self, step: &AgentStep, memory: &[Message]) -> StepDecision;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_overrides() {
+        let overrides = StepOverrides {
+            prompt: Some("Search in French.".to_string()),
+            force_tool: None,
+            skip_tools: vec!["visit_website".to_string()],
+        };
+        assert!(overrides.allows("search"));
+        assert!(!overrides.allows("visit_website"));
+
+        let mut memory = Vec::new();
+        overrides.apply_to_memory(&mut memory);
+        assert_eq!(
+            memory[0].content,
+            "Search in French.\nDo not use the tools visit_website in this step."
+        );
+
+        let forced = StepOverrides {
+            force_tool: Some("search".to_string()),
+            ..Default::default()
+        };
+        assert!(forced.allows("search"));
+        assert!(!forced.allows("final_answer"));
+        assert!(StepOverrides::default().is_empty());
+    }
+}
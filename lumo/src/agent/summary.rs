@@ -0,0 +1,155 @@
+//! Titles and summaries of conversations, e.g. to list the conversations of a chat UI.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::Model,
+        types::{Message, MessageRole},
+    },
+    prompts::SUMMARIZE_SYSTEM_PROMPT,
+};
+
+/// Messages longer than this are cut in the transcript given to the model.
+const MAX_MESSAGE_CHARS: usize = 2000;
+/// Observations are cut shorter, they are rarely needed for the summary.
+const MAX_OBSERVATION_CHARS: usize = 500;
+
+/// A short title of a conversation and a bullet summary of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub title: String,
+    pub summary: Vec<String>,
+}
+
+/// Write a title and a bullet summary of the messages with the model.
+pub async fn summarize(
+    model: &dyn Model,
+    messages: &[Message],
+) -> Result<ConversationSummary, AgentError> {
+    let transcript = format_transcript(messages);
+    if transcript.is_empty() {
+        return Err(AgentError::Execution(
+            "There are no messages to summarize".to_string(),
+        ));
+    }
+    let prompt = vec![
+        Message {
+            role: MessageRole::System,
+            content: SUMMARIZE_SYSTEM_PROMPT.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        },
+        Message {
+            role: MessageRole::User,
+            content: format!("Conversation:\n{}", transcript),
+            tool_call_id: None,
+            tool_calls: None,
+        },
+    ];
+    let response = model
+        .run(prompt, None, vec![], Some(500), None)
+        .await?
+        .get_response()?;
+    Ok(parse_summary(&response))
+}
+
+/// The messages as plain text, without the system prompt and the tool call requests.
+pub(crate) fn format_transcript(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .filter(|message| !message.content.trim().is_empty())
+        .filter_map(|message| {
+            let (speaker, max_chars) = match message.role {
+                MessageRole::User => ("User", MAX_MESSAGE_CHARS),
+                MessageRole::Assistant => ("Assistant", MAX_MESSAGE_CHARS),
+                MessageRole::ToolResponse => ("Tool", MAX_OBSERVATION_CHARS),
+                MessageRole::System | MessageRole::ToolCall => return None,
+            };
+            let content = message.content.trim();
+            let content = match content.char_indices().nth(max_chars) {
+                Some((end, _)) => format!("{}...", &content[..end]),
+                None => content.to_string(),
+            };
+            Some(format!("{}: {}", speaker, content))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Parse the JSON answer of the model. Models that ignore the format get their first line as the title and the
+/// other lines as the summary.
+fn parse_summary(response: &str) -> ConversationSummary {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => response,
+    };
+    if let Ok(summary) = serde_json::from_str::<ConversationSummary>(json) {
+        return ConversationSummary {
+            title: clean_title(&summary.title),
+            summary: summary.summary,
+        };
+    }
+    let mut lines = response
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+        .filter(|line| !line.is_empty());
+    ConversationSummary {
+        title: clean_title(lines.next().unwrap_or_default()),
+        summary: lines.map(str::to_string).collect(),
+    }
+}
+
+fn clean_title(title: &str) -> String {
+    title
+        .trim()
+        .trim_start_matches("Title:")
+        .trim()
+        .trim_matches(['"', '\''])
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize() {
+        let model = MockModel::new(vec![MockResponse::text(
+            r#"```json
+{"title": "Capital of France.", "summary": ["The user asked for the capital of France", "It is Paris"]}
+```"#,
+        )]);
+        let messages = vec![
+            message(MessageRole::System, "You are Lumo"),
+            message(MessageRole::User, "What is the capital of France?"),
+            message(MessageRole::Assistant, "The capital of France is Paris."),
+        ];
+        let summary = summarize(&model, &messages).await.unwrap();
+        assert_eq!(summary.title, "Capital of France");
+        assert_eq!(summary.summary.len(), 2);
+
+        let transcript = &model.requests()[0][1].content;
+        assert!(transcript.contains("User: What is the capital of France?"));
+        assert!(!transcript.contains("You are Lumo"));
+    }
+
+    #[test]
+    fn test_parse_plain_summary() {
+        let summary = parse_summary("Title: \"Capital of France\"\n- Asked for the capital\n- It is Paris");
+        assert_eq!(summary.title, "Capital of France");
+        assert_eq!(summary.summary, vec!["Asked for the capital", "It is Paris"]);
+    }
+}
@@ -0,0 +1,235 @@
+//! The synthesis step: once the agent has gathered enough observations, a model call without tools writes the final
+//! answer from them, instead of the model calling `final_answer` or more tools.
+//!
+//! The step is taken when the model calls again the tools it already called with the same arguments, or answers with
+//! neither text nor tool calls, on the last step of the run, and when the tool calls or the observations of the run
+//! reach their limits.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{openai::ToolCall, tokenizer::TokenCounter};
+
+use super::agent_step::{AgentStep, Step};
+
+/// The message added to the memory of the synthesis call.
+pub const SYNTHESIS_PROMPT: &str = "You have gathered enough information. Do not call any tool: write the final answer to the task now, based on the observations above.";
+
+/// Why a step wrote the final answer from the observations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SynthesisTrigger {
+    /// The model called the same tools with the same arguments as earlier in the run.
+    RepeatedCall,
+    /// The model answered with neither text nor tool calls.
+    EmptyResponse,
+    /// The step is the last one of the run.
+    LastStep,
+    /// The run made `max_tool_calls` tool calls.
+    ToolCallLimit,
+    /// The observations of the run reached `max_observation_tokens`.
+    ObservationTokens,
+}
+
+/// When the agent stops calling tools and writes the final answer from its observations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Synthesis {
+    /// Write the answer when the model repeats the tool calls of an earlier step.
+    pub on_repeated_call: bool,
+    /// Write the answer on the last step of the run, instead of offering the tools once more.
+    pub on_last_step: bool,
+    /// Write the answer once the run made this many tool calls.
+    pub max_tool_calls: Option<usize>,
+    /// Write the answer once the observations of the run reach this many tokens.
+    pub max_observation_tokens: Option<usize>,
+}
+
+impl Default for Synthesis {
+    fn default() -> Self {
+        Self {
+            on_repeated_call: true,
+            on_last_step: true,
+            max_tool_calls: None,
+            max_observation_tokens: None,
+        }
+    }
+}
+
+/// The action steps of the current task.
+fn task_steps(logs: &[Step]) -> impl Iterator<Item = &AgentStep> {
+    let start = logs
+        .iter()
+        .rposition(|step| matches!(step, Step::TaskStep(_)))
+        .unwrap_or(0);
+    logs[start..].iter().filter_map(|step| match step {
+        Step::ActionStep(step) => Some(step),
+        _ => None,
+    })
+}
+
+impl Synthesis {
+    pub fn with_max_tool_calls(mut self, max_tool_calls: usize) -> Self {
+        self.max_tool_calls = Some(max_tool_calls);
+        self
+    }
+
+    pub fn with_max_observation_tokens(mut self, max_observation_tokens: usize) -> Self {
+        self.max_observation_tokens = Some(max_observation_tokens);
+        self
+    }
+
+    /// Why the step `step` of the run should write the answer before calling the model with the tools. Only once
+    /// the run called a tool, so there are observations to write it from.
+    pub fn trigger_before_step(
+        &self,
+        logs: &[Step],
+        step: usize,
+        max_steps: usize,
+        counter: &dyn TokenCounter,
+    ) -> Option<SynthesisTrigger> {
+        let tool_calls = task_steps(logs)
+            .map(|step| step.tool_call.as_ref().map_or(0, Vec::len))
+            .sum::<usize>();
+        if tool_calls == 0 {
+            return None;
+        }
+        if self.on_last_step && max_steps > 1 && step >= max_steps {
+            return Some(SynthesisTrigger::LastStep);
+        }
+        if self.max_tool_calls.is_some_and(|max| tool_calls >= max) {
+            return Some(SynthesisTrigger::ToolCallLimit);
+        }
+        if let Some(max) = self.max_observation_tokens {
+            let tokens = task_steps(logs)
+                .flat_map(|step| step.observation_contents())
+                .map(|observation| counter.count_tokens(&observation))
+                .sum::<usize>();
+            if tokens >= max {
+                return Some(SynthesisTrigger::ObservationTokens);
+            }
+        }
+        None
+    }
+
+    /// Why the response of the model, with its tool calls, should be replaced by an answer written from the
+    /// observations.
+    pub fn trigger_for_response(
+        &self,
+        logs: &[Step],
+        tool_calls: &[ToolCall],
+        response: &str,
+    ) -> Option<SynthesisTrigger> {
+        if tool_calls.is_empty() {
+            return response
+                .trim()
+                .is_empty()
+                .then_some(SynthesisTrigger::EmptyResponse);
+        }
+        if !self.on_repeated_call
+            || tool_calls
+                .iter()
+                .any(|tool_call| tool_call.function.name == "final_answer")
+        {
+            return None;
+        }
+        let earlier = task_steps(logs)
+            .flat_map(|step| step.tool_call.iter().flatten())
+            .collect::<Vec<_>>();
+        tool_calls
+            .iter()
+            .all(|tool_call| {
+                earlier.iter().any(|earlier| {
+                    earlier.function.name == tool_call.function.name
+                        && earlier.function.arguments == tool_call.function.arguments
+                })
+            })
+            .then_some(SynthesisTrigger::RepeatedCall)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::ToolObservation;
+    use crate::models::{openai::FunctionCall, tokenizer::HeuristicCounter};
+    use serde_json::json;
+
+    fn search(query: &str) -> ToolCall {
+        ToolCall {
+            id: Some(format!("call_{}", query)),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: json!({ "query": query }),
+            },
+        }
+    }
+
+    fn logs() -> Vec<Step> {
+        let mut step = AgentStep::new(1, None);
+        step.tool_call = Some(vec![search("Paris")]);
+        step.observations = Some(vec![ToolObservation::new(
+            "Paris is the capital of France. ".repeat(50),
+        )]);
+        vec![
+            Step::TaskStep("What is the capital of France?".to_string()),
+            Step::ActionStep(step),
+        ]
+    }
+
+    #[test]
+    fn test_trigger_before_step() {
+        let synthesis = Synthesis::default();
+        let counter = HeuristicCounter;
+        assert_eq!(synthesis.trigger_before_step(&logs(), 2, 5, &counter), None);
+        assert_eq!(
+            synthesis.trigger_before_step(&logs(), 5, 5, &counter),
+            Some(SynthesisTrigger::LastStep)
+        );
+        // Nothing to write the answer from before a tool is called.
+        assert_eq!(
+            synthesis.trigger_before_step(&logs()[..1], 5, 5, &counter),
+            None
+        );
+        assert_eq!(
+            synthesis
+                .clone()
+                .with_max_tool_calls(1)
+                .trigger_before_step(&logs(), 2, 5, &counter),
+            Some(SynthesisTrigger::ToolCallLimit)
+        );
+        assert_eq!(
+            synthesis
+                .with_max_observation_tokens(100)
+                .trigger_before_step(&logs(), 2, 5, &counter),
+            Some(SynthesisTrigger::ObservationTokens)
+        );
+    }
+
+    #[test]
+    fn test_trigger_for_response() {
+        let synthesis = Synthesis::default();
+        assert_eq!(
+            synthesis.trigger_for_response(&logs(), &[search("Paris")], ""),
+            Some(SynthesisTrigger::RepeatedCall)
+        );
+        assert_eq!(
+            synthesis.trigger_for_response(&logs(), &[search("Paris"), search("Rome")], ""),
+            None
+        );
+        assert_eq!(
+            synthesis.trigger_for_response(&logs(), &[], " \n"),
+            Some(SynthesisTrigger::EmptyResponse)
+        );
+        assert_eq!(synthesis.trigger_for_response(&logs(), &[], "Paris"), None);
+
+        let synthesis = Synthesis {
+            on_repeated_call: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            synthesis.trigger_for_response(&logs(), &[search("Paris")], ""),
+            None
+        );
+    }
+}
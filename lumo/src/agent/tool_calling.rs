@@ -0,0 +1,156 @@
+//! Tool calling for models without native tool calls.
+//!
+//! In prompt-based tool calling, the agent sends no tools in the request. The tools are described in the system
+//! prompt, the tool calls and tool responses of the memory are written as `Action:` and `Observation:` text, and the
+//! tool calls are parsed from the text of the response with [`parse_tool_calls`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::models::model_traits::Model;
+use crate::models::types::{Message, MessageRole};
+use crate::tools::ToolInfo;
+
+use super::function_calling_agent::parse_tool_calls;
+use super::multistep_agent::get_tool_descriptions;
+
+/// How the agent gives the tools to the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolCallingMode {
+    /// Native tool calls when the model supports them, see [`Model::supports_native_tools`], else prompt-based.
+    #[default]
+    Auto,
+    /// Always send the tools in the `tools` field of the request.
+    Native,
+    /// Always describe the tools in the prompt and parse the tool calls from the text of the response.
+    Prompt,
+}
+
+impl ToolCallingMode {
+    /// Whether the tools are described in the prompt rather than sent to the model.
+    pub fn uses_prompt(self, model: &dyn Model) -> bool {
+        match self {
+            ToolCallingMode::Auto => !model.supports_native_tools(),
+            ToolCallingMode::Native => false,
+            ToolCallingMode::Prompt => true,
+        }
+    }
+}
+
+/// The tools and how to call them, for system prompts without `{{tool_descriptions}}`.
+pub fn prompt_tool_instructions(tools: &[ToolInfo]) -> String {
+    format!(
+        "You can call the following tools:\n{}\n\nTo call a tool, write `Action:` followed by a JSON object with the \"name\" of the tool and its \"arguments\", then stop and wait for its observation:\nAction:\n{{\"name\": \"tool_name\", \"arguments\": {{...}}}}",
+        get_tool_descriptions(tools).join("")
+    )
+}
+
+/// Rewrite the messages for prompt-based tool calling: the tool calls of the assistant messages become `Action:`
+/// text unless the message already has it, and the tool responses become user messages. When `tools` is not empty,
+/// they are described at the end of the system prompt.
+pub fn prompt_tool_messages(messages: Vec<Message>, tools: &[ToolInfo]) -> Vec<Message> {
+    let mut messages = messages
+        .into_iter()
+        .map(|message| match message.role {
+            MessageRole::ToolResponse | MessageRole::ToolCall => {
+                let content = if message.content.starts_with("Observation:") {
+                    message.content
+                } else {
+                    format!("Observation: {}", message.content)
+                };
+                Message::new(MessageRole::User, &content)
+            }
+            role => {
+                let mut content = message.content;
+                let tool_calls = message.tool_calls.unwrap_or_default();
+                if !tool_calls.is_empty() && parse_tool_calls(&content).is_err() {
+                    for tool_call in tool_calls {
+                        let action = json!({
+                            "name": tool_call.function.name,
+                            "arguments": tool_call.function.arguments,
+                        });
+                        if !content.trim().is_empty() {
+                            content.push('\n');
+                        }
+                        content.push_str(&format!("Action:\n{}", action));
+                    }
+                }
+                Message::new(role, &content)
+            }
+        })
+        .collect::<Vec<_>>();
+    if !tools.is_empty() {
+        let instructions = prompt_tool_instructions(tools);
+        match messages.first_mut() {
+            Some(system) if system.role == MessageRole::System => {
+                system.content = format!("{}\n\n{}", system.content, instructions);
+            }
+            _ => messages.insert(0, Message::new(MessageRole::System, &instructions)),
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::MockModel;
+    use crate::models::openai::{FunctionCall, ToolCall};
+    use crate::models::types::MessageBuilder;
+    use crate::tools::{FinalAnswerTool, ToolGroup};
+
+    #[test]
+    fn test_prompt_tool_messages() {
+        let tool_call = ToolCall {
+            id: Some("call_1".to_string()),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: json!({"query": "Paris"}),
+            },
+        };
+        let messages = vec![
+            Message::new(MessageRole::System, "You are a helpful assistant."),
+            Message::new(MessageRole::User, "What is the capital of France?"),
+            MessageBuilder::new(MessageRole::Assistant, "")
+                .with_tool_calls(vec![tool_call.clone()])
+                .build(),
+            Message {
+                role: MessageRole::ToolResponse,
+                content: "Observation: Paris".to_string(),
+                tool_call_id: Some("call_1".to_string()),
+                tool_calls: None,
+            },
+            // Written by a model in prompt mode, with the action in its text.
+            MessageBuilder::new(
+                MessageRole::Assistant,
+                "Action: {\"name\": \"search\", \"arguments\": {\"query\": \"Paris\"}}",
+            )
+            .with_tool_calls(vec![tool_call])
+            .build(),
+        ];
+        let tools: Vec<Box<dyn crate::tools::AsyncTool>> = vec![Box::new(FinalAnswerTool::new())];
+        let prompt_messages = prompt_tool_messages(messages, &tools.tool_info());
+
+        assert!(prompt_messages[0].content.contains("final_answer"));
+        assert!(prompt_messages[0].content.contains("Action:"));
+        let action = parse_tool_calls(&prompt_messages[2].content).unwrap();
+        assert_eq!(action[0].function.name, "search");
+        assert_eq!(action[0].function.arguments["query"], "Paris");
+        assert_eq!(prompt_messages[3].role, MessageRole::User);
+        assert_eq!(prompt_messages[3].content, "Observation: Paris");
+        assert_eq!(prompt_messages[4].content.matches("Action:").count(), 1);
+        assert!(prompt_messages
+            .iter()
+            .all(|message| message.tool_calls.is_none()));
+    }
+
+    #[test]
+    fn test_uses_prompt() {
+        let model = MockModel::new(vec![]);
+        assert!(!ToolCallingMode::Auto.uses_prompt(&model));
+        assert!(ToolCallingMode::Prompt.uses_prompt(&model));
+        assert!(!ToolCallingMode::Native.uses_prompt(&model));
+    }
+}
@@ -0,0 +1,228 @@
+//! Tool calls of a step that use the results of other calls of the same step.
+//!
+//! A model that calls several tools at once cannot wait for the result of one call before writing the next one, e.g.
+//! to visit a URL found by a search. An argument can instead refer to the result of another call of the step as
+//! `$tool_call[N].result`, where `N` is the position of the call in the step, from 0. The calls run in waves: a call
+//! runs once the calls it refers to are done, with the references replaced by their results.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::models::openai::ToolCall;
+
+fn reference_regex() -> &'static Regex {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    REFERENCE.get_or_init(|| Regex::new(r"\$tool_call\[(\d+)\]\.result").unwrap())
+}
+
+/// The positions of the calls whose results the arguments refer to.
+pub fn references(arguments: &Value) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut values = vec![arguments];
+    while let Some(value) = values.pop() {
+        match value {
+            Value::String(text) => positions.extend(
+                reference_regex()
+                    .captures_iter(text)
+                    .filter_map(|captures| captures[1].parse::<usize>().ok()),
+            ),
+            Value::Array(items) => values.extend(items),
+            Value::Object(map) => values.extend(map.values()),
+            _ => {}
+        }
+    }
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+/// The wave each call runs in, from 0. A call runs in the wave after the last of the calls it refers to. A call that
+/// refers to a missing call, to itself, or to a call that refers back to it cannot run, and gets the error instead.
+pub fn execution_waves(tool_calls: &[ToolCall]) -> Vec<Result<usize, String>> {
+    let references = tool_calls
+        .iter()
+        .map(|tool_call| references(&tool_call.function.arguments))
+        .collect::<Vec<_>>();
+    let mut waves: Vec<Option<Result<usize, String>>> = vec![None; tool_calls.len()];
+    for position in 0..tool_calls.len() {
+        let _ = wave_of(position, &references, &mut waves, &mut Vec::new());
+    }
+    waves.into_iter().map(Option::unwrap).collect()
+}
+
+fn wave_of(
+    position: usize,
+    references: &[Vec<usize>],
+    waves: &mut [Option<Result<usize, String>>],
+    path: &mut Vec<usize>,
+) -> Result<usize, String> {
+    if let Some(wave) = &waves[position] {
+        return wave.clone();
+    }
+    path.push(position);
+    let mut wave = Ok(0);
+    for &reference in &references[position] {
+        let reference_wave = if reference >= references.len() {
+            Err(format!(
+                "$tool_call[{}].result refers to a tool call that does not exist, the step has {} tool calls",
+                reference,
+                references.len()
+            ))
+        } else if path.contains(&reference) {
+            Err(format!(
+                "$tool_call[{}].result cannot be used: the tool calls refer to each other",
+                reference
+            ))
+        } else {
+            wave_of(reference, references, waves, path)
+                .map_err(|e| format!("$tool_call[{}].result cannot be computed: {}", reference, e))
+        };
+        match reference_wave {
+            Ok(reference_wave) => wave = wave.map(|wave: usize| wave.max(reference_wave + 1)),
+            Err(e) => {
+                wave = Err(e);
+                break;
+            }
+        }
+    }
+    path.pop();
+    waves[position] = Some(wave.clone());
+    wave
+}
+
+/// The call with the references in its arguments replaced by the results of the calls, or why it cannot run. The
+/// results are `None` for the calls that have not run, and the error of the calls that failed.
+pub fn resolve_tool_call(
+    tool_call: &ToolCall,
+    results: &[Option<Result<String, String>>],
+) -> Result<ToolCall, String> {
+    for position in references(&tool_call.function.arguments) {
+        match results.get(position) {
+            Some(Some(Ok(_))) => {}
+            Some(Some(Err(e))) => {
+                return Err(format!(
+                    "Not run: it uses the result of tool call {}, which failed: {}",
+                    position, e
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "Not run: it uses the result of tool call {}, which did not run",
+                    position
+                ))
+            }
+        }
+    }
+    let mut tool_call = tool_call.clone();
+    substitute(&mut tool_call.function.arguments, results);
+    Ok(tool_call)
+}
+
+fn substitute(value: &mut Value, results: &[Option<Result<String, String>>]) {
+    match value {
+        Value::String(text) => {
+            let replaced = reference_regex().replace_all(text, |captures: &regex::Captures| {
+                let position = captures[1].parse::<usize>().unwrap_or(usize::MAX);
+                match results.get(position) {
+                    Some(Some(Ok(result))) => result.clone(),
+                    _ => captures[0].to_string(),
+                }
+            });
+            *text = replaced.into_owned();
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| substitute(item, results)),
+        Value::Object(map) => map.values_mut().for_each(|item| substitute(item, results)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::openai::FunctionCall;
+
+    fn tool_call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: Some(format!("call_{}", name)),
+            call_type: Some("function".to_string()),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments,
+            },
+        }
+    }
+
+    #[test]
+    fn test_execution_waves() {
+        let tool_calls = [
+            tool_call("search", json!({"query": "Rust 2024 edition"})),
+            tool_call("visit", json!({"url": "$tool_call[0].result"})),
+            tool_call(
+                "summarize",
+                json!({"texts": ["$tool_call[1].result", "$tool_call[0].result"]}),
+            ),
+            tool_call("weather", json!({"city": "Paris"})),
+        ];
+        assert_eq!(
+            execution_waves(&tool_calls),
+            vec![Ok(0), Ok(1), Ok(2), Ok(0)]
+        );
+
+        let tool_calls = [
+            tool_call("a", json!({"x": "$tool_call[1].result"})),
+            tool_call("b", json!({"x": "$tool_call[0].result"})),
+            tool_call("c", json!({"x": "$tool_call[7].result"})),
+            tool_call("d", json!({"x": "$tool_call[3].result"})),
+            tool_call("e", json!({"x": "$tool_call[2].result"})),
+        ];
+        let waves = execution_waves(&tool_calls);
+        assert!(waves[0]
+            .as_ref()
+            .unwrap_err()
+            .contains("refer to each other"));
+        assert!(waves[1].is_err());
+        assert!(waves[2].as_ref().unwrap_err().contains("does not exist"));
+        assert!(waves[3]
+            .as_ref()
+            .unwrap_err()
+            .contains("refer to each other"));
+        assert!(waves[4]
+            .as_ref()
+            .unwrap_err()
+            .contains("cannot be computed"));
+    }
+
+    #[test]
+    fn test_resolve_tool_call() {
+        let results = vec![
+            Some(Ok("https://example.com".to_string())),
+            Some(Err("Timeout".to_string())),
+            None,
+        ];
+        let resolved = resolve_tool_call(
+            &tool_call(
+                "visit",
+                json!({"url": "$tool_call[0].result", "note": "From $tool_call[0].result."}),
+            ),
+            &results,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.function.arguments,
+            json!({"url": "https://example.com", "note": "From https://example.com."})
+        );
+        assert_eq!(resolved.id.as_deref(), Some("call_visit"));
+
+        let error = resolve_tool_call(&tool_call("a", json!("$tool_call[1].result")), &results);
+        assert_eq!(
+            error.unwrap_err(),
+            "Not run: it uses the result of tool call 1, which failed: Timeout"
+        );
+        let error = resolve_tool_call(&tool_call("a", json!("$tool_call[2].result")), &results);
+        assert!(error.unwrap_err().contains("did not run"));
+    }
+}
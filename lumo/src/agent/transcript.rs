@@ -0,0 +1,308 @@
+//! Conversion between the agent logs and OpenAI-style chat messages.
+
+use crate::models::types::{Message, MessageRole};
+
+use super::agent_step::{AgentStep, Step, ToolObservation};
+
+const FACTS_PREFIX: &str = "[FACTS]:\n";
+const PLAN_PREFIX: &str = "[PLAN]:\n";
+const OBSERVATIONS_PREFIX: &str = "Observations: ";
+const ERROR_PREFIX: &str = "Error: ";
+
+fn message(role: MessageRole, content: String) -> Message {
+    Message {
+        role,
+        content,
+        tool_call_id: None,
+        tool_calls: None,
+    }
+}
+
+/// Convert the agent logs to a chat transcript.
+///
+/// Tool calls are exported as assistant messages with `tool_calls`, followed by one `tool` message per call.
+pub fn steps_to_messages(steps: &[Step]) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for step in steps {
+        match step {
+            Step::SystemPromptStep(prompt) => {
+                messages.push(message(MessageRole::System, prompt.clone()))
+            }
+            Step::TaskStep(task) => messages.push(message(MessageRole::User, task.clone())),
+            Step::PlanningStep(facts, plan) => {
+                messages.push(message(
+                    MessageRole::Assistant,
+                    format!("{}{}", FACTS_PREFIX, facts),
+                ));
+                messages.push(message(
+                    MessageRole::Assistant,
+                    format!("{}{}", PLAN_PREFIX, plan),
+                ));
+            }
+            Step::ToolCall(_) => {}
+            Step::ActionStep(step_log) => {
+                let llm_output = step_log.llm_output.clone().unwrap_or_default();
+                match &step_log.tool_call {
+                    Some(tool_calls) => {
+                        messages.push(Message {
+                            role: MessageRole::Assistant,
+                            content: llm_output,
+                            tool_call_id: None,
+                            tool_calls: Some(tool_calls.clone()),
+                        });
+                        for (i, tool_call) in tool_calls.iter().enumerate() {
+                            messages.push(Message {
+                                role: MessageRole::ToolResponse,
+                                content: step_log
+                                    .observation_of(i)
+                                    .map(|observation| observation.content.clone())
+                                    .unwrap_or_default(),
+                                tool_call_id: tool_call.id.clone(),
+                                tool_calls: None,
+                            });
+                        }
+                    }
+                    None => {
+                        if step_log.llm_output.is_some() {
+                            messages.push(message(MessageRole::Assistant, llm_output.clone()));
+                        }
+                        if step_log.observations.is_some() && step_log.final_answer.is_none() {
+                            messages.push(message(
+                                MessageRole::User,
+                                format!(
+                                    "{}{}",
+                                    OBSERVATIONS_PREFIX,
+                                    step_log.observation_contents().join("\n")
+                                ),
+                            ));
+                        }
+                    }
+                }
+                if let Some(error) = &step_log.error {
+                    messages.push(message(
+                        MessageRole::User,
+                        format!("{}{}", ERROR_PREFIX, error.message()),
+                    ));
+                }
+            }
+        }
+    }
+    messages
+}
+
+/// Rebuild agent logs from a chat transcript produced by [`steps_to_messages`] or any OpenAI-style conversation.
+pub fn messages_to_steps(messages: Vec<Message>) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut task: Option<String> = None;
+    let mut facts: Option<String> = None;
+    let mut step_number = 0;
+
+    for message in messages {
+        match message.role {
+            MessageRole::System => steps.push(Step::SystemPromptStep(message.content)),
+            MessageRole::User => {
+                if let Some(observations) = message.content.strip_prefix(OBSERVATIONS_PREFIX) {
+                    if let Some(Step::ActionStep(step_log)) = steps.last_mut() {
+                        // The step was taken for a final answer until its observations came, e.g. the code of a
+                        // code agent.
+                        step_log.final_answer = None;
+                        step_log.observations = Some(vec![ToolObservation::new(observations)]);
+                        continue;
+                    }
+                }
+                if message.content.starts_with(ERROR_PREFIX) {
+                    if let Some(Step::ActionStep(step_log)) = steps.last_mut() {
+                        if step_log.tool_call.is_none() && step_log.final_answer.take().is_some() {
+                            step_log.observations = None;
+                        }
+                        step_log.error = Some(crate::errors::AgentError::Execution(
+                            message.content[ERROR_PREFIX.len()..].to_string(),
+                        ));
+                        continue;
+                    }
+                }
+                let content = message
+                    .content
+                    .strip_prefix("New Task: ")
+                    .unwrap_or(&message.content)
+                    .to_string();
+                task = Some(content.clone());
+                steps.push(Step::TaskStep(content));
+            }
+            MessageRole::Assistant | MessageRole::ToolCall => {
+                if let Some(content) = message.content.strip_prefix(FACTS_PREFIX) {
+                    facts = Some(content.to_string());
+                    continue;
+                }
+                if let Some(plan) = message.content.strip_prefix(PLAN_PREFIX) {
+                    steps.push(Step::PlanningStep(
+                        facts.take().unwrap_or_default(),
+                        plan.to_string(),
+                    ));
+                    continue;
+                }
+                step_number += 1;
+                let tool_calls = message.tool_calls.filter(|calls| !calls.is_empty());
+                let final_answer = if tool_calls.is_none() {
+                    Some(message.content.clone())
+                } else {
+                    None
+                };
+                steps.push(Step::ActionStep(AgentStep {
+                    llm_output: Some(message.content.clone()),
+                    observations: final_answer
+                        .clone()
+                        .map(|answer| vec![ToolObservation::new(answer)]),
+                    tool_call: tool_calls,
+                    final_answer,
+                    step: step_number,
+                    task: task.clone(),
+                    ..Default::default()
+                }));
+            }
+            MessageRole::ToolResponse => {
+                if let Some(Step::ActionStep(step_log)) = steps.last_mut() {
+                    // Match the response to its call by id, falling back to the call order.
+                    let position = step_log.observations.as_ref().map_or(0, Vec::len);
+                    let call = step_log.tool_call.as_ref().and_then(|calls| {
+                        calls
+                            .iter()
+                            .find(|call| {
+                                message.tool_call_id.is_some() && call.id == message.tool_call_id
+                            })
+                            .or_else(|| calls.get(position))
+                    });
+                    if call.is_some_and(|call| call.function.name == "final_answer") {
+                        step_log.final_answer = Some(message.content.clone());
+                    }
+                    let observation = match call {
+                        Some(call) => ToolObservation::success(call, message.content),
+                        None => ToolObservation::new(message.content),
+                    };
+                    step_log
+                        .observations
+                        .get_or_insert_with(Vec::new)
+                        .push(observation);
+                }
+            }
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::{FunctionCall, ToolCall};
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let steps = vec![
+            Step::SystemPromptStep("You are a helpful assistant".to_string()),
+            Step::TaskStep("What is the capital of France?".to_string()),
+            Step::ActionStep(AgentStep {
+                llm_output: Some("".to_string()),
+                tool_call: Some(vec![ToolCall {
+                    id: Some("call_1".to_string()),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name: "final_answer".to_string(),
+                        arguments: json!({"answer": "Paris"}),
+                    },
+                }]),
+                observations: Some(vec![ToolObservation {
+                    tool_call_id: Some("call_1".to_string()),
+                    content: "Paris".to_string(),
+                    is_error: false,
+                }]),
+                final_answer: Some("Paris".to_string()),
+                step: 1,
+                task: Some("What is the capital of France?".to_string()),
+                ..Default::default()
+            }),
+        ];
+
+        let messages = steps_to_messages(&steps);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2].role, MessageRole::Assistant);
+        assert_eq!(messages[3].role, MessageRole::ToolResponse);
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("call_1"));
+
+        let imported = messages_to_steps(messages.clone());
+        assert_eq!(imported.len(), 3);
+        match &imported[2] {
+            Step::ActionStep(step_log) => {
+                assert_eq!(step_log.final_answer.as_deref(), Some("Paris"));
+                assert_eq!(step_log.observation_contents(), vec!["Paris".to_string()]);
+                assert_eq!(
+                    step_log.observation_of(0).unwrap().tool_call_id.as_deref(),
+                    Some("call_1")
+                );
+            }
+            _ => panic!("Expected an action step"),
+        }
+        assert_eq!(
+            serde_json::to_value(steps_to_messages(&imported)).unwrap(),
+            serde_json::to_value(messages).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_round_trip_code_agent() {
+        let code = |code: &str| {
+            format!(
+                "Thought: I will run code.\nCode:\n```py\n{}\n```<end_code>",
+                code
+            )
+        };
+        let steps = vec![
+            Step::SystemPromptStep("You are a helpful assistant".to_string()),
+            Step::TaskStep("What is 6 times 7?".to_string()),
+            Step::ActionStep(AgentStep {
+                llm_output: Some(code("print(6 * 7)")),
+                observations: Some(vec![ToolObservation::new("42")]),
+                step: 1,
+                ..Default::default()
+            }),
+            Step::ActionStep(AgentStep {
+                llm_output: Some(code("print(answer)")),
+                error: Some(crate::errors::AgentError::Execution(
+                    "name 'answer' is not defined".to_string(),
+                )),
+                step: 2,
+                ..Default::default()
+            }),
+            Step::ActionStep(AgentStep {
+                llm_output: Some(code("final_answer(42)")),
+                final_answer: Some("42".to_string()),
+                step: 3,
+                ..Default::default()
+            }),
+        ];
+
+        let messages = steps_to_messages(&steps);
+        let imported = messages_to_steps(messages.clone());
+        let actions = imported
+            .iter()
+            .filter_map(|step| match step {
+                Step::ActionStep(step_log) => Some(step_log),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].final_answer, None);
+        assert_eq!(actions[0].observation_contents(), vec!["42".to_string()]);
+        assert_eq!(actions[1].final_answer, None);
+        assert!(actions[1].observations.is_none());
+        assert_eq!(
+            actions[1].error.as_ref().unwrap().message(),
+            "name 'answer' is not defined"
+        );
+        assert!(actions[2].final_answer.is_some());
+        assert_eq!(
+            serde_json::to_value(steps_to_messages(&imported)).unwrap(),
+            serde_json::to_value(messages).unwrap()
+        );
+    }
+}
@@ -1,13 +1,17 @@
 use std::fmt;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AgentError {
     Parsing(String),
     Execution(String),
     MaxSteps(String),
     Generation(String),
+    /// A guardrail blocked the input, a tool call or the answer. The message is the JSON-serialized violation.
+    Guardrail(String),
+    /// The run was cancelled with its `CancellationToken`.
+    Cancelled(String),
 }
 
 impl std::error::Error for AgentError {}
@@ -19,6 +23,8 @@ impl AgentError {
             Self::Execution(msg) => msg,
             Self::MaxSteps(msg) => msg,
             Self::Generation(msg) => msg,
+            Self::Guardrail(msg) => msg,
+            Self::Cancelled(msg) => msg,
         }
     }
 }
@@ -29,6 +35,8 @@ impl std::fmt::Display for AgentError {
             Self::Execution(msg) => write!(f, "{}", msg),
             Self::MaxSteps(msg) => write!(f, "{}", msg),
             Self::Generation(msg) => write!(f, "{}", msg),
+            Self::Guardrail(msg) => write!(f, "{}", msg),
+            Self::Cancelled(msg) => write!(f, "{}", msg),
         }
     }
 }
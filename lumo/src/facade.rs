@@ -0,0 +1,250 @@
+//! A stable facade over the agent internals.
+//!
+//! The agent builders and traits still change between minor releases. Applications that only need to
+//! run agents should use [`AgentHandle`], [`RunOptions`] and [`EventStream`] instead: these types only
+//! grow new options (they are `#[non_exhaustive]`), so upgrading lumo does not break code written
+//! against them.
+
+use crate::errors::AgentError;
+
+#[cfg(not(feature = "stream"))]
+use crate::agent::Agent;
+
+#[cfg(feature = "stream")]
+use {
+    crate::{
+        agent::{AgentStream, Step},
+        models::openai::Status,
+    },
+    futures::{Stream, StreamExt},
+    std::{
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    tokio::sync::broadcast,
+};
+
+/// The agents that can be wrapped in an [`AgentHandle`].
+///
+/// This is implemented for every agent. With the `stream` feature it requires [`AgentStream`] so that
+/// handles can be streamed.
+#[cfg(feature = "stream")]
+pub trait HandleAgent: AgentStream {}
+#[cfg(feature = "stream")]
+impl<T: AgentStream> HandleAgent for T {}
+
+/// The agents that can be wrapped in an [`AgentHandle`].
+///
+/// This is implemented for every agent. With the `stream` feature it requires `AgentStream` so that
+/// handles can be streamed.
+#[cfg(not(feature = "stream"))]
+pub trait HandleAgent: Agent {}
+#[cfg(not(feature = "stream"))]
+impl<T: Agent> HandleAgent for T {}
+
+/// Options for a single run of an [`AgentHandle`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RunOptions {
+    /// Clear the memory of previous runs before starting. Defaults to `true`.
+    pub reset: bool,
+    /// Run a planning step every `planning_interval` steps. `None` keeps the agent's setting.
+    pub planning_interval: Option<usize>,
+    /// Emit the model tokens on the [`EventStream`] as well as the steps. Defaults to `false`.
+    pub stream_tokens: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            reset: true,
+            planning_interval: None,
+            stream_tokens: false,
+        }
+    }
+}
+
+impl RunOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_reset(mut self, reset: bool) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    pub fn with_planning_interval(mut self, planning_interval: Option<usize>) -> Self {
+        self.planning_interval = planning_interval;
+        self
+    }
+
+    pub fn with_stream_tokens(mut self, stream_tokens: bool) -> Self {
+        self.stream_tokens = stream_tokens;
+        self
+    }
+}
+
+/// An owned, type-erased agent.
+///
+/// Any agent can be turned into a handle with `AgentHandle::new` or `.into()`.
+pub struct AgentHandle {
+    agent: Box<dyn HandleAgent>,
+}
+
+impl AgentHandle {
+    pub fn new<A: HandleAgent + 'static>(agent: A) -> Self {
+        Self {
+            agent: Box::new(agent),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.agent.name()
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.agent.description()
+    }
+
+    /// Run the agent on a task with the default options and return the final answer.
+    pub async fn run(&mut self, task: &str) -> Result<String, AgentError> {
+        self.run_with_options(task, RunOptions::default()).await
+    }
+
+    /// Run the agent on a task and return the final answer.
+    pub async fn run_with_options(
+        &mut self,
+        task: &str,
+        options: RunOptions,
+    ) -> Result<String, AgentError> {
+        self.apply_options(&options);
+        self.agent.run(task, options.reset).await
+    }
+
+    /// Run the agent on a task and return the steps (and optionally the model tokens) as they are produced.
+    #[cfg(feature = "stream")]
+    pub fn stream<'a>(
+        &'a mut self,
+        task: &'a str,
+        options: RunOptions,
+    ) -> Result<EventStream<'a>, AgentError> {
+        self.apply_options(&options);
+        let (tx, tokens) = if options.stream_tokens {
+            let (tx, rx) = broadcast::channel(100);
+            (Some(tx), Some(status_stream(rx)))
+        } else {
+            (None, None)
+        };
+        let steps = self
+            .agent
+            .stream_run(task, options.reset, tx)
+            .map_err(|e| AgentError::Execution(e.to_string()))?;
+        Ok(EventStream { steps, tokens })
+    }
+
+    /// Access the wrapped agent. Methods reached through here are not covered by the facade's stability guarantees.
+    pub fn agent_mut(&mut self) -> &mut dyn HandleAgent {
+        self.agent.as_mut()
+    }
+
+    fn apply_options(&mut self, options: &RunOptions) {
+        if options.planning_interval.is_some() {
+            self.agent.set_planning_interval(options.planning_interval);
+        }
+    }
+}
+
+impl<A: HandleAgent + 'static> From<A> for AgentHandle {
+    fn from(agent: A) -> Self {
+        Self::new(agent)
+    }
+}
+
+/// An event produced while streaming an [`AgentHandle`].
+#[cfg(feature = "stream")]
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum RunEvent {
+    /// A token or tool call update from the model. Only sent when [`RunOptions::stream_tokens`] is set.
+    Status(Status),
+    /// A completed step. The last step carries the final answer. Boxed, as steps are much larger than the
+    /// status updates.
+    Step(Box<Step>),
+}
+
+#[cfg(feature = "stream")]
+impl RunEvent {
+    /// The final answer if this event ends the run.
+    pub fn final_answer(&self) -> Option<&str> {
+        match self {
+            RunEvent::Step(step) => match step.as_ref() {
+                Step::ActionStep(step) => step.final_answer.as_deref(),
+                _ => None,
+            },
+            RunEvent::Status(_) => None,
+        }
+    }
+}
+
+/// The events of a streamed run, see [`AgentHandle::stream`].
+#[cfg(feature = "stream")]
+pub struct EventStream<'a> {
+    steps: Pin<Box<dyn Stream<Item = anyhow::Result<Step>> + 'a>>,
+    tokens: Option<Pin<Box<dyn Stream<Item = Status> + Send>>>,
+}
+
+#[cfg(feature = "stream")]
+fn status_stream(rx: broadcast::Receiver<Status>) -> Pin<Box<dyn Stream<Item = Status> + Send>> {
+    Box::pin(futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(status) => return Some((status, rx)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
+
+#[cfg(feature = "stream")]
+impl Stream for EventStream<'_> {
+    type Item = Result<RunEvent, AgentError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(tokens) = self.tokens.as_mut() {
+            match tokens.poll_next_unpin(cx) {
+                Poll::Ready(Some(status)) => return Poll::Ready(Some(Ok(RunEvent::Status(status)))),
+                Poll::Ready(None) => self.tokens = None,
+                Poll::Pending => {}
+            }
+        }
+        match self.steps.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(step))) => Poll::Ready(Some(Ok(RunEvent::Step(Box::new(step))))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e
+                .downcast::<AgentError>()
+                .unwrap_or_else(|e| AgentError::Execution(e.to_string()))))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_options_defaults() {
+        let options = RunOptions::default();
+        assert!(options.reset);
+        assert!(options.planning_interval.is_none());
+        assert!(!options.stream_tokens);
+
+        let options = RunOptions::new()
+            .with_reset(false)
+            .with_planning_interval(Some(2));
+        assert!(!options.reset);
+        assert_eq!(options.planning_interval, Some(2));
+    }
+}
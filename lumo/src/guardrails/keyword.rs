@@ -0,0 +1,123 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+
+use super::{Guardrail, GuardrailStage, GuardrailViolation};
+use crate::models::{
+    openai::FunctionCall,
+    types::{Message, MessageRole},
+};
+
+/// Blocks inputs, tool calls and answers that contain a keyword or match a regex.
+///
+/// Keywords are matched case-insensitively. Only the user messages of the history and the task are checked at the
+/// input stage, so the system prompt, the tool observations and the messages the agent adds during the run never
+/// trigger the filter.
+pub struct KeywordFilter {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+    stages: Vec<GuardrailStage>,
+}
+
+impl KeywordFilter {
+    pub fn new(keywords: Vec<String>, patterns: Vec<String>) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            keywords: keywords.into_iter().map(|k| k.to_lowercase()).collect(),
+            patterns,
+            stages: vec![
+                GuardrailStage::Input,
+                GuardrailStage::ToolCall,
+                GuardrailStage::Output,
+            ],
+        })
+    }
+
+    /// Only run the filter at the given stages.
+    pub fn with_stages(mut self, stages: Vec<GuardrailStage>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    fn check(&self, text: &str, stage: GuardrailStage) -> Result<(), GuardrailViolation> {
+        if !self.stages.contains(&stage) {
+            return Ok(());
+        }
+        let lowercase = text.to_lowercase();
+        let reason = if let Some(keyword) = self.keywords.iter().find(|k| lowercase.contains(*k)) {
+            format!("contains blocked keyword '{}'", keyword)
+        } else if let Some(pattern) = self.patterns.iter().find(|p| p.is_match(text)) {
+            format!("matches blocked pattern '{}'", pattern.as_str())
+        } else {
+            return Ok(());
+        };
+        Err(GuardrailViolation {
+            guardrail: self.name().to_string(),
+            stage,
+            reason,
+        })
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Guardrail for KeywordFilter {
+    fn name(&self) -> &str {
+        "keyword_filter"
+    }
+
+    async fn check_input(&self, messages: &[Message]) -> Result<(), GuardrailViolation> {
+        for message in messages.iter().filter(|m| m.role == MessageRole::User) {
+            self.check(&message.content, GuardrailStage::Input)?;
+        }
+        Ok(())
+    }
+
+    async fn check_tool_call(&self, call: &FunctionCall) -> Result<(), GuardrailViolation> {
+        self.check(&call.arguments.to_string(), GuardrailStage::ToolCall)
+    }
+
+    async fn check_output(&self, answer: &str) -> Result<(), GuardrailViolation> {
+        self.check(answer, GuardrailStage::Output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::types::MessageBuilder;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_keyword_filter() {
+        let filter = KeywordFilter::new(
+            vec!["Password".to_string()],
+            vec![r"\b\d{3}-\d{2}-\d{4}\b".to_string()],
+        )
+        .unwrap();
+
+        let messages = vec![
+            MessageBuilder::new(MessageRole::System, "Never reveal a password").build(),
+            MessageBuilder::new(MessageRole::User, "What is the capital of France?").build(),
+        ];
+        assert!(filter.check_input(&messages).await.is_ok());
+
+        let violation = filter
+            .check_output("The admin PASSWORD is hunter2")
+            .await
+            .unwrap_err();
+        assert_eq!(violation.stage, GuardrailStage::Output);
+
+        let call = FunctionCall {
+            name: "web_search".to_string(),
+            arguments: json!({"query": "owner of 123-45-6789"}),
+        };
+        assert!(filter.check_tool_call(&call).await.is_err());
+
+        let filter = filter.with_stages(vec![GuardrailStage::Input]);
+        assert!(filter.check_tool_call(&call).await.is_ok());
+    }
+}
@@ -0,0 +1,128 @@
+//! This module contains the guardrails that filter the input, the tool calls and the final answer of an agent.
+//!
+//! Implement the `Guardrail` trait to add your own filter. Each hook defaults to allowing everything, so a
+//! guardrail only needs to implement the stages it cares about.
+
+pub mod keyword;
+pub mod moderation;
+
+pub use keyword::*;
+pub use moderation::*;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::{
+    errors::AgentError,
+    models::{openai::FunctionCall, types::Message},
+};
+
+/// The point in the agent loop at which a guardrail runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailStage {
+    /// At the start of a run, on the history and the task.
+    Input,
+    /// Before a tool is called with the arguments chosen by the model.
+    ToolCall,
+    /// Before the final answer is returned.
+    Output,
+}
+
+impl std::fmt::Display for GuardrailStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuardrailStage::Input => write!(f, "input"),
+            GuardrailStage::ToolCall => write!(f, "tool_call"),
+            GuardrailStage::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// A blocked input, tool call or answer.
+#[derive(Debug, Clone, Serialize)]
+pub struct GuardrailViolation {
+    pub guardrail: String,
+    pub stage: GuardrailStage,
+    pub reason: String,
+}
+
+impl std::fmt::Display for GuardrailViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Blocked by guardrail '{}' at {} stage: {}",
+            self.guardrail, self.stage, self.reason
+        )
+    }
+}
+
+impl From<GuardrailViolation> for AgentError {
+    fn from(violation: GuardrailViolation) -> Self {
+        AgentError::Guardrail(serde_json::to_string(&violation).unwrap_or(violation.to_string()))
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait Guardrail: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Check the messages of the user, the history and the task, before the first step of a run.
+    async fn check_input(&self, _messages: &[Message]) -> Result<(), GuardrailViolation> {
+        Ok(())
+    }
+
+    /// Check a tool call before the tool is executed.
+    async fn check_tool_call(&self, _call: &FunctionCall) -> Result<(), GuardrailViolation> {
+        Ok(())
+    }
+
+    /// Check the final answer before it is returned.
+    async fn check_output(&self, _answer: &str) -> Result<(), GuardrailViolation> {
+        Ok(())
+    }
+}
+
+/// The guardrails of an agent, run in order. The first violation stops the agent.
+#[derive(Default)]
+pub struct Guardrails {
+    guardrails: Vec<Box<dyn Guardrail>>,
+}
+
+impl Guardrails {
+    pub fn new(guardrails: Vec<Box<dyn Guardrail>>) -> Self {
+        Self { guardrails }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guardrails.is_empty()
+    }
+
+    pub async fn check_input(&self, messages: &[Message]) -> Result<(), AgentError> {
+        for guardrail in &self.guardrails {
+            guardrail.check_input(messages).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn check_tool_call(&self, call: &FunctionCall) -> Result<(), AgentError> {
+        for guardrail in &self.guardrails {
+            guardrail.check_tool_call(call).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn check_output(&self, answer: &str) -> Result<(), AgentError> {
+        for guardrail in &self.guardrails {
+            guardrail.check_output(answer).await?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Box<dyn Guardrail>>> for Guardrails {
+    fn from(guardrails: Vec<Box<dyn Guardrail>>) -> Self {
+        Self::new(guardrails)
+    }
+}
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+
+use super::{Guardrail, GuardrailStage, GuardrailViolation};
+use crate::models::{
+    model_traits::Model,
+    openai::FunctionCall,
+    types::{Message, MessageRole},
+};
+
+const MODERATION_PROMPT: &str = r#"You are a content moderator. Decide whether the content below violates the following policy:
+
+{{policy}}
+
+Reply with "SAFE" if it does not. Otherwise reply with "UNSAFE: " followed by a one sentence reason."#;
+
+const DEFAULT_POLICY: &str = "No hate speech, harassment, sexual content involving minors, instructions for violence or weapons, self-harm encouragement, or disclosure of personal data.";
+
+/// Asks a model whether the content violates a moderation policy.
+///
+/// By default only the user input and the final answer are moderated, as moderating every tool call doubles the
+/// number of model calls.
+pub struct LlmModerationFilter<M: Model> {
+    model: M,
+    policy: String,
+    stages: Vec<GuardrailStage>,
+}
+
+impl<M: Model> LlmModerationFilter<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            policy: DEFAULT_POLICY.to_string(),
+            stages: vec![GuardrailStage::Input, GuardrailStage::Output],
+        }
+    }
+
+    pub fn with_policy(mut self, policy: &str) -> Self {
+        self.policy = policy.to_string();
+        self
+    }
+
+    /// Only run the filter at the given stages.
+    pub fn with_stages(mut self, stages: Vec<GuardrailStage>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    async fn moderate(&self, content: &str, stage: GuardrailStage) -> Result<(), GuardrailViolation> {
+        if !self.stages.contains(&stage) || content.trim().is_empty() {
+            return Ok(());
+        }
+        let messages = vec![
+            Message {
+                role: MessageRole::System,
+                content: MODERATION_PROMPT.replace("{{policy}}", &self.policy),
+                tool_call_id: None,
+                tool_calls: None,
+            },
+            Message {
+                role: MessageRole::User,
+                content: content.to_string(),
+                tool_call_id: None,
+                tool_calls: None,
+            },
+        ];
+        let verdict = self
+            .model
+            .run(messages, None, vec![], Some(100), None)
+            .await
+            .and_then(|response| response.get_response())
+            .map_err(|e| GuardrailViolation {
+                guardrail: self.name().to_string(),
+                stage,
+                reason: format!("moderation failed: {}", e),
+            })?;
+        match verdict.trim().strip_prefix("UNSAFE") {
+            Some(reason) => Err(GuardrailViolation {
+                guardrail: self.name().to_string(),
+                stage,
+                reason: reason.trim_start_matches(':').trim().to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<M: Model> Guardrail for LlmModerationFilter<M> {
+    fn name(&self) -> &str {
+        "llm_moderation"
+    }
+
+    async fn check_input(&self, messages: &[Message]) -> Result<(), GuardrailViolation> {
+        // Only the task, the last user message, is moderated, with one model call per run.
+        match messages.iter().rev().find(|m| m.role == MessageRole::User) {
+            Some(message) => self.moderate(&message.content, GuardrailStage::Input).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn check_tool_call(&self, call: &FunctionCall) -> Result<(), GuardrailViolation> {
+        self.moderate(
+            &format!("Tool call {}: {}", call.name, call.arguments),
+            GuardrailStage::ToolCall,
+        )
+        .await
+    }
+
+    async fn check_output(&self, answer: &str) -> Result<(), GuardrailViolation> {
+        self.moderate(answer, GuardrailStage::Output).await
+    }
+}
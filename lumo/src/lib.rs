@@ -42,12 +42,43 @@
 
 //! ```
 
+// The features below spawn processes, run Python or need tokio's multi-threaded runtime.
+#[cfg(all(
+    target_arch = "wasm32",
+    any(
+        feature = "code-agent",
+        feature = "mcp",
+        feature = "plugins",
+        feature = "screenshot"
+    )
+))]
+compile_error!("the `code-agent`, `mcp`, `plugins` and `screenshot` features are not available on wasm32");
+#[cfg(all(target_arch = "wasm32", feature = "telemetry"))]
+compile_error!("the `telemetry` feature is not available on wasm32, use lumo with `default-features = false`");
+
+// Lets the code generated by `lumo-macros`, which refers to `::lumo`, be used inside this crate.
+extern crate self as lumo;
+
 pub mod agent;
 pub mod errors;
+pub mod facade;
+pub mod guardrails;
 #[cfg(feature = "code-agent")]
 pub mod local_python_interpreter;
-pub(crate) mod logger;
+pub mod logger;
 pub mod models;
+pub mod prelude;
+pub mod pipeline;
+pub mod presets;
 pub mod prompts;
+pub mod runtime;
+pub mod secrets;
 pub mod telemetry;
 pub mod tools;
+
+/// Re-exports used by the code generated by `lumo-macros`.
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow;
+    pub use async_trait;
+}
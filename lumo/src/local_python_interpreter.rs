@@ -1,3 +1,4 @@
+use crate::agent::Artifact;
 use crate::errors::InterpreterError;
 use crate::tools::tool_traits::AsyncTool;
 use crate::tools::ToolInfo;
@@ -397,13 +398,78 @@ fn extract_constant_from_pyobject(
     }
 }
 
+/// Helpers that collect the matplotlib figures and the pandas dataframes of a call. They live outside the namespace
+/// of the code, and only look at the libraries the code imported.
+const ARTIFACT_HELPERS: &str = r#"
+import os
+os.environ.setdefault("MPLBACKEND", "Agg")
+
+def ids(namespace):
+    return {name: id(value) for name, value in namespace.items()}
+
+def capture(namespace, previous):
+    import base64, io, json, sys
+    artifacts = []
+    plt = sys.modules.get("matplotlib.pyplot")
+    if plt is not None:
+        for number in plt.get_fignums():
+            try:
+                buffer = io.BytesIO()
+                plt.figure(number).savefig(buffer, format="png", bbox_inches="tight")
+                artifacts.append({
+                    "name": f"figure_{number}.png",
+                    "kind": "image",
+                    "mime_type": "image/png",
+                    "data": base64.b64encode(buffer.getvalue()).decode(),
+                })
+            except Exception:
+                pass
+        plt.close("all")
+    pd = sys.modules.get("pandas")
+    if pd is not None:
+        for name, value in list(namespace.items()):
+            # Only the dataframes assigned by this call, not the ones of earlier calls.
+            if name.startswith("_") or not isinstance(value, pd.DataFrame) or previous.get(name) == id(value):
+                continue
+            try:
+                head = value.head(PREVIEW_ROWS)
+                try:
+                    preview = head.to_markdown()
+                except ImportError:
+                    preview = head.to_string()
+                rows, columns = value.shape
+                artifacts.append({
+                    "name": f"{name}.csv",
+                    "kind": "table",
+                    "mime_type": "text/csv",
+                    "data": value.head(MAX_CSV_ROWS).to_csv(),
+                    "preview": f"{preview}\n[{rows} rows x {columns} columns]",
+                })
+            except Exception:
+                pass
+    return json.dumps(artifacts)
+"#;
+
+/// Rows of a dataframe shown to the model.
+const PREVIEW_ROWS: usize = 10;
+/// Rows of a dataframe kept in its CSV artifact.
+const MAX_CSV_ROWS: usize = 1000;
+
+/// The output of the code, the namespace after the call, and the artifacts of the call.
+type Evaluation = (PyResult<String>, HashMap<String, PyObject>, Vec<Artifact>);
+
+/// Run the code in a namespace rebuilt from `state`, then store the namespace back into `state`.
+///
+/// The code runs with a single dict as both globals and locals, like a module or a REPL, so functions defined in
+/// one call can see the imports and variables of earlier calls. The state is saved even if the code raises, so the
+/// assignments made before the error are kept.
 fn evaluate_python_code(
     code: &str,
     custom_tools: Option<&[Box<dyn AsyncTool>]>,
     static_tools: &HashMap<&'static str, &'static str>,
     state: &mut HashMap<String, Py<PyAny>>,
     runtime: Option<&Runtime>,
-) -> Result<String, InterpreterError> {
+) -> Result<(String, Vec<Artifact>), InterpreterError> {
     let custom_tools = custom_tools.map(|tools| setup_custom_tools(tools, runtime.unwrap()));
     let code = code.to_string();
     let static_tools = static_tools.clone();
@@ -415,74 +481,94 @@ fn evaluate_python_code(
     });
 
     // Move Python operations to a separate thread using std::thread
-    let handle = std::thread::spawn(move || {
-        Python::with_gil(|py| -> PyResult<(String, HashMap<String, PyObject>)> {
-            let locals = state_clone.into_py_dict(py)?;
-            let globals = PyDict::new(py);
-
-            // Add base Python tools to globals
-            for name in static_tools.keys() {
-                if let Ok(builtin) = {
-                    let cmd = CString::new(format!("__builtins__.{}", name)).unwrap();
-                    py.eval(&cmd, None, None)
-                } {
-                    globals.set_item(name, builtin)?;
+    let handle = std::thread::spawn(
+        move || -> PyResult<Evaluation> {
+            Python::with_gil(|py| {
+                let helpers = PyDict::new(py);
+                helpers.set_item("PREVIEW_ROWS", PREVIEW_ROWS)?;
+                helpers.set_item("MAX_CSV_ROWS", MAX_CSV_ROWS)?;
+                let cmd = CString::new(ARTIFACT_HELPERS).unwrap();
+                py.run(&cmd, Some(&helpers), None)?;
+
+                let namespace = state_clone.into_py_dict(py)?;
+                let mut injected = vec![
+                    "__builtins__".to_string(),
+                    "math".to_string(),
+                    "stdout".to_string(),
+                ];
+
+                // Add base Python tools to the namespace
+                for name in static_tools.keys() {
+                    if let Ok(builtin) = {
+                        let cmd = CString::new(format!("__builtins__.{}", name)).unwrap();
+                        py.eval(&cmd, None, None)
+                    } {
+                        namespace.set_item(name, builtin)?;
+                        injected.push(name.to_string());
+                    }
                 }
-            }
 
-            // Add custom tools to globals
-            if let Some(tools) = custom_tools {
-                for (name, tool) in tools {
-                    globals.set_item(name.to_string(), tool.into_bound_py_any(py)?)?;
+                // Add custom tools to the namespace
+                if let Some(tools) = custom_tools {
+                    for (name, tool) in tools {
+                        namespace.set_item(name.to_string(), tool.into_bound_py_any(py)?)?;
+                        injected.push(name.to_string());
+                    }
                 }
-            }
-
-            // Add math module functions that are in base_tools
-            let math = PyModule::import(py, "math")?;
-            globals.set_item("math", math)?;
-
-            // Setup StringIO for capturing output
-            let io = PyModule::import(py, "io")?;
-            let string_io = io.call_method0("StringIO")?;
-            globals.set_item("stdout", string_io.clone())?;
-
-            // Redirect stdout
-            let cmd = CString::new("import sys; sys.stdout = stdout".to_string()).unwrap();
-            py.run(&cmd, Some(&globals), None)?;
-
-            let code_str = CString::new(code).unwrap();
-            // Run the user code with restricted globals
-            py.run(&code_str, Some(&globals), Some(&locals))?;
-
-            // Get the output
-            locals.set_item(
-                "print_logs",
-                string_io.call_method0("getvalue")?.extract::<String>()?,
-            )?;
-
-            // Create new state from locals
-            let mut new_state = HashMap::new();
-            for key in locals.keys() {
-                let value = locals.get_item(key.clone()).unwrap();
-                new_state.insert(key.to_string(), value.into_pyobject(py)?.into());
-            }
 
-            let output = locals
-                .get_item("print_logs")
-                .map(|obj| obj.unwrap().extract::<String>().unwrap_or_default())?;
+                // Add math module functions that are in base_tools
+                let math = PyModule::import(py, "math")?;
+                namespace.set_item("math", math)?;
+
+                // Setup StringIO for capturing output
+                let io = PyModule::import(py, "io")?;
+                let string_io = io.call_method0("StringIO")?;
+                namespace.set_item("stdout", string_io.clone())?;
+
+                // Redirect stdout
+                let cmd = CString::new("import sys; sys.stdout = stdout".to_string()).unwrap();
+                py.run(&cmd, Some(&namespace), None)?;
+
+                let previous = helpers.get_item("ids")?.unwrap().call1((&namespace,))?;
+                let code_str = CString::new(code).unwrap();
+                // Run the user code with restricted globals
+                let result = py
+                    .run(&code_str, Some(&namespace), None)
+                    .and_then(|_| string_io.call_method0("getvalue")?.extract::<String>());
+
+                // Collect the figures and dataframes. The figures are closed even if the code failed, and a failed
+                // capture only loses the artifacts.
+                let artifacts = helpers
+                    .get_item("capture")?
+                    .unwrap()
+                    .call1((&namespace, previous))
+                    .and_then(|artifacts| artifacts.extract::<String>())
+                    .ok()
+                    .and_then(|artifacts| serde_json::from_str(&artifacts).ok())
+                    .unwrap_or_default();
+
+                // Create new state from the namespace, without the tools that are injected on every call
+                let mut new_state = HashMap::new();
+                for (key, value) in namespace.iter() {
+                    let key = key.to_string();
+                    if !injected.contains(&key) {
+                        new_state.insert(key, value.unbind());
+                    }
+                }
 
-            Ok((output, new_state))
-        })
-    });
+                Ok((result, new_state, artifacts))
+            })
+        },
+    );
 
     // Convert the JoinHandle result into our Result type
     match handle.join() {
         Ok(result) => {
-            let (output, new_state) = result?;
+            let (output, new_state, artifacts) = result?;
             // Update the original state with new values
             state.clear();
             state.extend(new_state);
-            Ok(output)
+            Ok((output?, artifacts))
         }
         Err(e) => Err(InterpreterError::RuntimeError(format!(
             "Thread panicked: {:?}",
@@ -497,6 +583,7 @@ pub struct LocalPythonInterpreter {
     custom_tools: Option<Vec<Box<dyn AsyncTool>>>,
     state: HashMap<String, PyObject>,
     runtime: Option<Runtime>,
+    artifacts: Vec<Artifact>,
 }
 
 impl LocalPythonInterpreter {
@@ -519,20 +606,47 @@ impl LocalPythonInterpreter {
             custom_tools,
             state: HashMap::new(),
             runtime,
+            artifacts: Vec::new(),
         }
     }
 
     pub fn forward(&mut self, code: &str) -> Result<(String, String), InterpreterError> {
-        let execution_logs = evaluate_python_code(
+        self.artifacts.clear();
+        let (execution_logs, artifacts) = evaluate_python_code(
             code,
             self.custom_tools.as_deref(),
             &self.static_tools,
             &mut self.state,
             self.runtime.as_ref(),
         )?;
+        self.artifacts = artifacts;
 
         Ok(("".to_string(), execution_logs.to_string()))
     }
+
+    /// The figures and dataframes produced by the last call to `forward`.
+    pub fn take_artifacts(&mut self) -> Vec<Artifact> {
+        std::mem::take(&mut self.artifacts)
+    }
+
+    /// Clear the variables, functions and imports defined by previous calls to `forward`.
+    pub fn reset(&mut self) {
+        self.state.clear();
+    }
+}
+
+impl Drop for LocalPythonInterpreter {
+    fn drop(&mut self) {
+        // Release the Python objects of the session now rather than when the GIL is next taken.
+        if !self.state.is_empty() {
+            Python::with_gil(|_| self.state.clear());
+        }
+        // Dropping a runtime blocks until its tasks finish, which panics when the interpreter is dropped by an async
+        // task.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -548,6 +662,33 @@ mod tests {
         assert_eq!(execution_logs, "Hello, world!\n");
     }
 
+    #[test]
+    fn test_figure_artifacts() {
+        // A stand-in for matplotlib.pyplot, which may not be installed.
+        let code = r#"import sys, types
+class Figure:
+    def savefig(self, buffer, format=None, bbox_inches=None):
+        buffer.write(b"png")
+plt = types.ModuleType("matplotlib.pyplot")
+plt.get_fignums = lambda: [1]
+plt.figure = lambda number: Figure()
+plt.close = lambda which: None
+sys.modules["matplotlib.pyplot"] = plt
+print("plotted")"#;
+        let mut interpreter = LocalPythonInterpreter::new(None, None);
+        let (_, execution_logs) = interpreter.forward(code).unwrap();
+        assert_eq!(execution_logs, "plotted\n");
+        let artifacts = interpreter.take_artifacts();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].name, "figure_1.png");
+        assert_eq!(artifacts[0].data, "cG5n");
+        assert!(interpreter.take_artifacts().is_empty());
+
+        let code = "del sys.modules['matplotlib.pyplot']";
+        interpreter.forward(code).unwrap();
+        assert!(interpreter.take_artifacts().is_empty());
+    }
+
     #[test]
     fn test_evaluate_python_code_with_joined_str() {
         let code = r#"word = 'strawberry'
@@ -572,6 +713,15 @@ print(f"The letter 'r' appears {r_count} times in the word '{word}'.")"#;
         );
     }
 
+    #[tokio::test]
+    async fn test_drop_in_async_context() {
+        let tools: Vec<Box<dyn AsyncTool>> = vec![Box::new(FinalAnswerTool::new())];
+        let mut interpreter = LocalPythonInterpreter::new(Some(&tools), None);
+        interpreter.forward("answer = 42").unwrap();
+        // Dropping the runtime of the tools the usual way would panic here.
+        drop(interpreter);
+    }
+
     #[test]
     fn test_evaluate_python_code_with_subscript() {
         let code = textwrap::dedent(
@@ -863,4 +1013,22 @@ for url in urls:
         let (_, logs_2) = local_python_interpreter.forward(&code_2).unwrap();
         println!("logs_2: {:?}", logs_2);
     }
+
+    #[test]
+    fn test_session_persists_across_calls() {
+        let mut interpreter = LocalPythonInterpreter::new(None, None);
+        interpreter
+            .forward("import json\nvalues = [1, 2, 3]\ndef dump():\n    return json.dumps(values)")
+            .unwrap();
+        let (_, logs) = interpreter.forward("print(dump())").unwrap();
+        assert_eq!(logs, "[1, 2, 3]\n");
+
+        // Assignments made before an error are kept.
+        assert!(interpreter.forward("total = sum(values)\nraise ValueError('oops')").is_err());
+        let (_, logs) = interpreter.forward("print(total)").unwrap();
+        assert_eq!(logs, "6\n");
+
+        interpreter.reset();
+        assert!(interpreter.forward("print(values)").is_err());
+    }
 }
@@ -1,3 +1,5 @@
+//! The colored terminal logger that prints the steps of the agents.
+
 use colored::Colorize;
 use log::{Level, Metadata, Record};
 use std::io::Write;
@@ -126,3 +128,11 @@ impl log::Log for ColoredLogger {
 }
 
 pub static LOGGER: ColoredLogger = ColoredLogger;
+
+/// Install [`LOGGER`] as the `log` logger with the given level. An application that already installed its own
+/// logger keeps it, and its level. Returns whether [`LOGGER`] was installed.
+pub fn init(level: log::LevelFilter) -> bool {
+    log::set_logger(&LOGGER)
+        .map(|()| log::set_max_level(level))
+        .is_ok()
+}
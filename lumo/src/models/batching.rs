@@ -0,0 +1,177 @@
+//! Batching of streamed tokens.
+//!
+//! Models stream their output a few characters at a time, and sending each delta as its own event makes thousands of
+//! tiny events and HTTP frames. A [`TokenBatcher`] groups the deltas and sends them once the batch is large or old
+//! enough. The first delta of the answer and of the reasoning is sent at once, so that batching does not delay the
+//! first token the user sees.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::reasoning::ContentChunk;
+use crate::runtime::Instant;
+
+/// When a batch of tokens is sent. Each token is sent at once by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TokenBatching {
+    /// Send a batch once it holds this many characters. No limit when 0.
+    pub max_chars: usize,
+    /// Send a batch once its first token waited this long, in milliseconds. No limit when 0.
+    pub max_delay_ms: u64,
+}
+
+impl TokenBatching {
+    pub fn new(max_chars: usize, max_delay: Duration) -> Self {
+        TokenBatching {
+            max_chars,
+            max_delay_ms: max_delay.as_millis() as u64,
+        }
+    }
+
+    /// Whether tokens are batched at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_chars > 1 || self.max_delay_ms > 0
+    }
+
+    fn max_delay(&self) -> Option<Duration> {
+        (self.max_delay_ms > 0).then(|| Duration::from_millis(self.max_delay_ms))
+    }
+}
+
+/// Groups streamed chunks of the same kind. Chunks of a different kind flush the batch, so the order is kept.
+#[derive(Debug, Default)]
+pub struct TokenBatcher {
+    batching: TokenBatching,
+    pending: Option<ContentChunk>,
+    pending_chars: usize,
+    pending_since: Option<Instant>,
+    sent_answer: bool,
+    sent_reasoning: bool,
+}
+
+impl TokenBatcher {
+    pub fn new(batching: TokenBatching) -> Self {
+        TokenBatcher {
+            batching,
+            ..Default::default()
+        }
+    }
+
+    /// Add a chunk, and return the batches to send now.
+    pub fn push(&mut self, chunk: ContentChunk) -> Vec<ContentChunk> {
+        if !self.batching.is_enabled() {
+            return vec![chunk];
+        }
+        let mut batches = Vec::new();
+        let first = match &chunk {
+            ContentChunk::Answer(_) => !std::mem::replace(&mut self.sent_answer, true),
+            ContentChunk::Reasoning(_) => !std::mem::replace(&mut self.sent_reasoning, true),
+        };
+        let same_kind = matches!(
+            (&self.pending, &chunk),
+            (Some(ContentChunk::Answer(_)), ContentChunk::Answer(_))
+                | (Some(ContentChunk::Reasoning(_)), ContentChunk::Reasoning(_))
+        );
+        if !same_kind {
+            batches.extend(self.flush());
+        }
+        if first {
+            batches.push(chunk);
+            return batches;
+        }
+
+        self.pending_chars += match &chunk {
+            ContentChunk::Answer(text) | ContentChunk::Reasoning(text) => text.chars().count(),
+        };
+        self.pending = Some(match (self.pending.take(), chunk) {
+            (Some(ContentChunk::Answer(pending)), ContentChunk::Answer(text)) => {
+                ContentChunk::Answer(pending + &text)
+            }
+            (Some(ContentChunk::Reasoning(pending)), ContentChunk::Reasoning(text)) => {
+                ContentChunk::Reasoning(pending + &text)
+            }
+            (_, chunk) => {
+                self.pending_since = Some(Instant::now());
+                chunk
+            }
+        });
+
+        let full = self.batching.max_chars > 0 && self.pending_chars >= self.batching.max_chars;
+        let due = self
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if full || due {
+            batches.extend(self.flush());
+        }
+        batches
+    }
+
+    /// Take the pending batch, e.g. at the end of the stream or before an event of another type.
+    pub fn flush(&mut self) -> Option<ContentChunk> {
+        self.pending_chars = 0;
+        self.pending_since = None;
+        self.pending.take()
+    }
+
+    /// When the pending batch is due. Wait until then to send it when no token comes in the meantime.
+    pub fn deadline(&self) -> Option<Instant> {
+        Some(self.pending_since? + self.batching.max_delay()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(text: &str) -> ContentChunk {
+        ContentChunk::Answer(text.to_string())
+    }
+
+    fn reasoning(text: &str) -> ContentChunk {
+        ContentChunk::Reasoning(text.to_string())
+    }
+
+    #[test]
+    fn test_token_batcher() {
+        // Without batching every chunk is sent at once.
+        let mut batcher = TokenBatcher::new(TokenBatching::default());
+        assert_eq!(batcher.push(answer("a")), vec![answer("a")]);
+        assert_eq!(batcher.flush(), None);
+
+        let mut batcher = TokenBatcher::new(TokenBatching::new(5, Duration::from_secs(60)));
+        let mut batches = Vec::new();
+        for chunk in [
+            reasoning("Let"),
+            reasoning(" me"),
+            reasoning(" see"),
+            answer("The"),
+            answer(" an"),
+            answer("swer"),
+            answer(" is"),
+        ] {
+            batches.extend(batcher.push(chunk));
+        }
+        assert!(batcher.deadline().is_some());
+        batches.extend(batcher.flush());
+        assert_eq!(
+            batches,
+            vec![
+                reasoning("Let"),
+                reasoning(" me see"),
+                answer("The"),
+                answer(" answer"),
+                answer(" is"),
+            ]
+        );
+        assert_eq!(batcher.deadline(), None);
+
+        // A batch older than the delay is sent with the next chunk.
+        let mut batcher = TokenBatcher::new(TokenBatching::new(0, Duration::from_millis(1)));
+        batcher.push(answer("a"));
+        assert!(batcher.push(answer("b")).is_empty());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(batcher.push(answer("c")), vec![answer("bc")]);
+    }
+}
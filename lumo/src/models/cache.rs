@@ -0,0 +1,396 @@
+//! A cache of model responses on disk, so that running the same example or test again during development does not
+//! call the provider again.
+//!
+//! A [`CachedModel`] wraps a model and keys each request on the model id, the messages, the tools, the max tokens and
+//! the extra arguments. Only successful responses are cached. The cache is opt-in and meant for development: a
+//! response is replayed as long as the request is the same, even if the provider would answer differently today.
+//!
+//! Set `LUMO_MODEL_CACHE_BYPASS=1` to call the model without reading the cache, e.g. to refresh it. The responses are
+//! still written.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::{Model, ModelResponse, ModelSampling},
+        openai::{Status, ToolCall},
+        tokenizer::TokenCounter,
+        types::Message,
+    },
+    telemetry::{
+        otel::{
+            global,
+            trace::{FutureExt, Span, TraceContextExt, Tracer},
+            Context, KeyValue,
+        },
+        RunMetadata,
+    },
+    tools::tool_traits::ToolInfo,
+};
+
+/// Where the responses are cached unless configured otherwise.
+pub const DEFAULT_CACHE_DIR: &str = ".lumo-cache/models";
+
+/// A cached response: its content, reasoning and tool calls.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedResponse {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl CachedResponse {
+    fn from_response(response: &dyn ModelResponse) -> Result<Self, AgentError> {
+        Ok(Self {
+            content: response.get_response()?,
+            reasoning: response.get_reasoning(),
+            tool_calls: response.get_tools_used()?,
+        })
+    }
+}
+
+impl ModelResponse for CachedResponse {
+    fn get_response(&self) -> Result<String, AgentError> {
+        Ok(self.content.clone())
+    }
+
+    fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError> {
+        Ok(self.tool_calls.clone())
+    }
+
+    fn get_reasoning(&self) -> Option<String> {
+        self.reasoning.clone()
+    }
+}
+
+/// A file of the cache.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    model_id: String,
+    created_at: DateTime<Utc>,
+    response: CachedResponse,
+}
+
+/// Wraps a model and caches its responses on disk.
+pub struct CachedModel<M: Model> {
+    model: M,
+    model_id: String,
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    bypass: bool,
+}
+
+impl<M: Model> CachedModel<M> {
+    /// Cache the responses of the model in `.lumo-cache/models`. The model id is part of the key, so that the
+    /// responses of different models are not mixed.
+    pub fn new(model_id: &str, model: M) -> Self {
+        Self {
+            model,
+            model_id: model_id.to_string(),
+            dir: PathBuf::from(DEFAULT_CACHE_DIR),
+            ttl: None,
+            bypass: std::env::var("LUMO_MODEL_CACHE_BYPASS")
+                .is_ok_and(|value| matches!(value.as_str(), "1" | "true")),
+        }
+    }
+
+    pub fn with_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Call the model again for responses older than this. Responses never expire by default.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Call the model without reading the cache. The responses are still cached.
+    pub fn with_bypass(mut self, bypass: bool) -> Self {
+        self.bypass = bypass;
+        self
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.model
+    }
+
+    /// Remove every cached response.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn key(
+        &self,
+        input_messages: &[Message],
+        history: &Option<Vec<Message>>,
+        tools: &[ToolInfo],
+        max_tokens: Option<usize>,
+        args: &Option<HashMap<String, Vec<String>>>,
+    ) -> String {
+        // A map in a stable order, so that the same arguments give the same key.
+        let args = args
+            .as_ref()
+            .map(|args| args.iter().collect::<BTreeMap<_, _>>());
+        let request = json!({
+            "model_id": self.model_id,
+            "messages": input_messages,
+            "history": history,
+            "tools": tools,
+            "max_tokens": max_tokens,
+            "args": args,
+        });
+        let digest = Sha256::digest(request.to_string().as_bytes());
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The cached response of the key, unless it expired or the cache is bypassed.
+    fn read(&self, key: &str) -> Option<CachedResponse> {
+        if self.bypass {
+            return None;
+        }
+        let content = std::fs::read_to_string(self.path(key)).ok()?;
+        let entry = match serde_json::from_str::<CacheEntry>(&content) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Ignoring the invalid model cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+        let expired = self.ttl.is_some_and(|ttl| {
+            Utc::now()
+                .signed_duration_since(entry.created_at)
+                .to_std()
+                .unwrap_or_default()
+                >= ttl
+        });
+        (!expired).then_some(entry.response)
+    }
+
+    fn write(&self, key: &str, response: CachedResponse) {
+        let entry = CacheEntry {
+            model_id: self.model_id.clone(),
+            created_at: Utc::now(),
+            response,
+        };
+        let path = self.path(key);
+        // Written to a temporary file first, so that a concurrent read never sees half an entry.
+        let temporary = path.with_extension("json.tmp");
+        let result = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&temporary, serde_json::to_vec_pretty(&entry)?))
+            .and_then(|_| std::fs::rename(&temporary, &path));
+        if let Err(e) = result {
+            log::warn!("Failed to cache the model response in {:?}: {}", path, e);
+        }
+    }
+
+    fn span(&self, name: &'static str, key: &str, hit: bool) -> Context {
+        let tracer = global::tracer("lumo");
+        let mut span = tracer
+            .span_builder(name)
+            .with_start_time(crate::runtime::SystemTime::now())
+            .start_with_context(&tracer, &Context::current());
+        span.set_attributes(RunMetadata::current_attributes());
+        span.set_attributes(vec![
+            KeyValue::new("llm.model_name", self.model_id.clone()),
+            KeyValue::new("lumo.cache.key", key.to_string()),
+            KeyValue::new("lumo.cache.hit", hit),
+            KeyValue::new("lumo.cache.bypass", self.bypass),
+        ]);
+        Context::current_with_span(span)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<M: Model> Model for CachedModel<M> {
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let key = self.key(&input_messages, &history, &tools, max_tokens, &args);
+        let cached = self.read(&key);
+        let cx = self.span("CachedModel::run", &key, cached.is_some());
+        if let Some(response) = cached {
+            cx.span().end();
+            return Ok(Box::new(response));
+        }
+        let response = self
+            .model
+            .run(input_messages, history, tools, max_tokens, args)
+            .with_context(cx.clone())
+            .await;
+        if let Ok(response) = &response {
+            self.write(&key, CachedResponse::from_response(response.as_ref())?);
+        }
+        cx.span().end();
+        response
+    }
+
+    /// A cached response is sent to the stream at once: its reasoning, then its content.
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let key = self.key(&input_messages, &history, &tools, max_tokens, &args);
+        let cached = self.read(&key);
+        let cx = self.span("CachedModel::run_stream", &key, cached.is_some());
+        if let Some(response) = cached {
+            if let Some(reasoning) = &response.reasoning {
+                let _ = tx.send(Status::Reasoning(reasoning.clone()));
+            }
+            if !response.content.is_empty() {
+                let _ = tx.send(Status::FirstContent(response.content.clone()));
+            }
+            cx.span().end();
+            return Ok(Box::new(response));
+        }
+        let response = self
+            .model
+            .run_stream(input_messages, history, tools, max_tokens, args, tx)
+            .with_context(cx.clone())
+            .await;
+        if let Ok(response) = &response {
+            self.write(&key, CachedResponse::from_response(response.as_ref())?);
+        }
+        cx.span().end();
+        response
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.model.context_window()
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        self.model.max_output_tokens()
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        self.model.token_counter()
+    }
+
+    fn supports_native_tools(&self) -> bool {
+        self.model.supports_native_tools()
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        self.model.sampling()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        mock::{MockModel, MockResponse},
+        types::MessageRole,
+    };
+
+    fn messages(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_cached_model() {
+        let dir = std::env::temp_dir().join(format!("lumo-model-cache-{}", nanoid::nanoid!()));
+        let responses = vec![MockResponse::text("Paris"), MockResponse::text("Lyon")];
+        let model = CachedModel::new("mock", MockModel::new(responses.clone()))
+            .with_dir(&dir)
+            .with_bypass(false);
+
+        let first = model.run(messages("Capital of France?"), None, vec![], None, None);
+        assert_eq!(first.await.unwrap().get_response().unwrap(), "Paris");
+        // The same request is answered from the cache, so the mock still has its second response.
+        let cached = model.run(messages("Capital of France?"), None, vec![], None, None);
+        assert_eq!(cached.await.unwrap().get_response().unwrap(), "Paris");
+        let other = model.run(messages("Second city?"), None, vec![], None, None);
+        assert_eq!(other.await.unwrap().get_response().unwrap(), "Lyon");
+
+        // Another model instance reads the same cache, unless it is bypassed or the responses expired.
+        let model = CachedModel::new("mock", MockModel::new(responses.clone())).with_dir(&dir);
+        let (tx, mut rx) = broadcast::channel(4);
+        let streamed = model.run_stream(messages("Second city?"), None, vec![], None, None, tx);
+        assert_eq!(streamed.await.unwrap().get_response().unwrap(), "Lyon");
+        assert!(matches!(rx.recv().await, Ok(Status::FirstContent(content)) if content == "Lyon"));
+
+        let model = model.with_bypass(true);
+        let bypassed = model.run(messages("Second city?"), None, vec![], None, None);
+        assert_eq!(bypassed.await.unwrap().get_response().unwrap(), "Paris");
+
+        let model = CachedModel::new("mock", MockModel::new(responses))
+            .with_dir(&dir)
+            .with_bypass(false)
+            .with_ttl(Duration::ZERO);
+        let expired = model.run(messages("Capital of France?"), None, vec![], None, None);
+        assert_eq!(expired.await.unwrap().get_response().unwrap(), "Paris");
+        let expired = model.run(messages("Second city?"), None, vec![], None, None);
+        assert_eq!(expired.await.unwrap().get_response().unwrap(), "Lyon");
+
+        model.clear().unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_key() {
+        let model = CachedModel::new("mock", MockModel::new(vec![]));
+        let args = |stop: &str| {
+            Some(HashMap::from([(
+                "stop".to_string(),
+                vec![stop.to_string()],
+            )]))
+        };
+        let key = model.key(&messages("Hi"), &None, &[], None, &args("Observation:"));
+        assert_eq!(
+            key,
+            model.key(&messages("Hi"), &None, &[], None, &args("Observation:"))
+        );
+        assert_eq!(key.len(), 64);
+        assert_ne!(
+            key,
+            model.key(&messages("Hi"), &None, &[], None, &args("<end_code>"))
+        );
+        assert_ne!(
+            key,
+            model.key(&messages("Hi"), &None, &[], Some(10), &args("Observation:"))
+        );
+        let other = CachedModel::new("other", MockModel::new(vec![]));
+        assert_ne!(
+            key,
+            other.key(&messages("Hi"), &None, &[], None, &args("Observation:"))
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Cancellation of the model requests of a run.
+//!
+//! A [`CancellationToken`] is shared by whoever may cancel the run, e.g. a user or a server shutting down, and the
+//! agent running it. Once the token is cancelled, the pending model request is dropped, which aborts its HTTP request
+//! or closes its event stream so the provider stops generating, and the run ends with [`AgentError::Cancelled`]
+//! after sending [`Status::Cancelled`].
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+
+use futures::future::{select, Either};
+use tokio::sync::{broadcast, watch};
+
+use crate::{errors::AgentError, models::openai::Status};
+
+/// Cancels the runs it is given to. Clones share the same state, and a cancelled token stays cancelled.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut rx = self.cancelled.subscribe();
+        // The sender lives as long as `self`, so waiting only ends with the cancellation.
+        let _ = rx.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The error of a cancelled run, after sending `Status::Cancelled` to `tx`.
+pub fn cancelled_error(tx: Option<&broadcast::Sender<Status>>) -> AgentError {
+    if let Some(tx) = tx {
+        let _ = tx.send(Status::Cancelled);
+    }
+    AgentError::Cancelled("The run was cancelled".to_string())
+}
+
+/// Wait for the request unless the token is cancelled first, in which case the request is dropped.
+pub async fn cancellable<T>(
+    token: Option<&CancellationToken>,
+    tx: Option<&broadcast::Sender<Status>>,
+    request: impl Future<Output = Result<T, AgentError>>,
+) -> Result<T, AgentError> {
+    let Some(token) = token else {
+        return request.await;
+    };
+    if token.is_cancelled() {
+        return Err(cancelled_error(tx));
+    }
+    match select(pin!(request), pin!(token.cancelled())).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(cancelled_error(tx)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancellable() {
+        let token = CancellationToken::new();
+        let done = cancellable(Some(&token), None, async { Ok(1) }).await;
+        assert_eq!(done.unwrap(), 1);
+
+        let (tx, mut rx) = broadcast::channel(10);
+        let pending = cancellable(Some(&token), Some(&tx), futures::future::pending::<Result<(), AgentError>>());
+        let cancel = async {
+            tokio::task::yield_now().await;
+            token.clone().cancel();
+        };
+        let (result, ()) = futures::join!(pending, cancel);
+        assert!(matches!(result, Err(AgentError::Cancelled(_))));
+        assert!(matches!(rx.try_recv(), Ok(Status::Cancelled)));
+
+        // A cancelled token cancels the next requests right away.
+        assert!(token.is_cancelled());
+        assert!(cancellable(Some(&token), None, async { Ok(()) }).await.is_err());
+    }
+}
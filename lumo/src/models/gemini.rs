@@ -10,13 +10,15 @@ use crate::{
 };
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::Client;
+use reqwest_eventsource::{Event, RequestBuilderExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::sync::broadcast;
 
 use super::{
-    model_traits::{Model, ModelResponse},
+    model_traits::{Model, ModelResponse, ModelSampling},
     openai::{FunctionCall, ToolCall},
 };
 
@@ -44,6 +46,54 @@ struct GeminiChatRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<GeminiTool>,
     generation_config: GeminiGenerationConfig,
+    #[serde(rename = "safetySettings", skip_serializing_if = "Vec::is_empty")]
+    safety_settings: Vec<GeminiSafetySetting>,
+}
+
+/// How much the model thinks before answering, for the models that support thinking (Gemini 2.5 and later).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    /// No thinking, for the lowest latency. Not every model can turn thinking off.
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    /// The thinking budget in tokens.
+    pub fn thinking_budget(self) -> u32 {
+        match self {
+            ReasoningEffort::None => 0,
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 24576,
+        }
+    }
+}
+
+/// Blocks the responses of a harm category above a threshold, e.g.
+/// `GeminiSafetySetting::new("HARM_CATEGORY_DANGEROUS_CONTENT", "BLOCK_ONLY_HIGH")`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GeminiSafetySetting {
+    pub category: String,
+    pub threshold: String,
+}
+
+impl GeminiSafetySetting {
+    pub fn new(category: &str, threshold: &str) -> Self {
+        Self {
+            category: category.to_string(),
+            threshold: threshold.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GeminiThinkingConfig {
+    #[serde(rename = "thinkingBudget")]
+    thinking_budget: u32,
 }
 
 /// Configuration parameters for text generation
@@ -61,22 +111,39 @@ struct GeminiGenerationConfig {
     /// Top-k sampling parameter
     #[serde(skip_serializing_if = "Option::is_none", rename = "topK")]
     top_k: Option<u32>,
+    /// Seed of the sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
     /// Stop sequences
     #[serde(rename = "stopSequences", skip_serializing_if = "Option::is_none")]
     stop_sequences: Option<Vec<String>>,
+    /// Thinking budget
+    #[serde(rename = "thinkingConfig", skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+    /// MIME type of the response, e.g. `application/json` for JSON mode
+    #[serde(rename = "responseMimeType", skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    /// Schema of a JSON response
+    #[serde(rename = "responseSchema", skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
 }
 
 /// Individual completion candidate
 #[derive(Deserialize, Debug)]
 struct GeminiCandidate {
-    /// Content of the candidate response
+    /// Content of the candidate response, missing when the response is blocked
+    #[serde(default)]
     content: GeminiResponseContent,
+    /// Why the generation stopped, e.g. `STOP` or `SAFETY`
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
 }
 
 /// Content block within a response
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 struct GeminiResponseContent {
     /// Parts making up the content
+    #[serde(default)]
     parts: Vec<GeminiResponsePart>,
 }
 
@@ -94,24 +161,49 @@ struct GeminiResponsePart {
     /// Tool calls
     #[serde(rename = "functionCall")]
     function_call: Option<GeminiFunctionCall>,
+    /// Whether the text is a thought summary rather than the answer
+    #[serde(default)]
+    thought: bool,
 }
 
 /// Response from the chat completion API
 #[derive(Deserialize, Debug)]
 struct GeminiChatResponse {
-    /// Generated completion candidates
+    /// Generated completion candidates. A chunk of a stream may have none.
+    #[serde(default)]
     candidates: Vec<GeminiCandidate>,
 }
 
+impl GeminiChatResponse {
+    fn candidate(&self) -> Result<&GeminiCandidate, AgentError> {
+        self.candidates
+            .first()
+            .ok_or_else(|| AgentError::Generation("Gemini returned no candidates".to_string()))
+    }
+}
+
 impl ModelResponse for GeminiChatResponse {
     fn get_response(&self) -> Result<String, AgentError> {
-        Ok(self.candidates[0].content.parts[0]
-            .text
-            .clone()
-            .unwrap_or_default())
+        let candidate = self.candidate()?;
+        if candidate.content.parts.is_empty() {
+            if let Some(reason) = candidate.finish_reason.as_deref().filter(|r| *r != "STOP") {
+                return Err(AgentError::Generation(format!(
+                    "Gemini returned no content, finish reason: {}",
+                    reason
+                )));
+            }
+        }
+        Ok(candidate
+            .content
+            .parts
+            .iter()
+            .filter(|part| !part.thought)
+            .filter_map(|part| part.text.as_deref())
+            .collect())
     }
     fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError> {
-        Ok(self.candidates[0]
+        Ok(self
+            .candidate()?
             .content
             .parts
             .iter()
@@ -128,6 +220,68 @@ impl ModelResponse for GeminiChatResponse {
     }
 }
 
+/// Accumulates the chunks of `streamGenerateContent`, broadcasting the text and the function calls as they arrive.
+#[derive(Default)]
+struct StreamedResponse {
+    text: String,
+    function_calls: Vec<GeminiFunctionCall>,
+    finish_reason: Option<String>,
+}
+
+impl StreamedResponse {
+    fn push(&mut self, chunk: GeminiChatResponse, tx: &broadcast::Sender<Status>) {
+        let Some(candidate) = chunk.candidates.into_iter().next() else {
+            return;
+        };
+        for part in candidate.content.parts {
+            if let Some(text) = part.text.filter(|text| !part.thought && !text.is_empty()) {
+                let status = if self.text.is_empty() {
+                    Status::FirstContent(text.clone())
+                } else {
+                    Status::Content(text.clone())
+                };
+                let _ = tx.send(status);
+                self.text.push_str(&text);
+            }
+            // Gemini streams each function call whole, in a single part.
+            if let Some(function_call) = part.function_call {
+                let _ = tx.send(Status::ToolCallStart(function_call.name.clone()));
+                let _ = tx.send(Status::ToolCallContent(function_call.args.to_string()));
+                self.function_calls.push(function_call);
+            }
+        }
+        if candidate.finish_reason.is_some() {
+            self.finish_reason = candidate.finish_reason;
+        }
+    }
+
+    fn into_response(self) -> GeminiChatResponse {
+        let mut parts = Vec::new();
+        if !self.text.is_empty() {
+            parts.push(GeminiResponsePart {
+                text: Some(self.text),
+                function_call: None,
+                thought: false,
+            });
+        }
+        parts.extend(
+            self.function_calls
+                .into_iter()
+                .map(|function_call| GeminiResponsePart {
+                    text: None,
+                    function_call: Some(function_call),
+                    thought: false,
+                }),
+        );
+        GeminiChatResponse {
+            candidates: vec![GeminiCandidate {
+                content: GeminiResponseContent { parts },
+                finish_reason: self.finish_reason,
+            }],
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GeminiServerModel {
     pub base_url: String,
@@ -136,6 +290,16 @@ pub struct GeminiServerModel {
     pub temperature: f32,
     pub api_key: String,
     pub history: Option<Vec<Message>>,
+    /// Thinking budget in tokens, `0` turns thinking off. The model default when `None`.
+    pub thinking_budget: Option<u32>,
+    /// MIME type of the answers, e.g. `application/json`. Only sent when the request has no tools, since Gemini does
+    /// not support function calling together with a response MIME type.
+    pub response_mime_type: Option<String>,
+    /// Schema of the JSON answers, sent with `response_mime_type`.
+    pub response_schema: Option<Value>,
+    pub safety_settings: Vec<GeminiSafetySetting>,
+    /// The seed of the sampling, so that the same request gets the same response.
+    pub seed: Option<u64>,
 }
 
 impl GeminiServerModel {
@@ -163,69 +327,36 @@ impl GeminiServerModel {
             temperature: temperature.unwrap_or(0.5),
             api_key,
             history,
+            thinking_budget: None,
+            response_mime_type: None,
+            response_schema: None,
+            safety_settings: Vec::new(),
+            seed: None,
         }
     }
-}
-
-pub struct GeminiServerModelBuilder {
-    base_url: Option<String>,
-    model_id: Option<String>,
-    temperature: Option<f32>,
-    api_key: Option<String>,
-    history: Option<Vec<Message>>,
-}
 
-impl GeminiServerModelBuilder {
-    pub fn new(model_id: &str) -> Self {
-        Self {
-            base_url: None,
-            model_id: Some(model_id.to_string()),
-            temperature: None,
-            api_key: None,
-            history: None,
+    /// The URL of `streamGenerateContent` with server-sent events, derived from the `generateContent` base URL.
+    fn stream_url(&self) -> String {
+        let url = self
+            .base_url
+            .replacen(":generateContent", ":streamGenerateContent", 1);
+        if url.contains("alt=sse") {
+            url
+        } else if url.contains('?') {
+            format!("{}&alt=sse", url)
+        } else {
+            format!("{}?alt=sse", url)
         }
     }
-    pub fn with_base_url(mut self, base_url: Option<&str>) -> Self {
-        self.base_url = base_url.map(|s| s.to_string());
-        self
-    }
-    pub fn with_model_id(mut self, model_id: Option<&str>) -> Self {
-        self.model_id = model_id.map(|s| s.to_string());
-        self
-    }
-    pub fn with_temperature(mut self, temperature: Option<f32>) -> Self {
-        self.temperature = temperature;
-        self
-    }
-    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
-        self.api_key = api_key.map(|s| s.to_string());
-        self
-    }
-    pub fn with_history(mut self, history: Option<Vec<Message>>) -> Self {
-        self.history = history;
-        self
-    }
-    pub fn build(self) -> Result<GeminiServerModel> {
-        Ok(GeminiServerModel::new(
-            self.base_url.as_deref(),
-            self.model_id.as_deref(),
-            self.temperature,
-            self.api_key,
-            self.history,
-        ))
-    }
-}
 
-#[async_trait]
-impl Model for GeminiServerModel {
-    async fn run(
+    fn request(
         &self,
         messages: Vec<Message>,
         history: Option<Vec<Message>>,
         tools_to_call_from: Vec<ToolInfo>,
         max_tokens: Option<usize>,
         args: Option<HashMap<String, Vec<String>>>,
-    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+    ) -> Value {
         let mut chat_contents = Vec::with_capacity(messages.len());
 
         if let Some(history) = history {
@@ -289,8 +420,21 @@ impl Model for GeminiServerModel {
                 temperature: Some(self.temperature),
                 top_p: None,
                 top_k: None,
+                seed: self.seed,
                 stop_sequences,
+                thinking_config: self
+                    .thinking_budget
+                    .map(|thinking_budget| GeminiThinkingConfig { thinking_budget }),
+                response_mime_type: self
+                    .response_mime_type
+                    .clone()
+                    .filter(|_| tools_to_call_from.is_none()),
+                response_schema: self
+                    .response_schema
+                    .clone()
+                    .filter(|_| tools_to_call_from.is_none() && self.response_mime_type.is_some()),
             },
+            safety_settings: self.safety_settings.clone(),
         };
 
         let mut request = json!(request);
@@ -301,6 +445,130 @@ impl Model for GeminiServerModel {
 
             });
         }
+        request
+    }
+}
+
+pub struct GeminiServerModelBuilder {
+    base_url: Option<String>,
+    model_id: Option<String>,
+    temperature: Option<f32>,
+    api_key: Option<String>,
+    history: Option<Vec<Message>>,
+    thinking_budget: Option<u32>,
+    response_mime_type: Option<String>,
+    response_schema: Option<Value>,
+    safety_settings: Vec<GeminiSafetySetting>,
+    seed: Option<u64>,
+}
+
+impl GeminiServerModelBuilder {
+    pub fn new(model_id: &str) -> Self {
+        Self {
+            base_url: None,
+            model_id: Some(model_id.to_string()),
+            temperature: None,
+            api_key: None,
+            history: None,
+            thinking_budget: None,
+            response_mime_type: None,
+            response_schema: None,
+            safety_settings: Vec::new(),
+            seed: None,
+        }
+    }
+    pub fn with_base_url(mut self, base_url: Option<&str>) -> Self {
+        self.base_url = base_url.map(|s| s.to_string());
+        self
+    }
+    pub fn with_model_id(mut self, model_id: Option<&str>) -> Self {
+        self.model_id = model_id.map(|s| s.to_string());
+        self
+    }
+    pub fn with_temperature(mut self, temperature: Option<f32>) -> Self {
+        self.temperature = temperature;
+        self
+    }
+    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
+        self.api_key = api_key.map(|s| s.to_string());
+        self
+    }
+    pub fn with_history(mut self, history: Option<Vec<Message>>) -> Self {
+        self.history = history;
+        self
+    }
+    /// The number of tokens the model may think with, `0` to turn thinking off.
+    pub fn with_thinking_budget(mut self, thinking_budget: u32) -> Self {
+        self.thinking_budget = Some(thinking_budget);
+        self
+    }
+    /// Sets the thinking budget from an effort level.
+    pub fn with_reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.thinking_budget = Some(effort.thinking_budget());
+        self
+    }
+    /// E.g. `application/json` for JSON mode. Ignored for the requests with tools.
+    pub fn with_response_mime_type(mut self, mime_type: &str) -> Self {
+        self.response_mime_type = Some(mime_type.to_string());
+        self
+    }
+    /// JSON mode with the answers following `schema`, an OpenAPI schema object.
+    pub fn with_response_schema(mut self, schema: Value) -> Self {
+        self.response_mime_type = Some("application/json".to_string());
+        self.response_schema = Some(schema);
+        self
+    }
+    pub fn with_safety_settings(mut self, safety_settings: Vec<GeminiSafetySetting>) -> Self {
+        self.safety_settings = safety_settings;
+        self
+    }
+    pub fn with_safety_setting(mut self, category: &str, threshold: &str) -> Self {
+        self.safety_settings
+            .push(GeminiSafetySetting::new(category, threshold));
+        self
+    }
+    /// The seed of the sampling, so that the same request gets the same response from the same model.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+    pub fn build(self) -> Result<GeminiServerModel> {
+        let mut model = GeminiServerModel::new(
+            self.base_url.as_deref(),
+            self.model_id.as_deref(),
+            self.temperature,
+            self.api_key,
+            self.history,
+        );
+        model.thinking_budget = self.thinking_budget;
+        model.response_mime_type = self.response_mime_type;
+        model.response_schema = self.response_schema;
+        model.safety_settings = self.safety_settings;
+        model.seed = self.seed;
+        Ok(model)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Model for GeminiServerModel {
+    fn sampling(&self) -> ModelSampling {
+        ModelSampling {
+            model_id: Some(self.model_id.clone()),
+            temperature: Some(self.temperature),
+            seed: self.seed,
+        }
+    }
+
+    async fn run(
+        &self,
+        messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools_to_call_from: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let request = self.request(messages, history, tools_to_call_from, max_tokens, args);
         println!(
             "Request: {}",
             serde_json::to_string_pretty(&request).unwrap()
@@ -328,7 +596,6 @@ impl Model for GeminiServerModel {
         }
     }
 
-    #[allow(unused_variables)]
     async fn run_stream(
         &self,
         messages: Vec<Message>,
@@ -338,7 +605,40 @@ impl Model for GeminiServerModel {
         args: Option<HashMap<String, Vec<String>>>,
         tx: broadcast::Sender<Status>,
     ) -> Result<Box<dyn ModelResponse>, AgentError> {
-        unimplemented!()
+        let request = self.request(messages, history, tools_to_call_from, max_tokens, args);
+        let mut stream = self
+            .client
+            .post(self.stream_url())
+            .json(&request)
+            .eventsource()
+            .map_err(|e| AgentError::Generation(format!("Failed to create event source: {}", e)))?;
+
+        let mut streamed = StreamedResponse::default();
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(Event::Open) => {}
+                Ok(Event::Message(event)) => {
+                    let chunk =
+                        serde_json::from_str::<GeminiChatResponse>(&event.data).map_err(|e| {
+                            AgentError::Generation(format!(
+                                "Failed to deserialize response from Gemini: {}",
+                                e
+                            ))
+                        })?;
+                    streamed.push(chunk, &tx);
+                }
+                Err(reqwest_eventsource::Error::StreamEnded) => break,
+                Err(e) => {
+                    stream.close();
+                    return Err(AgentError::Generation(format!(
+                        "Failed to get response from Gemini: {}",
+                        e
+                    )));
+                }
+            }
+        }
+        stream.close();
+        Ok(Box::new(streamed.into_response()))
     }
 }
 
@@ -371,4 +671,71 @@ mod tests {
 
         println!("Response: {}", response.get_response().unwrap());
     }
+
+    #[test]
+    fn test_generation_options() {
+        let model = GeminiServerModelBuilder::new("gemini-2.5-flash")
+            .with_api_key(Some("key"))
+            .with_reasoning_effort(ReasoningEffort::Low)
+            .with_response_schema(json!({"type": "OBJECT"}))
+            .with_safety_setting("HARM_CATEGORY_HARASSMENT", "BLOCK_ONLY_HIGH")
+            .build()
+            .unwrap();
+        let message = Message {
+            role: MessageRole::User,
+            content: "Hello".to_string(),
+            tool_call_id: None,
+            tool_calls: None,
+        };
+
+        let request = model.request(vec![message.clone()], None, vec![], None, None);
+        let config = &request["generation_config"];
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 1024);
+        assert_eq!(config["responseMimeType"], "application/json");
+        assert_eq!(config["responseSchema"]["type"], "OBJECT");
+        assert_eq!(request["safetySettings"][0]["threshold"], "BLOCK_ONLY_HIGH");
+
+        // Gemini does not support JSON mode together with function calling.
+        let tool = crate::tools::AnyTool::tool_info(&crate::tools::FinalAnswerTool::new());
+        let request = model.request(vec![message], None, vec![tool], None, None);
+        assert!(request["generation_config"]
+            .get("responseMimeType")
+            .is_none());
+        assert!(request["generation_config"].get("thinkingConfig").is_some());
+
+        assert_eq!(
+            model.stream_url(),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:streamGenerateContent?key=key&alt=sse"
+        );
+    }
+
+    #[test]
+    fn test_streamed_response() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut streamed = StreamedResponse::default();
+        for chunk in [
+            json!({"candidates": [{"content": {"parts": [{"text": "Thinking about it", "thought": true}]}}]}),
+            json!({"candidates": [{"content": {"parts": [{"text": "Let me "}]}}]}),
+            json!({"candidates": [{"content": {"parts": [{"text": "search."}, {"functionCall": {"name": "search", "args": {"query": "rust"}}}]}, "finishReason": "STOP"}]}),
+            json!({"usageMetadata": {"totalTokenCount": 10}}),
+        ] {
+            streamed.push(serde_json::from_value(chunk).unwrap(), &tx);
+        }
+        let response = streamed.into_response();
+        assert_eq!(response.get_response().unwrap(), "Let me search.");
+        let tools = response.get_tools_used().unwrap();
+        assert_eq!(tools[0].function.name, "search");
+        assert_eq!(tools[0].function.arguments["query"], "rust");
+
+        assert!(matches!(rx.try_recv(), Ok(Status::FirstContent(text)) if text == "Let me "));
+        assert!(matches!(rx.try_recv(), Ok(Status::Content(text)) if text == "search."));
+        assert!(matches!(rx.try_recv(), Ok(Status::ToolCallStart(name)) if name == "search"));
+    }
+
+    #[test]
+    fn test_blocked_response() {
+        let response: GeminiChatResponse =
+            serde_json::from_value(json!({"candidates": [{"finishReason": "SAFETY"}]})).unwrap();
+        assert!(response.get_response().is_err());
+    }
 }
@@ -0,0 +1,368 @@
+//! Models served by Hugging Face: Inference Providers, Inference Endpoints and self-hosted Text Generation Inference
+//! (TGI). All of them expose an OpenAI-compatible `/v1/chat/completions` route.
+//!
+//! Not every model served by Hugging Face supports native tool calls. With [`HuggingFaceToolCalling::Auto`], the
+//! model falls back to describing the tools in the prompt and parsing `<tool_call>` blocks from the output when the
+//! server rejects the tools.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+
+use crate::{
+    agent::parse_tool_calls,
+    errors::AgentError,
+    models::{
+        model_traits::{Model, ModelResponse, ModelSampling},
+        openai::{
+            AssistantMessage, Choice, OpenAIResponse, OpenAIServerModel, Status,
+        },
+        types::{Message, MessageRole},
+    },
+    secrets::SecretKey,
+    tools::tool_traits::ToolInfo,
+};
+
+const DEFAULT_BASE_URL: &str = "https://router.huggingface.co/v1/chat/completions";
+const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+
+/// How the model is given the tools.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HuggingFaceToolCalling {
+    /// Use native tool calls, and fall back to prompt-based tool calls if the server rejects them.
+    #[default]
+    Auto,
+    /// Always send the tools in the request.
+    Native,
+    /// Always describe the tools in the prompt and parse the tool calls from the output.
+    Prompt,
+}
+
+/// The token of the Hugging Face account, from `HF_TOKEN` or `HUGGINGFACEHUB_API_TOKEN`.
+pub fn hf_token() -> Option<String> {
+    std::env::var("HF_TOKEN")
+        .or_else(|_| std::env::var("HUGGINGFACEHUB_API_TOKEN"))
+        .ok()
+}
+
+/// The chat completions URL of a Hugging Face endpoint. Endpoint and TGI URLs are given without the route, e.g.
+/// `https://xyz.endpoints.huggingface.cloud`.
+fn chat_completions_url(base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    if base_url.ends_with("/chat/completions") {
+        base_url.to_string()
+    } else if base_url.ends_with("/v1") {
+        format!("{}/chat/completions", base_url)
+    } else {
+        format!("{}{}", base_url, CHAT_COMPLETIONS_PATH)
+    }
+}
+
+#[derive(Debug)]
+pub struct HuggingFaceModel {
+    pub model: OpenAIServerModel,
+    pub tool_calling: HuggingFaceToolCalling,
+    /// Set when the server rejected native tool calls in `Auto` mode.
+    prompt_fallback: AtomicBool,
+}
+
+impl HuggingFaceModel {
+    pub fn new(
+        base_url: Option<&str>,
+        model_id: Option<&str>,
+        temperature: Option<f32>,
+        api_key: Option<String>,
+        history: Option<Vec<Message>>,
+    ) -> Self {
+        let base_url = chat_completions_url(base_url.unwrap_or(DEFAULT_BASE_URL));
+        // Self-hosted TGI servers usually have no token.
+        let api_key = api_key.or_else(hf_token).unwrap_or_default();
+        HuggingFaceModel {
+            model: OpenAIServerModel::new(
+                Some(&base_url),
+                // TGI serves a single model and accepts any name.
+                Some(model_id.unwrap_or("tgi")),
+                temperature,
+                Some(api_key),
+                history,
+            ),
+            tool_calling: HuggingFaceToolCalling::default(),
+            prompt_fallback: AtomicBool::new(false),
+        }
+    }
+
+    fn use_prompt_tools(&self, tools: &[ToolInfo]) -> bool {
+        !tools.is_empty()
+            && match self.tool_calling {
+                HuggingFaceToolCalling::Auto => self.prompt_fallback.load(Ordering::Relaxed),
+                HuggingFaceToolCalling::Native => false,
+                HuggingFaceToolCalling::Prompt => true,
+            }
+    }
+
+    /// Whether the error is the server rejecting the tools, so the request can be retried with prompt-based tools.
+    fn should_fall_back(&self, error: &AgentError, tools: &[ToolInfo]) -> bool {
+        self.tool_calling == HuggingFaceToolCalling::Auto
+            && !tools.is_empty()
+            && matches!(error, AgentError::Generation(message) if message.to_lowercase().contains("tool"))
+    }
+
+    fn fall_back(&self, error: &AgentError) {
+        tracing::warn!(
+            model = %self.model.model_id,
+            error = %error,
+            "Native tool calls are not supported, falling back to prompt-based tool calls"
+        );
+        self.prompt_fallback.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct HuggingFaceModelBuilder {
+    base_url: Option<String>,
+    model_id: Option<String>,
+    temperature: Option<f32>,
+    api_key: Option<String>,
+    history: Option<Vec<Message>>,
+    tool_calling: Option<HuggingFaceToolCalling>,
+    secret: Option<SecretKey>,
+}
+
+impl HuggingFaceModelBuilder {
+    pub fn new(model_id: &str) -> Self {
+        Self {
+            base_url: None,
+            model_id: Some(model_id.to_string()),
+            temperature: None,
+            api_key: None,
+            history: None,
+            tool_calling: None,
+            secret: None,
+        }
+    }
+    /// The Inference Providers router by default. Inference Endpoint and TGI URLs can be given without the
+    /// `/v1/chat/completions` route.
+    pub fn with_base_url(mut self, base_url: Option<&str>) -> Self {
+        self.base_url = base_url.map(|s| s.to_string());
+        self
+    }
+    pub fn with_model_id(mut self, model_id: Option<&str>) -> Self {
+        self.model_id = model_id.map(|s| s.to_string());
+        self
+    }
+    pub fn with_temperature(mut self, temperature: Option<f32>) -> Self {
+        self.temperature = temperature;
+        self
+    }
+    /// Defaults to `HF_TOKEN`.
+    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
+        self.api_key = api_key.map(|s| s.to_string());
+        self
+    }
+    /// Read the token from a secret provider on each request. Replaces `with_api_key`.
+    pub fn with_secret(mut self, secret: SecretKey) -> Self {
+        self.secret = Some(secret);
+        self
+    }
+    pub fn with_history(mut self, history: Option<Vec<Message>>) -> Self {
+        self.history = history;
+        self
+    }
+    pub fn with_tool_calling(mut self, tool_calling: Option<HuggingFaceToolCalling>) -> Self {
+        self.tool_calling = tool_calling;
+        self
+    }
+    pub fn build(self) -> Result<HuggingFaceModel> {
+        let mut model = HuggingFaceModel::new(
+            self.base_url.as_deref(),
+            self.model_id.as_deref(),
+            self.temperature,
+            self.api_key,
+            self.history,
+        );
+        model.tool_calling = self.tool_calling.unwrap_or_default();
+        model.model.secret = self.secret;
+        Ok(model)
+    }
+}
+
+/// Describe the tools in a system message, and rewrite tool calls and tool responses as plain messages for servers
+/// that do not accept them.
+fn prompt_tool_messages(messages: Vec<Message>, tools: &[ToolInfo]) -> Vec<Message> {
+    let tools_description = tools
+        .iter()
+        .map(|tool| {
+            format!(
+                "- {}: {}\n  Arguments: {}",
+                tool.function.name,
+                tool.function.description,
+                serde_json::to_string(&tool.function.parameters).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut prompt_messages = vec![Message {
+        role: MessageRole::System,
+        content: format!(
+            "You can call the following tools:\n{}\n\nTo call a tool, answer with a single tool call in this format:\n<tool_call>\n{{\"name\": \"tool_name\", \"arguments\": {{...}}}}\n</tool_call>",
+            tools_description
+        ),
+        tool_call_id: None,
+        tool_calls: None,
+    }];
+    for message in messages {
+        let message = match message.role {
+            MessageRole::ToolResponse | MessageRole::ToolCall => Message {
+                role: MessageRole::User,
+                content: message.content,
+                tool_call_id: None,
+                tool_calls: None,
+            },
+            _ => {
+                let calls = message
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .map(|call| {
+                        format!(
+                            "<tool_call>\n{}\n</tool_call>",
+                            serde_json::json!({ "name": call.function.name, "arguments": call.function.arguments })
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Message {
+                    role: message.role,
+                    content: [vec![message.content], calls].concat().join("\n").trim().to_string(),
+                    tool_call_id: None,
+                    tool_calls: None,
+                }
+            }
+        };
+        prompt_messages.push(message);
+    }
+    prompt_messages
+}
+
+/// Parse the `<tool_call>` blocks from the output of a prompt-based request.
+fn prompt_tool_response(output: String) -> OpenAIResponse {
+    let tool_calls = parse_tool_calls(&output).ok();
+    OpenAIResponse {
+        choices: vec![Choice {
+            message: AssistantMessage {
+                role: MessageRole::Assistant,
+                content: Some(output),
+                tool_calls,
+                refusal: None,
+                reasoning_content: None,
+            },
+        }],
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Model for HuggingFaceModel {
+    /// A model in `Prompt` mode leaves the tools to the agent, which describes them in its own prompt.
+    fn supports_native_tools(&self) -> bool {
+        self.tool_calling != HuggingFaceToolCalling::Prompt
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        self.model.sampling()
+    }
+
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        if !self.use_prompt_tools(&tools) {
+            match self
+                .model
+                .run(input_messages.clone(), history.clone(), tools.clone(), max_tokens, args.clone())
+                .await
+            {
+                Err(e) if self.should_fall_back(&e, &tools) => self.fall_back(&e),
+                result => return result,
+            }
+        }
+        let messages = [history.unwrap_or_default(), input_messages].concat();
+        let response = self
+            .model
+            .run(prompt_tool_messages(messages, &tools), None, vec![], max_tokens, args)
+            .await?;
+        Ok(Box::new(prompt_tool_response(response.get_response()?)))
+    }
+
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        if !self.use_prompt_tools(&tools) {
+            match self
+                .model
+                .run_stream(
+                    input_messages.clone(),
+                    history.clone(),
+                    tools.clone(),
+                    max_tokens,
+                    args.clone(),
+                    tx.clone(),
+                )
+                .await
+            {
+                Err(e) if self.should_fall_back(&e, &tools) => self.fall_back(&e),
+                result => return result,
+            }
+        }
+        let messages = [history.unwrap_or_default(), input_messages].concat();
+        let response = self
+            .model
+            .run_stream(prompt_tool_messages(messages, &tools), None, vec![], max_tokens, args, tx)
+            .await?;
+        Ok(Box::new(prompt_tool_response(response.get_response()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_completions_url() {
+        assert_eq!(
+            chat_completions_url("https://xyz.endpoints.huggingface.cloud/"),
+            "https://xyz.endpoints.huggingface.cloud/v1/chat/completions"
+        );
+        assert_eq!(
+            chat_completions_url("http://localhost:8080/v1"),
+            "http://localhost:8080/v1/chat/completions"
+        );
+        assert_eq!(chat_completions_url(DEFAULT_BASE_URL), DEFAULT_BASE_URL);
+    }
+
+    #[test]
+    fn test_prompt_tool_response() {
+        let response = prompt_tool_response(
+            "I will search.\n<tool_call>\n{\"name\": \"duckduckgo_search\", \"arguments\": {\"query\": \"rust\"}}\n</tool_call>"
+                .to_string(),
+        );
+        let tool_calls = response.get_tools_used().unwrap();
+        assert_eq!(tool_calls[0].function.name, "duckduckgo_search");
+        assert_eq!(tool_calls[0].function.arguments["query"], "rust");
+
+        let response = prompt_tool_response("Paris".to_string());
+        assert!(response.get_tools_used().unwrap().is_empty());
+        assert_eq!(response.get_response().unwrap(), "Paris");
+    }
+}
@@ -0,0 +1,321 @@
+//! Models that replay canned responses, to test agents without network calls.
+//!
+//! A [`MockModel`] returns the responses of a fixture in order, one per model call. A [`RecordingModel`] wraps a
+//! real model and records its responses, so they can be saved as a fixture and replayed later.
+//!
+//! Fixtures are YAML or JSON files, chosen by their extension:
+//!
+//! ```yaml
+//! responses:
+//!   - tool_calls:
+//!       - function:
+//!           name: duckduckgo_search
+//!           arguments: { query: "capital of France" }
+//!   - content: "The capital of France is Paris."
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::{Model, ModelResponse, ModelSampling},
+        openai::{Status, ToolCall},
+        tokenizer::TokenCounter,
+        types::Message,
+    },
+    tools::tool_traits::ToolInfo,
+};
+
+/// A response of the model: its content and the tools it called.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockResponse {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl MockResponse {
+    pub fn text(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+        }
+    }
+
+    pub fn tool_call(name: &str, arguments: serde_json::Value) -> Self {
+        Self {
+            content: String::new(),
+            tool_calls: vec![ToolCall {
+                id: Some(format!("call_{}", nanoid::nanoid!())),
+                call_type: Some("function".to_string()),
+                function: crate::models::openai::FunctionCall {
+                    name: name.to_string(),
+                    arguments,
+                },
+            }],
+        }
+    }
+}
+
+impl ModelResponse for MockResponse {
+    fn get_response(&self) -> Result<String, AgentError> {
+        Ok(self.content.clone())
+    }
+
+    fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError> {
+        Ok(self.tool_calls.clone())
+    }
+}
+
+/// The responses of a fixture file, in the order of the model calls.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    pub responses: Vec<MockResponse>,
+}
+
+impl Fixture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fixture: {:?}", path))?;
+        if is_json(path) {
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse fixture: {:?}", path))
+        } else {
+            serde_yaml::from_str(&content).with_context(|| format!("Failed to parse fixture: {:?}", path))
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            serde_yaml::to_string(self)?
+        };
+        std::fs::write(path, content).with_context(|| format!("Failed to write fixture: {:?}", path))
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "json")
+}
+
+/// A model that returns canned responses, one per call.
+#[derive(Debug, Default)]
+pub struct MockModel {
+    responses: Vec<MockResponse>,
+    next: AtomicUsize,
+    requests: Mutex<Vec<Vec<Message>>>,
+}
+
+impl MockModel {
+    pub fn new(responses: Vec<MockResponse>) -> Self {
+        Self {
+            responses,
+            next: AtomicUsize::new(0),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn from_fixture(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Fixture::load(path)?.responses))
+    }
+
+    /// The messages the model was called with, one entry per call.
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next_response(&self, messages: Vec<Message>) -> Result<MockResponse, AgentError> {
+        self.requests.lock().unwrap().push(messages);
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        self.responses.get(index).cloned().ok_or_else(|| {
+            AgentError::Generation(format!(
+                "MockModel has no response for call {} ({} responses)",
+                index + 1,
+                self.responses.len()
+            ))
+        })
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Model for MockModel {
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        _tools: Vec<ToolInfo>,
+        _max_tokens: Option<usize>,
+        _args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let messages = [history.unwrap_or_default(), input_messages].concat();
+        Ok(Box::new(self.next_response(messages)?))
+    }
+
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        _tools: Vec<ToolInfo>,
+        _max_tokens: Option<usize>,
+        _args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let messages = [history.unwrap_or_default(), input_messages].concat();
+        let response = self.next_response(messages)?;
+
+        // Stream the content word by word, like a real model.
+        for (i, token) in response.content.split_inclusive(' ').enumerate() {
+            let status = if i == 0 {
+                Status::FirstContent(token.to_string())
+            } else {
+                Status::Content(token.to_string())
+            };
+            let _ = tx.send(status);
+        }
+        for tool_call in &response.tool_calls {
+            let _ = tx.send(Status::ToolCallStart(tool_call.function.name.clone()));
+            let _ = tx.send(Status::ToolCallContent(tool_call.function.arguments.to_string()));
+        }
+        Ok(Box::new(response))
+    }
+}
+
+/// Wraps a model and records its responses, to save them as a fixture for a [`MockModel`].
+#[derive(Debug)]
+pub struct RecordingModel<M: Model> {
+    model: M,
+    responses: Mutex<Vec<MockResponse>>,
+}
+
+impl<M: Model> RecordingModel<M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            responses: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn fixture(&self) -> Fixture {
+        Fixture {
+            responses: self.responses.lock().unwrap().clone(),
+        }
+    }
+
+    /// Save the recorded responses, as YAML or JSON depending on the extension of `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fixture().save(path)
+    }
+
+    fn record(&self, response: &dyn ModelResponse) -> Result<(), AgentError> {
+        self.responses.lock().unwrap().push(MockResponse {
+            content: response.get_response()?,
+            tool_calls: response.get_tools_used()?,
+        });
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<M: Model> Model for RecordingModel<M> {
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let response = self
+            .model
+            .run(input_messages, history, tools, max_tokens, args)
+            .await?;
+        self.record(response.as_ref())?;
+        Ok(response)
+    }
+
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        let response = self
+            .model
+            .run_stream(input_messages, history, tools, max_tokens, args, tx)
+            .await?;
+        self.record(response.as_ref())?;
+        Ok(response)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.model.context_window()
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        self.model.max_output_tokens()
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        self.model.token_counter()
+    }
+
+    fn supports_native_tools(&self) -> bool {
+        self.model.supports_native_tools()
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        self.model.sampling()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, FunctionCallingAgentBuilder};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_mock_model_replays_fixture() {
+        let path = std::env::temp_dir().join(format!("lumo-fixture-{}.yaml", nanoid::nanoid!()));
+        let recording = RecordingModel::new(MockModel::new(vec![
+            MockResponse::tool_call("duckduckgo_search", json!({"query": "capital of France"})),
+            MockResponse::text("Paris"),
+        ]));
+        for _ in 0..2 {
+            recording.run(vec![], None, vec![], None, None).await.unwrap();
+        }
+        recording.save(&path).unwrap();
+
+        let model = MockModel::from_fixture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let response = model.run(vec![], None, vec![], None, None).await.unwrap();
+        let tool_calls = response.get_tools_used().unwrap();
+        assert_eq!(tool_calls[0].function.arguments, json!({"query": "capital of France"}));
+        let mut agent = FunctionCallingAgentBuilder::new(model)
+            .with_max_steps(Some(2))
+            .build()
+            .unwrap();
+        let answer = agent.run("What is the capital of France?", true).await.unwrap();
+        assert_eq!(answer, "Paris");
+
+        // The fixture has no responses left, so the next run fails instead of calling a real model.
+        assert!(agent.run("What is the capital of Spain?", true).await.is_err());
+    }
+}
@@ -1,5 +1,15 @@
+pub mod batching;
+pub mod cache;
+pub mod cancellation;
 pub mod gemini;
+pub mod huggingface;
+pub mod mock;
 pub mod model_traits;
 pub mod ollama;
 pub mod openai;
+pub mod partial_json;
+pub mod providers;
+pub mod racing;
+pub mod reasoning;
+pub mod tokenizer;
 pub mod types;
@@ -4,20 +4,40 @@ use crate::{
     errors::AgentError,
     models::{
         openai::{Status, ToolCall},
+        tokenizer::{HeuristicCounter, TokenCounter},
         types::Message,
     },
     tools::tool_traits::ToolInfo,
 };
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 
+/// The settings the responses of a model are sampled with, recorded in the manifests of reproducible runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelSampling {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// The seed sent to the provider, for the providers that sample deterministically with one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
 pub trait ModelResponse: Send + Sync {
     fn get_response(&self) -> Result<String, AgentError>;
     fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError>;
+
+    /// The reasoning of a reasoning model, kept out of `get_response`.
+    fn get_reasoning(&self) -> Option<String> {
+        None
+    }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait Model: Send + Sync + 'static {
     async fn run(
         &self,
@@ -37,4 +57,29 @@ pub trait Model: Send + Sync + 'static {
         args: Option<HashMap<String, Vec<String>>>,
         tx: broadcast::Sender<Status>,
     ) -> Result<Box<dyn ModelResponse>, AgentError>;
+
+    /// The number of tokens the model accepts, prompt and response included, if known.
+    fn context_window(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of tokens reserved for the response when `max_tokens` is not given.
+    fn max_output_tokens(&self) -> usize {
+        4500
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        Box::new(HeuristicCounter)
+    }
+
+    /// Whether the model takes tools in the `tools` field of the request. Agents in `ToolCallingMode::Auto` describe
+    /// the tools in the prompt and parse the tool calls from the text of the response for models that do not.
+    fn supports_native_tools(&self) -> bool {
+        true
+    }
+
+    /// The model id, temperature and seed of the requests, when the model knows them.
+    fn sampling(&self) -> ModelSampling {
+        ModelSampling::default()
+    }
 }
@@ -1,22 +1,31 @@
 use std::collections::HashMap;
 
-use opentelemetry::{
-    global,
-    trace::{Span, Tracer},
-    Context, KeyValue,
-};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::sync::broadcast;
 
-use crate::{errors::AgentError, models::openai::Status, tools::ToolInfo};
+use crate::{
+    errors::AgentError,
+    models::openai::Status,
+    telemetry::{
+        otel::{
+            global,
+            trace::{Span, Tracer},
+            Context, KeyValue,
+        },
+        RunMetadata,
+    },
+    tools::ToolInfo,
+};
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::Client;
 
 use super::{
-    model_traits::{Model, ModelResponse},
+    model_traits::{Model, ModelResponse, ModelSampling},
     openai::{FunctionCall, ToolCall},
+    reasoning::{merge_reasoning, split_reasoning},
     types::{Message, MessageRole},
 };
 
@@ -31,6 +40,9 @@ pub struct AssistantMessage {
     pub content: Option<String>,
     pub tool_calls: Option<Vec<OllamaToolCall>>,
     pub refusal: Option<String>,
+    /// The reasoning of a thinking model, when the request asks Ollama to separate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -59,7 +71,12 @@ pub struct OllamaMessage {
 
 impl ModelResponse for OllamaResponse {
     fn get_response(&self) -> Result<String, AgentError> {
-        Ok(self.message.content.clone().unwrap_or_default())
+        Ok(split_reasoning(self.message.content.as_deref().unwrap_or_default()).1)
+    }
+
+    fn get_reasoning(&self) -> Option<String> {
+        let inline = split_reasoning(self.message.content.as_deref().unwrap_or_default()).0;
+        merge_reasoning(self.message.thinking.as_deref(), inline)
     }
 
     fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError> {
@@ -81,6 +98,86 @@ impl ModelResponse for OllamaResponse {
     }
 }
 
+/// A model of the Ollama server, as listed by `/api/tags`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OllamaModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<OllamaModelDetails>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OllamaModelDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaTags {
+    #[serde(default)]
+    models: Vec<OllamaModelInfo>,
+}
+
+/// A progress event of `/api/pull`, e.g. `pulling manifest` or the download of a layer.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PullProgress {
+    /// The downloaded percentage of the current layer.
+    pub fn percent(&self) -> Option<f64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => {
+                Some(completed as f64 * 100.0 / total as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether two model names are the same model. Ollama adds the `latest` tag to names without a tag.
+fn same_model(a: &str, b: &str) -> bool {
+    let with_tag = |name: &str| {
+        if name.contains(':') {
+            name.to_string()
+        } else {
+            format!("{}:latest", name)
+        }
+    };
+    with_tag(a) == with_tag(b)
+}
+
+/// Parse the complete lines of the newline delimited JSON in `buffer`, leaving the incomplete last line in it.
+fn take_progress_lines(buffer: &mut String) -> Vec<PullProgress> {
+    let Some(end) = buffer.rfind('\n') else {
+        return vec![];
+    };
+    let lines = buffer.drain(..=end).collect::<String>();
+    lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct OllamaModel {
     pub model_id: String,
@@ -90,6 +187,143 @@ pub struct OllamaModel {
     pub ctx_length: usize,
     pub max_tokens: usize,
     pub native_tools: bool,
+    /// How long Ollama keeps the model loaded after a request, e.g. `30m`, or `-1` to keep it loaded.
+    pub keep_alive: Option<String>,
+    /// Pull the model when it is not available in the Ollama server.
+    pub auto_pull: bool,
+    /// Ask a thinking model to return its reasoning in the `thinking` field rather than in the content.
+    pub think: bool,
+    /// The seed of the sampling, so that the same request gets the same response.
+    pub seed: Option<u64>,
+}
+
+impl OllamaModel {
+    /// The models available in the Ollama server.
+    pub async fn list_models(&self) -> Result<Vec<OllamaModelInfo>, AgentError> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.url))
+            .send()
+            .await
+            .map_err(|e| {
+                AgentError::Generation(format!(
+                    "Failed to connect to Ollama at {}: {}. Is Ollama running?",
+                    self.url, e
+                ))
+            })?;
+        if !response.status().is_success() {
+            return Err(AgentError::Generation(format!(
+                "Failed to list the Ollama models: HTTP {}",
+                response.status()
+            )));
+        }
+        let tags = response.json::<OllamaTags>().await.map_err(|e| {
+            AgentError::Generation(format!("Failed to parse the Ollama models: {}", e))
+        })?;
+        Ok(tags.models)
+    }
+
+    /// Whether the model is available in the Ollama server.
+    pub async fn is_available(&self) -> Result<bool, AgentError> {
+        Ok(self
+            .list_models()
+            .await?
+            .iter()
+            .any(|model| same_model(&model.name, &self.model_id)))
+    }
+
+    /// Pull the model into the Ollama server, calling `on_progress` with the progress events of the download.
+    pub async fn pull(&self, mut on_progress: impl FnMut(&PullProgress) + Send) -> Result<(), AgentError> {
+        let response = self
+            .client
+            .post(format!("{}/api/pull", self.url))
+            .json(&json!({ "model": self.model_id, "stream": true }))
+            .send()
+            .await
+            .map_err(|e| AgentError::Generation(format!("Failed to pull {}: {}", self.model_id, e)))?;
+        if !response.status().is_success() {
+            let error_message = response.text().await.unwrap_or_default();
+            return Err(AgentError::Generation(format!(
+                "Failed to pull {}: {}",
+                self.model_id, error_message
+            )));
+        }
+        let mut buffer = String::new();
+        let mut success = false;
+        let mut chunks = response.bytes_stream();
+        loop {
+            let chunk = chunks
+                .next()
+                .await
+                .transpose()
+                .map_err(|e| AgentError::Generation(format!("Failed to pull {}: {}", self.model_id, e)))?;
+            let finished = chunk.is_none();
+            match chunk {
+                Some(chunk) => buffer.push_str(&String::from_utf8_lossy(&chunk)),
+                None => buffer.push('\n'),
+            }
+            for progress in take_progress_lines(&mut buffer) {
+                if let Some(error) = &progress.error {
+                    return Err(AgentError::Generation(format!(
+                        "Failed to pull {}: {}",
+                        self.model_id, error
+                    )));
+                }
+                success |= progress.status == "success";
+                on_progress(&progress);
+            }
+            if finished {
+                break;
+            }
+        }
+        if success {
+            Ok(())
+        } else {
+            Err(AgentError::Generation(format!(
+                "The pull of {} ended before it finished",
+                self.model_id
+            )))
+        }
+    }
+
+    /// Check that the model is available, and pull it if it is missing and `auto_pull` is set.
+    pub async fn ensure_model(&self, on_progress: impl FnMut(&PullProgress) + Send) -> Result<(), AgentError> {
+        if self.is_available().await? {
+            return Ok(());
+        }
+        if self.auto_pull {
+            return self.pull(on_progress).await;
+        }
+        Err(self.missing_model_error().await)
+    }
+
+    async fn missing_model_error(&self) -> AgentError {
+        let available = match self.list_models().await {
+            Ok(models) if !models.is_empty() => format!(
+                " Available models: {}.",
+                models
+                    .iter()
+                    .map(|model| model.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => String::new(),
+        };
+        AgentError::Generation(format!(
+            "The model {} is not available in Ollama at {}. Pull it with `ollama pull {}`.{}",
+            self.model_id, self.url, self.model_id, available
+        ))
+    }
+
+    async fn post_chat(&self, body: &serde_json::Value) -> Result<reqwest::Response, AgentError> {
+        self.client
+            .post(format!("{}/api/chat", self.url))
+            .header("Content-Type", "application/json")
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| AgentError::Generation(format!("Failed to get response from Ollama: {}", e)))
+    }
 }
 
 #[derive(Default)]
@@ -101,8 +335,15 @@ pub struct OllamaModelBuilder {
     ctx_length: Option<usize>,
     max_tokens: Option<usize>,
     native_tools: Option<bool>,
+    keep_alive: Option<String>,
+    auto_pull: bool,
+    think: bool,
+    seed: Option<u64>,
 }
 
+/// Keep the model loaded between the steps of a run, which are often further apart than the 5 minutes of Ollama.
+const DEFAULT_KEEP_ALIVE: &str = "30m";
+
 impl OllamaModelBuilder {
     pub fn new() -> Self {
         Self {
@@ -113,6 +354,10 @@ impl OllamaModelBuilder {
             ctx_length: None,
             max_tokens: None,
             native_tools: None,
+            keep_alive: Some(DEFAULT_KEEP_ALIVE.to_string()),
+            auto_pull: false,
+            think: false,
+            seed: None,
         }
     }
 
@@ -156,6 +401,32 @@ impl OllamaModelBuilder {
         self
     }
 
+    /// How long Ollama keeps the model loaded after a request, e.g. `10m` or `-1` to keep it loaded. `None` uses
+    /// the default of the server. Defaults to 30 minutes.
+    pub fn with_keep_alive(mut self, keep_alive: Option<&str>) -> Self {
+        self.keep_alive = keep_alive.map(str::to_string);
+        self
+    }
+
+    /// Pull the model when it is not available in the Ollama server, instead of failing.
+    pub fn with_auto_pull(mut self, auto_pull: bool) -> Self {
+        self.auto_pull = auto_pull;
+        self
+    }
+
+    /// Ask a thinking model like `deepseek-r1` or `qwen3` to return its reasoning apart from the answer. Only
+    /// thinking models accept it. The reasoning inlined in `<think>` tags is separated either way.
+    pub fn with_think(mut self, think: bool) -> Self {
+        self.think = think;
+        self
+    }
+
+    /// The seed of the sampling, so that the same request gets the same response from the same model.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
     pub fn build(self) -> OllamaModel {
         OllamaModel {
             model_id: self.model_id,
@@ -165,12 +436,37 @@ impl OllamaModelBuilder {
             ctx_length: self.ctx_length.unwrap_or(2048),
             max_tokens: self.max_tokens.unwrap_or(1500),
             native_tools: self.native_tools.unwrap_or(false),
+            keep_alive: self.keep_alive,
+            auto_pull: self.auto_pull,
+            think: self.think,
+            seed: self.seed,
         }
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Model for OllamaModel {
+    fn context_window(&self) -> Option<usize> {
+        Some(self.ctx_length)
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    fn supports_native_tools(&self) -> bool {
+        self.native_tools
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        ModelSampling {
+            model_id: Some(self.model_id.clone()),
+            temperature: Some(self.temperature),
+            seed: self.seed,
+        }
+    }
+
     async fn run(
         &self,
         messages: Vec<Message>,
@@ -216,13 +512,23 @@ impl Model for OllamaModel {
             }),
             "max_tokens": max_tokens.unwrap_or(self.max_tokens),
         });
+        if let Some(keep_alive) = &self.keep_alive {
+            body["keep_alive"] = json!(keep_alive);
+        }
+        if let Some(seed) = self.seed {
+            body["options"]["seed"] = json!(seed);
+        }
+        if self.think {
+            body["think"] = json!(true);
+        }
 
         let parent_cx = Context::current();
         let tracer = global::tracer("lumo");
         let mut span = tracer
             .span_builder("OllamaModel::run")
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .start_with_context(&tracer, &parent_cx);
+        span.set_attributes(RunMetadata::current_attributes());
         span.set_attributes(vec![
             KeyValue::new("input.value", serde_json::to_string(&messages).unwrap()),
             KeyValue::new("llm.model_name", self.model_id.clone()),
@@ -248,16 +554,22 @@ impl Model for OllamaModel {
             ));
         }
 
-        let response = self
-            .client
-            .post(format!("{}/api/chat", self.url))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| {
-                AgentError::Generation(format!("Failed to get response from Ollama: {}", e))
-            })?;
+        let mut response = self.post_chat(&body).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            if !self.auto_pull {
+                return Err(self.missing_model_error().await);
+            }
+            log::info!("Pulling {} from Ollama", self.model_id);
+            let mut last_status = String::new();
+            self.pull(|progress| {
+                if progress.status != last_status {
+                    log::info!("Pulling {}: {}", self.model_id, progress.status);
+                    last_status = progress.status.clone();
+                }
+            })
+            .await?;
+            response = self.post_chat(&body).await?;
+        }
         let status = response.status();
         if status.is_client_error() {
             let error_message = response.text().await.unwrap_or_default();
@@ -273,7 +585,7 @@ impl Model for OllamaModel {
             "output.value",
             serde_json::to_string_pretty(&output).unwrap(),
         ));
-        span.end_with_timestamp(std::time::SystemTime::now());
+        span.end_with_timestamp(crate::runtime::SystemTime::now());
         Ok(Box::new(output))
     }
 
@@ -290,3 +602,44 @@ impl Model for OllamaModel {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_model() {
+        assert!(same_model("qwen2.5:latest", "qwen2.5"));
+        assert!(same_model("qwen2.5:7b", "qwen2.5:7b"));
+        assert!(!same_model("qwen2.5:7b", "qwen2.5"));
+    }
+
+    #[test]
+    fn test_take_progress_lines() {
+        let mut buffer = concat!(
+            "{\"status\":\"pulling manifest\"}\n",
+            "{\"status\":\"pulling 6a0746a1ec1a\",\"total\":200,\"completed\":50}\n",
+            "{\"status\":\"succ"
+        )
+        .to_string();
+        let progress = take_progress_lines(&mut buffer);
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0].status, "pulling manifest");
+        assert_eq!(progress[1].percent(), Some(25.0));
+        assert_eq!(buffer, "{\"status\":\"succ");
+
+        buffer.push_str("ess\"}\n");
+        assert_eq!(take_progress_lines(&mut buffer)[0].status, "success");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_reasoning() {
+        let response: OllamaResponse = serde_json::from_str(
+            r#"{"message": {"role": "assistant", "content": "Hi!", "thinking": "A greeting."}}"#,
+        )
+        .unwrap();
+        assert_eq!(response.get_response().unwrap(), "Hi!");
+        assert_eq!(response.get_reasoning().as_deref(), Some("A greeting."));
+    }
+}
@@ -1,22 +1,35 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
+    agent::Step,
     errors::AgentError,
     models::{
-        model_traits::{Model, ModelResponse},
+        batching::{TokenBatcher, TokenBatching},
+        model_traits::{Model, ModelResponse, ModelSampling},
+        providers::{ProviderPreset, ProviderQuirks},
+        reasoning::{merge_reasoning, split_reasoning, ContentChunk, ThinkTagFilter},
+        tokenizer::{TiktokenCounter, TokenCounter},
         types::{Message, MessageRole},
     },
+    runtime::{self, Instant},
+    secrets::SecretKey,
+    telemetry::{
+        otel::{
+            global,
+            trace::{Span, Tracer},
+            Context, KeyValue,
+        },
+        RunMetadata,
+    },
     tools::tool_traits::ToolInfo,
 };
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::StreamExt;
 use nanoid::nanoid;
-use opentelemetry::{
-    global,
-    trace::{Span, Tracer},
-    Context, KeyValue,
-};
 use reqwest::Client;
 use reqwest_eventsource::{Event, EventSource, RequestBuilderExt};
 use serde::{Deserialize, Serialize};
@@ -30,7 +43,84 @@ pub enum Status {
     Content(String),
     ToolCallStart(String),
     ToolCallContent(String),
+    /// The code the code agent is about to execute.
+    CodeExecutionStart(String),
+    /// The observation (or error) produced by executing the code.
+    CodeExecutionEnd(String),
+    /// A tool call the agent starts. It ends with `ToolCallResult` or `ToolCallFailed`.
+    ToolCallStarted {
+        id: Option<String>,
+        name: String,
+        arguments: Value,
+    },
+    /// A tool call that ran, with the start of its observation. `success` is false when the tool returned an error.
+    ToolCallResult {
+        id: Option<String>,
+        name: String,
+        observation: String,
+        latency_ms: u64,
+        success: bool,
+    },
+    /// A tool call that did not run its tool: its arguments are invalid, it uses the result of a failed call, or its
+    /// tool is unavailable.
+    ToolCallFailed {
+        id: Option<String>,
+        name: String,
+        error: String,
+    },
+    /// A question to the user. The run waits for the answer.
+    ClarificationRequired(String),
+    /// A piece of the reasoning of a reasoning model, streamed apart from the content.
+    Reasoning(String),
+    /// A step the agent finished. Only sent by `Agent::run_with_status`: the steps of `stream_run` are the items of
+    /// its stream.
+    Step(Box<Step>),
+    /// A status of a managed agent run by a tool call of the agent, tagged with the name of the managed agent.
+    ManagedAgent {
+        agent: String,
+        status: Box<Status>,
+    },
     Error(String),
+    /// The run was cancelled, its pending model request is aborted.
+    Cancelled,
+}
+
+/// The characters of the observation sent with [`Status::ToolCallResult`].
+pub const TOOL_RESULT_PREVIEW_CHARS: usize = 1_000;
+
+impl Status {
+    pub fn tool_call_started(tool_call: &ToolCall) -> Self {
+        Status::ToolCallStarted {
+            id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            arguments: tool_call.function.arguments.clone(),
+        }
+    }
+
+    pub fn tool_call_result(tool_call: &ToolCall, observation: &str, latency: Duration, success: bool) -> Self {
+        let mut preview = observation
+            .chars()
+            .take(TOOL_RESULT_PREVIEW_CHARS)
+            .collect::<String>();
+        if preview.len() < observation.len() {
+            preview.push_str("...");
+        }
+        Status::ToolCallResult {
+            id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            observation: preview,
+            latency_ms: latency.as_millis() as u64,
+            success,
+        }
+    }
+
+    pub fn tool_call_failed(tool_call: &ToolCall, error: impl Into<String>) -> Self {
+        Status::ToolCallFailed {
+            id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            error: error.into(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -49,6 +139,13 @@ pub struct AssistantMessage {
     pub content: Option<String>,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub refusal: Option<String>,
+    /// The reasoning of DeepSeek-R1 and other reasoning models served behind the OpenAI API.
+    #[serde(
+        default,
+        alias = "reasoning",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub reasoning_content: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -66,6 +163,8 @@ pub struct DeltaMessage {
     pub role: Option<MessageRole>,
     pub content: Option<String>,
     pub tool_calls: Option<Vec<ToolCallStream>>,
+    #[serde(default, alias = "reasoning")]
+    pub reasoning_content: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -163,7 +262,7 @@ where
 
 impl ModelResponse for OpenAIResponse {
     fn get_response(&self) -> Result<String, AgentError> {
-        Ok(self
+        let content = self
             .choices
             .first()
             .ok_or(AgentError::Generation(
@@ -172,7 +271,8 @@ impl ModelResponse for OpenAIResponse {
             .message
             .content
             .clone()
-            .unwrap_or_default())
+            .unwrap_or_default();
+        Ok(split_reasoning(&content).1)
     }
 
     fn get_tools_used(&self) -> Result<Vec<ToolCall>, AgentError> {
@@ -187,6 +287,108 @@ impl ModelResponse for OpenAIResponse {
             .clone()
             .unwrap_or_default())
     }
+
+    fn get_reasoning(&self) -> Option<String> {
+        let message = &self.choices.first()?.message;
+        let inline = split_reasoning(message.content.as_deref().unwrap_or_default()).0;
+        merge_reasoning(message.reasoning_content.as_deref(), inline)
+    }
+}
+
+/// Constrain the model output to valid tool calls for OpenAI-compatible servers that produce malformed
+/// tool-call JSON (vLLM, llama.cpp, LM Studio, ...).
+///
+/// Instead of sending `tools`, the request carries a JSON schema matching either a call to one of the tools
+/// (`{"name": ..., "arguments": {...}}`) or a plain answer (`{"answer": ...}`), and the output is parsed back
+/// into tool calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallConstraint {
+    /// vLLM's `guided_json` request parameter.
+    GuidedJson,
+    /// `response_format` with a `json_schema`, supported by llama.cpp and most other servers.
+    ResponseFormat,
+}
+
+/// Build the JSON schema of a constrained tool call for the given tools.
+pub fn tool_call_schema(tools: &[ToolInfo]) -> Value {
+    let mut variants = tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "enum": [tool.function.name] },
+                    "arguments": tool.function.parameters,
+                },
+                "required": ["name", "arguments"],
+            })
+        })
+        .collect::<Vec<_>>();
+    variants.push(json!({
+        "type": "object",
+        "properties": {
+            "answer": { "type": "string" },
+        },
+        "required": ["answer"],
+    }));
+    json!({ "anyOf": variants })
+}
+
+fn apply_tool_call_constraint(body: &mut Value, constraint: ToolCallConstraint, tools: &[ToolInfo]) {
+    let schema = tool_call_schema(tools);
+    match constraint {
+        ToolCallConstraint::GuidedJson => body["guided_json"] = schema,
+        ToolCallConstraint::ResponseFormat => {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "tool_call",
+                    "schema": schema,
+                },
+            })
+        }
+    }
+}
+
+/// Parse the output of a constrained request into its content and tool calls.
+///
+/// Output that does not match the constraint is returned as content.
+pub fn parse_constrained_output(output: &str) -> (Option<String>, Option<Vec<ToolCall>>) {
+    match serde_json::from_str::<Value>(output.trim()) {
+        Ok(Value::Object(mut map)) => {
+            if let Some(Value::String(name)) = map.remove("name") {
+                let tool_call = ToolCall {
+                    id: Some(format!("call_{}", nanoid!(16))),
+                    call_type: Some("function".to_string()),
+                    function: FunctionCall {
+                        name,
+                        arguments: map.remove("arguments").unwrap_or(json!({})),
+                    },
+                };
+                (None, Some(vec![tool_call]))
+            } else if let Some(Value::String(answer)) = map.remove("answer") {
+                (Some(answer), None)
+            } else {
+                (Some(output.to_string()), None)
+            }
+        }
+        _ => (Some(output.to_string()), None),
+    }
+}
+
+fn constrained_response(output: &str) -> OpenAIResponse {
+    let (content, tool_calls) = parse_constrained_output(output);
+    OpenAIResponse {
+        choices: vec![Choice {
+            message: AssistantMessage {
+                role: MessageRole::Assistant,
+                content,
+                tool_calls,
+                refusal: None,
+                reasoning_content: None,
+            },
+        }],
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -197,6 +399,21 @@ pub struct OpenAIServerModel {
     pub temperature: f32,
     pub api_key: String,
     pub history: Option<Vec<Message>>,
+    pub tool_call_constraint: Option<ToolCallConstraint>,
+    /// Send the tools in strict mode, so that the arguments the model generates always match their parameters.
+    pub strict_tools: bool,
+    /// The backend rejected the strict tools, they are sent as regular tools from then on.
+    strict_tools_rejected: Arc<AtomicBool>,
+    /// How the streamed tokens are grouped before they are broadcast.
+    pub token_batching: TokenBatching,
+    /// Read the API key from a secret provider on each request instead of using `api_key`.
+    pub secret: Option<SecretKey>,
+    /// How the requests are adapted to the provider, see `ProviderPreset`.
+    pub quirks: ProviderQuirks,
+    /// Whether the server accepts the `tools` field. Agents describe the tools in the prompt when it does not.
+    pub native_tools: bool,
+    /// The seed of the sampling, for the providers that support it, so that the same request gets the same response.
+    pub seed: Option<u64>,
 }
 
 impl OpenAIServerModel {
@@ -220,8 +437,78 @@ impl OpenAIServerModel {
             temperature: temperature.unwrap_or(0.5),
             api_key,
             history,
+            tool_call_constraint: None,
+            strict_tools: false,
+            strict_tools_rejected: Arc::new(AtomicBool::new(false)),
+            token_batching: TokenBatching::default(),
+            secret: None,
+            quirks: ProviderQuirks::default(),
+            native_tools: true,
+            seed: None,
+        }
+    }
+
+    /// The API key, read from the secret provider when the model has a secret.
+    async fn authorization(&self) -> Result<String, AgentError> {
+        let api_key = match &self.secret {
+            Some(secret) => secret
+                .resolve()
+                .await
+                .map_err(|e| AgentError::Generation(format!("{:#}", e)))?,
+            None => self.api_key.clone(),
+        };
+        Ok(format!("Bearer {}", api_key))
+    }
+
+    fn uses_strict_tools(&self) -> bool {
+        self.strict_tools && !self.strict_tools_rejected.load(Ordering::SeqCst)
+    }
+
+    /// The `tools` of the request body, in strict mode when it is on.
+    fn tools_body(&self, tools: &[ToolInfo]) -> Value {
+        if self.uses_strict_tools() {
+            json!(tools.iter().map(ToolInfo::to_strict).collect::<Vec<_>>())
+        } else {
+            json!(tools)
         }
     }
+
+    /// Send the tools as regular tools from now on, after the backend rejected a request with strict tools.
+    fn reject_strict_tools(&self, error: &str) {
+        self.strict_tools_rejected.store(true, Ordering::SeqCst);
+        log::warn!(
+            "{} rejected the strict tool schemas, sending regular tools instead: {}",
+            self.base_url,
+            error
+        );
+    }
+
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, AgentError> {
+        self.client
+            .post(&self.base_url)
+            .header("Authorization", self.authorization().await?)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| AgentError::Generation(format!("Failed to get response from OpenAI: {}", e)))
+    }
+
+    async fn event_source(&self, body: &Value) -> Result<EventSource, AgentError> {
+        self.client
+            .post(&self.base_url)
+            .header("Authorization", self.authorization().await?)
+            .header("Accept", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .json(body)
+            .eventsource()
+            .map_err(|e| AgentError::Generation(format!("Failed to create event source: {}", e)))
+    }
+}
+
+/// Whether the status of a response means the backend rejected the request, e.g. the `strict` field of its tools.
+fn is_rejection(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::BAD_REQUEST || status == reqwest::StatusCode::UNPROCESSABLE_ENTITY
 }
 
 pub struct OpenAIServerModelBuilder {
@@ -230,6 +517,13 @@ pub struct OpenAIServerModelBuilder {
     temperature: Option<f32>,
     api_key: Option<String>,
     history: Option<Vec<Message>>,
+    tool_call_constraint: Option<ToolCallConstraint>,
+    strict_tools: bool,
+    token_batching: TokenBatching,
+    secret: Option<SecretKey>,
+    provider: Option<ProviderPreset>,
+    native_tools: bool,
+    seed: Option<u64>,
 }
 
 impl OpenAIServerModelBuilder {
@@ -240,6 +534,13 @@ impl OpenAIServerModelBuilder {
             temperature: None,
             api_key: None,
             history: None,
+            tool_call_constraint: None,
+            strict_tools: false,
+            token_batching: TokenBatching::default(),
+            secret: None,
+            provider: None,
+            native_tools: true,
+            seed: None,
         }
     }
     pub fn with_base_url(mut self, base_url: Option<&str>) -> Self {
@@ -258,23 +559,110 @@ impl OpenAIServerModelBuilder {
         self.api_key = api_key.map(|s| s.to_string());
         self
     }
+    /// Read the API key from a secret provider on each request, so that a rotated key is used at once. Replaces
+    /// `with_api_key`.
+    pub fn with_secret(mut self, secret: SecretKey) -> Self {
+        self.secret = Some(secret);
+        self
+    }
     pub fn with_history(mut self, history: Option<Vec<Message>>) -> Self {
         self.history = history;
         self
     }
+    /// Constrain tool calls with a JSON schema, for local servers that produce malformed tool-call JSON.
+    pub fn with_tool_call_constraint(
+        mut self,
+        tool_call_constraint: Option<ToolCallConstraint>,
+    ) -> Self {
+        self.tool_call_constraint = tool_call_constraint;
+        self
+    }
+    /// Send the tools with strict-mode schemas (OpenAI structured outputs), so that the arguments always match the
+    /// parameters. Tools whose parameters can not be made strict are sent as they are. When the backend rejects
+    /// strict tools, the request is sent again without them, and so are the next ones.
+    pub fn with_strict_tools(mut self, strict_tools: bool) -> Self {
+        self.strict_tools = strict_tools;
+        self
+    }
+    /// Broadcast the streamed tokens in batches, to send fewer events.
+    pub fn with_token_batching(mut self, token_batching: TokenBatching) -> Self {
+        self.token_batching = token_batching;
+        self
+    }
+    /// Use a provider by name: its base URL unless one is set, its API key from the environment unless one is set,
+    /// and its quirks.
+    pub fn with_provider(mut self, provider: ProviderPreset) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+    /// Whether the server accepts the `tools` field, on by default. Turn it off for servers without tool calling,
+    /// e.g. llama.cpp without `--jinja`, so that agents describe the tools in the prompt instead.
+    pub fn with_native_tools(mut self, native_tools: bool) -> Self {
+        self.native_tools = native_tools;
+        self
+    }
+    /// The `seed` of the requests, so that the same request gets the same response from the providers that support
+    /// it. OpenAI samples deterministically on a best-effort basis.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
     pub fn build(self) -> Result<OpenAIServerModel> {
-        Ok(OpenAIServerModel::new(
-            self.base_url.as_deref(),
+        let base_url = self
+            .base_url
+            .or_else(|| self.provider.map(|provider| provider.base_url().to_string()));
+        // The key of a secret is read on each request, `OPENAI_API_KEY` is not needed.
+        let api_key = match (&self.secret, self.api_key, self.provider) {
+            (Some(_), _, _) => Some(String::new()),
+            (None, Some(api_key), _) => Some(api_key),
+            (None, None, Some(provider)) => Some(
+                std::env::var(provider.api_key_env())
+                    .map_err(|_| anyhow::anyhow!("{} is not set", provider.api_key_env()))?,
+            ),
+            (None, None, None) => None,
+        };
+        let provider = self
+            .provider
+            .or_else(|| base_url.as_deref().and_then(ProviderPreset::from_base_url));
+        let mut model = OpenAIServerModel::new(
+            base_url.as_deref(),
             self.model_id.as_deref(),
             self.temperature,
-            self.api_key,
+            api_key,
             self.history,
-        ))
+        );
+        model.tool_call_constraint = self.tool_call_constraint;
+        model.strict_tools = self.strict_tools;
+        model.token_batching = self.token_batching;
+        model.secret = self.secret;
+        model.native_tools = self.native_tools;
+        model.seed = self.seed;
+        if let Some(provider) = provider {
+            model.quirks = provider.quirks(&model.model_id);
+        }
+        Ok(model)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Model for OpenAIServerModel {
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        Box::new(TiktokenCounter::for_model(&self.model_id))
+    }
+
+    fn supports_native_tools(&self) -> bool {
+        self.native_tools
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        ModelSampling {
+            model_id: Some(self.model_id.clone()),
+            temperature: Some(self.temperature),
+            seed: self.seed,
+        }
+    }
+
     async fn run(
         &self,
         messages: Vec<Message>,
@@ -295,13 +683,17 @@ impl Model for OpenAIServerModel {
             "temperature": self.temperature,
             "max_tokens": max_tokens,
         });
+        if let Some(seed) = self.seed {
+            body["seed"] = json!(seed);
+        }
 
         let parent_cx = Context::current();
         let tracer = global::tracer("lumo");
         let mut span = tracer
             .span_builder("OpenAIServerModel::run")
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .start_with_context(&tracer, &parent_cx);
+        span.set_attributes(RunMetadata::current_attributes());
         span.set_attributes(vec![
             KeyValue::new("input.value", serde_json::to_string(&messages).unwrap()),
             KeyValue::new("llm.model_name", self.model_id.clone()),
@@ -327,34 +719,41 @@ impl Model for OpenAIServerModel {
             }
         }
 
-        if !tools_to_call_from.is_empty() {
-            body["tools"] = json!(tools_to_call_from);
+        let constraint = self
+            .tool_call_constraint
+            .filter(|_| !tools_to_call_from.is_empty());
+        if let Some(constraint) = constraint {
+            apply_tool_call_constraint(&mut body, constraint, &tools_to_call_from);
+        } else if !tools_to_call_from.is_empty() {
+            body["tools"] = self.tools_body(&tools_to_call_from);
             // body["tool_choice"] = json!("required");
             span.set_attribute(KeyValue::new(
                 "gen_ai.request.tool_choice",
                 serde_json::to_string(&body["tool_choice"]).unwrap(),
             ));
         }
+        self.quirks.apply(&mut body);
+        let strict = constraint.is_none() && !tools_to_call_from.is_empty() && self.uses_strict_tools();
 
-        let response = self
-            .client
-            .post(&self.base_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| {
-                AgentError::Generation(format!("Failed to get response from OpenAI: {}", e))
-            })?;
+        let mut response = self.send(&body).await?;
+        if strict && is_rejection(response.status()) {
+            self.reject_strict_tools(&response.text().await.unwrap_or_default());
+            body["tools"] = json!(tools_to_call_from);
+            response = self.send(&body).await?;
+        }
 
         match response.status() {
             reqwest::StatusCode::OK => {
-                let response = response.json::<OpenAIResponse>().await.unwrap();
+                let mut response = response.json::<OpenAIResponse>().await.unwrap();
+                if constraint.is_some() {
+                    let output = response.get_response()?;
+                    response = constrained_response(&output);
+                }
                 span.set_attribute(KeyValue::new(
                     "output.value",
                     serde_json::to_string_pretty(&response).unwrap(),
                 ));
-                span.end_with_timestamp(std::time::SystemTime::now());
+                span.end_with_timestamp(crate::runtime::SystemTime::now());
                 Ok(Box::new(response))
             }
             _ => Err(AgentError::Generation(format!(
@@ -387,13 +786,17 @@ impl Model for OpenAIServerModel {
             "max_tokens": max_tokens,
             "stream": true,
         });
+        if let Some(seed) = self.seed {
+            body["seed"] = json!(seed);
+        }
 
         let parent_cx = Context::current();
         let tracer = global::tracer("lumo");
         let mut span = tracer
             .span_builder("OpenAIServerModel::run_stream")
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .start_with_context(&tracer, &parent_cx);
+        span.set_attributes(RunMetadata::current_attributes());
         span.set_attributes(vec![
             KeyValue::new("input.value", serde_json::to_string(&messages).unwrap()),
             KeyValue::new("llm.model_name", self.model_id.clone()),
@@ -403,8 +806,9 @@ impl Model for OpenAIServerModel {
         let tracer = global::tracer("lumo");
         let mut span = tracer
             .span_builder("OpenAIServerModel::run")
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .start_with_context(&tracer, &parent_cx);
+        span.set_attributes(RunMetadata::current_attributes());
         span.set_attributes(vec![
             KeyValue::new("input.value", serde_json::to_string(&messages).unwrap()),
             KeyValue::new("llm.model_name", self.model_id.clone()),
@@ -430,34 +834,55 @@ impl Model for OpenAIServerModel {
             }
         }
 
-        if !tools_to_call_from.is_empty() {
-            body["tools"] = json!(tools_to_call_from);
+        let constraint = self
+            .tool_call_constraint
+            .filter(|_| !tools_to_call_from.is_empty());
+        if let Some(constraint) = constraint {
+            apply_tool_call_constraint(&mut body, constraint, &tools_to_call_from);
+        } else if !tools_to_call_from.is_empty() {
+            body["tools"] = self.tools_body(&tools_to_call_from);
             // body["tool_choice"] = json!("auto");
             span.set_attribute(KeyValue::new(
                 "gen_ai.request.tool_choice",
                 serde_json::to_string(&body["tool_choice"]).unwrap(),
             ));
         }
-
-        let stream = self
-            .client
-            .post(&self.base_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Accept", "text/event-stream")
-            .header("Cache-Control", "no-cache")
-            .header("Connection", "keep-alive")
-            .json(&body)
-            .eventsource()
-            .map_err(|e| AgentError::Generation(format!("Failed to create event source: {}", e)))?;
+        self.quirks.apply(&mut body);
+        let strict = constraint.is_none() && !tools_to_call_from.is_empty() && self.uses_strict_tools();
+
+        let mut stream = self.event_source(&body).await?;
+        if strict {
+            // A rejected request fails when the stream opens, before any content.
+            match stream.next().await {
+                Some(Err(reqwest_eventsource::Error::InvalidStatusCode(status, response)))
+                    if is_rejection(status) =>
+                {
+                    self.reject_strict_tools(&response.text().await.unwrap_or_default());
+                    body["tools"] = json!(tools_to_call_from);
+                    stream = self.event_source(&body).await?;
+                }
+                Some(Err(e)) => {
+                    return Err(AgentError::Generation(format!(
+                        "Failed to get response from OpenAI: {}",
+                        e
+                    )))
+                }
+                _ => {}
+            }
+        }
 
         let (tx_provider, rx_provider) = channel::<OpenAIStreamResponse>(32);
-        tokio::spawn(forward_deserialized_chat_response_stream(
+        // Aborted with the request, which closes the event stream.
+        let _forward = runtime::spawn(forward_deserialized_chat_response_stream(
             stream,
             tx_provider,
         ));
-        let response = process_stream_with_separate_tasks(rx_provider, tx)
+        let response = process_stream_with_separate_tasks(rx_provider, tx, self.token_batching)
             .await
             .map_err(|e| AgentError::Generation(format!("Failed to process stream: {}", e)))?;
+        if constraint.is_some() {
+            return Ok(Box::new(constrained_response(&response.get_response()?)));
+        }
         Ok(response)
     }
 }
@@ -467,7 +892,8 @@ async fn forward_deserialized_chat_response_stream(
     tx: Sender<OpenAIStreamResponse>,
 ) -> anyhow::Result<()> {
     while let Some(event) = stream.next().await {
-        let event = event?;
+        // The errors of the browser's fetch are not `Send`, so only their message is kept.
+        let event = event.map_err(|e| anyhow::anyhow!("{}", e))?;
         if let Event::Message(event) = event {
             let data = serde_json::from_str::<OpenAIStreamResponse>(&event.data);
             match data {
@@ -570,7 +996,7 @@ pub async fn process_stream_with_broadcast(
         tool_calls.push(last);
     }
 
-    println!("Broadcast task completed");
+    log::debug!("Broadcast task completed");
 
     // Close the broadcast channel when stream ends
     drop(tx);
@@ -587,6 +1013,7 @@ pub async fn process_stream_with_broadcast(
                     Some(tool_calls)
                 },
                 refusal: None,
+                reasoning_content: None,
             },
         }],
     });
@@ -602,9 +1029,14 @@ pub async fn process_stream_with_broadcast(
 /// 2. Multiple consumers with different needs
 /// 3. Non-blocking UI updates
 /// 4. Error isolation between accumulation and broadcasting
+///
+/// The content is broadcast in batches when `batching` is enabled. A batch waiting for more tokens is sent once it is
+/// due even when the stream stalls. The tasks are aborted when the returned future is dropped, e.g. when the run is
+/// cancelled.
 pub async fn process_stream_with_separate_tasks(
     mut stream: Receiver<OpenAIStreamResponse>,
     tx: broadcast::Sender<Status>,
+    batching: TokenBatching,
 ) -> Result<Box<dyn ModelResponse>, anyhow::Error> {
     // Channel for communication between tasks
     let (accumulation_tx, mut accumulation_rx) = channel::<OpenAIStreamResponse>(32);
@@ -612,8 +1044,9 @@ pub async fn process_stream_with_separate_tasks(
     let mut first_content = true;
 
     // Spawn accumulation task
-    let accumulation_handle = tokio::spawn(async move {
+    let accumulation_handle = runtime::spawn(async move {
         let mut accumulated_content = String::new();
+        let mut accumulated_reasoning = String::new();
         let mut tool_calls: Vec<ToolCall> = Vec::new();
         let mut current_tool_call: Option<ToolCall> = None;
         let mut current_arguments = String::new();
@@ -623,6 +1056,9 @@ pub async fn process_stream_with_separate_tasks(
             if let Some(content) = &res.choices[0].delta.content {
                 accumulated_content.push_str(content);
             }
+            if let Some(reasoning) = &res.choices[0].delta.reasoning_content {
+                accumulated_reasoning.push_str(reasoning);
+            }
 
             // Process tool calls
             if let Some(tool_calls_delta) = &res.choices[0].delta.tool_calls {
@@ -669,32 +1105,67 @@ pub async fn process_stream_with_separate_tasks(
         }
 
         // Return accumulated data
-        (accumulated_content, tool_calls)
+        (accumulated_content, accumulated_reasoning, tool_calls)
     });
 
     // Spawn broadcasting task
     let tx_clone = tx.clone();
-    let broadcast_handle = tokio::spawn(async move {
-        while let Some(res) = stream.recv().await {
+    let broadcast_handle = runtime::spawn(async move {
+        let mut think_filter = ThinkTagFilter::new();
+        let mut batcher = TokenBatcher::new(batching);
+        let mut broadcast = |chunk: ContentChunk| {
+            let status = match chunk {
+                ContentChunk::Reasoning(reasoning) => Status::Reasoning(reasoning),
+                ContentChunk::Answer(content) if first_content => {
+                    first_content = false;
+                    Status::FirstContent(content)
+                }
+                ContentChunk::Answer(content) => Status::Content(content),
+            };
+            if let Err(e) = tx_clone.send(status) {
+                eprintln!("Failed to broadcast content: {}", e);
+            }
+        };
+        loop {
+            let deadline = batcher.deadline();
+            let until_deadline = deadline
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                .unwrap_or_default();
+            let res = tokio::select! {
+                res = stream.recv() => res,
+                // Send the pending batch when no token comes before it is due
+                _ = runtime::sleep(until_deadline), if deadline.is_some() => {
+                    batcher.flush().into_iter().for_each(&mut broadcast);
+                    continue;
+                }
+            };
+            let Some(res) = res else {
+                break;
+            };
+
             // Forward to accumulation task
             if let Err(e) = accumulation_tx.send(res.clone()).await {
                 eprintln!("Failed to send to accumulation task: {}", e);
                 break;
             }
 
-            // Broadcast content immediately
+            // Broadcast content, with the reasoning apart
+            if let Some(reasoning) = &res.choices[0].delta.reasoning_content {
+                batcher
+                    .push(ContentChunk::Reasoning(reasoning.clone()))
+                    .into_iter()
+                    .for_each(&mut broadcast);
+            }
             if let Some(content) = &res.choices[0].delta.content {
-                if first_content {
-                    if let Err(e) = tx_clone.send(Status::FirstContent(content.clone())) {
-                        eprintln!("Failed to broadcast first content: {}", e);
-                    }
-                    first_content = false;
-                } else if let Err(e) = tx_clone.send(Status::Content(content.clone())) {
-                    eprintln!("Failed to broadcast content: {}", e);
+                for chunk in think_filter.push(content) {
+                    batcher.push(chunk).into_iter().for_each(&mut broadcast);
                 }
             }
-
         }
+        for chunk in think_filter.finish() {
+            batcher.push(chunk).into_iter().for_each(&mut broadcast);
+        }
+        batcher.flush().into_iter().for_each(&mut broadcast);
 
         // Close the accumulation channel
         drop(accumulation_tx);
@@ -705,7 +1176,7 @@ pub async fn process_stream_with_separate_tasks(
         tokio::join!(accumulation_handle, broadcast_handle);
 
     // Handle any errors from the tasks
-    let (accumulated_content, tool_calls) =
+    let (accumulated_content, accumulated_reasoning, tool_calls) =
         accumulation_result.map_err(|e| anyhow::anyhow!("Accumulation task failed: {}", e))?;
 
     broadcast_result.map_err(|e| anyhow::anyhow!("Broadcast task failed: {}", e))?;
@@ -725,6 +1196,7 @@ pub async fn process_stream_with_separate_tasks(
                     Some(tool_calls)
                 },
                 refusal: None,
+                reasoning_content: Some(accumulated_reasoning).filter(|reasoning| !reasoning.is_empty()),
             },
         }],
     });
@@ -873,6 +1345,24 @@ mod tests {
                 Status::ToolCallContent(content) => {
                     println!("Tool call content: {}", content);
                 }
+                Status::CodeExecutionStart(code) => {
+                    println!("Code execution started: {}", code);
+                }
+                Status::CodeExecutionEnd(output) => {
+                    println!("Code execution ended: {}", output);
+                }
+                Status::ClarificationRequired(question) => {
+                    println!("Clarification required: {}", question);
+                }
+                Status::Reasoning(reasoning) => {
+                    println!("Reasoning: {}", reasoning);
+                }
+                Status::Step(_)
+                | Status::ManagedAgent { .. }
+                | Status::ToolCallStarted { .. }
+                | Status::ToolCallResult { .. }
+                | Status::ToolCallFailed { .. }
+                | Status::Cancelled => {}
                 Status::Error(error) => {
                     eprintln!("Error: {}", error);
                 }
@@ -909,7 +1399,9 @@ mod tests {
 
         // Spawn a task to simulate the stream processing with separate tasks
         let separate_tasks_handle =
-            tokio::spawn(async move { process_stream_with_separate_tasks(mock_rx, tx).await });
+            tokio::spawn(async move {
+                process_stream_with_separate_tasks(mock_rx, tx, TokenBatching::default()).await
+            });
 
         // Simulate some stream data
         tokio::spawn(async move {
@@ -921,6 +1413,7 @@ mod tests {
                         role: Some(MessageRole::Assistant),
                         content: Some("Patch embeddings are...".to_string()),
                         tool_calls: None,
+                        reasoning_content: None,
                     },
                 }],
             };
@@ -951,6 +1444,24 @@ mod tests {
                 Status::ToolCallContent(content) => {
                     println!("Tool call content: {}", content);
                 }
+                Status::CodeExecutionStart(code) => {
+                    println!("Code execution started: {}", code);
+                }
+                Status::CodeExecutionEnd(output) => {
+                    println!("Code execution ended: {}", output);
+                }
+                Status::ClarificationRequired(question) => {
+                    println!("Clarification required: {}", question);
+                }
+                Status::Reasoning(reasoning) => {
+                    println!("Reasoning: {}", reasoning);
+                }
+                Status::Step(_)
+                | Status::ManagedAgent { .. }
+                | Status::ToolCallStarted { .. }
+                | Status::ToolCallResult { .. }
+                | Status::ToolCallFailed { .. }
+                | Status::Cancelled => {}
                 Status::Error(error) => {
                     eprintln!("Error: {}", error);
                 }
@@ -967,4 +1478,132 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_constrained_tool_calls() {
+        let tool = DuckDuckGoSearchTool::new().tool_info();
+        let schema = tool_call_schema(&[tool]);
+        assert_eq!(schema["anyOf"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            schema["anyOf"][0]["properties"]["name"]["enum"],
+            json!(["duckduckgo_search"])
+        );
+
+        let (content, tool_calls) = parse_constrained_output(
+            r#"{"name": "duckduckgo_search", "arguments": {"query": "rust"}}"#,
+        );
+        assert!(content.is_none());
+        let tool_calls = tool_calls.unwrap();
+        assert_eq!(tool_calls[0].function.name, "duckduckgo_search");
+        assert_eq!(tool_calls[0].function.arguments, json!({"query": "rust"}));
+
+        let (content, tool_calls) = parse_constrained_output(r#"{"answer": "Paris"}"#);
+        assert_eq!(content.as_deref(), Some("Paris"));
+        assert!(tool_calls.is_none());
+    }
+
+    #[test]
+    fn test_strict_tools() {
+        let model = OpenAIServerModelBuilder::new("gpt-4o-mini")
+            .with_api_key(Some("test"))
+            .with_strict_tools(true)
+            .build()
+            .unwrap();
+        let tools = vec![DuckDuckGoSearchTool::new().tool_info()];
+        let body = model.tools_body(&tools);
+        assert_eq!(body[0]["function"]["strict"], true);
+        assert_eq!(body[0]["function"]["parameters"]["additionalProperties"], false);
+
+        // A clone shares the fallback, so the next requests of the agent send regular tools.
+        model.clone().reject_strict_tools("strict is not supported");
+        let body = model.tools_body(&tools);
+        assert!(body[0]["function"].get("strict").is_none());
+    }
+
+    #[test]
+    fn test_provider_preset() {
+        let model = OpenAIServerModelBuilder::new("deepseek-chat")
+            .with_provider(ProviderPreset::DeepSeek)
+            .with_api_key(Some("test"))
+            .build()
+            .unwrap();
+        assert_eq!(model.base_url, ProviderPreset::DeepSeek.base_url());
+        assert_eq!(model.quirks.max_tokens, Some(8192));
+
+        // The quirks of a provider also apply to its base URL.
+        let model = OpenAIServerModelBuilder::new("grok-4")
+            .with_base_url(Some("https://api.x.ai/v1/chat/completions"))
+            .with_api_key(Some("test"))
+            .build()
+            .unwrap();
+        assert_eq!(model.quirks.tool_choice, Some("auto"));
+        let model = OpenAIServerModelBuilder::new("gpt-4o-mini")
+            .with_api_key(Some("test"))
+            .build()
+            .unwrap();
+        assert_eq!(model.quirks, ProviderQuirks::default());
+    }
+
+    #[test]
+    fn test_reasoning() {
+        let response: OpenAIResponse = serde_json::from_value(json!({
+            "choices": [{"message": {
+                "role": "assistant",
+                "content": "<think>2 + 2 is 4.</think>\n\nThe answer is 4.",
+                "reasoning_content": "The user asks for a sum.",
+            }}]
+        }))
+        .unwrap();
+        assert_eq!(response.get_response().unwrap(), "The answer is 4.");
+        assert_eq!(
+            response.get_reasoning().as_deref(),
+            Some("The user asks for a sum.\n2 + 2 is 4.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_reasoning() {
+        let (stream_tx, stream_rx) = channel(8);
+        let (tx, mut rx) = broadcast::channel(16);
+        for (content, reasoning) in [(None, Some("Adding.")), (Some("4"), None)] {
+            let delta = json!({"role": "assistant", "content": content, "reasoning_content": reasoning});
+            stream_tx
+                .send(serde_json::from_value(json!({"choices": [{"delta": delta}]})).unwrap())
+                .await
+                .unwrap();
+        }
+        drop(stream_tx);
+
+        let response = process_stream_with_separate_tasks(stream_rx, tx, TokenBatching::default())
+            .await
+            .unwrap();
+        assert_eq!(response.get_response().unwrap(), "4");
+        assert_eq!(response.get_reasoning().as_deref(), Some("Adding."));
+        assert!(matches!(rx.recv().await, Ok(Status::Reasoning(reasoning)) if reasoning == "Adding."));
+        assert!(matches!(rx.recv().await, Ok(Status::FirstContent(content)) if content == "4"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_token_batching() {
+        let (stream_tx, stream_rx) = channel(8);
+        let (tx, mut rx) = broadcast::channel(16);
+        for content in ["The", " an", "swer", " is", " 4"] {
+            let delta = json!({"role": "assistant", "content": content});
+            stream_tx
+                .send(serde_json::from_value(json!({"choices": [{"delta": delta}]})).unwrap())
+                .await
+                .unwrap();
+        }
+        drop(stream_tx);
+
+        let batching = TokenBatching::new(5, std::time::Duration::from_secs(60));
+        let response = process_stream_with_separate_tasks(stream_rx, tx, batching)
+            .await
+            .unwrap();
+        assert_eq!(response.get_response().unwrap(), "The answer is 4");
+        assert!(matches!(rx.recv().await, Ok(Status::FirstContent(content)) if content == "The"));
+        assert!(matches!(rx.recv().await, Ok(Status::Content(content)) if content == " answer"));
+        assert!(matches!(rx.recv().await, Ok(Status::Content(content)) if content == " is 4"));
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,541 @@
+//! Parsing of a JSON answer while it is streamed, so structured answers can be rendered before they are complete.
+//!
+//! [`PartialJson`] reads the tokens of an answer and returns what changed in the value parsed so far as a JSON Patch
+//! (RFC 6902). Strings, arrays and objects are read as far as they go; numbers and `true`, `false` and `null` only
+//! once they are complete, so a value never changes type. The first patch of an answer replaces the whole document.
+//! A ```` ```json ```` fence around the answer is skipped, and an answer that does not start as JSON has no patches.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// An operation of a JSON Patch. The path is a JSON Pointer, `""` for the whole document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Replace { path: String, value: Value },
+    Remove { path: String },
+}
+
+/// The value of a JSON answer streamed in tokens.
+#[derive(Debug, Clone, Default)]
+pub struct PartialJson {
+    text: String,
+    value: Option<Value>,
+    /// No more patches: the text is not JSON, or the whole answer is known.
+    closed: bool,
+}
+
+impl PartialJson {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value parsed so far, `None` before the first value or when the text is not JSON.
+    pub fn value(&self) -> Option<&Value> {
+        self.value.as_ref()
+    }
+
+    /// Add the next tokens. Returns the patch from the last value to the new one, empty when it did not change.
+    pub fn push(&mut self, tokens: &str) -> Vec<PatchOperation> {
+        self.text.push_str(tokens);
+        self.update(false)
+    }
+
+    /// Read the end of the text, e.g. a number the answer ends with. Returns the patch of the last changes.
+    pub fn finish(&mut self) -> Vec<PatchOperation> {
+        self.update(true)
+    }
+
+    /// Replace the text with the whole answer, e.g. once the agent returns it. Returns the patch from the last value
+    /// to the value of the answer. The tokens pushed afterwards are ignored.
+    pub fn complete(&mut self, answer: &str) -> Vec<PatchOperation> {
+        self.text = answer.to_string();
+        self.closed = false;
+        let patch = self.update(true);
+        self.closed = true;
+        patch
+    }
+
+    fn update(&mut self, end: bool) -> Vec<PatchOperation> {
+        if self.closed {
+            return vec![];
+        }
+        let value = match parse_partial(&self.text, end) {
+            Ok(Some(value)) => value,
+            Ok(None) => return vec![],
+            Err(Invalid) => {
+                self.closed = true;
+                return vec![];
+            }
+        };
+        let patch = match &self.value {
+            Some(previous) => diff(previous, &value),
+            None => vec![PatchOperation::Replace {
+                path: String::new(),
+                value: value.clone(),
+            }],
+        };
+        self.value = Some(value);
+        patch
+    }
+}
+
+/// The text is not JSON.
+#[derive(Debug)]
+struct Invalid;
+
+/// A value read up to the end of the text.
+enum Parsed {
+    Complete(Value),
+    /// A string, array or object cut by the end of the text.
+    Partial(Value),
+    /// Nothing that can be shown yet, e.g. a number that may go on.
+    Nothing,
+}
+
+/// The value of the text so far. With `end`, the text is complete and so is a number at its end.
+fn parse_partial(text: &str, end: bool) -> Result<Option<Value>, Invalid> {
+    let mut text = text.trim_start();
+    if let Some(fenced) = text.strip_prefix("```") {
+        // The language of the fence, e.g. `json`, is on the first line.
+        match fenced.find('\n') {
+            Some(newline) => text = fenced[newline + 1..].trim_start(),
+            None if "json".starts_with(fenced.trim()) => return Ok(None),
+            None => return Err(Invalid),
+        }
+    } else if "```".starts_with(text) {
+        return Ok(None);
+    }
+    let mut parser = Parser {
+        text,
+        position: 0,
+        end,
+    };
+    match parser.value()? {
+        Parsed::Complete(value) | Parsed::Partial(value) => Ok(Some(value)),
+        Parsed::Nothing => Ok(None),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    end: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Parsed, Invalid> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(Parsed::Nothing),
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(_) => Err(Invalid),
+        }
+    }
+
+    fn object(&mut self) -> Result<Parsed, Invalid> {
+        self.position += 1;
+        let mut object = Map::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Parsed::Partial(Value::Object(object))),
+                Some(b'}') if object.is_empty() => {
+                    self.position += 1;
+                    return Ok(Parsed::Complete(Value::Object(object)));
+                }
+                Some(b'"') => {}
+                Some(_) => return Err(Invalid),
+            }
+            // A key is only added with its value.
+            let key = match self.string()? {
+                Parsed::Complete(Value::String(key)) => key,
+                _ => return Ok(Parsed::Partial(Value::Object(object))),
+            };
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Parsed::Partial(Value::Object(object))),
+                Some(b':') => self.position += 1,
+                Some(_) => return Err(Invalid),
+            }
+            match self.value()? {
+                Parsed::Complete(value) => {
+                    object.insert(key, value);
+                }
+                Parsed::Partial(value) => {
+                    object.insert(key, value);
+                    return Ok(Parsed::Partial(Value::Object(object)));
+                }
+                Parsed::Nothing => return Ok(Parsed::Partial(Value::Object(object))),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Parsed::Partial(Value::Object(object))),
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Parsed::Complete(Value::Object(object)));
+                }
+                Some(_) => return Err(Invalid),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Parsed, Invalid> {
+        self.position += 1;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Parsed::Complete(Value::Array(array)));
+        }
+        loop {
+            match self.value()? {
+                Parsed::Complete(value) => array.push(value),
+                Parsed::Partial(value) => {
+                    array.push(value);
+                    return Ok(Parsed::Partial(Value::Array(array)));
+                }
+                Parsed::Nothing => return Ok(Parsed::Partial(Value::Array(array))),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(Parsed::Partial(Value::Array(array))),
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Parsed::Complete(Value::Array(array)));
+                }
+                Some(_) => return Err(Invalid),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Parsed, Invalid> {
+        let start = self.position;
+        let bytes = self.text.as_bytes();
+        let mut position = start + 1;
+        while position < bytes.len() {
+            match bytes[position] {
+                b'\\' => position += 2,
+                b'"' => {
+                    self.position = position + 1;
+                    return serde_json::from_str(&self.text[start..self.position])
+                        .map(Parsed::Complete)
+                        .map_err(|_| Invalid);
+                }
+                _ => position += 1,
+            }
+        }
+        self.position = self.text.len();
+        // The end of the text may cut an escape sequence, which is left out until it is complete.
+        let mut content = &self.text[start + 1..];
+        for _ in 0..12 {
+            if let Ok(value) = serde_json::from_str::<Value>(&format!("\"{}\"", content)) {
+                return Ok(Parsed::Partial(value));
+            }
+            let Some((last, _)) = content.char_indices().next_back() else {
+                break;
+            };
+            content = &content[..last];
+        }
+        Err(Invalid)
+    }
+
+    fn number(&mut self) -> Result<Parsed, Invalid> {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|byte| matches!(byte, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+        {
+            self.position += 1;
+        }
+        if self.peek().is_none() && !self.end {
+            return Ok(Parsed::Nothing);
+        }
+        serde_json::from_str(&self.text[start..self.position])
+            .map(Parsed::Complete)
+            .map_err(|_| Invalid)
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Parsed, Invalid> {
+        let rest = &self.text[self.position..];
+        if rest.starts_with(literal) {
+            self.position += literal.len();
+            Ok(Parsed::Complete(value))
+        } else if literal.starts_with(rest) {
+            self.position = self.text.len();
+            Ok(Parsed::Nothing)
+        } else {
+            Err(Invalid)
+        }
+    }
+}
+
+/// The JSON Patch that turns `from` into `to`.
+pub fn diff(from: &Value, to: &Value) -> Vec<PatchOperation> {
+    let mut patch = Vec::new();
+    diff_at(from, to, &mut String::new(), &mut patch);
+    patch
+}
+
+fn diff_at(from: &Value, to: &Value, path: &mut String, patch: &mut Vec<PatchOperation>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for key in from.keys().filter(|key| !to.contains_key(*key)) {
+                patch.push(PatchOperation::Remove {
+                    path: format!("{}/{}", path, escape(key)),
+                });
+            }
+            for (key, value) in to {
+                let length = path.len();
+                path.push('/');
+                path.push_str(&escape(key));
+                match from.get(key) {
+                    Some(previous) => diff_at(previous, value, path, patch),
+                    None => patch.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: value.clone(),
+                    }),
+                }
+                path.truncate(length);
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (index, (previous, value)) in from.iter().zip(to).enumerate() {
+                let length = path.len();
+                path.push_str(&format!("/{}", index));
+                diff_at(previous, value, path, patch);
+                path.truncate(length);
+            }
+            for (index, value) in to.iter().enumerate().skip(from.len()) {
+                patch.push(PatchOperation::Add {
+                    path: format!("{}/{}", path, index),
+                    value: value.clone(),
+                });
+            }
+            for index in (to.len()..from.len()).rev() {
+                patch.push(PatchOperation::Remove {
+                    path: format!("{}/{}", path, index),
+                });
+            }
+        }
+        (from, to) if from != to => patch.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: to.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Apply a JSON Patch to a value, e.g. to follow a streamed answer.
+pub fn apply_patch(value: &mut Value, patch: &[PatchOperation]) -> Result<(), String> {
+    for operation in patch {
+        let (path, new_value) = match operation {
+            PatchOperation::Add { path, value } | PatchOperation::Replace { path, value } => {
+                (path, Some(value.clone()))
+            }
+            PatchOperation::Remove { path } => (path, None),
+        };
+        if path.is_empty() {
+            *value = new_value.unwrap_or(Value::Null);
+            continue;
+        }
+        let (parent, key) = path
+            .rsplit_once('/')
+            .ok_or_else(|| format!("Invalid path {}", path))?;
+        let key = unescape(key);
+        let parent = value
+            .pointer_mut(parent)
+            .ok_or_else(|| format!("No value at {}", parent))?;
+        match (parent, operation) {
+            (Value::Object(object), PatchOperation::Remove { .. }) => {
+                object.remove(&key);
+            }
+            (Value::Object(object), _) => {
+                object.insert(key, new_value.unwrap_or_default());
+            }
+            (Value::Array(array), operation) => {
+                let index = match key.as_str() {
+                    "-" => array.len(),
+                    index => index
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid index in {}", path))?,
+                };
+                match operation {
+                    PatchOperation::Add { .. } if index <= array.len() => {
+                        array.insert(index, new_value.unwrap_or_default())
+                    }
+                    PatchOperation::Replace { .. } if index < array.len() => {
+                        array[index] = new_value.unwrap_or_default()
+                    }
+                    PatchOperation::Remove { .. } if index < array.len() => {
+                        array.remove(index);
+                    }
+                    _ => return Err(format!("Index out of bounds in {}", path)),
+                }
+            }
+            _ => return Err(format!("No object or array at {}", path)),
+        }
+    }
+    Ok(())
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(key: &str) -> String {
+    key.replace("~1", "/").replace("~0", "~")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Stream the text a few characters at a time, following the patches.
+    fn stream(text: &str, size: usize) -> (Value, Vec<Vec<PatchOperation>>) {
+        let mut partial = PartialJson::new();
+        let mut document = Value::Null;
+        let mut patches = Vec::new();
+        let chars = text.chars().collect::<Vec<_>>();
+        for chunk in chars.chunks(size) {
+            let patch = partial.push(&chunk.iter().collect::<String>());
+            apply_patch(&mut document, &patch).unwrap();
+            assert_eq!(Some(&document), partial.value().or(Some(&Value::Null)));
+            patches.push(patch);
+        }
+        let patch = partial.finish();
+        apply_patch(&mut document, &patch).unwrap();
+        patches.push(patch);
+        (document, patches)
+    }
+
+    #[test]
+    fn test_partial_json() {
+        let answer = json!({
+            "title": "Capitals \"of\" Europe \u{1F1EA}\u{1F1FA}",
+            "rows": [
+                { "country": "France", "capital": "Paris", "population": 2.1e6, "coastal": false },
+                { "country": "Germany", "capital": "Berlin", "population": 3645000, "coastal": null },
+            ],
+            "a/b~c": [],
+            "count": 2
+        });
+        let text = serde_json::to_string_pretty(&answer).unwrap();
+        for size in [1, 2, 3, 7, 50] {
+            let (document, _) = stream(&text, size);
+            assert_eq!(document, answer, "chunks of {}", size);
+        }
+        let (document, _) = stream(&format!("```json\n{}\n```", text), 4);
+        assert_eq!(document, answer);
+    }
+
+    #[test]
+    fn test_partial_values() {
+        let mut partial = PartialJson::new();
+        assert_eq!(
+            partial.push(r#"{"items": ["Par"#),
+            vec![PatchOperation::Replace {
+                path: String::new(),
+                value: json!({ "items": ["Par"] }),
+            }]
+        );
+        assert_eq!(
+            partial.push(r#"is", 4"#),
+            vec![PatchOperation::Replace {
+                path: "/items/0".to_string(),
+                value: json!("Paris"),
+            }]
+        );
+        // The number may go on, and is added once it is complete.
+        assert_eq!(
+            partial.push("2, tr"),
+            vec![PatchOperation::Add {
+                path: "/items/1".to_string(),
+                value: json!(42),
+            }]
+        );
+        assert!(partial.push("u").is_empty());
+        assert_eq!(
+            partial.push("e], \"n\\u00"),
+            vec![PatchOperation::Add {
+                path: "/items/2".to_string(),
+                value: json!(true),
+            }]
+        );
+        assert_eq!(
+            partial.value(),
+            Some(&json!({ "items": ["Paris", 42, true] }))
+        );
+
+        let mut number = PartialJson::new();
+        assert!(number.push("12").is_empty());
+        assert_eq!(number.finish().len(), 1);
+        assert_eq!(number.value(), Some(&json!(12)));
+
+        // The whole answer replaces the tokens, which may be missing some of it.
+        let mut answer = PartialJson::new();
+        answer.push(r#"{"rows": [1, "#);
+        assert_eq!(
+            answer.complete(r#"{"rows": [1, 2]}"#),
+            vec![PatchOperation::Add {
+                path: "/rows/1".to_string(),
+                value: json!(2),
+            }]
+        );
+        assert!(answer.push("]}").is_empty());
+    }
+
+    #[test]
+    fn test_not_json() {
+        let mut partial = PartialJson::new();
+        assert!(partial.push("The capital").is_empty());
+        assert!(partial.push(" is {\"Paris\"}").is_empty());
+        assert_eq!(partial.value(), None);
+
+        let mut fenced = PartialJson::new();
+        assert!(fenced.push("``").is_empty());
+        assert!(fenced.push("`python\nprint(1)").is_empty());
+        assert_eq!(fenced.value(), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        let from = json!({ "a": 1, "b": [1, 2, 3], "c": { "d": "x" } });
+        let to = json!({ "b": [1, 5], "c": { "d": "x", "e/f": true }, "g": null });
+        let patch = diff(&from, &to);
+        assert_eq!(
+            serde_json::to_value(&patch).unwrap(),
+            json!([
+                { "op": "remove", "path": "/a" },
+                { "op": "replace", "path": "/b/1", "value": 5 },
+                { "op": "remove", "path": "/b/2" },
+                { "op": "add", "path": "/c/e~1f", "value": true },
+                { "op": "add", "path": "/g", "value": null },
+            ])
+        );
+        let mut document = from.clone();
+        apply_patch(&mut document, &patch).unwrap();
+        assert_eq!(document, to);
+        assert!(diff(&to, &to).is_empty());
+    }
+}
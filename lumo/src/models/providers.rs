@@ -0,0 +1,204 @@
+//! Presets of the providers served through the OpenAI-compatible API, selected by name instead of by base URL.
+//!
+//! A preset gives the base URL, the environment variable of the API key and the default model of the provider, and
+//! the [`ProviderQuirks`] that adapt the requests of [`OpenAIServerModel`](super::openai::OpenAIServerModel) to it.
+//! The quirks are also applied when the base URL of a model is the one of a provider, e.g. on the server.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// A provider with an OpenAI-compatible API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderPreset {
+    /// Grok models of xAI.
+    Xai,
+    /// DeepSeek's `deepseek-chat` and `deepseek-reasoner`.
+    DeepSeek,
+}
+
+impl ProviderPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProviderPreset::Xai => "xai",
+            ProviderPreset::DeepSeek => "deepseek",
+        }
+    }
+
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            ProviderPreset::Xai => "https://api.x.ai/v1/chat/completions",
+            ProviderPreset::DeepSeek => "https://api.deepseek.com/chat/completions",
+        }
+    }
+
+    /// The environment variable of the API key.
+    pub fn api_key_env(&self) -> &'static str {
+        match self {
+            ProviderPreset::Xai => "XAI_API_KEY",
+            ProviderPreset::DeepSeek => "DEEPSEEK_API_KEY",
+        }
+    }
+
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            ProviderPreset::Xai => "grok-4",
+            ProviderPreset::DeepSeek => "deepseek-chat",
+        }
+    }
+
+    /// The provider serving `base_url`, if it is one of the presets.
+    pub fn from_base_url(base_url: &str) -> Option<Self> {
+        let base_url = base_url.to_lowercase();
+        if base_url.contains("api.x.ai") {
+            Some(ProviderPreset::Xai)
+        } else if base_url.contains("api.deepseek.com") {
+            Some(ProviderPreset::DeepSeek)
+        } else {
+            None
+        }
+    }
+
+    /// How the requests for `model_id` are adapted to the provider.
+    ///
+    /// - Grok is sent `tool_choice: auto` with the tools, so that it may answer without calling one.
+    /// - DeepSeek rejects a `max_tokens` over the limit of the model instead of capping it. Its reasoning comes in the
+    ///   `reasoning_content` field, which is read as the reasoning of the step and never sent back, as DeepSeek
+    ///   rejects the messages that have it.
+    pub fn quirks(&self, model_id: &str) -> ProviderQuirks {
+        match self {
+            ProviderPreset::Xai => ProviderQuirks {
+                tool_choice: Some("auto"),
+                ..Default::default()
+            },
+            ProviderPreset::DeepSeek => ProviderQuirks {
+                max_tokens: Some(if model_id.contains("reasoner") {
+                    65_536
+                } else {
+                    8_192
+                }),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl FromStr for ProviderPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "xai" | "x.ai" | "grok" => Ok(ProviderPreset::Xai),
+            "deepseek" => Ok(ProviderPreset::DeepSeek),
+            _ => Err(anyhow!(
+                "Unknown provider {}, the presets are xai and deepseek",
+                name
+            )),
+        }
+    }
+}
+
+/// What a provider does differently from OpenAI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProviderQuirks {
+    /// The `tool_choice` sent with the tools.
+    pub tool_choice: Option<&'static str>,
+    /// The largest `max_tokens` the provider accepts.
+    pub max_tokens: Option<usize>,
+}
+
+impl ProviderQuirks {
+    /// Adapt the body of a chat completions request.
+    pub fn apply(&self, body: &mut Value) {
+        if let (Some(tool_choice), Some(_)) = (self.tool_choice, body.get("tools")) {
+            if body.get("tool_choice").is_none() {
+                body["tool_choice"] = json!(tool_choice);
+            }
+        }
+        if let (Some(limit), Some(max_tokens)) = (self.max_tokens, body["max_tokens"].as_u64()) {
+            body["max_tokens"] = json!(max_tokens.min(limit as u64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{model_traits::ModelResponse, openai::OpenAIResponse};
+
+    #[test]
+    fn test_provider_presets() {
+        assert_eq!(
+            "Grok".parse::<ProviderPreset>().unwrap(),
+            ProviderPreset::Xai
+        );
+        assert_eq!(
+            "deepseek".parse::<ProviderPreset>().unwrap(),
+            ProviderPreset::DeepSeek
+        );
+        assert!("mistral".parse::<ProviderPreset>().is_err());
+        assert_eq!(
+            ProviderPreset::from_base_url("https://api.deepseek.com/v1/chat/completions"),
+            Some(ProviderPreset::DeepSeek)
+        );
+        assert_eq!(
+            ProviderPreset::from_base_url(ProviderPreset::Xai.base_url()),
+            Some(ProviderPreset::Xai)
+        );
+        assert_eq!(
+            ProviderPreset::from_base_url("https://api.openai.com/v1/chat/completions"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_provider_quirks() {
+        let mut body = json!({"model": "grok-4", "max_tokens": 4500, "tools": []});
+        ProviderPreset::Xai.quirks("grok-4").apply(&mut body);
+        assert_eq!(body["tool_choice"], "auto");
+        assert_eq!(body["max_tokens"], 4500);
+
+        // Without tools, there is no tool choice.
+        let mut body = json!({"model": "grok-4", "max_tokens": 4500});
+        ProviderPreset::Xai.quirks("grok-4").apply(&mut body);
+        assert!(body.get("tool_choice").is_none());
+
+        let mut body = json!({"model": "deepseek-chat", "max_tokens": 20000});
+        ProviderPreset::DeepSeek
+            .quirks("deepseek-chat")
+            .apply(&mut body);
+        assert_eq!(body["max_tokens"], 8192);
+        let mut body = json!({"model": "deepseek-reasoner", "max_tokens": 20000});
+        ProviderPreset::DeepSeek
+            .quirks("deepseek-reasoner")
+            .apply(&mut body);
+        assert_eq!(body["max_tokens"], 20000);
+    }
+
+    #[test]
+    fn test_deepseek_reasoning_content() {
+        let response: OpenAIResponse = serde_json::from_value(json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "reasoning_content": "The user wants the weather, I need the tool.",
+                    "tool_calls": [{
+                        "id": "call_0",
+                        "type": "function",
+                        "function": {"name": "weather", "arguments": "{\"city\": \"Paris\"}"}
+                    }]
+                }
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            response.get_reasoning().as_deref(),
+            Some("The user wants the weather, I need the tool.")
+        );
+        assert_eq!(response.get_response().unwrap(), "");
+        let tool_calls = response.get_tools_used().unwrap();
+        assert_eq!(tool_calls[0].function.arguments, json!({"city": "Paris"}));
+    }
+}
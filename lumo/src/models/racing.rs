@@ -0,0 +1,433 @@
+//! A model that races several providers, for when one of them is slow or flaky.
+//!
+//! A [`RacingModel`] sends every request to all its providers at once and returns the first successful response.
+//! The other requests are cancelled. When streaming, only the tokens of the first provider to stream are sent to the
+//! caller, and its response is returned unless it fails, so the tokens of different providers are not mixed.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    errors::AgentError,
+    models::{
+        model_traits::{Model, ModelResponse},
+        openai::Status,
+        tokenizer::TokenCounter,
+        types::Message,
+    },
+    runtime::Instant,
+    tools::tool_traits::ToolInfo,
+};
+
+const NO_LEADER: usize = usize::MAX;
+/// Statuses buffered per provider while streaming.
+const STREAM_CAPACITY: usize = 256;
+
+/// How a provider fared in the races of a [`RacingModel`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProviderStats {
+    pub name: String,
+    /// Races the provider was part of.
+    pub requests: usize,
+    /// Races the provider won.
+    pub wins: usize,
+    /// Requests that failed.
+    pub failures: usize,
+    /// Requests that were cancelled because another provider won.
+    pub cancelled: usize,
+    /// Total latency of the successful responses, won or not.
+    pub total_latency_ms: u64,
+    pub last_error: Option<String>,
+}
+
+impl ProviderStats {
+    /// The average latency of the successful responses.
+    pub fn average_latency_ms(&self) -> Option<u64> {
+        let responses = self.requests - self.failures - self.cancelled;
+        (responses > 0).then(|| self.total_latency_ms / responses as u64)
+    }
+
+    /// The share of the races the provider won.
+    pub fn win_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.requests as f64
+        }
+    }
+}
+
+/// The outcome of the request to one provider.
+enum Outcome {
+    Won(u64),
+    Succeeded(u64),
+    Failed(String),
+}
+
+/// Sends every request to all its providers and returns the first successful response.
+#[derive(Default)]
+pub struct RacingModel {
+    providers: Vec<(String, Box<dyn Model>)>,
+    stats: Mutex<Vec<ProviderStats>>,
+}
+
+impl RacingModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a provider to the race. The name identifies it in the stats and errors.
+    pub fn with_provider(mut self, name: &str, model: impl Model) -> Self {
+        self.providers.push((name.to_string(), Box::new(model)));
+        self.stats.get_mut().unwrap().push(ProviderStats {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// The stats of the providers, in the order they were added.
+    pub fn stats(&self) -> Vec<ProviderStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    fn check_providers(&self) -> Result<(), AgentError> {
+        if self.providers.is_empty() {
+            return Err(AgentError::Generation(
+                "RacingModel has no providers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Record the outcomes of a race. Providers without an outcome were cancelled.
+    fn record(&self, outcomes: Vec<Option<Outcome>>) {
+        let mut stats = self.stats.lock().unwrap();
+        for (stats, outcome) in stats.iter_mut().zip(outcomes) {
+            stats.requests += 1;
+            match outcome {
+                Some(Outcome::Won(latency_ms)) => {
+                    stats.wins += 1;
+                    stats.total_latency_ms += latency_ms;
+                }
+                Some(Outcome::Succeeded(latency_ms)) => stats.total_latency_ms += latency_ms,
+                Some(Outcome::Failed(error)) => {
+                    stats.failures += 1;
+                    stats.last_error = Some(error);
+                }
+                None => stats.cancelled += 1,
+            }
+        }
+    }
+
+    fn all_failed(&self, outcomes: &[Option<Outcome>]) -> AgentError {
+        let errors = self
+            .providers
+            .iter()
+            .zip(outcomes)
+            .filter_map(|((name, _), outcome)| match outcome {
+                Some(Outcome::Failed(error)) => Some(format!("{}: {}", name, error)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        AgentError::Generation(format!("All providers failed. {}", errors.join("; ")))
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Model for RacingModel {
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        self.check_providers()?;
+        let start = Instant::now();
+        let mut races = self
+            .providers
+            .iter()
+            .enumerate()
+            .map(|(index, (_, model))| {
+                let request = model.run(
+                    input_messages.clone(),
+                    history.clone(),
+                    tools.clone(),
+                    max_tokens,
+                    args.clone(),
+                );
+                async move { (index, request.await) }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut outcomes = self.providers.iter().map(|_| None).collect::<Vec<_>>();
+        while let Some((index, result)) = races.next().await {
+            let latency_ms = start.elapsed().as_millis() as u64;
+            match result {
+                Ok(response) => {
+                    // Dropping the other requests cancels them.
+                    outcomes[index] = Some(Outcome::Won(latency_ms));
+                    self.record(outcomes);
+                    return Ok(response);
+                }
+                Err(error) => outcomes[index] = Some(Outcome::Failed(error.to_string())),
+            }
+        }
+        let error = self.all_failed(&outcomes);
+        self.record(outcomes);
+        Err(error)
+    }
+
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        self.check_providers()?;
+        let start = Instant::now();
+        // The provider streamed to the caller: the first one to stream a token.
+        let leader = AtomicUsize::new(NO_LEADER);
+        let claim = |index: usize| match leader.compare_exchange(
+            NO_LEADER,
+            index,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => true,
+            Err(current) => current == index,
+        };
+        let mut races = self
+            .providers
+            .iter()
+            .enumerate()
+            .map(|(index, (_, model))| {
+                let (provider_tx, mut provider_rx) = broadcast::channel(STREAM_CAPACITY);
+                let request = model.run_stream(
+                    input_messages.clone(),
+                    history.clone(),
+                    tools.clone(),
+                    max_tokens,
+                    args.clone(),
+                    provider_tx,
+                );
+                let tx = tx.clone();
+                let claim = &claim;
+                let forward = async move {
+                    // Ends when the request is done and its sender dropped.
+                    loop {
+                        match provider_rx.recv().await {
+                            Ok(status) if claim(index) => {
+                                let _ = tx.send(status);
+                            }
+                            Ok(_) | Err(RecvError::Lagged(_)) => {}
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                };
+                async move { (index, futures::join!(request, forward).0) }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut outcomes = self.providers.iter().map(|_| None).collect::<Vec<_>>();
+        // A response that completed while another provider was streaming, in case that one fails.
+        let mut standby = None;
+        let mut leader_failed = false;
+        while let Some((index, result)) = races.next().await {
+            let latency_ms = start.elapsed().as_millis() as u64;
+            let winner = match result {
+                Ok(response) if leader_failed || claim(index) => {
+                    Some((index, response, latency_ms))
+                }
+                Ok(response) => {
+                    outcomes[index] = Some(Outcome::Succeeded(latency_ms));
+                    standby.get_or_insert((index, response, latency_ms));
+                    None
+                }
+                Err(error) => {
+                    outcomes[index] = Some(Outcome::Failed(error.to_string()));
+                    if leader.load(Ordering::SeqCst) == index {
+                        // Its tokens were streamed, but the answer is now the one of another provider.
+                        leader_failed = true;
+                        standby.take()
+                    } else {
+                        None
+                    }
+                }
+            };
+            if let Some((index, response, latency_ms)) = winner {
+                outcomes[index] = Some(Outcome::Won(latency_ms));
+                drop(races);
+                self.record(outcomes);
+                return Ok(response);
+            }
+        }
+        drop(races);
+        let error = self.all_failed(&outcomes);
+        self.record(outcomes);
+        Err(error)
+    }
+
+    /// The smallest context window of the providers, so that a request fits all of them.
+    fn context_window(&self) -> Option<usize> {
+        self.providers
+            .iter()
+            .filter_map(|(_, model)| model.context_window())
+            .min()
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        self.providers
+            .iter()
+            .map(|(_, model)| model.max_output_tokens())
+            .min()
+            .unwrap_or(4500)
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        match self.providers.first() {
+            Some((_, model)) => model.token_counter(),
+            None => Box::new(crate::models::tokenizer::HeuristicCounter),
+        }
+    }
+
+    /// Native tool calls only when every provider supports them, since any of them can answer.
+    fn supports_native_tools(&self) -> bool {
+        self.providers
+            .iter()
+            .all(|(_, model)| model.supports_native_tools())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+    use std::time::Duration;
+
+    /// A provider that answers after a delay, or fails.
+    struct Provider {
+        delay_ms: u64,
+        answer: Option<&'static str>,
+    }
+
+    impl Provider {
+        async fn answer(&self) -> Result<MockModel, AgentError> {
+            tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+            match self.answer {
+                Some(answer) => Ok(MockModel::new(vec![MockResponse::text(answer)])),
+                None => Err(AgentError::Generation(
+                    "503 Service Unavailable".to_string(),
+                )),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Model for Provider {
+        async fn run(
+            &self,
+            input_messages: Vec<Message>,
+            history: Option<Vec<Message>>,
+            tools: Vec<ToolInfo>,
+            max_tokens: Option<usize>,
+            args: Option<HashMap<String, Vec<String>>>,
+        ) -> Result<Box<dyn ModelResponse>, AgentError> {
+            let model = self.answer().await?;
+            model
+                .run(input_messages, history, tools, max_tokens, args)
+                .await
+        }
+
+        async fn run_stream(
+            &self,
+            input_messages: Vec<Message>,
+            history: Option<Vec<Message>>,
+            tools: Vec<ToolInfo>,
+            max_tokens: Option<usize>,
+            args: Option<HashMap<String, Vec<String>>>,
+            tx: broadcast::Sender<Status>,
+        ) -> Result<Box<dyn ModelResponse>, AgentError> {
+            let model = self.answer().await?;
+            model
+                .run_stream(input_messages, history, tools, max_tokens, args, tx)
+                .await
+        }
+    }
+
+    fn provider(delay_ms: u64, answer: Option<&'static str>) -> Provider {
+        Provider { delay_ms, answer }
+    }
+
+    #[tokio::test]
+    async fn test_racing_model() {
+        let model = RacingModel::new()
+            .with_provider("flaky", provider(0, None))
+            .with_provider("slow", provider(500, Some("Paris, slowly")))
+            .with_provider("fast", provider(20, Some("Paris")));
+        let response = model.run(vec![], None, vec![], None, None).await.unwrap();
+        assert_eq!(response.get_response().unwrap(), "Paris");
+
+        let stats = model.stats();
+        assert_eq!((stats[0].requests, stats[0].failures), (1, 1));
+        assert_eq!(
+            stats[0].last_error.as_deref(),
+            Some("503 Service Unavailable")
+        );
+        assert_eq!((stats[1].wins, stats[1].cancelled), (0, 1));
+        assert_eq!(stats[1].average_latency_ms(), None);
+        assert_eq!(stats[2].wins, 1);
+        assert_eq!(stats[2].win_rate(), 1.0);
+
+        let model = RacingModel::new()
+            .with_provider("a", provider(0, None))
+            .with_provider("b", provider(10, None));
+        let error = model
+            .run(vec![], None, vec![], None, None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "All providers failed. a: 503 Service Unavailable; b: 503 Service Unavailable"
+        );
+        assert!(RacingModel::new()
+            .run(vec![], None, vec![], None, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_racing_stream() {
+        let model = RacingModel::new()
+            .with_provider("slow", provider(500, Some("Paris, slowly")))
+            .with_provider("fast", provider(20, Some("The capital is Paris")));
+        let (tx, mut rx) = broadcast::channel(32);
+        let response = model
+            .run_stream(vec![], None, vec![], None, None, tx)
+            .await
+            .unwrap();
+        assert_eq!(response.get_response().unwrap(), "The capital is Paris");
+
+        let mut streamed = String::new();
+        while let Ok(status) = rx.try_recv() {
+            if let Status::FirstContent(token) | Status::Content(token) = status {
+                streamed.push_str(&token);
+            }
+        }
+        assert_eq!(streamed, "The capital is Paris");
+        assert_eq!(model.stats()[1].wins, 1);
+    }
+}
@@ -0,0 +1,169 @@
+//! Separation of the reasoning of reasoning models (o-series, DeepSeek-R1, QwQ, ...) from their answer.
+//!
+//! Some backends return the reasoning in a `reasoning_content` field, others inline it in the content between
+//! `<think>` and `</think>`. The reasoning is kept out of the content so it does not end up in the memory replayed to
+//! the model, nor in the code and tool calls parsed from the output.
+
+const THINK_START: &str = "<think>";
+const THINK_END: &str = "</think>";
+
+/// Split the `<think>` block of a model output into `(reasoning, answer)`.
+///
+/// Models that open the block in the prompt template only output the closing tag, so text before a lone `</think>`
+/// is reasoning as well. An unclosed `<think>` makes the rest of the output reasoning.
+pub fn split_reasoning(content: &str) -> (Option<String>, String) {
+    let (before, reasoning, after) = match (content.find(THINK_START), content.find(THINK_END)) {
+        (Some(start), Some(end)) if start < end => (
+            &content[..start],
+            &content[start + THINK_START.len()..end],
+            &content[end + THINK_END.len()..],
+        ),
+        (Some(start), None) => (&content[..start], &content[start + THINK_START.len()..], ""),
+        (_, Some(end)) => ("", &content[..end], &content[end + THINK_END.len()..]),
+        (None, None) => return (None, content.to_string()),
+    };
+    let reasoning = reasoning.trim();
+    let answer = format!("{}{}", before, after.trim_start());
+    (
+        Some(reasoning.to_string()).filter(|reasoning| !reasoning.is_empty()),
+        answer,
+    )
+}
+
+/// Join the reasoning of a `reasoning_content` field with the reasoning inlined in the content.
+pub fn merge_reasoning(field: Option<&str>, inline: Option<String>) -> Option<String> {
+    match (field.filter(|field| !field.trim().is_empty()), inline) {
+        (Some(field), Some(inline)) => Some(format!("{}\n{}", field.trim(), inline)),
+        (Some(field), None) => Some(field.trim().to_string()),
+        (None, inline) => inline,
+    }
+}
+
+/// A piece of streamed content, once the `<think>` tags are taken out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentChunk {
+    Reasoning(String),
+    Answer(String),
+}
+
+/// Routes streamed content to the reasoning or the answer, following the `<think>` tags across chunks.
+#[derive(Debug, Default)]
+pub struct ThinkTagFilter {
+    in_think: bool,
+    /// The end of the previous chunk that may be the start of a tag.
+    pending: String,
+}
+
+impl ThinkTagFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &str) -> Vec<ContentChunk> {
+        let mut text = std::mem::take(&mut self.pending);
+        text.push_str(chunk);
+        let mut chunks = Vec::new();
+        loop {
+            let tag = if self.in_think {
+                THINK_END
+            } else {
+                THINK_START
+            };
+            match text.find(tag) {
+                Some(index) => {
+                    self.emit(&text[..index], &mut chunks);
+                    text = text[index + tag.len()..].to_string();
+                    self.in_think = !self.in_think;
+                }
+                None => {
+                    // Hold back a suffix that could be the beginning of the tag.
+                    let keep = (1..tag.len())
+                        .rev()
+                        .find(|len| text.ends_with(&tag[..*len]))
+                        .unwrap_or(0);
+                    let split = text.len() - keep;
+                    self.emit(&text[..split], &mut chunks);
+                    self.pending = text[split..].to_string();
+                    return chunks;
+                }
+            }
+        }
+    }
+
+    /// The text held back at the end of the stream.
+    pub fn finish(&mut self) -> Vec<ContentChunk> {
+        let mut chunks = Vec::new();
+        let pending = std::mem::take(&mut self.pending);
+        self.emit(&pending, &mut chunks);
+        chunks
+    }
+
+    fn emit(&self, text: &str, chunks: &mut Vec<ContentChunk>) {
+        if text.is_empty() {
+            return;
+        }
+        chunks.push(if self.in_think {
+            ContentChunk::Reasoning(text.to_string())
+        } else {
+            ContentChunk::Answer(text.to_string())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_reasoning() {
+        assert_eq!(
+            split_reasoning("<think>\nThe user greets me.\n</think>\n\nHello!"),
+            (
+                Some("The user greets me.".to_string()),
+                "Hello!".to_string()
+            )
+        );
+        assert_eq!(
+            split_reasoning("The user greets me.</think>Hello!"),
+            (
+                Some("The user greets me.".to_string()),
+                "Hello!".to_string()
+            )
+        );
+        assert_eq!(
+            split_reasoning("<think>Still thinking"),
+            (Some("Still thinking".to_string()), "".to_string())
+        );
+        assert_eq!(
+            split_reasoning("<think></think>Hello!"),
+            (None, "Hello!".to_string())
+        );
+        assert_eq!(split_reasoning("Hello!"), (None, "Hello!".to_string()));
+    }
+
+    #[test]
+    fn test_think_tag_filter() {
+        let mut filter = ThinkTagFilter::new();
+        let mut chunks = Vec::new();
+        for chunk in [
+            "<thi",
+            "nk>Let me",
+            " see.</th",
+            "ink>The answer",
+            " is 4. <",
+        ] {
+            chunks.extend(filter.push(chunk));
+        }
+        chunks.extend(filter.finish());
+        assert_eq!(
+            chunks,
+            vec![
+                ContentChunk::Reasoning("Let me".to_string()),
+                ContentChunk::Reasoning(" see.".to_string()),
+                ContentChunk::Answer("The answer".to_string()),
+                ContentChunk::Answer(" is 4. ".to_string()),
+                ContentChunk::Answer("<".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Token counting used to fit the agent memory in the context window of a model.
+
+use tiktoken_rs::{
+    tokenizer::{get_tokenizer, Tokenizer},
+    CoreBPE,
+};
+
+use super::types::Message;
+
+/// Tokens added by the chat format for every message, on top of its content.
+const TOKENS_PER_MESSAGE: usize = 4;
+
+pub trait TokenCounter: Send + Sync {
+    fn count_tokens(&self, text: &str) -> usize;
+
+    fn count_message_tokens(&self, messages: &[Message]) -> usize {
+        messages
+            .iter()
+            .map(|message| {
+                let tool_calls = message
+                    .tool_calls
+                    .as_ref()
+                    .map(|calls| self.count_tokens(&serde_json::to_string(calls).unwrap_or_default()))
+                    .unwrap_or_default();
+                TOKENS_PER_MESSAGE + self.count_tokens(&message.content) + tool_calls
+            })
+            .sum()
+    }
+}
+
+/// Counts tokens with the BPE encoding of an OpenAI model.
+pub struct TiktokenCounter {
+    bpe: &'static CoreBPE,
+}
+
+impl TiktokenCounter {
+    /// Use the encoding of the model. Unknown models, like most models served by OpenAI-compatible APIs, use
+    /// `cl100k_base`, which is close enough for budgeting.
+    pub fn for_model(model_id: &str) -> Self {
+        let bpe = match get_tokenizer(model_id) {
+            Some(Tokenizer::O200kBase) => tiktoken_rs::o200k_base_singleton(),
+            Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+            Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+            Some(Tokenizer::R50kBase) | Some(Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+            _ => tiktoken_rs::cl100k_base_singleton(),
+        };
+        Self { bpe }
+    }
+}
+
+impl TokenCounter for TiktokenCounter {
+    fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+}
+
+/// Estimates about four characters per token, for models without a known tokenizer.
+pub struct HeuristicCounter;
+
+impl TokenCounter for HeuristicCounter {
+    fn count_tokens(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::types::{MessageBuilder, MessageRole};
+
+    #[test]
+    fn test_token_counters() {
+        let counter = TiktokenCounter::for_model("gpt-4o-mini");
+        assert_eq!(counter.count_tokens("hello world"), 2);
+        assert_eq!(HeuristicCounter.count_tokens("hello world"), 3);
+
+        let messages = vec![MessageBuilder::new(MessageRole::User, "hello world").build()];
+        assert_eq!(counter.count_message_tokens(&messages), 6);
+    }
+}
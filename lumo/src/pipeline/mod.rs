@@ -0,0 +1,806 @@
+//! Multi-agent pipelines defined in YAML or JSON instead of Rust.
+//!
+//! A pipeline lists its agents, with their type, model, tools and prompts, and how they are wired: the agents an
+//! agent manages, and optionally the stages the task goes through, one agent after the other. A stage can be skipped
+//! depending on the output of an earlier stage.
+//!
+//! ```yaml
+//! model:
+//!   provider: openai
+//!   model_id: gpt-4.1-mini
+//! agents:
+//!   - name: researcher
+//!     description: Searches the web and reads the pages it finds.
+//!     tools: [DuckDuckGo, VisitWebsite]
+//!   - name: editor
+//!     managed_agents: [researcher]
+//!     system_prompt: You write short, sourced reports.
+//! stages:
+//!   - agent: editor
+//!   - agent: researcher
+//!     when:
+//!       contains: "not enough sources"
+//!     task: "Find more sources for this report: {{previous}}"
+//! ```
+//!
+//! ```rust,ignore
+//! let pipeline = lumo::pipeline::Pipeline::from_file("pipeline.yaml")?;
+//! let output = pipeline.run("What is new in Rust 1.85?").await?;
+//! println!("{}", output.answer);
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+#[cfg(feature = "code-agent")]
+use crate::agent::CodeAgentBuilder;
+#[cfg(feature = "code-agent")]
+use crate::tools::PythonInterpreterTool;
+#[cfg(feature = "screenshot")]
+use crate::tools::WebScreenshotTool;
+use crate::{
+    agent::{Agent, FunctionCallingAgentBuilder},
+    errors::AgentError,
+    models::{
+        gemini::GeminiServerModelBuilder,
+        huggingface::HuggingFaceModelBuilder,
+        model_traits::{Model, ModelResponse, ModelSampling},
+        ollama::OllamaModelBuilder,
+        openai::{OpenAIServerModelBuilder, Status},
+        providers::ProviderPreset,
+        tokenizer::TokenCounter,
+        types::Message,
+    },
+    presets::PresetAgentType,
+    tools::{
+        AsyncTool, DuckDuckGoSearchTool, E2BInterpreterTool, ExaSearchTool, FinalAnswerTool,
+        GoogleDriveTool, GoogleSearchTool, SlackTool, TavilySearchTool, ToolInfo, VisitWebsiteTool, WebCrawlTool,
+        ZoteroTool,
+    },
+};
+
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4.1-mini";
+/// The task of the stages after the first one, unless they have their own.
+const DEFAULT_NEXT_STAGE_TASK: &str = "{{task}}\n\nThe result of the previous stage:\n{{previous}}";
+
+/// The provider of a model.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelProvider {
+    /// OpenAI, or any server with an OpenAI-compatible API.
+    #[default]
+    OpenAI,
+    Ollama,
+    Gemini,
+    HuggingFace,
+    /// Grok, through the OpenAI-compatible API of xAI.
+    Xai,
+    DeepSeek,
+}
+
+impl ModelProvider {
+    /// The preset of the providers served through the OpenAI-compatible API.
+    pub fn preset(&self) -> Option<ProviderPreset> {
+        match self {
+            ModelProvider::Xai => Some(ProviderPreset::Xai),
+            ModelProvider::DeepSeek => Some(ProviderPreset::DeepSeek),
+            _ => None,
+        }
+    }
+}
+
+/// The model of an agent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelSpec {
+    #[serde(default)]
+    pub provider: ModelProvider,
+    pub model_id: Option<String>,
+    pub base_url: Option<String>,
+    pub temperature: Option<f32>,
+    /// The environment variable holding the API key, instead of the usual one of the provider, e.g.
+    /// `OPENAI_API_KEY`.
+    pub api_key_env: Option<String>,
+    /// The API key, set in code. It cannot be read from a definition, so that definitions hold no secrets.
+    #[serde(skip)]
+    pub api_key: Option<String>,
+}
+
+impl ModelSpec {
+    /// The API key set in code, or else from `api_key_env`, or else from `default_env`.
+    fn api_key(&self, default_env: Option<&str>) -> Result<Option<String>> {
+        if let Some(api_key) = &self.api_key {
+            return Ok(Some(api_key.clone()));
+        }
+        match (&self.api_key_env, default_env) {
+            (Some(env), _) => std::env::var(env)
+                .map(Some)
+                .map_err(|_| anyhow!("{} is not set", env)),
+            (None, Some(env)) => Ok(std::env::var(env).ok()),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Create the model, with the API key from the environment.
+    pub fn build(&self) -> Result<Box<dyn Model>> {
+        Ok(match self.provider {
+            ModelProvider::OpenAI => {
+                // Local OpenAI-compatible servers usually need no key.
+                let api_key = match (self.api_key(Some("OPENAI_API_KEY"))?, &self.base_url) {
+                    (Some(api_key), _) => api_key,
+                    (None, Some(_)) => String::new(),
+                    (None, None) => return Err(anyhow!("OPENAI_API_KEY is not set")),
+                };
+                Box::new(
+                    OpenAIServerModelBuilder::new(
+                        self.model_id.as_deref().unwrap_or(DEFAULT_OPENAI_MODEL),
+                    )
+                    .with_base_url(self.base_url.as_deref())
+                    .with_temperature(self.temperature)
+                    .with_api_key(Some(&api_key))
+                    .build()?,
+                )
+            }
+            ModelProvider::Ollama => {
+                let mut builder = OllamaModelBuilder::new().temperature(self.temperature);
+                if let Some(model_id) = &self.model_id {
+                    builder = builder.model_id(model_id);
+                }
+                if let Some(base_url) = &self.base_url {
+                    builder = builder.url(base_url);
+                }
+                Box::new(builder.build())
+            }
+            ModelProvider::Gemini => {
+                let api_key = self
+                    .api_key(Some("GOOGLE_API_KEY"))?
+                    .ok_or_else(|| anyhow!("GOOGLE_API_KEY is not set"))?;
+                let mut builder = GeminiServerModelBuilder::new(
+                    self.model_id.as_deref().unwrap_or("gemini-2.0-flash"),
+                )
+                .with_temperature(self.temperature)
+                .with_api_key(Some(&api_key));
+                if let Some(base_url) = &self.base_url {
+                    builder = builder.with_base_url(Some(base_url));
+                }
+                Box::new(builder.build()?)
+            }
+            ModelProvider::HuggingFace => {
+                // Without `api_key_env`, the builder reads `HF_TOKEN`.
+                let api_key = self.api_key(None)?;
+                Box::new(
+                    HuggingFaceModelBuilder::new(self.model_id.as_deref().unwrap_or("tgi"))
+                        .with_base_url(self.base_url.as_deref())
+                        .with_temperature(self.temperature)
+                        .with_api_key(api_key.as_deref())
+                        .build()?,
+                )
+            }
+            ModelProvider::Xai | ModelProvider::DeepSeek => {
+                let preset = self.provider.preset().unwrap();
+                let mut builder = OpenAIServerModelBuilder::new(
+                    self.model_id.as_deref().unwrap_or(preset.default_model()),
+                )
+                .with_provider(preset)
+                .with_base_url(self.base_url.as_deref())
+                .with_temperature(self.temperature);
+                // Without a key, the builder reads the one of the provider, e.g. `XAI_API_KEY`.
+                if let Some(api_key) = self.api_key(None)? {
+                    builder = builder.with_api_key(Some(&api_key));
+                }
+                Box::new(builder.build()?)
+            }
+        })
+    }
+}
+
+/// An agent of a pipeline.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentDefinition {
+    /// The name of the agent, used by the managers of the agent and by the stages.
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub agent_type: PresetAgentType,
+    /// What the agent does, shown to the agents that manage it.
+    pub description: Option<String>,
+    /// The model of the agent, instead of the model of the pipeline.
+    pub model: Option<ModelSpec>,
+    /// The names of the tools of the agent, e.g. `DuckDuckGo` or `VisitWebsite`.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Overrides the default system prompt of the agent.
+    pub system_prompt: Option<String>,
+    pub max_steps: Option<usize>,
+    pub planning_interval: Option<usize>,
+    /// The names of the agents this agent can hand tasks to.
+    #[serde(default)]
+    pub managed_agents: Vec<String>,
+}
+
+/// Run a stage only when the output of an earlier stage contains, or does not contain, a text. Case insensitive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageCondition {
+    /// The stage whose output is checked. The last stage that ran when missing.
+    pub stage: Option<String>,
+    pub contains: Option<String>,
+    pub not_contains: Option<String>,
+}
+
+impl StageCondition {
+    fn matches(&self, output: &str) -> bool {
+        let output = output.to_lowercase();
+        self.contains
+            .as_ref()
+            .is_none_or(|text| output.contains(&text.to_lowercase()))
+            && self
+                .not_contains
+                .as_ref()
+                .is_none_or(|text| !output.contains(&text.to_lowercase()))
+    }
+}
+
+/// A step of the pipeline, run by one agent.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageDefinition {
+    /// The name of the stage, for `{{stages.<name>}}` and conditions. The name of its agent by default.
+    pub name: Option<String>,
+    pub agent: String,
+    /// The task of the agent. `{{task}}` is the task of the pipeline, `{{previous}}` the output of the last stage
+    /// that ran and `{{stages.<name>}}` the output of a stage. The task of the pipeline for the first stage, followed
+    /// by the output of the previous stage for the others, by default.
+    pub task: Option<String>,
+    /// Skip the stage unless this condition holds.
+    pub when: Option<StageCondition>,
+}
+
+impl StageDefinition {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.agent)
+    }
+}
+
+/// The definition of a pipeline, as written in YAML or JSON.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineDefinition {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// The model of the agents that do not have their own.
+    pub model: Option<ModelSpec>,
+    pub agents: Vec<AgentDefinition>,
+    /// The stages of the task, in order. Without stages, the task is given to the first agent that no other agent
+    /// manages.
+    #[serde(default)]
+    pub stages: Vec<StageDefinition>,
+}
+
+impl PipelineDefinition {
+    /// Parse a definition in YAML, or in JSON, which is also YAML.
+    pub fn from_yaml(definition: &str) -> Result<Self> {
+        let definition: PipelineDefinition =
+            serde_yaml::from_str(definition).context("Invalid pipeline definition")?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let definition = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the pipeline {}", path.display()))?;
+        Self::from_yaml(&definition)
+    }
+
+    pub fn agent(&self, name: &str) -> Option<&AgentDefinition> {
+        self.agents.iter().find(|agent| agent.name == name)
+    }
+
+    /// Check that the names are unique, that the agents and stages refer to agents that exist, and that no agent
+    /// manages itself, directly or not.
+    pub fn validate(&self) -> Result<()> {
+        if self.agents.is_empty() {
+            return Err(anyhow!("The pipeline has no agents"));
+        }
+        let mut names = HashSet::new();
+        for agent in &self.agents {
+            if agent.name.trim().is_empty() {
+                return Err(anyhow!("An agent of the pipeline has no name"));
+            }
+            if !names.insert(agent.name.as_str()) {
+                return Err(anyhow!("The agent {} is defined twice", agent.name));
+            }
+        }
+        for agent in &self.agents {
+            for managed in &agent.managed_agents {
+                if self.agent(managed).is_none() {
+                    return Err(anyhow!(
+                        "The agent {} manages {}, which is not defined",
+                        agent.name,
+                        managed
+                    ));
+                }
+            }
+            self.check_cycle(&agent.name, &mut Vec::new())?;
+        }
+
+        let mut stages = HashSet::new();
+        for stage in &self.stages {
+            if self.agent(&stage.agent).is_none() {
+                return Err(anyhow!(
+                    "The stage {} runs {}, which is not defined",
+                    stage.name(),
+                    stage.agent
+                ));
+            }
+            if let Some(condition) = stage.when.as_ref().and_then(|when| when.stage.as_ref()) {
+                if !stages.contains(condition.as_str()) {
+                    return Err(anyhow!(
+                        "The condition of the stage {} refers to {}, which is not an earlier stage",
+                        stage.name(),
+                        condition
+                    ));
+                }
+            }
+            if !stages.insert(stage.name()) {
+                return Err(anyhow!(
+                    "The stage {} is defined twice, give the stages different names",
+                    stage.name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cycle<'a>(&'a self, name: &'a str, path: &mut Vec<&'a str>) -> Result<()> {
+        if path.contains(&name) {
+            path.push(name);
+            return Err(anyhow!(
+                "The agents manage each other: {}",
+                path.join(" -> ")
+            ));
+        }
+        path.push(name);
+        for managed in self.agent(name).into_iter().flat_map(|a| &a.managed_agents) {
+            self.check_cycle(managed, path)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    /// The stages of the pipeline, or a single stage running the first agent that no other agent manages.
+    fn stages(&self) -> Vec<StageDefinition> {
+        if !self.stages.is_empty() {
+            return self.stages.clone();
+        }
+        let managed = self
+            .agents
+            .iter()
+            .flat_map(|agent| &agent.managed_agents)
+            .collect::<HashSet<_>>();
+        let root = self
+            .agents
+            .iter()
+            .find(|agent| !managed.contains(&agent.name))
+            .unwrap_or(&self.agents[0]);
+        vec![StageDefinition {
+            name: None,
+            agent: root.name.clone(),
+            task: None,
+            when: None,
+        }]
+    }
+}
+
+/// Creates the model of an agent.
+pub type ModelFactory = Arc<dyn Fn(&ModelSpec) -> Result<Box<dyn Model>> + Send + Sync>;
+/// Creates a tool by name.
+pub type ToolFactory = Arc<dyn Fn(&str) -> Result<Box<dyn AsyncTool>> + Send + Sync>;
+
+/// The tools a pipeline can use by default, configured from the environment.
+pub fn default_tool(name: &str) -> Result<Box<dyn AsyncTool>> {
+    Ok(match name {
+        "DuckDuckGo" => Box::new(DuckDuckGoSearchTool::new()),
+        "VisitWebsite" => Box::new(VisitWebsiteTool::new()),
+        "WebCrawl" => Box::new(WebCrawlTool::new()),
+        "GoogleSearchTool" => Box::new(GoogleSearchTool::new(None)),
+        "ExaSearchTool" => Box::new(ExaSearchTool::new(3, None)),
+        "TavilySearchTool" => Box::new(TavilySearchTool::new(None)),
+        "E2BInterpreter" => Box::new(E2BInterpreterTool::new(None)),
+        "Slack" => Box::new(SlackTool::from_env()?),
+        "Zotero" => Box::new(ZoteroTool::from_env()?),
+        "GoogleDrive" => Box::new(GoogleDriveTool::from_env()?),
+        #[cfg(feature = "code-agent")]
+        "PythonInterpreter" => Box::new(PythonInterpreterTool::new()),
+        #[cfg(feature = "screenshot")]
+        "WebScreenshot" => Box::new(WebScreenshotTool::new()),
+        _ => return Err(anyhow!("Unknown tool {}", name)),
+    })
+}
+
+/// The output of a stage. `None` when the stage was skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageOutput {
+    pub name: String,
+    pub agent: String,
+    pub output: Option<String>,
+}
+
+/// The output of a run of a pipeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineOutput {
+    /// The output of the last stage that ran.
+    pub answer: String,
+    pub stages: Vec<StageOutput>,
+}
+
+/// A pipeline ready to run. The agents are created for each run, so runs do not share their memory.
+#[derive(Clone)]
+pub struct Pipeline {
+    definition: PipelineDefinition,
+    model_factory: ModelFactory,
+    tool_factory: ToolFactory,
+}
+
+impl Pipeline {
+    pub fn new(definition: PipelineDefinition) -> Result<Self> {
+        definition.validate()?;
+        Ok(Pipeline {
+            definition,
+            model_factory: Arc::new(ModelSpec::build),
+            tool_factory: Arc::new(default_tool),
+        })
+    }
+
+    pub fn from_yaml(definition: &str) -> Result<Self> {
+        Self::new(PipelineDefinition::from_yaml(definition)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(PipelineDefinition::from_file(path)?)
+    }
+
+    /// Create the models with this function instead of [`ModelSpec::build`], e.g. to choose the API keys.
+    pub fn with_model_factory(
+        mut self,
+        factory: impl Fn(&ModelSpec) -> Result<Box<dyn Model>> + Send + Sync + 'static,
+    ) -> Self {
+        self.model_factory = Arc::new(factory);
+        self
+    }
+
+    /// Create the tools with this function instead of [`default_tool`], e.g. to add custom tools.
+    pub fn with_tool_factory(
+        mut self,
+        factory: impl Fn(&str) -> Result<Box<dyn AsyncTool>> + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_factory = Arc::new(factory);
+        self
+    }
+
+    pub fn definition(&self) -> &PipelineDefinition {
+        &self.definition
+    }
+
+    /// Create an agent of the pipeline, with the agents it manages.
+    pub fn build_agent(&self, name: &str) -> Result<Box<dyn Agent>> {
+        let definition = self
+            .definition
+            .agent(name)
+            .ok_or_else(|| anyhow!("The agent {} is not defined", name))?;
+        let managed_agents = definition
+            .managed_agents
+            .iter()
+            .map(|managed| self.build_agent(managed))
+            .collect::<Result<Vec<_>>>()?;
+        let mut tools = definition
+            .tools
+            .iter()
+            .map(|tool| (self.tool_factory)(tool))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Failed to create the tools of {}", name))?;
+        let spec = definition
+            .model
+            .clone()
+            .or_else(|| self.definition.model.clone())
+            .unwrap_or_default();
+        let model = BoxedModel(
+            (self.model_factory)(&spec)
+                .with_context(|| format!("Failed to create the model of {}", name))?,
+        );
+        match definition.agent_type {
+            PresetAgentType::FunctionCalling => {
+                // The code agent adds the final answer tool itself.
+                if !tools.iter().any(|tool| tool.name() == "final_answer") {
+                    tools.push(Box::new(FinalAnswerTool::new()));
+                }
+                Ok(Box::new(
+                    FunctionCallingAgentBuilder::new(model)
+                        .with_name(Some(&definition.name))
+                        .with_description(definition.description.as_deref())
+                        .with_tools(tools)
+                        .with_managed_agents(managed_agents)
+                        .with_system_prompt(definition.system_prompt.as_deref())
+                        .with_max_steps(definition.max_steps)
+                        .with_planning_interval(definition.planning_interval)
+                        .build()?,
+                ))
+            }
+            #[cfg(feature = "code-agent")]
+            PresetAgentType::Code => Ok(Box::new(
+                CodeAgentBuilder::new(model)
+                    .with_name(Some(&definition.name))
+                    .with_description(definition.description.as_deref())
+                    .with_tools(tools)
+                    .with_managed_agents(managed_agents)
+                    .with_system_prompt(definition.system_prompt.as_deref())
+                    .with_max_steps(definition.max_steps)
+                    .with_planning_interval(definition.planning_interval)
+                    .build()?,
+            )),
+            #[cfg(not(feature = "code-agent"))]
+            PresetAgentType::Code => Err(anyhow!(
+                "The agent {} is a code agent, which needs the code-agent feature",
+                name
+            )),
+        }
+    }
+
+    /// Run the task through the stages of the pipeline.
+    pub async fn run(&self, task: &str) -> Result<PipelineOutput> {
+        let mut outputs: Vec<StageOutput> = Vec::new();
+        let mut previous: Option<String> = None;
+        for stage in self.definition.stages() {
+            let checked = match stage.when.as_ref().and_then(|when| when.stage.as_ref()) {
+                Some(name) => outputs
+                    .iter()
+                    .find(|output| &output.name == name)
+                    .and_then(|output| output.output.clone()),
+                None => previous.clone(),
+            };
+            if let Some(condition) = &stage.when {
+                if !condition.matches(checked.as_deref().unwrap_or_default()) {
+                    outputs.push(StageOutput {
+                        name: stage.name().to_string(),
+                        agent: stage.agent.clone(),
+                        output: None,
+                    });
+                    continue;
+                }
+            }
+            let template = match (&stage.task, &previous) {
+                (Some(template), _) => template.as_str(),
+                (None, Some(_)) => DEFAULT_NEXT_STAGE_TASK,
+                (None, None) => "{{task}}",
+            };
+            let stage_task = render_task(template, task, previous.as_deref(), &outputs);
+            let mut agent = self.build_agent(&stage.agent)?;
+            let output = agent
+                .run(&stage_task, true)
+                .await
+                .with_context(|| format!("The stage {} failed", stage.name()))?;
+            previous = Some(output.clone());
+            outputs.push(StageOutput {
+                name: stage.name().to_string(),
+                agent: stage.agent.clone(),
+                output: Some(output),
+            });
+        }
+        Ok(PipelineOutput {
+            answer: previous.unwrap_or_default(),
+            stages: outputs,
+        })
+    }
+}
+
+/// Fill the `{{task}}`, `{{previous}}` and `{{stages.<name>}}` placeholders of the task of a stage. Skipped stages are
+/// empty.
+fn render_task(
+    template: &str,
+    task: &str,
+    previous: Option<&str>,
+    outputs: &[StageOutput],
+) -> String {
+    let mut rendered = template
+        .replace("{{task}}", task)
+        .replace("{{previous}}", previous.unwrap_or_default());
+    for output in outputs {
+        rendered = rendered.replace(
+            &format!("{{{{stages.{}}}}}", output.name),
+            output.output.as_deref().unwrap_or_default(),
+        );
+    }
+    rendered
+}
+
+/// The model of an agent of a pipeline, whatever its provider.
+struct BoxedModel(Box<dyn Model>);
+
+impl Debug for BoxedModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BoxedModel")
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Model for BoxedModel {
+    async fn run(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        self.0
+            .run(input_messages, history, tools, max_tokens, args)
+            .await
+    }
+
+    async fn run_stream(
+        &self,
+        input_messages: Vec<Message>,
+        history: Option<Vec<Message>>,
+        tools: Vec<ToolInfo>,
+        max_tokens: Option<usize>,
+        args: Option<HashMap<String, Vec<String>>>,
+        tx: broadcast::Sender<Status>,
+    ) -> Result<Box<dyn ModelResponse>, AgentError> {
+        self.0
+            .run_stream(input_messages, history, tools, max_tokens, args, tx)
+            .await
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.0.context_window()
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        self.0.max_output_tokens()
+    }
+
+    fn token_counter(&self) -> Box<dyn TokenCounter> {
+        self.0.token_counter()
+    }
+
+    fn supports_native_tools(&self) -> bool {
+        self.0.supports_native_tools()
+    }
+
+    fn sampling(&self) -> ModelSampling {
+        self.0.sampling()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::{MockModel, MockResponse};
+
+    const DEFINITION: &str = r#"
+agents:
+  - name: researcher
+    description: Finds the facts.
+    model:
+      model_id: researcher
+  - name: writer
+    model:
+      model_id: writer
+    managed_agents: [researcher]
+  - name: reviewer
+    model:
+      model_id: reviewer
+stages:
+  - agent: writer
+  - agent: reviewer
+    when:
+      contains: DRAFT
+    task: "Review {{stages.writer}} about {{task}}"
+  - name: rewrite
+    agent: writer
+    when:
+      stage: reviewer
+      not_contains: approved
+"#;
+
+    #[test]
+    fn test_validate_definition() {
+        let definition = PipelineDefinition::from_yaml(DEFINITION).unwrap();
+        assert_eq!(definition.agents.len(), 3);
+        assert_eq!(
+            definition.agents[0].agent_type,
+            PresetAgentType::FunctionCalling
+        );
+        assert_eq!(definition.stages[2].name(), "rewrite");
+        let definition = PipelineDefinition::from_yaml(
+            "model: {provider: xai}\nagents: [{name: a, model: {provider: deepseek}}]",
+        )
+        .unwrap();
+        assert_eq!(
+            definition.model.unwrap().provider.preset(),
+            Some(ProviderPreset::Xai)
+        );
+        assert_eq!(
+            definition.agents[0].model.as_ref().unwrap().provider,
+            ModelProvider::DeepSeek
+        );
+
+        let error = |yaml: &str| PipelineDefinition::from_yaml(yaml).unwrap_err().to_string();
+        assert!(error("agents: []").contains("no agents"));
+        assert!(error("agents: [{name: a, managed_agents: [b]}]").contains("not defined"));
+        assert!(
+            error("agents: [{name: a, managed_agents: [b]}, {name: b, managed_agents: [a]}]")
+                .contains("a -> b -> a")
+        );
+        assert!(error("agents: [{name: a}]\nstages: [{agent: b}]").contains("not defined"));
+        assert!(
+            error("agents: [{name: a}]\nstages: [{agent: a}, {agent: a}]")
+                .contains("defined twice")
+        );
+        assert!(
+            error("agents: [{name: a}]\nstages: [{agent: a, when: {stage: b}}]")
+                .contains("not an earlier stage")
+        );
+        assert!(error("agents: [{name: a, tool: [x]}]").contains("Invalid pipeline definition"));
+    }
+
+    #[tokio::test]
+    async fn test_run_pipeline() {
+        let answers = HashMap::from([
+            ("writer", "DRAFT: Rust 1.85 stabilizes async closures"),
+            ("reviewer", "Approved"),
+            ("researcher", "Async closures"),
+        ]);
+        let pipeline = Pipeline::from_yaml(DEFINITION)
+            .unwrap()
+            .with_model_factory(move |spec| {
+                let answer = answers[spec.model_id.as_deref().unwrap()];
+                Ok(Box::new(MockModel::new(vec![MockResponse::tool_call(
+                    "final_answer",
+                    serde_json::json!({ "answer": answer }),
+                )])))
+            });
+        let output = pipeline.run("Rust 1.85").await.unwrap();
+        assert_eq!(output.answer, "Approved");
+        let stages = output
+            .stages
+            .iter()
+            .map(|stage| (stage.name.as_str(), stage.output.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            vec![
+                ("writer", Some("DRAFT: Rust 1.85 stabilizes async closures")),
+                ("reviewer", Some("Approved")),
+                ("rewrite", None),
+            ]
+        );
+
+        assert_eq!(
+            render_task(
+                "Review {{stages.writer}} about {{task}}",
+                "Rust 1.85",
+                None,
+                &output.stages
+            ),
+            "Review DRAFT: Rust 1.85 stabilizes async closures about Rust 1.85"
+        );
+        let error = Pipeline::from_yaml("agents: [{name: a, tools: [Unknown]}]")
+            .unwrap()
+            .build_agent("a")
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains("Unknown tool Unknown"));
+    }
+}
@@ -0,0 +1,38 @@
+//! The commonly used types of lumo in one import.
+//!
+//! ```rust,ignore
+//! use lumo::prelude::*;
+//! ```
+
+pub use crate::agent::{Agent, AgentStep, FunctionCallingAgent, FunctionCallingAgentBuilder, Step};
+#[cfg(feature = "stream")]
+pub use crate::agent::AgentStream;
+#[cfg(feature = "code-agent")]
+pub use crate::agent::{CodeAgent, CodeAgentBuilder};
+#[cfg(feature = "mcp")]
+pub use crate::agent::{McpAgent, McpAgentBuilder};
+pub use crate::errors::AgentError;
+#[cfg(feature = "stream")]
+pub use crate::facade::{EventStream, RunEvent};
+pub use crate::facade::{AgentHandle, RunOptions};
+pub use crate::models::{
+    cancellation::CancellationToken,
+    gemini::{GeminiServerModel, GeminiServerModelBuilder},
+    huggingface::{HuggingFaceModel, HuggingFaceModelBuilder},
+    mock::{MockModel, MockResponse, RecordingModel},
+    model_traits::{Model, ModelResponse},
+    ollama::{OllamaModel, OllamaModelBuilder},
+    openai::{OpenAIServerModel, OpenAIServerModelBuilder, Status},
+    types::{Message, MessageRole},
+};
+pub use crate::tools::{
+    AgentTool, AnyTool, AskUserTool, AsyncTool, DuckDuckGoSearchTool, E2BInterpreterTool,
+    ElasticsearchTool, ExaSearchTool, FinalAnswerTool, GoogleSearchTool, MultiSearchTool,
+    TavilySearchTool, Tool, UserInput, VisitWebsiteTool,
+};
+#[cfg(feature = "code-agent")]
+pub use crate::tools::PythonInterpreterTool;
+#[cfg(feature = "screenshot")]
+pub use crate::tools::WebScreenshotTool;
+#[cfg(feature = "macros")]
+pub use crate::tools::LumoTool;
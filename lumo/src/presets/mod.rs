@@ -0,0 +1,205 @@
+//! Ready-made agent configurations that can be instantiated by name.
+//!
+//! ```rust,ignore
+//! use lumo::presets;
+//!
+//! let mut agent = presets::build_agent("researcher", model)?;
+//! let answer = agent.run("What is new in Rust 1.85?", true).await?;
+//! ```
+
+use std::fmt::Debug;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+#[cfg(feature = "code-agent")]
+use crate::agent::CodeAgentBuilder;
+#[cfg(feature = "code-agent")]
+use crate::tools::{DuckDuckGoSearchTool, PythonInterpreterTool};
+use crate::{
+    agent::{Agent, FunctionCallingAgentBuilder},
+    models::model_traits::Model,
+    tools::{AsyncTool, TavilySearchTool, VisitWebsiteTool},
+};
+
+#[cfg(feature = "code-agent")]
+const DATA_ANALYST_SYSTEM_PROMPT: &str = r#"You are a data analyst. You answer questions by finding the data, analysing it with Python and explaining the results.
+
+1. Use the python interpreter for every calculation, do not compute the numbers yourself.
+2. When the data comes from the web, say where it comes from.
+3. Give the numbers that support your answer, and say how confident you are in them.
+4. If the data is not enough to answer the question, say so.
+
+The current time is {{current_time}}"#;
+
+/// The agent a preset runs on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresetAgentType {
+    #[default]
+    FunctionCalling,
+    #[serde(alias = "code-agent")]
+    Code,
+}
+
+impl PresetAgentType {
+    /// The name of the agent type, as used by the CLI and the server.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresetAgentType::FunctionCalling => "function-calling",
+            PresetAgentType::Code => "code-agent",
+        }
+    }
+}
+
+/// A named agent configuration: the agent type, its tools, planning interval and system prompt.
+#[derive(Debug, Clone)]
+pub struct AgentPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub agent_type: PresetAgentType,
+    pub planning_interval: Option<usize>,
+    /// Overrides the default system prompt of the agent.
+    pub system_prompt: Option<&'static str>,
+    tools: fn() -> Result<Vec<Box<dyn AsyncTool>>>,
+}
+
+impl AgentPreset {
+    /// Create the tools of the preset. Fails when a tool needs an API key that is not set.
+    pub fn tools(&self) -> Result<Vec<Box<dyn AsyncTool>>> {
+        (self.tools)()
+    }
+
+    /// Build the agent of the preset with the given model.
+    pub fn build<M: Model + Debug + Send + Sync + 'static>(
+        &self,
+        model: M,
+    ) -> Result<Box<dyn Agent>> {
+        let tools = self.tools()?;
+        match self.agent_type {
+            PresetAgentType::FunctionCalling => Ok(Box::new(
+                FunctionCallingAgentBuilder::new(model)
+                    .with_name(Some(self.name))
+                    .with_description(Some(self.description))
+                    .with_tools(tools)
+                    .with_system_prompt(self.system_prompt)
+                    .with_planning_interval(self.planning_interval)
+                    .build()?,
+            )),
+            #[cfg(feature = "code-agent")]
+            PresetAgentType::Code => Ok(Box::new(
+                CodeAgentBuilder::new(model)
+                    .with_name(Some(self.name))
+                    .with_description(Some(self.description))
+                    .with_tools(tools)
+                    .with_system_prompt(self.system_prompt)
+                    .with_planning_interval(self.planning_interval)
+                    .build()?,
+            )),
+            #[cfg(not(feature = "code-agent"))]
+            PresetAgentType::Code => Err(anyhow!(
+                "The {} preset needs the code-agent feature",
+                self.name
+            )),
+        }
+    }
+}
+
+/// All the available presets.
+pub fn presets() -> Vec<AgentPreset> {
+    vec![
+        AgentPreset {
+            name: "researcher",
+            description: "Searches the web with Tavily and reads the pages it finds, planning its research every 3 steps.",
+            agent_type: PresetAgentType::FunctionCalling,
+            planning_interval: Some(3),
+            system_prompt: None,
+            tools: || {
+                if std::env::var("TAVILY_API_KEY").is_err() {
+                    return Err(anyhow!("The researcher preset needs TAVILY_API_KEY to be set"));
+                }
+                Ok(vec![
+                    Box::new(TavilySearchTool::new(None)),
+                    Box::new(VisitWebsiteTool::new()),
+                ])
+            },
+        },
+        #[cfg(feature = "code-agent")]
+        AgentPreset {
+            name: "coder",
+            description: "Solves tasks by writing and running Python code.",
+            agent_type: PresetAgentType::Code,
+            planning_interval: None,
+            system_prompt: None,
+            tools: || Ok(vec![]),
+        },
+        #[cfg(feature = "code-agent")]
+        AgentPreset {
+            name: "data-analyst",
+            description: "Finds data on the web and analyses it with a Python interpreter.",
+            agent_type: PresetAgentType::FunctionCalling,
+            planning_interval: Some(4),
+            system_prompt: Some(DATA_ANALYST_SYSTEM_PROMPT),
+            tools: || {
+                Ok(vec![
+                    Box::new(PythonInterpreterTool::new()),
+                    Box::new(DuckDuckGoSearchTool::new()),
+                    Box::new(VisitWebsiteTool::new()),
+                ])
+            },
+        },
+    ]
+}
+
+/// The names of the available presets.
+pub fn names() -> Vec<&'static str> {
+    presets().iter().map(|preset| preset.name).collect()
+}
+
+/// The preset with the given name.
+pub fn get(name: &str) -> Option<AgentPreset> {
+    presets().into_iter().find(|preset| preset.name == name)
+}
+
+/// Build the agent of the preset with the given name.
+pub fn build_agent<M: Model + Debug + Send + Sync + 'static>(
+    name: &str,
+    model: M,
+) -> Result<Box<dyn Agent>> {
+    get(name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown preset {}, available presets: {}",
+                name,
+                names().join(", ")
+            )
+        })?
+        .build(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mock::MockModel;
+
+    #[test]
+    fn test_get_preset() {
+        let researcher = get("researcher").unwrap();
+        assert_eq!(researcher.agent_type, PresetAgentType::FunctionCalling);
+        assert_eq!(researcher.planning_interval, Some(3));
+        assert!(get("unknown").is_none());
+        assert!(build_agent("unknown", MockModel::new(vec![])).is_err());
+    }
+
+    #[cfg(feature = "code-agent")]
+    #[test]
+    fn test_build_preset() {
+        let agent = build_agent("data-analyst", MockModel::new(vec![])).unwrap();
+        assert_eq!(agent.name(), "data-analyst");
+        assert_eq!(agent.get_planning_interval(), Some(4));
+        assert!(agent.get_system_prompt().starts_with("You are a data analyst"));
+
+        let agent = build_agent("coder", MockModel::new(vec![])).unwrap();
+        assert_eq!(agent.name(), "coder");
+    }
+}
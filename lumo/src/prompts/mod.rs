@@ -0,0 +1,740 @@
+//! This module contains the prompts for the agents.
+
+use serde::{Deserialize, Serialize};
+
+mod de;
+mod es;
+mod fr;
+
+/// The system prompt for the code agent.
+pub const CODE_SYSTEM_PROMPT: &str = r#"You are an expert assistant who can solve any task using code blobs. You will be given a task to solve as best you can.
+To do so, you have been given access to a list of tools: these tools are basically Python functions which you can call with code.
+To solve the task, you must plan forward to proceed in a series of steps, in a cycle of 'Thought:', 'Code:', and 'Observation:' sequences.
+
+At each step, in the 'Thought:' sequence, you should first explain your reasoning towards solving the task and the tools that you want to use.
+Then in the 'Code:' sequence, you should write the code in simple Python. The code sequence must end with '<end_code>' sequence.
+During each intermediate step, you can use 'print()' to save whatever important information you will then need.
+These print outputs will then appear in the 'Observation:' field, which will be available as input for the next step.
+In the end you have to return a final answer using the `final_answer` tool.
+
+Here are a few examples using notional tools:
+---
+Task: "Generate an image of the oldest person in this document."
+
+Thought: I will proceed step by step and use the following tools: `document_qa` to find the oldest person in the document, then `image_generator` to generate an image according to the answer.
+Code:
+```py
+answer = document_qa(document=document, question="Who is the oldest person mentioned?")
+print(answer)
+```<end_code>
+Observation: "The oldest person in the document is John Doe, a 55 year old lumberjack living in Newfoundland."
+
+Thought: I will now generate an image showcasing the oldest person.
+Code:
+```py
+image = image_generator("A portrait of John Doe, a 55-year-old man living in Canada.")
+final_answer(image)
+```<end_code>
+
+---
+Task: "What is the result of the following operation: 5 + 3 + 1294.678?"
+
+Thought: I will use python code to compute the result of the operation and then return the final answer using the `final_answer` tool
+Code:
+```py
+result = 5 + 3 + 1294.678
+final_answer(result)
+```<end_code>
+
+---
+Task:
+"Answer the question in the variable `question` about the image stored in the variable `image`. The question is in French.
+You have been provided with these additional arguments, that you can access using the keys as variables in your python code:
+{'question': 'Quel est l'animal sur l'image?', 'image': 'path/to/image.jpg'}"
+
+Thought: I will use the following tools: `translator` to translate the question into English and then `image_qa` to answer the question on the input image.
+Code:
+```py
+translated_question = translator(question=question, src_lang="French", tgt_lang="English")
+print(f"The translated question is {translated_question}.")
+answer = image_qa(image=image, question=translated_question)
+final_answer(f"The answer is {answer}")
+```<end_code>
+
+---
+Task:
+In a 1979 interview, Stanislaus Ulam discusses with Martin Sherwin about other great physicists of his time, including Oppenheimer.
+What does he say was the consequence of Einstein learning too much math on his creativity, in one word?
+
+Thought: I need to find and read the 1979 interview of Stanislaus Ulam with Martin Sherwin.
+Code:
+```py
+pages = search(query="1979 interview Stanislaus Ulam Martin Sherwin physicists Einstein")
+print(pages)
+```<end_code>
+Observation:
+No result found for query "1979 interview Stanislaus Ulam Martin Sherwin physicists Einstein".
+
+Thought: The query was maybe too restrictive and did not find any results. Let's try again with a broader query.
+Code:
+```py
+pages = search(query="1979 interview Stanislaus Ulam")
+print(pages)
+```<end_code>
+Observation:
+Found 6 pages:
+[Stanislaus Ulam 1979 interview](https://ahf.nuclearmuseum.org/voices/oral-histories/stanislaus-ulams-interview-1979/)
+
+[Ulam discusses Manhattan Project](https://ahf.nuclearmuseum.org/manhattan-project/ulam-manhattan-project/)
+
+(truncated)
+
+Thought: I will read the first 2 pages to know more.
+Code:
+```py
+for url in ["https://ahf.nuclearmuseum.org/voices/oral-histories/stanislaus-ulams-interview-1979/", "https://ahf.nuclearmuseum.org/manhattan-project/ulam-manhattan-project/"]:
+    whole_page = visit_webpage(url)
+    print(whole_page)
+    print("\n" + "="*80 + "\n")  # Print separator between pages
+```<end_code>
+Observation:
+Manhattan Project Locations:
+Los Alamos, NM
+Stanislaus Ulam was a Polish-American mathematician. He worked on the Manhattan Project at Los Alamos and later helped design the hydrogen bomb. In this interview, he discusses his work at
+(truncated)
+
+Thought: I now have the final answer: from the webpages visited, Stanislaus Ulam says of Einstein: "He learned too much mathematics and sort of diminished, it seems to me personally, it seems to me his purely physics creativity." Let's answer in one word.
+Code:
+```py
+final_answer("diminished")
+```<end_code>
+
+---
+Task: "Which city has the highest population: Guangzhou or Shanghai?"
+
+Thought: I need to get the populations for both cities and compare them: I will use the tool `search` to get the population of both cities.
+Code:
+```py
+for city in ["Guangzhou", "Shanghai"]:
+    print(f"Population {city}:", search(f"{city} population")
+```<end_code>
+Observation:
+Population Guangzhou: ['Guangzhou has a population of 15 million inhabitants as of 2021.']
+Population Shanghai: '26 million (2019)'
+
+Thought: Now I know that Shanghai has the highest population.
+Code:
+```py
+final_answer("Shanghai")
+```<end_code>
+
+---
+Task: "What is the current age of the pope, raised to the power 0.36?"
+
+Thought: I will use the tool `wiki` to get the age of the pope, and confirm that with a web search.
+Code:
+```py
+pope_age_wiki = wiki(query="current pope age")
+print("Pope age as per wikipedia:", pope_age_wiki)
+pope_age_search = web_search(query="current pope age")
+print("Pope age as per google search:", pope_age_search)
+```<end_code>
+Observation:
+Pope age: "The pope Francis is currently 88 years old."
+
+Thought: I know that the pope is 88 years old. Let's compute the result using python code.
+Code:
+```py
+pope_current_age = 88 ** 0.36
+final_answer(pope_current_age)
+```<end_code>
+
+Above example were using notional tools that might not exist for you. On top of performing computations in the Python code snippets that you create, you only have access to these tools:
+
+{{tool_descriptions}}
+
+{{managed_agents_descriptions}}
+
+Here are the rules you should always follow to solve your task:
+1. Always provide a 'Thought:' sequence, and a 'Code:\n```py' sequence ending with '```<end_code>' sequence, else you will fail.
+2. Use only variables that you have defined!
+3. Always use the right arguments for the tools. DO NOT pass the arguments as a dict as in 'answer = wiki({'query': "What is the place where James Bond lives?"})', but use the arguments directly as in 'answer = wiki(query="What is the place where James Bond lives?")'.
+4. Take care to not chain too many sequential tool calls in the same code block, especially when the output format is unpredictable. For instance, a call to search has an unpredictable return format, so do not have another tool call that depends on its output in the same block: rather output results with print() to use them in the next block.
+5. Call a tool only when needed, and never re-do a tool call that you previously did with the exact same parameters.
+6. Don't name any new variable with the same name as a tool: for instance don't name a variable 'final_answer'.
+7. Never create any notional variables in our code, as having these in your logs will derail you from the true variables.
+8. You can use imports in your code, but only from the following list of modules: {{authorized_imports}}
+9. The state persists between code executions: so if in one step you've created variables or imported modules, these will all persist.
+10. Don't give up! You're in charge of solving the task, not providing directions to solve it.
+11. The current time is {{current_time}}.
+
+Now Begin! If you solve the task correctly, you will receive a reward of $1,000,000.
+
+"#;
+
+/// The system prompt for the facts agent. This prompt is used to build a comprehensive preparatory survey of which facts we have at our disposal and which ones we still need.
+pub const SYSTEM_PROMPT_FACTS: &str = r#"Below I will present you a task.
+
+You will now build a comprehensive preparatory survey of which facts we have at our disposal and which ones we still need.
+To do so, you will have to read the task and identify things that must be discovered in order to successfully complete it.
+Don't make any assumptions. For each item, provide a thorough reasoning. Here is how you will structure this survey:
+
+---
+### 1. Facts given in the task
+List here the specific facts given in the task that could help you (there might be nothing here).
+
+### 2. Facts to look up
+List here any facts that we may need to look up.
+Also list where to find each of these, for instance a website, a file... - maybe the task contains some sources that you should re-use here.
+
+### 3. Facts to derive
+List here anything that we want to derive from the above by logical reasoning, for instance computation or simulation.
+
+Keep in mind that "facts" will typically be specific names, dates, values, etc. Your answer should use the below headings:
+### 1. Facts given in the task
+### 2. Facts to look up
+### 3. Facts to derive
+Do not add anything else."#;
+
+/// The system prompt for the plan agent. This prompt is used to develop a step-by-step high-level plan to solve a task.
+pub const SYSTEM_PROMPT_PLAN: &str = r#"You are a world expert at making efficient plans to solve any task using a set of carefully crafted tools.
+
+Now for the given task, develop a step-by-step high-level plan taking into account the above inputs and list of facts.
+This plan should involve individual tasks based on the available tools, that if executed correctly will yield the correct answer.
+Do not skip steps, do not add any superfluous steps. Only write the high-level plan, DO NOT DETAIL INDIVIDUAL TOOL CALLS.
+After writing the final step of the plan, write the '<end_plan>' tag and stop there."#;
+
+/// The user prompt for the facts agent, with the `{{task}}`.
+pub const USER_PROMPT_FACTS: &str = r#"Here is the task:
+```
+{{task}}
+```
+Now Begin!"#;
+
+/// The user prompt for the plan agent, with the `{{task}}`, `{{tool_descriptions}}`, `{{managed_agents_descriptions}}`
+/// and `{{facts}}` of the task.
+pub const USER_PROMPT_PLAN: &str = r#"Here is your task:
+
+Task:
+```
+{{task}}
+```
+
+Your plan can leverage any of these tools:
+{{tool_descriptions}}
+
+{{managed_agents_descriptions}}
+
+List of facts that you know:
+```
+{{facts}}
+```
+
+Now begin! Write your plan below"#;
+
+/// The user prompt for the plan agent. This prompt is used to develop a step-by-step high-level plan to solve a task.
+pub fn user_prompt_plan(
+    task: &str,
+    tool_descriptions: &str,
+    managed_agent_descriptions: &str,
+    answer_facts: &str,
+) -> String {
+    ENGLISH_PROMPTS.user_prompt_plan(task, tool_descriptions, managed_agent_descriptions, answer_facts)
+}
+
+/// The system prompt for the tool calling agent. This prompt is used for models that do not have tool calling capabilities.
+pub const TOOL_CALLING_SYSTEM_PROMPT: &str = r#"You are an expert assistant who can solve any task using  tool calls. You will be given a task to solve as best you can.
+To do so, you have been given access to the following tools: {{tool_names}}
+
+The tool call you write is an action: after the tool is executed, you will get the result of the tool call as an "observation".
+This Action/Observation can repeat N times, you should take several steps when needed.
+
+You can use the result of the previous action as input for the next action.
+The observation will always be a string: it can represent a file, like "image_1.jpg".
+Then you can use it as input for the next action. You can do it for instance as follows:
+
+Observation: "image_1.jpg"
+
+Action:
+{
+  "name": "image_transformer",
+  "arguments": {"image": "image_1.jpg"}
+}
+
+To provide the final answer to the task, use an action blob with "tool_name": "final_answer" tool. It is the only way to complete the task, else you will be stuck on a loop. So your final output should look like this:
+Action:
+{
+  "name": "final_answer",
+  "arguments": {"answer": "insert your final answer here"}
+}
+
+
+Here are a few examples using notional tools:
+---
+Task: "Generate an image of the oldest person in this document."
+
+Action:
+{
+  "name": "document_qa",
+  "arguments": {"document": "document.pdf", "question": "Who is the oldest person mentioned?"}
+}
+Observation: "The oldest person in the document is John Doe, a 55 year old lumberjack living in Newfoundland."
+
+Action:
+{
+  "name": "image_generator",
+  "arguments": {"prompt": "A portrait of John Doe, a 55-year-old man living in Canada."}
+}
+Observation: "image.png"
+
+Action:
+{
+  "name": "final_answer",
+  "arguments": "image.png"
+}
+
+---
+Task: "What is the result of the following operation: 5 + 3 + 1294.678?"
+
+Action:
+{
+    "name": "python_interpreter",
+    "arguments": {"code": "5 + 3 + 1294.678"}
+}
+Observation: 1302.678
+
+Action:
+{
+  "name": "final_answer",
+  "arguments": "1302.678"
+}
+
+---
+Task: "Which city has the highest population , Guangzhou or Shanghai?"
+
+Action:
+{
+    "name": "search",
+    "arguments": "Population Guangzhou"
+}
+Observation: ['Guangzhou has a population of 15 million inhabitants as of 2021.']
+
+
+Action:
+{
+    "name": "search",
+    "arguments": "Population Shanghai"
+}
+Observation: '26 million (2019)'
+
+Action:
+{
+  "name": "final_answer",
+  "arguments": "Shanghai"
+}
+
+
+Above example were using notional tools that might not exist for you. You only have access to these tools:
+
+{{tool_descriptions}}
+
+{{managed_agents_descriptions}}
+
+Here are the rules you should always follow to solve your task:
+1. ALWAYS provide a tool call, else you will fail.
+2. Always use the right arguments for the tools. Never use variable names as the action arguments, use the value instead.
+3. Call a tool only when needed: do not call the search agent if you do not need information, try to solve the task yourself.
+If no tool call is needed, use final_answer tool to return your answer.
+4. Never re-do a tool call that you previously did with the exact same parameters.
+5. To use the result of a tool call in another tool call of the same step, write `$tool_call[N].result` in the arguments, where N is the position of that call in the step, starting from 0. The call runs once the result is known, with the result in its arguments.
+6. The current time is {{current_time}}.
+
+Now Begin! If you solve the task correctly and call the final_answer tool to give your answer, you will receive a reward of $1,000,000.
+"#;
+
+/// The system prompt for the function calling agent. This prompt is used for models that have tool calling capabilities.
+pub const FUNCTION_CALLING_SYSTEM_PROMPT: &str = r#"You are an expert assistant who can solve any task using  tool calls. You will be given a task to solve as best you can.
+To do so, you have been given access to the following tools: {{tool_names}}
+
+
+The tool call you write is an action: after the tool is executed, you will get the result of the tool call as an "observation".
+This Action/Observation can repeat N times, you should take several steps when needed.
+
+You can use the result of the previous action as input for the next action.
+The observation will always be a string: it can represent a file, like "image_1.jpg".
+
+Here are the rules you should always follow to solve your task:
+1. ALWAYS provide a tool call, else you will fail.
+2. Always use the right arguments for the tools. Never use variable names as the action arguments, use the value instead.
+3. If no tool call is needed, use final_answer tool to return your answer.
+4. Never re-do a tool call that you previously did with the exact same parameters.
+5. The current time is {{current_time}}.
+
+Now Begin! If you solve the task correctly, you will receive a reward of $1,000,000.
+"#;
+
+/// The built-in prompts of the agents in one language: the system prompts and the prompts of the planning steps. The
+/// language of the prompts steers the language the agents think and answer in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prompts {
+    /// The ISO 639-1 code of the language, e.g. `fr`.
+    pub language: &'static str,
+    /// The system prompt of the function calling and MCP agents, like [`TOOL_CALLING_SYSTEM_PROMPT`].
+    pub tool_calling_system_prompt: &'static str,
+    /// The system prompt of the code agent, like [`CODE_SYSTEM_PROMPT`].
+    pub code_system_prompt: &'static str,
+    /// Asks for the facts of the task in the first planning step, like [`SYSTEM_PROMPT_FACTS`].
+    pub facts_system_prompt: &'static str,
+    /// Gives the `{{task}}` to the facts agent, like [`USER_PROMPT_FACTS`].
+    pub facts_user_prompt: &'static str,
+    /// Asks for the plan in the first planning step, like [`SYSTEM_PROMPT_PLAN`].
+    pub plan_system_prompt: &'static str,
+    /// Gives the task, the tools, the managed agents and the facts to the plan agent, like [`USER_PROMPT_PLAN`].
+    pub plan_user_prompt: &'static str,
+    /// Introduces the facts in the memory of the agent.
+    pub facts_heading: &'static str,
+    /// Introduces the plan in the memory of the agent.
+    pub plan_heading: &'static str,
+}
+
+/// The prompts in English, the default of the agents.
+pub static ENGLISH_PROMPTS: Prompts = Prompts {
+    language: "en",
+    tool_calling_system_prompt: TOOL_CALLING_SYSTEM_PROMPT,
+    code_system_prompt: CODE_SYSTEM_PROMPT,
+    facts_system_prompt: SYSTEM_PROMPT_FACTS,
+    facts_user_prompt: USER_PROMPT_FACTS,
+    plan_system_prompt: SYSTEM_PROMPT_PLAN,
+    plan_user_prompt: USER_PROMPT_PLAN,
+    facts_heading: "Here are the facts that I know so far:",
+    plan_heading: "Here is the plan of action that I will follow for the task:",
+};
+
+/// The languages of the built-in prompts.
+static PROMPTS: [&Prompts; 4] = [&ENGLISH_PROMPTS, &fr::PROMPTS, &de::PROMPTS, &es::PROMPTS];
+
+impl Prompts {
+    /// The prompts in the language, from its code such as `fr`, `fr-FR` or the locale `fr_FR.UTF-8`. `None` when the
+    /// prompts are not translated to the language.
+    pub fn for_language(language: &str) -> Option<&'static Prompts> {
+        let code = language
+            .split(['-', '_', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        PROMPTS.into_iter().find(|prompts| prompts.language == code)
+    }
+
+    /// The prompts in the language, or an error listing the languages of the prompts.
+    pub fn try_for_language(language: &str) -> anyhow::Result<&'static Prompts> {
+        Self::for_language(language).ok_or_else(|| {
+            anyhow::anyhow!(
+                "No prompts in {}, the prompts are in {}",
+                language,
+                Self::languages().join(", ")
+            )
+        })
+    }
+
+    /// The codes of the languages of the built-in prompts.
+    pub fn languages() -> Vec<&'static str> {
+        PROMPTS.iter().map(|prompts| prompts.language).collect()
+    }
+
+    /// The user prompt of the facts agent for the task.
+    pub fn user_prompt_facts(&self, task: &str) -> String {
+        self.facts_user_prompt.replace("{{task}}", task)
+    }
+
+    /// The user prompt of the plan agent for the task. Placeholders written in the task are left as they are.
+    pub fn user_prompt_plan(
+        &self,
+        task: &str,
+        tool_descriptions: &str,
+        managed_agent_descriptions: &str,
+        answer_facts: &str,
+    ) -> String {
+        let (before, after) = self
+            .plan_user_prompt
+            .split_once("{{task}}")
+            .unwrap_or((self.plan_user_prompt, ""));
+        let fill = |part: &str| {
+            part.replace("{{tool_descriptions}}", tool_descriptions)
+                .replace("{{managed_agents_descriptions}}", managed_agent_descriptions)
+                .replace("{{facts}}", answer_facts)
+        };
+        format!("{}{}{}", fill(before), task, fill(after))
+    }
+}
+
+/// The system prompt used to title and summarize a conversation.
+pub const SUMMARIZE_SYSTEM_PROMPT: &str = r#"You write titles and summaries of conversations between a user and an AI assistant.
+
+Given the conversation, answer with a JSON object and nothing else:
+{"title": "<a title of at most 8 words, without quotes or a trailing period>", "summary": ["<bullet>", "..."]}
+
+The summary has between 1 and 5 short bullets covering what the user asked and what the assistant found or did. Write them in the language of the conversation."#;
+
+/// The system prompt used to extract the facts worth remembering from a run, see `LongTermMemory`.
+pub const MEMORY_EXTRACTION_SYSTEM_PROMPT: &str = r#"You pick the facts worth remembering from a conversation between a user and an AI assistant, so the assistant can use them in its future conversations with the same user.
+
+Remember lasting facts: what the user said about themselves, their preferences, projects and constraints, and facts about the people, organizations, places and things of the conversation that may matter again. Do not remember the question itself, facts that only mattered for this task, general knowledge, or facts that are already known.
+
+Answer with a JSON object and nothing else:
+{"memories": [{"fact": "<one short, self-contained sentence>", "entities": ["<names of the entities the fact is about>"]}]}
+
+Answer with {"memories": []} when there is nothing worth remembering."#;
+
+/// Introduces the memories of earlier runs added to the system prompt.
+pub const MEMORIES_PROMPT: &str = "What you remember from earlier conversations, which may help with the task:";
+
+/// The system prompt used to check that a final answer addresses the task.
+pub const ANSWER_VALIDATION_SYSTEM_PROMPT: &str = r#"You check the final answers of an AI agent before they are given to the user.
+
+You are given the task of the user, the numbered observations the agent collected with its tools, and its final answer. Decide whether the answer addresses the task and is supported by the observations or by well-established facts. An answer that only describes what the agent would do, answers a different question or contradicts the observations is not valid.
+
+Answer with a JSON object and nothing else:
+{"valid": true or false, "reason": "<one sentence explaining the verdict>", "supporting_observations": [<numbers of the observations that support the answer>]}"#;
+
+pub const JSON_ANSWER_PROMPT: &str = "Give your final answer as a single JSON value, with nothing before or after it: no text and no code fences. Use the structure that fits the answer, e.g. an array of objects for a table or a list.";
+
+/// The guidance added after an error of unknown cause.
+pub const RETRY_PROMPT: &str = "Now let's retry: take care not to repeat previous errors! If you have retried several times, try a completely different approach.";
+
+/// The guidance added after a rate limited call.
+pub const RATE_LIMITED_RETRY_PROMPT: &str = "The service is rate limiting the calls. Don't repeat the same call right away: wait for your next steps before calling it again, or use another tool that can give the same information.";
+
+/// The guidance added after a call to a page or resource that does not exist.
+pub const NOT_FOUND_RETRY_PROMPT: &str = "The resource was not found. Don't guess the address again: search for it to get a valid URL or identifier, or use another source.";
+
+/// The guidance added after a call with arguments that don't match the parameters of the tool.
+pub const INVALID_ARGUMENTS_RETRY_PROMPT: &str = "The arguments of the call are invalid. Check the names, types and required fields of the parameters of the tool, and call it again with fixed arguments.";
+
+/// The guidance added after a call that timed out.
+pub const TIMEOUT_RETRY_PROMPT: &str = "The call timed out. Try again with a smaller request, e.g. a narrower query, or use another tool.";
+
+/// The guidance added after a call refused for lack of access.
+pub const UNAUTHORIZED_RETRY_PROMPT: &str = "The call was refused: access to the resource is not allowed. Don't retry it, use another tool or source instead.";
+
+/// The causes of errors that get their own retry guidance, guessed from the error message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    RateLimited,
+    NotFound,
+    InvalidArguments,
+    Timeout,
+    Unauthorized,
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of an error from its message, e.g. `RateLimited` for a `429 Too Many Requests`.
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let has_status = |code: &str| {
+            message
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|word| word == code)
+        };
+        let has_any = |patterns: &[&str]| patterns.iter().any(|pattern| message.contains(pattern));
+        if has_any(&[
+            "invalid arguments",
+            "error when executing tool with arguments",
+            "missing field",
+            "unknown field",
+            "invalid type",
+            "tool not found",
+        ]) {
+            Self::InvalidArguments
+        } else if has_status("429") || has_any(&["rate limit", "rate-limit", "too many requests"]) {
+            Self::RateLimited
+        } else if has_status("401")
+            || has_status("403")
+            || has_any(&[
+                "unauthorized",
+                "forbidden",
+                "access denied",
+                "permission denied",
+            ])
+        {
+            Self::Unauthorized
+        } else if has_status("404") || has_any(&["not found", "no such"]) {
+            Self::NotFound
+        } else if has_any(&["timed out", "timeout", "deadline exceeded"]) {
+            Self::Timeout
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The guidance written to the memory after an error, by kind of error. Defaults to the `*_RETRY_PROMPT`s.
+///
+/// ```
+/// use lumo::prompts::{ErrorKind, RetryPrompts};
+///
+/// let prompts = RetryPrompts::default()
+///     .with_prompt(ErrorKind::NotFound, "Search the documentation index for the right page.");
+/// assert_eq!(
+///     prompts.get(ErrorKind::classify("HTTP 404")),
+///     "Search the documentation index for the right page."
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPrompts {
+    pub rate_limited: String,
+    pub not_found: String,
+    pub invalid_arguments: String,
+    pub timeout: String,
+    pub unauthorized: String,
+    pub other: String,
+}
+
+impl Default for RetryPrompts {
+    fn default() -> Self {
+        Self {
+            rate_limited: RATE_LIMITED_RETRY_PROMPT.to_string(),
+            not_found: NOT_FOUND_RETRY_PROMPT.to_string(),
+            invalid_arguments: INVALID_ARGUMENTS_RETRY_PROMPT.to_string(),
+            timeout: TIMEOUT_RETRY_PROMPT.to_string(),
+            unauthorized: UNAUTHORIZED_RETRY_PROMPT.to_string(),
+            other: RETRY_PROMPT.to_string(),
+        }
+    }
+}
+
+impl RetryPrompts {
+    pub fn get(&self, kind: ErrorKind) -> &str {
+        match kind {
+            ErrorKind::RateLimited => &self.rate_limited,
+            ErrorKind::NotFound => &self.not_found,
+            ErrorKind::InvalidArguments => &self.invalid_arguments,
+            ErrorKind::Timeout => &self.timeout,
+            ErrorKind::Unauthorized => &self.unauthorized,
+            ErrorKind::Other => &self.other,
+        }
+    }
+
+    pub fn with_prompt(mut self, kind: ErrorKind, prompt: impl Into<String>) -> Self {
+        let prompt = prompt.into();
+        match kind {
+            ErrorKind::RateLimited => self.rate_limited = prompt,
+            ErrorKind::NotFound => self.not_found = prompt,
+            ErrorKind::InvalidArguments => self.invalid_arguments = prompt,
+            ErrorKind::Timeout => self.timeout = prompt,
+            ErrorKind::Unauthorized => self.unauthorized = prompt,
+            ErrorKind::Other => self.other = prompt,
+        }
+        self
+    }
+
+    /// The guidance for an error message.
+    pub fn for_error(&self, message: &str) -> &str {
+        self.get(ErrorKind::classify(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_errors() {
+        let cases = [
+            (
+                "HTTP status client error (429 Too Many Requests)",
+                ErrorKind::RateLimited,
+            ),
+            ("Rate limited", ErrorKind::RateLimited),
+            (
+                "HTTP status client error (404 Not Found) for url",
+                ErrorKind::NotFound,
+            ),
+            (
+                "Invalid arguments for tool 'search':\n- missing `query`",
+                ErrorKind::InvalidArguments,
+            ),
+            ("Tool not found", ErrorKind::InvalidArguments),
+            ("operation timed out", ErrorKind::Timeout),
+            ("401 Unauthorized", ErrorKind::Unauthorized),
+            ("Division by zero", ErrorKind::Other),
+            // Numbers are only status codes on their own.
+            ("Found 4290 results", ErrorKind::Other),
+        ];
+        for (message, kind) in cases {
+            assert_eq!(ErrorKind::classify(message), kind, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_localized_prompts() {
+        assert_eq!(Prompts::for_language("fr").unwrap().language, "fr");
+        assert_eq!(Prompts::for_language("de-AT").unwrap().language, "de");
+        assert_eq!(Prompts::for_language("es_ES.UTF-8").unwrap().language, "es");
+        assert_eq!(Prompts::for_language("EN").unwrap(), &ENGLISH_PROMPTS);
+        assert!(Prompts::for_language("xx").is_none());
+        assert!(Prompts::try_for_language("xx")
+            .unwrap_err()
+            .to_string()
+            .ends_with("en, fr, de, es"));
+        assert_eq!(Prompts::languages(), vec!["en", "fr", "de", "es"]);
+
+        // The translations keep the placeholders and the markers the agents fill and parse.
+        let placeholders = |prompt: &str| {
+            [
+                "{{tool_names}}",
+                "{{tool_descriptions}}",
+                "{{managed_agents_descriptions}}",
+                "{{current_time}}",
+                "{{authorized_imports}}",
+                "{{task}}",
+                "{{facts}}",
+                "<end_code>",
+                "<end_plan>",
+                "final_answer",
+            ]
+            .into_iter()
+            .filter(|placeholder| prompt.contains(placeholder))
+            .collect::<Vec<_>>()
+        };
+        for prompts in PROMPTS {
+            let pairs = [
+                (prompts.tool_calling_system_prompt, TOOL_CALLING_SYSTEM_PROMPT),
+                (prompts.code_system_prompt, CODE_SYSTEM_PROMPT),
+                (prompts.facts_system_prompt, SYSTEM_PROMPT_FACTS),
+                (prompts.facts_user_prompt, USER_PROMPT_FACTS),
+                (prompts.plan_system_prompt, SYSTEM_PROMPT_PLAN),
+                (prompts.plan_user_prompt, USER_PROMPT_PLAN),
+            ];
+            for (translated, english) in pairs {
+                assert_eq!(placeholders(translated), placeholders(english), "{}", prompts.language);
+            }
+        }
+    }
+
+    #[test]
+    fn test_user_prompts() {
+        let prompt = user_prompt_plan("Find {{facts}}", "[search]", "", "None");
+        assert!(prompt.starts_with("Here is your task:\n\nTask:\n```\nFind {{facts}}\n```"));
+        assert!(prompt.contains("any of these tools:\n[search]\n"));
+        assert!(prompt.contains("```\nNone\n```"));
+
+        let prompt = Prompts::for_language("fr").unwrap().user_prompt_facts("Trouver");
+        assert_eq!(prompt, "Voici la tâche :\n```\nTrouver\n```\nMaintenant, commence !");
+    }
+
+    #[test]
+    fn test_retry_prompts() {
+        let prompts = RetryPrompts::default().with_prompt(ErrorKind::Timeout, "Wait.");
+        assert_eq!(prompts.for_error("request timed out"), "Wait.");
+        assert_eq!(prompts.for_error("Division by zero"), RETRY_PROMPT);
+
+        // Prompts missing from a config keep their defaults.
+        let prompts: RetryPrompts =
+            serde_json::from_str(r#"{"rate_limited": "Slow down."}"#).unwrap();
+        assert_eq!(prompts.get(ErrorKind::RateLimited), "Slow down.");
+        assert_eq!(prompts.get(ErrorKind::NotFound), NOT_FOUND_RETRY_PROMPT);
+    }
+}
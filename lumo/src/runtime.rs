@@ -0,0 +1,103 @@
+//! The async runtime of the agents.
+//!
+//! Natively the agents run on tokio. On `wasm32-unknown-unknown`, e.g. in a browser or a Cloudflare Worker, they run
+//! on the event loop of the host: the tasks are spawned with `wasm-bindgen-futures`, the timers are the host's, and
+//! the clock is read with `web-time` since the clock of `std` panics there. The futures of the host are not `Send`,
+//! so the traits of the crate only require `Send` futures natively.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::{pin, Pin};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{select, Either, RemoteHandle};
+use futures::FutureExt;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// `Send` natively, where the tasks may move between threads, and nothing on `wasm32`, where there is one thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+/// `Send` natively, where the tasks may move between threads, and nothing on `wasm32`, where there is one thread.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// Wait for the duration.
+pub async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    #[cfg(target_arch = "wasm32")]
+    futures_timer::Delay::new(duration).await;
+}
+
+/// The output of the future, or `None` when it does not complete within the duration.
+pub async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    match select(pin!(future), pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(((), _)) => None,
+    }
+}
+
+/// Run the future in the background. It is aborted when the returned task is dropped, so the tasks reading a
+/// response stream do not outlive a cancelled request.
+pub fn spawn<F>(future: F) -> Task<F::Output>
+where
+    F: Future + MaybeSend + 'static,
+    F::Output: MaybeSend + 'static,
+{
+    let (remote, handle) = AssertUnwindSafe(future).catch_unwind().remote_handle();
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::spawn(remote);
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(remote);
+    Task(handle)
+}
+
+/// A task started with [`spawn`]. Its output is an error when the task panicked.
+pub struct Task<T>(RemoteHandle<std::thread::Result<T>>);
+
+impl<T: 'static> Future for Task<T> {
+    type Output = anyhow::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|output| output.map_err(|_| anyhow::anyhow!("The task panicked")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spawn() {
+        assert_eq!(spawn(async { 1 }).await.unwrap(), 1);
+        assert!(spawn(async { panic!("failed") }).await.is_err());
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let task = spawn(async move {
+            let _tx = tx;
+            futures::future::pending::<()>().await
+        });
+        drop(task);
+        // The task is aborted, dropping its sender.
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        assert_eq!(timeout(Duration::from_secs(1), async { 1 }).await, Some(1));
+        let pending = futures::future::pending::<()>();
+        assert_eq!(timeout(Duration::from_millis(10), pending).await, None);
+    }
+}
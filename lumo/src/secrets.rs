@@ -0,0 +1,497 @@
+//! Secrets: the API keys of the models and tools.
+//!
+//! A [`SecretProvider`] gives the value of a secret by name, e.g. `OPENAI_API_KEY`. The models and tools that take a
+//! [`SecretKey`] ask the provider for the key on each request, so a rotated key is used without restarting the
+//! application. The providers are:
+//!
+//! - [`EnvSecrets`]: the environment variables.
+//! - [`EncryptedFileSecrets`]: a file encrypted with a passphrase, written with `lumo secrets set`. With the
+//!   `encrypted-secrets` feature, which is not available on wasm32.
+//! - [`VaultSecrets`]: a KV version 2 secrets engine of HashiCorp Vault.
+//! - [`SecretChain`]: the first of several providers that has the secret.
+
+#[cfg(feature = "encrypted-secrets")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+#[cfg(feature = "encrypted-secrets")]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(feature = "encrypted-secrets")]
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::runtime::Instant;
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait SecretProvider: Send + Sync {
+    /// The value of the secret, `None` when the provider does not have it.
+    async fn get(&self, name: &str) -> Result<Option<String>>;
+}
+
+/// A secret of a provider, read each time it is used.
+#[derive(Clone)]
+pub struct SecretKey {
+    pub name: String,
+    provider: Arc<dyn SecretProvider>,
+}
+
+impl SecretKey {
+    pub fn new(name: impl Into<String>, provider: Arc<dyn SecretProvider>) -> Self {
+        Self {
+            name: name.into(),
+            provider,
+        }
+    }
+
+    /// The current value of the secret.
+    pub async fn resolve(&self) -> Result<String> {
+        self.provider
+            .get(&self.name)
+            .await
+            .with_context(|| format!("Failed to read the secret {}", self.name))?
+            .ok_or_else(|| anyhow!("The secret {} is not set", self.name))
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// The secrets in the environment variables of the same name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnvSecrets;
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl SecretProvider for EnvSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        Ok(std::env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+}
+
+/// The decrypted secrets, with the modification time of the file they were read from.
+#[cfg(feature = "encrypted-secrets")]
+type DecryptedSecrets = (SystemTime, HashMap<String, String>);
+
+/// Secrets in a file encrypted with a passphrase, in the [age](https://age-encryption.org) format, so `age -d` also
+/// decrypts it. The file is read again when it changes, so that `lumo secrets set` rotates a key while the application
+/// runs; the key is only derived from the passphrase again then.
+#[cfg(feature = "encrypted-secrets")]
+#[derive(Clone)]
+pub struct EncryptedFileSecrets {
+    path: PathBuf,
+    passphrase: String,
+    work_factor: Option<u8>,
+    cache: Arc<Mutex<Option<DecryptedSecrets>>>,
+}
+
+#[cfg(feature = "encrypted-secrets")]
+impl EncryptedFileSecrets {
+    pub fn new(path: impl AsRef<Path>, passphrase: &str) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            passphrase: passphrase.to_string(),
+            work_factor: None,
+            cache: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The file of `LUMO_SECRETS_FILE`, encrypted with the passphrase of `LUMO_SECRETS_PASSPHRASE`. `None` when
+    /// `LUMO_SECRETS_FILE` is not set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var("LUMO_SECRETS_FILE") else {
+            return Ok(None);
+        };
+        let passphrase = std::env::var("LUMO_SECRETS_PASSPHRASE")
+            .map_err(|_| anyhow!("LUMO_SECRETS_PASSPHRASE must be set with LUMO_SECRETS_FILE"))?;
+        Ok(Some(Self::new(path, &passphrase)))
+    }
+
+    /// The scrypt work factor, `N = 2^log_n`, when the file is written. More is slower to open and to brute-force.
+    /// By default, about a second of work on this machine.
+    pub fn with_work_factor(mut self, log_n: u8) -> Self {
+        self.work_factor = Some(log_n.clamp(1, 63));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All the secrets of the file, none when it does not exist yet. Blocks while the file is read and decrypted.
+    pub fn load(&self) -> Result<HashMap<String, String>> {
+        let modified = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", self.path)),
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((cached, secrets)) = cache.as_ref() {
+            if *cached == modified {
+                return Ok(secrets.clone());
+            }
+        }
+        let content =
+            std::fs::read(&self.path).with_context(|| format!("Failed to read {:?}", self.path))?;
+        let secrets = decrypt(&content, &self.passphrase)
+            .with_context(|| format!("Failed to decrypt {:?}", self.path))?;
+        *cache = Some((modified, secrets.clone()));
+        Ok(secrets)
+    }
+
+    /// Add or replace a secret.
+    pub fn set(&self, name: &str, value: &str) -> Result<()> {
+        let mut secrets = self.load()?;
+        secrets.insert(name.to_string(), value.to_string());
+        self.save(&secrets)
+    }
+
+    /// Remove a secret. Returns whether the file had it.
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        let mut secrets = self.load()?;
+        let removed = secrets.remove(name).is_some();
+        if removed {
+            self.save(&secrets)?;
+        }
+        Ok(removed)
+    }
+
+    /// The names of the secrets, sorted.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let mut names = self.load()?.into_keys().collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Write the secrets to a new file that replaces the old one, readable by its owner only.
+    fn save(&self, secrets: &HashMap<String, String>) -> Result<()> {
+        let content = encrypt(secrets, &self.passphrase, self.work_factor)?;
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        // Created with its permissions, so the secrets are never readable by others. A file left by an interrupted
+        // save would keep its own permissions, so it is removed first.
+        let _ = std::fs::remove_file(&tmp);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, content.as_bytes()))
+            .with_context(|| format!("Failed to write {:?}", tmp))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {:?}", self.path))?;
+        *self.cache.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "encrypted-secrets")]
+#[async_trait]
+impl SecretProvider for EncryptedFileSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        // Reading the file and deriving the key block, so they run outside of the async tasks.
+        let secrets = self.clone();
+        let mut secrets = tokio::task::spawn_blocking(move || secrets.load()).await??;
+        Ok(secrets.remove(name))
+    }
+}
+
+#[cfg(feature = "encrypted-secrets")]
+fn encrypt(
+    secrets: &HashMap<String, String>,
+    passphrase: &str,
+    work_factor: Option<u8>,
+) -> Result<String> {
+    let mut recipient = age::scrypt::Recipient::new(passphrase.to_string().into());
+    if let Some(log_n) = work_factor {
+        recipient.set_work_factor(log_n);
+    }
+    // Sorted, so that the same secrets give the same plaintext
+    let data = serde_json::to_vec(&secrets.iter().collect::<BTreeMap<_, _>>())?;
+    age::encrypt_and_armor(&recipient, &data).context("Failed to encrypt the secrets")
+}
+
+#[cfg(feature = "encrypted-secrets")]
+fn decrypt(content: &[u8], passphrase: &str) -> Result<HashMap<String, String>> {
+    let identity = age::scrypt::Identity::new(passphrase.to_string().into());
+    let plaintext = age::decrypt(&identity, content).map_err(|e| match e {
+        age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys => {
+            anyhow!("Wrong passphrase, or the file was modified")
+        }
+        e => anyhow!("Not a lumo secrets file: {}", e),
+    })?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// How long the secrets read from Vault are used before they are read again, unless set with `with_ttl`.
+pub const DEFAULT_VAULT_TTL: Duration = Duration::from_secs(60);
+
+/// The fields of a secret of a KV version 2 secrets engine of HashiCorp Vault, e.g. `OPENAI_API_KEY` in the secret
+/// `secret/lumo`. The secret is read again once the TTL is over, so a rotated key is used within the TTL.
+pub struct VaultSecrets {
+    address: String,
+    token: String,
+    mount: String,
+    path: String,
+    namespace: Option<String>,
+    ttl: Duration,
+    client: reqwest::Client,
+    cache: Mutex<Option<(Instant, HashMap<String, String>)>>,
+}
+
+impl VaultSecrets {
+    /// The secret at `path` of the `secret` mount of the Vault server at `address`, e.g. `https://vault:8200`.
+    pub fn new(address: &str, token: &str, path: &str) -> Self {
+        Self {
+            address: address.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: "secret".to_string(),
+            path: path.trim_matches('/').to_string(),
+            namespace: None,
+            ttl: DEFAULT_VAULT_TTL,
+            client: reqwest::Client::new(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// The secret of `VAULT_SECRET_PATH` (`lumo` by default) in the mount of `VAULT_MOUNT` (`secret` by default), at
+    /// `VAULT_ADDR` with `VAULT_TOKEN`. `None` when `VAULT_ADDR` is not set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(address) = std::env::var("VAULT_ADDR") else {
+            return Ok(None);
+        };
+        let token = std::env::var("VAULT_TOKEN")
+            .map_err(|_| anyhow!("VAULT_TOKEN must be set with VAULT_ADDR"))?;
+        let path = std::env::var("VAULT_SECRET_PATH").unwrap_or_else(|_| "lumo".to_string());
+        let mut vault = Self::new(&address, &token, &path);
+        if let Ok(mount) = std::env::var("VAULT_MOUNT") {
+            vault = vault.with_mount(&mount);
+        }
+        vault.namespace = std::env::var("VAULT_NAMESPACE").ok();
+        Ok(Some(vault))
+    }
+
+    pub fn with_mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    async fn fetch(&self) -> Result<HashMap<String, String>> {
+        let url = format!("{}/v1/{}/data/{}", self.address, self.mount, self.path);
+        let mut request = self.client.get(&url).header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(HashMap::new());
+        }
+        let response = response.error_for_status().with_context(|| {
+            format!(
+                "Vault rejected the request for {}/{}",
+                self.mount, self.path
+            )
+        })?;
+        Ok(vault_fields(&response.json::<Value>().await?))
+    }
+}
+
+/// The string fields of the response to a read of a KV version 2 secret.
+fn vault_fields(response: &Value) -> HashMap<String, String> {
+    response["data"]["data"]
+        .as_object()
+        .map(|fields| {
+            fields
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl SecretProvider for VaultSecrets {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        if let Some((read_at, secrets)) = self.cache.lock().unwrap().as_ref() {
+            if read_at.elapsed() < self.ttl {
+                return Ok(secrets.get(name).cloned());
+            }
+        }
+        let secrets = self.fetch().await?;
+        let value = secrets.get(name).cloned();
+        *self.cache.lock().unwrap() = Some((Instant::now(), secrets));
+        Ok(value)
+    }
+}
+
+/// The secret of the first provider that has it.
+#[derive(Default, Clone)]
+pub struct SecretChain {
+    providers: Vec<Arc<dyn SecretProvider>>,
+}
+
+impl SecretChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_provider(mut self, provider: impl SecretProvider + 'static) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Vault when `VAULT_ADDR` is set, then the encrypted file when `LUMO_SECRETS_FILE` is set, then the environment
+    /// variables.
+    pub fn from_env() -> Result<Self> {
+        let mut chain = Self::new();
+        if let Some(vault) = VaultSecrets::from_env()? {
+            chain = chain.with_provider(vault);
+        }
+        #[cfg(feature = "encrypted-secrets")]
+        if let Some(file) = EncryptedFileSecrets::from_env()? {
+            chain = chain.with_provider(file);
+        }
+        #[cfg(not(feature = "encrypted-secrets"))]
+        if std::env::var("LUMO_SECRETS_FILE").is_ok() {
+            return Err(anyhow!(
+                "LUMO_SECRETS_FILE needs lumo built with the encrypted-secrets feature"
+            ));
+        }
+        Ok(chain.with_provider(EnvSecrets))
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl SecretProvider for SecretChain {
+    async fn get(&self, name: &str) -> Result<Option<String>> {
+        for provider in &self.providers {
+            if let Some(value) = provider.get(name).await? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "encrypted-secrets")]
+    #[tokio::test]
+    async fn test_encrypted_file_secrets() {
+        let path = std::env::temp_dir().join(format!("lumo-secrets-{}.json", nanoid::nanoid!()));
+        let secrets = EncryptedFileSecrets::new(&path, "correct horse").with_work_factor(2);
+        assert!(secrets.get("OPENAI_API_KEY").await.unwrap().is_none());
+
+        secrets.set("OPENAI_API_KEY", "sk-old").unwrap();
+        secrets.set("EXA_API_KEY", "exa").unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+        }
+        assert!(!content.contains("sk-old"));
+        assert_eq!(
+            secrets.names().unwrap(),
+            vec!["EXA_API_KEY", "OPENAI_API_KEY"]
+        );
+
+        // Another instance, like a running server, sees the rotated key.
+        let reader = Arc::new(EncryptedFileSecrets::new(&path, "correct horse"));
+        let key = SecretKey::new("OPENAI_API_KEY", reader.clone());
+        assert_eq!(key.resolve().await.unwrap(), "sk-old");
+        std::thread::sleep(Duration::from_millis(20));
+        secrets.set("OPENAI_API_KEY", "sk-new").unwrap();
+        assert_eq!(key.resolve().await.unwrap(), "sk-new");
+        assert!(secrets.remove("EXA_API_KEY").unwrap());
+        assert!(reader.get("EXA_API_KEY").await.unwrap().is_none());
+        assert_eq!(
+            format!("{:?}", key),
+            "SecretKey { name: \"OPENAI_API_KEY\" }"
+        );
+
+        let wrong = EncryptedFileSecrets::new(&path, "wrong horse");
+        assert!(format!("{:#}", wrong.load().unwrap_err()).contains("Wrong passphrase"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "encrypted-secrets")]
+    #[tokio::test]
+    async fn test_secret_chain() {
+        let path = std::env::temp_dir().join(format!("lumo-secrets-{}.json", nanoid::nanoid!()));
+        let file = EncryptedFileSecrets::new(&path, "passphrase").with_work_factor(2);
+        file.set("PATH", "from the file").unwrap();
+        let chain = Arc::new(
+            SecretChain::new()
+                .with_provider(file)
+                .with_provider(EnvSecrets),
+        );
+        assert_eq!(
+            chain.get("PATH").await.unwrap().as_deref(),
+            Some("from the file")
+        );
+        assert_eq!(
+            chain.get("CARGO_PKG_NAME").await.unwrap().as_deref(),
+            Some("lumo")
+        );
+        let error = SecretKey::new("LUMO_MISSING_SECRET", chain)
+            .resolve()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The secret LUMO_MISSING_SECRET is not set"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_vault_fields() {
+        let response = serde_json::json!({
+            "data": {
+                "data": {"OPENAI_API_KEY": "sk-vault", "max_results": 5},
+                "metadata": {"version": 3}
+            }
+        });
+        let fields = vault_fields(&response);
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields["OPENAI_API_KEY"], "sk-vault");
+    }
+}
@@ -0,0 +1,708 @@
+//! Where the spans of the agents are exported, and which traces are kept.
+//!
+//! The spans go to Langfuse, to any OTLP/HTTP endpoint such as an OpenTelemetry Collector, or to a JSONL file for
+//! deployments without network access. With `TRACE_FALLBACK_FILE`, the spans that Langfuse or the OTLP endpoint
+//! fail to receive are written to a JSONL file instead of being lost. Traces can be sampled by ratio, by rate, or
+//! both; the spans of a trace are kept or dropped together.
+//!
+//! [`init_tracer`] reads the settings from the environment:
+//!
+//! - `TRACE_EXPORTER`: `langfuse`, `otlp`, `jsonl` or `none`. When missing, Langfuse is used when its keys are set,
+//!   then OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set, then JSONL when
+//!   `TRACE_FILE` is set.
+//! - `LANGFUSE_PUBLIC_KEY`, `LANGFUSE_SECRET_KEY` and `LANGFUSE_HOST`, with a `_DEV` suffix in debug builds.
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` and `OTEL_EXPORTER_OTLP_HEADERS`, read by
+//!   the OTLP exporter. The endpoint defaults to a local collector at `http://localhost:4318`.
+//! - `TRACE_FILE`: the file of the JSONL exporter, `traces.jsonl` by default.
+//! - `TRACE_FALLBACK_FILE`: the file the spans that failed to export are written to.
+//! - `TRACE_SAMPLE_RATIO`: the share of traces kept, from 0 to 1.
+//! - `TRACE_RATE_LIMIT`: the maximum number of traces kept per second.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use opentelemetry::{
+    global,
+    trace::{
+        Link, SamplingDecision, SamplingResult, SpanKind, Status, TraceId, TracerProvider as _,
+    },
+    Array, Context, Key, KeyValue, Value as OtelValue,
+};
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::{
+    error::{OTelSdkError, OTelSdkResult},
+    trace::{
+        BatchConfigBuilder, BatchSpanProcessor, Sampler, SdkTracerProvider, ShouldSample, SpanData,
+        SpanExporter,
+    },
+    Resource,
+};
+use serde_json::{json, Map, Value};
+
+/// The file of the JSONL exporter, unless `TRACE_FILE` is set.
+const DEFAULT_TRACE_FILE: &str = "traces.jsonl";
+
+/// The OTLP/HTTP endpoint of a local collector, used when no endpoint is set.
+const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318";
+
+/// Where the spans are exported.
+#[derive(Clone, PartialEq)]
+pub enum TraceExporter {
+    /// A Langfuse instance, e.g. `https://cloud.langfuse.com`.
+    Langfuse {
+        host: String,
+        public_key: String,
+        secret_key: String,
+    },
+    /// An OTLP/HTTP endpoint. `None` uses the endpoint and headers of the `OTEL_EXPORTER_OTLP_*` variables.
+    Otlp { endpoint: Option<String> },
+    /// One JSON object per span, appended to a file.
+    Jsonl { path: PathBuf },
+}
+
+impl std::fmt::Debug for TraceExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceExporter::Langfuse { host, .. } => f
+                .debug_struct("Langfuse")
+                .field("host", host)
+                .finish_non_exhaustive(),
+            TraceExporter::Otlp { endpoint } => {
+                f.debug_struct("Otlp").field("endpoint", endpoint).finish()
+            }
+            TraceExporter::Jsonl { path } => f.debug_struct("Jsonl").field("path", path).finish(),
+        }
+    }
+}
+
+impl TraceExporter {
+    /// The exporter of the environment, `None` when tracing is off.
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::from_vars(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let suffix = if cfg!(debug_assertions) { "_DEV" } else { "" };
+        let langfuse = match (
+            var(&format!("LANGFUSE_PUBLIC_KEY{}", suffix)),
+            var(&format!("LANGFUSE_SECRET_KEY{}", suffix)),
+            var(&format!("LANGFUSE_HOST{}", suffix)),
+        ) {
+            (Some(public_key), Some(secret_key), Some(host)) => Some(TraceExporter::Langfuse {
+                host,
+                public_key,
+                secret_key,
+            }),
+            _ => None,
+        };
+        let otlp = var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+            .or_else(|| var("OTEL_EXPORTER_OTLP_ENDPOINT"))
+            .map(|_| TraceExporter::Otlp { endpoint: None });
+        let jsonl = var("TRACE_FILE").map(|path| TraceExporter::Jsonl { path: path.into() });
+
+        let exporter = match var("TRACE_EXPORTER").map(|exporter| exporter.to_lowercase()) {
+            None => langfuse.or(otlp).or(jsonl),
+            Some(exporter) => match exporter.as_str() {
+                "langfuse" => Some(langfuse.ok_or_else(|| {
+                    anyhow!(
+                        "TRACE_EXPORTER is langfuse, but LANGFUSE_PUBLIC_KEY{0}, LANGFUSE_SECRET_KEY{0} or LANGFUSE_HOST{0} is not set",
+                        suffix
+                    )
+                })?),
+                "otlp" => Some(otlp.unwrap_or(TraceExporter::Otlp {
+                    endpoint: Some(DEFAULT_OTLP_ENDPOINT.to_string()),
+                })),
+                "jsonl" => Some(jsonl.unwrap_or_else(|| TraceExporter::Jsonl {
+                    path: DEFAULT_TRACE_FILE.into(),
+                })),
+                "none" => None,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown TRACE_EXPORTER '{}', expected langfuse, otlp, jsonl or none",
+                        exporter
+                    ))
+                }
+            },
+        };
+        Ok(exporter)
+    }
+
+    /// Where the spans go: the Langfuse host, the OTLP endpoint or the file.
+    pub fn destination(&self) -> String {
+        match self {
+            TraceExporter::Langfuse { host, .. } => host.clone(),
+            TraceExporter::Otlp { endpoint } => endpoint.clone().unwrap_or_else(|| {
+                std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+                    .or_else(|_| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT"))
+                    .unwrap_or_else(|_| DEFAULT_OTLP_ENDPOINT.to_string())
+            }),
+            TraceExporter::Jsonl { path } => path.display().to_string(),
+        }
+    }
+
+    fn otlp_exporter(&self) -> Result<Option<opentelemetry_otlp::SpanExporter>> {
+        let builder = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_protocol(opentelemetry_otlp::Protocol::HttpBinary);
+        let builder = match self {
+            TraceExporter::Langfuse {
+                host,
+                public_key,
+                secret_key,
+            } => {
+                // Basic Auth: base64(public_key:secret_key)
+                let auth_header = format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD
+                        .encode(format!("{}:{}", public_key, secret_key))
+                );
+                builder
+                    .with_endpoint(format!("{}/api/public/otel/v1/traces", host))
+                    .with_headers([("Authorization".to_string(), auth_header)].into())
+            }
+            TraceExporter::Otlp {
+                endpoint: Some(endpoint),
+            } => builder.with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/'))),
+            TraceExporter::Otlp { endpoint: None } => builder,
+            TraceExporter::Jsonl { .. } => return Ok(None),
+        };
+        Ok(Some(builder.build()?))
+    }
+}
+
+/// Which traces are kept: a share of them, at most a number per second, or both. Only the root spans are sampled,
+/// and the other spans of a trace follow their root.
+#[derive(Debug, Clone)]
+pub struct TraceSampler {
+    ratio: f64,
+    rate_limit: Option<RateLimit>,
+}
+
+impl TraceSampler {
+    /// Keep `ratio` of the traces, from 0 to 1.
+    pub fn ratio(ratio: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(anyhow!(
+                "The trace sample ratio must be between 0 and 1, got {}",
+                ratio
+            ));
+        }
+        Ok(Self {
+            ratio,
+            rate_limit: None,
+        })
+    }
+
+    /// Keep at most `per_second` traces per second, allowing bursts of as many.
+    pub fn with_rate_limit(mut self, per_second: f64) -> Result<Self> {
+        if per_second.is_nan() || per_second <= 0.0 {
+            return Err(anyhow!(
+                "The trace rate limit must be positive, got {}",
+                per_second
+            ));
+        }
+        self.rate_limit = Some(RateLimit::new(per_second));
+        Ok(self)
+    }
+
+    /// The sampler of `TRACE_SAMPLE_RATIO` and `TRACE_RATE_LIMIT`, `None` when both are missing.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let ratio = var("TRACE_SAMPLE_RATIO")
+            .map(|ratio| ratio.parse::<f64>().context("Invalid TRACE_SAMPLE_RATIO"))
+            .transpose()?;
+        let rate_limit = var("TRACE_RATE_LIMIT")
+            .map(|limit| limit.parse::<f64>().context("Invalid TRACE_RATE_LIMIT"))
+            .transpose()?;
+        if ratio.is_none() && rate_limit.is_none() {
+            return Ok(None);
+        }
+        let sampler = Self::ratio(ratio.unwrap_or(1.0))?;
+        match rate_limit {
+            Some(rate_limit) => sampler.with_rate_limit(rate_limit).map(Some),
+            None => Ok(Some(sampler)),
+        }
+    }
+}
+
+impl ShouldSample for TraceSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let mut result = Sampler::TraceIdRatioBased(self.ratio).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        );
+        if result.decision == SamplingDecision::RecordAndSample
+            && self
+                .rate_limit
+                .as_ref()
+                .is_some_and(|limit| !limit.try_acquire())
+        {
+            result.decision = SamplingDecision::Drop;
+        }
+        result
+    }
+}
+
+/// A token bucket refilled with `per_second` tokens per second.
+#[derive(Debug, Clone)]
+struct RateLimit {
+    per_second: f64,
+    bucket: Arc<Mutex<(f64, Instant)>>,
+}
+
+impl RateLimit {
+    fn new(per_second: f64) -> Self {
+        Self {
+            per_second,
+            bucket: Arc::new(Mutex::new((per_second.max(1.0), Instant::now()))),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, refilled_at) = &mut *bucket;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.per_second)
+            .min(self.per_second.max(1.0));
+        *refilled_at = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Writes each span as a line of JSON, with its ids, times, status, attributes and events.
+#[derive(Debug)]
+pub struct JsonlExporter {
+    file: Mutex<File>,
+    service_name: Option<String>,
+}
+
+impl JsonlExporter {
+    /// Append the spans to the file, creating it if needed.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open the trace file {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            service_name: None,
+        })
+    }
+
+    fn write(&self, batch: &[SpanData]) -> std::io::Result<()> {
+        let mut lines = String::new();
+        for span in batch {
+            lines.push_str(&span_json(span, self.service_name.as_deref()).to_string());
+            lines.push('\n');
+        }
+        let mut file = self.file.lock().unwrap();
+        file.write_all(lines.as_bytes())?;
+        file.flush()
+    }
+}
+
+impl SpanExporter for JsonlExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        self.write(&batch)
+            .map_err(|e| OTelSdkError::InternalFailure(format!("Failed to write the spans: {}", e)))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.service_name = resource
+            .get(&Key::new("service.name"))
+            .map(|name| name.to_string());
+    }
+}
+
+/// Exports the spans, and writes those that fail to export to a JSONL file.
+#[derive(Debug)]
+pub struct FallbackExporter<E> {
+    exporter: E,
+    fallback: JsonlExporter,
+}
+
+impl<E: SpanExporter> FallbackExporter<E> {
+    pub fn new(exporter: E, fallback: JsonlExporter) -> Self {
+        Self { exporter, fallback }
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for FallbackExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        match self.exporter.export(batch.clone()).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                log::warn!(
+                    "Failed to export {} spans, writing them to the fallback file: {}",
+                    batch.len(),
+                    e
+                );
+                self.fallback.export(batch).await
+            }
+        }
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        self.exporter.shutdown()
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.exporter.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.exporter.set_resource(resource);
+        self.fallback.set_resource(resource);
+    }
+}
+
+fn time_json(time: SystemTime) -> Value {
+    json!(DateTime::<Utc>::from(time).to_rfc3339())
+}
+
+fn value_json(value: &OtelValue) -> Value {
+    match value {
+        OtelValue::Bool(value) => json!(value),
+        OtelValue::I64(value) => json!(value),
+        OtelValue::F64(value) => json!(value),
+        OtelValue::String(value) => json!(value.as_str()),
+        OtelValue::Array(Array::Bool(values)) => json!(values),
+        OtelValue::Array(Array::I64(values)) => json!(values),
+        OtelValue::Array(Array::F64(values)) => json!(values),
+        OtelValue::Array(Array::String(values)) => {
+            json!(values
+                .iter()
+                .map(|value| value.as_str())
+                .collect::<Vec<_>>())
+        }
+        value => json!(value.to_string()),
+    }
+}
+
+fn attributes_json(attributes: &[KeyValue]) -> Value {
+    Value::Object(
+        attributes
+            .iter()
+            .map(|attribute| (attribute.key.to_string(), value_json(&attribute.value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+fn span_json(span: &SpanData, service_name: Option<&str>) -> Value {
+    let parent_span_id = (span.parent_span_id != opentelemetry::trace::SpanId::INVALID)
+        .then(|| span.parent_span_id.to_string());
+    let status = match &span.status {
+        Status::Unset => json!("unset"),
+        Status::Ok => json!("ok"),
+        Status::Error { description } => json!({ "error": description }),
+    };
+    let duration_ms = span
+        .end_time
+        .duration_since(span.start_time)
+        .map(|duration| duration.as_secs_f64() * 1000.0)
+        .unwrap_or_default();
+    json!({
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": parent_span_id,
+        "name": span.name,
+        "kind": format!("{:?}", span.span_kind),
+        "service": service_name,
+        "scope": span.instrumentation_scope.name(),
+        "start_time": time_json(span.start_time),
+        "end_time": time_json(span.end_time),
+        "duration_ms": duration_ms,
+        "status": status,
+        "attributes": attributes_json(&span.attributes),
+        "events": span.events.iter().map(|event| json!({
+            "name": event.name,
+            "time": time_json(event.timestamp),
+            "attributes": attributes_json(&event.attributes),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// The exporter and the sampler of the traces.
+#[derive(Debug, Clone)]
+pub struct TraceConfig {
+    pub exporter: TraceExporter,
+    /// The file the spans that fail to export are written to. Not used by the JSONL exporter.
+    pub fallback_file: Option<PathBuf>,
+    /// Every trace is kept when `None`, unless the `OTEL_TRACES_SAMPLER` variables say otherwise.
+    pub sampler: Option<TraceSampler>,
+}
+
+impl TraceConfig {
+    /// The configuration of the environment, `None` when tracing is off.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(exporter) = TraceExporter::from_env()? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            exporter,
+            fallback_file: std::env::var("TRACE_FALLBACK_FILE")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            sampler: TraceSampler::from_env()?,
+        }))
+    }
+
+    /// The tracer provider of the configuration, exporting the spans in batches.
+    pub fn build(&self) -> Result<SdkTracerProvider> {
+        let batch_config = || {
+            BatchConfigBuilder::default()
+                .with_max_queue_size(512)
+                .build()
+        };
+        let processor = match (self.exporter.otlp_exporter()?, &self.fallback_file) {
+            (Some(exporter), Some(fallback_file)) => BatchSpanProcessor::builder(
+                FallbackExporter::new(exporter, JsonlExporter::new(fallback_file)?),
+            )
+            .with_batch_config(batch_config())
+            .build(),
+            (Some(exporter), None) => BatchSpanProcessor::builder(exporter)
+                .with_batch_config(batch_config())
+                .build(),
+            (None, _) => {
+                let TraceExporter::Jsonl { path } = &self.exporter else {
+                    unreachable!("only the JSONL exporter is not an OTLP exporter");
+                };
+                BatchSpanProcessor::builder(JsonlExporter::new(path)?)
+                    .with_batch_config(batch_config())
+                    .build()
+            }
+        };
+
+        let mut builder = SdkTracerProvider::builder()
+            .with_span_processor(processor)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name("lumo")
+                    .with_attributes(vec![
+                        KeyValue::new(
+                            "deployment.environment",
+                            if cfg!(debug_assertions) {
+                                "development".to_string()
+                            } else {
+                                std::env::var("ENVIRONMENT")
+                                    .unwrap_or_else(|_| "production".to_string())
+                            },
+                        ),
+                        KeyValue::new("deployment.name", "lumo"),
+                        KeyValue::new("deployment.version", env!("CARGO_PKG_VERSION")),
+                    ])
+                    .build(),
+            );
+        if let Some(sampler) = &self.sampler {
+            builder = builder.with_sampler(Sampler::ParentBased(Box::new(sampler.clone())));
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Set the global tracer provider from the environment. Returns the provider, to flush and shut it down on exit, and
+/// where the spans go. `None` when tracing is off or its configuration is invalid, which is logged.
+pub fn init_tracer() -> Option<(SdkTracerProvider, String)> {
+    let config = match TraceConfig::from_env() {
+        Ok(Some(config)) => config,
+        Ok(None) => return None,
+        Err(e) => {
+            log::error!("Invalid tracing configuration, tracing is off: {:#}", e);
+            return None;
+        }
+    };
+    let tracer_provider = match config.build() {
+        Ok(tracer_provider) => tracer_provider,
+        Err(e) => {
+            log::error!("Failed to start tracing: {:#}", e);
+            return None;
+        }
+    };
+
+    // Initialize the tracer
+    let _ = tracer_provider.tracer("lumo");
+
+    // Set the global tracer provider
+    global::set_tracer_provider(tracer_provider.clone());
+
+    Some((tracer_provider, config.exporter.destination()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Span, TraceContextExt, Tracer};
+    use std::collections::HashMap;
+
+    fn exporter(vars: &[(&str, &str)]) -> Result<Option<TraceExporter>> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        TraceExporter::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_trace_exporter() {
+        assert_eq!(exporter(&[]).unwrap(), None);
+        let suffix = if cfg!(debug_assertions) { "_DEV" } else { "" };
+        let langfuse = [
+            (format!("LANGFUSE_PUBLIC_KEY{}", suffix), "pk"),
+            (format!("LANGFUSE_SECRET_KEY{}", suffix), "sk"),
+            (
+                format!("LANGFUSE_HOST{}", suffix),
+                "https://cloud.langfuse.com",
+            ),
+        ];
+        let mut vars = langfuse
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect::<Vec<_>>();
+        vars.push(("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"));
+        assert_eq!(
+            exporter(&vars).unwrap().unwrap().destination(),
+            "https://cloud.langfuse.com"
+        );
+        vars.push(("TRACE_EXPORTER", "otlp"));
+        assert_eq!(
+            exporter(&vars).unwrap(),
+            Some(TraceExporter::Otlp { endpoint: None })
+        );
+
+        assert_eq!(
+            exporter(&[("TRACE_FILE", "/var/log/lumo/traces.jsonl")]).unwrap(),
+            Some(TraceExporter::Jsonl {
+                path: "/var/log/lumo/traces.jsonl".into()
+            })
+        );
+        assert_eq!(
+            exporter(&[("TRACE_EXPORTER", "jsonl")]).unwrap(),
+            Some(TraceExporter::Jsonl {
+                path: DEFAULT_TRACE_FILE.into()
+            })
+        );
+        assert_eq!(
+            exporter(&[("TRACE_EXPORTER", "otlp")]).unwrap(),
+            Some(TraceExporter::Otlp {
+                endpoint: Some(DEFAULT_OTLP_ENDPOINT.to_string())
+            })
+        );
+        assert_eq!(
+            exporter(&[("TRACE_EXPORTER", "none"), ("TRACE_FILE", "traces.jsonl")]).unwrap(),
+            None
+        );
+        assert!(exporter(&[("TRACE_EXPORTER", "langfuse")]).is_err());
+        assert!(exporter(&[("TRACE_EXPORTER", "zipkin")]).is_err());
+
+        // The keys are not printed.
+        let debug = format!("{:?}", exporter(&vars[..3]).unwrap().unwrap());
+        assert!(!debug.contains("sk"));
+    }
+
+    #[test]
+    fn test_trace_sampler() {
+        let sample = |sampler: &TraceSampler, trace_id: u128| {
+            sampler
+                .should_sample(
+                    None,
+                    TraceId::from(trace_id),
+                    "run",
+                    &SpanKind::Internal,
+                    &[],
+                    &[],
+                )
+                .decision
+        };
+        assert!(TraceSampler::ratio(1.5).is_err());
+        let none = TraceSampler::ratio(0.0).unwrap();
+        assert_eq!(sample(&none, 1), SamplingDecision::Drop);
+
+        let limited = TraceSampler::ratio(1.0)
+            .unwrap()
+            .with_rate_limit(2.0)
+            .unwrap();
+        let decisions = (1..=3).map(|id| sample(&limited, id)).collect::<Vec<_>>();
+        assert_eq!(
+            decisions,
+            vec![
+                SamplingDecision::RecordAndSample,
+                SamplingDecision::RecordAndSample,
+                SamplingDecision::Drop
+            ]
+        );
+        assert!(TraceSampler::ratio(1.0)
+            .unwrap()
+            .with_rate_limit(0.0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_jsonl_exporter() {
+        let path = std::env::temp_dir().join(format!("lumo-traces-{}.jsonl", nanoid::nanoid!()));
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(JsonlExporter::new(&path).unwrap())
+            .with_resource(Resource::builder().with_service_name("lumo").build())
+            .build();
+        let tracer = provider.tracer("test");
+        let mut run = tracer.start("run");
+        run.set_attribute(KeyValue::new("input.value", "What is 2 + 2?"));
+        let cx = Context::current_with_span(run);
+        let mut step = tracer.start_with_context("step", &cx);
+        step.add_event("tool call", vec![KeyValue::new("tool", "calculator")]);
+        step.set_status(Status::error("timeout"));
+        step.end();
+        cx.span().end();
+        provider.force_flush().unwrap();
+
+        let spans = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(spans.len(), 2);
+        let (step, run) = (&spans[0], &spans[1]);
+        assert_eq!(step["name"], "step");
+        assert_eq!(step["trace_id"], run["trace_id"]);
+        assert_eq!(step["parent_span_id"], run["span_id"]);
+        assert_eq!(step["status"]["error"], "timeout");
+        assert_eq!(step["events"][0]["attributes"]["tool"], "calculator");
+        assert_eq!(step["service"], "lumo");
+        assert_eq!(run["parent_span_id"], Value::Null);
+        assert_eq!(run["attributes"]["input.value"], "What is 2 + 2?");
+    }
+}
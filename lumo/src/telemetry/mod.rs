@@ -1,17 +1,80 @@
+#[cfg(feature = "trace-export")]
+pub mod export;
+#[cfg(not(feature = "telemetry"))]
+pub(crate) mod noop;
+
+/// The OpenTelemetry API, or stand-ins for it that do nothing when the `telemetry` feature is off.
+#[cfg(not(feature = "telemetry"))]
+pub(crate) use noop as otel;
+#[cfg(feature = "telemetry")]
+pub(crate) use opentelemetry as otel;
+
+use std::collections::HashMap;
+
 use chrono;
-use opentelemetry::{
+use otel::{
     global::{self},
-    trace::{SpanKind, Status, TraceContextExt, Tracer},
-    Context, KeyValue,
+    trace::{Span, SpanKind, Status, TraceContextExt, Tracer},
+    Array, Context, KeyValue, StringValue, Value as OtelValue,
 };
 use serde_json::Value;
 use tracing;
 
-use crate::models::openai::ToolCall;
+use crate::{agent::AnswerValidation, models::openai::ToolCall};
+
+/// Tags and metadata of a run, added to all of its spans so traces can be filtered, e.g. by user, tenant or
+/// experiment. They are exported with the attribute names used by Langfuse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunMetadata {
+    pub tags: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl RunMetadata {
+    pub fn new(tags: Vec<String>, metadata: HashMap<String, String>) -> Self {
+        Self { tags, metadata }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.metadata.is_empty()
+    }
+
+    pub fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = Vec::new();
+        if !self.tags.is_empty() {
+            let tags = self.tags.iter().cloned().map(StringValue::from).collect::<Vec<_>>();
+            attributes.push(KeyValue::new(
+                "langfuse.trace.tags",
+                OtelValue::Array(Array::String(tags)),
+            ));
+        }
+        for (key, value) in &self.metadata {
+            attributes.push(KeyValue::new(
+                format!("langfuse.trace.metadata.{}", key),
+                value.clone(),
+            ));
+            match key.as_str() {
+                "user_id" => attributes.push(KeyValue::new("user.id", value.clone())),
+                "session_id" => attributes.push(KeyValue::new("session.id", value.clone())),
+                _ => {}
+            }
+        }
+        attributes
+    }
+
+    /// The attributes of the run metadata attached to the current context, if any.
+    pub fn current_attributes() -> Vec<KeyValue> {
+        Context::current()
+            .get::<RunMetadata>()
+            .map(RunMetadata::attributes)
+            .unwrap_or_default()
+    }
+}
 
 pub struct AgentTelemetry {
     tracer_name: String,
     current_context: Option<Context>,
+    run_metadata: RunMetadata,
 }
 
 impl AgentTelemetry {
@@ -19,9 +82,18 @@ impl AgentTelemetry {
         Self {
             tracer_name: tracer_name.to_string(),
             current_context: None,
+            run_metadata: RunMetadata::default(),
         }
     }
 
+    pub fn set_run_metadata(&mut self, run_metadata: RunMetadata) {
+        self.run_metadata = run_metadata;
+    }
+
+    pub fn run_metadata(&self) -> &RunMetadata {
+        &self.run_metadata
+    }
+
     pub fn start_step(&mut self, step_number: i64) -> Context {
         let parent_cx = Context::current();
         let tracer_name = self.tracer_name.clone();
@@ -30,10 +102,10 @@ impl AgentTelemetry {
         // Get current timestamp for consistent ordering
         let start_time = chrono::Local::now().to_rfc3339();
 
-        let span = tracer
+        let mut span = tracer
             .span_builder(format!("Step {}", step_number))
             .with_kind(SpanKind::Internal)
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .with_attributes(vec![
                 KeyValue::new("gen_ai.operation.name", "agent_step"),
                 KeyValue::new("step_type", "action"),
@@ -41,8 +113,10 @@ impl AgentTelemetry {
                 KeyValue::new("start_time", start_time),
             ])
             .start_with_context(&tracer, &parent_cx);
+        span.set_attributes(self.run_metadata.attributes());
 
-        let cx = Context::current_with_span(span);
+        // The model and tool spans of the step read the run metadata from the context.
+        let cx = Context::current_with_span(span).with_value(self.run_metadata.clone());
         self.current_context = Some(cx.clone());
         cx
     }
@@ -84,16 +158,17 @@ impl AgentTelemetry {
         cx: &Context,
     ) -> Context {
         let tracer = global::tracer("lumo");
-        let span = tracer
+        let mut span = tracer
             .span_builder(function_name.to_string())
             .with_kind(SpanKind::Internal)
             .with_attributes(vec![
                 KeyValue::new("gen_ai.operation.name", "tool_calls"),
                 KeyValue::new("timestamp", chrono::Utc::now().to_rfc3339()),
             ])
-            .with_start_time(std::time::SystemTime::now())
+            .with_start_time(crate::runtime::SystemTime::now())
             .start_with_context(&tracer, cx);
-        let cx = Context::current_with_span(span);
+        span.set_attributes(self.run_metadata.attributes());
+        let cx = cx.with_span(span);
 
         cx.span()
             .set_attribute(KeyValue::new("gen_ai.tool.name", function_name.to_string()));
@@ -126,6 +201,38 @@ impl AgentTelemetry {
             .set_attribute(KeyValue::new("output.value", result.to_string()));
     }
 
+    /// Record that a tool is no longer available for the run after failing `failures` times in a row.
+    pub fn log_circuit_breaker_open(&self, tool: &str, failures: usize, cx: &Context) {
+        let tracer = global::tracer(self.tracer_name.clone());
+        let mut span = tracer
+            .span_builder("Circuit breaker")
+            .with_kind(SpanKind::Internal)
+            .with_start_time(crate::runtime::SystemTime::now())
+            .with_attributes(vec![
+                KeyValue::new("gen_ai.operation.name", "circuit_breaker"),
+                KeyValue::new("circuit_breaker.tool", tool.to_string()),
+                KeyValue::new("circuit_breaker.state", "open"),
+                KeyValue::new("circuit_breaker.failures", failures as i64),
+            ])
+            .start_with_context(&tracer, cx);
+        span.set_attributes(self.run_metadata.attributes());
+        span.set_status(Status::error("Tool unavailable"));
+        span.end();
+        tracing::warn!(tool = %tool, failures, "Tool made unavailable after repeated failures");
+    }
+
+    /// Record the tools that are unavailable for the rest of the run on the step.
+    pub fn log_unavailable_tools(&self, tools: &[String], cx: &Context) {
+        if !tools.is_empty() {
+            cx.span().set_attribute(KeyValue::new(
+                "circuit_breaker.open_tools",
+                OtelValue::Array(Array::String(
+                    tools.iter().map(|tool| StringValue::from(tool.clone())).collect(),
+                )),
+            ));
+        }
+    }
+
     pub fn log_final_answer(&self, answer: &str) {
         if let Some(cx) = &self.current_context {
             tracing::info!(answer = %answer, "Final answer received");
@@ -153,7 +260,7 @@ impl AgentTelemetry {
     pub fn end_step(&mut self) {
         if let Some(cx) = self.current_context.take() {
             // End the span with the current timestamp
-            let end_time = std::time::SystemTime::now();
+            let end_time = crate::runtime::SystemTime::now();
             cx.span()
                 .set_attribute(KeyValue::new("end_time", chrono::Utc::now().to_rfc3339()));
             cx.span().end_with_timestamp(end_time);
@@ -165,3 +272,49 @@ impl AgentTelemetry {
         }
     }
 }
+
+/// Record the verdict on a final answer as a span of the current run.
+pub fn log_answer_validation(validation: &AnswerValidation) {
+    let tracer = global::tracer("lumo");
+    let mut span = tracer
+        .span_builder("Answer validation")
+        .with_kind(SpanKind::Internal)
+        .with_start_time(crate::runtime::SystemTime::now())
+        .with_attributes(vec![
+            KeyValue::new("gen_ai.operation.name", "answer_validation"),
+            KeyValue::new("answer_validation.valid", validation.valid),
+            KeyValue::new("answer_validation.reason", validation.reason.clone()),
+            KeyValue::new(
+                "answer_validation.supporting_observations",
+                validation.supporting_observations.len() as i64,
+            ),
+        ])
+        .start_with_context(&tracer, &Context::current());
+    span.set_attributes(RunMetadata::current_attributes());
+    if !validation.valid {
+        span.set_status(Status::error("Final answer rejected"));
+    }
+    span.end();
+}
+
+#[cfg(all(test, feature = "telemetry"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_metadata_attributes() {
+        let run_metadata = RunMetadata::new(
+            vec!["experiment-a".to_string()],
+            HashMap::from([("user_id".to_string(), "user-1".to_string())]),
+        );
+        let attributes = run_metadata.attributes();
+        let keys = attributes.iter().map(|kv| kv.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["langfuse.trace.tags", "langfuse.trace.metadata.user_id", "user.id"]
+        );
+
+        let _guard = Context::current().with_value(run_metadata).attach();
+        assert_eq!(RunMetadata::current_attributes().len(), 3);
+    }
+}
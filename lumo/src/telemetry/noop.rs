@@ -0,0 +1,180 @@
+//! Stand-ins for the parts of the OpenTelemetry API used by the agents and models, when the `telemetry` feature is
+//! off. They have the same names and signatures, and do nothing: no span is created, and the attributes are dropped.
+
+use std::borrow::Cow;
+
+use crate::runtime::SystemTime;
+
+#[derive(Debug, Clone, Default)]
+pub struct Context;
+
+impl Context {
+    pub fn current() -> Self {
+        Context
+    }
+
+    pub fn with_value<T>(&self, _value: T) -> Self {
+        Context
+    }
+
+    pub fn get<T>(&self) -> Option<&T> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyValue;
+
+impl KeyValue {
+    pub fn new<K, V>(_key: K, _value: V) -> Self {
+        KeyValue
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StringValue;
+
+impl From<String> for StringValue {
+    fn from(_value: String) -> Self {
+        StringValue
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum Array {
+    String(Vec<StringValue>),
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum Value {
+    Array(Array),
+}
+
+pub mod global {
+    use super::*;
+
+    pub fn tracer(_name: impl Into<Cow<'static, str>>) -> trace::NoopTracer {
+        trace::NoopTracer
+    }
+}
+
+pub mod trace {
+    use super::*;
+    use std::marker::PhantomData;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SpanKind {
+        Internal,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Status {
+        Error { description: Cow<'static, str> },
+    }
+
+    impl Status {
+        pub fn error(description: impl Into<Cow<'static, str>>) -> Self {
+            Status::Error {
+                description: description.into(),
+            }
+        }
+    }
+
+    pub trait Tracer {
+        fn span_builder(&self, _name: impl Into<Cow<'static, str>>) -> SpanBuilder {
+            SpanBuilder
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct NoopTracer;
+
+    impl Tracer for NoopTracer {}
+
+    #[derive(Debug, Clone)]
+    pub struct SpanBuilder;
+
+    impl SpanBuilder {
+        pub fn with_kind(self, _kind: SpanKind) -> Self {
+            self
+        }
+
+        pub fn with_start_time(self, _start_time: impl Into<SystemTime>) -> Self {
+            self
+        }
+
+        pub fn with_attributes(self, _attributes: impl IntoIterator<Item = KeyValue>) -> Self {
+            self
+        }
+
+        pub fn start_with_context(self, _tracer: &impl Tracer, _parent_cx: &Context) -> NoopSpan {
+            NoopSpan
+        }
+    }
+
+    pub trait Span {
+        fn set_attribute(&mut self, _attribute: KeyValue) {}
+
+        fn set_attributes(&mut self, _attributes: impl IntoIterator<Item = KeyValue>) {}
+
+        fn set_status(&mut self, _status: Status) {}
+
+        fn end(&mut self) {}
+
+        fn end_with_timestamp(&mut self, _timestamp: SystemTime) {}
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct NoopSpan;
+
+    impl Span for NoopSpan {}
+
+    /// The span of a context, see [`TraceContextExt::span`].
+    #[derive(Debug)]
+    pub struct SpanRef<'a>(PhantomData<&'a Context>);
+
+    impl SpanRef<'_> {
+        pub fn set_attribute(&self, _attribute: KeyValue) {}
+
+        pub fn set_attributes(&self, _attributes: impl IntoIterator<Item = KeyValue>) {}
+
+        pub fn set_status(&self, _status: Status) {}
+
+        pub fn end(&self) {}
+
+        pub fn end_with_timestamp(&self, _timestamp: SystemTime) {}
+    }
+
+    pub trait TraceContextExt {
+        fn current_with_span(span: NoopSpan) -> Self;
+
+        fn with_span(&self, span: NoopSpan) -> Self;
+
+        fn span(&self) -> SpanRef<'_>;
+    }
+
+    impl TraceContextExt for Context {
+        fn current_with_span(_span: NoopSpan) -> Self {
+            Context
+        }
+
+        fn with_span(&self, _span: NoopSpan) -> Self {
+            Context
+        }
+
+        fn span(&self) -> SpanRef<'_> {
+            SpanRef(PhantomData)
+        }
+    }
+
+    pub trait FutureExt: Sized {
+        /// The future itself, as there is no context to attach.
+        fn with_context(self, _cx: Context) -> Self {
+            self
+        }
+    }
+
+    impl<T: Sized> FutureExt for T {}
+}
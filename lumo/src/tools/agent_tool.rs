@@ -0,0 +1,208 @@
+//! This module contains the agent tool. It wraps any agent so that it can be called by another agent like a regular tool.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+
+use super::tool_traits::{AnyTool, AsyncTool, ToolFunctionInfo, ToolInfo, ToolType};
+use crate::{agent::Agent, errors::AgentError, models::openai::Status};
+
+/// Statuses of a managed agent buffered before they are forwarded to the calling agent.
+const STATUS_CHANNEL_CAPACITY: usize = 1024;
+
+/// Wraps an [`Agent`] so it can be used anywhere an [`AsyncTool`] is expected.
+///
+/// The wrapped agent is run with `reset = true` on every call. When the calling agent streams, the tokens and steps
+/// of the wrapped agent are sent to its stream as [`Status::ManagedAgent`]. The `task` argument is passed to the
+/// agent as its task; any other arguments are appended to the task as JSON so sub-agents can take
+/// richer parameter schemas.
+#[derive(Clone)]
+pub struct AgentTool {
+    name: &'static str,
+    description: &'static str,
+    parameters: Value,
+    agent: Arc<Mutex<Box<dyn Agent>>>,
+}
+
+impl AgentTool {
+    pub fn new(agent: Box<dyn Agent>) -> Self {
+        Self {
+            name: agent.name(),
+            description: agent.description(),
+            parameters: default_parameters(),
+            agent: Arc::new(Mutex::new(agent)),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Box::leak(name.to_string().into_boxed_str());
+        self
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Box::leak(description.to_string().into_boxed_str());
+        self
+    }
+
+    /// Set the JSON schema of the arguments the calling model should provide.
+    pub fn with_parameters(mut self, parameters: Value) -> Self {
+        self.parameters = parameters;
+        self
+    }
+}
+
+fn default_parameters() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "task": {
+                "type": "string",
+                "description": "The task to perform. Be very verbose and include all the context the team member needs."
+            }
+        },
+        "required": ["task"]
+    })
+}
+
+/// Build the task given to the wrapped agent from the tool call arguments.
+pub fn task_from_arguments(arguments: &Value) -> String {
+    match arguments {
+        Value::String(task) => task.clone(),
+        Value::Object(map) => {
+            let task = map.get("task").and_then(Value::as_str);
+            let extra = map
+                .iter()
+                .filter(|(key, _)| key.as_str() != "task")
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<serde_json::Map<_, _>>();
+            match (task, extra.is_empty()) {
+                (Some(task), true) => task.to_string(),
+                (Some(task), false) => format!(
+                    "{}\n\nAdditional arguments: {}",
+                    task,
+                    Value::Object(extra)
+                ),
+                (None, _) => Value::Object(extra).to_string(),
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Run a managed agent on `task`. With `tx`, its statuses are forwarded to `tx` while it runs, tagged with `name`.
+pub async fn run_managed_agent(
+    agent: &mut dyn Agent,
+    name: &str,
+    task: &str,
+    tx: Option<broadcast::Sender<Status>>,
+) -> Result<String, AgentError> {
+    let Some(tx) = tx else {
+        return agent.run(task, true).await;
+    };
+    let forward = |status: Status| {
+        let _ = tx.send(Status::ManagedAgent {
+            agent: name.to_string(),
+            status: Box::new(status),
+        });
+    };
+    let (agent_tx, mut agent_rx) = broadcast::channel(STATUS_CHANNEL_CAPACITY);
+    let run = agent.run_with_status(task, true, Some(agent_tx));
+    tokio::pin!(run);
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            status = agent_rx.recv() => match status {
+                Ok(status) => forward(status),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(agent = %name, "Skipped {} statuses of the managed agent", skipped)
+                }
+                Err(RecvError::Closed) => break run.await,
+            },
+        }
+    };
+    // The statuses sent just before the run ended.
+    loop {
+        match agent_rx.try_recv() {
+            Ok(status) => forward(status),
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => break,
+        }
+    }
+    result
+}
+
+impl AnyTool for AgentTool {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        ToolInfo {
+            tool_type: ToolType::Function,
+            function: ToolFunctionInfo {
+                name: self.name.to_string(),
+                description: self.description.to_string(),
+                parameters: self.parameters.clone(),
+                strict: None,
+            },
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncTool for AgentTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        let task = task_from_arguments(&json_args);
+        tracing::info!(agent = %self.name, "Executing tool call: Agent Selected {}", self.name);
+        let mut agent = self.agent.lock().await;
+        agent.run(&task, true).await
+    }
+
+    async fn forward_json_with_status(
+        &self,
+        json_args: Value,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
+        let task = task_from_arguments(&json_args);
+        tracing::info!(agent = %self.name, "Executing tool call: Agent Selected {}", self.name);
+        let mut agent = self.agent.lock().await;
+        run_managed_agent(agent.as_mut(), self.name, &task, tx).await
+    }
+
+    async fn shutdown(&self) -> Result<(), AgentError> {
+        self.agent.lock().await.shutdown().await
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_from_arguments() {
+        assert_eq!(
+            task_from_arguments(&json!({"task": "Find the weather"})),
+            "Find the weather"
+        );
+        assert_eq!(
+            task_from_arguments(&json!({"task": "Find the weather", "city": "Paris"})),
+            "Find the weather\n\nAdditional arguments: {\"city\":\"Paris\"}"
+        );
+        assert_eq!(
+            task_from_arguments(&json!({"city": "Paris"})),
+            "{\"city\":\"Paris\"}"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! This module contains the ask user tool. The model uses this tool to ask the user a clarification question when
+//! the task is ambiguous, instead of guessing.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use super::{base::BaseTool, tool_traits::Tool};
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "AskUserToolParams")]
+pub struct AskUserToolParams {
+    #[schemars(description = "The question to ask the user")]
+    question: String,
+    #[schemars(description = "What you will assume if the user does not answer")]
+    assumption: Option<String>,
+}
+
+/// Where the questions of the agent are answered, e.g. a terminal prompt or a client of the server.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait UserInput: Send + Sync {
+    /// Ask the user a question and wait for the answer. Returns `None` when the user gives no answer.
+    async fn ask(&self, question: &str) -> Result<Option<String>>;
+}
+
+/// Pauses the run to ask the user a question. When the user does not answer within the timeout, the run continues
+/// with the assumption of the model, which is recorded in the observation.
+#[derive(Clone)]
+pub struct AskUserTool {
+    pub tool: BaseTool,
+    input: Arc<dyn UserInput>,
+    timeout: Option<Duration>,
+}
+
+impl Debug for AskUserTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AskUserTool")
+            .field("tool", &self.tool)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl AskUserTool {
+    pub fn new(input: impl UserInput + 'static) -> Self {
+        AskUserTool {
+            tool: BaseTool {
+                name: "ask_user",
+                description: "Asks the user a clarification question and returns the answer. Use this only when the task is ambiguous and you cannot find the missing information with the other tools.",
+            },
+            input: Arc::new(input),
+            timeout: None,
+        }
+    }
+
+    /// How long to wait for the answer. Waits indefinitely by default.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn forward(&self, question: &str, assumption: Option<&str>) -> Result<String> {
+        let answer = match self.timeout {
+            Some(timeout) => crate::runtime::timeout(timeout, self.input.ask(question))
+                .await
+                .unwrap_or(Ok(None))?,
+            None => self.input.ask(question).await?,
+        };
+        match answer.filter(|answer| !answer.trim().is_empty()) {
+            Some(answer) => Ok(format!("The user answered: {}", answer)),
+            None => {
+                tracing::info!(question = %question, assumption = ?assumption, "The user did not answer");
+                Ok(match assumption {
+                    Some(assumption) => format!(
+                        "The user did not answer. Continue with the assumption: {}. State this assumption in the final answer.",
+                        assumption
+                    ),
+                    None => "The user did not answer. Continue with your best assumption and state it in the final answer.".to_string(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for AskUserTool {
+    type Params = AskUserToolParams;
+
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: AskUserToolParams) -> Result<String> {
+        self.forward(&arguments.question, arguments.assumption.as_deref())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedInput(Option<&'static str>);
+
+    #[async_trait]
+    impl UserInput for FixedInput {
+        async fn ask(&self, _question: &str) -> Result<Option<String>> {
+            Ok(self.0.map(|answer| answer.to_string()))
+        }
+    }
+
+    struct SilentInput;
+
+    #[async_trait]
+    impl UserInput for SilentInput {
+        async fn ask(&self, _question: &str) -> Result<Option<String>> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ask_user_tool() {
+        let tool = AskUserTool::new(FixedInput(Some("Paris, France")));
+        let observation = tool.forward("Which Paris?", None).await.unwrap();
+        assert_eq!(observation, "The user answered: Paris, France");
+
+        let tool = AskUserTool::new(FixedInput(None));
+        let observation = tool.forward("Which Paris?", Some("Paris, France")).await.unwrap();
+        assert!(observation.contains("Continue with the assumption: Paris, France"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_user_timeout() {
+        let tool = AskUserTool::new(SilentInput).with_timeout(Some(Duration::from_millis(10)));
+        let observation = tool.forward("Which Paris?", Some("Paris, France")).await.unwrap();
+        assert!(observation.starts_with("The user did not answer"));
+    }
+}
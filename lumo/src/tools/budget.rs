@@ -0,0 +1,298 @@
+//! Budgets for tools with paid APIs, like Exa, Tavily or Google Search.
+//!
+//! A [`BudgetedTool`] wraps a paid tool and counts its calls, per run and per day. Once the budget is spent, the
+//! calls go to a free fallback tool, e.g. DuckDuckGo, and the observation tells the model about the downgrade.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::tool_traits::{AnyTool, AsyncTool, ToolInfo};
+use crate::errors::AgentError;
+
+/// The calls a tool may make. Unlimited when not set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolBudget {
+    /// Calls of the tool in a run. Counted per tool instance, so an agent reused for several runs shares it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls_per_run: Option<usize>,
+    /// Calls of the tool per UTC day, across the runs sharing the same [`DailyUsage`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls_per_day: Option<usize>,
+}
+
+/// The calls of the tools today, by tool name. Kept in memory, so it starts over with the process.
+#[derive(Debug, Default)]
+pub struct DailyUsage {
+    calls: Mutex<HashMap<String, (NaiveDate, usize)>>,
+}
+
+impl DailyUsage {
+    /// The usage shared by the whole process.
+    pub fn global() -> Arc<DailyUsage> {
+        static USAGE: OnceLock<Arc<DailyUsage>> = OnceLock::new();
+        USAGE.get_or_init(Default::default).clone()
+    }
+
+    /// The calls of the tool today.
+    pub fn calls_today(&self, tool: &str) -> usize {
+        let today = Utc::now().date_naive();
+        match self.calls.lock().unwrap().get(tool) {
+            Some((day, calls)) if *day == today => *calls,
+            _ => 0,
+        }
+    }
+
+    /// Count a call of the tool if it is within the limit.
+    fn try_call(&self, tool: &str, limit: Option<usize>) -> bool {
+        let today = Utc::now().date_naive();
+        let mut calls = self.calls.lock().unwrap();
+        let (day, count) = calls.entry(tool.to_string()).or_insert((today, 0));
+        if *day != today {
+            *day = today;
+            *count = 0;
+        }
+        if limit.is_some_and(|limit| *count >= limit) {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+/// A paid tool with a budget, and the free tool its calls go to once the budget is spent.
+pub struct BudgetedTool {
+    tool: Box<dyn AsyncTool>,
+    fallback: Option<Box<dyn AsyncTool>>,
+    budget: ToolBudget,
+    run_calls: Arc<AtomicUsize>,
+    usage: Arc<DailyUsage>,
+}
+
+impl BudgetedTool {
+    /// Count the calls of the tool per day with the [`DailyUsage::global`] usage.
+    pub fn new(tool: Box<dyn AsyncTool>, budget: ToolBudget) -> Self {
+        Self {
+            tool,
+            fallback: None,
+            budget,
+            run_calls: Arc::new(AtomicUsize::new(0)),
+            usage: DailyUsage::global(),
+        }
+    }
+
+    /// The tool called once the budget is spent. Without it, the calls fail.
+    pub fn with_fallback(mut self, fallback: Box<dyn AsyncTool>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    pub fn with_usage(mut self, usage: Arc<DailyUsage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Count a call against the budget. The reason the budget is spent otherwise.
+    fn try_call(&self) -> Result<(), String> {
+        let name = self.tool.name();
+        if let Some(limit) = self.budget.calls_per_run {
+            if self.run_calls.load(Ordering::SeqCst) >= limit {
+                return Err(format!("{} calls per run", limit));
+            }
+        }
+        if !self.usage.try_call(name, self.budget.calls_per_day) {
+            return Err(format!(
+                "{} calls per day",
+                self.budget.calls_per_day.unwrap_or_default()
+            ));
+        }
+        self.run_calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// The arguments the fallback takes, e.g. only the `query` of a search.
+fn fallback_arguments(arguments: &Value, fallback: &ToolInfo) -> Value {
+    let names = fallback.get_parameter_names();
+    match arguments {
+        Value::Object(arguments) => Value::Object(
+            arguments
+                .iter()
+                .filter(|(name, _)| names.contains(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+impl AnyTool for BudgetedTool {
+    fn name(&self) -> &'static str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description()
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        self.tool.tool_info()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncTool for BudgetedTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        let reason = match self.try_call() {
+            Ok(()) => return self.tool.forward_json(json_args).await,
+            Err(reason) => reason,
+        };
+        let Some(fallback) = &self.fallback else {
+            return Err(AgentError::Execution(format!(
+                "The budget of {} is spent ({}). Answer with the information you already have.",
+                self.tool.name(),
+                reason
+            )));
+        };
+        tracing::info!(
+            tool = self.tool.name(),
+            fallback = fallback.name(),
+            "Tool budget spent ({})",
+            reason
+        );
+        let observation = fallback
+            .forward_json(fallback_arguments(&json_args, &fallback.tool_info()))
+            .await?;
+        Ok(format!(
+            "[The budget of {} is spent ({}), so these results are from {} instead.]\n{}",
+            self.tool.name(),
+            reason,
+            fallback.name(),
+            observation
+        ))
+    }
+
+    async fn shutdown(&self) -> Result<(), AgentError> {
+        self.tool.shutdown().await?;
+        match &self.fallback {
+            Some(fallback) => fallback.shutdown().await,
+            None => Ok(()),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(Self {
+            tool: self.tool.clone_box(),
+            fallback: self.fallback.as_ref().map(|fallback| fallback.clone_box()),
+            budget: self.budget,
+            run_calls: self.run_calls.clone(),
+            usage: self.usage.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{base::BaseTool, tool_traits::Tool};
+    use anyhow::Result;
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct SearchParams {
+        query: String,
+        filter_year: Option<String>,
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    struct FreeSearchParams {
+        query: String,
+    }
+
+    #[derive(Clone)]
+    struct PaidSearch(BaseTool);
+
+    #[async_trait]
+    impl Tool for PaidSearch {
+        type Params = SearchParams;
+        fn name(&self) -> &'static str {
+            self.0.name
+        }
+        fn description(&self) -> &'static str {
+            self.0.description
+        }
+        async fn forward(&self, params: SearchParams) -> Result<String> {
+            Ok(format!("paid: {} {:?}", params.query, params.filter_year))
+        }
+    }
+
+    #[derive(Clone)]
+    struct FreeSearch;
+
+    #[async_trait]
+    impl Tool for FreeSearch {
+        type Params = FreeSearchParams;
+        fn name(&self) -> &'static str {
+            "free_search"
+        }
+        fn description(&self) -> &'static str {
+            "A free search"
+        }
+        async fn forward(&self, params: FreeSearchParams) -> Result<String> {
+            Ok(format!("free: {}", params.query))
+        }
+    }
+
+    fn paid_search() -> Box<dyn AsyncTool> {
+        Box::new(PaidSearch(BaseTool {
+            name: "paid_search",
+            description: "A paid search",
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_budgeted_tool() {
+        let usage = Arc::new(DailyUsage::default());
+        let budget = ToolBudget {
+            calls_per_run: Some(2),
+            calls_per_day: Some(3),
+        };
+        let tool = BudgetedTool::new(paid_search(), budget)
+            .with_fallback(Box::new(FreeSearch))
+            .with_usage(usage.clone());
+        let args = json!({"query": "rust", "filter_year": "2024"});
+        assert_eq!(
+            tool.forward_json(args.clone()).await.unwrap(),
+            "paid: rust Some(\"2024\")"
+        );
+        assert_eq!(
+            tool.clone_box().forward_json(args.clone()).await.unwrap(),
+            "paid: rust Some(\"2024\")"
+        );
+
+        // The clone shares the calls of the run, and the fallback only gets the arguments it takes.
+        let observation = tool.forward_json(args.clone()).await.unwrap();
+        assert_eq!(
+            observation,
+            "[The budget of paid_search is spent (2 calls per run), so these results are from free_search instead.]\nfree: rust"
+        );
+
+        // A new run of the same day has one call left.
+        let tool = BudgetedTool::new(paid_search(), budget).with_usage(usage.clone());
+        assert!(tool
+            .forward_json(args.clone())
+            .await
+            .unwrap()
+            .starts_with("paid"));
+        let error = tool.forward_json(args).await.unwrap_err();
+        assert!(error.to_string().contains("(3 calls per day)"));
+        assert_eq!(usage.calls_today("paid_search"), 3);
+    }
+}
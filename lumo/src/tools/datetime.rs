@@ -0,0 +1,673 @@
+//! This module contains the date and time tool. The model uses this tool to get the current time in time zones, to
+//! read dates written in words such as "next Tuesday at 3pm" in a time zone, to add durations to dates and to count
+//! the time between two dates, instead of working them out itself.
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc,
+    Weekday,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    base::BaseTool,
+    time_zone::{weekday_name, Zone},
+    tool_traits::Tool,
+};
+
+const DATE_EXAMPLES: &str =
+    "e.g. '2026-10-20T15:00:00', 'now', 'tomorrow at noon', 'next Tuesday 3pm', 'in 2 weeks' or 'March 5 2027'";
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[schemars(title = "DateTimeAction")]
+pub enum DateTimeAction {
+    /// The current time in the time zones.
+    Now,
+    /// Read a date, and show it in the time zones.
+    Parse,
+    /// Add a duration to a date.
+    Add,
+    /// The time from a date to another.
+    Difference,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "DateTimeToolParams")]
+pub struct DateTimeToolParams {
+    #[schemars(
+        description = "`now` for the current time, `parse` to read a date and convert it to other time zones, `add` to add a duration to a date, `difference` for the time between `datetime` and `end`"
+    )]
+    action: DateTimeAction,
+    #[schemars(
+        description = "The date, in ISO 8601 or in English words, e.g. 'next Tuesday 3pm', 'in 2 weeks' or 'March 5 2027'. Now when missing"
+    )]
+    datetime: Option<String>,
+    #[schemars(
+        description = "The time zone `datetime` is written in, and that relative dates such as 'tomorrow' are counted in: an IANA name such as 'Asia/Tokyo', or an offset such as 'UTC+05:30'"
+    )]
+    time_zone: Option<String>,
+    #[schemars(
+        description = "The time zones to show the result in, e.g. ['Asia/Tokyo', 'Europe/Paris']. The time zone of the date when missing"
+    )]
+    time_zones: Option<Vec<String>>,
+    #[schemars(description = "The second date of `difference`, written as `datetime`")]
+    end: Option<String>,
+    #[schemars(description = "Years to add, negative to subtract")]
+    years: Option<i32>,
+    #[schemars(description = "Months to add, negative to subtract")]
+    months: Option<i32>,
+    #[schemars(description = "Weeks to add, negative to subtract")]
+    weeks: Option<i64>,
+    #[schemars(description = "Days to add, negative to subtract")]
+    days: Option<i64>,
+    #[schemars(description = "Hours to add, negative to subtract")]
+    hours: Option<i64>,
+    #[schemars(description = "Minutes to add, negative to subtract")]
+    minutes: Option<i64>,
+}
+
+/// Gives the current time in time zones, reads dates written in ISO 8601 or in English words, converts them between
+/// time zones, adds durations to them and counts the time between two dates.
+#[derive(Debug, Clone)]
+pub struct DateTimeTool {
+    pub tool: BaseTool,
+    /// The time zone of the dates that do not name one.
+    time_zone: Zone,
+}
+
+impl Default for DateTimeTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DateTimeTool {
+    /// A tool whose dates are in the time zone of the system unless the model names one.
+    pub fn new() -> Self {
+        DateTimeTool {
+            tool: BaseTool {
+                name: "datetime",
+                description: "Gives the current date and time in any time zone, reads dates written in words such as 'next Tuesday at 3pm' or 'in 2 weeks', converts dates between time zones, adds durations to dates and counts the time between two dates. Use it instead of working out dates yourself.",
+            },
+            time_zone: Zone::local(),
+        }
+    }
+
+    pub fn with_time_zone(mut self, time_zone: Zone) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    fn run(&self, arguments: DateTimeToolParams, now: DateTime<Utc>) -> Result<Value> {
+        let zone = match &arguments.time_zone {
+            Some(name) => name.parse::<Zone>()?,
+            None => self.time_zone.clone(),
+        };
+        let targets = match &arguments.time_zones {
+            Some(names) if !names.is_empty() => names
+                .iter()
+                .map(|name| name.parse::<Zone>())
+                .collect::<Result<Vec<_>>>()?,
+            _ => vec![zone.clone()],
+        };
+        let read = |text: Option<&str>| match text.map(str::trim).filter(|text| !text.is_empty()) {
+            Some(text) => parse_datetime(text, &now, &zone),
+            None => Ok(zone.from_utc(&now)),
+        };
+        let times = |time: &DateTime<FixedOffset>| {
+            targets
+                .iter()
+                .map(|zone| describe(time, zone))
+                .collect::<Vec<_>>()
+        };
+        Ok(match arguments.action {
+            DateTimeAction::Now => json!({ "times": times(&zone.from_utc(&now)) }),
+            DateTimeAction::Parse => {
+                let time = read(arguments.datetime.as_deref())?;
+                json!({ "input": arguments.datetime, "times": times(&time) })
+            }
+            DateTimeAction::Add => {
+                let start = read(arguments.datetime.as_deref())?;
+                let time = add(&start, &zone, &arguments)?;
+                json!({ "start": describe(&start, &zone), "times": times(&time) })
+            }
+            DateTimeAction::Difference => {
+                let start = read(arguments.datetime.as_deref())?;
+                let end =
+                    read(Some(arguments.end.as_deref().ok_or_else(|| {
+                        anyhow!("end is required to count the difference")
+                    })?))?;
+                let seconds = (end - start).num_seconds();
+                let date = |time: &DateTime<FixedOffset>| {
+                    zone.from_utc(&time.with_timezone(&Utc)).date_naive()
+                };
+                let calendar_days = (date(&end) - date(&start)).num_days();
+                json!({
+                    "start": describe(&start, &zone),
+                    "end": describe(&end, &zone),
+                    "seconds": seconds,
+                    "hours": seconds as f64 / 3600.0,
+                    "days": seconds as f64 / 86400.0,
+                    "calendar_days": calendar_days,
+                    "duration": format_duration(seconds),
+                })
+            }
+        })
+    }
+}
+
+/// A time in a zone, as returned to the model.
+fn describe(time: &DateTime<FixedOffset>, zone: &Zone) -> Value {
+    let instant = time.with_timezone(&Utc);
+    let local = zone.from_utc(&instant);
+    json!({
+        "time_zone": zone.name(),
+        "datetime": local.to_rfc3339(),
+        "date": local.format("%Y-%m-%d").to_string(),
+        "time": local.format("%H:%M:%S").to_string(),
+        "weekday": weekday_name(local.weekday()),
+        "abbreviation": zone.abbreviation(&instant),
+    })
+}
+
+/// Add the duration of the arguments: the years, months, weeks and days to the date in the zone, keeping the time of
+/// the day across daylight saving time changes, then the hours and minutes.
+fn add(
+    start: &DateTime<FixedOffset>,
+    zone: &Zone,
+    arguments: &DateTimeToolParams,
+) -> Result<DateTime<FixedOffset>> {
+    let overflow = || anyhow!("The date is out of range");
+    let months = arguments.years.unwrap_or(0) as i64 * 12 + arguments.months.unwrap_or(0) as i64;
+    let mut local = zone.from_utc(&start.with_timezone(&Utc)).naive_local();
+    local = if months >= 0 {
+        local.checked_add_months(Months::new(months as u32))
+    } else {
+        local.checked_sub_months(Months::new(months.unsigned_abs() as u32))
+    }
+    .ok_or_else(overflow)?;
+    let days = arguments.weeks.unwrap_or(0) * 7 + arguments.days.unwrap_or(0);
+    local = local
+        .checked_add_signed(Duration::try_days(days).ok_or_else(overflow)?)
+        .ok_or_else(overflow)?;
+    let minutes = arguments.hours.unwrap_or(0) * 60 + arguments.minutes.unwrap_or(0);
+    let time = zone
+        .from_local(&local)
+        .checked_add_signed(Duration::try_minutes(minutes).ok_or_else(overflow)?)
+        .ok_or_else(overflow)?;
+    Ok(zone.from_utc(&time.with_timezone(&Utc)))
+}
+
+/// A duration in seconds in words, e.g. `3 days 4 hours 5 minutes`.
+fn format_duration(seconds: i64) -> String {
+    let sign = if seconds < 0 { "-" } else { "" };
+    let seconds = seconds.unsigned_abs();
+    let parts = [
+        (seconds / 86400, "day"),
+        (seconds % 86400 / 3600, "hour"),
+        (seconds % 3600 / 60, "minute"),
+        (seconds % 60, "second"),
+    ]
+    .into_iter()
+    .filter(|(value, _)| *value > 0)
+    .map(|(value, unit)| format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" }))
+    .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "0 seconds".to_string()
+    } else {
+        format!("{}{}", sign, parts.join(" "))
+    }
+}
+
+/// A date, or a date and time when the words give one, e.g. "in 3 hours".
+enum DatePart {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    let weekdays = [
+        ("mon", Weekday::Mon),
+        ("tue", Weekday::Tue),
+        ("wed", Weekday::Wed),
+        ("thu", Weekday::Thu),
+        ("fri", Weekday::Fri),
+        ("sat", Weekday::Sat),
+        ("sun", Weekday::Sun),
+    ];
+    let (_, weekday) = weekdays
+        .iter()
+        .find(|(prefix, _)| word.starts_with(prefix))?;
+    let full = weekday_name(*weekday).to_ascii_lowercase();
+    (word.len() >= 3 && full.starts_with(word.trim_end_matches('.'))
+        || ["tues", "thur", "thurs"].contains(&word))
+    .then_some(*weekday)
+}
+
+fn month(word: &str) -> Option<u32> {
+    let months = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    let word = word.trim_end_matches('.');
+    if word.len() < 3 && word != "may" {
+        return None;
+    }
+    months
+        .iter()
+        .position(|month| month.starts_with(word) || (word == "sept" && *month == "september"))
+        .map(|i| i as u32 + 1)
+}
+
+fn number(word: &str) -> Option<i64> {
+    let words = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+        "eleven", "twelve",
+    ];
+    match word {
+        "a" | "an" => Some(1),
+        _ => word
+            .parse()
+            .ok()
+            .or_else(|| words.iter().position(|w| *w == word).map(|i| i as i64)),
+    }
+}
+
+/// The day of the month of `5`, `5th` or `21st`.
+fn day_of_month(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !["", "st", "nd", "rd", "th"].contains(&suffix) {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// The time of `15:30`, `3pm`, `3:30pm`, `noon` or `midnight`.
+fn clock(word: &str) -> Option<NaiveTime> {
+    match word {
+        "noon" | "midday" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (digits, meridiem) =
+        if let Some(digits) = word.strip_suffix("am").or(word.strip_suffix("a.m.")) {
+            (digits, Some(false))
+        } else if let Some(digits) = word.strip_suffix("pm").or(word.strip_suffix("p.m.")) {
+            (digits, Some(true))
+        } else {
+            (word, None)
+        };
+    let mut parts = digits.split(':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = match parts.next() {
+        Some(minute) => minute.parse().ok()?,
+        None if meridiem.is_some() => 0,
+        None => return None,
+    };
+    let second: u32 = match parts.next() {
+        Some(second) => second.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    let hour = match meridiem {
+        Some(pm) if (1..=12).contains(&hour) => hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+fn add_units(start: NaiveDateTime, amount: i64, unit: &str) -> Option<DatePart> {
+    let unit = unit.trim_end_matches('s');
+    let date_time = match unit {
+        "second" | "sec" => start.checked_add_signed(Duration::try_seconds(amount)?)?,
+        "minute" | "min" => start.checked_add_signed(Duration::try_minutes(amount)?)?,
+        "hour" | "hr" => start.checked_add_signed(Duration::try_hours(amount)?)?,
+        "day" | "week" | "fortnight" => {
+            let days = amount
+                * [1, 7, 14][["day", "week", "fortnight"]
+                    .iter()
+                    .position(|u| *u == unit)?];
+            return Some(DatePart::Date(
+                start.date().checked_add_signed(Duration::try_days(days)?)?,
+            ));
+        }
+        "month" | "year" => {
+            let months = if unit == "year" { amount * 12 } else { amount };
+            let date = if months >= 0 {
+                start
+                    .date()
+                    .checked_add_months(Months::new(months as u32))?
+            } else {
+                start
+                    .date()
+                    .checked_sub_months(Months::new(months.unsigned_abs() as u32))?
+            };
+            return Some(DatePart::Date(date));
+        }
+        _ => return None,
+    };
+    Some(DatePart::DateTime(date_time))
+}
+
+/// The date of the words, other than the time of the day, relative to `now`.
+fn date_part(words: &[&str], now: NaiveDateTime) -> Option<DatePart> {
+    let today = now.date();
+    let date = |date: NaiveDate| Some(DatePart::Date(date));
+    match words {
+        [] | ["today"] => date(today),
+        ["now"] | ["right", "now"] => Some(DatePart::DateTime(now)),
+        ["tomorrow"] => date(today.succ_opt()?),
+        ["yesterday"] => date(today.pred_opt()?),
+        ["day", "after", "tomorrow"] => date(today + Duration::days(2)),
+        ["day", "before", "yesterday"] => date(today - Duration::days(2)),
+        ["in", amount, unit] | [amount, unit, "from", "now"] | [amount, unit, "later"] => {
+            add_units(now, number(amount)?, unit)
+        }
+        [amount, unit, "ago"] => add_units(now, -number(amount)?, unit),
+        [which @ ("next" | "last" | "this"), unit @ ("week" | "month" | "year")] => {
+            let amount = match *which {
+                "next" => 1,
+                "last" => -1,
+                _ => 0,
+            };
+            add_units(now, amount, unit)
+        }
+        [day] | ["this", day] if weekday(day).is_some() => {
+            let days = (weekday(day)?.num_days_from_monday() + 7
+                - today.weekday().num_days_from_monday())
+                % 7;
+            date(today + Duration::days(days as i64))
+        }
+        ["next", day] if weekday(day).is_some() => {
+            let days = (weekday(day)?.num_days_from_monday() + 6
+                - today.weekday().num_days_from_monday())
+                % 7
+                + 1;
+            date(today + Duration::days(days as i64))
+        }
+        ["last", day] if weekday(day).is_some() => {
+            let days = (today.weekday().num_days_from_monday() + 6
+                - weekday(day)?.num_days_from_monday())
+                % 7
+                + 1;
+            date(today - Duration::days(days as i64))
+        }
+        [iso] if NaiveDate::parse_from_str(iso, "%Y-%m-%d").is_ok() => {
+            date(NaiveDate::parse_from_str(iso, "%Y-%m-%d").ok()?)
+        }
+        // March 5, March 5 2027, 5 March, 5th of March 2027
+        _ => {
+            let words = words
+                .iter()
+                .copied()
+                .filter(|word| !["of", "the"].contains(word))
+                .collect::<Vec<_>>();
+            let (month, day, year) = match words[..] {
+                [m, d] if month(m).is_some() => (month(m)?, day_of_month(d)?, None),
+                [d, m] if month(m).is_some() => (month(m)?, day_of_month(d)?, None),
+                [m, d, y] if month(m).is_some() => {
+                    (month(m)?, day_of_month(d)?, Some(y.parse().ok()?))
+                }
+                [d, m, y] if month(m).is_some() => {
+                    (month(m)?, day_of_month(d)?, Some(y.parse().ok()?))
+                }
+                _ => return None,
+            };
+            match year {
+                Some(year) => date(NaiveDate::from_ymd_opt(year, month, day)?),
+                // The next one, today included.
+                None => {
+                    let this_year = NaiveDate::from_ymd_opt(today.year(), month, day);
+                    match this_year.filter(|date| *date >= today) {
+                        Some(date) => Some(DatePart::Date(date)),
+                        None => date(NaiveDate::from_ymd_opt(today.year() + 1, month, day)?),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read a date written in ISO 8601 or in English words, relative to `now` in `zone`. A date without a time zone is in
+/// `zone`, and a date without a time of the day is at midnight.
+pub fn parse_datetime(
+    text: &str,
+    now: &DateTime<Utc>,
+    zone: &Zone,
+) -> Result<DateTime<FixedOffset>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time);
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(local) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(zone.from_local(&local));
+        }
+    }
+
+    let lowercase = text.to_lowercase().replace(',', " ");
+    let mut words = lowercase.split_whitespace().collect::<Vec<_>>();
+    // 3 pm -> 3pm
+    let mut merged = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        match words.get(i + 1) {
+            Some(meridiem @ (&"am" | &"pm" | &"a.m." | &"p.m."))
+                if clock(&format!("{}{}", words[i], meridiem)).is_some() =>
+            {
+                merged.push(format!("{}{}", words[i], meridiem));
+                i += 2;
+            }
+            _ => {
+                merged.push(words[i].to_string());
+                i += 1;
+            }
+        }
+    }
+    words = merged.iter().map(String::as_str).collect();
+
+    // The time of the day, with its optional `at`.
+    let mut time = None;
+    if let Some(position) = words.iter().position(|word| clock(word).is_some()) {
+        time = clock(words[position]);
+        words.remove(position);
+        if position > 0 && words[position - 1] == "at" {
+            words.remove(position - 1);
+        }
+    } else if let Some(position) = words.iter().position(|word| *word == "at") {
+        // at 3
+        let hour = words
+            .get(position + 1)
+            .and_then(|hour| hour.parse::<u32>().ok())
+            .and_then(|hour| NaiveTime::from_hms_opt(hour, 0, 0))
+            .ok_or_else(|| anyhow!("Could not read the time of '{}'", text))?;
+        time = Some(hour);
+        words.drain(position..position + 2);
+    }
+    words.retain(|word| *word != "on");
+    if words.first() == Some(&"the") {
+        words.remove(0);
+    }
+
+    let local_now = zone.from_utc(now).naive_local();
+    let Some(part) = date_part(&words, local_now) else {
+        bail!(
+            "Could not read the date '{}'. Write it {}",
+            text,
+            DATE_EXAMPLES
+        );
+    };
+    let local = match (part, time) {
+        (DatePart::Date(date), time) => date.and_time(time.unwrap_or(NaiveTime::MIN)),
+        (DatePart::DateTime(date_time), Some(time)) => date_time.date().and_time(time),
+        (DatePart::DateTime(date_time), None) => date_time,
+    };
+    Ok(zone.from_local(&local))
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for DateTimeTool {
+    type Params = DateTimeToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: DateTimeToolParams) -> Result<String> {
+        Ok(self.run(arguments, Utc::now())?.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        // Friday
+        DateTime::parse_from_rfc3339("2026-10-16T09:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn parse(text: &str, zone: &str) -> String {
+        parse_datetime(text, &now(), &zone.parse().unwrap())
+            .unwrap()
+            .to_rfc3339()
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(
+            parse("2026-10-20T15:00:00+09:00", "UTC"),
+            "2026-10-20T15:00:00+09:00"
+        );
+        assert_eq!(
+            parse("2026-10-20 15:00", "UTC+02:00"),
+            "2026-10-20T15:00:00+02:00"
+        );
+        assert_eq!(parse("now", "UTC"), "2026-10-16T09:30:00+00:00");
+        assert_eq!(parse("today", "UTC+09:00"), "2026-10-16T00:00:00+09:00");
+        // Still the day before in UTC-10.
+        assert_eq!(
+            parse("tomorrow at noon", "UTC-10"),
+            "2026-10-16T12:00:00-10:00"
+        );
+        assert_eq!(
+            parse("next Tuesday 3pm", "UTC+09:00"),
+            "2026-10-20T15:00:00+09:00"
+        );
+        assert_eq!(parse("next friday", "UTC"), "2026-10-23T00:00:00+00:00");
+        assert_eq!(parse("Friday", "UTC"), "2026-10-16T00:00:00+00:00");
+        assert_eq!(
+            parse("last Sunday at 9:45", "UTC"),
+            "2026-10-11T09:45:00+00:00"
+        );
+        assert_eq!(parse("in 2 weeks", "UTC"), "2026-10-30T00:00:00+00:00");
+        assert_eq!(parse("in 3 hours", "UTC"), "2026-10-16T12:30:00+00:00");
+        assert_eq!(
+            parse("two days ago at 8 am", "UTC"),
+            "2026-10-14T08:00:00+00:00"
+        );
+        assert_eq!(parse("next month", "UTC"), "2026-11-16T00:00:00+00:00");
+        assert_eq!(parse("March 5", "UTC"), "2027-03-05T00:00:00+00:00");
+        assert_eq!(parse("December 25th", "UTC"), "2026-12-25T00:00:00+00:00");
+        assert_eq!(
+            parse("5th of March 2028 at 18:30", "UTC"),
+            "2028-03-05T18:30:00+00:00"
+        );
+        assert_eq!(
+            parse("on 2026-11-02 at 9pm", "UTC"),
+            "2026-11-02T21:00:00+00:00"
+        );
+
+        let error = parse_datetime("when pigs fly", &now(), &Zone::utc())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Could not read the date 'when pigs fly'"));
+    }
+
+    fn params(value: Value) -> DateTimeToolParams {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_actions() {
+        let tool = DateTimeTool::new().with_time_zone(Zone::utc());
+        let result = tool
+            .run(
+                params(json!({"action": "now", "time_zones": ["UTC+09:00", "UTC-04:00"]})),
+                now(),
+            )
+            .unwrap();
+        assert_eq!(result["times"][0]["datetime"], "2026-10-16T18:30:00+09:00");
+        assert_eq!(result["times"][1]["date"], "2026-10-16");
+        assert_eq!(result["times"][1]["time"], "05:30:00");
+
+        // "What's the date next Tuesday in Tokyo?"
+        let result = tool
+            .run(
+                params(json!({"action": "parse", "datetime": "next Tuesday", "time_zone": "UTC+09:00"})),
+                now(),
+            )
+            .unwrap();
+        assert_eq!(result["times"][0]["date"], "2026-10-20");
+        assert_eq!(result["times"][0]["weekday"], "Tuesday");
+
+        let result = tool
+            .run(
+                params(json!({"action": "add", "datetime": "2026-01-31T10:00:00Z", "months": 1, "days": 1, "hours": -2})),
+                now(),
+            )
+            .unwrap();
+        assert_eq!(result["times"][0]["datetime"], "2026-03-01T08:00:00+00:00");
+
+        let result = tool
+            .run(
+                params(json!({"action": "difference", "datetime": "2026-10-16T22:00:00Z", "end": "2026-10-18T01:30:00Z"})),
+                now(),
+            )
+            .unwrap();
+        assert_eq!(result["seconds"], 27 * 3600 + 1800);
+        assert_eq!(result["calendar_days"], 2);
+        assert_eq!(result["duration"], "1 day 3 hours 30 minutes");
+
+        let error = tool
+            .run(params(json!({"action": "difference"})), now())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("end is required"));
+        assert!(tool
+            .run(
+                params(json!({"action": "now", "time_zones": ["Mars/Base"]})),
+                now()
+            )
+            .is_err());
+    }
+}
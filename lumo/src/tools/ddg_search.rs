@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use super::base::BaseTool;
 use super::tool_traits::Tool;
+use super::web_content::WebContentPipeline;
 use anyhow::Result;
 
 #[derive(Deserialize, JsonSchema)]
@@ -14,6 +15,14 @@ use anyhow::Result;
 pub struct DuckDuckGoSearchToolParams {
     #[schemars(description = "The query to search for")]
     query: String,
+    #[schemars(description = "Optional region code to localize the results, e.g. 'us-en', 'uk-en' or 'de-de'")]
+    region: Option<String>,
+    #[schemars(description = "Optional safe search level, only a stricter one than the default is used")]
+    safesearch: Option<SafeSearch>,
+    #[schemars(description = "Optional time range to restrict the results to")]
+    time_range: Option<TimeRange>,
+    #[schemars(description = "Optional number of results to return")]
+    max_results: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -23,9 +32,68 @@ pub struct SearchResult {
     pub url: String,
 }
 
+/// The safe search level of a DuckDuckGo search, ordered from the strictest to the least strict.
+#[derive(
+    Debug, Serialize, Deserialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SafeSearch {
+    Strict,
+    #[default]
+    Moderate,
+    Off,
+}
+
+impl SafeSearch {
+    fn as_param(&self) -> &'static str {
+        match self {
+            SafeSearch::Strict => "1",
+            SafeSearch::Moderate => "-1",
+            SafeSearch::Off => "-2",
+        }
+    }
+}
+
+/// Restrict a DuckDuckGo search to results from the last day, week, month or year.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeRange {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl TimeRange {
+    fn as_param(&self) -> &'static str {
+        match self {
+            TimeRange::Day => "d",
+            TimeRange::Week => "w",
+            TimeRange::Month => "m",
+            TimeRange::Year => "y",
+        }
+    }
+}
+
+/// The options of a single search. Unset options fall back to the defaults of the tool.
+#[derive(Debug, Default, Clone)]
+pub struct DuckDuckGoSearchOptions {
+    pub max_results: Option<usize>,
+    pub region: Option<String>,
+    pub safe_search: Option<SafeSearch>,
+    pub time_range: Option<TimeRange>,
+}
+
 #[derive(Debug, Serialize, Default, Clone)]
 pub struct DuckDuckGoSearchTool {
     pub tool: BaseTool,
+    pub max_results: Option<usize>,
+    pub region: Option<String>,
+    /// The least strict safe search of the searches. The model can only ask for a stricter one.
+    pub safe_search: SafeSearch,
+    pub time_range: Option<TimeRange>,
+    /// How the snippets of the results are read. They are read as plain text by default.
+    pub content: WebContentPipeline,
 }
 
 impl DuckDuckGoSearchTool {
@@ -35,15 +103,76 @@ impl DuckDuckGoSearchTool {
                 name: "duckduckgo_search",
                 description: "Performs a duckduckgo web search for your query then returns a string of the top search results.",
             },
+            max_results: None,
+            region: None,
+            safe_search: SafeSearch::default(),
+            time_range: None,
+            content: WebContentPipeline::new().with_markdown(false),
         }
     }
 
+    /// Limit the number of results. The model can ask for fewer results but never more.
+    pub fn with_max_results(mut self, max_results: Option<usize>) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    /// Set the default region, e.g. `us-en` or `de-de`.
+    pub fn with_region(mut self, region: Option<&str>) -> Self {
+        self.region = region.map(|r| r.to_string());
+        self
+    }
+
+    /// Set the safe search level. The model can ask for a stricter level but never a less strict one.
+    pub fn with_safe_search(mut self, safe_search: Option<SafeSearch>) -> Self {
+        self.safe_search = safe_search.unwrap_or_default();
+        self
+    }
+
+    pub fn with_time_range(mut self, time_range: Option<TimeRange>) -> Self {
+        self.time_range = time_range;
+        self
+    }
+
+    /// Set the pipeline reading the snippets, e.g. to cap their length.
+    pub fn with_content_pipeline(mut self, content: WebContentPipeline) -> Self {
+        self.content = content;
+        self
+    }
+
+    fn query_params<'a>(
+        &'a self,
+        query: &'a str,
+        options: &'a DuckDuckGoSearchOptions,
+    ) -> Vec<(&'static str, &'a str)> {
+        let safe_search = options.safe_search.map_or(self.safe_search, |safe_search| {
+            safe_search.min(self.safe_search)
+        });
+        let mut params = vec![("q", query), ("kp", safe_search.as_param())];
+        if let Some(region) = options.region.as_ref().or(self.region.as_ref()) {
+            params.push(("kl", region));
+        }
+        if let Some(time_range) = options.time_range.or(self.time_range) {
+            params.push(("df", time_range.as_param()));
+        }
+        params
+    }
+
     pub async fn forward(&self, query: &str) -> Result<Vec<SearchResult>> {
+        self.search(query, &DuckDuckGoSearchOptions::default()).await
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        options: &DuckDuckGoSearchOptions,
+    ) -> Result<Vec<SearchResult>> {
         let client = reqwest::Client::builder()
             .user_agent("Mozilla/5.0 (compatible; MyRustTool/1.0)")
             .build()?;
         let response = client
-            .get(format!("https://html.duckduckgo.com/html/?q={}", query))
+            .get("https://html.duckduckgo.com/html/")
+            .query(&self.query_params(query, options))
             .send()
             .await?;
         let html = response.text().await?;
@@ -63,7 +192,7 @@ impl DuckDuckGoSearchTool {
             let snippet_element = result.select(&snippet_selector).next();
             if let (Some(title), Some(snippet)) = (title_element, snippet_element) {
                 let title_text = title.text().collect::<String>().trim().to_string();
-                let snippet_text = snippet.text().collect::<String>().trim().to_string();
+                let snippet_text = self.content.extract(&snippet.html()).unwrap_or_default();
                 let url = result
                     .select(&url_selector)
                     .next()
@@ -82,11 +211,19 @@ impl DuckDuckGoSearchTool {
                 }
             }
         }
+        let max_results = match (options.max_results, self.max_results) {
+            (Some(requested), Some(max)) => Some(requested.min(max)),
+            (requested, max) => requested.or(max),
+        };
+        if let Some(max_results) = max_results {
+            results.truncate(max_results);
+        }
         Ok(results)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Tool for DuckDuckGoSearchTool {
     type Params = DuckDuckGoSearchToolParams;
     fn name(&self) -> &'static str {
@@ -97,7 +234,13 @@ impl Tool for DuckDuckGoSearchTool {
     }
     async fn forward(&self, arguments: DuckDuckGoSearchToolParams) -> Result<String> {
         let query = arguments.query;
-        let results = self.forward(&query).await?;
+        let options = DuckDuckGoSearchOptions {
+            max_results: arguments.max_results,
+            region: arguments.region,
+            safe_search: arguments.safesearch,
+            time_range: arguments.time_range,
+        };
+        let results = self.search(&query, &options).await?;
         let results_string = results
             .iter()
             .map(|r| format!("[{}]({}) \n{}", r.title, r.url, r.snippet))
@@ -121,4 +264,44 @@ mod tests {
         let result = tool.forward(query).await.unwrap();
         assert!(result.iter().any(|r| r.snippet.contains("Paris")));
     }
+
+    #[test]
+    fn test_duckduckgo_query_params() {
+        let tool = DuckDuckGoSearchTool::new()
+            .with_region(Some("de-de"))
+            .with_safe_search(Some(SafeSearch::Strict));
+        let options = DuckDuckGoSearchOptions {
+            safe_search: Some(SafeSearch::Off),
+            time_range: Some(TimeRange::Week),
+            ..Default::default()
+        };
+        assert_eq!(
+            tool.query_params("rust", &options),
+            vec![("q", "rust"), ("kp", "1"), ("kl", "de-de"), ("df", "w")]
+        );
+        assert_eq!(
+            tool.query_params("rust", &DuckDuckGoSearchOptions::default()),
+            vec![("q", "rust"), ("kp", "1"), ("kl", "de-de")]
+        );
+
+        // The safe search of the tool is a floor, the model can only make it stricter.
+        let tool = DuckDuckGoSearchTool::new();
+        let options = |safe_search| DuckDuckGoSearchOptions {
+            safe_search: Some(safe_search),
+            ..Default::default()
+        };
+        assert_eq!(
+            tool.query_params("rust", &options(SafeSearch::Off))[1],
+            ("kp", "-1")
+        );
+        assert_eq!(
+            tool.query_params("rust", &options(SafeSearch::Strict))[1],
+            ("kp", "1")
+        );
+        let tool = tool.with_safe_search(Some(SafeSearch::Off));
+        assert_eq!(
+            tool.query_params("rust", &options(SafeSearch::Moderate))[1],
+            ("kp", "-1")
+        );
+    }
 }
@@ -0,0 +1,487 @@
+//! Text of the documents the tools read, as markdown: the pages of websites with their tables, PDFs and Word
+//! documents. The type of a document is found from its content type, or else from the extension of its url.
+
+use std::io::{Cursor, Read};
+
+use htmd::HtmlToMarkdown;
+use regex::Regex;
+use scraper::{node::Text, ElementRef, Html, Node, Selector};
+
+/// The content type of Word documents.
+pub const DOCX_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+/// Bytes of the text of a Word document read at most, as a compressed document can be much larger once inflated.
+const MAX_DOCX_XML_LENGTH: u64 = 50_000_000;
+
+/// The type of a document, which decides how its text is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentType {
+    Html,
+    /// Plain text, markdown, JSON, CSV or XML, read as is.
+    Text,
+    Pdf,
+    Docx,
+    /// Images, videos, archives and other files without text.
+    Binary,
+}
+
+impl DocumentType {
+    /// The type of the document from its content type, e.g. `text/html; charset=utf-8`, or else from the
+    /// extension of the path of its url. Documents without either are read as HTML.
+    pub fn detect(content_type: Option<&str>, path: &str) -> Self {
+        let content_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase())
+            .unwrap_or_default();
+        match content_type.as_str() {
+            "text/html" | "application/xhtml+xml" => return DocumentType::Html,
+            "application/pdf" => return DocumentType::Pdf,
+            DOCX_CONTENT_TYPE => return DocumentType::Docx,
+            "" | "application/octet-stream" | "binary/octet-stream" => {}
+            value
+                if value.starts_with("text/")
+                    || value.ends_with("+json")
+                    || value.ends_with("+xml")
+                    || matches!(
+                        value,
+                        "application/json" | "application/xml" | "application/x-yaml"
+                    ) =>
+            {
+                return DocumentType::Text
+            }
+            _ => return DocumentType::Binary,
+        }
+        let extension = path
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "pdf" => DocumentType::Pdf,
+            "docx" => DocumentType::Docx,
+            "txt" | "md" | "csv" | "json" | "xml" | "yaml" | "yml" => DocumentType::Text,
+            _ if content_type.is_empty() => DocumentType::Html,
+            _ => DocumentType::Binary,
+        }
+    }
+}
+
+/// A markdown table of the rows, the first one being the header. Short rows are padded with empty cells.
+pub fn markdown_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let line = |row: &[String]| {
+        let cells = (0..columns)
+            .map(|i| escape_cell(row.get(i).map(String::as_str).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(line(row));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(columns)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// The text of a cell on a single line, without the pipes that would end it.
+fn escape_cell(cell: &str) -> String {
+    cell.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+/// The markdown of a page, with its data tables as markdown tables. Tables of a single column, and the ones holding
+/// other tables, lay out the page and are converted as the rest of the page.
+pub fn html_to_markdown(html: &str) -> Result<String, String> {
+    let converter = HtmlToMarkdown::builder()
+        .skip_tags(vec!["script", "style", "header", "nav", "footer"])
+        .build();
+    let (html, tables) = extract_tables(html);
+    let mut markdown = converter
+        .convert(&html)
+        .map_err(|e| format!("Failed to convert the page to markdown: {}", e))?;
+    for (i, table) in tables.iter().enumerate() {
+        markdown = markdown.replace(&placeholder(i), &format!("\n\n{}\n\n", table));
+    }
+    if tables.is_empty() {
+        return Ok(markdown);
+    }
+    let blank_lines = Regex::new(r"\n[ \t]*\n(?:[ \t]*\n)+").unwrap();
+    Ok(blank_lines
+        .replace_all(&markdown, "\n\n")
+        .trim()
+        .to_string())
+}
+
+/// The text standing for the table `i` while the page is converted, made of characters pages do not have.
+fn placeholder(i: usize) -> String {
+    format!("\u{E000}{}\u{E001}", i)
+}
+
+/// The page with its data tables replaced by placeholders, and the markdown of these tables.
+fn extract_tables(html: &str) -> (String, Vec<String>) {
+    let mut document = Html::parse_document(html);
+    let selector = Selector::parse("table").unwrap();
+    let found = document
+        .select(&selector)
+        .filter(|table| table.select(&selector).next().is_none())
+        .map(|table| (table.id(), table_rows(table)))
+        .filter(|(_, rows)| rows.iter().map(Vec::len).max().unwrap_or(0) >= 2)
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        return (html.to_string(), vec![]);
+    }
+    let mut tables = Vec::with_capacity(found.len());
+    for (id, rows) in found {
+        let Some(mut node) = document.tree.get_mut(id) else {
+            continue;
+        };
+        node.insert_before(Node::Text(Text {
+            text: placeholder(tables.len()).into(),
+        }));
+        node.detach();
+        tables.push(markdown_table(&rows));
+    }
+    (document.html(), tables)
+}
+
+/// The text of the cells of a table by row, the cells spanning several columns being followed by empty ones. The
+/// rows of the tables nested in it are left out.
+fn table_rows(table: ElementRef) -> Vec<Vec<String>> {
+    let rows = Selector::parse("tr").unwrap();
+    table
+        .select(&rows)
+        .filter(|row| {
+            row.ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|element| element.value().name() == "table")
+                .is_some_and(|parent| parent.id() == table.id())
+        })
+        .map(|row| {
+            let mut cells = Vec::new();
+            for cell in row.children().filter_map(ElementRef::wrap) {
+                if !matches!(cell.value().name(), "td" | "th") {
+                    continue;
+                }
+                cells.push(cell.text().collect::<String>());
+                let span = cell
+                    .value()
+                    .attr("colspan")
+                    .and_then(|span| span.trim().parse::<usize>().ok())
+                    .unwrap_or(1)
+                    .clamp(1, 100);
+                cells.extend(std::iter::repeat_n(String::new(), span - 1));
+            }
+            cells
+        })
+        .filter(|cells| cells.iter().any(|cell| !cell.trim().is_empty()))
+        .collect()
+}
+
+/// The text of a Word document as markdown: its paragraphs, headings, list items and tables. `None` when the bytes
+/// are not a Word document.
+pub fn extract_docx_text(bytes: &[u8]) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).ok()?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .ok()?
+        .take(MAX_DOCX_XML_LENGTH)
+        .read_to_string(&mut xml)
+        .ok()?;
+    Some(docx_markdown(&xml))
+}
+
+/// A table of a Word document being read.
+#[derive(Default)]
+struct DocxTable {
+    rows: Vec<Vec<String>>,
+    cell: String,
+    span: usize,
+}
+
+/// The markdown of the `word/document.xml` of a Word document.
+fn docx_markdown(xml: &str) -> String {
+    let tokens = Regex::new(r"<(/?)([A-Za-z][\w.:-]*)([^>]*?)(/?)>|([^<]+)").unwrap();
+    let value = Regex::new(r#"w:val="([^"]*)""#).unwrap();
+    let mut blocks = Vec::new();
+    let mut tables: Vec<DocxTable> = Vec::new();
+    let mut paragraph = String::new();
+    let mut style = String::new();
+    let mut list_item = false;
+    let mut in_text = false;
+    for token in tokens.captures_iter(xml) {
+        if let Some(text) = token.get(5) {
+            if in_text {
+                paragraph.push_str(&unescape_xml(text.as_str()));
+            }
+            continue;
+        }
+        let closing = &token[1] == "/";
+        let self_closing = &token[4] == "/";
+        let attributes = &token[3];
+        match (&token[2], closing) {
+            ("w:t", _) => in_text = !closing && !self_closing,
+            ("w:tab", false) => paragraph.push('\t'),
+            ("w:br" | "w:cr", false) => paragraph.push('\n'),
+            ("w:pStyle", false) => {
+                style = value
+                    .captures(attributes)
+                    .map(|value| value[1].to_string())
+                    .unwrap_or_default();
+            }
+            ("w:numPr", false) => list_item = true,
+            ("w:p", false) if !self_closing => {
+                paragraph.clear();
+                style.clear();
+                list_item = false;
+            }
+            ("w:p", true) => {
+                let text = paragraph.trim();
+                match tables.last_mut() {
+                    Some(table) => {
+                        if !text.is_empty() {
+                            if !table.cell.is_empty() {
+                                table.cell.push(' ');
+                            }
+                            table.cell.push_str(text);
+                        }
+                    }
+                    None if text.is_empty() => {}
+                    None => blocks.push(match heading_level(&style) {
+                        Some(level) => format!("{} {}", "#".repeat(level), text),
+                        None if list_item => format!("- {}", text),
+                        None => text.to_string(),
+                    }),
+                }
+                paragraph.clear();
+            }
+            ("w:tbl", false) => tables.push(DocxTable::default()),
+            ("w:tr", false) => {
+                if let Some(table) = tables.last_mut() {
+                    table.rows.push(Vec::new());
+                }
+            }
+            ("w:tc", false) => {
+                if let Some(table) = tables.last_mut() {
+                    table.cell.clear();
+                    table.span = 1;
+                }
+            }
+            ("w:gridSpan", false) => {
+                if let Some(table) = tables.last_mut() {
+                    table.span = value
+                        .captures(attributes)
+                        .and_then(|value| value[1].parse().ok())
+                        .unwrap_or(1_usize)
+                        .clamp(1, 100);
+                }
+            }
+            ("w:tc", true) => {
+                if let Some(table) = tables.last_mut() {
+                    let cell = std::mem::take(&mut table.cell);
+                    let span = table.span;
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell);
+                        row.extend(std::iter::repeat_n(String::new(), span.max(1) - 1));
+                    }
+                }
+            }
+            ("w:tbl", true) => {
+                let Some(table) = tables.pop() else {
+                    continue;
+                };
+                let rows = table
+                    .rows
+                    .into_iter()
+                    .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+                    .collect::<Vec<_>>();
+                match tables.last_mut() {
+                    // A table in a cell is read as the text of the cell.
+                    Some(parent) => {
+                        let text = rows.iter().flatten().filter(|cell| !cell.is_empty());
+                        for cell in text {
+                            if !parent.cell.is_empty() {
+                                parent.cell.push(' ');
+                            }
+                            parent.cell.push_str(cell);
+                        }
+                    }
+                    None if rows.is_empty() => {}
+                    None => blocks.push(markdown_table(&rows)),
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks.join("\n\n")
+}
+
+/// The markdown heading level of a paragraph style, e.g. 2 for `Heading2`. The title is a first level heading.
+fn heading_level(style: &str) -> Option<usize> {
+    if style.eq_ignore_ascii_case("Title") {
+        return Some(1);
+    }
+    let level = style
+        .strip_prefix("Heading")
+        .or_else(|| style.strip_prefix("heading"))?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+/// The text of XML character data, with its entities and character references replaced.
+fn unescape_xml(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            unescaped.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| {
+                    entity
+                        .strip_prefix('#')
+                        .and_then(|decimal| decimal.parse().ok())
+                })
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_detect() {
+        let detect = DocumentType::detect;
+        assert_eq!(
+            detect(Some("text/html; charset=utf-8"), "/"),
+            DocumentType::Html
+        );
+        assert_eq!(
+            detect(Some("application/pdf"), "/report"),
+            DocumentType::Pdf
+        );
+        assert_eq!(
+            detect(Some(DOCX_CONTENT_TYPE), "/download"),
+            DocumentType::Docx
+        );
+        assert_eq!(
+            detect(Some("application/octet-stream"), "/files/Report.DOCX"),
+            DocumentType::Docx
+        );
+        assert_eq!(detect(None, "/paper.pdf"), DocumentType::Pdf);
+        assert_eq!(detect(Some("application/json"), "/api"), DocumentType::Text);
+        assert_eq!(detect(Some("text/csv"), "/prices.csv"), DocumentType::Text);
+        assert_eq!(detect(None, "/about"), DocumentType::Html);
+        assert_eq!(detect(Some("image/png"), "/logo.png"), DocumentType::Binary);
+        assert_eq!(
+            detect(Some("application/octet-stream"), "/setup.exe"),
+            DocumentType::Binary
+        );
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let rows = vec![
+            vec!["Plan".to_string(), "Price".to_string()],
+            vec!["Free".to_string(), "0 | 0".to_string()],
+            vec!["Pro\n plan".to_string()],
+        ];
+        assert_eq!(
+            markdown_table(&rows),
+            "| Plan | Price |\n| --- | --- |\n| Free | 0 \\| 0 |\n| Pro plan |  |"
+        );
+    }
+
+    #[test]
+    fn test_html_tables() {
+        let html = r#"<html><body><p>Our plans:</p>
+            <table class="prices">
+                <thead><tr><th>Plan</th><th>Price</th></tr></thead>
+                <tbody>
+                    <tr><td>Free</td><td>$0</td></tr>
+                    <tr><td colspan="2">Contact us for <b>Enterprise</b></td></tr>
+                </tbody>
+            </table>
+            <table><tr><td>A layout table of one column</td></tr></table>
+        </body></html>"#;
+        let (_, tables) = extract_tables(html);
+        assert_eq!(
+            tables,
+            ["| Plan | Price |\n| --- | --- |\n| Free | $0 |\n| Contact us for Enterprise |  |"]
+        );
+        let markdown = html_to_markdown(html).unwrap();
+        assert!(markdown.contains(&tables[0]));
+        assert!(markdown.contains("A layout table of one column"));
+        assert!(!markdown.contains('\u{E000}'));
+
+        // The tables holding other tables lay out the page.
+        let html = "<table><tr><td><table><tr><td>a</td><td>b</td></tr></table></td><td>c</td></tr></table>";
+        let (_, tables) = extract_tables(html);
+        assert_eq!(tables, ["| a | b |\n| --- | --- |"]);
+    }
+
+    fn docx(document: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("[Content_Types].xml", options).unwrap();
+        writer.write_all(b"<Types/>").unwrap();
+        writer.start_file("word/document.xml", options).unwrap();
+        writer.write_all(document.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_docx_text() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Quarterly report</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Sales grew by </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>12%</w:t></w:r><w:r><w:t xml:space="preserve"> &amp; costs fell.</w:t></w:r></w:p>
+<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Europe</w:t></w:r></w:p>
+<w:p/>
+<w:tbl><w:tblPr/><w:tr><w:tc><w:p><w:r><w:t>Region</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Sales</w:t></w:r></w:p></w:tc></w:tr>
+<w:tr><w:tc><w:p><w:r><w:t>Europe</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>1,200</w:t></w:r></w:p><w:p><w:r><w:t>units</w:t></w:r></w:p></w:tc></w:tr>
+<w:tr><w:tc><w:tcPr><w:gridSpan w:val="2"/></w:tcPr><w:p><w:r><w:t>Total &lt;estimated&gt;</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+<w:p><w:r><w:delText>Removed</w:delText><w:t>The end</w:t></w:r></w:p>
+</w:body></w:document>"#;
+        assert_eq!(
+            extract_docx_text(&docx(document)).unwrap(),
+            "# Quarterly report\n\nSales grew by 12% & costs fell.\n\n- Europe\n\n| Region | Sales |\n| --- | --- |\n| Europe | 1,200 units |\n| Total <estimated> |  |\n\nThe end"
+        );
+        assert!(extract_docx_text(b"%PDF-1.4").is_none());
+    }
+}
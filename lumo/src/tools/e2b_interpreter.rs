@@ -0,0 +1,399 @@
+//! This module contains the E2B interpreter tool. The model uses this tool to run code in a remote E2B sandbox, so
+//! code can be executed without running it on the host.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use futures::lock::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::base::BaseTool;
+use super::tool_traits::Tool;
+
+const DEFAULT_API_URL: &str = "https://api.e2b.app";
+const DEFAULT_DOMAIN: &str = "e2b.app";
+const DEFAULT_TEMPLATE: &str = "code-interpreter-v1";
+/// Port of the code interpreter inside the sandbox.
+const INTERPRETER_PORT: u16 = 49999;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum E2BLanguage {
+    #[default]
+    Python,
+    Bash,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "E2BInterpreterToolParams")]
+pub struct E2BInterpreterToolParams {
+    #[schemars(
+        description = "The code to execute. Variables and files persist between calls. Make sure to print the result."
+    )]
+    code: String,
+    #[schemars(description = "The language of the code: python (default) or bash")]
+    language: Option<E2BLanguage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sandbox {
+    #[serde(rename = "sandboxID")]
+    sandbox_id: String,
+    envd_access_token: Option<String>,
+    domain: Option<String>,
+}
+
+/// The sandbox was killed after its timeout, and its state was lost.
+#[derive(Debug)]
+struct SandboxExpired;
+
+impl std::fmt::Display for SandboxExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The sandbox has expired and its state was lost. Run the code again."
+        )
+    }
+}
+
+impl std::error::Error for SandboxExpired {}
+
+/// An event of the NDJSON stream returned by the code interpreter.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ExecutionEvent {
+    Stdout {
+        text: String,
+    },
+    Stderr {
+        text: String,
+    },
+    Result {
+        text: Option<String>,
+        #[serde(default)]
+        is_main_result: bool,
+    },
+    Error {
+        name: String,
+        value: String,
+        #[serde(default)]
+        traceback: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// The output of code executed in the sandbox.
+#[derive(Debug, Default, Clone)]
+pub struct E2BExecution {
+    pub stdout: String,
+    pub stderr: String,
+    pub result: Option<String>,
+    pub error: Option<String>,
+}
+
+impl E2BExecution {
+    fn from_events(body: &str) -> Self {
+        let mut execution = E2BExecution::default();
+        for event in body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str::<ExecutionEvent>(line).ok())
+        {
+            match event {
+                ExecutionEvent::Stdout { text } => execution.stdout.push_str(&text),
+                ExecutionEvent::Stderr { text } => execution.stderr.push_str(&text),
+                ExecutionEvent::Result {
+                    text: Some(text),
+                    is_main_result: true,
+                } => execution.result = Some(text),
+                ExecutionEvent::Error {
+                    name,
+                    value,
+                    traceback,
+                } => {
+                    execution.error = Some(
+                        format!("{}: {}\n{}", name, value, traceback)
+                            .trim()
+                            .to_string(),
+                    )
+                }
+                _ => {}
+            }
+        }
+        execution
+    }
+}
+
+/// Runs Python and shell code in an E2B sandbox. The sandbox is created on the first call and reused, so
+/// variables and files persist between calls.
+#[derive(Clone)]
+pub struct E2BInterpreterTool {
+    pub tool: BaseTool,
+    api_key: String,
+    api_url: String,
+    template: String,
+    /// How long the sandbox is kept alive after it is created.
+    sandbox_timeout: Duration,
+    /// How long a single execution can run.
+    execution_timeout: Duration,
+    /// Files uploaded to the sandbox when it is created.
+    files: Vec<(String, Vec<u8>)>,
+    client: reqwest::Client,
+    sandbox: Arc<Mutex<Option<Sandbox>>>,
+}
+
+impl E2BInterpreterTool {
+    pub fn new(api_key: Option<String>) -> Self {
+        let api_key = api_key
+            .unwrap_or_else(|| std::env::var("E2B_API_KEY").expect("E2B_API_KEY is not set"));
+        E2BInterpreterTool {
+            tool: BaseTool {
+                name: "e2b_interpreter",
+                description: "Executes python or bash code in a remote sandbox and returns the output. Make sure to print the result using print() or echo.",
+            },
+            api_key,
+            api_url: DEFAULT_API_URL.to_string(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            sandbox_timeout: Duration::from_secs(300),
+            execution_timeout: Duration::from_secs(60),
+            files: Vec::new(),
+            client: reqwest::Client::new(),
+            sandbox: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn with_api_url(mut self, api_url: Option<&str>) -> Self {
+        if let Some(api_url) = api_url {
+            self.api_url = api_url.trim_end_matches('/').to_string();
+        }
+        self
+    }
+
+    pub fn with_template(mut self, template: Option<&str>) -> Self {
+        if let Some(template) = template {
+            self.template = template.to_string();
+        }
+        self
+    }
+
+    pub fn with_sandbox_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if let Some(timeout) = timeout {
+            self.sandbox_timeout = timeout;
+        }
+        self
+    }
+
+    pub fn with_execution_timeout(mut self, timeout: Option<Duration>) -> Self {
+        if let Some(timeout) = timeout {
+            self.execution_timeout = timeout;
+        }
+        self
+    }
+
+    /// Files to upload to the sandbox when it is created, as `(path, content)` pairs.
+    pub fn with_files(mut self, files: Vec<(String, Vec<u8>)>) -> Self {
+        self.files.extend(files);
+        self
+    }
+
+    async fn create_sandbox(&self) -> Result<Sandbox> {
+        let response = self
+            .client
+            .post(format!("{}/sandboxes", self.api_url))
+            .header("X-API-Key", &self.api_key)
+            .json(&json!({
+                "templateID": self.template,
+                "timeout": self.sandbox_timeout.as_secs(),
+            }))
+            .send()
+            .await
+            .context("Failed to create E2B sandbox")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to create E2B sandbox: {} {}", status, body);
+        }
+        Ok(response.json::<Sandbox>().await?)
+    }
+
+    /// The sandbox, created on first use.
+    async fn sandbox(&self) -> Result<Sandbox> {
+        let mut sandbox = self.sandbox.lock().await;
+        if let Some(sandbox) = sandbox.as_ref() {
+            return Ok(sandbox.clone());
+        }
+        let created = self.create_sandbox().await?;
+        for (path, content) in &self.files {
+            let execution = self
+                .run_code(&created, &upload_code(path, content), E2BLanguage::Python)
+                .await?;
+            if let Some(error) = execution.error {
+                anyhow::bail!("Failed to upload {}: {}", path, error);
+            }
+        }
+        *sandbox = Some(created.clone());
+        Ok(created)
+    }
+
+    /// Execute code in the sandbox.
+    pub async fn execute(&self, code: &str, language: E2BLanguage) -> Result<E2BExecution> {
+        let sandbox = self.sandbox().await?;
+        let response = self.run_code(&sandbox, code, language).await;
+        if response.as_ref().is_err_and(|e| e.is::<SandboxExpired>()) {
+            // A new sandbox is created on the next call.
+            *self.sandbox.lock().await = None;
+        }
+        response
+    }
+
+    async fn run_code(
+        &self,
+        sandbox: &Sandbox,
+        code: &str,
+        language: E2BLanguage,
+    ) -> Result<E2BExecution> {
+        let domain = sandbox.domain.as_deref().unwrap_or(DEFAULT_DOMAIN);
+        let mut request = self
+            .client
+            .post(format!(
+                "https://{}-{}.{}/execute",
+                INTERPRETER_PORT, sandbox.sandbox_id, domain
+            ))
+            .timeout(self.execution_timeout)
+            .json(&json!({ "code": code, "language": language }));
+        if let Some(token) = &sandbox.envd_access_token {
+            request = request.header("X-Access-Token", token);
+        }
+
+        let response = request.send().await.map_err(|e| {
+            if e.is_timeout() {
+                anyhow::anyhow!(
+                    "Execution timed out after {}s",
+                    self.execution_timeout.as_secs()
+                )
+            } else {
+                anyhow::anyhow!("Failed to execute code in E2B sandbox: {}", e)
+            }
+        })?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(SandboxExpired.into());
+        }
+        let body = response.error_for_status()?.text().await?;
+        Ok(E2BExecution::from_events(&body))
+    }
+
+    /// Write a file in the sandbox.
+    pub async fn upload_file(&self, path: &str, content: &[u8]) -> Result<()> {
+        let execution = self
+            .execute(&upload_code(path, content), E2BLanguage::Python)
+            .await?;
+        match execution.error {
+            Some(error) => anyhow::bail!("Failed to upload {}: {}", path, error),
+            None => Ok(()),
+        }
+    }
+
+    /// Read a file from the sandbox.
+    pub async fn download_file(&self, path: &str) -> Result<Vec<u8>> {
+        let code = format!(
+            "import base64\nwith open({path:?}, 'rb') as f:\n    print(base64.b64encode(f.read()).decode(), end='')"
+        );
+        let execution = self.execute(&code, E2BLanguage::Python).await?;
+        if let Some(error) = execution.error {
+            anyhow::bail!("Failed to download {}: {}", path, error);
+        }
+        Ok(base64::engine::general_purpose::STANDARD.decode(execution.stdout.trim())?)
+    }
+
+    /// Kill the sandbox. A new one is created on the next call.
+    pub async fn close(&self) -> Result<()> {
+        if let Some(sandbox) = self.sandbox.lock().await.take() {
+            self.client
+                .delete(format!("{}/sandboxes/{}", self.api_url, sandbox.sandbox_id))
+                .header("X-API-Key", &self.api_key)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+/// Python code writing `content` to `path`.
+fn upload_code(path: &str, content: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+    format!(
+        "import base64, os\nos.makedirs(os.path.dirname(os.path.abspath({path:?})), exist_ok=True)\nwith open({path:?}, 'wb') as f:\n    f.write(base64.b64decode({encoded:?}))"
+    )
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for E2BInterpreterTool {
+    type Params = E2BInterpreterToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+    async fn forward(&self, arguments: E2BInterpreterToolParams) -> Result<String> {
+        let execution = self
+            .execute(&arguments.code, arguments.language.unwrap_or_default())
+            .await?;
+        if let Some(error) = execution.error {
+            return Err(anyhow::anyhow!(
+                "Error executing code: {}\nStdout: {}",
+                error,
+                execution.stdout
+            ));
+        }
+
+        let mut output = execution.stdout;
+        if let Some(result) = execution.result {
+            output.push_str(&result);
+        }
+        if !execution.stderr.is_empty() {
+            output.push_str(&format!("\nStderr: {}", execution.stderr));
+        }
+        if output.trim().is_empty() {
+            Ok("No Results. Make sure to print the result.".to_string())
+        } else {
+            Ok(format!("Evaluation Result: {}", output))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_execution_events() {
+        let body = [
+            r#"{"type":"stdout","text":"hello\n","timestamp":1}"#,
+            r#"{"type":"result","text":"2","is_main_result":true}"#,
+            r#"{"type":"number_of_executions","execution_count":1}"#,
+            r#"{"type":"end_of_execution"}"#,
+        ]
+        .join("\n");
+        let execution = E2BExecution::from_events(&body);
+        assert_eq!(execution.stdout, "hello\n");
+        assert_eq!(execution.result.as_deref(), Some("2"));
+        assert!(execution.error.is_none());
+
+        let body = r#"{"type":"error","name":"NameError","value":"name 'x' is not defined","traceback":""}"#;
+        let execution = E2BExecution::from_events(body);
+        assert_eq!(
+            execution.error.as_deref(),
+            Some("NameError: name 'x' is not defined")
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! This module contains the Elasticsearch tool. The model uses this tool to retrieve documents from an Elasticsearch
+//! or OpenSearch index, with BM25 search and, when an embedding model is set, hybrid BM25 and kNN search.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{base::BaseTool, tool_traits::Tool};
+
+const DEFAULT_EMBEDDING_URL: &str = "https://api.openai.com/v1/embeddings";
+const DEFAULT_TOP_K: usize = 5;
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "ElasticsearchToolParams")]
+pub struct ElasticsearchToolParams {
+    #[schemars(description = "The query to search the documents for")]
+    query: String,
+    #[schemars(description = "The number of documents to return (default: 5)")]
+    top_k: Option<usize>,
+}
+
+/// The search engine serving the index. The kNN query differs between the two.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchEngine {
+    #[default]
+    Elasticsearch,
+    OpenSearch,
+}
+
+#[derive(Debug, Clone)]
+pub enum ElasticsearchAuth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// An Elasticsearch API key, sent as `Authorization: ApiKey <key>`.
+    ApiKey(String),
+    Bearer(String),
+}
+
+/// The fields of the documents in the index. Nested fields are written with dots, e.g. `metadata.url`.
+#[derive(Debug, Clone)]
+pub struct FieldMapping {
+    /// The text of the document, searched with BM25 and returned to the model.
+    pub content: String,
+    pub title: Option<String>,
+    /// The url or path of the document.
+    pub source: Option<String>,
+    /// The dense vector of the document, searched with kNN.
+    pub vector: Option<String>,
+}
+
+impl Default for FieldMapping {
+    fn default() -> Self {
+        FieldMapping {
+            content: "content".to_string(),
+            title: Some("title".to_string()),
+            source: Some("url".to_string()),
+            vector: None,
+        }
+    }
+}
+
+/// An OpenAI-compatible embedding model, used to embed the query for kNN search.
+#[derive(Debug, Clone)]
+struct EmbeddingModel {
+    model_id: String,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+/// A document returned by the search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub title: Option<String>,
+    pub source: Option<String>,
+    pub content: String,
+    pub score: f64,
+}
+
+/// Format the documents as numbered context blocks, so the model can cite them.
+pub fn format_documents(documents: &[Document]) -> String {
+    documents
+        .iter()
+        .enumerate()
+        .map(|(i, document)| {
+            let mut block = format!("[{}] {}", i + 1, document.title.as_deref().unwrap_or("Untitled"));
+            if let Some(source) = &document.source {
+                block.push_str(&format!("\nSource: {}", source));
+            }
+            block.push_str(&format!("\n{}", document.content));
+            block
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Searches an Elasticsearch or OpenSearch index. Without an embedding model, or without a vector field in the
+/// mapping, the search is BM25 only.
+#[derive(Debug, Clone)]
+pub struct ElasticsearchTool {
+    pub tool: BaseTool,
+    url: String,
+    index: String,
+    engine: SearchEngine,
+    auth: Option<ElasticsearchAuth>,
+    fields: FieldMapping,
+    embedding_model: Option<EmbeddingModel>,
+    top_k: usize,
+}
+
+impl ElasticsearchTool {
+    pub fn new(url: &str, index: &str) -> Self {
+        ElasticsearchTool {
+            tool: BaseTool {
+                name: "document_search",
+                description: "Searches the document store for your query and returns the most relevant passages with their sources.",
+            },
+            url: url.trim_end_matches('/').to_string(),
+            index: index.to_string(),
+            engine: SearchEngine::default(),
+            auth: None,
+            fields: FieldMapping::default(),
+            embedding_model: None,
+            top_k: DEFAULT_TOP_K,
+        }
+    }
+
+    /// Configure the tool from `ELASTICSEARCH_URL`, `ELASTICSEARCH_INDEX` and either `ELASTICSEARCH_API_KEY` or
+    /// `ELASTICSEARCH_USERNAME` and `ELASTICSEARCH_PASSWORD`.
+    pub fn from_env() -> Result<Self> {
+        let url = std::env::var("ELASTICSEARCH_URL").map_err(|_| anyhow!("ELASTICSEARCH_URL is not set"))?;
+        let index =
+            std::env::var("ELASTICSEARCH_INDEX").map_err(|_| anyhow!("ELASTICSEARCH_INDEX is not set"))?;
+        let auth = match (
+            std::env::var("ELASTICSEARCH_API_KEY"),
+            std::env::var("ELASTICSEARCH_USERNAME"),
+        ) {
+            (Ok(api_key), _) => Some(ElasticsearchAuth::ApiKey(api_key)),
+            (_, Ok(username)) => Some(ElasticsearchAuth::Basic {
+                username,
+                password: std::env::var("ELASTICSEARCH_PASSWORD").ok(),
+            }),
+            _ => None,
+        };
+        Ok(Self::new(&url, &index).with_auth(auth))
+    }
+
+    pub fn with_engine(mut self, engine: SearchEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: Option<ElasticsearchAuth>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_fields(mut self, fields: FieldMapping) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Embed the query with an OpenAI-compatible model for kNN search. `base_url` is the embeddings endpoint, and
+    /// the API key defaults to `OPENAI_API_KEY`.
+    pub fn with_embedding_model(
+        mut self,
+        model_id: &str,
+        base_url: Option<&str>,
+        api_key: Option<&str>,
+    ) -> Self {
+        self.embedding_model = Some(EmbeddingModel {
+            model_id: model_id.to_string(),
+            base_url: base_url.unwrap_or(DEFAULT_EMBEDDING_URL).to_string(),
+            api_key: api_key
+                .map(|key| key.to_string())
+                .or_else(|| std::env::var("OPENAI_API_KEY").ok()),
+        });
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: Option<usize>) -> Self {
+        self.top_k = top_k.unwrap_or(DEFAULT_TOP_K);
+        self
+    }
+
+    /// Name and description of the tool, e.g. to tell the model what the index contains.
+    pub fn with_description(mut self, name: &'static str, description: &'static str) -> Self {
+        self.tool = BaseTool { name, description };
+        self
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.auth {
+            Some(ElasticsearchAuth::Basic { username, password }) => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Some(ElasticsearchAuth::ApiKey(api_key)) => {
+                request.header("Authorization", format!("ApiKey {}", api_key))
+            }
+            Some(ElasticsearchAuth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// The body of the search request. With a query vector, the BM25 and kNN scores are summed.
+    fn search_body(&self, query: &str, vector: Option<&[f32]>, top_k: usize) -> Value {
+        let mut text_fields = vec![self.fields.content.clone()];
+        if let Some(title) = &self.fields.title {
+            text_fields.push(format!("{}^2", title));
+        }
+        let text_query = json!({ "multi_match": { "query": query, "fields": text_fields } });
+        let source_fields = [Some(&self.fields.content), self.fields.title.as_ref(), self.fields.source.as_ref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        match (vector, &self.fields.vector, self.engine) {
+            (Some(vector), Some(vector_field), SearchEngine::Elasticsearch) => json!({
+                "size": top_k,
+                "_source": source_fields,
+                "query": text_query,
+                "knn": {
+                    "field": vector_field,
+                    "query_vector": vector,
+                    "k": top_k,
+                    "num_candidates": top_k * 10,
+                },
+            }),
+            (Some(vector), Some(vector_field), SearchEngine::OpenSearch) => json!({
+                "size": top_k,
+                "_source": source_fields,
+                "query": {
+                    "bool": {
+                        "should": [
+                            text_query,
+                            { "knn": { vector_field: { "vector": vector, "k": top_k } } },
+                        ],
+                    },
+                },
+            }),
+            _ => json!({
+                "size": top_k,
+                "_source": source_fields,
+                "query": text_query,
+            }),
+        }
+    }
+
+    async fn embed(&self, embedding_model: &EmbeddingModel, query: &str) -> Result<Vec<f32>> {
+        let mut request = reqwest::Client::new()
+            .post(&embedding_model.base_url)
+            .json(&json!({ "model": embedding_model.model_id, "input": query }));
+        if let Some(api_key) = &embedding_model.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Embedding model returned HTTP {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let response: Value = response.json().await?;
+        serde_json::from_value(response["data"][0]["embedding"].clone())
+            .map_err(|_| anyhow!("Embedding model returned no embedding"))
+    }
+
+    pub async fn search(&self, query: &str, top_k: Option<usize>) -> Result<Vec<Document>> {
+        let top_k = top_k.unwrap_or(self.top_k);
+        let vector = match (&self.embedding_model, &self.fields.vector) {
+            (Some(embedding_model), Some(_)) => Some(self.embed(embedding_model, query).await?),
+            _ => None,
+        };
+        let body = self.search_body(query, vector.as_deref(), top_k);
+
+        let request = reqwest::Client::new()
+            .post(format!("{}/{}/_search", self.url, self.index))
+            .json(&body);
+        let response = self.authorize(request).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Search returned HTTP {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let response: Value = response.json().await?;
+        Ok(self.documents(&response))
+    }
+
+    fn documents(&self, response: &Value) -> Vec<Document> {
+        response["hits"]["hits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hit| {
+                let source = &hit["_source"];
+                Some(Document {
+                    content: field(source, &self.fields.content)?,
+                    title: self.fields.title.as_ref().and_then(|title| field(source, title)),
+                    source: self.fields.source.as_ref().and_then(|path| field(source, path)),
+                    score: hit["_score"].as_f64().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The text of a field of a document, following the dots of nested fields.
+fn field(source: &Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(source, |value, key| value.get(key))?;
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for ElasticsearchTool {
+    type Params = ElasticsearchToolParams;
+
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: ElasticsearchToolParams) -> Result<String> {
+        let documents = self.search(&arguments.query, arguments.top_k).await?;
+        if documents.is_empty() {
+            return Err(anyhow!("No documents found for query: {}", arguments.query));
+        }
+        Ok(format_documents(&documents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool() -> ElasticsearchTool {
+        ElasticsearchTool::new("http://localhost:9200/", "docs").with_fields(FieldMapping {
+            vector: Some("embedding".to_string()),
+            source: Some("metadata.url".to_string()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_search_body() {
+        let body = tool().search_body("rust", None, 3);
+        assert_eq!(body["query"]["multi_match"]["fields"], json!(["content", "title^2"]));
+        assert!(body.get("knn").is_none());
+
+        let body = tool().search_body("rust", Some(&[0.5, 1.0]), 3);
+        assert_eq!(body["knn"]["field"], "embedding");
+        assert_eq!(body["knn"]["num_candidates"], 30);
+
+        let body = tool()
+            .with_engine(SearchEngine::OpenSearch)
+            .search_body("rust", Some(&[0.5, 1.0]), 3);
+        assert_eq!(body["query"]["bool"]["should"][1]["knn"]["embedding"]["k"], 3);
+    }
+
+    #[test]
+    fn test_documents() {
+        let response = json!({
+            "hits": { "hits": [
+                { "_score": 2.5, "_source": { "title": "Rust", "content": "A language.", "metadata": { "url": "https://rust-lang.org" } } },
+                { "_score": 1.0, "_source": { "title": "Empty" } },
+            ] }
+        });
+        let documents = tool().documents(&response);
+        assert_eq!(documents.len(), 1);
+        assert_eq!(
+            format_documents(&documents),
+            "[1] Rust\nSource: https://rust-lang.org\nA language."
+        );
+    }
+}
@@ -8,6 +8,7 @@ use serde_json::json;
 
 use super::base::BaseTool;
 use super::tool_traits::Tool;
+use crate::secrets::SecretKey;
 use anyhow::Result;
 
 #[derive(Deserialize, JsonSchema)]
@@ -49,6 +50,8 @@ pub struct ExaSearchTool {
     pub tool: BaseTool,
     pub max_results: usize,
     pub api_key: String,
+    #[serde(skip)]
+    pub secret: Option<SecretKey>,
 }
 
 impl ExaSearchTool {
@@ -65,6 +68,22 @@ impl ExaSearchTool {
             },
             max_results,
             api_key,
+            secret: None,
+        }
+    }
+
+    /// Read the API key from a secret provider on each search, so that a rotated key is used at once.
+    pub fn from_secret(max_results: usize, secret: SecretKey) -> Self {
+        Self {
+            secret: Some(secret),
+            ..Self::new(max_results, Some(String::new()))
+        }
+    }
+
+    async fn api_key(&self) -> Result<String> {
+        match &self.secret {
+            Some(secret) => secret.resolve().await,
+            None => Ok(self.api_key.clone()),
         }
     }
     pub async fn forward(&self, query: &str) -> Result<ExaSearchResponse> {
@@ -72,7 +91,7 @@ impl ExaSearchTool {
         let mut headers = HeaderMap::new();
         headers.insert(
             "x-api-key",
-            HeaderValue::from_str(&self.api_key).expect("Invalid API key"),
+            HeaderValue::from_str(&self.api_key().await?).expect("Invalid API key"),
         );
 
         let body = json!({
@@ -94,7 +113,8 @@ impl ExaSearchTool {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Tool for ExaSearchTool {
     type Params = ExaSearchToolParams;
     fn name(&self) -> &'static str {
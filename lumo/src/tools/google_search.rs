@@ -8,6 +8,7 @@ use serde_json::json;
 
 use super::base::BaseTool;
 use super::tool_traits::Tool;
+use crate::secrets::SecretKey;
 
 #[derive(Deserialize, JsonSchema)]
 #[schemars(title = "GoogleSearchToolParams")]
@@ -22,11 +23,13 @@ pub struct GoogleSearchToolParams {
 pub struct GoogleSearchTool {
     pub tool: BaseTool,
     pub api_key: String,
+    #[serde(skip)]
+    pub secret: Option<SecretKey>,
 }
 
 impl GoogleSearchTool {
     pub fn new(api_key: Option<String>) -> Self {
-        let api_key = api_key.unwrap_or(std::env::var("SERPAPI_API_KEY").unwrap());
+        let api_key = api_key.unwrap_or_else(|| std::env::var("SERPAPI_API_KEY").unwrap());
 
         GoogleSearchTool {
             tool: BaseTool {
@@ -34,6 +37,22 @@ impl GoogleSearchTool {
                 description: "Performs a google web search for your query then returns a string of the top search results.",
             },
             api_key,
+            secret: None,
+        }
+    }
+
+    /// Read the API key from a secret provider on each search, so that a rotated key is used at once.
+    pub fn from_secret(secret: SecretKey) -> Self {
+        Self {
+            secret: Some(secret),
+            ..Self::new(Some(String::new()))
+        }
+    }
+
+    async fn api_key(&self) -> Result<String> {
+        match &self.secret {
+            Some(secret) => secret.resolve().await,
+            None => Ok(self.api_key.clone()),
         }
     }
 
@@ -42,7 +61,7 @@ impl GoogleSearchTool {
             let mut params = json!({
                 "engine": "google",
                 "q": query,
-                "api_key": self.api_key,
+                "api_key": self.api_key().await?,
                 "google_domain": "google.com",
             });
 
@@ -122,7 +141,8 @@ impl GoogleSearchTool {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Tool for GoogleSearchTool {
     type Params = GoogleSearchToolParams;
     fn name(&self) -> &'static str {
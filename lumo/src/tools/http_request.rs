@@ -0,0 +1,308 @@
+//! This module contains the HTTP request tool. The model uses this tool to call REST APIs, e.g. the internal APIs of
+//! a company, without a custom tool for each of them. Only the domains of the allowlist can be called.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::redirect;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    base::BaseTool,
+    tool_traits::Tool,
+    visit_website::{matches_domain, normalize_domains},
+};
+
+/// Response bodies are cut after this many bytes unless the tool is configured otherwise.
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 100_000;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+#[schemars(title = "HttpMethod")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+}
+
+impl From<HttpMethod> for reqwest::Method {
+    fn from(method: HttpMethod) -> Self {
+        match method {
+            HttpMethod::Get => reqwest::Method::GET,
+            HttpMethod::Post => reqwest::Method::POST,
+            HttpMethod::Put => reqwest::Method::PUT,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "HttpRequestToolParams")]
+pub struct HttpRequestToolParams {
+    #[schemars(description = "GET, POST or PUT (default: GET)")]
+    #[serde(default)]
+    method: HttpMethod,
+    #[schemars(description = "The url to call, e.g. https://api.example.com/v1/orders")]
+    url: String,
+    #[schemars(
+        description = "Optionally the headers of the request, e.g. {\"Accept\": \"application/json\"}"
+    )]
+    headers: Option<HashMap<String, String>>,
+    #[schemars(description = "Optionally the query parameters, added to the ones of the url")]
+    query: Option<HashMap<String, String>>,
+    #[schemars(description = "Optionally the JSON body of a POST or PUT request")]
+    body: Option<Value>,
+}
+
+/// Calls REST APIs on the domains of its allowlist, and returns the status, headers and body of the response.
+#[derive(Debug, Clone)]
+pub struct HttpRequestTool {
+    pub tool: BaseTool,
+    /// Domains the tool may call, including their subdomains. Redirects to other domains are not followed.
+    allowed_domains: Vec<String>,
+    /// Headers sent with every request, e.g. the credentials of an API. They take precedence over the headers of the
+    /// model.
+    headers: HashMap<String, String>,
+    /// Number of bytes of the response body read at most.
+    max_response_size: usize,
+    timeout: Duration,
+}
+
+impl HttpRequestTool {
+    /// A tool that may only call the given domains and their subdomains, e.g. `api.example.com` or
+    /// `*.internal.example.com`.
+    pub fn new(allowed_domains: Vec<String>) -> Self {
+        HttpRequestTool {
+            tool: BaseTool {
+                name: "http_request",
+                description: "Sends an HTTP GET, POST or PUT request to a REST API and returns the status, headers and body of the response. Use it to read from and write to the APIs you are given access to. Long bodies are cut",
+            },
+            allowed_domains: normalize_domains(allowed_domains),
+            headers: HashMap::new(),
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Send these headers with every request, e.g. `Authorization`, without showing them to the model.
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = max_response_size.max(1);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn check_url(&self, url: &str) -> Result<Url> {
+        let url = Url::parse(url).map_err(|e| anyhow!("{} is not a valid url: {}", url, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!(
+                "Only http and https urls can be called, not {}",
+                url
+            ));
+        }
+        if !matches_domain(&self.allowed_domains, &url) {
+            return Err(anyhow!(
+                "Calling {} is not allowed. Only these domains can be called: {}",
+                url,
+                self.allowed_domains.join(", ")
+            ));
+        }
+        Ok(url)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn client(&self) -> Result<reqwest::Client> {
+        let allowed_domains = self.allowed_domains.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if matches_domain(&allowed_domains, attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        });
+        Ok(reqwest::Client::builder().redirect(policy).build()?)
+    }
+
+    /// The redirects are followed by the host's fetch on wasm32, without checking their domains.
+    #[cfg(target_arch = "wasm32")]
+    fn client(&self) -> Result<reqwest::Client> {
+        Ok(reqwest::Client::new())
+    }
+
+    pub async fn request(&self, params: HttpRequestToolParams) -> Result<String> {
+        let url = self.check_url(&params.url)?;
+        let mut request = self.client()?.request(params.method.into(), url.clone());
+        if let Some(query) = &params.query {
+            request = request.query(query);
+        }
+        for (name, value) in params.headers.iter().flatten() {
+            if !self
+                .headers
+                .keys()
+                .any(|key| key.eq_ignore_ascii_case(name))
+            {
+                request = request.header(name, value);
+            }
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(body) = &params.body {
+            if params.method == HttpMethod::Get {
+                return Err(anyhow!("A GET request cannot have a body, use POST or PUT"));
+            }
+            request = request.json(body);
+        }
+
+        let response = request
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to call {}: {}", url, e))?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        let mut chunks = response.bytes_stream();
+        while let Some(chunk) = chunks
+            .next()
+            .await
+            .transpose()
+            .map_err(|e| anyhow!("Failed to read the response of {}: {}", url, e))?
+        {
+            let remaining = self.max_response_size - body.len();
+            if chunk.len() > remaining {
+                body.extend_from_slice(&chunk[..remaining]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(format_response(status, headers, &body, truncated))
+    }
+}
+
+/// The response as JSON for the model. A JSON body is kept as JSON unless it is cut.
+fn format_response(
+    status: u16,
+    headers: HashMap<String, String>,
+    body: &[u8],
+    truncated: bool,
+) -> String {
+    let body = match serde_json::from_slice::<Value>(body) {
+        Ok(json) if !truncated => json,
+        _ => Value::String(String::from_utf8_lossy(body).to_string()),
+    };
+    let mut response = json!({
+        "status": status,
+        "headers": headers,
+        "body": body,
+    });
+    if truncated {
+        response["truncated"] = json!(true);
+    }
+    response.to_string()
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for HttpRequestTool {
+    type Params = HttpRequestToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: HttpRequestToolParams) -> Result<String> {
+        self.request(arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allowed_domains() {
+        let tool = HttpRequestTool::new(vec!["*.Example.com".to_string()]);
+        assert!(tool.check_url("https://api.example.com/v1/orders").is_ok());
+        assert!(tool.check_url("http://example.com").is_ok());
+        let error = tool
+            .check_url("https://example.org")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Only these domains can be called: example.com"));
+        assert!(tool.check_url("file:///etc/passwd").is_err());
+        assert!(HttpRequestTool::new(vec![])
+            .check_url("https://example.com")
+            .is_err());
+
+        // Checked before the request is sent.
+        let params = HttpRequestToolParams {
+            method: HttpMethod::Get,
+            url: "https://api.example.com".to_string(),
+            headers: None,
+            query: None,
+            body: Some(json!({"id": 1})),
+        };
+        let error = tool.forward(params).await.unwrap_err().to_string();
+        assert!(error.contains("cannot have a body"));
+    }
+
+    #[test]
+    fn test_format_response() {
+        let headers = HashMap::from([("content-type".to_string(), "application/json".to_string())]);
+        let response: Value = serde_json::from_str(&format_response(
+            200,
+            headers.clone(),
+            br#"{"id": 1}"#,
+            false,
+        ))
+        .unwrap();
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], json!({"id": 1}));
+        assert!(response.get("truncated").is_none());
+
+        let response: Value =
+            serde_json::from_str(&format_response(500, headers, br#"{"id": 1"#, true)).unwrap();
+        assert_eq!(response["body"], r#"{"id": 1"#);
+        assert_eq!(response["truncated"], true);
+
+        let params: HttpRequestToolParams =
+            serde_json::from_value(json!({"method": "POST", "url": "https://example.com"}))
+                .unwrap();
+        assert_eq!(params.method, HttpMethod::Post);
+    }
+}
@@ -1,26 +1,71 @@
 //! This module contains the tools that can be used in an agent. These are the default tools that are available.
 //! You can also implement your own tools by implementing the `Tool` trait.
 
+pub mod agent_tool;
+pub mod ask_user;
 pub mod base;
+pub mod budget;
+pub mod datetime;
 pub mod ddg_search;
+pub mod documents;
+pub mod e2b_interpreter;
+pub mod elasticsearch;
 pub mod exa_search;
 pub mod tavily_search;
 pub mod final_answer;
+pub mod google_drive;
 pub mod google_search;
+pub mod http_request;
+pub mod multi_search;
+pub mod slack;
+pub mod strict_schema;
+pub mod time_zone;
 pub mod tool_traits;
+pub mod validation;
 pub mod visit_website;
+pub mod web_content;
+pub mod web_crawl;
+pub mod webhook;
+pub mod zotero;
 
+#[cfg(feature = "plugins")]
+pub mod plugin;
 #[cfg(feature = "code-agent")]
 pub mod python_interpreter;
+#[cfg(feature = "screenshot")]
+pub mod web_screenshot;
 
+pub use agent_tool::*;
+pub use ask_user::*;
 pub use base::*;
+pub use budget::*;
+pub use datetime::*;
 pub use ddg_search::*;
+pub use documents::*;
+pub use e2b_interpreter::*;
+pub use elasticsearch::*;
 pub use exa_search::*;
 pub use final_answer::*;
+pub use google_drive::*;
 pub use google_search::*;
+pub use http_request::*;
+pub use multi_search::*;
+pub use slack::*;
 pub use tavily_search::*;
+pub use time_zone::*;
 pub use tool_traits::*;
 pub use visit_website::*;
+pub use web_content::*;
+pub use web_crawl::*;
+pub use webhook::*;
+pub use zotero::*;
 
+#[cfg(feature = "plugins")]
+pub use plugin::*;
 #[cfg(feature = "code-agent")]
 pub use python_interpreter::*;
+#[cfg(feature = "screenshot")]
+pub use web_screenshot::*;
+
+#[cfg(feature = "macros")]
+pub use lumo_macros::LumoTool;
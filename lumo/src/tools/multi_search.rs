@@ -0,0 +1,298 @@
+//! This module contains the multi-query search tool.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::base::BaseTool;
+use super::ddg_search::{DuckDuckGoSearchTool, SearchResult};
+use super::exa_search::ExaSearchTool;
+use super::tavily_search::TavilySearchTool;
+use super::tool_traits::Tool;
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "MultiSearchToolParams")]
+pub struct MultiSearchToolParams {
+    #[schemars(description = "The queries to search for. Each query is searched separately")]
+    queries: Vec<String>,
+}
+
+/// The search engine the queries of a `MultiSearchTool` are sent to.
+#[derive(Debug, Clone)]
+pub enum SearchBackend {
+    DuckDuckGo(DuckDuckGoSearchTool),
+    Tavily(TavilySearchTool),
+    Exa(ExaSearchTool),
+}
+
+impl SearchBackend {
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        match self {
+            SearchBackend::DuckDuckGo(tool) => tool.forward(query).await,
+            SearchBackend::Tavily(tool) => {
+                let params = serde_json::from_value(json!({ "query": query }))?;
+                let response: Value = serde_json::from_str(&tool.forward(params).await?)?;
+                let results = response["results"]
+                    .as_array()
+                    .map(|results| {
+                        results
+                            .iter()
+                            .map(|r| SearchResult {
+                                title: r["title"].as_str().unwrap_or_default().to_string(),
+                                snippet: r["content"].as_str().unwrap_or_default().to_string(),
+                                url: r["url"].as_str().unwrap_or_default().to_string(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(results)
+            }
+            SearchBackend::Exa(tool) => {
+                let response = tool.forward(query).await?;
+                Ok(response
+                    .results
+                    .into_iter()
+                    .map(|r| SearchResult {
+                        title: r.title,
+                        snippet: if r.summary.is_empty() { r.text } else { r.summary },
+                        url: r.url,
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+impl From<DuckDuckGoSearchTool> for SearchBackend {
+    fn from(tool: DuckDuckGoSearchTool) -> Self {
+        SearchBackend::DuckDuckGo(tool)
+    }
+}
+
+impl From<TavilySearchTool> for SearchBackend {
+    fn from(tool: TavilySearchTool) -> Self {
+        SearchBackend::Tavily(tool)
+    }
+}
+
+impl From<ExaSearchTool> for SearchBackend {
+    fn from(tool: ExaSearchTool) -> Self {
+        SearchBackend::Exa(tool)
+    }
+}
+
+/// Searches several queries concurrently and merges the results, so the agent can research a topic from several
+/// angles in a single step.
+#[derive(Debug, Clone)]
+pub struct MultiSearchTool {
+    pub tool: BaseTool,
+    pub backend: SearchBackend,
+    pub max_queries: usize,
+    pub max_results_per_query: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergedSearchResult {
+    pub title: String,
+    pub snippet: String,
+    pub url: String,
+    pub queries: Vec<String>,
+}
+
+impl MultiSearchTool {
+    pub fn new(backend: impl Into<SearchBackend>) -> Self {
+        MultiSearchTool {
+            tool: BaseTool {
+                name: "multi_search",
+                description: "Performs a web search for each of the given queries at the same time and returns the merged top search results. Use it to search for several related queries in one step.",
+            },
+            backend: backend.into(),
+            max_queries: 5,
+            max_results_per_query: None,
+        }
+    }
+
+    /// Limit the number of queries searched per call. Extra queries are ignored. Defaults to 5.
+    pub fn with_max_queries(mut self, max_queries: Option<usize>) -> Self {
+        self.max_queries = max_queries.unwrap_or(5);
+        self
+    }
+
+    pub fn with_max_results_per_query(mut self, max_results: Option<usize>) -> Self {
+        self.max_results_per_query = max_results;
+        self
+    }
+
+    pub async fn forward(
+        &self,
+        queries: &[String],
+    ) -> (Vec<MergedSearchResult>, Vec<(String, anyhow::Error)>) {
+        let queries = queries.iter().take(self.max_queries).collect::<Vec<_>>();
+        let responses = join_all(queries.iter().map(|query| self.backend.search(query))).await;
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        for (query, response) in queries.into_iter().zip(responses) {
+            match response {
+                Ok(mut query_results) => {
+                    if let Some(max_results) = self.max_results_per_query {
+                        query_results.truncate(max_results);
+                    }
+                    results.push((query.clone(), query_results));
+                }
+                Err(e) => errors.push((query.clone(), e)),
+            }
+        }
+        (merge_results(results), errors)
+    }
+}
+
+/// Normalize a URL so that the same page found by different queries is only listed once. Only the scheme and the
+/// host are lowercased, as the paths and queries of many sites are case-sensitive.
+fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => (String::new(), url),
+    };
+    let (host, path) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let url = match scheme.as_str() {
+        "" | "http" | "https" => format!("{}{}", host, path),
+        _ => format!("{}://{}{}", scheme, host, path),
+    };
+    url.trim_end_matches('/').to_string()
+}
+
+/// Merge the results of each query in order, keeping the first result for each URL.
+fn merge_results(results: Vec<(String, Vec<SearchResult>)>) -> Vec<MergedSearchResult> {
+    let mut merged: Vec<MergedSearchResult> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (query, query_results) in results {
+        for result in query_results {
+            let key = normalize_url(&result.url);
+            if key.is_empty() {
+                continue;
+            }
+            if let Some(&index) = seen.get(&key) {
+                let existing = &mut merged[index];
+                if !existing.queries.contains(&query) {
+                    existing.queries.push(query.clone());
+                }
+                continue;
+            }
+            seen.insert(key, merged.len());
+            merged.push(MergedSearchResult {
+                title: result.title,
+                snippet: result.snippet,
+                url: result.url,
+                queries: vec![query.clone()],
+            });
+        }
+    }
+    merged
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for MultiSearchTool {
+    type Params = MultiSearchToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+    async fn forward(&self, arguments: MultiSearchToolParams) -> Result<String> {
+        if arguments.queries.is_empty() {
+            return Err(anyhow::anyhow!("No queries given"));
+        }
+        let (results, errors) = self.forward(&arguments.queries).await;
+        if results.is_empty() {
+            if let Some((query, e)) = errors.first() {
+                return Err(anyhow::anyhow!("Search failed for query '{}': {}", query, e));
+            }
+            return Err(anyhow::anyhow!(
+                "No results found for queries: {}",
+                arguments.queries.join(", ")
+            ));
+        }
+        let mut output = results
+            .iter()
+            .map(|r| {
+                format!(
+                    "[{}]({}) \n{} \nFound by: {}",
+                    r.title,
+                    r.url,
+                    r.snippet,
+                    r.queries.join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        for (query, e) in errors {
+            output.push_str(&format!("\n\nSearch failed for query '{}': {}", query, e));
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str, url: &str) -> SearchResult {
+        SearchResult {
+            title: title.to_string(),
+            snippet: String::new(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_results() {
+        let merged = merge_results(vec![
+            (
+                "rust async".to_string(),
+                vec![
+                    result("Tokio", "https://tokio.rs/"),
+                    result("Async book", "rust-lang.github.io/async-book"),
+                ],
+            ),
+            (
+                "tokio runtime".to_string(),
+                vec![
+                    result("Tokio docs", "www.tokio.rs"),
+                    result("Runtime", "https://docs.rs/tokio/latest/tokio/runtime"),
+                ],
+            ),
+        ]);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].title, "Tokio");
+        assert_eq!(merged[0].queries, vec!["rust async", "tokio runtime"]);
+        assert_eq!(merged[2].queries, vec!["tokio runtime"]);
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("HTTPS://WWW.Example.com/Docs/Page?Id=AbC#Top"),
+            "example.com/Docs/Page?Id=AbC#Top"
+        );
+        assert_eq!(normalize_url(" http://example.com/ "), "example.com");
+        assert_eq!(normalize_url("Example.com?q=Rust"), "example.com?q=Rust");
+        assert_eq!(
+            normalize_url("FTP://Files.Example.com/README"),
+            "ftp://files.example.com/README"
+        );
+        assert_ne!(
+            normalize_url("https://github.com/rust-lang/Rust"),
+            normalize_url("https://github.com/rust-lang/rust")
+        );
+    }
+}
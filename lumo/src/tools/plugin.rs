@@ -0,0 +1,496 @@
+//! This module loads tools at runtime from a plugins directory, so that tools can be added without recompiling.
+//!
+//! Each plugin is a subdirectory with a `plugin.yaml` manifest giving the name, the description and the JSON
+//! schema of the parameters of the tool, and how to run it:
+//!
+//! ```yaml
+//! name: word_count
+//! description: Count the words of a text.
+//! parameters:
+//!   type: object
+//!   properties:
+//!     text:
+//!       type: string
+//!   required: [text]
+//! command: python3
+//! args: [word_count.py]
+//! ```
+//!
+//! A subprocess plugin (`command`) is started in the plugin directory for each call. It reads one JSON request,
+//! `{"tool": "word_count", "arguments": {"text": "..."}}`, on stdin and writes `{"output": "..."}` or
+//! `{"error": "..."}` on stdout. Any other output is used as the output of the tool.
+//!
+//! A WASM plugin (`wasm: word_count.wasm`, with the `wasm-plugins` feature) is a module without imports that
+//! exports its `memory`, `alloc(len: i32) -> i32`, which returns a buffer for the request, and
+//! `call(ptr: i32, len: i32) -> i64`, which takes the JSON request and returns the JSON response as
+//! `(ptr << 32) | len`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::tool_traits::{AnyTool, AsyncTool, ToolFunctionInfo, ToolInfo, ToolType};
+use crate::errors::AgentError;
+
+/// The manifest of a plugin in its directory.
+pub const MANIFEST_FILE: &str = "plugin.yaml";
+
+/// Time a call has to finish, unless `timeout_secs` is set.
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Fuel, roughly the number of instructions, a WASM call may use, unless `max_fuel` is set.
+#[cfg(feature = "wasm-plugins")]
+const DEFAULT_MAX_FUEL: u64 = 10_000_000_000;
+
+/// The `plugin.yaml` of a plugin. Exactly one of `command` and `wasm` is set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments. Defaults to an object without properties.
+    #[serde(default)]
+    pub parameters: Option<Value>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables added to the inherited environment of the process.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The WASM module, relative to the plugin directory.
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Fuel a WASM call may use before it is stopped.
+    #[serde(default)]
+    pub max_fuel: Option<u64>,
+}
+
+/// How the tool of a plugin is run.
+#[derive(Clone)]
+enum Runtime {
+    Process {
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        timeout: Duration,
+    },
+    #[cfg(feature = "wasm-plugins")]
+    Wasm(Arc<wasm::WasmModule>),
+}
+
+/// A tool loaded from a plugin directory.
+#[derive(Clone)]
+pub struct PluginTool {
+    name: &'static str,
+    description: &'static str,
+    parameters: Value,
+    dir: Arc<PathBuf>,
+    runtime: Runtime,
+}
+
+impl PluginTool {
+    /// Load the plugin of the directory from its `plugin.yaml`.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let path = dir.join(MANIFEST_FILE);
+        let manifest = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read plugin manifest: {:?}", path))?;
+        let manifest: PluginManifest = serde_yaml::from_str(&manifest)
+            .with_context(|| format!("Failed to parse plugin manifest: {:?}", path))?;
+        Self::from_manifest(manifest, dir)
+    }
+
+    pub fn from_manifest(manifest: PluginManifest, dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let valid_name = !manifest.name.is_empty()
+            && manifest
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            bail!(
+                "Invalid plugin name '{}': use letters, digits, '_' and '-'",
+                manifest.name
+            );
+        }
+        let timeout = Duration::from_secs(manifest.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        let runtime = match (manifest.command, &manifest.wasm) {
+            (Some(command), None) => Runtime::Process {
+                command,
+                args: manifest.args,
+                env: manifest.env,
+                timeout,
+            },
+            #[cfg(feature = "wasm-plugins")]
+            (None, Some(module)) => Runtime::Wasm(Arc::new(wasm::WasmModule::load(
+                &dir.join(module),
+                manifest.max_fuel.unwrap_or(DEFAULT_MAX_FUEL),
+            )?)),
+            #[cfg(not(feature = "wasm-plugins"))]
+            (None, Some(_)) => bail!(
+                "Plugin '{}' is a WASM module, which needs the wasm-plugins feature",
+                manifest.name
+            ),
+            _ => bail!(
+                "Plugin '{}' must have either a command or a wasm module",
+                manifest.name
+            ),
+        };
+        let parameters = manifest
+            .parameters
+            .unwrap_or_else(|| json!({ "type": "object", "properties": {} }));
+        if !parameters.is_object() {
+            bail!(
+                "The parameters of plugin '{}' must be a JSON schema object",
+                manifest.name
+            );
+        }
+        Ok(Self {
+            name: Box::leak(manifest.name.into_boxed_str()),
+            description: Box::leak(manifest.description.into_boxed_str()),
+            parameters,
+            dir: Arc::new(dir),
+            runtime,
+        })
+    }
+
+    async fn call(&self, request: String) -> Result<String> {
+        match &self.runtime {
+            Runtime::Process {
+                command,
+                args,
+                env,
+                timeout,
+            } => {
+                let mut child = Command::new(command)
+                    .args(args)
+                    .envs(env)
+                    .current_dir(self.dir.as_ref())
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .with_context(|| format!("Failed to start plugin '{}'", self.name))?;
+                let mut stdin = child.stdin.take().expect("stdin is piped");
+                // Written from a task while the output is read, so a plugin that does not read its input, or
+                // writes its output first, can not block the call past the timeout.
+                let writer = tokio::spawn(async move {
+                    stdin.write_all(request.as_bytes()).await?;
+                    stdin.write_all(b"\n").await
+                });
+                let output = tokio::time::timeout(*timeout, child.wait_with_output()).await;
+                writer.abort();
+                let output = output.map_err(|_| {
+                    anyhow!("Plugin '{}' timed out after {:?}", self.name, timeout)
+                })??;
+                if !output.status.success() {
+                    bail!(
+                        "Plugin '{}' failed with {}: {}",
+                        self.name,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            #[cfg(feature = "wasm-plugins")]
+            Runtime::Wasm(module) => {
+                let module = module.clone();
+                tokio::task::spawn_blocking(move || module.call(&request)).await?
+            }
+        }
+    }
+}
+
+/// The output of the tool from the response of the plugin.
+fn parse_response(response: &str) -> Result<String, AgentError> {
+    match serde_json::from_str::<Value>(response.trim()) {
+        Ok(Value::Object(response)) if response.contains_key("error") => {
+            Err(AgentError::Execution(match &response["error"] {
+                Value::String(error) => error.clone(),
+                error => error.to_string(),
+            }))
+        }
+        Ok(Value::Object(response)) if response.contains_key("output") => {
+            Ok(match &response["output"] {
+                Value::String(output) => output.clone(),
+                output => output.to_string(),
+            })
+        }
+        _ => Ok(response.trim().to_string()),
+    }
+}
+
+impl AnyTool for PluginTool {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        ToolInfo {
+            tool_type: ToolType::Function,
+            function: ToolFunctionInfo {
+                name: self.name.to_string(),
+                description: self.description.to_string(),
+                parameters: self.parameters.clone(),
+                strict: None,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncTool for PluginTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        let request = json!({ "tool": self.name, "arguments": json_args }).to_string();
+        let response = self
+            .call(request)
+            .await
+            .map_err(|e| AgentError::Execution(e.to_string()))?;
+        parse_response(&response)
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(self.clone())
+    }
+}
+
+/// Load the plugins of the subdirectories of `dir` that have a `plugin.yaml`, sorted by directory name. A missing
+/// directory has no plugins.
+pub fn load_plugins(dir: impl AsRef<Path>) -> Result<Vec<Box<dyn AsyncTool>>> {
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut plugin_dirs = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read plugins directory: {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(MANIFEST_FILE).is_file())
+        .collect::<Vec<_>>();
+    plugin_dirs.sort();
+    let mut tools: Vec<Box<dyn AsyncTool>> = Vec::new();
+    for plugin_dir in plugin_dirs {
+        let tool = PluginTool::load(&plugin_dir)
+            .with_context(|| format!("Failed to load plugin {:?}", plugin_dir))?;
+        if tools.iter().any(|t| t.name() == tool.name()) {
+            bail!("Two plugins are named '{}'", tool.name());
+        }
+        tools.push(Box::new(tool));
+    }
+    Ok(tools)
+}
+
+#[cfg(feature = "wasm-plugins")]
+mod wasm {
+    use std::path::Path;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use wasmtime::{Config, Engine, Instance, Module, Store};
+
+    /// A compiled WASM plugin. Each call runs in a new instance, so calls do not share state.
+    pub struct WasmModule {
+        engine: Engine,
+        module: Module,
+        max_fuel: u64,
+    }
+
+    impl WasmModule {
+        pub fn load(path: &Path, max_fuel: u64) -> Result<Self> {
+            let mut config = Config::new();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config)?;
+            let module = Module::from_file(&engine, path)
+                .with_context(|| format!("Failed to load WASM module: {:?}", path))?;
+            Ok(Self {
+                engine,
+                module,
+                max_fuel,
+            })
+        }
+
+        pub fn call(&self, request: &str) -> Result<String> {
+            let mut store = Store::new(&self.engine, ());
+            store.set_fuel(self.max_fuel)?;
+            let instance = Instance::new(&mut store, &self.module, &[])?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| anyhow!("The WASM plugin does not export its memory"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let call = instance.get_typed_func::<(i32, i32), i64>(&mut store, "call")?;
+
+            let len = i32::try_from(request.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as u32 as usize, request.as_bytes())?;
+            let result = call.call(&mut store, (ptr, len))? as u64;
+            let (ptr, len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+            if ptr.checked_add(len).is_none_or(|end| end > memory.data_size(&store)) {
+                bail!("The WASM plugin returned a response outside of its memory");
+            }
+            let mut response = vec![0; len];
+            memory.read(&store, ptr, &mut response)?;
+            String::from_utf8(response).context("The WASM plugin returned invalid UTF-8")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(yaml: &str) -> PluginManifest {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(r#"{"output": "42 words"}"#).unwrap(),
+            "42 words"
+        );
+        assert_eq!(
+            parse_response(r#"{"output": {"words": 42}}"#).unwrap(),
+            r#"{"words":42}"#
+        );
+        assert!(matches!(
+            parse_response(r#"{"error": "No text"}"#),
+            Err(AgentError::Execution(e)) if e == "No text"
+        ));
+        assert_eq!(parse_response("42 words\n").unwrap(), "42 words");
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let both = manifest("name: echo\ndescription: Echo\ncommand: cat\nwasm: echo.wasm");
+        assert!(PluginTool::from_manifest(both, ".").is_err());
+        let none = manifest("name: echo\ndescription: Echo");
+        assert!(PluginTool::from_manifest(none, ".").is_err());
+        let name = manifest("name: echo tool\ndescription: Echo\ncommand: cat");
+        assert!(PluginTool::from_manifest(name, ".").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_plugin() {
+        let dir = std::env::temp_dir().join(format!("lumo-plugins-{}", nanoid::nanoid!()));
+        std::fs::create_dir_all(dir.join("echo")).unwrap();
+        std::fs::write(
+            dir.join("echo").join(MANIFEST_FILE),
+            r#"
+name: echo
+description: Send the request back.
+parameters:
+  type: object
+  properties:
+    text:
+      type: string
+  required: [text]
+command: sh
+args: ["-c", "cat"]
+"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("fail")).unwrap();
+        std::fs::write(
+            dir.join("fail").join(MANIFEST_FILE),
+            "name: fail\ndescription: Fail.\ncommand: sh\nargs: [\"-c\", \"echo broken >&2; exit 3\"]\n",
+        )
+        .unwrap();
+
+        let tools = load_plugins(&dir).unwrap();
+        assert_eq!(
+            tools.iter().map(|tool| tool.name()).collect::<Vec<_>>(),
+            ["echo", "fail"]
+        );
+        assert_eq!(
+            tools[0].tool_info().function.parameters["required"],
+            json!(["text"])
+        );
+        let output = tools[0]
+            .forward_json(json!({"text": "hello"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!({"tool": "echo", "arguments": {"text": "hello"}})
+        );
+        let error = tools[1].forward_json(json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("broken"));
+
+        assert!(load_plugins(dir.join("missing")).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_plugin_ignoring_input() {
+        // The request is larger than the pipe buffer and never read, the call still stops at the timeout.
+        let stuck = "name: stuck\ndescription: Stuck.\ncommand: sleep\nargs: [\"10\"]\ntimeout_secs: 1\n";
+        let tool = PluginTool::from_manifest(manifest(stuck), std::env::temp_dir()).unwrap();
+        let start = std::time::Instant::now();
+        let error = tool
+            .forward_json(json!({"text": "a".repeat(1024 * 1024)}))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(feature = "wasm-plugins")]
+    #[tokio::test]
+    async fn test_wasm_plugin() {
+        // Answers every request with the fixed response at offset 0, and takes requests at offset 1024.
+        let response = r#"{"output": "pong"}"#;
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{}")
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "call") (param i32 i32) (result i64) i64.const {}))"#,
+            response.replace('"', "\\22"),
+            response.len()
+        );
+        let dir = std::env::temp_dir().join(format!("lumo-plugins-{}", nanoid::nanoid!()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ping.wat"), wat).unwrap();
+        let tool = PluginTool::from_manifest(
+            manifest("name: ping\ndescription: Ping.\nwasm: ping.wat"),
+            &dir,
+        )
+        .unwrap();
+        assert_eq!(tool.forward_json(json!({})).await.unwrap(), "pong");
+
+        // A response past the end of the memory is refused before it is read.
+        std::fs::write(
+            dir.join("ping.wat"),
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "call") (param i32 i32) (result i64) i64.const 0x0000fff0_7fffffff))"#,
+        )
+        .unwrap();
+        let tool = PluginTool::from_manifest(
+            manifest("name: ping\ndescription: Ping.\nwasm: ping.wat"),
+            &dir,
+        )
+        .unwrap();
+        let error = tool.forward_json(json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("outside of its memory"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
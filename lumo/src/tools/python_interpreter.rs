@@ -2,7 +2,6 @@
 use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::Deserialize;
-use std::mem::ManuallyDrop;
 use std::sync::{Arc, RwLock};
 
 use super::base::BaseTool;
@@ -24,7 +23,7 @@ pub struct PythonInterpreterToolParams {
 #[derive(Clone, Default)]
 pub struct PythonInterpreterTool {
     pub tool: BaseTool,
-    pub interpreter: Arc<RwLock<ManuallyDrop<LocalPythonInterpreter>>>,
+    pub interpreter: Arc<RwLock<LocalPythonInterpreter>>,
 }
 
 impl PythonInterpreterTool {
@@ -34,7 +33,7 @@ impl PythonInterpreterTool {
                 name: "python_interpreter",
                 description:  "This is a tool that evaluates python code. It can be used to perform calculations. Make sure to print the result using print()."
             },
-            interpreter: Arc::new(RwLock::new(ManuallyDrop::new(LocalPythonInterpreter::new(None, None)))),
+            interpreter: Arc::new(RwLock::new(LocalPythonInterpreter::new(None, None))),
         }
     }
 }
@@ -49,13 +48,25 @@ impl Tool for PythonInterpreterTool {
         self.tool.description
     }
     async fn forward(&self, arguments: PythonInterpreterToolParams) -> Result<String> {
-        let result = self.interpreter.write().unwrap().forward(&arguments.code);
+        let mut interpreter = self.interpreter.write().unwrap();
+        let result = interpreter.forward(&arguments.code);
+        let artifacts = interpreter
+            .take_artifacts()
+            .iter()
+            .map(|artifact| artifact.describe())
+            .collect::<Vec<_>>();
         match result {
             Ok(result) => {
-                if result.1.is_empty() {
+                if result.1.is_empty() && artifacts.is_empty() {
                     Ok("No Results. Make sure to print the result using print().".to_string())
-                } else {
+                } else if artifacts.is_empty() {
                     Ok(format!("Evaluation Result: {}", result.1))
+                } else {
+                    Ok(format!(
+                        "Evaluation Result: {}\nArtifacts:\n{}",
+                        result.1,
+                        artifacts.join("\n")
+                    ))
                 }
             }
             Err(e) => Err(anyhow::anyhow!("Error evaluating code: {}", e)),
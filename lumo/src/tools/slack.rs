@@ -0,0 +1,360 @@
+//! This module contains the Slack tool. The model uses this tool to post messages to a Slack channel, e.g. to deliver
+//! the results of a long task to a team, and optionally to read the recent messages of a channel.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{base::BaseTool, tool_traits::Tool};
+
+const DEFAULT_BASE_URL: &str = "https://slack.com/api";
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 100;
+/// Pages of `conversations.list` read to find a channel by name.
+const MAX_CHANNEL_PAGES: usize = 10;
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[schemars(title = "SlackAction")]
+pub enum SlackAction {
+    /// Post a message to the channel.
+    Post,
+    /// Read the recent messages of the channel.
+    Read,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "SlackToolParams")]
+pub struct SlackToolParams {
+    #[schemars(
+        description = "`post` to send a message to the channel, `read` to get its recent messages"
+    )]
+    action: SlackAction,
+    #[schemars(description = "The channel name, e.g. #reports, or its id")]
+    channel: String,
+    #[schemars(description = "The message to post, in Slack markdown (mrkdwn). Required to post")]
+    text: Option<String>,
+    #[schemars(description = "The number of recent messages to read (default: 20)")]
+    limit: Option<usize>,
+}
+
+/// Posts to and reads Slack channels with a bot token. The bot must be a member of the channels.
+#[derive(Debug, Clone)]
+pub struct SlackTool {
+    pub tool: BaseTool,
+    token: String,
+    base_url: String,
+    /// The channels the tool may use, by name or id. Every channel of the bot when empty.
+    allowed_channels: Vec<String>,
+    read_history: bool,
+}
+
+impl SlackTool {
+    pub fn new(token: &str) -> Self {
+        SlackTool {
+            tool: BaseTool {
+                name: "slack",
+                description:
+                    "Posts a message to a Slack channel, or reads the recent messages of a channel.",
+            },
+            token: token.to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            allowed_channels: Vec::new(),
+            read_history: false,
+        }
+    }
+
+    /// Configure the tool from `SLACK_BOT_TOKEN`, and `SLACK_ALLOWED_CHANNELS`, a comma-separated list of channels.
+    pub fn from_env() -> Result<Self> {
+        let token =
+            std::env::var("SLACK_BOT_TOKEN").map_err(|_| anyhow!("SLACK_BOT_TOKEN is not set"))?;
+        let allowed_channels = std::env::var("SLACK_ALLOWED_CHANNELS")
+            .map(|channels| channels.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        Ok(Self::new(&token).with_allowed_channels(allowed_channels))
+    }
+
+    /// Only let the model use these channels, by name (with or without `#`) or id.
+    pub fn with_allowed_channels(mut self, channels: Vec<String>) -> Self {
+        self.allowed_channels = channels
+            .iter()
+            .map(|channel| normalize_channel(channel))
+            .filter(|channel| !channel.is_empty())
+            .collect();
+        self
+    }
+
+    /// Let the model read the recent messages of the channels. Needs the `channels:history` and `channels:read`
+    /// scopes, and `groups:*` for private channels.
+    pub fn with_read_history(mut self, read_history: bool) -> Self {
+        self.read_history = read_history;
+        self
+    }
+
+    /// The Slack Web API, e.g. for a proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn check_channel(&self, channel: &str) -> Result<()> {
+        let channel = normalize_channel(channel);
+        if channel.is_empty() {
+            return Err(anyhow!("The channel is empty"));
+        }
+        if !self.allowed_channels.is_empty() && !self.allowed_channels.contains(&channel) {
+            return Err(anyhow!(
+                "The channel {} is not allowed. Allowed channels: {}",
+                channel,
+                self.allowed_channels.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    async fn call(&self, method: &str, request: reqwest::RequestBuilder) -> Result<Value> {
+        let response = request
+            .bearer_auth(&self.token)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to call Slack {}: {}", method, e))?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Slack {} failed: HTTP {}",
+                method,
+                response.status()
+            ));
+        }
+        check_response(method, response.json().await?)
+    }
+
+    async fn post(&self, channel: &str, text: &str) -> Result<String> {
+        let method = "chat.postMessage";
+        let response = self
+            .call(
+                method,
+                reqwest::Client::new()
+                    .post(format!("{}/{}", self.base_url, method))
+                    .json(&json!({ "channel": channel, "text": text })),
+            )
+            .await?;
+        Ok(format!(
+            "Posted the message to {} (ts {}).",
+            channel,
+            response["ts"].as_str().unwrap_or_default()
+        ))
+    }
+
+    /// The id of a channel given by name. Ids are returned as they are.
+    async fn channel_id(&self, channel: &str) -> Result<String> {
+        let name = normalize_channel(channel);
+        if is_channel_id(&name) {
+            return Ok(name.to_uppercase());
+        }
+        let method = "conversations.list";
+        let mut cursor = String::new();
+        for _ in 0..MAX_CHANNEL_PAGES {
+            let response = self
+                .call(
+                    method,
+                    reqwest::Client::new()
+                        .get(format!("{}/{}", self.base_url, method))
+                        .query(&[
+                            ("types", "public_channel,private_channel"),
+                            ("exclude_archived", "true"),
+                            ("limit", "1000"),
+                            ("cursor", cursor.as_str()),
+                        ]),
+                )
+                .await?;
+            let found = response["channels"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|c| c["name"].as_str() == Some(name.as_str()))
+                .and_then(|c| c["id"].as_str());
+            if let Some(id) = found {
+                return Ok(id.to_string());
+            }
+            cursor = response["response_metadata"]["next_cursor"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            if cursor.is_empty() {
+                break;
+            }
+        }
+        Err(anyhow!("The channel {} was not found", channel))
+    }
+
+    async fn read(&self, channel: &str, limit: usize) -> Result<String> {
+        let id = self.channel_id(channel).await?;
+        let method = "conversations.history";
+        let response = self
+            .call(
+                method,
+                reqwest::Client::new()
+                    .get(format!("{}/{}", self.base_url, method))
+                    .query(&[("channel", id), ("limit", limit.to_string())]),
+            )
+            .await?;
+        Ok(format_history(&response))
+    }
+
+    pub async fn forward(&self, arguments: SlackToolParams) -> Result<String> {
+        self.check_channel(&arguments.channel)?;
+        match arguments.action {
+            SlackAction::Post => {
+                let text = arguments
+                    .text
+                    .filter(|text| !text.trim().is_empty())
+                    .ok_or_else(|| anyhow!("The text of the message is required to post"))?;
+                self.post(&arguments.channel, &text).await
+            }
+            SlackAction::Read if !self.read_history => {
+                Err(anyhow!("Reading channels is not enabled for this tool"))
+            }
+            SlackAction::Read => {
+                let limit = arguments
+                    .limit
+                    .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                    .clamp(1, MAX_HISTORY_LIMIT);
+                self.read(&arguments.channel, limit).await
+            }
+        }
+    }
+}
+
+/// The channel name without `#` and in lowercase, as Slack names are, or the id.
+fn normalize_channel(channel: &str) -> String {
+    channel.trim().trim_start_matches('#').to_lowercase()
+}
+
+/// Public, private and direct message channel ids, e.g. `C0123ABCD`.
+fn is_channel_id(channel: &str) -> bool {
+    channel.len() >= 9
+        && channel.starts_with(['c', 'g', 'd'])
+        && channel.chars().all(|c| c.is_ascii_alphanumeric())
+        && channel.chars().any(|c| c.is_ascii_digit())
+}
+
+/// The response of the Web API, or its error. Slack answers errors with HTTP 200 and `"ok": false`.
+fn check_response(method: &str, response: Value) -> Result<Value> {
+    if response["ok"].as_bool() == Some(true) {
+        return Ok(response);
+    }
+    Err(anyhow!(
+        "Slack {} failed: {}",
+        method,
+        response["error"].as_str().unwrap_or("unknown error")
+    ))
+}
+
+/// The messages of `conversations.history`, oldest first.
+fn format_history(response: &Value) -> String {
+    let messages = response["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .rev()
+        .map(|message| {
+            let author = message["user"]
+                .as_str()
+                .or(message["username"].as_str())
+                .or(message["bot_id"].as_str())
+                .unwrap_or("unknown");
+            let time = message["ts"]
+                .as_str()
+                .and_then(|ts| ts.split('.').next()?.parse::<i64>().ok())
+                .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+                .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default();
+            format!(
+                "[{}] {}: {}",
+                time,
+                author,
+                message["text"].as_str().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>();
+    if messages.is_empty() {
+        "The channel has no messages.".to_string()
+    } else {
+        messages.join("\n")
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for SlackTool {
+    type Params = SlackToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: SlackToolParams) -> Result<String> {
+        self.forward(arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_allowed_channels() {
+        let tool = SlackTool::new("xoxb-test")
+            .with_allowed_channels(vec!["#Reports".to_string(), "C0123ABCD".to_string()]);
+        assert!(tool.check_channel("reports").is_ok());
+        assert!(tool.check_channel("#reports").is_ok());
+        assert!(tool.check_channel("c0123abcd").is_ok());
+        let error = tool.check_channel("#general").unwrap_err().to_string();
+        assert!(error.contains("Allowed channels: reports, c0123abcd"));
+
+        // Checked before any call to Slack.
+        let read = SlackToolParams {
+            action: SlackAction::Read,
+            channel: "#reports".to_string(),
+            text: None,
+            limit: None,
+        };
+        let error = tool.forward(read).await.unwrap_err().to_string();
+        assert!(error.contains("not enabled"));
+        let post = SlackToolParams {
+            action: SlackAction::Post,
+            channel: "#reports".to_string(),
+            text: Some(" ".to_string()),
+            limit: None,
+        };
+        assert!(tool.forward(post).await.is_err());
+    }
+
+    #[test]
+    fn test_responses() {
+        assert!(is_channel_id("c0123abcd"));
+        assert!(!is_channel_id("general"));
+
+        let error = check_response(
+            "chat.postMessage",
+            json!({"ok": false, "error": "not_in_channel"}),
+        );
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Slack chat.postMessage failed: not_in_channel"
+        );
+
+        let history = json!({"ok": true, "messages": [
+            {"user": "U2", "text": "Done, see the report", "ts": "1700000060.000200"},
+            {"user": "U1", "text": "Is the report ready?", "ts": "1700000000.000100"},
+        ]});
+        assert_eq!(
+            format_history(&history),
+            "[2023-11-14 22:13 UTC] U1: Is the report ready?\n[2023-11-14 22:14 UTC] U2: Done, see the report"
+        );
+    }
+}
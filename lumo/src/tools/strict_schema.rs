@@ -0,0 +1,246 @@
+//! This module turns the JSON schema of tool parameters into a strict-mode schema for OpenAI structured outputs.
+//!
+//! In strict mode, the model's arguments always match the schema, but the schema must list every property as
+//! required and forbid additional properties. Optional properties become nullable instead, and the model sends
+//! `null` for the ones it leaves out.
+
+use serde_json::{Map, Value};
+
+/// Keywords strict mode does not support. They are removed; the arguments are still validated against the
+/// original schema before the tool runs.
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "$schema",
+    "default",
+    "format",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "examples",
+];
+
+/// The strict-mode version of a parameters schema, or `None` when it can not be made strict, e.g. when it has
+/// free-form values or maps.
+pub fn strict_schema(schema: &Value) -> Option<Value> {
+    let mut schema = schema.clone();
+    make_strict(&mut schema).then_some(schema)
+}
+
+fn make_strict(schema: &mut Value) -> bool {
+    let Some(schema) = schema.as_object_mut() else {
+        // `true` accepts any value.
+        return false;
+    };
+    if schema.contains_key("$ref") || schema.contains_key("allOf") {
+        return false;
+    }
+    for keyword in UNSUPPORTED_KEYWORDS {
+        schema.remove(*keyword);
+    }
+    if let Some(variants) = schema.remove("oneOf") {
+        schema.insert("anyOf".to_string(), variants);
+    }
+    if let Some(variants) = schema.get_mut("anyOf").and_then(Value::as_array_mut) {
+        if !variants.iter_mut().all(make_strict) {
+            return false;
+        }
+    }
+    if let Some(items) = schema.get_mut("items") {
+        if !make_strict(items) {
+            return false;
+        }
+    }
+
+    if has_type(schema, "object") {
+        if schema
+            .get("additionalProperties")
+            .is_some_and(|additional| additional != &Value::Bool(false))
+        {
+            return false;
+        }
+        let required = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let properties = schema
+            .entry("properties")
+            .or_insert_with(|| Value::Object(Map::new()));
+        let Some(properties) = properties.as_object_mut() else {
+            return false;
+        };
+        for (name, property) in properties.iter_mut() {
+            if !make_strict(property) {
+                return false;
+            }
+            if !required.contains(&Value::String(name.clone())) {
+                make_nullable(property);
+            }
+        }
+        let names = properties.keys().cloned().map(Value::String).collect();
+        schema.insert("required".to_string(), Value::Array(names));
+        schema.insert("additionalProperties".to_string(), Value::Bool(false));
+    } else if !schema.contains_key("type")
+        && !schema.contains_key("anyOf")
+        && !schema.contains_key("enum")
+    {
+        // No type at all accepts any value.
+        return false;
+    }
+    true
+}
+
+fn has_type(schema: &Map<String, Value>, name: &str) -> bool {
+    match schema.get("type") {
+        Some(Value::String(schema_type)) => schema_type == name,
+        Some(Value::Array(types)) => types.iter().any(|schema_type| schema_type == name),
+        _ => false,
+    }
+}
+
+/// Let the property be `null`, the value strict mode sends for a property the model leaves out.
+fn make_nullable(property: &mut Value) {
+    let Some(schema) = property.as_object_mut() else {
+        return;
+    };
+    if has_type(schema, "null") {
+        return;
+    }
+    match schema.get_mut("type") {
+        Some(Value::String(schema_type)) => {
+            let types = vec![Value::String(schema_type.clone()), Value::from("null")];
+            schema.insert("type".to_string(), Value::Array(types));
+        }
+        Some(Value::Array(types)) => types.push(Value::from("null")),
+        _ => {
+            if let Some(variants) = schema.get_mut("anyOf").and_then(Value::as_array_mut) {
+                if !variants.iter().any(|variant| variant["type"] == "null") {
+                    variants.push(serde_json::json!({ "type": "null" }));
+                }
+                return;
+            }
+        }
+    }
+    if let Some(values) = schema.get_mut("enum").and_then(Value::as_array_mut) {
+        if !values.contains(&Value::Null) {
+            values.push(Value::Null);
+        }
+    }
+}
+
+/// Remove the `null` arguments of the properties that are not required, so that a tool called in strict mode
+/// gets the defaults of the properties the model left out.
+pub fn remove_omitted_arguments(schema: &Value, arguments: &Value) -> Value {
+    let (Some(properties), Value::Object(arguments)) = (schema.get("properties"), arguments) else {
+        return arguments.clone();
+    };
+    let required = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    Value::Object(
+        arguments
+            .iter()
+            .filter(|(name, value)| {
+                !value.is_null()
+                    || properties.get(name.as_str()).is_none()
+                    || required.contains(&Value::String(name.to_string()))
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_strict_schema() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "SearchParams",
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" },
+                "max_results": { "type": "integer", "format": "uint", "minimum": 0, "default": 5 },
+                "year": { "type": ["string", "null"] },
+                "order": { "type": "string", "enum": ["date", "relevance"] },
+                "filters": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "site": { "type": "string" } },
+                        "required": ["site"],
+                    },
+                },
+            },
+            "required": ["query"],
+        });
+        let strict = strict_schema(&schema).unwrap();
+        let mut required = strict["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|name| name.as_str().unwrap())
+            .collect::<Vec<_>>();
+        required.sort();
+        assert_eq!(
+            required,
+            ["filters", "max_results", "order", "query", "year"]
+        );
+        assert_eq!(strict["additionalProperties"], false);
+        assert!(strict.get("$schema").is_none());
+        assert_eq!(strict["properties"]["query"]["type"], "string");
+        assert_eq!(
+            strict["properties"]["max_results"],
+            json!({ "type": ["integer", "null"] })
+        );
+        assert_eq!(
+            strict["properties"]["year"]["type"],
+            json!(["string", "null"])
+        );
+        assert_eq!(
+            strict["properties"]["order"]["enum"],
+            json!(["date", "relevance", null])
+        );
+        let item = &strict["properties"]["filters"]["items"];
+        assert_eq!(item["additionalProperties"], false);
+        assert_eq!(item["properties"]["site"]["type"], "string");
+
+        // Maps and free-form values can not be strict.
+        let map = json!({
+            "type": "object",
+            "properties": { "headers": { "type": "object", "additionalProperties": { "type": "string" } } },
+        });
+        assert!(strict_schema(&map).is_none());
+        let any = json!({ "type": "object", "properties": { "value": true } });
+        assert!(strict_schema(&any).is_none());
+    }
+
+    #[test]
+    fn test_remove_omitted_arguments() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": ["string", "null"] },
+                "max_results": { "type": "integer" },
+            },
+            "required": ["query"],
+        });
+        let arguments = json!({ "query": null, "max_results": null, "extra": null });
+        assert_eq!(
+            remove_omitted_arguments(&schema, &arguments),
+            json!({ "query": null, "extra": null })
+        );
+    }
+}
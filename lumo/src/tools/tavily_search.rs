@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use super::base::BaseTool;
 use super::tool_traits::Tool;
+use crate::secrets::SecretKey;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[schemars(title = "SearchDepth")]
@@ -75,11 +76,13 @@ pub struct TavilySearchToolParams {
 pub struct TavilySearchTool {
     pub tool: BaseTool,
     pub api_key: String,
+    #[serde(skip)]
+    pub secret: Option<SecretKey>,
 }
 
 impl TavilySearchTool {
     pub fn new(api_key: Option<String>) -> Self {
-        let api_key = api_key.unwrap_or(std::env::var("TAVILY_API_KEY").unwrap());
+        let api_key = api_key.unwrap_or_else(|| std::env::var("TAVILY_API_KEY").unwrap());
         let tool = BaseTool {
             name: "tavily_search",
             description: "Performs a Tavily web search for your query then returns a string of the top search results with LLMs.",
@@ -87,6 +90,22 @@ impl TavilySearchTool {
         Self {
             tool,
             api_key,
+            secret: None,
+        }
+    }
+
+    /// Read the API key from a secret provider on each search, so that a rotated key is used at once.
+    pub fn from_secret(secret: SecretKey) -> Self {
+        Self {
+            secret: Some(secret),
+            ..Self::new(Some(String::new()))
+        }
+    }
+
+    async fn api_key(&self) -> Result<String> {
+        match &self.secret {
+            Some(secret) => secret.resolve().await,
+            None => Ok(self.api_key.clone()),
         }
     }
 
@@ -95,7 +114,7 @@ impl TavilySearchTool {
         let response = client
             .post("https://api.tavily.com")
             .json(&arguments)
-            .header("Bearer", &self.api_key)
+            .header("Bearer", self.api_key().await?)
             .header("Content-Type", "application/json")
             .send()
             .await;
@@ -118,7 +137,8 @@ impl TavilySearchTool {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Tool for TavilySearchTool {
     type Params = TavilySearchToolParams;
     fn name(&self) -> &'static str {
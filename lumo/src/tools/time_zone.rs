@@ -0,0 +1,377 @@
+//! Time zones for the date and time tool and the `{{current_time}}` of the system prompts.
+//!
+//! A zone is an IANA name such as `Asia/Tokyo`, `UTC`, or a fixed offset such as `UTC+05:30`. The rules of the IANA
+//! zones, with their past changes, come from the tz database of `chrono-tz`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// The rules of a zone: those of an IANA zone, or a fixed offset with its abbreviation.
+#[derive(Debug, Clone, PartialEq)]
+enum Rules {
+    Iana(Tz),
+    Fixed(String, FixedOffset),
+}
+
+/// Parse an offset such as `+05:30`, `-3` or `+09:00:00` in seconds.
+fn parse_hms(text: &str) -> Result<i64> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let mut seconds = 0;
+    let mut parts = 0;
+    for (i, part) in digits.split(':').enumerate() {
+        let value: i64 = part
+            .parse()
+            .map_err(|_| anyhow!("Invalid time zone offset '{}'", text))?;
+        if i > 2 || (i > 0 && value >= 60) {
+            bail!("Invalid time zone offset '{}'", text);
+        }
+        seconds += value * [3600, 60, 1][i];
+        parts += 1;
+    }
+    if parts == 0 || seconds > 168 * 3600 {
+        bail!("Invalid time zone offset '{}'", text);
+    }
+    Ok(sign * seconds)
+}
+
+/// Format an offset in seconds as `+09:00`.
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}:{:02}", sign, offset / 3600, offset % 3600 / 60)
+}
+
+/// A time zone, by IANA name or fixed offset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Zone {
+    name: String,
+    rules: Rules,
+}
+
+impl Zone {
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_string(),
+            rules: Rules::Fixed("UTC".to_string(), Utc.fix()),
+        }
+    }
+
+    /// The time zone of the system: the `TZ` environment variable, or else the zone configured in the system, or else
+    /// its current offset.
+    pub fn local() -> Self {
+        let name = std::env::var("TZ")
+            .ok()
+            .map(|tz| tz.trim_start_matches(':').to_string())
+            .or_else(|| iana_time_zone::get_timezone().ok());
+        if let Some(zone) = name.and_then(|name| name.parse().ok()) {
+            return zone;
+        }
+        let offset = chrono::Local::now().offset().fix();
+        Self {
+            name: "local".to_string(),
+            rules: Rules::Fixed(
+                format!("UTC{}", format_offset(offset.local_minus_utc())),
+                offset,
+            ),
+        }
+    }
+
+    /// The name of the zone, e.g. `Asia/Tokyo` or `UTC+05:30`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The abbreviation of the zone at an instant, e.g. `JST`.
+    pub fn abbreviation(&self, instant: &DateTime<Utc>) -> String {
+        match &self.rules {
+            Rules::Iana(tz) => tz.offset_from_utc_datetime(&instant.naive_utc()).to_string(),
+            Rules::Fixed(abbreviation, _) => abbreviation.clone(),
+        }
+    }
+
+    /// The offset of the zone at an instant.
+    pub fn offset(&self, instant: &DateTime<Utc>) -> FixedOffset {
+        match &self.rules {
+            Rules::Iana(tz) => tz.offset_from_utc_datetime(&instant.naive_utc()).fix(),
+            Rules::Fixed(_, offset) => *offset,
+        }
+    }
+
+    /// The local time of an instant.
+    pub fn from_utc(&self, instant: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        instant.with_timezone(&self.offset(instant))
+    }
+
+    /// The instant of a local time. A time repeated when the clocks go back is the first one, and a time skipped when
+    /// they go forward is read with the offset before the change.
+    pub fn from_local(&self, local: &NaiveDateTime) -> DateTime<FixedOffset> {
+        let tz = match &self.rules {
+            Rules::Iana(tz) => tz,
+            Rules::Fixed(_, offset) => return offset.from_utc_datetime(&(*local - *offset)),
+        };
+        match tz.from_local_datetime(local) {
+            LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.fixed_offset(),
+            LocalResult::None => {
+                // Skipped: read with the offset of the day before, and shown with the one after the change.
+                let before = self.offset(&Utc.from_utc_datetime(&(*local - Duration::days(1))));
+                self.from_utc(&Utc.from_utc_datetime(&(*local - before)))
+            }
+        }
+    }
+
+    /// The current time in the zone.
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        self.from_utc(&Utc::now())
+    }
+
+    /// The time as written in the system prompts, e.g. `Friday 2026-10-16 21:04:05 JST (UTC+09:00, Asia/Tokyo)`.
+    pub fn describe(&self, time: &DateTime<FixedOffset>) -> String {
+        let instant = time.with_timezone(&Utc);
+        let local = self.from_utc(&instant);
+        let abbreviation = self.abbreviation(&instant);
+        let offset = format!("UTC{}", format_offset(local.offset().local_minus_utc()));
+        let details = [offset.as_str(), self.name.as_str()]
+            .into_iter()
+            .filter(|detail| *detail != abbreviation)
+            .fold(Vec::new(), |mut details, detail| {
+                if !details.contains(&detail) {
+                    details.push(detail);
+                }
+                details
+            });
+        let mut text = format!(
+            "{} {} {}",
+            weekday_name(local.weekday()),
+            local.format("%Y-%m-%d %H:%M:%S"),
+            abbreviation
+        );
+        if !details.is_empty() {
+            text.push_str(&format!(" ({})", details.join(", ")));
+        }
+        text
+    }
+}
+
+/// The English name of a weekday.
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+/// The `{{current_time}}` of the system prompts: the current time in the zone, or in the time zone of the system
+/// when there is none.
+pub fn current_time(zone: Option<&Zone>) -> String {
+    match zone {
+        Some(zone) => zone.describe(&zone.now()),
+        None => chrono::Local::now().to_string(),
+    }
+}
+
+impl FromStr for Zone {
+    type Err = anyhow::Error;
+
+    /// Parse an IANA name such as `Europe/Paris`, `UTC` or `Z`, or an offset such as `UTC+9`, `GMT-03:30` or
+    /// `+05:30`.
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim();
+        if ["utc", "gmt", "z", "etc/utc", "etc/gmt"].contains(&name.to_ascii_lowercase().as_str()) {
+            return Ok(Self::utc());
+        }
+        let offset = ["UTC", "GMT", "utc", "gmt", ""]
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .filter(|offset| offset.starts_with(['+', '-']));
+        if let Some(offset) = offset {
+            let offset = if offset.len() == 5 && !offset.contains(':') {
+                // +0530
+                format!("{}:{}", &offset[..3], &offset[3..])
+            } else {
+                offset.to_string()
+            };
+            let seconds = parse_hms(&offset)?;
+            if seconds.abs() > 14 * 3600 {
+                bail!("The offset of '{}' is beyond 14 hours", s);
+            }
+            let name = format!("UTC{}", format_offset(seconds as i32));
+            let offset = FixedOffset::east_opt(seconds as i32).unwrap_or(Utc.fix());
+            return Ok(Self {
+                rules: Rules::Fixed(name.clone(), offset),
+                name,
+            });
+        }
+        let tz = Tz::from_str_insensitive(name).map_err(|_| {
+            anyhow!(
+                "Unknown time zone '{}'. Use an IANA name such as Europe/Paris, or an offset such as UTC+05:30",
+                s
+            )
+        })?;
+        Ok(Self {
+            name: tz.name().to_string(),
+            rules: Rules::Iana(tz),
+        })
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Zone> for String {
+    fn from(zone: Zone) -> Self {
+        zone.name
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn zone(name: &str) -> Zone {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_rules() {
+        let new_york = zone("America/New_York");
+        // Daylight saving time from the second Sunday of March to the first Sunday of November in 2026.
+        assert_eq!(
+            new_york
+                .offset(&utc("2026-03-08T06:59:59Z"))
+                .local_minus_utc(),
+            -5 * 3600
+        );
+        assert_eq!(
+            new_york
+                .offset(&utc("2026-03-08T07:00:00Z"))
+                .local_minus_utc(),
+            -4 * 3600
+        );
+        assert_eq!(new_york.abbreviation(&utc("2026-07-01T12:00:00Z")), "EDT");
+        assert_eq!(
+            new_york
+                .offset(&utc("2026-11-01T05:59:59Z"))
+                .local_minus_utc(),
+            -4 * 3600
+        );
+        assert_eq!(
+            new_york
+                .offset(&utc("2026-11-01T06:00:00Z"))
+                .local_minus_utc(),
+            -5 * 3600
+        );
+
+        // The rules of the past: daylight saving time started on the first Sunday of April until 2007.
+        assert_eq!(
+            new_york
+                .offset(&utc("2006-03-20T12:00:00Z"))
+                .local_minus_utc(),
+            -5 * 3600
+        );
+
+        // Southern hemisphere: daylight saving time across the new year.
+        let sydney = zone("Australia/Sydney");
+        assert_eq!(sydney.abbreviation(&utc("2026-01-15T00:00:00Z")), "AEDT");
+        assert_eq!(sydney.abbreviation(&utc("2026-07-15T00:00:00Z")), "AEST");
+
+        let kolkata = zone("Asia/Kolkata");
+        assert_eq!(
+            kolkata.from_utc(&utc("2026-10-16T12:00:00Z")).to_rfc3339(),
+            "2026-10-16T17:30:00+05:30"
+        );
+        assert_eq!(
+            zone("America/Santiago").abbreviation(&utc("2026-01-15T00:00:00Z")),
+            "-03"
+        );
+    }
+
+    #[test]
+    fn test_from_local() {
+        let paris = zone("Europe/Paris");
+        let local = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(
+            paris.from_local(&local("2026-07-01 12:00")).to_rfc3339(),
+            "2026-07-01T12:00:00+02:00"
+        );
+        assert_eq!(
+            paris.from_local(&local("2026-12-01 12:00")).to_rfc3339(),
+            "2026-12-01T12:00:00+01:00"
+        );
+        // 02:30 is repeated on October 25, the first one is in summer time.
+        assert_eq!(
+            paris.from_local(&local("2026-10-25 02:30")).to_rfc3339(),
+            "2026-10-25T02:30:00+02:00"
+        );
+        // 02:30 is skipped on March 29.
+        assert_eq!(
+            paris.from_local(&local("2026-03-29 02:30")).to_rfc3339(),
+            "2026-03-29T03:30:00+02:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_zone() {
+        assert_eq!("utc".parse::<Zone>().unwrap(), Zone::utc());
+        let zone: Zone = "UTC+5:30".parse().unwrap();
+        assert_eq!(zone.name(), "UTC+05:30");
+        assert_eq!(zone.offset(&Utc::now()).local_minus_utc(), 5 * 3600 + 1800);
+        assert_eq!("-0300".parse::<Zone>().unwrap().name(), "UTC-03:00");
+        assert_eq!("GMT-3".parse::<Zone>().unwrap().name(), "UTC-03:00");
+        assert!("UTC+15".parse::<Zone>().is_err());
+        assert!("Mars/Olympus_Mons".parse::<Zone>().is_err());
+        assert!("../../etc/passwd".parse::<Zone>().is_err());
+
+        let tokyo: Zone = "asia/tokyo".parse().unwrap();
+        assert_eq!(tokyo.offset(&Utc::now()).local_minus_utc(), 9 * 3600);
+        assert_eq!(
+            tokyo.describe(&utc("2026-10-16T12:04:05Z").fixed_offset()),
+            format!(
+                "Friday 2026-10-16 21:04:05 JST (UTC+09:00, {})",
+                tokyo.name()
+            )
+        );
+        assert_eq!(
+            zone.describe(&utc("2026-10-16T12:04:05Z").fixed_offset()),
+            "Friday 2026-10-16 17:34:05 UTC+05:30"
+        );
+        assert_eq!(
+            serde_json::to_value(&zone).unwrap(),
+            serde_json::json!("UTC+05:30")
+        );
+        assert!(serde_json::from_value::<Zone>(serde_json::json!("Nowhere")).is_err());
+    }
+}
@@ -9,14 +9,20 @@ use serde::Serialize;
 use serde_json::{json, Value};
 use std::fmt::Debug;
 
+use tokio::sync::broadcast;
+
 use crate::errors::{AgentError, AgentExecutionError};
-use crate::models::openai::FunctionCall;
+use crate::models::openai::{FunctionCall, Status};
+
+use super::strict_schema::{remove_omitted_arguments, strict_schema};
+use super::validation::{format_violations, validate_arguments};
 
 /// A trait for parameters that can be used in a tool. This defines the arguments that can be passed to the tool.
 pub trait Parameters: DeserializeOwned + JsonSchema {}
 
 /// A trait for tools that can be used in an agent.
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait Tool: Send + Sync {
     type Params: Parameters;
     /// The name of the tool.
@@ -27,25 +33,28 @@ pub trait Tool: Send + Sync {
     async fn forward(&self, arguments: Self::Params) -> Result<String>;
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub enum ToolType {
     #[serde(rename = "function")]
     Function,
 }
 
 /// A struct that contains information about a tool. This is used to serialize the tool for the API.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct ToolInfo {
     #[serde(rename = "type")]
     pub tool_type: ToolType,
     pub function: ToolFunctionInfo,
 }
 /// This struct contains information about the function to call when the tool is used.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct ToolFunctionInfo {
     pub name: String,
     pub description: String,
     pub parameters: Value,
+    /// Ask OpenAI-compatible backends to only generate arguments that match the parameters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
 impl ToolInfo {
@@ -62,10 +71,22 @@ impl ToolInfo {
                 name: tool.name().to_string(),
                 description: tool.description().to_string(),
                 parameters: serde_json::to_value(parameters).unwrap(),
+                strict: None,
             },
         }
     }
 
+    /// The tool with a strict-mode schema for OpenAI structured outputs. The tool is returned unchanged when its
+    /// parameters can not be made strict, e.g. when they have free-form values.
+    pub fn to_strict(&self) -> ToolInfo {
+        let mut tool = self.clone();
+        if let Some(parameters) = strict_schema(&self.function.parameters) {
+            tool.function.parameters = parameters;
+            tool.function.strict = Some(true);
+        }
+        tool
+    }
+
     pub fn get_parameter_names(&self) -> Vec<String> {
         if let Some(schema) = &self.function.parameters.get("properties") {
             return schema.as_object().unwrap().keys().cloned().collect();
@@ -78,9 +99,16 @@ pub fn get_json_schema(tool: &ToolInfo) -> serde_json::Value {
     json!(tool)
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait ToolGroup {
     async fn call(&self, arguments: &FunctionCall) -> Result<String, AgentExecutionError>;
+    /// Like `call`, with the channel the statuses of the tool are sent to, e.g. the tokens of a managed agent.
+    async fn call_with_status(
+        &self,
+        arguments: &FunctionCall,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentExecutionError>;
     fn tool_info(&self) -> Vec<ToolInfo>;
 }
 
@@ -90,13 +118,32 @@ pub trait AnyTool: Send + Sync {
     fn tool_info(&self) -> ToolInfo;
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait AsyncTool: AnyTool {
     async fn forward_json(&self, json_args: serde_json::Value) -> Result<String, AgentError>;
+    /// Like `forward_json`, for tools that report their progress to the stream of the calling agent. Other tools
+    /// ignore `tx`.
+    async fn forward_json_with_status(
+        &self,
+        json_args: serde_json::Value,
+        _tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
+        self.forward_json(json_args).await
+    }
+    /// Release what the tool holds outside of memory, such as the agent wrapped by an [`AgentTool`]. Called by
+    /// [`Agent::shutdown`] of the agent that owns the tool; the other tools have nothing to release.
+    ///
+    /// [`AgentTool`]: crate::tools::AgentTool
+    /// [`Agent::shutdown`]: crate::agent::Agent::shutdown
+    async fn shutdown(&self) -> Result<(), AgentError> {
+        Ok(())
+    }
     fn clone_box(&self) -> Box<dyn AsyncTool>;
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl<T: Tool + Clone + 'static> AsyncTool for T {
     async fn forward_json(&self, json_args: serde_json::Value) -> Result<String, AgentError> {
         let params = serde_json::from_value::<T::Params>(json_args.clone()).map_err(|e| {
@@ -132,13 +179,29 @@ impl<T: Tool + Clone + 'static> AnyTool for T {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ToolGroup for Vec<Box<dyn AsyncTool>> {
     async fn call(&self, arguments: &FunctionCall) -> Result<String, AgentError> {
+        self.call_with_status(arguments, None).await
+    }
+    async fn call_with_status(
+        &self,
+        arguments: &FunctionCall,
+        tx: Option<broadcast::Sender<Status>>,
+    ) -> Result<String, AgentError> {
         let tool = self.iter().find(|tool| tool.name() == arguments.name);
         if let Some(tool) = tool {
-            let p = arguments.arguments.clone();
-            return tool.forward_json(p).await;
+            let schema = tool.tool_info().function.parameters;
+            let mut p = remove_omitted_arguments(&schema, &arguments.arguments);
+            if let Err(violations) = validate_arguments(&schema, &mut p) {
+                return Err(AgentError::Parsing(format_violations(
+                    tool.name(),
+                    &violations,
+                    &schema,
+                )));
+            }
+            return tool.forward_json_with_status(p, tx).await;
         }
         Err(AgentError::Execution("Tool not found".to_string()))
     }
@@ -146,3 +209,57 @@ impl ToolGroup for Vec<Box<dyn AsyncTool>> {
         self.iter().map(|tool| tool.tool_info()).collect()
     }
 }
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate::tools::LumoTool;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct WeatherParams {
+        #[schemars(description = "The city to get the weather for")]
+        city: String,
+    }
+
+    /// Get the current weather for a city.
+    #[derive(Clone, LumoTool)]
+    #[tool(params = WeatherParams)]
+    struct WeatherTool;
+
+    impl WeatherTool {
+        async fn run(&self, params: WeatherParams) -> Result<String> {
+            Ok(format!("It is sunny in {}", params.city))
+        }
+    }
+
+    #[derive(Clone, LumoTool)]
+    #[tool(name = "add", description = "Add two numbers", params = AddParams, forward = add)]
+    struct Calculator;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct AddParams {
+        a: i64,
+        b: i64,
+    }
+
+    async fn add(_tool: &Calculator, params: AddParams) -> Result<String> {
+        Ok((params.a + params.b).to_string())
+    }
+
+    #[tokio::test]
+    async fn test_derive_lumo_tool() {
+        let tool = WeatherTool;
+        let info = AnyTool::tool_info(&tool);
+        assert_eq!(info.function.name, "weather_tool");
+        assert_eq!(info.function.description, "Get the current weather for a city.");
+        assert_eq!(info.get_parameter_names(), vec!["city"]);
+        let result = tool.forward_json(json!({"city": "Paris"})).await.unwrap();
+        assert_eq!(result, "It is sunny in Paris");
+
+        let tools: Vec<Box<dyn AsyncTool>> = vec![Box::new(Calculator)];
+        assert_eq!(tools[0].name(), "add");
+        assert_eq!(tools[0].forward_json(json!({"a": 1, "b": 2})).await.unwrap(), "3");
+    }
+}
@@ -0,0 +1,317 @@
+//! This module validates the arguments of a tool call against the tool's JSON schema.
+//!
+//! Only the subset of JSON schema produced by schemars for tool parameters is checked: `type`,
+//! `required`, `properties`, `additionalProperties: false`, `enum`, `items`, `anyOf`/`oneOf`/`allOf`, `nullable`
+//! and `$ref` to the `definitions` or `$defs` of the schema. Whole numbers written as floats, e.g. `5.0`, are
+//! accepted as integers.
+
+use serde_json::Value;
+
+/// `$ref`s followed at most without going down the arguments, so that a schema referring to itself ends.
+const MAX_REF_DEPTH: usize = 16;
+
+/// Validate `arguments` against `schema` and return the list of violations, if any. The whole numbers given for
+/// integers are rewritten as integers, so that they can be deserialized.
+pub fn validate_arguments(schema: &Value, arguments: &mut Value) -> Result<(), Vec<String>> {
+    let mut violations = Vec::new();
+    validate_value(schema, schema, arguments, "arguments", 0, &mut violations);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Format the violations of a tool call into an observation the model can act on.
+pub fn format_violations(tool_name: &str, violations: &[String], schema: &Value) -> String {
+    format!(
+        "Invalid arguments for tool '{}':\n{}\nCall the tool again with arguments matching its parameters: {}",
+        tool_name,
+        violations
+            .iter()
+            .map(|violation| format!("- {}", violation))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        schema.get("properties").unwrap_or(schema)
+    )
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// The integer a float without a fractional part stands for, e.g. `5` for `5.0`. JSON schema counts them as
+/// integers, and models often write integers this way.
+fn whole_number(value: &Value) -> Option<i64> {
+    let n = value
+        .as_f64()
+        .filter(|_| !value.is_i64() && !value.is_u64())?;
+    (n.fract() == 0.0 && n >= i64::MIN as f64 && n <= i64::MAX as f64).then_some(n as i64)
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    let actual = type_name(value);
+    expected == actual
+        || (expected == "number" && actual == "integer")
+        || (expected == "integer" && whole_number(value).is_some())
+}
+
+/// The schema a `$ref` points to, e.g. `#/definitions/Filter` or `#/$defs/Filter`, in the root schema.
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+/// Validate the value against the schema, `root` being the schema of the arguments the `$ref`s point in, and
+/// `depth` the number of `$ref`s followed for this value.
+fn validate_value(
+    root: &Value,
+    schema: &Value,
+    value: &mut Value,
+    path: &str,
+    depth: usize,
+    violations: &mut Vec<String>,
+) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if value.is_null() && schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        return;
+    }
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        if depth >= MAX_REF_DEPTH {
+            return;
+        }
+        // Unknown references, e.g. to other documents, are not checked.
+        if let Some(target) = resolve_ref(root, reference) {
+            validate_value(root, target, value, path, depth + 1, violations);
+        }
+    }
+
+    if let Some(schemas) = schema.get("allOf").and_then(Value::as_array) {
+        for schema in schemas {
+            validate_value(root, schema, value, path, depth, violations);
+        }
+    }
+
+    for key in ["anyOf", "oneOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            // The value is validated on a copy for each variant, and takes the rewrites of the one it matches.
+            let valid = variants.iter().find_map(|variant| {
+                let mut candidate = value.clone();
+                let mut variant_violations = Vec::new();
+                validate_value(
+                    root,
+                    variant,
+                    &mut candidate,
+                    path,
+                    depth,
+                    &mut variant_violations,
+                );
+                variant_violations.is_empty().then_some(candidate)
+            });
+            match valid {
+                Some(candidate) => *value = candidate,
+                None => {
+                    violations.push(format!(
+                        "{}: {} does not match any of the allowed schemas",
+                        path, value
+                    ));
+                    return;
+                }
+            }
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let expected_types = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !expected_types.is_empty() && !expected_types.iter().any(|t| matches_type(t, value)) {
+            violations.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                expected_types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+        if expected_types.contains(&"integer") && !expected_types.contains(&"number") {
+            if let Some(n) = whole_number(value) {
+                *value = Value::from(n);
+            }
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(&*value) {
+            violations.push(format!(
+                "{}: {} is not one of {}",
+                path,
+                value,
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for field in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(field) {
+                        violations.push(format!("{}: missing required field '{}'", path, field));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, field_value) in map.iter_mut() {
+                let field_path = format!("{}.{}", path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(field_schema) => {
+                        validate_value(root, field_schema, field_value, &field_path, 0, violations)
+                    }
+                    None => {
+                        if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                            violations.push(format!("{}: unknown field", field_path));
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter_mut().enumerate() {
+                    let item_path = format!("{}[{}]", path, i);
+                    validate_value(root, item_schema, item, &item_path, 0, violations);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {"type": "string"},
+                "max_results": {"type": ["integer", "null"]},
+                "depth": {"enum": ["basic", "advanced"]},
+                "urls": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["query"]
+        })
+    }
+
+    #[test]
+    fn test_valid_arguments() {
+        assert!(validate_arguments(&schema(), &mut json!({"query": "rust"})).is_ok());
+        assert!(validate_arguments(
+            &schema(),
+            &mut json!({"query": "rust", "max_results": null, "depth": "basic", "urls": ["a"]})
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let violations = validate_arguments(
+            &schema(),
+            &mut json!({"max_results": "five", "depth": "deep", "urls": [1]}),
+        )
+        .unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                "arguments: missing required field 'query'",
+                "arguments.depth: \"deep\" is not one of [\"basic\",\"advanced\"]",
+                "arguments.max_results: expected integer or null, got string",
+                "arguments.urls[0]: expected string, got integer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_floats() {
+        let mut arguments = json!({"query": "rust", "max_results": 5.0});
+        assert!(validate_arguments(&schema(), &mut arguments).is_ok());
+        assert_eq!(arguments, json!({"query": "rust", "max_results": 5}));
+        let limit = serde_json::from_value::<Option<usize>>(arguments["max_results"].clone());
+        assert_eq!(limit.unwrap(), Some(5));
+        assert_eq!(
+            validate_arguments(&schema(), &mut json!({"query": "rust", "max_results": 5.5}))
+                .unwrap_err(),
+            vec!["arguments.max_results: expected integer or null, got number"]
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        // The schema schemars generates for a parameter of a nested type, and for a recursive type.
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "filter": {"allOf": [{"$ref": "#/definitions/Filter"}], "description": "The filter"},
+                "tree": {"$ref": "#/$defs/Node"}
+            },
+            "definitions": {
+                "Filter": {
+                    "type": "object",
+                    "properties": {"field": {"type": "string"}, "limit": {"type": "integer"}},
+                    "required": ["field"]
+                },
+                "Loop": {"$ref": "#/definitions/Loop"}
+            },
+            "$defs": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/Node"}}
+                    }
+                }
+            }
+        });
+        assert!(validate_arguments(
+            &schema,
+            &mut json!({
+                "filter": {"field": "title", "limit": 10},
+                "tree": {"name": "a", "children": [{"name": "b", "children": [{"name": "c"}]}]}
+            })
+        )
+        .is_ok());
+        assert_eq!(
+            validate_arguments(
+                &schema,
+                &mut json!({
+                    "filter": {"limit": "ten"},
+                    "tree": {"children": [{"children": [{"name": 3}]}]}
+                })
+            )
+            .unwrap_err(),
+            vec![
+                "arguments.filter: missing required field 'field'",
+                "arguments.filter.limit: expected integer, got string",
+                "arguments.tree.children[0].children[0].name: expected string, got integer",
+            ]
+        );
+        let looping = json!({"$ref": "#/definitions/Loop", "definitions": schema["definitions"]});
+        assert!(validate_arguments(&looping, &mut json!({"a": 1})).is_ok());
+    }
+}
@@ -1,86 +1,327 @@
 //! This module contains the visit website tool. The model uses this tool to visit a webpage and read its content as a markdown string.
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::IpAddr;
+
 use async_trait::async_trait;
-use htmd::HtmlToMarkdown;
 use reqwest::Url;
 use schemars::JsonSchema;
+use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 
-use super::{base::BaseTool, tool_traits::Tool};
+use super::{
+    base::BaseTool,
+    documents::{extract_docx_text, DocumentType},
+    tool_traits::Tool,
+    web_content::WebContentPipeline,
+};
 use anyhow::Result;
 
 #[derive(Debug, Serialize, Default, Clone)]
 pub struct VisitWebsiteTool {
     pub tool: BaseTool,
+    /// Domains the tool may visit, including their subdomains. All domains are allowed when empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_domains: Vec<String>,
+    /// Number of characters returned at most by a visit. Longer pages are read in chunks with `start_index`.
+    pub max_length: usize,
+    /// How the HTML pages are read. Readability is off unless the model asks for it.
+    pub content: WebContentPipeline,
 }
 
+/// Pages are cut after this many characters unless the tool or the model asks for another length.
+const DEFAULT_MAX_LENGTH: usize = 20_000;
+
 impl VisitWebsiteTool {
     pub fn new() -> Self {
         VisitWebsiteTool {
             tool: BaseTool {
                 name: "visit_website",
-                description: "Visits a webpage at the given url and reads its content as a markdown string. Use this to browse webpages",
+                description: "Visits a webpage at the given url and reads its content as a markdown string. Use this to browse webpages. Long pages are cut, read the rest with start_index. Use readability or a CSS selector to read only the part of the page you need. Tables are read as markdown tables, PDFs and Word documents as text",
             },
+            allowed_domains: vec![],
+            max_length: DEFAULT_MAX_LENGTH,
+            content: WebContentPipeline::new(),
         }
     }
 
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    /// Set the pipeline reading the HTML pages. The pages are still cut after `max_length` characters.
+    pub fn with_content_pipeline(mut self, content: WebContentPipeline) -> Self {
+        self.content = content;
+        self
+    }
+
+    pub fn with_allowed_domains(mut self, allowed_domains: Vec<String>) -> Self {
+        self.allowed_domains = normalize_domains(allowed_domains);
+        self
+    }
+
+    fn is_allowed(&self, url: &Url) -> bool {
+        self.allowed_domains.is_empty() || matches_domain(&self.allowed_domains, url)
+    }
+
     pub async fn forward(&self, url: &str) -> String {
+        self.visit(&VisitWebsiteToolParams {
+            url: url.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Visit the page and return the requested part of its content.
+    pub async fn visit(&self, params: &VisitWebsiteToolParams) -> String {
         let client = reqwest::Client::builder()
             .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-            .timeout(std::time::Duration::from_secs(10))
             .build()
             .unwrap_or_else(|_| reqwest::Client::new());
-        let url = match Url::parse(url) {
+        let url = match Url::parse(&params.url) {
             Ok(url) => url,
-            Err(_) => Url::parse(&format!("https://{}", url)).unwrap(),
+            Err(_) => match Url::parse(&format!("https://{}", params.url)) {
+                Ok(url) => url,
+                Err(_) => return format!("{} is not a valid url", params.url),
+            },
         };
-
-        // Check if URL points to a PDF
-        if url.path().to_lowercase().ends_with(".pdf") {
-            return "This URL points to a PDF file which cannot be processed directly. Please download and view the PDF separately.".to_string();
+        if !self.is_allowed(&url) {
+            return format!(
+                "Visiting {} is not allowed. Only these domains can be visited: {}",
+                url,
+                self.allowed_domains.join(", ")
+            );
         }
 
-        let response = client.get(url.clone()).send().await;
+        let response = client
+            .get(url.clone())
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await;
 
-        match response {
+        let content = match response {
             Ok(resp) => {
                 if resp.status().is_success() {
-                    match resp.text().await {
-                        Ok(text) => {
-                            let converter = HtmlToMarkdown::builder()
-                                .skip_tags(vec!["script", "style", "header", "nav", "footer"])
-                                .build();
-                            converter.convert(&text).unwrap()
+                    let content_type = resp
+                        .headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
+                    let document_type = DocumentType::detect(content_type.as_deref(), url.path());
+                    match document_type {
+                        DocumentType::Pdf | DocumentType::Docx => {
+                            let Ok(bytes) = resp.bytes().await else {
+                                return "Failed to read response body".to_string();
+                            };
+                            let text = match document_type {
+                                DocumentType::Pdf => extract_pdf_text(&bytes),
+                                _ => extract_docx_text(&bytes),
+                            };
+                            match text {
+                                Some(text) => text,
+                                None => return format!("Failed to extract the text of the document {}", url),
+                            }
+                        }
+                        DocumentType::Binary => {
+                            return format!(
+                                "{} is a {} file, which cannot be read as text. Try another website URL.",
+                                url,
+                                content_type.unwrap_or_default()
+                            )
                         }
-                        Err(_) => "Failed to read response text".to_string(),
+                        DocumentType::Text => match resp.text().await {
+                            Ok(text) => text,
+                            Err(_) => return "Failed to read response text".to_string(),
+                        },
+                        DocumentType::Html => match resp.text().await {
+                            Ok(text) => match self.extract(&text, params) {
+                                Ok(markdown) => markdown,
+                                Err(message) => return message,
+                            },
+                            Err(_) => return "Failed to read response text".to_string(),
+                        },
                     }
                 } else if resp.status().as_u16() == 999 {
-                    "The website appears to be blocking automated access. Try visiting the URL directly in your browser.".to_string()
+                    return "The website appears to be blocking automated access. Try visiting the URL directly in your browser.".to_string();
                 } else {
-                    format!(
+                    return format!(
                         "Failed to fetch the webpage {}: HTTP {} - {}. Try another website URL.",
                         url,
                         resp.status(),
                         resp.status().canonical_reason().unwrap_or("Unknown Error")
-                    )
+                    );
                 }
             }
-            Err(e) => format!(
-                "Failed to make the request to {}: {}. Try another website URL.",
-                url, e
-            ),
+            Err(e) => {
+                return format!(
+                    "Failed to make the request to {}: {}. Try another website URL.",
+                    url, e
+                )
+            }
+        };
+        paginate(
+            &content,
+            params.start_index.unwrap_or(0),
+            params.max_length.unwrap_or(self.max_length).max(1),
+        )
+    }
+
+    /// The part of the page asked by the params, read by the content pipeline. The error is the message returned to
+    /// the model.
+    fn extract(&self, html: &str, params: &VisitWebsiteToolParams) -> Result<String, String> {
+        match params.selector.as_deref() {
+            Some(selector) => self.content.with_readability(false).extract(&select(html, selector)?),
+            None => self
+                .content
+                .with_readability(params.readability.unwrap_or(self.content.readability))
+                .extract(html),
+        }
+    }
+}
+
+/// Lowercase the domains of an allowlist, `*.example.com` being the same as `example.com`.
+pub(crate) fn normalize_domains(domains: Vec<String>) -> Vec<String> {
+    domains
+        .into_iter()
+        .map(|domain| domain.trim_start_matches("*.").to_lowercase())
+        .collect()
+}
+
+/// Whether the host of the url is one of the normalized domains or one of their subdomains.
+pub(crate) fn matches_domain(domains: &[String], url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    domains
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Refuse the urls whose host is, or resolves to, a loopback, private or other address that is not reachable from
+/// the internet, so that the url can not reach the services of the local network.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn check_public_host(url: &Url) -> Result<()> {
+    use anyhow::Context as _;
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?;
+    // IPv6 hosts are in brackets, e.g. `[::1]`.
+    let addresses = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => tokio::net::lookup_host((host, url.port_or_known_default().unwrap_or(443)))
+            .await
+            .with_context(|| format!("Failed to resolve {}", host))?
+            .map(|address| address.ip())
+            .collect::<Vec<IpAddr>>(),
+    };
+    if addresses.iter().any(|ip| !is_public(*ip)) {
+        return Err(anyhow::anyhow!(
+            "{} is on a loopback or private address",
+            url
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the address is reachable from the internet, i.e. not a loopback, private, link-local or reserved one.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // Shared address space of the carrier-grade NATs, 100.64.0.0/10.
+                || (a == 100 && b & 0xc0 == 64))
         }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// The HTML of the elements matching the CSS selector.
+fn select(html: &str, selector: &str) -> Result<String, String> {
+    let parsed = Selector::parse(selector)
+        .map_err(|e| format!("Invalid CSS selector {}: {}", selector, e))?;
+    let document = Html::parse_document(html);
+    let elements = document.select(&parsed).map(|e| e.html()).collect::<Vec<_>>();
+    if elements.is_empty() {
+        return Err(format!(
+            "No element matches the selector {}. Try another selector or visit the page without one.",
+            selector
+        ));
+    }
+    Ok(elements.join("\n"))
+}
+
+/// The text of a PDF. `pdf-extract` panics on some malformed files, which are reported as failures.
+pub(crate) fn extract_pdf_text(bytes: &[u8]) -> Option<String> {
+    std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .ok()?
+        .ok()
+}
+
+/// The characters of `content` from `start` on, at most `max_length` of them, with a note telling the model how to
+/// read the rest.
+pub(crate) fn paginate(content: &str, start: usize, max_length: usize) -> String {
+    let total = content.chars().count();
+    if start == 0 && total <= max_length {
+        return content.to_string();
+    }
+    if start >= total {
+        return format!(
+            "start_index {} is past the end of the page, which has {} characters.",
+            start, total
+        );
+    }
+    let end = (start + max_length).min(total);
+    let chunk = content.chars().skip(start).take(end - start).collect::<String>();
+    if end < total {
+        format!(
+            "{}\n\n[Showing characters {} to {} of {}. Visit the page again with start_index={} to read the next chunk.]",
+            chunk, start, end, total, end
+        )
+    } else {
+        format!("{}\n\n[Showing characters {} to {} of {}, the end of the page.]", chunk, start, end, total)
     }
 }
 
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, JsonSchema, Default)]
 #[schemars(title = "VisitWebsiteToolParams")]
 pub struct VisitWebsiteToolParams {
     #[schemars(description = "The url of the website to visit")]
     url: String,
+    #[schemars(
+        description = "Optionally only read the main article of the page, without menus, sidebars and comments"
+    )]
+    readability: Option<bool>,
+    #[schemars(
+        description = "Optionally only read the elements matching this CSS selector, e.g. `table.prices` or `#content`"
+    )]
+    selector: Option<String>,
+    #[schemars(
+        description = "Optionally the maximum number of characters to read. Longer pages are cut"
+    )]
+    max_length: Option<usize>,
+    #[schemars(
+        description = "Optionally the character to start reading from, to read the next chunk of a cut page"
+    )]
+    start_index: Option<usize>,
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl Tool for VisitWebsiteTool {
     type Params = VisitWebsiteToolParams;
     fn name(&self) -> &'static str {
@@ -92,8 +333,7 @@ impl Tool for VisitWebsiteTool {
     }
 
     async fn forward(&self, arguments: VisitWebsiteToolParams) -> Result<String> {
-        let url = arguments.url;
-        Ok(self.forward(&url).await)
+        Ok(self.visit(&arguments).await)
     }
 }
 
@@ -107,4 +347,82 @@ mod tests {
         let _result = tool.forward(&url).await;
         println!("{}", _result);
     }
+
+    #[test]
+    fn test_allowed_domains() {
+        let tool = VisitWebsiteTool::new().with_allowed_domains(vec!["*.rust-lang.org".to_string()]);
+        assert!(tool.is_allowed(&Url::parse("https://rust-lang.org/learn").unwrap()));
+        assert!(tool.is_allowed(&Url::parse("https://doc.rust-lang.org/std").unwrap()));
+        assert!(!tool.is_allowed(&Url::parse("https://evil-rust-lang.org").unwrap()));
+        assert!(VisitWebsiteTool::new().is_allowed(&Url::parse("https://example.com").unwrap()));
+    }
+
+    const PAGE: &str = r#"<html><head><title>Rust 2024</title></head><body>
+        <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+        <div id="sidebar"><p><a href="/a">A related post with a rather long title that is only a link</a></p></div>
+        <div id="article">
+            <p>The Rust 2024 edition is the largest edition ever released, with many changes to the language.</p>
+            <p>Editions let the language evolve without breaking the existing code of its users.</p>
+        </div>
+        <table class="prices"><tr><td>Free</td></tr></table>
+    </body></html>"#;
+
+    #[test]
+    fn test_is_public() {
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let content = select(PAGE, "table.prices td").unwrap();
+        assert_eq!(content, "<td>Free</td>");
+        assert!(select(PAGE, "#missing").unwrap_err().contains("No element matches"));
+        assert!(select(PAGE, "[[").unwrap_err().contains("Invalid CSS selector"));
+    }
+
+    #[test]
+    fn test_extract() {
+        let tool = VisitWebsiteTool::new()
+            .with_content_pipeline(WebContentPipeline::new().with_markdown(false));
+        let params = |readability, selector: Option<&str>| VisitWebsiteToolParams {
+            readability,
+            selector: selector.map(str::to_string),
+            ..Default::default()
+        };
+        let page = tool.extract(PAGE, &params(None, None)).unwrap();
+        assert!(page.contains("related post") && page.contains("Free"));
+        let article = tool.extract(PAGE, &params(Some(true), None)).unwrap();
+        assert!(article.starts_with("Rust 2024\n"));
+        assert!(!article.contains("related post"));
+        assert_eq!(tool.extract(PAGE, &params(Some(true), Some("td"))).unwrap(), "Free");
+    }
+
+    #[test]
+    fn test_paginate() {
+        assert_eq!(paginate("abcdef", 0, 10), "abcdef");
+        let first = paginate("abcdef", 0, 4);
+        assert!(first.starts_with("abcd\n"));
+        assert!(first.contains("start_index=4"));
+        let last = paginate("abcdef", 4, 4);
+        assert!(last.starts_with("ef\n"));
+        assert!(last.contains("the end of the page"));
+        assert!(paginate("abcdef", 6, 4).contains("past the end"));
+    }
 }
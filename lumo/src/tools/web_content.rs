@@ -0,0 +1,373 @@
+//! This module contains the pipeline the web tools use to turn the HTML of a page into the text the model reads.
+//!
+//! The pipeline runs the same stages for every tool, so that a page costs about the same tokens whichever tool read
+//! it: readability keeps the main article of the page, the article is converted to markdown (or to plain text),
+//! boilerplate lines such as cookie banners and repeated menu entries are removed, and the result is capped.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Serialize;
+
+use super::documents::html_to_markdown;
+
+/// Lines of this many characters at most can be boilerplate. Longer lines are content.
+const MAX_BOILERPLATE_LENGTH: usize = 80;
+
+/// Elements whose text is never content.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "head", "header", "nav", "footer",
+];
+
+/// Elements starting a new line in plain text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Short lines that are part of the chrome of a site rather than of its content.
+fn boilerplate() -> &'static Regex {
+    static BOILERPLATE: OnceLock<Regex> = OnceLock::new();
+    BOILERPLATE.get_or_init(|| {
+        Regex::new(
+        r"(?i)^[\s*_#>\[-]*(skip to (main )?content|(accept|reject|manage) (all )?cookies|(this (web)?site|we) uses? cookies.*|cookie (settings|preferences|policy)|subscribe to (our|the) newsletter|sign up for (our|the) newsletter|all rights reserved.*|(©|\(c\)|copyright) .*|share (this|on) .*|follow us( on .*)?|back to top|advertisement|(log|sign) (in|up|out)|privacy policy|terms (of (use|service)|and conditions))[\s*_\].:!]*(\([^)]*\))?$",
+        )
+        .unwrap()
+    })
+}
+
+/// Lines made only of an image, `![alt](src)`, or of an empty link, `[](href)`.
+fn empty_media() -> &'static Regex {
+    static EMPTY_MEDIA: OnceLock<Regex> = OnceLock::new();
+    EMPTY_MEDIA.get_or_init(|| Regex::new(r"^(\s*(!\[[^\]]*\]|\[\s*\])\([^)]*\))+\s*$").unwrap())
+}
+
+/// The stages turning the HTML of a page into the text given to the model. Each stage can be turned off, and the
+/// tools expose the pipeline so that it can be tuned for all their pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WebContentPipeline {
+    /// Keep only the title and main article of the page, without menus, sidebars and comments.
+    pub readability: bool,
+    /// Convert the page to markdown, keeping its headings, links and tables. The page is read as plain text
+    /// otherwise, which costs fewer tokens.
+    pub markdown: bool,
+    /// Remove the lines of the site's chrome: cookie banners, sharing links, lone images and the short lines repeated
+    /// on the page, such as menu entries.
+    pub remove_boilerplate: bool,
+    /// Characters kept at most, the cut content ending with `…`.
+    pub max_length: Option<usize>,
+}
+
+impl Default for WebContentPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebContentPipeline {
+    /// All the stages but readability, without a length cap.
+    pub fn new() -> Self {
+        WebContentPipeline {
+            readability: false,
+            markdown: true,
+            remove_boilerplate: true,
+            max_length: None,
+        }
+    }
+
+    pub fn with_readability(mut self, readability: bool) -> Self {
+        self.readability = readability;
+        self
+    }
+
+    pub fn with_markdown(mut self, markdown: bool) -> Self {
+        self.markdown = markdown;
+        self
+    }
+
+    pub fn with_boilerplate_removal(mut self, remove_boilerplate: bool) -> Self {
+        self.remove_boilerplate = remove_boilerplate;
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: Option<usize>) -> Self {
+        self.max_length = max_length.map(|max_length| max_length.max(1));
+        self
+    }
+
+    /// The content of the page after all the stages. The error is the message returned to the model.
+    pub fn extract(&self, html: &str) -> Result<String, String> {
+        let html = if self.readability {
+            readable_content(html)
+        } else {
+            html.to_string()
+        };
+        let content = if self.markdown {
+            html_to_markdown(&html)?
+        } else {
+            html_to_text(&html)
+        };
+        Ok(self.finish(&content))
+    }
+
+    /// Run the stages after the conversion on content that already is text, e.g. the text of a PDF.
+    pub fn finish(&self, content: &str) -> String {
+        let content = if self.remove_boilerplate {
+            remove_boilerplate(content)
+        } else {
+            content.trim().to_string()
+        };
+        match self.max_length {
+            Some(max_length) => truncate(&content, max_length),
+            None => content,
+        }
+    }
+}
+
+/// The title and main article of the page, found like Readability does: each paragraph scores its text length for
+/// its parent and half of it for its grandparent, minus the text of the links, and the best scoring element wins.
+pub fn readable_content(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let paragraphs = Selector::parse("p, pre, td, blockquote").unwrap();
+    let links = Selector::parse("a").unwrap();
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&paragraphs) {
+        let text_length = paragraph.text().map(str::len).sum::<usize>() as f64;
+        let link_length = paragraph
+            .select(&links)
+            .flat_map(|link| link.text())
+            .map(str::len)
+            .sum::<usize>() as f64;
+        let score = text_length - link_length;
+        if score < 25.0 {
+            continue;
+        }
+        let mut ancestors = paragraph.ancestors().filter_map(ElementRef::wrap);
+        if let Some(parent) = ancestors.next() {
+            *scores.entry(parent.id()).or_insert(0.0) += score;
+        }
+        if let Some(grandparent) = ancestors.next() {
+            *scores.entry(grandparent.id()).or_insert(0.0) += score / 2.0;
+        }
+    }
+    let best = scores
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .and_then(|(id, _)| document.tree.get(id))
+        .and_then(ElementRef::wrap);
+    let Some(article) = best else {
+        return html.to_string();
+    };
+    let title = Selector::parse("title").unwrap();
+    match document.select(&title).next() {
+        Some(title) => format!(
+            "<h1>{}</h1>\n{}",
+            title.text().collect::<String>().trim(),
+            article.html()
+        ),
+        None => article.html(),
+    }
+}
+
+/// The text of the page, one line per block element, without the text of scripts, styles and menus.
+pub fn html_to_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let mut text = String::new();
+    push_text(document.root_element(), false, &mut text);
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Push the text of the element, the line breaks of the HTML source being spaces outside of `pre` elements.
+fn push_text(element: ElementRef, preformatted: bool, text: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(node) if preformatted => text.push_str(node),
+            Node::Text(node) => text.extend(node.chars().map(|c| if c == '\n' { ' ' } else { c })),
+            Node::Element(_) => {
+                let Some(child) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                let name = child.value().name();
+                if SKIPPED_ELEMENTS.contains(&name) {
+                    continue;
+                }
+                let block = BLOCK_ELEMENTS.contains(&name);
+                if block {
+                    text.push('\n');
+                }
+                push_text(child, preformatted || name == "pre", text);
+                if block {
+                    text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The content without its boilerplate lines and with at most one blank line in a row. Code blocks and tables are
+/// kept as they are.
+pub fn remove_boilerplate(content: &str) -> String {
+    let mut seen = HashSet::new();
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_code = false;
+    for line in content.lines() {
+        let line = line.trim_end();
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            lines.push(line);
+            continue;
+        }
+        if in_code || trimmed.starts_with('|') {
+            lines.push(line);
+            continue;
+        }
+        if trimmed.is_empty() {
+            if lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push("");
+            }
+            continue;
+        }
+        let short = trimmed.chars().count() <= MAX_BOILERPLATE_LENGTH;
+        if short
+            && (boilerplate().is_match(trimmed)
+                || empty_media().is_match(trimmed)
+                || !seen.insert(trimmed))
+        {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// The first `max_length` characters of the content, followed by `…` when it is cut.
+pub fn truncate(content: &str, max_length: usize) -> String {
+    match content.char_indices().nth(max_length) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head><title>Rust 2024</title><style>p { color: red }</style></head><body>
+        <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+        <div id="sidebar"><p><a href="/a">A related post with a rather long title that is only a link</a></p></div>
+        <div id="article">
+            <p>The Rust 2024 edition is the largest edition ever released, with many changes to the language.</p>
+            <p>Editions let the language evolve without breaking the existing code of its users.</p>
+        </div>
+        <p>We use cookies to improve your experience.</p>
+        <footer>All rights reserved</footer>
+        <script>track()</script>
+    </body></html>"#;
+
+    #[test]
+    fn test_readable_content() {
+        let content = readable_content(PAGE);
+        assert!(content.starts_with("<h1>Rust 2024</h1>"));
+        assert!(content.contains("largest edition"));
+        assert!(!content.contains("related post"));
+        assert!(!content.contains("Blog"));
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let text = html_to_text(PAGE);
+        assert!(text.contains(
+            "The Rust 2024 edition is the largest edition ever released, with many changes to the language.\n\
+             Editions let the language evolve"
+        ));
+        assert!(!text.contains("Home"));
+        assert!(!text.contains("color"));
+        assert!(!text.contains("track()"));
+        assert!(!text.contains("All rights reserved"));
+        assert_eq!(
+            html_to_text("<b>Bold</b> and\n   <i>italic</i>"),
+            "Bold and italic"
+        );
+    }
+
+    #[test]
+    fn test_remove_boilerplate() {
+        let content = "Skip to content\n\n\n# Rust 2024\n[Home](/)\n![](/logo.png)\n\nThe largest edition ever.\n\n\
+                       Accept all cookies\n[Home](/)\n```\nlet a = 1;\nlet a = 1;\n```\n| a |\n| a |\n\
+                       © 2024 The Rust Foundation";
+        assert_eq!(
+            remove_boilerplate(content),
+            "# Rust 2024\n[Home](/)\n\nThe largest edition ever.\n\n```\nlet a = 1;\nlet a = 1;\n```\n| a |\n| a |"
+        );
+        let long = "Sign in ".repeat(20);
+        assert_eq!(remove_boilerplate(&long), long.trim());
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Lumo is a toolkit", 4), "Lumo…");
+        assert_eq!(truncate("Lumo", 4), "Lumo");
+        assert_eq!(truncate("héllo", 2), "hé…");
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let pipeline = WebContentPipeline::new()
+            .with_readability(true)
+            .with_markdown(false)
+            .with_max_length(Some(30));
+        assert_eq!(
+            pipeline.extract(PAGE).unwrap(),
+            "Rust 2024\nThe Rust 2024 editio…"
+        );
+        let text = WebContentPipeline::new()
+            .with_markdown(false)
+            .extract(PAGE)
+            .unwrap();
+        assert!(text.contains("related post"));
+        assert!(!text.contains("cookies"));
+        let text = WebContentPipeline::new()
+            .with_markdown(false)
+            .with_boilerplate_removal(false)
+            .extract(PAGE)
+            .unwrap();
+        assert!(text.contains("We use cookies"));
+        assert_eq!(pipeline.finish("  \nRust\n\n\n\n2024  "), "Rust\n\n2024");
+    }
+}
@@ -0,0 +1,485 @@
+//! This module contains the web crawl tool. The model uses this tool to read a whole site, e.g. to summarize a
+//! documentation site, in one step instead of visiting its pages one by one.
+//!
+//! The crawl starts at a seed URL and follows the links of the same host, breadth first, up to a depth and a number
+//! of pages. The rules of the site's robots.txt are respected.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::Url;
+use schemars::JsonSchema;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use super::{
+    base::BaseTool,
+    tool_traits::Tool,
+    web_content::WebContentPipeline,
+};
+
+/// The name of the crawler in robots.txt.
+const ROBOTS_USER_AGENT: &str = "lumo";
+const DEFAULT_MAX_PAGES: usize = 10;
+const DEFAULT_MAX_DEPTH: usize = 2;
+/// Characters of each page in the digest.
+const DEFAULT_MAX_LENGTH: usize = 2_000;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Links that are not pages.
+const SKIPPED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "svg", "webp", "ico", "css", "js", "json", "xml", "zip", "gz",
+    "pdf", "mp3", "mp4", "woff", "woff2",
+];
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "WebCrawlToolParams")]
+pub struct WebCrawlToolParams {
+    #[schemars(description = "The url to start crawling from")]
+    url: String,
+    #[schemars(description = "Optionally the maximum number of pages to read")]
+    max_pages: Option<usize>,
+    #[schemars(
+        description = "Optionally how many links away from the start page to go: 0 only reads the start page"
+    )]
+    max_depth: Option<usize>,
+    #[schemars(
+        description = "Optionally only follow links whose path starts with this, e.g. /docs/"
+    )]
+    path_prefix: Option<String>,
+}
+
+/// A page read by the crawl.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrawledPage {
+    pub url: String,
+    pub title: String,
+    /// Links away from the seed.
+    pub depth: usize,
+    /// The content of the page read by the tool's pipeline, cut after the tool's `max_length`.
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebCrawlTool {
+    pub tool: BaseTool,
+    /// Pages read at most by a crawl. The model can ask for fewer.
+    pub max_pages: usize,
+    /// Links followed at most from the seed. The model can ask for fewer.
+    pub max_depth: usize,
+    /// Characters of each page in the digest.
+    pub max_length: usize,
+    pub respect_robots: bool,
+    /// How the pages are read. Only the main article of each page is kept by default.
+    pub content: WebContentPipeline,
+}
+
+impl Default for WebCrawlTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebCrawlTool {
+    pub fn new() -> Self {
+        WebCrawlTool {
+            tool: BaseTool {
+                name: "web_crawl",
+                description: "Crawls a website from a start url, following its links within the same domain, and returns the readable text of each page. Use this to read a whole site, e.g. a documentation site, instead of visiting its pages one by one",
+            },
+            max_pages: DEFAULT_MAX_PAGES,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_length: DEFAULT_MAX_LENGTH,
+            respect_robots: true,
+            content: WebContentPipeline::new().with_readability(true),
+        }
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages.max(1);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    /// Whether to skip the pages disallowed by the site's robots.txt. On by default.
+    pub fn with_respect_robots(mut self, respect_robots: bool) -> Self {
+        self.respect_robots = respect_robots;
+        self
+    }
+
+    /// Set the pipeline reading the pages. The pages are still cut after `max_length` characters.
+    pub fn with_content_pipeline(mut self, content: WebContentPipeline) -> Self {
+        self.content = content;
+        self
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .user_agent(format!(
+                "Mozilla/5.0 (compatible; {}/{})",
+                ROBOTS_USER_AGENT,
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new())
+    }
+
+    /// The robots.txt rules of the seed's site. A missing robots.txt allows everything.
+    async fn robots(&self, client: &reqwest::Client, seed: &Url) -> RobotsRules {
+        if !self.respect_robots {
+            return RobotsRules::default();
+        }
+        let Ok(url) = seed.join("/robots.txt") else {
+            return RobotsRules::default();
+        };
+        match client.get(url).timeout(FETCH_TIMEOUT).send().await {
+            Ok(response) if response.status().is_success() => RobotsRules::parse(
+                &response.text().await.unwrap_or_default(),
+                ROBOTS_USER_AGENT,
+            ),
+            _ => RobotsRules::default(),
+        }
+    }
+
+    /// The HTML of a page, or `None` for failures and other content types.
+    async fn fetch(client: &reqwest::Client, url: &Url) -> Option<String> {
+        let response = client
+            .get(url.clone())
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+        let is_html = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_none_or(|value| value.contains("html"));
+        if !response.status().is_success() || !is_html {
+            return None;
+        }
+        response.text().await.ok()
+    }
+
+    /// Crawl from the seed, breadth first. Pages are fetched one at a time, so the site is not flooded.
+    pub async fn crawl(&self, params: &WebCrawlToolParams) -> Result<Vec<CrawledPage>> {
+        let seed = Url::parse(&params.url)
+            .or_else(|_| Url::parse(&format!("https://{}", params.url)))
+            .map_err(|_| anyhow!("{} is not a valid url", params.url))?;
+        let max_pages = params
+            .max_pages
+            .unwrap_or(self.max_pages)
+            .clamp(1, self.max_pages);
+        let max_depth = params
+            .max_depth
+            .unwrap_or(self.max_depth)
+            .min(self.max_depth);
+        let path_prefix = params.path_prefix.as_deref().unwrap_or("/");
+
+        let client = Self::client();
+        let robots = self.robots(&client, &seed).await;
+        if !robots.allows(seed.path()) {
+            return Err(anyhow!(
+                "{} is disallowed by the robots.txt of the site",
+                seed
+            ));
+        }
+        let mut pages = Vec::new();
+        let mut seen = HashSet::from([seed.to_string()]);
+        let mut queue = VecDeque::from([(seed, 0)]);
+        while let Some((url, depth)) = queue.pop_front() {
+            if pages.len() >= max_pages {
+                break;
+            }
+            let Some(html) = Self::fetch(&client, &url).await else {
+                continue;
+            };
+            if depth < max_depth {
+                for link in extract_links(&html, &url) {
+                    if link.path().starts_with(path_prefix)
+                        && robots.allows(link.path())
+                        && seen.insert(link.to_string())
+                    {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+            let content = self
+                .content
+                .with_max_length(Some(self.max_length))
+                .extract(&html)
+                .unwrap_or_default();
+            pages.push(CrawledPage {
+                url: url.to_string(),
+                title: page_title(&html).unwrap_or_else(|| url.path().to_string()),
+                depth,
+                content,
+            });
+        }
+        Ok(pages)
+    }
+
+    pub async fn forward(&self, params: &WebCrawlToolParams) -> Result<String> {
+        let pages = self.crawl(params).await?;
+        Ok(digest(&params.url, &pages))
+    }
+}
+
+/// The pages of the same host linked from a page, without fragments and without links to files.
+fn extract_links(html: &str, base: &Url) -> Vec<Url> {
+    let document = Html::parse_document(html);
+    let anchors = Selector::parse("a[href]").unwrap();
+    let mut links = Vec::new();
+    for anchor in document.select(&anchors) {
+        let Some(mut link) = anchor
+            .value()
+            .attr("href")
+            .and_then(|href| base.join(href).ok())
+        else {
+            continue;
+        };
+        link.set_fragment(None);
+        let extension = link
+            .path()
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase())
+            .unwrap_or_default();
+        if matches!(link.scheme(), "http" | "https")
+            && link.host_str() == base.host_str()
+            && !SKIPPED_EXTENSIONS.contains(&extension.as_str())
+            && !links.contains(&link)
+        {
+            links.push(link);
+        }
+    }
+    links
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let title = Selector::parse("title").unwrap();
+    let title = document.select(&title).next()?.text().collect::<String>();
+    let title = title.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// The pages as a markdown digest, one section per page.
+fn digest(seed: &str, pages: &[CrawledPage]) -> String {
+    if pages.is_empty() {
+        return format!("No page could be read from {}.", seed);
+    }
+    let plural = if pages.len() == 1 { "" } else { "s" };
+    let mut digest = format!("Crawled {} page{} from {}.\n", pages.len(), plural, seed);
+    for (i, page) in pages.iter().enumerate() {
+        digest.push_str(&format!(
+            "\n## {}. {}\nURL: {} (depth {})\n\n{}\n",
+            i + 1,
+            page.title,
+            page.url,
+            page.depth,
+            page.content
+        ));
+    }
+    digest
+}
+
+#[derive(Debug)]
+struct RobotsRule {
+    allow: bool,
+    /// The length of the pattern in robots.txt. The longest matching rule wins.
+    length: usize,
+    pattern: Regex,
+}
+
+/// The `Allow` and `Disallow` rules of a robots.txt for a user agent.
+#[derive(Debug, Default)]
+struct RobotsRules {
+    rules: Vec<RobotsRule>,
+}
+
+impl RobotsRules {
+    /// The rules of the groups naming the user agent, or of the `*` groups if no group names it.
+    fn parse(content: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+        let mut specific = Vec::new();
+        let mut any = Vec::new();
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    // A user agent after rules starts a new group.
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_lowercase());
+                }
+                key @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    // An empty `Disallow` allows everything.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (key == "allow", value.to_string());
+                    if agents
+                        .iter()
+                        .any(|agent| user_agent.starts_with(agent.as_str()) && agent != "*")
+                    {
+                        specific.push(rule.clone());
+                    }
+                    if agents.iter().any(|agent| agent == "*") {
+                        any.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let rules = if specific.is_empty() { any } else { specific };
+        let mut robots = RobotsRules::default();
+        for (allow, pattern) in rules {
+            // `*` matches any characters and a trailing `$` anchors the end of the path.
+            let (pattern, anchored) = match pattern.strip_suffix('$') {
+                Some(pattern) => (pattern, "$"),
+                None => (pattern.as_str(), ""),
+            };
+            let regex = pattern
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".*");
+            if let Ok(regex) = Regex::new(&format!("^{}{}", regex, anchored)) {
+                robots.rules.push(RobotsRule {
+                    allow,
+                    length: pattern.len(),
+                    pattern: regex,
+                });
+            }
+        }
+        robots
+    }
+
+    /// Whether the path may be crawled: the longest matching rule decides, `Allow` winning ties.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(path))
+            .max_by_key(|rule| (rule.length, rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for WebCrawlTool {
+    type Params = WebCrawlToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: WebCrawlToolParams) -> Result<String> {
+        self.forward(&arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::web_content::truncate;
+
+    #[test]
+    fn test_robots_rules() {
+        let robots = RobotsRules::parse(
+            "# Comments are ignored\n\
+             User-agent: *\n\
+             Disallow: /private/\n\
+             Allow: /private/public/\n\
+             Disallow: /*.cgi$\n\
+             \n\
+             User-agent: googlebot\n\
+             Disallow: /\n",
+            ROBOTS_USER_AGENT,
+        );
+        assert!(robots.allows("/docs/intro"));
+        assert!(!robots.allows("/private/keys"));
+        assert!(robots.allows("/private/public/page"));
+        assert!(!robots.allows("/bin/search.cgi"));
+        assert!(robots.allows("/bin/search.cgi/page"));
+
+        // A group naming the crawler replaces the `*` group.
+        let robots = RobotsRules::parse(
+            "User-agent: *\nDisallow: /\n\nUser-agent: Lumo\nDisallow: /admin\n",
+            "lumo",
+        );
+        assert!(robots.allows("/docs"));
+        assert!(!robots.allows("/admin/users"));
+        assert!(RobotsRules::parse("User-agent: *\nDisallow:\n", "lumo").allows("/"));
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let html = r##"<html><body>
+            <a href="/docs/intro#setup">Intro</a>
+            <a href="guide">Guide</a>
+            <a href="https://docs.rs/lumo/page">Same host</a>
+            <a href="https://github.com/StarlightSearch/lumo">Other host</a>
+            <a href="/logo.png">Logo</a>
+            <a href="mailto:team@example.com">Mail</a>
+            <a href="/docs/intro">Intro again</a>
+        </body></html>"##;
+        let base = Url::parse("https://docs.rs/lumo/").unwrap();
+        let links = extract_links(html, &base)
+            .into_iter()
+            .map(|link| link.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            [
+                "https://docs.rs/docs/intro",
+                "https://docs.rs/lumo/guide",
+                "https://docs.rs/lumo/page"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        let page = CrawledPage {
+            url: "https://docs.rs/lumo/".to_string(),
+            title: "Lumo".to_string(),
+            depth: 0,
+            content: truncate("Lumo is a toolkit for agents", 4),
+        };
+        assert_eq!(
+            digest("https://docs.rs/lumo/", &[page]),
+            "Crawled 1 page from https://docs.rs/lumo/.\n\n## 1. Lumo\nURL: https://docs.rs/lumo/ (depth 0)\n\nLumo…\n"
+        );
+        assert_eq!(
+            digest("https://docs.rs", &[]),
+            "No page could be read from https://docs.rs."
+        );
+        assert_eq!(
+            page_title("<title> Lumo </title>"),
+            Some("Lumo".to_string())
+        );
+    }
+}
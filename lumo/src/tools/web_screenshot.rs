@@ -0,0 +1,492 @@
+//! This module contains the web screenshot tool. The model uses this tool to render a webpage in a headless
+//! browser and look at it, which works on JavaScript-heavy pages that the visit website tool cannot read.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use chromiumoxide::{
+    cdp::browser_protocol::{
+        fetch::{ContinueRequestParams, EnableParams, EventRequestPaused, FailRequestParams},
+        network::ErrorReason,
+        page::CaptureScreenshotFormat,
+    },
+    page::{Page, ScreenshotParams},
+    Browser, BrowserConfig,
+};
+use futures::StreamExt;
+use htmd::HtmlToMarkdown;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    base::BaseTool,
+    tool_traits::Tool,
+    visit_website::{check_public_host, matches_domain, normalize_domains},
+};
+
+const DEFAULT_VISION_URL: &str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_PROMPT: &str =
+    "Describe this screenshot of a webpage. Include the main content, the text that is visible and the layout.";
+/// Characters of the rendered page returned when there is no vision model.
+const MAX_CONTENT_CHARS: usize = 20000;
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "WebScreenshotToolParams")]
+pub struct WebScreenshotToolParams {
+    #[schemars(description = "The url of the webpage to capture")]
+    url: String,
+    #[schemars(
+        description = "What to look for in the screenshot, e.g. 'the price of the product'"
+    )]
+    question: Option<String>,
+    #[schemars(
+        description = "Capture the whole page instead of the visible window (default: false)"
+    )]
+    full_page: Option<bool>,
+}
+
+/// An OpenAI-compatible model that accepts images, used to describe the screenshots.
+#[derive(Debug, Clone)]
+struct VisionModel {
+    model_id: String,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+/// Renders a webpage in a headless Chromium, saves a screenshot and describes it with a vision model. Without a
+/// vision model, the tool returns the path of the screenshot and the text of the rendered page.
+#[derive(Debug, Clone)]
+pub struct WebScreenshotTool {
+    pub tool: BaseTool,
+    output_dir: PathBuf,
+    width: u32,
+    height: u32,
+    /// How long to wait after the page loaded, for scripts to render the content.
+    render_delay: Duration,
+    chrome_executable: Option<PathBuf>,
+    vision_model: Option<VisionModel>,
+    /// Domains the tool may capture, including their subdomains. All domains are allowed when empty.
+    allowed_domains: Vec<String>,
+    /// Whether pages on loopback and private addresses may be captured, e.g. `http://localhost:3000`. Checked for
+    /// every request of the page, its redirects, frames and resources included.
+    allow_private_hosts: bool,
+    /// Run Chromium without its sandbox, which it needs as root and in some containers.
+    no_sandbox: bool,
+}
+
+impl Default for WebScreenshotTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebScreenshotTool {
+    pub fn new() -> Self {
+        WebScreenshotTool {
+            tool: BaseTool {
+                name: "web_screenshot",
+                description: "Opens a webpage in a browser, takes a screenshot and describes what is on the page. Use this for pages that need JavaScript or when visit_website returns no useful content.",
+            },
+            output_dir: std::env::temp_dir().join("lumo-screenshots"),
+            width: 1280,
+            height: 800,
+            render_delay: Duration::from_secs(2),
+            chrome_executable: None,
+            vision_model: None,
+            allowed_domains: vec![],
+            allow_private_hosts: false,
+            no_sandbox: false,
+        }
+    }
+
+    /// Describe the screenshots with a vision model. `base_url` is the chat completions endpoint, and the API key
+    /// defaults to `OPENAI_API_KEY`.
+    pub fn with_vision_model(
+        mut self,
+        model_id: &str,
+        base_url: Option<&str>,
+        api_key: Option<&str>,
+    ) -> Self {
+        self.vision_model = Some(VisionModel {
+            model_id: model_id.to_string(),
+            base_url: base_url.unwrap_or(DEFAULT_VISION_URL).to_string(),
+            api_key: api_key
+                .map(|key| key.to_string())
+                .or_else(|| std::env::var("OPENAI_API_KEY").ok()),
+        });
+        self
+    }
+
+    pub fn with_output_dir(mut self, output_dir: Option<PathBuf>) -> Self {
+        if let Some(output_dir) = output_dir {
+            self.output_dir = output_dir;
+        }
+        self
+    }
+
+    pub fn with_window_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_render_delay(mut self, render_delay: Option<Duration>) -> Self {
+        if let Some(render_delay) = render_delay {
+            self.render_delay = render_delay;
+        }
+        self
+    }
+
+    /// Path of the Chromium executable. By default it is detected from the `CHROME` env variable and the usual
+    /// install locations.
+    pub fn with_chrome_executable(mut self, chrome_executable: Option<PathBuf>) -> Self {
+        self.chrome_executable = chrome_executable;
+        self
+    }
+
+    /// Only capture pages of these domains and their subdomains, e.g. `example.com` or `*.example.com`.
+    pub fn with_allowed_domains(mut self, allowed_domains: Vec<String>) -> Self {
+        self.allowed_domains = normalize_domains(allowed_domains);
+        self
+    }
+
+    /// Allow pages on loopback and private addresses, which are refused by default so that the model cannot reach
+    /// the services of the local network.
+    pub fn with_private_hosts(mut self, allow_private_hosts: bool) -> Self {
+        self.allow_private_hosts = allow_private_hosts;
+        self
+    }
+
+    /// Run Chromium with `--no-sandbox`. Only use it when Chromium cannot start otherwise, e.g. as root in a
+    /// container.
+    pub fn with_no_sandbox(mut self, no_sandbox: bool) -> Self {
+        self.no_sandbox = no_sandbox;
+        self
+    }
+
+    /// Refuse the urls outside of the allowlist and, unless they are allowed, the ones on private addresses.
+    async fn check_url(&self, url: &Url) -> Result<()> {
+        if !self.allowed_domains.is_empty() && !matches_domain(&self.allowed_domains, url) {
+            return Err(anyhow!(
+                "Capturing {} is not allowed. Only these domains can be captured: {}",
+                url,
+                self.allowed_domains.join(", ")
+            ));
+        }
+        if self.allow_private_hosts {
+            return Ok(());
+        }
+        check_public_host(url)
+            .await
+            .map_err(|e| anyhow!("Capturing {} is not allowed: {}", url, e))
+    }
+
+    /// Render the page and capture it as a PNG. Returns the title of the page, its HTML and the screenshot.
+    pub async fn capture(&self, url: &Url, full_page: bool) -> Result<(String, String, Vec<u8>)> {
+        let mut config = BrowserConfig::builder()
+            .window_size(self.width, self.height)
+            .viewport(None);
+        if self.no_sandbox {
+            config = config.no_sandbox();
+        }
+        if let Some(chrome_executable) = &self.chrome_executable {
+            config = config.chrome_executable(chrome_executable);
+        }
+        let config = config.build().map_err(|e| anyhow!(e))?;
+        let (mut browser, mut handler) = Browser::launch(config)
+            .await
+            .context("Failed to launch Chromium. Is it installed?")?;
+        let handler = tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+        let result = async {
+            let page = browser.new_page("about:blank").await?;
+            // Every request of the page is paused until it is checked, so that redirects, frames and resources can
+            // not reach the urls the tool refuses either.
+            let mut paused = page.event_listener::<EventRequestPaused>().await?;
+            page.execute(EnableParams::default()).await?;
+            let capture = self.capture_page(&page, url, full_page);
+            let intercept = async {
+                while let Some(request) = paused.next().await {
+                    self.continue_if_allowed(&page, &request).await;
+                }
+            };
+            tokio::select! {
+                result = capture => result,
+                _ = intercept => Err(anyhow!("The browser closed the page")),
+            }
+        }
+        .await;
+
+        let _ = browser.close().await;
+        let _ = browser.wait().await;
+        handler.abort();
+        result.with_context(|| format!("Failed to capture {}", url))
+    }
+
+    /// Continue the paused request if the tool may load its url, or fail it. The urls of the data in the page, such
+    /// as `data:` and `blob:` urls, are always loaded.
+    async fn continue_if_allowed(&self, page: &Page, request: &EventRequestPaused) {
+        let allowed = match Url::parse(&request.request.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                self.check_url(&url).await.is_ok()
+            }
+            Ok(url) => matches!(url.scheme(), "data" | "blob"),
+            Err(_) => false,
+        };
+        let result = if allowed {
+            page.execute(ContinueRequestParams::new(request.request_id.clone()))
+                .await
+                .map(|_| ())
+        } else {
+            log::debug!("Blocked the request of the page to {}", request.request.url);
+            page.execute(FailRequestParams::new(
+                request.request_id.clone(),
+                ErrorReason::BlockedByClient,
+            ))
+            .await
+            .map(|_| ())
+        };
+        if let Err(e) = result {
+            log::debug!(
+                "Failed to answer the request to {}: {}",
+                request.request.url,
+                e
+            );
+        }
+    }
+
+    /// Load the url in the page, wait for it to render and capture it.
+    async fn capture_page(
+        &self,
+        page: &Page,
+        url: &Url,
+        full_page: bool,
+    ) -> Result<(String, String, Vec<u8>)> {
+        page.goto(url.as_str()).await?;
+        tokio::time::sleep(self.render_delay).await;
+        let title = page.get_title().await?.unwrap_or_default();
+        let html = page.content().await?;
+        let screenshot = page
+            .screenshot(
+                ScreenshotParams::builder()
+                    .format(CaptureScreenshotFormat::Png)
+                    .full_page(full_page)
+                    .build(),
+            )
+            .await?;
+        Ok((title, html, screenshot))
+    }
+
+    async fn describe(
+        &self,
+        vision_model: &VisionModel,
+        screenshot: &[u8],
+        question: &str,
+    ) -> Result<String> {
+        let image = base64::engine::general_purpose::STANDARD.encode(screenshot);
+        let body = json!({
+            "model": vision_model.model_id,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": question },
+                    { "type": "image_url", "image_url": { "url": format!("data:image/png;base64,{}", image) } },
+                ],
+            }],
+        });
+        let mut request = reqwest::Client::new()
+            .post(&vision_model.base_url)
+            .json(&body);
+        if let Some(api_key) = &vision_model.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Vision model returned HTTP {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        let response: serde_json::Value = response.json().await?;
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| anyhow!("Vision model returned no description"))
+    }
+
+    pub async fn forward(
+        &self,
+        url: &str,
+        question: Option<&str>,
+        full_page: bool,
+    ) -> Result<String> {
+        let url = normalize_url(url)?;
+        self.check_url(&url).await?;
+        let (title, html, screenshot) = self.capture(&url, full_page).await?;
+
+        tokio::fs::create_dir_all(&self.output_dir).await?;
+        let path = self
+            .output_dir
+            .join(format!("screenshot-{}.png", nanoid::nanoid!()));
+        tokio::fs::write(&path, &screenshot)
+            .await
+            .with_context(|| format!("Failed to save the screenshot to {:?}", path))?;
+        let header = format!(
+            "Screenshot of {} ({}) saved to {}",
+            url,
+            title,
+            path.display()
+        );
+        self.answer(header, &html, &screenshot, question).await
+    }
+
+    /// The description of the screenshot by the vision model, or the text of the rendered page without one.
+    async fn answer(
+        &self,
+        header: String,
+        html: &str,
+        screenshot: &[u8],
+        question: Option<&str>,
+    ) -> Result<String> {
+        match &self.vision_model {
+            Some(vision_model) => {
+                let description = self
+                    .describe(vision_model, screenshot, question.unwrap_or(DEFAULT_PROMPT))
+                    .await?;
+                Ok(format!("{}\n\n{}", header, description))
+            }
+            None => {
+                let content = HtmlToMarkdown::builder()
+                    .skip_tags(vec!["script", "style", "header", "nav", "footer"])
+                    .build()
+                    .convert(html)
+                    .unwrap_or_default();
+                Ok(format!(
+                    "{}\n\nRendered content:\n{}",
+                    header,
+                    content.chars().take(MAX_CONTENT_CHARS).collect::<String>()
+                ))
+            }
+        }
+    }
+}
+
+/// The url the model asked for, `https://` being added when it has no scheme.
+fn normalize_url(url: &str) -> Result<Url> {
+    let parsed = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{}", url))
+    }
+    .map_err(|e| anyhow!("{} is not a valid url: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(anyhow!(
+            "Only http and https urls can be captured, not {}",
+            url
+        ));
+    }
+    Ok(parsed)
+}
+
+#[async_trait]
+impl Tool for WebScreenshotTool {
+    type Params = WebScreenshotToolParams;
+
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: WebScreenshotToolParams) -> Result<String> {
+        self.forward(
+            &arguments.url,
+            arguments.question.as_deref(),
+            arguments.full_page.unwrap_or(false),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("example.com/pricing").unwrap().as_str(),
+            "https://example.com/pricing"
+        );
+        assert_eq!(
+            normalize_url("http://example.com").unwrap().as_str(),
+            "http://example.com/"
+        );
+        // Without the check of the scheme, `localhost:8080` would be parsed as a url of the `localhost` scheme.
+        assert_eq!(
+            normalize_url("localhost:8080/admin").unwrap().as_str(),
+            "https://localhost:8080/admin"
+        );
+        assert!(normalize_url("file:///etc/passwd").is_err());
+        assert!(normalize_url("chrome://settings").is_err());
+        assert!(normalize_url("https://").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        let tool = WebScreenshotTool::new();
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://[::1]/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://localhost/",
+        ] {
+            let error = tool.check_url(&Url::parse(url).unwrap()).await.unwrap_err();
+            assert!(error.to_string().contains("private address"), "{}", url);
+        }
+        let tool = tool.with_private_hosts(true);
+        assert!(tool
+            .check_url(&Url::parse("http://127.0.0.1:8080/").unwrap())
+            .await
+            .is_ok());
+
+        let tool = tool.with_allowed_domains(vec!["*.example.com".to_string()]);
+        assert!(tool
+            .check_url(&Url::parse("https://docs.example.com/").unwrap())
+            .await
+            .is_ok());
+        let error = tool
+            .check_url(&Url::parse("https://example.org/").unwrap())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not allowed"));
+    }
+
+    #[tokio::test]
+    async fn test_answer_without_vision_model() {
+        let tool = WebScreenshotTool::new();
+        let header =
+            "Screenshot of https://example.com/ (Example) saved to /tmp/screenshot.png".to_string();
+        let answer = tool
+            .answer(header.clone(), "<p>Rendered by a script</p>", b"png", None)
+            .await
+            .unwrap();
+        assert!(answer.starts_with(&format!("{}\n\nRendered content:\n", header)));
+        assert!(answer.contains("Rendered by a script"));
+
+        let html = format!("<p>{}</p>", "a".repeat(MAX_CONTENT_CHARS * 2));
+        let answer = tool
+            .answer(header.clone(), &html, b"png", None)
+            .await
+            .unwrap();
+        assert!(answer.chars().count() <= header.chars().count() + MAX_CONTENT_CHARS + 20);
+    }
+}
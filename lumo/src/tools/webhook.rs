@@ -0,0 +1,472 @@
+//! This module contains the webhook tool. The model uses this tool to POST a JSON payload to webhooks registered in
+//! advance, e.g. to notify a service when a task is done. The model picks a webhook by name and never sees its url or
+//! secret, so it can only call the webhooks of its allowlist.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::redirect;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use super::tool_traits::{AnyTool, AsyncTool, ToolFunctionInfo, ToolInfo, ToolType};
+use crate::errors::AgentError;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Characters of the response body returned to the model.
+const MAX_RESPONSE_CHARS: usize = 2000;
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Lumo-Signature";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Lumo-Timestamp";
+
+/// A webhook the tool may call.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// The key of the HMAC-SHA256 signature of the requests. They are not signed without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// What the webhook does, shown to the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The JSON sent to the webhook, with `{{name}}` placeholders filled from the payload of the model. The payload
+    /// is sent as it is without a template.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<Value>,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: None,
+            description: None,
+            template: None,
+        }
+    }
+
+    pub fn with_secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn with_template(mut self, template: Value) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// The names of the placeholders of the template.
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut variables = BTreeSet::new();
+        if let Some(template) = &self.template {
+            collect_variables(template, &mut variables);
+        }
+        variables
+    }
+}
+
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("url", &self.url)
+            .field("secret", &self.secret.as_ref().map(|_| "***"))
+            .field("description", &self.description)
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+/// POSTs JSON payloads to the webhooks it is given, by name. With a secret, each request is signed with
+/// HMAC-SHA256 so the receiver can check that it comes from the agent: the `X-Lumo-Signature` header is
+/// `sha256=<hex>` of `<timestamp>.<body>`, with the Unix timestamp of the `X-Lumo-Timestamp` header.
+#[derive(Debug, Clone)]
+pub struct WebhookTool {
+    webhooks: BTreeMap<String, Webhook>,
+    timeout: Duration,
+}
+
+impl WebhookTool {
+    pub fn new(webhooks: impl IntoIterator<Item = (String, Webhook)>) -> Self {
+        Self {
+            webhooks: webhooks.into_iter().collect(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn webhook(&self, name: &str) -> anyhow::Result<&Webhook> {
+        self.webhooks.get(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown webhook '{}'. Available webhooks: {}",
+                name,
+                self.webhooks.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+
+    fn check_url(url: &str) -> anyhow::Result<Url> {
+        let url = Url::parse(url).map_err(|e| anyhow::anyhow!("Invalid webhook url: {}", e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow::anyhow!(
+                "Only http and https webhooks can be called"
+            ));
+        }
+        Ok(url)
+    }
+
+    /// Send the payload to the webhook and return its status and response.
+    pub async fn send(&self, name: &str, payload: &Value) -> anyhow::Result<String> {
+        let webhook = self.webhook(name)?;
+        let url = Self::check_url(&webhook.url)?;
+        let body = match &webhook.template {
+            Some(template) => render_template(template, payload)?,
+            None => payload.clone(),
+        }
+        .to_string();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .build()?;
+        // The redirects are followed by the host's fetch on wasm32.
+        #[cfg(target_arch = "wasm32")]
+        let client = reqwest::Client::new();
+        let mut request = client
+            .post(url)
+            .timeout(self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &webhook.secret {
+            let timestamp = chrono::Utc::now().timestamp();
+            request = request
+                .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    WEBHOOK_SIGNATURE_HEADER,
+                    sign_webhook(secret, timestamp, &body),
+                );
+        }
+        // The url may contain a token, so only the name of the webhook is shown to the model.
+        let response = request.body(body).send().await.map_err(|e| {
+            anyhow::anyhow!("Failed to call the webhook '{}': {}", name, e.without_url())
+        })?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        let text = shorten(&text);
+        if !status.is_success() {
+            return Err(anyhow::anyhow!(
+                "The webhook '{}' responded with HTTP {}: {}",
+                name,
+                status,
+                text
+            ));
+        }
+        Ok(json!({
+            "webhook": name,
+            "status": status.as_u16(),
+            "response": text,
+        })
+        .to_string())
+    }
+
+    fn full_description(&self) -> String {
+        let mut description = "Sends a JSON payload to a webhook, e.g. to notify a service when a task is done. Available webhooks:".to_string();
+        for (name, webhook) in &self.webhooks {
+            description.push_str(&format!("\n- {}", name));
+            if let Some(text) = &webhook.description {
+                description.push_str(&format!(": {}", text));
+            }
+            let variables = webhook.variables();
+            if !variables.is_empty() {
+                description.push_str(&format!(
+                    " (payload fields: {})",
+                    variables.into_iter().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        description
+    }
+}
+
+/// The signature of the `X-Lumo-Signature` header: `sha256=` and the hex HMAC-SHA256 of `<timestamp>.<body>`.
+pub fn sign_webhook(secret: &str, timestamp: i64, body: &str) -> String {
+    let tag = webhook_mac(secret, timestamp, body).finalize().into_bytes();
+    let hex = tag
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("sha256={}", hex)
+}
+
+/// Check the `X-Lumo-Signature` header of a webhook request, for receivers written in Rust. The comparison takes
+/// constant time.
+pub fn verify_webhook_signature(secret: &str, timestamp: i64, body: &str, signature: &str) -> bool {
+    let Some(hex) = signature.strip_prefix("sha256=") else {
+        return false;
+    };
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return false;
+    }
+    let Ok(tag) = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return false;
+    };
+    webhook_mac(secret, timestamp, body)
+        .verify_slice(&tag)
+        .is_ok()
+}
+
+/// The HMAC-SHA256 of `<timestamp>.<body>`.
+fn webhook_mac(secret: &str, timestamp: i64, body: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    mac
+}
+
+/// The `{{name}}` placeholders of a string, in order.
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        names.push(rest[start + 2..start + 2 + end].trim());
+        rest = &rest[start + 2 + end + 2..];
+    }
+    names
+}
+
+fn collect_variables(template: &Value, variables: &mut BTreeSet<String>) {
+    match template {
+        Value::String(text) => variables.extend(placeholders(text).into_iter().map(str::to_string)),
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| collect_variables(item, variables)),
+        Value::Object(map) => map
+            .values()
+            .for_each(|value| collect_variables(value, variables)),
+        _ => {}
+    }
+}
+
+/// Fill the placeholders of the template with the fields of the payload. A string that is only a placeholder takes
+/// the value of the field with its JSON type; otherwise the value is inserted in the string.
+fn render_template(template: &Value, payload: &Value) -> anyhow::Result<Value> {
+    let field = |name: &str| {
+        payload
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("The payload is missing the field '{}'", name))
+    };
+    Ok(match template {
+        Value::String(text) => {
+            let only_placeholder = text
+                .strip_prefix("{{")
+                .and_then(|text| text.strip_suffix("}}"))
+                .filter(|name| !name.contains("{{") && !name.contains("}}"));
+            if let Some(name) = only_placeholder {
+                return field(name.trim()).cloned();
+            }
+            let mut rendered = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start + 2..].find("}}") else {
+                    break;
+                };
+                rendered.push_str(&rest[..start]);
+                match field(rest[start + 2..start + 2 + end].trim())? {
+                    Value::String(value) => rendered.push_str(value),
+                    value => rendered.push_str(&value.to_string()),
+                }
+                rest = &rest[start + 2 + end + 2..];
+            }
+            rendered.push_str(rest);
+            Value::String(rendered)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_template(item, payload))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), render_template(value, payload)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        _ => template.clone(),
+    })
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() > MAX_RESPONSE_CHARS {
+        format!(
+            "{}... (shortened)",
+            text.chars().take(MAX_RESPONSE_CHARS).collect::<String>()
+        )
+    } else {
+        text.to_string()
+    }
+}
+
+impl AnyTool for WebhookTool {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sends a JSON payload to a webhook, e.g. to notify a service when a task is done."
+    }
+
+    fn tool_info(&self) -> ToolInfo {
+        ToolInfo {
+            tool_type: ToolType::Function,
+            function: ToolFunctionInfo {
+                name: self.name().to_string(),
+                description: self.full_description(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "webhook": {
+                            "type": "string",
+                            "enum": self.webhooks.keys().collect::<Vec<_>>(),
+                            "description": "The name of the webhook to call"
+                        },
+                        "payload": {
+                            "type": "object",
+                            "description": "The JSON payload, or the fields of the payload of the webhook"
+                        }
+                    },
+                    "required": ["webhook", "payload"]
+                }),
+                strict: None,
+            },
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncTool for WebhookTool {
+    async fn forward_json(&self, json_args: Value) -> Result<String, AgentError> {
+        let Some(name) = json_args.get("webhook").and_then(Value::as_str) else {
+            return Err(AgentError::Parsing(format!(
+                "Error when executing tool with arguments: {}: missing field `webhook`",
+                json_args
+            )));
+        };
+        let payload = json_args.get("payload").cloned().unwrap_or(json!({}));
+        self.send(name, &payload)
+            .await
+            .map_err(|e| AgentError::Execution(e.to_string()))
+    }
+
+    fn clone_box(&self) -> Box<dyn AsyncTool> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template = json!({
+            "text": "Task {{ task }} is done: {{summary}}",
+            "count": "{{count}}",
+            "tags": ["lumo", "{{tag}}"],
+            "fixed": 1
+        });
+        let webhook = Webhook::new("https://example.com").with_template(template.clone());
+        assert_eq!(
+            webhook.variables().into_iter().collect::<Vec<_>>(),
+            ["count", "summary", "tag", "task"]
+        );
+        let payload = json!({"task": "report", "summary": "3 issues", "count": 3, "tag": "ci"});
+        assert_eq!(
+            render_template(&template, &payload).unwrap(),
+            json!({
+                "text": "Task report is done: 3 issues",
+                "count": 3,
+                "tags": ["lumo", "ci"],
+                "fixed": 1
+            })
+        );
+        let error = render_template(&template, &json!({"task": "report"}))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("missing the field"));
+    }
+
+    #[test]
+    fn test_signature() {
+        let body = r#"{"text":"done"}"#;
+        let signature = sign_webhook("secret", 1700000000, body);
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert!(verify_webhook_signature(
+            "secret", 1700000000, body, &signature
+        ));
+        assert!(!verify_webhook_signature(
+            "other", 1700000000, body, &signature
+        ));
+        assert!(!verify_webhook_signature(
+            "secret", 1700000001, body, &signature
+        ));
+        assert!(!verify_webhook_signature(
+            "secret", 1700000000, "{}", &signature
+        ));
+        assert!(!verify_webhook_signature(
+            "secret",
+            1700000000,
+            body,
+            "sha256=zz"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_webhook() {
+        let tool = WebhookTool::new([(
+            "deploy".to_string(),
+            Webhook::new("https://hooks.example.com/abc")
+                .with_secret("s3cr3t")
+                .with_description("Notifies the deploy channel"),
+        )]);
+        let info = tool.tool_info();
+        assert!(info
+            .function
+            .description
+            .contains("- deploy: Notifies the deploy channel"));
+        assert_eq!(
+            info.function.parameters["properties"]["webhook"]["enum"],
+            json!(["deploy"])
+        );
+        assert!(!format!("{:?}", tool).contains("s3cr3t"));
+
+        // Checked before anything is sent.
+        let error = tool
+            .forward_json(json!({"webhook": "other", "payload": {}}))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Unknown webhook 'other'. Available webhooks: deploy"));
+        assert!(WebhookTool::check_url("file:///etc/passwd").is_err());
+    }
+}
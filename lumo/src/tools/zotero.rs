@@ -0,0 +1,384 @@
+//! This module contains the Zotero tool. The model uses this tool to look up the references of a Zotero library: its
+//! collections, the citation metadata of its items, their attachments, and the text of the attached PDFs.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    base::BaseTool,
+    tool_traits::Tool,
+    visit_website::{extract_pdf_text, paginate},
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.zotero.org";
+const API_VERSION: &str = "3";
+const DEFAULT_LIMIT: usize = 10;
+/// The Zotero API returns at most 100 results per request.
+const MAX_LIMIT: usize = 100;
+/// Characters of a PDF returned at once unless the tool is configured otherwise.
+const DEFAULT_MAX_LENGTH: usize = 20_000;
+
+/// The library the tool reads, a user library or a group library, by its numeric id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZoteroLibrary {
+    User(String),
+    Group(String),
+}
+
+impl ZoteroLibrary {
+    fn path(&self) -> String {
+        match self {
+            ZoteroLibrary::User(id) => format!("users/{}", id),
+            ZoteroLibrary::Group(id) => format!("groups/{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[schemars(title = "ZoteroAction")]
+pub enum ZoteroAction {
+    /// List the collections of the library.
+    Collections,
+    /// Search the items of the library, or of a collection.
+    Search,
+    /// List the attachments of an item.
+    Attachments,
+    /// Read the text of a PDF attachment.
+    Pdf,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[schemars(title = "ZoteroToolParams")]
+pub struct ZoteroToolParams {
+    #[schemars(
+        description = "`collections` to list the collections, `search` to find items and their citation metadata, `attachments` to list the attachments of an item, `pdf` to read a PDF attachment"
+    )]
+    action: ZoteroAction,
+    #[schemars(
+        description = "The words to search in the titles, authors and years of the items. Every item when missing"
+    )]
+    query: Option<String>,
+    #[schemars(description = "Optionally only search the items of the collection with this key")]
+    collection: Option<String>,
+    #[schemars(
+        description = "The key of the item whose attachments to list, or of the PDF attachment to read"
+    )]
+    item_key: Option<String>,
+    #[schemars(description = "The number of results (default: 10)")]
+    limit: Option<usize>,
+    #[schemars(
+        description = "Optionally the character to start reading the PDF from, to read the next chunk of a long PDF"
+    )]
+    start_index: Option<usize>,
+}
+
+/// Searches a Zotero library with an API key, and returns citation metadata and the text of attached PDFs.
+#[derive(Debug, Clone)]
+pub struct ZoteroTool {
+    pub tool: BaseTool,
+    api_key: String,
+    library: ZoteroLibrary,
+    base_url: String,
+    /// Number of characters of a PDF returned at once.
+    max_length: usize,
+}
+
+impl ZoteroTool {
+    pub fn new(api_key: &str, library: ZoteroLibrary) -> Self {
+        ZoteroTool {
+            tool: BaseTool {
+                name: "zotero",
+                description: "Searches a Zotero library of references. Lists its collections, finds items with their citation metadata (title, authors, date, DOI, publication), lists the attachments of an item and reads the text of PDF attachments.",
+            },
+            api_key: api_key.to_string(),
+            library,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            max_length: DEFAULT_MAX_LENGTH,
+        }
+    }
+
+    /// Configure the tool from `ZOTERO_API_KEY`, and `ZOTERO_USER_ID` or `ZOTERO_GROUP_ID`.
+    pub fn from_env() -> Result<Self> {
+        let api_key =
+            std::env::var("ZOTERO_API_KEY").map_err(|_| anyhow!("ZOTERO_API_KEY is not set"))?;
+        let library = match (
+            std::env::var("ZOTERO_USER_ID"),
+            std::env::var("ZOTERO_GROUP_ID"),
+        ) {
+            (Ok(id), _) => ZoteroLibrary::User(id),
+            (_, Ok(id)) => ZoteroLibrary::Group(id),
+            _ => return Err(anyhow!("ZOTERO_USER_ID or ZOTERO_GROUP_ID must be set")),
+        };
+        Ok(Self::new(&api_key, library))
+    }
+
+    /// The Zotero Web API, e.g. for a proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
+        let url = format!("{}/{}/{}", self.base_url, self.library.path(), path);
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("Zotero-API-Key", &self.api_key)
+            .header("Zotero-API-Version", API_VERSION)
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to call Zotero {}: {}", path, e))?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Zotero {} failed: HTTP {}",
+                path,
+                response.status()
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn collections(&self, limit: usize) -> Result<String> {
+        let response: Vec<Value> = self
+            .get("collections", &[("limit", limit.to_string())])
+            .await?
+            .json()
+            .await?;
+        let collections = response
+            .iter()
+            .map(|collection| {
+                json!({
+                    "key": collection["key"],
+                    "name": collection["data"]["name"],
+                    "parentCollection": collection["data"]["parentCollection"],
+                    "numItems": collection["meta"]["numItems"],
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Value::Array(collections).to_string())
+    }
+
+    async fn search(
+        &self,
+        query: Option<&str>,
+        collection: Option<&str>,
+        limit: usize,
+    ) -> Result<String> {
+        let path = match collection {
+            Some(collection) => format!("collections/{}/items/top", collection),
+            None => "items/top".to_string(),
+        };
+        let mut params = vec![("limit", limit.to_string())];
+        if let Some(query) = query.filter(|query| !query.trim().is_empty()) {
+            params.push(("q", query.to_string()));
+            params.push(("qmode", "titleCreatorYear".to_string()));
+        }
+        let response: Vec<Value> = self.get(&path, &params).await?.json().await?;
+        let items = response.iter().map(citation).collect::<Vec<_>>();
+        Ok(Value::Array(items).to_string())
+    }
+
+    async fn attachments(&self, item_key: &str) -> Result<String> {
+        let response: Vec<Value> = self
+            .get(&format!("items/{}/children", item_key), &[])
+            .await?
+            .json()
+            .await?;
+        let attachments = response
+            .iter()
+            .filter(|child| child["data"]["itemType"] == "attachment")
+            .map(|child| {
+                json!({
+                    "key": child["key"],
+                    "title": child["data"]["title"],
+                    "contentType": child["data"]["contentType"],
+                    "filename": child["data"]["filename"],
+                    "url": child["data"]["url"],
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Value::Array(attachments).to_string())
+    }
+
+    async fn pdf(&self, item_key: &str, start: usize) -> Result<String> {
+        let path = format!("items/{}/file", item_key);
+        let bytes = self
+            .get(&path, &[])
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| anyhow!("Failed to download the file of {}: {}", item_key, e))?;
+        let text = extract_pdf_text(&bytes).ok_or_else(|| {
+            anyhow!(
+                "The file of {} is not a readable PDF. Use the key of a PDF attachment",
+                item_key
+            )
+        })?;
+        Ok(paginate(&text, start, self.max_length))
+    }
+}
+
+/// The citation metadata of a Zotero item, with a short formatted reference.
+fn citation(item: &Value) -> Value {
+    let data = &item["data"];
+    let creators = data["creators"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|creator| match creator["name"].as_str() {
+            Some(name) => name.to_string(),
+            None => format!(
+                "{}, {}",
+                creator["lastName"].as_str().unwrap_or_default(),
+                creator["firstName"].as_str().unwrap_or_default()
+            )
+            .trim_end_matches([',', ' '])
+            .to_string(),
+        })
+        .collect::<Vec<_>>();
+    let publication = [
+        "publicationTitle",
+        "bookTitle",
+        "proceedingsTitle",
+        "publisher",
+    ]
+    .iter()
+    .find_map(|field| data[*field].as_str().filter(|value| !value.is_empty()));
+    let year = item["meta"]["parsedDate"]
+        .as_str()
+        .or(data["date"].as_str())
+        .and_then(|date| date.get(..4))
+        .filter(|year| year.chars().all(|c| c.is_ascii_digit()));
+
+    let mut reference = match creators.as_slice() {
+        [] => String::new(),
+        [creator] => format!("{} ", creator),
+        [first, second] => format!("{} & {} ", first, second),
+        [first, ..] => format!("{} et al. ", first),
+    };
+    reference.push_str(&format!("({}). ", year.unwrap_or("n.d.")));
+    reference.push_str(data["title"].as_str().unwrap_or_default());
+    if let Some(publication) = publication {
+        reference.push_str(&format!(". {}", publication));
+    }
+    if let Some(doi) = data["DOI"].as_str().filter(|doi| !doi.is_empty()) {
+        reference.push_str(&format!(". https://doi.org/{}", doi));
+    }
+
+    json!({
+        "key": item["key"],
+        "itemType": data["itemType"],
+        "title": data["title"],
+        "creators": creators,
+        "date": data["date"],
+        "publication": publication,
+        "DOI": data["DOI"],
+        "url": data["url"],
+        "numAttachments": item["meta"]["numChildren"],
+        "citation": reference,
+    })
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl Tool for ZoteroTool {
+    type Params = ZoteroToolParams;
+    fn name(&self) -> &'static str {
+        self.tool.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.tool.description
+    }
+
+    async fn forward(&self, arguments: ZoteroToolParams) -> Result<String> {
+        let limit = arguments.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let item_key = || {
+            arguments
+                .item_key
+                .as_deref()
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| anyhow!("An item_key is required for this action"))
+        };
+        match arguments.action {
+            ZoteroAction::Collections => self.collections(limit).await,
+            ZoteroAction::Search => {
+                self.search(
+                    arguments.query.as_deref(),
+                    arguments.collection.as_deref(),
+                    limit,
+                )
+                .await
+            }
+            ZoteroAction::Attachments => self.attachments(item_key()?).await,
+            ZoteroAction::Pdf => {
+                self.pdf(item_key()?, arguments.start_index.unwrap_or(0))
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citation() {
+        let item = json!({
+            "key": "ABCD2345",
+            "meta": {"parsedDate": "2017-06-12", "numChildren": 1},
+            "data": {
+                "itemType": "conferencePaper",
+                "title": "Attention Is All You Need",
+                "creators": [
+                    {"creatorType": "author", "firstName": "Ashish", "lastName": "Vaswani"},
+                    {"creatorType": "author", "firstName": "Noam", "lastName": "Shazeer"},
+                    {"creatorType": "author", "name": "Google Brain"}
+                ],
+                "date": "June 2017",
+                "proceedingsTitle": "Advances in Neural Information Processing Systems",
+                "DOI": "10.48550/arXiv.1706.03762",
+                "url": ""
+            }
+        });
+        let citation = citation(&item);
+        assert_eq!(citation["key"], "ABCD2345");
+        assert_eq!(
+            citation["creators"],
+            json!(["Vaswani, Ashish", "Shazeer, Noam", "Google Brain"])
+        );
+        assert_eq!(
+            citation["publication"],
+            "Advances in Neural Information Processing Systems"
+        );
+        assert_eq!(
+            citation["citation"],
+            "Vaswani, Ashish et al. (2017). Attention Is All You Need. Advances in Neural Information Processing Systems. https://doi.org/10.48550/arXiv.1706.03762"
+        );
+
+        let citation = super::citation(&json!({"key": "X", "data": {"title": "Untitled"}}));
+        assert_eq!(citation["citation"], "(n.d.). Untitled");
+        assert!(citation["publication"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_item_key_required() {
+        let tool = ZoteroTool::new("key", ZoteroLibrary::User("1".to_string()));
+        let params: ZoteroToolParams =
+            serde_json::from_value(json!({"action": "pdf", "item_key": " "})).unwrap();
+        let error = tool.forward(params).await.unwrap_err().to_string();
+        assert!(error.contains("item_key is required"));
+        assert_eq!(ZoteroLibrary::Group("7".to_string()).path(), "groups/7");
+    }
+}